@@ -0,0 +1,61 @@
+-- Notification Delivery Pipeline for GhostHub
+-- Durable outbound queue: priorities, retries with backoff, quiet hours and digests
+
+-- Queue timestamps are compared against NOW() by the worker
+ALTER TABLE notification_queue ALTER COLUMN scheduled_for TYPE TIMESTAMPTZ;
+ALTER TABLE notification_queue ALTER COLUMN sent_at TYPE TIMESTAMPTZ;
+ALTER TABLE notification_queue ALTER COLUMN created_at TYPE TIMESTAMPTZ;
+ALTER TABLE notification_queue ALTER COLUMN scheduled_for SET DEFAULT NOW();
+ALTER TABLE notification_queue ALTER COLUMN created_at SET DEFAULT NOW();
+
+-- Delivery tracking
+ALTER TABLE notification_queue ADD COLUMN IF NOT EXISTS template_id UUID REFERENCES notification_templates(id) ON DELETE SET NULL;
+ALTER TABLE notification_queue ADD COLUMN IF NOT EXISTS notification_type VARCHAR(100);
+ALTER TABLE notification_queue ADD COLUMN IF NOT EXISTS priority VARCHAR(20) DEFAULT 'normal'; -- low, normal, high, critical
+ALTER TABLE notification_queue ADD COLUMN IF NOT EXISTS max_attempts INTEGER DEFAULT 5;
+ALTER TABLE notification_queue ADD COLUMN IF NOT EXISTS last_attempt_at TIMESTAMPTZ;
+ALTER TABLE notification_queue ADD COLUMN IF NOT EXISTS locked_at TIMESTAMPTZ;
+ALTER TABLE notification_queue ADD COLUMN IF NOT EXISTS digest_id UUID;
+
+ALTER TABLE notification_queue DROP CONSTRAINT IF EXISTS notification_queue_status_check;
+ALTER TABLE notification_queue ADD CONSTRAINT notification_queue_status_check
+    CHECK (status IN ('pending', 'processing', 'sent', 'failed', 'cancelled', 'batched'));
+
+-- Quiet hours and digest settings (no-ops where the per-user preferences table already has them)
+ALTER TABLE notification_preferences ADD COLUMN IF NOT EXISTS digest_frequency VARCHAR(50) DEFAULT 'daily';
+ALTER TABLE notification_preferences ADD COLUMN IF NOT EXISTS quiet_hours_start TIME DEFAULT '22:00';
+ALTER TABLE notification_preferences ADD COLUMN IF NOT EXISTS quiet_hours_end TIME DEFAULT '08:00';
+ALTER TABLE notification_preferences ADD COLUMN IF NOT EXISTS weekend_notifications BOOLEAN DEFAULT false;
+
+-- Digests collect low-priority items per recipient and period
+CREATE TABLE notification_digests (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    recipient_type VARCHAR(50) NOT NULL, -- user or contact
+    recipient_id UUID NOT NULL,
+    channel VARCHAR(50) NOT NULL DEFAULT 'email',
+    frequency VARCHAR(20) NOT NULL CHECK (frequency IN ('hourly', 'daily')),
+
+    -- Collection window
+    period_start TIMESTAMPTZ NOT NULL,
+    period_end TIMESTAMPTZ NOT NULL,
+    item_count INTEGER DEFAULT 0,
+
+    -- Status
+    status VARCHAR(50) DEFAULT 'open' CHECK (status IN ('open', 'sent', 'failed')),
+    sent_at TIMESTAMPTZ,
+    error_message TEXT,
+    retry_count INTEGER DEFAULT 0,
+
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE(recipient_type, recipient_id, channel, frequency, period_start)
+);
+
+ALTER TABLE notification_queue ADD CONSTRAINT notification_queue_digest_fk
+    FOREIGN KEY (digest_id) REFERENCES notification_digests(id) ON DELETE SET NULL;
+
+-- Indexes
+CREATE INDEX idx_notification_queue_processing ON notification_queue(locked_at)
+    WHERE status = 'processing';
+CREATE INDEX idx_notification_queue_digest ON notification_queue(digest_id);
+CREATE INDEX idx_notification_digests_due ON notification_digests(period_end)
+    WHERE status = 'open';
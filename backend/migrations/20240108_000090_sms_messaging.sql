@@ -0,0 +1,19 @@
+-- Two-way SMS for GhostHub
+-- Outbound delivery through sms_providers, inbound replies threaded onto tickets
+
+ALTER TABLE sms_messages ADD COLUMN IF NOT EXISTS direction VARCHAR(10) DEFAULT 'outbound'; -- outbound, inbound
+ALTER TABLE sms_messages ADD COLUMN IF NOT EXISTS notification_queue_id UUID REFERENCES notification_queue(id) ON DELETE SET NULL;
+ALTER TABLE sms_messages ADD COLUMN IF NOT EXISTS ticket_reply_id UUID;
+ALTER TABLE sms_messages ADD COLUMN IF NOT EXISTS received_at TIMESTAMPTZ;
+
+-- Replies coming in over SMS are attributed to contacts rather than staff
+ALTER TABLE ticket_replies ADD COLUMN IF NOT EXISTS contact_id UUID REFERENCES contacts(id);
+ALTER TABLE ticket_replies ALTER COLUMN user_id DROP NOT NULL;
+ALTER TABLE ticket_replies ADD COLUMN IF NOT EXISTS source VARCHAR(50) DEFAULT 'web'; -- web, email, sms, portal
+
+-- Indexes
+CREATE INDEX idx_sms_messages_to_number ON sms_messages(to_number, created_at DESC);
+CREATE INDEX idx_sms_messages_from_number ON sms_messages(from_number, created_at DESC);
+CREATE INDEX idx_sms_messages_external ON sms_messages(provider_id_external);
+CREATE INDEX idx_contacts_mobile_digits ON contacts(RIGHT(regexp_replace(mobile, '[^0-9]', '', 'g'), 10));
+CREATE INDEX idx_contacts_phone_digits ON contacts(RIGHT(regexp_replace(phone, '[^0-9]', '', 'g'), 10));
@@ -0,0 +1,33 @@
+-- Team Chat for GhostHub
+-- Global, per-client and per-ticket channels with read tracking and mentions
+
+-- channel_type now also covers: global, client, ticket
+CREATE UNIQUE INDEX idx_chat_channels_ticket_unique ON chat_channels(ticket_id)
+    WHERE channel_type = 'ticket' AND ticket_id IS NOT NULL;
+CREATE UNIQUE INDEX idx_chat_channels_client_unique ON chat_channels(client_id)
+    WHERE channel_type = 'client' AND client_id IS NOT NULL;
+
+-- Per-user read position for unread counts
+CREATE TABLE chat_channel_reads (
+    channel_id UUID NOT NULL REFERENCES chat_channels(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    last_read_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_read_message_id UUID REFERENCES chat_messages(id) ON DELETE SET NULL,
+    muted BOOLEAN DEFAULT false,
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (channel_id, user_id)
+);
+
+-- Mentions resolved when a message is posted
+CREATE TABLE chat_message_mentions (
+    message_id UUID NOT NULL REFERENCES chat_messages(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (message_id, user_id)
+);
+
+-- Indexes
+CREATE INDEX idx_chat_messages_channel_created ON chat_messages(channel_id, created_at DESC);
+CREATE INDEX idx_chat_messages_parent ON chat_messages(parent_message_id);
+CREATE INDEX idx_chat_message_mentions_user ON chat_message_mentions(user_id);
+CREATE INDEX idx_chat_channels_members ON chat_channels USING GIN(members);
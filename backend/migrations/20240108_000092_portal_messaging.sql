@@ -0,0 +1,60 @@
+-- Client Portal Messaging for GhostHub
+-- Conversations between portal contacts and staff that live outside the ticket queue
+
+CREATE TABLE portal_conversations (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    contact_id UUID NOT NULL REFERENCES contacts(id),
+    subject VARCHAR(500) NOT NULL,
+    category VARCHAR(50) DEFAULT 'general', -- general, billing, account, project
+    priority VARCHAR(50) DEFAULT 'normal', -- low, normal, high
+    status VARCHAR(50) DEFAULT 'open' CHECK (status IN ('open', 'awaiting_client', 'closed', 'converted')),
+
+    -- Staff ownership
+    assigned_to UUID REFERENCES users(id),
+    assigned_at TIMESTAMPTZ,
+
+    -- Conversion to ticket
+    ticket_id UUID REFERENCES tickets(id),
+    converted_at TIMESTAMPTZ,
+    converted_by UUID REFERENCES users(id),
+
+    -- Read tracking per side
+    last_message_at TIMESTAMPTZ DEFAULT NOW(),
+    contact_last_read_at TIMESTAMPTZ,
+    staff_last_read_at TIMESTAMPTZ,
+
+    closed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- Messages can now be written by staff as well as contacts
+ALTER TABLE portal_messages ADD COLUMN conversation_id UUID REFERENCES portal_conversations(id) ON DELETE CASCADE;
+ALTER TABLE portal_messages ADD COLUMN sender_type VARCHAR(20) NOT NULL DEFAULT 'contact'; -- contact, staff, system
+ALTER TABLE portal_messages ADD COLUMN user_id UUID REFERENCES users(id);
+ALTER TABLE portal_messages ADD COLUMN read_at TIMESTAMPTZ;
+ALTER TABLE portal_messages ADD COLUMN read_by_user_id UUID REFERENCES users(id);
+ALTER TABLE portal_messages ALTER COLUMN contact_id DROP NOT NULL;
+
+-- Files attached to portal messages; uploaded before the message is sent
+CREATE TABLE portal_message_attachments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    conversation_id UUID NOT NULL REFERENCES portal_conversations(id) ON DELETE CASCADE,
+    message_id UUID REFERENCES portal_messages(id) ON DELETE CASCADE,
+    original_filename VARCHAR(255) NOT NULL,
+    file_path TEXT NOT NULL,
+    mime_type VARCHAR(100) NOT NULL,
+    file_size BIGINT NOT NULL,
+    uploaded_by_contact_id UUID REFERENCES contacts(id),
+    uploaded_by_user_id UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- Indexes
+CREATE INDEX idx_portal_conversations_client ON portal_conversations(client_id);
+CREATE INDEX idx_portal_conversations_contact ON portal_conversations(contact_id);
+CREATE INDEX idx_portal_conversations_assigned ON portal_conversations(assigned_to) WHERE status IN ('open', 'awaiting_client');
+CREATE INDEX idx_portal_conversations_last_message ON portal_conversations(last_message_at DESC);
+CREATE INDEX idx_portal_messages_conversation ON portal_messages(conversation_id, created_at);
+CREATE INDEX idx_portal_message_attachments_message ON portal_message_attachments(message_id);
@@ -0,0 +1,81 @@
+-- Recurring Billing Engine for GhostHub
+-- Billing runs, billed periods, mid-cycle adjustments, usage and invoice line sources
+
+-- One row per run date; a crashed run is retried under the same row
+CREATE TABLE billing_runs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    run_date DATE NOT NULL UNIQUE,
+    status VARCHAR(50) DEFAULT 'running' CHECK (status IN ('running', 'completed', 'failed')),
+    clients_processed INTEGER DEFAULT 0,
+    invoices_created INTEGER DEFAULT 0,
+    total_amount DECIMAL(15,2) DEFAULT 0,
+    error_message TEXT,
+    triggered_by UUID REFERENCES users(id),
+    started_at TIMESTAMPTZ DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+-- Every period a profile has been billed for; the unique key stops a period being invoiced twice
+CREATE TABLE recurring_billing_periods (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    recurring_billing_id UUID NOT NULL REFERENCES recurring_billing(id) ON DELETE CASCADE,
+    period_start DATE NOT NULL,
+    period_end DATE NOT NULL,
+    days_billed INTEGER NOT NULL,
+    days_in_cycle INTEGER NOT NULL,
+    amount DECIMAL(15,2) NOT NULL,
+    invoice_id UUID REFERENCES invoices(id) ON DELETE SET NULL,
+    billing_run_id UUID REFERENCES billing_runs(id),
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE(recurring_billing_id, period_start)
+);
+
+-- Price or quantity changes inside an already billed period, charged or credited on the next invoice
+CREATE TABLE recurring_billing_adjustments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    recurring_billing_id UUID NOT NULL REFERENCES recurring_billing(id) ON DELETE CASCADE,
+    effective_date DATE NOT NULL,
+    period_end DATE NOT NULL,
+    previous_amount DECIMAL(15,2) NOT NULL,
+    new_amount DECIMAL(15,2) NOT NULL,
+    prorated_amount DECIMAL(15,2) NOT NULL,
+    description TEXT,
+    invoice_id UUID REFERENCES invoices(id) ON DELETE SET NULL,
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- Usage reported against usage_based profiles, billed in arrears
+CREATE TABLE recurring_billing_usage (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    recurring_billing_id UUID NOT NULL REFERENCES recurring_billing(id) ON DELETE CASCADE,
+    usage_date DATE NOT NULL,
+    quantity DECIMAL(12,2) NOT NULL,
+    description TEXT,
+    invoice_id UUID REFERENCES invoices(id) ON DELETE SET NULL,
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- Graduated pricing for tiered profiles: [{"up_to": 10, "unit_price": 50.00}, {"up_to": null, "unit_price": 40.00}]
+ALTER TABLE recurring_billing ADD COLUMN IF NOT EXISTS pricing_tiers JSONB DEFAULT '[]';
+ALTER TABLE recurring_billing ADD COLUMN IF NOT EXISTS prorate BOOLEAN DEFAULT true;
+
+-- Invoice shape used by the invoices API plus line provenance
+ALTER TABLE invoices ADD COLUMN IF NOT EXISTS billing_run_id UUID REFERENCES billing_runs(id);
+ALTER TABLE invoice_line_items ADD COLUMN IF NOT EXISTS line_total DECIMAL(15,2);
+ALTER TABLE invoice_line_items ADD COLUMN IF NOT EXISTS tax_amount DECIMAL(15,2);
+ALTER TABLE invoice_line_items ALTER COLUMN total_price DROP NOT NULL;
+ALTER TABLE invoice_line_items ADD COLUMN IF NOT EXISTS source_type VARCHAR(50); -- recurring, adjustment, usage, time_entry, expense, manual
+ALTER TABLE invoice_line_items ADD COLUMN IF NOT EXISTS source_id UUID;
+ALTER TABLE invoice_line_items ADD COLUMN IF NOT EXISTS period_start DATE;
+ALTER TABLE invoice_line_items ADD COLUMN IF NOT EXISTS period_end DATE;
+
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS invoice_id UUID REFERENCES invoices(id);
+
+-- Indexes
+CREATE INDEX idx_recurring_billing_periods_invoice ON recurring_billing_periods(invoice_id);
+CREATE INDEX idx_recurring_billing_adjustments_unbilled ON recurring_billing_adjustments(recurring_billing_id) WHERE invoice_id IS NULL;
+CREATE INDEX idx_recurring_billing_usage_unbilled ON recurring_billing_usage(recurring_billing_id) WHERE invoice_id IS NULL;
+CREATE INDEX idx_invoice_line_items_source ON invoice_line_items(source_type, source_id);
+CREATE INDEX idx_time_entries_invoice ON time_entries(invoice_id);
@@ -0,0 +1,41 @@
+-- Metered Recurring Billing for GhostHub
+-- Recurring line items whose quantity comes from a live metric, snapshotted onto the invoice line
+
+ALTER TABLE recurring_billing_items ADD COLUMN IF NOT EXISTS metric_type VARCHAR(50); -- m365_licensed_users, m365_license_sku, asset_count, backup_storage_gb
+ALTER TABLE recurring_billing_items ADD COLUMN IF NOT EXISTS metric_filter JSONB DEFAULT '{}'; -- e.g. {"sku_part_number": "SPE_E3"} or {"asset_type": "workstation"}
+ALTER TABLE recurring_billing_items ADD COLUMN IF NOT EXISTS min_quantity DECIMAL(10,2); -- contractual minimum billed when the metric is lower
+ALTER TABLE recurring_billing_items ADD COLUMN IF NOT EXISTS last_metric_quantity DECIMAL(12,2);
+ALTER TABLE recurring_billing_items ADD COLUMN IF NOT EXISTS last_metric_at TIMESTAMPTZ;
+
+-- Backup storage reported per protected item by backup integrations or by hand
+CREATE TABLE backup_storage_usage (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    asset_id UUID REFERENCES assets(id) ON DELETE SET NULL,
+    provider VARCHAR(100) NOT NULL, -- veeam, datto, azure_backup, manual
+    protected_item VARCHAR(255) NOT NULL,
+    storage_gb DECIMAL(12,2) NOT NULL,
+    recorded_at TIMESTAMPTZ DEFAULT NOW(),
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- What a metered line was billed on: the measured value and the records behind it
+CREATE TABLE invoice_line_metric_snapshots (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    invoice_id UUID NOT NULL REFERENCES invoices(id) ON DELETE CASCADE,
+    invoice_line_item_id UUID NOT NULL REFERENCES invoice_line_items(id) ON DELETE CASCADE,
+    recurring_billing_item_id UUID REFERENCES recurring_billing_items(id) ON DELETE SET NULL,
+    metric_type VARCHAR(50) NOT NULL,
+    metric_filter JSONB DEFAULT '{}',
+    measured_quantity DECIMAL(12,2) NOT NULL,
+    billed_quantity DECIMAL(12,2) NOT NULL,
+    previous_quantity DECIMAL(12,2),
+    evidence JSONB DEFAULT '[]',
+    captured_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- Indexes
+CREATE INDEX idx_recurring_billing_items_metric ON recurring_billing_items(metric_type) WHERE metric_type IS NOT NULL;
+CREATE INDEX idx_backup_storage_usage_client ON backup_storage_usage(client_id, protected_item, recorded_at DESC);
+CREATE INDEX idx_invoice_line_metric_snapshots_invoice ON invoice_line_metric_snapshots(invoice_id);
+CREATE INDEX idx_invoice_line_metric_snapshots_line ON invoice_line_metric_snapshots(invoice_line_item_id);
@@ -0,0 +1,45 @@
+-- Prepaid Hour Blocks for GhostHub
+-- Hour or dollar blocks sold against a contract, drawn down by billable time before overage applies
+
+ALTER TABLE contracts ADD COLUMN IF NOT EXISTS block_rollover_policy VARCHAR(20) DEFAULT 'none' CHECK (block_rollover_policy IN ('none', 'full', 'capped'));
+ALTER TABLE contracts ADD COLUMN IF NOT EXISTS block_rollover_cap DECIMAL(10,2); -- max hours/dollars carried forward under 'capped'
+ALTER TABLE contracts ADD COLUMN IF NOT EXISTS block_rollover_months INTEGER DEFAULT 3; -- validity of a rolled-over block
+ALTER TABLE contracts ADD COLUMN IF NOT EXISTS block_validity_months INTEGER DEFAULT 12; -- default validity of a new block
+ALTER TABLE contracts ADD COLUMN IF NOT EXISTS low_balance_percent INTEGER DEFAULT 20;
+
+CREATE TABLE contract_prepaid_blocks (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    contract_id UUID NOT NULL REFERENCES contracts(id) ON DELETE CASCADE,
+    client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    block_type VARCHAR(20) NOT NULL CHECK (block_type IN ('hours', 'amount')),
+    quantity DECIMAL(12,2) NOT NULL CHECK (quantity > 0), -- hours, or dollars for amount blocks
+    price DECIMAL(15,2), -- what the client paid for the block
+    purchased_on DATE NOT NULL DEFAULT CURRENT_DATE,
+    expires_on DATE,
+    status VARCHAR(20) DEFAULT 'active' CHECK (status IN ('active', 'depleted', 'expired', 'rolled_over')),
+    expired_quantity DECIMAL(12,2) DEFAULT 0, -- balance forfeited or carried out at expiry
+    rolled_over_from UUID REFERENCES contract_prepaid_blocks(id),
+    invoice_id UUID REFERENCES invoices(id) ON DELETE SET NULL,
+    low_balance_alerted_at TIMESTAMPTZ,
+    notes TEXT,
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- One row per block a time entry drew on; deleting the entry gives the balance back
+CREATE TABLE prepaid_block_drawdowns (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    block_id UUID NOT NULL REFERENCES contract_prepaid_blocks(id) ON DELETE CASCADE,
+    time_entry_id UUID NOT NULL REFERENCES time_entries(id) ON DELETE CASCADE,
+    hours DECIMAL(10,2) NOT NULL,
+    amount DECIMAL(15,2) NOT NULL, -- dollars drawn; equals hours x rate for amount blocks
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE(block_id, time_entry_id)
+);
+
+-- Indexes
+CREATE INDEX idx_contract_prepaid_blocks_client ON contract_prepaid_blocks(client_id) WHERE status = 'active';
+CREATE INDEX idx_contract_prepaid_blocks_contract ON contract_prepaid_blocks(contract_id);
+CREATE INDEX idx_contract_prepaid_blocks_expiry ON contract_prepaid_blocks(expires_on) WHERE status = 'active';
+CREATE INDEX idx_prepaid_block_drawdowns_entry ON prepaid_block_drawdowns(time_entry_id);
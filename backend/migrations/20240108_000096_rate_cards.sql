@@ -0,0 +1,48 @@
+-- Rate Cards for GhostHub
+-- Hourly rates by work type and technician role, after-hours/weekend multipliers and billing increments
+
+CREATE TABLE rate_cards (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    client_id UUID REFERENCES clients(id) ON DELETE CASCADE, -- set for a client override card
+    is_default BOOLEAN DEFAULT false,
+    base_rate DECIMAL(10,2) NOT NULL, -- used when no rate row matches
+    business_hours_start TIME NOT NULL DEFAULT '08:00',
+    business_hours_end TIME NOT NULL DEFAULT '18:00',
+    after_hours_multiplier DECIMAL(5,2) NOT NULL DEFAULT 1.5,
+    weekend_multiplier DECIMAL(5,2) NOT NULL DEFAULT 2.0,
+    minimum_minutes INTEGER NOT NULL DEFAULT 0 CHECK (minimum_minutes >= 0),
+    increment_minutes INTEGER NOT NULL DEFAULT 1 CHECK (increment_minutes > 0),
+    rounding VARCHAR(10) NOT NULL DEFAULT 'up' CHECK (rounding IN ('up', 'nearest', 'down')),
+    is_active BOOLEAN DEFAULT true,
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- A NULL work type or role matches any; the most specific row wins
+CREATE TABLE rate_card_rates (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    rate_card_id UUID NOT NULL REFERENCES rate_cards(id) ON DELETE CASCADE,
+    work_type VARCHAR(50) CHECK (work_type IN ('remote', 'onsite', 'project')),
+    technician_role VARCHAR(50), -- matches users.role
+    hourly_rate DECIMAL(10,2) NOT NULL CHECK (hourly_rate >= 0),
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+ALTER TABLE projects ADD COLUMN IF NOT EXISTS rate_card_id UUID REFERENCES rate_cards(id) ON DELETE SET NULL;
+ALTER TABLE clients ADD COLUMN IF NOT EXISTS default_hourly_rate DECIMAL(10,2);
+
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS work_type VARCHAR(50) CHECK (work_type IN ('remote', 'onsite', 'project'));
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS billable_minutes INTEGER; -- duration after minimums and rounding
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS base_hourly_rate DECIMAL(10,2); -- rate before multipliers
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS rate_multiplier DECIMAL(5,2);
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS rate_card_id UUID REFERENCES rate_cards(id) ON DELETE SET NULL;
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS rate_source VARCHAR(20); -- rate_card, fallback, manual
+
+-- Indexes
+CREATE UNIQUE INDEX idx_rate_cards_single_default ON rate_cards(is_default) WHERE is_default = true;
+CREATE UNIQUE INDEX idx_rate_cards_client ON rate_cards(client_id) WHERE client_id IS NOT NULL AND is_active = true;
+CREATE UNIQUE INDEX idx_rate_card_rates_match ON rate_card_rates(rate_card_id, COALESCE(work_type, ''), COALESCE(technician_role, ''));
+CREATE INDEX idx_projects_rate_card ON projects(rate_card_id);
@@ -0,0 +1,39 @@
+-- Quote Lifecycle for GhostHub
+-- Sending, portal e-signature acceptance, expiry, revisions and conversion of quotes into projects, invoices and recurring billing
+
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS contact_id UUID REFERENCES contacts(id) ON DELETE SET NULL;
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS root_quote_id UUID REFERENCES quotes(id); -- first version of a revised quote
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS superseded_by UUID REFERENCES quotes(id);
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS recurring_total DECIMAL(10,2) NOT NULL DEFAULT 0; -- per-cycle value of recurring lines
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS sent_at TIMESTAMPTZ;
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS viewed_at TIMESTAMPTZ;
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS pdf_path VARCHAR;
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS accepted_by_contact_id UUID REFERENCES contacts(id) ON DELETE SET NULL;
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS accepted_name VARCHAR(255); -- typed-name signature
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS accepted_ip VARCHAR(64);
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS accepted_user_agent TEXT;
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS rejected_at TIMESTAMPTZ;
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS rejection_reason TEXT;
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS converted_at TIMESTAMPTZ;
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS converted_project_id UUID REFERENCES projects(id) ON DELETE SET NULL;
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS converted_invoice_id UUID REFERENCES invoices(id) ON DELETE SET NULL;
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS converted_recurring_billing_id UUID REFERENCES recurring_billing(id) ON DELETE SET NULL;
+-- status: draft, sent, accepted, rejected, expired, superseded
+
+ALTER TABLE quote_line_items ADD COLUMN IF NOT EXISTS item_type VARCHAR(20) NOT NULL DEFAULT 'service'
+    CHECK (item_type IN ('service', 'product', 'labor', 'recurring'));
+ALTER TABLE quote_line_items ADD COLUMN IF NOT EXISTS frequency VARCHAR(20); -- billing frequency of recurring lines
+ALTER TABLE quote_line_items ADD COLUMN IF NOT EXISTS tax_rate DECIMAL(5,2);
+ALTER TABLE quote_line_items ADD COLUMN IF NOT EXISTS sort_order INTEGER NOT NULL DEFAULT 0;
+
+-- Lines belong to their quote version
+ALTER TABLE quote_line_items DROP CONSTRAINT IF EXISTS quote_line_items_quote_id_fkey;
+ALTER TABLE quote_line_items ADD CONSTRAINT quote_line_items_quote_id_fkey
+    FOREIGN KEY (quote_id) REFERENCES quotes(id) ON DELETE CASCADE;
+
+-- Indexes
+CREATE INDEX IF NOT EXISTS idx_quotes_client ON quotes(client_id, status);
+CREATE INDEX IF NOT EXISTS idx_quotes_root ON quotes(root_quote_id);
+CREATE INDEX IF NOT EXISTS idx_quotes_expiry ON quotes(expiry_date) WHERE status = 'sent';
+CREATE INDEX IF NOT EXISTS idx_quote_line_items_quote ON quote_line_items(quote_id, sort_order);
@@ -0,0 +1,62 @@
+-- Credit Notes, Refunds and Voids for GhostHub
+-- Credit notes against invoices or as account credit, invoice voids that keep their lines, and refunds against recorded payments
+
+ALTER TABLE credit_notes ADD COLUMN IF NOT EXISTS credit_type VARCHAR(20) NOT NULL DEFAULT 'invoice'
+    CHECK (credit_type IN ('invoice', 'account')); -- issued against one invoice or as general account credit
+ALTER TABLE credit_notes ADD COLUMN IF NOT EXISTS refunded_amount DECIMAL(10,2) NOT NULL DEFAULT 0;
+ALTER TABLE credit_notes ADD COLUMN IF NOT EXISTS voided_at TIMESTAMPTZ;
+ALTER TABLE credit_notes ADD COLUMN IF NOT EXISTS voided_by UUID REFERENCES users(id);
+-- status: draft, issued, partially_applied, fully_applied, refunded, void
+UPDATE credit_notes SET remaining_amount = amount - COALESCE(applied_amount, 0) WHERE remaining_amount IS NULL;
+ALTER TABLE credit_notes ADD CONSTRAINT credit_notes_remaining_non_negative CHECK (remaining_amount >= 0) NOT VALID;
+
+-- Each use of a credit note against an invoice
+CREATE TABLE credit_note_applications (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    credit_note_id UUID NOT NULL REFERENCES credit_notes(id) ON DELETE CASCADE,
+    invoice_id UUID NOT NULL REFERENCES invoices(id) ON DELETE CASCADE,
+    amount DECIMAL(10,2) NOT NULL CHECK (amount > 0),
+    applied_by UUID REFERENCES users(id), -- NULL when applied by a billing run
+    applied_at TIMESTAMPTZ DEFAULT NOW(),
+    reversed_at TIMESTAMPTZ -- set when the invoice is voided and the credit returned to the note
+);
+
+-- Voids keep the invoice and its lines; the voided portion is tracked separately from the total
+ALTER TABLE invoices ADD COLUMN IF NOT EXISTS credited_amount DECIMAL(15,2) NOT NULL DEFAULT 0;
+ALTER TABLE invoices ADD COLUMN IF NOT EXISTS voided_amount DECIMAL(15,2) NOT NULL DEFAULT 0;
+ALTER TABLE invoices ADD COLUMN IF NOT EXISTS voided_at TIMESTAMPTZ;
+ALTER TABLE invoices ADD COLUMN IF NOT EXISTS voided_by UUID REFERENCES users(id);
+ALTER TABLE invoices ADD COLUMN IF NOT EXISTS void_reason TEXT;
+ALTER TABLE invoices ADD CONSTRAINT invoices_balance_non_negative CHECK (balance >= 0) NOT VALID;
+
+ALTER TABLE invoice_line_items ADD COLUMN IF NOT EXISTS voided_at TIMESTAMPTZ;
+ALTER TABLE invoice_line_items ADD COLUMN IF NOT EXISTS void_reason TEXT;
+
+ALTER TABLE payments ADD COLUMN IF NOT EXISTS refunded_amount DECIMAL(15,2) NOT NULL DEFAULT 0;
+
+-- Refunds are payment_transactions rows of type 'refund' pointing at what they refund
+ALTER TABLE payment_transactions ADD COLUMN IF NOT EXISTS payment_id UUID REFERENCES payments(id);
+ALTER TABLE payment_transactions ADD COLUMN IF NOT EXISTS credit_note_id UUID REFERENCES credit_notes(id);
+
+-- Audit trail of every change to an invoice balance other than a plain payment
+CREATE TABLE invoice_adjustments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    invoice_id UUID NOT NULL REFERENCES invoices(id) ON DELETE CASCADE,
+    adjustment_type VARCHAR(20) NOT NULL CHECK (adjustment_type IN ('void', 'partial_void', 'credit', 'refund')),
+    amount DECIMAL(15,2) NOT NULL,
+    balance_before DECIMAL(15,2) NOT NULL,
+    balance_after DECIMAL(15,2) NOT NULL,
+    reason TEXT,
+    credit_note_id UUID REFERENCES credit_notes(id),
+    payment_transaction_id UUID REFERENCES payment_transactions(id),
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- Indexes
+CREATE INDEX IF NOT EXISTS idx_credit_notes_client ON credit_notes(client_id, status);
+CREATE INDEX IF NOT EXISTS idx_credit_notes_invoice ON credit_notes(invoice_id);
+CREATE INDEX IF NOT EXISTS idx_credit_note_applications_note ON credit_note_applications(credit_note_id);
+CREATE INDEX IF NOT EXISTS idx_credit_note_applications_invoice ON credit_note_applications(invoice_id);
+CREATE INDEX IF NOT EXISTS idx_payment_transactions_payment ON payment_transactions(payment_id);
+CREATE INDEX IF NOT EXISTS idx_invoice_adjustments_invoice ON invoice_adjustments(invoice_id, created_at);
@@ -0,0 +1,132 @@
+-- Expense Tracking for GhostHub
+-- Expense entry against clients, projects and tickets with receipts, approval, category markup rules and rebilling
+
+-- Categories carry the markup and approval rules for their expenses
+CREATE TABLE IF NOT EXISTS expense_categories (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name VARCHAR NOT NULL,
+    description TEXT,
+    tax_deductible BOOLEAN DEFAULT false,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+ALTER TABLE expense_categories ADD COLUMN IF NOT EXISTS markup_percent DECIMAL(5,2) NOT NULL DEFAULT 0;
+ALTER TABLE expense_categories ADD COLUMN IF NOT EXISTS billable_by_default BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE expense_categories ADD COLUMN IF NOT EXISTS approval_threshold DECIMAL(10,2); -- auto-approve up to this amount; NULL means always review
+ALTER TABLE expense_categories ADD COLUMN IF NOT EXISTS is_active BOOLEAN NOT NULL DEFAULT true;
+
+INSERT INTO expense_categories (name, description, markup_percent, billable_by_default)
+SELECT name, description, markup, billable
+FROM (VALUES
+    ('hardware', 'Hardware purchased for clients', 15.00, true),
+    ('software', 'Software and licences purchased for clients', 10.00, true),
+    ('travel', 'Mileage, parking and travel', 0.00, false),
+    ('services', 'Third-party services', 10.00, true),
+    ('utilities', 'Internal utilities and overheads', 0.00, false)
+) AS defaults(name, description, markup, billable)
+WHERE NOT EXISTS (SELECT 1 FROM expense_categories ec WHERE LOWER(ec.name) = defaults.name);
+
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS category_id UUID REFERENCES expense_categories(id);
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS ticket_id UUID REFERENCES tickets(id) ON DELETE SET NULL;
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS vendor_id UUID REFERENCES vendors(id) ON DELETE SET NULL;
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS receipt_file_id UUID REFERENCES files(id) ON DELETE SET NULL;
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS rejected_by UUID REFERENCES users(id);
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS rejected_at TIMESTAMPTZ;
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS reimbursed_at TIMESTAMPTZ;
+
+UPDATE expenses e SET category_id = ec.id
+FROM expense_categories ec
+WHERE e.category_id IS NULL AND LOWER(ec.name) = LOWER(e.category);
+
+-- Expenses now count towards profitability on their own; previously they were
+-- cross-joined with time entries and revenue read columns invoices doesn't have
+CREATE OR REPLACE FUNCTION calculate_client_profitability(
+    p_client_id UUID,
+    p_start_date DATE,
+    p_end_date DATE
+) RETURNS void AS $$
+DECLARE
+    v_recurring DECIMAL(10,2);
+    v_product DECIMAL(10,2);
+    v_total DECIMAL(10,2);
+    v_expenses DECIMAL(10,2);
+    v_labor DECIMAL(10,2);
+    v_hours DECIMAL(10,2);
+    v_billable_hours DECIMAL(10,2);
+BEGIN
+    SELECT
+        COALESCE(SUM(li.line_total) FILTER (WHERE li.source_type IN ('recurring', 'adjustment', 'usage')), 0),
+        COALESCE(SUM(li.line_total) FILTER (WHERE li.source_type = 'expense'), 0)
+    INTO v_recurring, v_product
+    FROM invoice_line_items li
+    JOIN invoices i ON i.id = li.invoice_id
+    WHERE i.client_id = p_client_id
+      AND i.date BETWEEN p_start_date AND p_end_date
+      AND i.status NOT IN ('draft', 'void')
+      AND li.voided_at IS NULL;
+
+    SELECT COALESCE(SUM(i.subtotal), 0)
+    INTO v_total
+    FROM invoices i
+    WHERE i.client_id = p_client_id
+      AND i.date BETWEEN p_start_date AND p_end_date
+      AND i.status NOT IN ('draft', 'void');
+
+    SELECT COALESCE(SUM(e.amount), 0)
+    INTO v_expenses
+    FROM expenses e
+    WHERE e.client_id = p_client_id
+      AND e.expense_date BETWEEN p_start_date AND p_end_date
+      AND e.status <> 'rejected';
+
+    SELECT
+        COALESCE(SUM(te.duration_minutes * COALESCE(u.hourly_rate, 150) / 60.0), 0),
+        COALESCE(SUM(te.duration_minutes) / 60.0, 0),
+        COALESCE(SUM(te.duration_minutes) FILTER (WHERE te.billable) / 60.0, 0)
+    INTO v_labor, v_hours, v_billable_hours
+    FROM time_entries te
+    LEFT JOIN users u ON u.id = te.user_id
+    LEFT JOIN tickets t ON t.id = te.ticket_id
+    LEFT JOIN projects p ON p.id = te.project_id
+    WHERE COALESCE(t.client_id, p.client_id) = p_client_id
+      AND te.start_time::date BETWEEN p_start_date AND p_end_date;
+
+    INSERT INTO client_profitability (
+        client_id, period_start, period_end,
+        recurring_revenue, product_revenue, service_revenue, total_revenue,
+        expense_cost, labor_cost, total_cost,
+        gross_profit, gross_margin_percent,
+        hours_worked, billable_hours, utilization_rate, effective_hourly_rate
+    ) VALUES (
+        p_client_id, p_start_date, p_end_date,
+        v_recurring, v_product, v_total - v_recurring - v_product, v_total,
+        v_expenses, v_labor, v_expenses + v_labor,
+        v_total - (v_expenses + v_labor),
+        CASE WHEN v_total > 0 THEN ((v_total - (v_expenses + v_labor)) / v_total * 100) ELSE 0 END,
+        v_hours, v_billable_hours,
+        CASE WHEN v_hours > 0 THEN (v_billable_hours / v_hours * 100) ELSE 0 END,
+        CASE WHEN v_hours > 0 THEN ((v_total - v_expenses) / v_hours) ELSE 0 END
+    )
+    ON CONFLICT (client_id, period_start, period_end)
+    DO UPDATE SET
+        recurring_revenue = EXCLUDED.recurring_revenue,
+        product_revenue = EXCLUDED.product_revenue,
+        service_revenue = EXCLUDED.service_revenue,
+        total_revenue = EXCLUDED.total_revenue,
+        expense_cost = EXCLUDED.expense_cost,
+        labor_cost = EXCLUDED.labor_cost,
+        total_cost = EXCLUDED.total_cost,
+        gross_profit = EXCLUDED.gross_profit,
+        gross_margin_percent = EXCLUDED.gross_margin_percent,
+        hours_worked = EXCLUDED.hours_worked,
+        billable_hours = EXCLUDED.billable_hours,
+        utilization_rate = EXCLUDED.utilization_rate,
+        effective_hourly_rate = EXCLUDED.effective_hourly_rate,
+        calculated_at = NOW();
+END;
+$$ LANGUAGE plpgsql;
+
+-- Indexes
+CREATE INDEX IF NOT EXISTS idx_expenses_project ON expenses(project_id);
+CREATE INDEX IF NOT EXISTS idx_expenses_ticket ON expenses(ticket_id);
+CREATE INDEX IF NOT EXISTS idx_expenses_unbilled ON expenses(client_id, expense_date)
+    WHERE is_billable = true AND invoice_id IS NULL;
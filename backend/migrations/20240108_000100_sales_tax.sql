@@ -0,0 +1,110 @@
+-- Sales Tax for GhostHub
+-- Jurisdiction rates (state, county, city), taxable categories, client exemption certificates and per-line tax records
+
+CREATE TABLE tax_jurisdictions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name VARCHAR(255) NOT NULL,
+    level VARCHAR(20) NOT NULL,
+    state_code VARCHAR(2) NOT NULL,
+    county VARCHAR(100), -- required for county and city levels
+    city VARCHAR(100),   -- required for city level
+    rate DECIMAL(7,4) NOT NULL,
+    is_compound BOOLEAN NOT NULL DEFAULT false, -- charged on the line plus the taxes applied before it
+    priority INTEGER NOT NULL DEFAULT 0,        -- order within a level
+    effective_from DATE NOT NULL DEFAULT CURRENT_DATE,
+    effective_to DATE,
+    is_active BOOLEAN NOT NULL DEFAULT true,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+    CHECK (level IN ('state', 'county', 'city', 'special')),
+    CHECK (rate >= 0 AND rate < 100),
+    CHECK (effective_to IS NULL OR effective_to >= effective_from)
+);
+
+CREATE TABLE tax_categories (
+    code VARCHAR(50) PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    is_taxable BOOLEAN NOT NULL DEFAULT true,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+INSERT INTO tax_categories (code, name, is_taxable) VALUES
+    ('hardware', 'Hardware', true),
+    ('software', 'Packaged software', true),
+    ('product', 'Other products', true),
+    ('license', 'Software licences', true),
+    ('saas', 'Cloud subscriptions', false),
+    ('labor', 'Technician labour', false),
+    ('service', 'Professional services', false),
+    ('support', 'Managed support', false),
+    ('freight', 'Shipping and freight', false);
+
+-- States disagree on what is taxable (SaaS, IT labour), so a jurisdiction can override a category
+CREATE TABLE tax_category_rules (
+    jurisdiction_id UUID NOT NULL REFERENCES tax_jurisdictions(id) ON DELETE CASCADE,
+    category_code VARCHAR(50) NOT NULL REFERENCES tax_categories(code) ON DELETE CASCADE,
+    is_taxable BOOLEAN NOT NULL,
+    PRIMARY KEY (jurisdiction_id, category_code)
+);
+
+-- Where a client is taxed when it differs from, or is more precise than, its address
+CREATE TABLE client_tax_profiles (
+    client_id UUID PRIMARY KEY REFERENCES clients(id) ON DELETE CASCADE,
+    state_code VARCHAR(2),
+    county VARCHAR(100),
+    city VARCHAR(100),
+    postal_code VARCHAR(20),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE TABLE tax_exemption_certificates (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    certificate_number VARCHAR(100) NOT NULL,
+    state_code VARCHAR(2) NOT NULL,
+    jurisdiction_id UUID REFERENCES tax_jurisdictions(id) ON DELETE CASCADE, -- NULL covers every jurisdiction in the state
+    category_codes TEXT[], -- NULL covers every category
+    exemption_reason VARCHAR(50) NOT NULL, -- resale, nonprofit, government, manufacturing, other
+    issued_date DATE NOT NULL,
+    expiry_date DATE,
+    file_id UUID REFERENCES files(id) ON DELETE SET NULL,
+    notes TEXT,
+    revoked_at TIMESTAMPTZ,
+    revoked_by UUID REFERENCES users(id),
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+ALTER TABLE invoice_line_items ADD COLUMN IF NOT EXISTS tax_category VARCHAR(50);
+ALTER TABLE recurring_billing_items ADD COLUMN IF NOT EXISTS tax_category VARCHAR(50) REFERENCES tax_categories(code);
+ALTER TABLE expense_categories ADD COLUMN IF NOT EXISTS tax_category VARCHAR(50) REFERENCES tax_categories(code);
+
+UPDATE expense_categories SET tax_category = LOWER(name)
+WHERE tax_category IS NULL AND LOWER(name) IN ('hardware', 'software');
+
+-- One row per line and jurisdiction, including exempt and non-taxable sales for filing
+CREATE TABLE invoice_line_taxes (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    invoice_id UUID NOT NULL REFERENCES invoices(id) ON DELETE CASCADE,
+    invoice_line_item_id UUID NOT NULL REFERENCES invoice_line_items(id) ON DELETE CASCADE,
+    jurisdiction_id UUID REFERENCES tax_jurisdictions(id) ON DELETE SET NULL, -- NULL for a rate entered on the line
+    jurisdiction_name VARCHAR(255) NOT NULL,
+    level VARCHAR(20) NOT NULL, -- state, county, city, special, manual
+    state_code VARCHAR(2),
+    rate DECIMAL(7,4) NOT NULL,
+    is_compound BOOLEAN NOT NULL DEFAULT false,
+    taxable_amount DECIMAL(12,2) NOT NULL DEFAULT 0,
+    exempt_amount DECIMAL(12,2) NOT NULL DEFAULT 0,
+    exempt_reason VARCHAR(20), -- category, certificate
+    exemption_certificate_id UUID REFERENCES tax_exemption_certificates(id) ON DELETE SET NULL,
+    tax_amount DECIMAL(12,2) NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- Indexes
+CREATE INDEX idx_tax_jurisdictions_location ON tax_jurisdictions(state_code, county, city) WHERE is_active = true;
+CREATE INDEX idx_tax_exemption_certificates_client ON tax_exemption_certificates(client_id) WHERE revoked_at IS NULL;
+CREATE INDEX idx_invoice_line_taxes_invoice ON invoice_line_taxes(invoice_id);
+CREATE INDEX idx_invoice_line_taxes_line ON invoice_line_taxes(invoice_line_item_id);
+CREATE INDEX idx_invoice_line_taxes_jurisdiction ON invoice_line_taxes(jurisdiction_id);
@@ -0,0 +1,66 @@
+-- Multi-Currency for GhostHub
+-- Currencies, daily exchange rates, client billing currencies and the rate snapshot stored on each invoice and payment
+
+CREATE TABLE currencies (
+    code VARCHAR(3) PRIMARY KEY, -- ISO 4217
+    name VARCHAR(100) NOT NULL,
+    symbol VARCHAR(10) NOT NULL,
+    minor_units INTEGER NOT NULL DEFAULT 2,
+    is_base BOOLEAN NOT NULL DEFAULT false, -- the currency reports are converted to
+    is_active BOOLEAN NOT NULL DEFAULT true,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    CHECK (code ~ '^[A-Z]{3}$'),
+    CHECK (minor_units BETWEEN 0 AND 4)
+);
+
+INSERT INTO currencies (code, name, symbol, minor_units, is_base) VALUES
+    ('USD', 'US Dollar', '$', 2, true),
+    ('CAD', 'Canadian Dollar', 'CA$', 2, false),
+    ('GBP', 'Pound Sterling', '£', 2, false),
+    ('EUR', 'Euro', '€', 2, false),
+    ('AUD', 'Australian Dollar', 'A$', 2, false);
+
+-- One unit of from_currency is worth `rate` units of to_currency on rate_date
+CREATE TABLE exchange_rates (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    from_currency VARCHAR(3) NOT NULL REFERENCES currencies(code),
+    to_currency VARCHAR(3) NOT NULL REFERENCES currencies(code),
+    rate DECIMAL(18,8) NOT NULL,
+    rate_date DATE NOT NULL,
+    source VARCHAR(50) NOT NULL DEFAULT 'manual', -- manual, provider
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (from_currency, to_currency, rate_date),
+    CHECK (from_currency <> to_currency),
+    CHECK (rate > 0)
+);
+
+ALTER TABLE clients ADD COLUMN IF NOT EXISTS currency VARCHAR(3) NOT NULL DEFAULT 'USD' REFERENCES currencies(code);
+
+-- The rate used when the invoice was raised; base_total never moves with later rates
+ALTER TABLE invoices ADD COLUMN IF NOT EXISTS currency VARCHAR(3) NOT NULL DEFAULT 'USD' REFERENCES currencies(code);
+ALTER TABLE invoices ADD COLUMN IF NOT EXISTS base_currency VARCHAR(3) NOT NULL DEFAULT 'USD' REFERENCES currencies(code);
+ALTER TABLE invoices ADD COLUMN IF NOT EXISTS exchange_rate DECIMAL(18,8) NOT NULL DEFAULT 1;
+ALTER TABLE invoices ADD COLUMN IF NOT EXISTS exchange_rate_date DATE;
+ALTER TABLE invoices ADD COLUMN IF NOT EXISTS base_total DECIMAL(15,2);
+
+UPDATE invoices SET base_total = total, exchange_rate_date = date WHERE base_total IS NULL;
+
+-- Payments are in the invoice currency; the rate on the payment date gives the realised gain or loss
+ALTER TABLE payments ADD COLUMN IF NOT EXISTS currency VARCHAR(3) NOT NULL DEFAULT 'USD' REFERENCES currencies(code);
+ALTER TABLE payments ADD COLUMN IF NOT EXISTS exchange_rate DECIMAL(18,8) NOT NULL DEFAULT 1;
+ALTER TABLE payments ADD COLUMN IF NOT EXISTS base_amount DECIMAL(15,2);
+
+UPDATE payments SET base_amount = amount WHERE base_amount IS NULL;
+
+ALTER TABLE recurring_billing ADD COLUMN IF NOT EXISTS currency VARCHAR(3) NOT NULL DEFAULT 'USD' REFERENCES currencies(code);
+ALTER TABLE credit_notes ADD COLUMN IF NOT EXISTS currency VARCHAR(3) NOT NULL DEFAULT 'USD' REFERENCES currencies(code);
+
+-- What the expense was paid in; it is converted to the client's currency when rebilled
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS currency VARCHAR(3) NOT NULL DEFAULT 'USD' REFERENCES currencies(code);
+
+-- Indexes
+CREATE UNIQUE INDEX idx_currencies_single_base ON currencies(is_base) WHERE is_base = true;
+CREATE INDEX idx_exchange_rates_lookup ON exchange_rates(from_currency, to_currency, rate_date DESC);
+CREATE INDEX idx_invoices_currency ON invoices(currency);
+CREATE INDEX idx_credit_notes_client_currency ON credit_notes(client_id, currency);
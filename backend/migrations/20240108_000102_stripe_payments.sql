@@ -0,0 +1,32 @@
+-- Stripe Payment Collection for GhostHub
+-- Portal checkout, signed webhooks, saved cards and off-session charges for recurring billing
+
+ALTER TABLE clients ADD COLUMN IF NOT EXISTS stripe_customer_id VARCHAR(255);
+
+-- Detached cards are kept for the payment history but can no longer be charged
+ALTER TABLE payment_methods ADD COLUMN IF NOT EXISTS removed_at TIMESTAMPTZ;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_payment_methods_stripe
+    ON payment_methods(stripe_payment_method_id) WHERE stripe_payment_method_id IS NOT NULL;
+
+-- A portal session per checkout; session_token holds the Stripe Checkout Session id
+ALTER TABLE payment_portal_sessions ADD COLUMN IF NOT EXISTS invoice_id UUID REFERENCES invoices(id);
+ALTER TABLE payment_portal_sessions ADD COLUMN IF NOT EXISTS payment_transaction_id UUID REFERENCES payment_transactions(id);
+ALTER TABLE payment_portal_sessions ADD COLUMN IF NOT EXISTS checkout_url TEXT;
+ALTER TABLE payment_portal_sessions ADD COLUMN IF NOT EXISTS completed_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_payment_portal_sessions_transaction ON payment_portal_sessions(payment_transaction_id);
+
+-- One transaction per processor charge, so a replayed webhook can't record a payment twice
+CREATE UNIQUE INDEX IF NOT EXISTS idx_payment_transactions_processor
+    ON payment_transactions(processor, processor_transaction_id) WHERE processor_transaction_id IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_payment_transactions_pending
+    ON payment_transactions(status, initiated_at) WHERE status IN ('pending', 'processing');
+
+-- Stripe delivers events at least once; ids already handled are skipped
+CREATE TABLE stripe_webhook_events (
+    id VARCHAR(255) PRIMARY KEY,
+    event_type VARCHAR(100) NOT NULL,
+    received_at TIMESTAMPTZ DEFAULT NOW()
+);
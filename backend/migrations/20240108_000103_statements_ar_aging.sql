@@ -0,0 +1,26 @@
+-- Account Statements and AR Aging for GhostHub
+-- Periodic client statements built from the invoice ledger, and the weekly aging report delivery log
+
+-- How often a client is sent a statement for the period just ended
+ALTER TABLE clients ADD COLUMN IF NOT EXISTS statement_frequency VARCHAR(20) NOT NULL DEFAULT 'none'
+    CHECK (statement_frequency IN ('none', 'monthly', 'quarterly'));
+
+-- Statements are in the client's billing currency; line_items holds the ledger entries for the period
+ALTER TABLE billing_statements ADD COLUMN IF NOT EXISTS currency VARCHAR(3) REFERENCES currencies(code);
+ALTER TABLE billing_statements ADD COLUMN IF NOT EXISTS pdf_path TEXT;
+UPDATE billing_statements s SET currency = c.currency FROM clients c WHERE c.id = s.client_id AND s.currency IS NULL;
+ALTER TABLE billing_statements ALTER COLUMN currency SET NOT NULL;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_billing_statements_period
+    ON billing_statements(client_id, period_start, period_end);
+
+-- One aging report email per day it is due, however often the worker runs
+CREATE TABLE aging_report_deliveries (
+    report_date DATE PRIMARY KEY,
+    recipients TEXT[] NOT NULL,
+    sent_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- Ledger lookups by client and date
+CREATE INDEX IF NOT EXISTS idx_payments_invoice_date ON payments(invoice_id, payment_date);
+CREATE INDEX IF NOT EXISTS idx_invoices_client_date ON invoices(client_id, date);
@@ -0,0 +1,105 @@
+-- Dunning for GhostHub
+-- Escalating reminder sequences for overdue invoices, late fees, credit holds and payment promises
+
+-- A sequence of stages an overdue invoice moves through
+CREATE TABLE dunning_sequences (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    is_default BOOLEAN NOT NULL DEFAULT false, -- used for clients and contracts without their own sequence
+    is_active BOOLEAN NOT NULL DEFAULT true,   -- assigning an inactive sequence turns dunning off for that client
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX idx_dunning_sequences_default ON dunning_sequences(is_default) WHERE is_default;
+
+-- Templates use {{client_name}}, {{invoice_number}}, {{amount_due}}, {{due_date}}, {{days_overdue}},
+-- {{late_fee}} and {{pay_url}}
+CREATE TABLE dunning_stages (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    sequence_id UUID NOT NULL REFERENCES dunning_sequences(id) ON DELETE CASCADE,
+    stage_number INTEGER NOT NULL CHECK (stage_number > 0),
+    days_overdue INTEGER NOT NULL CHECK (days_overdue >= 0),
+    subject_template TEXT NOT NULL,
+    body_template TEXT NOT NULL,
+    priority VARCHAR(20) NOT NULL DEFAULT 'normal',
+    late_fee_rule_id UUID REFERENCES billing_automation_rules(id) ON DELETE SET NULL, -- an apply_late_fee rule
+    place_credit_hold BOOLEAN NOT NULL DEFAULT false,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (sequence_id, stage_number)
+);
+
+-- Contract sequence wins over the client's, which wins over the default
+ALTER TABLE clients ADD COLUMN IF NOT EXISTS dunning_sequence_id UUID REFERENCES dunning_sequences(id) ON DELETE SET NULL;
+ALTER TABLE contracts ADD COLUMN IF NOT EXISTS dunning_sequence_id UUID REFERENCES dunning_sequences(id) ON DELETE SET NULL;
+
+-- Clients on credit hold are flagged to technicians; holds placed by dunning lift once the invoices are paid
+ALTER TABLE clients ADD COLUMN IF NOT EXISTS credit_hold BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE clients ADD COLUMN IF NOT EXISTS credit_hold_reason TEXT;
+ALTER TABLE clients ADD COLUMN IF NOT EXISTS credit_hold_source VARCHAR(20) CHECK (credit_hold_source IN ('dunning', 'manual'));
+ALTER TABLE clients ADD COLUMN IF NOT EXISTS credit_hold_at TIMESTAMPTZ;
+
+-- Each stage reached by an invoice, so no stage runs twice
+CREATE TABLE dunning_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    invoice_id UUID NOT NULL REFERENCES invoices(id) ON DELETE CASCADE,
+    sequence_id UUID NOT NULL REFERENCES dunning_sequences(id),
+    stage_number INTEGER NOT NULL,
+    days_overdue INTEGER NOT NULL,
+    contact_id UUID REFERENCES contacts(id), -- who the reminder went to, NULL if nobody could be emailed
+    late_fee_amount DECIMAL(15,2) NOT NULL DEFAULT 0,
+    credit_hold_placed BOOLEAN NOT NULL DEFAULT false,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (invoice_id, sequence_id, stage_number)
+);
+
+-- A promise to pay pauses dunning for the invoice (or whole account when invoice_id is NULL) until promised_date
+CREATE TABLE payment_promises (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    invoice_id UUID REFERENCES invoices(id) ON DELETE CASCADE,
+    promised_amount DECIMAL(15,2) NOT NULL CHECK (promised_amount > 0),
+    promised_date DATE NOT NULL,
+    notes TEXT,
+    status VARCHAR(20) NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'kept', 'broken', 'cancelled')),
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ
+);
+
+-- Late fees are added to the invoice as a line and recorded as a balance adjustment
+ALTER TABLE invoice_adjustments DROP CONSTRAINT IF EXISTS invoice_adjustments_adjustment_type_check;
+ALTER TABLE invoice_adjustments ADD CONSTRAINT invoice_adjustments_adjustment_type_check
+    CHECK (adjustment_type IN ('void', 'partial_void', 'credit', 'refund', 'late_fee'));
+
+-- Indexes
+CREATE INDEX idx_dunning_stages_sequence ON dunning_stages(sequence_id, days_overdue);
+CREATE INDEX idx_dunning_events_invoice ON dunning_events(invoice_id, created_at);
+CREATE INDEX idx_payment_promises_client ON payment_promises(client_id, status);
+CREATE INDEX idx_payment_promises_invoice ON payment_promises(invoice_id) WHERE invoice_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_clients_credit_hold ON clients(id) WHERE credit_hold;
+
+-- Default sequence, inactive until billing turns it on
+WITH seq AS (
+    INSERT INTO dunning_sequences (name, description, is_default, is_active)
+    VALUES ('Standard', 'Friendly reminder, follow-ups and a final notice with credit hold', true, false)
+    RETURNING id
+)
+INSERT INTO dunning_stages (sequence_id, stage_number, days_overdue, subject_template, body_template, priority, place_credit_hold)
+SELECT seq.id, s.stage_number, s.days_overdue, s.subject_template, s.body_template, s.priority, s.place_credit_hold
+FROM seq, (VALUES
+    (1, 1, 'Reminder: invoice {{invoice_number}} is due',
+     'Hi {{client_name}}, a friendly reminder that invoice {{invoice_number}} for {{amount_due}} was due on {{due_date}}. You can pay online at {{pay_url}}.',
+     'normal', false),
+    (2, 7, 'Invoice {{invoice_number}} is {{days_overdue}} days overdue',
+     'Hi {{client_name}}, invoice {{invoice_number}} is now {{days_overdue}} days past due with {{amount_due}} outstanding. Please arrange payment at {{pay_url}} or let us know when to expect it.',
+     'normal', false),
+    (3, 14, 'Second notice: invoice {{invoice_number}}',
+     'Hi {{client_name}}, we have not yet received payment for invoice {{invoice_number}}, now {{days_overdue}} days overdue. Amount due: {{amount_due}}. Pay at {{pay_url}}.',
+     'high', false),
+    (4, 30, 'Final notice: invoice {{invoice_number}} - account on hold',
+     'Hi {{client_name}}, invoice {{invoice_number}} is {{days_overdue}} days overdue and your account has been placed on credit hold. Non-urgent work is paused until {{amount_due}} is paid at {{pay_url}}.',
+     'high', true)
+) AS s(stage_number, days_overdue, subject_template, body_template, priority, place_credit_hold);
@@ -0,0 +1,52 @@
+-- Accounting sync for GhostHub
+-- Account mappings, journal exports for QuickBooks/Xero and per-record sync state for the accounting API
+
+-- Where GhostHub amounts land in the books. source_key is 'default', a line's tax category or source type
+-- (income_account), a payment method or processor (deposit_account) or a client id (customer)
+CREATE TABLE accounting_mappings (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    mapping_type VARCHAR(30) NOT NULL CHECK (mapping_type IN (
+        'customer', 'income_account', 'deposit_account', 'receivable_account', 'tax_account', 'credit_account'
+    )),
+    source_key VARCHAR(100) NOT NULL DEFAULT 'default',
+    account_name VARCHAR(255) NOT NULL,  -- account or customer name as it appears in the books
+    account_code VARCHAR(50),            -- account code, required by Xero imports
+    external_id VARCHAR(100),            -- id in the accounting API, required for API sync
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (mapping_type, source_key)
+);
+
+-- One row per GhostHub record per target; a changed content_hash means the record is pushed again
+CREATE TABLE accounting_sync_records (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    target VARCHAR(30) NOT NULL,       -- quickbooks_online, or an export format
+    entity_type VARCHAR(30) NOT NULL,  -- client, invoice, payment, refund, credit_note
+    entity_id UUID NOT NULL,
+    external_id VARCHAR(100),
+    external_version VARCHAR(50),      -- e.g. the QuickBooks SyncToken
+    content_hash VARCHAR(64),
+    last_payload JSONB,                -- the journal entry last sent, reversed when the record changes
+    status VARCHAR(20) NOT NULL DEFAULT 'synced' CHECK (status IN ('synced', 'error', 'removed')),
+    last_error TEXT,
+    synced_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (target, entity_type, entity_id)
+);
+
+-- Journal files produced for import
+CREATE TABLE accounting_exports (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    format VARCHAR(30) NOT NULL CHECK (format IN ('quickbooks_iif', 'quickbooks_csv', 'xero_csv')),
+    period_start DATE NOT NULL,
+    period_end DATE NOT NULL,
+    changes_only BOOLEAN NOT NULL DEFAULT false, -- only records new or changed since the last export
+    entry_count INTEGER NOT NULL DEFAULT 0,
+    file_path VARCHAR(500) NOT NULL,
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- Indexes
+CREATE INDEX idx_accounting_sync_records_status ON accounting_sync_records(target, status);
+CREATE INDEX idx_accounting_exports_created ON accounting_exports(created_at DESC);
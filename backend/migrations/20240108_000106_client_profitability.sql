@@ -0,0 +1,39 @@
+-- Client profitability for GhostHub
+-- Monthly revenue, labour, expense and license cost per client, effective hourly rate per agreement,
+-- and budget-versus-actual tracking
+
+-- What an hour of a technician's time costs us, as opposed to what it is billed at (hourly_rate)
+ALTER TABLE users ADD COLUMN IF NOT EXISTS cost_rate DECIMAL(10,2);
+
+-- Licenses are costed monthly from their annual cost
+ALTER TABLE client_profitability ADD COLUMN IF NOT EXISTS license_cost DECIMAL(10,2) DEFAULT 0;
+
+-- Per agreement per month; a flat fee spread over the hours actually worked
+CREATE TABLE contract_profitability (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    contract_id UUID NOT NULL REFERENCES contracts(id) ON DELETE CASCADE,
+    client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    period_start DATE NOT NULL,
+    period_end DATE NOT NULL,
+    revenue DECIMAL(10,2) NOT NULL DEFAULT 0,
+    revenue_source VARCHAR(20) NOT NULL DEFAULT 'invoiced' CHECK (revenue_source IN ('invoiced', 'contract_value')),
+    hours_worked DECIMAL(10,2) NOT NULL DEFAULT 0,
+    labor_cost DECIMAL(10,2) NOT NULL DEFAULT 0,
+    gross_profit DECIMAL(10,2) NOT NULL DEFAULT 0,
+    gross_margin_percent DECIMAL(7,2) NOT NULL DEFAULT 0,
+    effective_hourly_rate DECIMAL(10,2), -- NULL when no time was logged
+    calculated_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (contract_id, period_start)
+);
+
+-- Losses can exceed -999.99%
+ALTER TABLE client_profitability ALTER COLUMN gross_margin_percent TYPE DECIMAL(7,2);
+ALTER TABLE client_profitability ALTER COLUMN net_margin_percent TYPE DECIMAL(7,2);
+
+-- Budget alerts fire once per threshold
+ALTER TABLE client_budgets ADD COLUMN IF NOT EXISTS exceeded_alert_sent_at TIMESTAMPTZ;
+
+-- Indexes
+CREATE INDEX idx_contract_profitability_client ON contract_profitability(client_id, period_start);
+CREATE INDEX IF NOT EXISTS idx_client_profitability_period ON client_profitability(period_start);
+CREATE INDEX IF NOT EXISTS idx_client_budgets_active ON client_budgets(status, end_date);
@@ -0,0 +1,78 @@
+-- Pre-billing review for GhostHub
+-- Unbilled time, expenses and held recurring invoices are staged per client, cleaned up by a reviewer
+-- (adjusted, written off, deferred, reworded, merged) and approved into invoices in batches
+
+CREATE TABLE billing_reviews (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    through_date DATE NOT NULL, -- work up to and including this date
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved', 'cancelled')),
+    invoice_id UUID REFERENCES invoices(id) ON DELETE SET NULL, -- time and expenses; held recurring invoices keep their own
+    notes TEXT,
+    approved_by UUID REFERENCES users(id),
+    approved_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- New work is added to the client's open review rather than starting another
+CREATE UNIQUE INDEX idx_billing_reviews_open ON billing_reviews(client_id) WHERE status = 'pending';
+
+CREATE TABLE billing_review_items (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    review_id UUID NOT NULL REFERENCES billing_reviews(id) ON DELETE CASCADE,
+    source_type VARCHAR(20) NOT NULL CHECK (source_type IN ('time_entry', 'expense', 'recurring')),
+    source_id UUID NOT NULL, -- time entry, expense, or line of a held recurring invoice
+    work_date DATE,
+    original_description TEXT NOT NULL,
+    description TEXT NOT NULL,
+    original_quantity DECIMAL(10,2) NOT NULL,
+    quantity DECIMAL(10,2) NOT NULL,
+    original_unit_price DECIMAL(12,2) NOT NULL,
+    unit_price DECIMAL(12,2) NOT NULL,
+    tax_category VARCHAR(50),
+    action VARCHAR(20) NOT NULL DEFAULT 'bill' CHECK (action IN ('bill', 'write_off', 'defer')),
+    action_reason TEXT,
+    deferred_until DATE,
+    merged_into UUID REFERENCES billing_review_items(id) ON DELETE SET NULL,
+    updated_by UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- Value given up in review, by source, for profitability
+CREATE TABLE billing_write_offs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    review_id UUID REFERENCES billing_reviews(id) ON DELETE SET NULL,
+    source_type VARCHAR(20) NOT NULL,
+    source_id UUID NOT NULL,
+    work_date DATE NOT NULL,
+    quantity DECIMAL(10,2) NOT NULL,
+    amount DECIMAL(12,2) NOT NULL, -- client currency
+    currency VARCHAR(3) NOT NULL,
+    base_amount DECIMAL(12,2) NOT NULL,
+    reason TEXT,
+    written_off_by UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS written_off_at TIMESTAMPTZ;
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS deferred_until DATE;
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS written_off_at TIMESTAMPTZ;
+ALTER TABLE expenses ADD COLUMN IF NOT EXISTS deferred_until DATE;
+
+-- NULL for invoices that never needed approval
+ALTER TABLE invoices ADD COLUMN IF NOT EXISTS approval_status VARCHAR(20)
+    CHECK (approval_status IN ('pending', 'approved'));
+ALTER TABLE invoices ADD COLUMN IF NOT EXISTS approved_by UUID REFERENCES users(id);
+ALTER TABLE invoices ADD COLUMN IF NOT EXISTS approved_at TIMESTAMPTZ;
+
+ALTER TABLE client_profitability ADD COLUMN IF NOT EXISTS write_off_amount DECIMAL(10,2) DEFAULT 0;
+
+-- Indexes
+CREATE INDEX idx_billing_reviews_status ON billing_reviews(status, created_at);
+CREATE INDEX idx_billing_review_items_review ON billing_review_items(review_id);
+CREATE INDEX idx_billing_review_items_source ON billing_review_items(source_type, source_id);
+CREATE INDEX idx_billing_write_offs_client ON billing_write_offs(client_id, work_date);
+CREATE INDEX idx_invoices_approval ON invoices(approval_status) WHERE approval_status = 'pending';
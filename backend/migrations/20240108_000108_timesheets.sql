@@ -0,0 +1,47 @@
+-- Timesheets for GhostHub
+-- Weekly submission and manager approval, locking of approved and billed time, and expected working hours
+-- for missing-time detection
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS manager_id UUID REFERENCES users(id) ON DELETE SET NULL;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS expected_daily_hours DECIMAL(4,2) DEFAULT 8;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS working_days SMALLINT[] DEFAULT '{1,2,3,4,5}'; -- ISO weekdays, Monday = 1
+
+-- One per technician per week; rows appear when a week is submitted or flagged for missing time
+CREATE TABLE timesheets (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    week_start DATE NOT NULL CHECK (EXTRACT(ISODOW FROM week_start) = 1),
+    status VARCHAR(20) NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'submitted', 'approved', 'rejected')),
+    total_minutes INTEGER NOT NULL DEFAULT 0,
+    billable_minutes INTEGER NOT NULL DEFAULT 0,
+    expected_minutes INTEGER NOT NULL DEFAULT 0,
+    submitted_at TIMESTAMPTZ,
+    submission_note TEXT,
+    reviewed_by UUID REFERENCES users(id),
+    reviewed_at TIMESTAMPTZ,
+    review_comment TEXT,
+    missing_time_notified_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (user_id, week_start)
+);
+
+CREATE TABLE timesheet_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    timesheet_id UUID NOT NULL REFERENCES timesheets(id) ON DELETE CASCADE,
+    action VARCHAR(20) NOT NULL CHECK (action IN ('submitted', 'approved', 'rejected')),
+    comment TEXT,
+    actor_id UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- Locked entries are corrected by an adjustment entry in an open week
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS adjusts_entry_id UUID REFERENCES time_entries(id) ON DELETE SET NULL;
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS adjustment_reason TEXT;
+
+-- Indexes
+CREATE INDEX idx_timesheets_status ON timesheets(status, week_start);
+CREATE INDEX idx_timesheet_events_timesheet ON timesheet_events(timesheet_id, created_at);
+CREATE INDEX idx_time_entries_adjusts ON time_entries(adjusts_entry_id) WHERE adjusts_entry_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_time_entries_user_start ON time_entries(user_id, start_time);
+CREATE INDEX idx_users_manager ON users(manager_id) WHERE manager_id IS NOT NULL;
@@ -0,0 +1,39 @@
+-- Timer reconciliation for GhostHub
+-- Long-running timer alerts, auto-stop at the end of the working day pending confirmation, overlapping entry
+-- detection, and idempotent sync of timer events recorded offline
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS workday_end TIME DEFAULT '18:00'; -- in users.timezone
+
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS long_running_notified_at TIMESTAMPTZ;
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS auto_stopped_at TIMESTAMPTZ;
+-- Auto-stopped entries are held back from billing until the technician confirms or corrects them
+ALTER TABLE time_entries ADD COLUMN IF NOT EXISTS pending_confirmation BOOLEAN NOT NULL DEFAULT false;
+
+-- Pairs are stored once, lower entry id first
+CREATE TABLE time_entry_overlaps (
+    entry_id UUID NOT NULL REFERENCES time_entries(id) ON DELETE CASCADE,
+    other_entry_id UUID NOT NULL REFERENCES time_entries(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    overlap_minutes INTEGER NOT NULL,
+    detected_at TIMESTAMPTZ DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ, -- set once the entries no longer overlap
+    PRIMARY KEY (entry_id, other_entry_id)
+);
+
+-- Timer events queued by a client while offline, applied once each
+CREATE TABLE timer_sync_events (
+    client_event_id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    event_type VARCHAR(20) NOT NULL CHECK (event_type IN ('start', 'stop', 'switch')),
+    occurred_at TIMESTAMPTZ NOT NULL,
+    time_entry_id UUID REFERENCES time_entries(id) ON DELETE SET NULL,
+    status VARCHAR(20) NOT NULL CHECK (status IN ('applied', 'rejected')),
+    message TEXT,
+    received_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- Indexes
+CREATE INDEX idx_time_entries_running ON time_entries(user_id) WHERE end_time IS NULL;
+CREATE INDEX idx_time_entries_pending_confirmation ON time_entries(user_id) WHERE pending_confirmation = true;
+CREATE INDEX idx_time_entry_overlaps_open ON time_entry_overlaps(user_id) WHERE resolved_at IS NULL;
+CREATE INDEX idx_timer_sync_events_user ON timer_sync_events(user_id, occurred_at);
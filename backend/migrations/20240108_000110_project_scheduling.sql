@@ -0,0 +1,57 @@
+-- Project scheduling for GhostHub
+-- Template task plans with relative dates and dependencies, finish-to-start task dependencies with lag,
+-- milestones, and scheduled dates for Gantt and critical path. Durations and offsets are in working days.
+
+ALTER TABLE project_templates ADD COLUMN IF NOT EXISTS category VARCHAR(50); -- onboarding, migration, ...
+ALTER TABLE project_templates ADD COLUMN IF NOT EXISTS is_active BOOLEAN DEFAULT true;
+ALTER TABLE project_templates ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ DEFAULT NOW();
+
+-- Replaces project_templates.default_tasks
+CREATE TABLE project_template_tasks (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    template_id UUID NOT NULL REFERENCES project_templates(id) ON DELETE CASCADE,
+    sort_order INTEGER NOT NULL DEFAULT 0,
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    start_offset_days INTEGER NOT NULL DEFAULT 0 CHECK (start_offset_days >= 0), -- earliest start after project start
+    duration_days INTEGER NOT NULL DEFAULT 1 CHECK (duration_days >= 0), -- 0 for milestones
+    estimated_hours DECIMAL(8,2),
+    is_milestone BOOLEAN NOT NULL DEFAULT false,
+    priority VARCHAR(50) DEFAULT 'medium',
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE TABLE project_template_dependencies (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    template_id UUID NOT NULL REFERENCES project_templates(id) ON DELETE CASCADE,
+    task_id UUID NOT NULL REFERENCES project_template_tasks(id) ON DELETE CASCADE,
+    depends_on_id UUID NOT NULL REFERENCES project_template_tasks(id) ON DELETE CASCADE,
+    lag_days INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (task_id, depends_on_id),
+    CHECK (task_id <> depends_on_id)
+);
+
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS start_date DATE; -- scheduled
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS start_constraint DATE; -- start no earlier than
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS duration_days INTEGER CHECK (duration_days >= 0);
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS is_milestone BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS sort_order INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS template_task_id UUID REFERENCES project_template_tasks(id) ON DELETE SET NULL;
+
+CREATE TABLE task_dependencies (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    task_id UUID NOT NULL REFERENCES tasks(id) ON DELETE CASCADE, -- successor
+    depends_on_task_id UUID NOT NULL REFERENCES tasks(id) ON DELETE CASCADE, -- predecessor
+    dependency_type VARCHAR(20) NOT NULL DEFAULT 'finish_to_start' CHECK (dependency_type IN ('finish_to_start')),
+    lag_days INTEGER NOT NULL DEFAULT 0, -- negative for lead time
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (task_id, depends_on_task_id),
+    CHECK (task_id <> depends_on_task_id)
+);
+
+-- Indexes
+CREATE INDEX idx_project_template_tasks_template ON project_template_tasks(template_id, sort_order);
+CREATE INDEX idx_project_template_dependencies_template ON project_template_dependencies(template_id);
+CREATE INDEX idx_task_dependencies_task ON task_dependencies(task_id);
+CREATE INDEX idx_task_dependencies_depends_on ON task_dependencies(depends_on_task_id);
+CREATE INDEX IF NOT EXISTS idx_tasks_assigned_dates ON tasks(assigned_to, start_date, due_date) WHERE assigned_to IS NOT NULL;
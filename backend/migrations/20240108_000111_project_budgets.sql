@@ -0,0 +1,44 @@
+-- Project budgets for GhostHub
+-- Fixed-fee and time-and-materials budgets in hours and money, burn alerts at 50/80/100%, and change orders
+-- approved by the client through the portal
+
+-- Fixed-fee projects burn all time and expenses against the fee; T&M projects only what is billable
+ALTER TABLE projects ADD COLUMN IF NOT EXISTS billing_type VARCHAR(30) NOT NULL DEFAULT 'time_and_materials'
+    CHECK (billing_type IN ('fixed_fee', 'time_and_materials'));
+ALTER TABLE projects ADD COLUMN IF NOT EXISTS budget_hours DECIMAL(10,2);
+-- Highest alert threshold sent (0, 50, 80 or 100); lowered when a change order adds budget
+ALTER TABLE projects ADD COLUMN IF NOT EXISTS budget_alert_level INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE projects ADD COLUMN IF NOT EXISTS budget_alerted_at TIMESTAMPTZ;
+
+CREATE TABLE project_change_orders (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    project_id UUID NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    number INTEGER NOT NULL, -- per project, CO-1, CO-2, ...
+    title VARCHAR(255) NOT NULL,
+    description TEXT,
+    amount_change DECIMAL(15,2) NOT NULL DEFAULT 0, -- negative to reduce scope
+    hours_change DECIMAL(10,2) NOT NULL DEFAULT 0,
+    schedule_days_change INTEGER NOT NULL DEFAULT 0,
+    status VARCHAR(20) NOT NULL DEFAULT 'draft'
+        CHECK (status IN ('draft', 'sent', 'approved', 'rejected', 'cancelled')),
+    contact_id UUID REFERENCES contacts(id) ON DELETE SET NULL,
+    sent_at TIMESTAMPTZ,
+    approved_at TIMESTAMPTZ,
+    approved_name VARCHAR(255), -- typed signature, or the staff member recording approval
+    approved_by_contact_id UUID REFERENCES contacts(id) ON DELETE SET NULL,
+    approved_by_user_id UUID REFERENCES users(id),
+    approved_ip VARCHAR(64),
+    approved_user_agent TEXT,
+    rejected_at TIMESTAMPTZ,
+    rejection_reason TEXT,
+    created_by UUID NOT NULL REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE (project_id, number)
+);
+
+-- Indexes
+CREATE INDEX idx_project_change_orders_project ON project_change_orders(project_id, status);
+CREATE INDEX IF NOT EXISTS idx_time_entries_project ON time_entries(project_id) WHERE project_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_projects_budget_active ON projects(status)
+    WHERE budget IS NOT NULL OR budget_hours IS NOT NULL;
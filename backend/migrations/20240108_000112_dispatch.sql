@@ -0,0 +1,71 @@
+-- Technician dispatch for GhostHub
+-- Appointments against tickets and project tasks, technician working hours and time off, travel time between
+-- client locations, and per-technician calendar feeds
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS workday_start TIME DEFAULT '09:00'; -- in users.timezone
+-- Secret in the technician's iCalendar feed URL; regenerating it revokes old subscriptions
+ALTER TABLE users ADD COLUMN IF NOT EXISTS calendar_token VARCHAR(64) UNIQUE;
+
+-- Weekly working hours; technicians without rows work users.working_days from workday_start to workday_end
+CREATE TABLE technician_availability (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    weekday SMALLINT NOT NULL CHECK (weekday BETWEEN 1 AND 7), -- ISO, Monday = 1
+    start_time TIME NOT NULL, -- in users.timezone
+    end_time TIME NOT NULL,
+    CHECK (end_time > start_time)
+);
+
+CREATE TABLE technician_time_off (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    starts_at TIMESTAMPTZ NOT NULL,
+    ends_at TIMESTAMPTZ NOT NULL,
+    kind VARCHAR(20) NOT NULL DEFAULT 'vacation' CHECK (kind IN ('vacation', 'sick', 'training', 'holiday', 'other')),
+    reason TEXT,
+    created_by UUID REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    CHECK (ends_at > starts_at)
+);
+
+-- Driving time between client sites; pairs are stored once, lower location id first
+CREATE TABLE location_travel_times (
+    from_location_id UUID NOT NULL REFERENCES locations(id) ON DELETE CASCADE,
+    to_location_id UUID NOT NULL REFERENCES locations(id) ON DELETE CASCADE,
+    minutes INTEGER NOT NULL CHECK (minutes >= 0),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (from_location_id, to_location_id),
+    CHECK (from_location_id < to_location_id)
+);
+
+CREATE TABLE appointments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    client_id UUID NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    ticket_id UUID REFERENCES tickets(id) ON DELETE CASCADE,
+    task_id UUID REFERENCES tasks(id) ON DELETE CASCADE,
+    technician_id UUID NOT NULL REFERENCES users(id),
+    location_id UUID REFERENCES locations(id) ON DELETE SET NULL, -- NULL for remote work
+    contact_id UUID REFERENCES contacts(id) ON DELETE SET NULL,
+    title VARCHAR(255) NOT NULL,
+    notes TEXT,
+    start_time TIMESTAMPTZ NOT NULL,
+    end_time TIMESTAMPTZ NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'scheduled'
+        CHECK (status IN ('scheduled', 'confirmed', 'in_progress', 'completed', 'cancelled', 'no_show')),
+    sequence INTEGER NOT NULL DEFAULT 0, -- iCalendar revision, bumped on every reschedule
+    confirmation_sent_at TIMESTAMPTZ,
+    cancelled_at TIMESTAMPTZ,
+    cancellation_reason TEXT,
+    created_by UUID NOT NULL REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+    CHECK (end_time > start_time),
+    CHECK ((ticket_id IS NOT NULL) <> (task_id IS NOT NULL))
+);
+
+-- Indexes
+CREATE INDEX idx_technician_availability_user ON technician_availability(user_id, weekday);
+CREATE INDEX idx_technician_time_off_user ON technician_time_off(user_id, starts_at);
+CREATE INDEX idx_appointments_technician ON appointments(technician_id, start_time);
+CREATE INDEX idx_appointments_ticket ON appointments(ticket_id) WHERE ticket_id IS NOT NULL;
+CREATE INDEX idx_appointments_task ON appointments(task_id) WHERE task_id IS NOT NULL;
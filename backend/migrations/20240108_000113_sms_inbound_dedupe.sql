@@ -0,0 +1,5 @@
+-- Provider retries of an inbound SMS webhook carry the same MessageSid; record each one once
+DROP INDEX IF EXISTS idx_sms_messages_external;
+CREATE INDEX idx_sms_messages_external ON sms_messages(provider_id_external) WHERE direction = 'outbound';
+CREATE UNIQUE INDEX idx_sms_messages_inbound_external ON sms_messages(provider_id_external)
+    WHERE direction = 'inbound' AND provider_id_external IS NOT NULL;
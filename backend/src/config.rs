@@ -1,11 +1,17 @@
 use std::env;
+use std::sync::OnceLock;
 use serde::{Deserialize, Serialize};
 
+const DEFAULT_APP_BASE_URL: &str = "https://ghosthub.local";
+
+static APP_BASE_URL: OnceLock<String> = OnceLock::new();
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub database_url: String,
     pub server_addr: String,
     pub jwt_secret: String,
+    pub redis_url: Option<String>,
     pub smtp: SmtpConfig,
 }
 
@@ -29,6 +35,8 @@ impl Config {
                 .unwrap_or_else(|_| "0.0.0.0:8080".to_string()),
             jwt_secret: env::var("JWT_SECRET")
                 .unwrap_or_else(|_| "your-secret-key-change-in-production".to_string()),
+            // Required when running more than one API replica
+            redis_url: env::var("REDIS_URL").ok(),
             smtp: SmtpConfig {
                 // SMTP2GO configuration
                 host: env::var("SMTP_HOST").unwrap_or_else(|_| "mail.smtp2go.com".to_string()),
@@ -51,4 +59,15 @@ impl Config {
             },
         })
     }
-}
\ No newline at end of file
+}
+
+/// Public address of the web app, without a trailing slash, for links in
+/// emails, portal notices and calendar feeds. Read once from APP_BASE_URL.
+pub fn app_base_url() -> &'static str {
+    APP_BASE_URL.get_or_init(|| {
+        env::var("APP_BASE_URL")
+            .unwrap_or_else(|_| DEFAULT_APP_BASE_URL.to_string())
+            .trim_end_matches('/')
+            .to_string()
+    })
+}
@@ -244,54 +244,9 @@ async fn upload_file(
         return Err(StatusCode::BAD_REQUEST);
     }
 
-    // Generate unique filename and file path
-    let file_id = Uuid::new_v4();
-    let file_extension = std::path::Path::new(&original_filename)
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .unwrap_or("");
-    
-    let filename = if file_extension.is_empty() {
-        file_id.to_string()
-    } else {
-        format!("{}.{}", file_id, file_extension)
-    };
-
-    // Create upload directory if it doesn't exist
-    let upload_dir = get_upload_directory();
-    fs::create_dir_all(&upload_dir).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    // Write file to disk
-    let file_path = format!("{}/{}", upload_dir, filename);
-    let mut file = fs::File::create(&file_path).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    file.write_all(&file_data).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    // Save file metadata to database
-    sqlx::query!(
-        r#"
-        INSERT INTO files (
-            id, client_id, ticket_id, asset_id, project_id, kb_article_id,
-            filename, original_filename, mime_type, file_size, file_path,
-            uploaded_by, created_at
-        )
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, NOW())
-        "#,
-        file_id,
-        client_id,
-        ticket_id,
-        asset_id,
-        project_id,
-        kb_article_id,
-        filename,
-        original_filename,
-        mime_type,
-        file_data.len() as i64,
-        file_path,
-        auth.0.id
-    )
-    .execute(&state.db_pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let links = FileLinks { client_id, ticket_id, asset_id, project_id, kb_article_id };
+    let (file_id, filename) =
+        store_file(&state.db_pool, auth.0.id, &links, &original_filename, &mime_type, &file_data).await?;
 
     // Log the upload
     log_audit_action(&state.db_pool, auth.0.id, "UPLOAD", "file", file_id).await;
@@ -388,7 +343,79 @@ async fn delete_file(
     Ok(Json(serde_json::json!({ "message": "File deleted successfully" })))
 }
 
-fn get_upload_directory() -> String {
+/// Records an uploaded file can be attached to.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct FileLinks {
+    pub client_id: Option<Uuid>,
+    pub ticket_id: Option<Uuid>,
+    pub asset_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub kb_article_id: Option<Uuid>,
+}
+
+/// Writes an upload to the upload directory and records it in `files`,
+/// returning the new file id and stored filename.
+pub(crate) async fn store_file(
+    db_pool: &sqlx::PgPool,
+    uploaded_by: Uuid,
+    links: &FileLinks,
+    original_filename: &str,
+    mime_type: &str,
+    file_data: &[u8],
+) -> Result<(Uuid, String), StatusCode> {
+    // Generate unique filename and file path
+    let file_id = Uuid::new_v4();
+    let file_extension = std::path::Path::new(original_filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("");
+    
+    let filename = if file_extension.is_empty() {
+        file_id.to_string()
+    } else {
+        format!("{}.{}", file_id, file_extension)
+    };
+
+    // Create upload directory if it doesn't exist
+    let upload_dir = get_upload_directory();
+    fs::create_dir_all(&upload_dir).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Write file to disk
+    let file_path = format!("{}/{}", upload_dir, filename);
+    let mut file = fs::File::create(&file_path).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    file.write_all(file_data).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Save file metadata to database
+    sqlx::query!(
+        r#"
+        INSERT INTO files (
+            id, client_id, ticket_id, asset_id, project_id, kb_article_id,
+            filename, original_filename, mime_type, file_size, file_path,
+            uploaded_by, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, NOW())
+        "#,
+        file_id,
+        links.client_id,
+        links.ticket_id,
+        links.asset_id,
+        links.project_id,
+        links.kb_article_id,
+        filename,
+        original_filename,
+        mime_type,
+        file_data.len() as i64,
+        file_path,
+        uploaded_by
+    )
+    .execute(db_pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((file_id, filename))
+}
+
+pub(crate) fn get_upload_directory() -> String {
     std::env::var("UPLOAD_DIRECTORY").unwrap_or_else(|_| "./uploads".to_string())
 }
 
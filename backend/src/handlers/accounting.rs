@@ -0,0 +1,289 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+    routing::{delete, get, post},
+    Router,
+};
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::handlers::internal;
+use crate::integrations::quickbooks::QuickBooksClient;
+use crate::services::accounting::{
+    self, AccountingExport, AccountingMapping, SyncRecord, SyncSummary, ENTITY_TYPES, EXPORT_COLUMNS,
+    EXPORT_FORMATS, MAPPING_COLUMNS, MAPPING_TYPES, SYNC_RECORD_COLUMNS,
+};
+use crate::AppState;
+
+pub fn accounting_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/mappings", get(list_mappings).put(upsert_mapping))
+        .route("/mappings/:id", delete(delete_mapping))
+        .route("/exports", get(list_exports).post(create_export))
+        .route("/exports/:id/download", get(download_export))
+        .route("/sync", post(run_sync))
+        .route("/sync-records", get(list_sync_records))
+        .route("/sync-records/resync", post(resync_records))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpsertMapping {
+    pub mapping_type: String,
+    pub source_key: Option<String>, // defaults to 'default'
+    pub account_name: String,
+    pub account_code: Option<String>,
+    pub external_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateExport {
+    pub format: String,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub changes_only: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncRecordQuery {
+    pub target: Option<String>,
+    pub status: Option<String>,
+    pub entity_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResyncRequest {
+    pub target: String,
+    pub entity_type: Option<String>, // all record types when omitted
+    pub entity_ids: Option<Vec<Uuid>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResyncResult {
+    pub marked: u64,
+}
+
+async fn list_mappings(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+) -> Result<Json<Vec<AccountingMapping>>, StatusCode> {
+    let mappings = sqlx::query_as::<_, AccountingMapping>(&format!(
+        "SELECT {} FROM accounting_mappings ORDER BY mapping_type, source_key",
+        MAPPING_COLUMNS
+    ))
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching accounting mappings"))?;
+
+    Ok(Json(mappings))
+}
+
+/// Creates or replaces the mapping for a type and source key. Changed mappings
+/// change the journal entries they feed, so affected records re-sync.
+async fn upsert_mapping(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Json(payload): Json<UpsertMapping>,
+) -> Result<Json<AccountingMapping>, StatusCode> {
+    if !MAPPING_TYPES.contains(&payload.mapping_type.as_str()) || payload.account_name.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let source_key = payload.source_key.filter(|k| !k.trim().is_empty()).unwrap_or_else(|| "default".to_string());
+
+    let mapping = sqlx::query_as::<_, AccountingMapping>(&format!(
+        "INSERT INTO accounting_mappings (mapping_type, source_key, account_name, account_code, external_id)
+         VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (mapping_type, source_key) DO UPDATE SET
+             account_name = EXCLUDED.account_name,
+             account_code = EXCLUDED.account_code,
+             external_id = EXCLUDED.external_id,
+             updated_at = NOW()
+         RETURNING {}",
+        MAPPING_COLUMNS
+    ))
+    .bind(&payload.mapping_type)
+    .bind(source_key.trim())
+    .bind(payload.account_name.trim())
+    .bind(&payload.account_code)
+    .bind(&payload.external_id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(internal("saving accounting mapping"))?;
+
+    Ok(Json(mapping))
+}
+
+async fn delete_mapping(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query("DELETE FROM accounting_mappings WHERE id = $1")
+        .bind(id)
+        .execute(&state.db_pool)
+        .await
+        .map_err(internal("deleting accounting mapping"))?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_exports(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+) -> Result<Json<Vec<AccountingExport>>, StatusCode> {
+    let exports = sqlx::query_as::<_, AccountingExport>(&format!(
+        "SELECT {} FROM accounting_exports ORDER BY created_at DESC LIMIT 200",
+        EXPORT_COLUMNS
+    ))
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching accounting exports"))?;
+
+    Ok(Json(exports))
+}
+
+/// Builds a journal file for the period and returns it as a download.
+async fn create_export(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<CreateExport>,
+) -> Result<Response, StatusCode> {
+    if !EXPORT_FORMATS.contains(&payload.format.as_str()) || payload.period_end < payload.period_start {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let (export, contents) = accounting::export(
+        &state.db_pool,
+        &payload.format,
+        payload.period_start,
+        payload.period_end,
+        payload.changes_only.unwrap_or(false),
+        Some(auth.0.id),
+    )
+    .await
+    .map_err(internal("exporting journal"))?;
+
+    Ok(file_response(&export, contents.into_bytes()))
+}
+
+async fn download_export(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Response, StatusCode> {
+    let export = sqlx::query_as::<_, AccountingExport>(&format!(
+        "SELECT {} FROM accounting_exports WHERE id = $1",
+        EXPORT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(internal("fetching accounting export"))?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let contents = tokio::fs::read(&export.file_path).await.map_err(|e| {
+        tracing::warn!("Accounting export file {} is missing: {}", export.file_path, e);
+        StatusCode::NOT_FOUND
+    })?;
+
+    Ok(file_response(&export, contents))
+}
+
+/// Pushes changes to QuickBooks Online now rather than waiting for the worker.
+async fn run_sync(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+) -> Result<Json<SyncSummary>, StatusCode> {
+    let client = QuickBooksClient::from_integration(&state.db_pool).await.map_err(|e| {
+        tracing::warn!("Accounting sync unavailable: {}", e);
+        StatusCode::UNPROCESSABLE_ENTITY
+    })?;
+    let from = client
+        .sync_from()
+        .unwrap_or_else(|| accounting::default_sync_from(Utc::now().date_naive()));
+
+    let summary = accounting::sync_api(&state.db_pool, &client, from)
+        .await
+        .map_err(internal("syncing to accounting"))?;
+
+    Ok(Json(summary))
+}
+
+async fn list_sync_records(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<SyncRecordQuery>,
+) -> Result<Json<Vec<SyncRecord>>, StatusCode> {
+    let records = sqlx::query_as::<_, SyncRecord>(&format!(
+        "SELECT {} FROM accounting_sync_records
+         WHERE ($1::TEXT IS NULL OR target = $1) AND ($2::TEXT IS NULL OR status = $2)
+           AND ($3::TEXT IS NULL OR entity_type = $3)
+         ORDER BY updated_at DESC
+         LIMIT 500",
+        SYNC_RECORD_COLUMNS
+    ))
+    .bind(params.target)
+    .bind(params.status)
+    .bind(params.entity_type)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching sync records"))?;
+
+    Ok(Json(records))
+}
+
+/// Marks records to be pushed again on the next sync or changes-only export.
+async fn resync_records(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Json(payload): Json<ResyncRequest>,
+) -> Result<Json<ResyncResult>, StatusCode> {
+    if let Some(entity_type) = &payload.entity_type {
+        if !ENTITY_TYPES.contains(&entity_type.as_str()) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    let result = sqlx::query(
+        "UPDATE accounting_sync_records SET content_hash = NULL, updated_at = NOW()
+         WHERE target = $1 AND status <> 'removed'
+           AND ($2::TEXT IS NULL OR entity_type = $2)
+           AND ($3::UUID[] IS NULL OR entity_id = ANY($3))",
+    )
+    .bind(&payload.target)
+    .bind(&payload.entity_type)
+    .bind(&payload.entity_ids)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("marking records for resync"))?;
+
+    Ok(Json(ResyncResult { marked: result.rows_affected() }))
+}
+
+fn file_response(export: &AccountingExport, contents: Vec<u8>) -> Response {
+    let extension = accounting::export_extension(&export.format);
+    let content_type = if extension == "csv" { "text/csv; charset=utf-8" } else { "text/plain; charset=utf-8" };
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"{}-{}-{}.{}\"",
+                    export.format,
+                    export.period_start.format("%Y%m%d"),
+                    export.period_end.format("%Y%m%d"),
+                    extension
+                ),
+            ),
+        ],
+        contents,
+    )
+        .into_response()
+}
@@ -0,0 +1,441 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+    routing::{get, post, put},
+    Router,
+};
+use chrono::{NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::handlers::internal;
+use crate::services::billing_review::{
+    self, ApprovedReview, BillingReview, ReviewItem, ReviewTotals, WriteOff, ITEM_ACTIONS, ITEM_COLUMNS,
+    REVIEW_COLUMNS, WRITE_OFF_COLUMNS,
+};
+use crate::AppState;
+
+const DEFAULT_PAYMENT_TERMS_DAYS: i32 = 30;
+
+pub fn billing_review_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_reviews))
+        .route("/prepare", post(prepare_reviews))
+        .route("/approve", post(approve_reviews))
+        .route("/write-offs", get(list_write_offs))
+        .route("/:id", get(get_review))
+        .route("/:id/items/:item_id", put(update_item))
+        .route("/:id/merge", post(merge_items))
+        .route("/:id/unmerge", post(unmerge_items))
+        .route("/:id/cancel", post(cancel_review))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewQuery {
+    pub status: Option<String>, // pending when omitted
+    pub client_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReviewSummary {
+    #[serde(flatten)]
+    pub review: BillingReview,
+    pub totals: ReviewTotals,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReviewDetail {
+    #[serde(flatten)]
+    pub review: BillingReview,
+    pub totals: ReviewTotals,
+    pub items: Vec<ReviewItem>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PrepareRequest {
+    pub through_date: Option<NaiveDate>, // today when omitted
+    pub client_id: Option<Uuid>,         // every client with unbilled work when omitted
+    pub minimum_hours: Option<Decimal>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PrepareResult {
+    pub review_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateItemRequest {
+    pub description: Option<String>,
+    pub quantity: Option<Decimal>,
+    pub unit_price: Option<Decimal>,
+    pub action: Option<String>,
+    pub action_reason: Option<String>,
+    pub deferred_until: Option<NaiveDate>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MergeRequest {
+    pub into: Uuid,
+    pub item_ids: Vec<Uuid>,
+    pub description: Option<String>, // rewords the merged line
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UnmergeRequest {
+    pub item_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApproveRequest {
+    pub review_ids: Vec<Uuid>,
+    pub payment_terms_days: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApprovalFailure {
+    pub review_id: Uuid,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApproveResult {
+    pub approved: Vec<ApprovedReview>,
+    pub failed: Vec<ApprovalFailure>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WriteOffQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub client_id: Option<Uuid>,
+}
+
+/// The approval queue: reviews with what each would bill, write off and defer.
+async fn list_reviews(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<ReviewQuery>,
+) -> Result<Json<Vec<ReviewSummary>>, StatusCode> {
+    let reviews = sqlx::query_as::<_, BillingReview>(&format!(
+        "SELECT {} FROM billing_reviews r JOIN clients c ON c.id = r.client_id
+         WHERE r.status = COALESCE($1, 'pending') AND ($2::UUID IS NULL OR r.client_id = $2)
+         ORDER BY c.name, r.created_at DESC
+         LIMIT 500",
+        REVIEW_COLUMNS
+    ))
+    .bind(params.status)
+    .bind(params.client_id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching billing reviews"))?;
+
+    let review_ids: Vec<Uuid> = reviews.iter().map(|r| r.id).collect();
+    let items = sqlx::query_as::<_, ReviewItem>(&format!(
+        "SELECT {} FROM billing_review_items WHERE review_id = ANY($1)",
+        ITEM_COLUMNS
+    ))
+    .bind(&review_ids)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching billing review items"))?;
+
+    let summaries = reviews
+        .into_iter()
+        .map(|review| {
+            let review_items: Vec<ReviewItem> = items.iter().filter(|i| i.review_id == review.id).cloned().collect();
+            ReviewSummary { totals: billing_review::review_totals(&review_items), review }
+        })
+        .collect();
+
+    Ok(Json(summaries))
+}
+
+/// Stages unbilled work now rather than waiting for the monthly run.
+async fn prepare_reviews(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Json(payload): Json<PrepareRequest>,
+) -> Result<Json<PrepareResult>, StatusCode> {
+    let through = payload.through_date.unwrap_or_else(|| Utc::now().date_naive());
+    let minimum_hours = payload.minimum_hours.unwrap_or(Decimal::ZERO);
+
+    let review_ids = match payload.client_id {
+        Some(client_id) => billing_review::prepare_review(&state.db_pool, client_id, through, minimum_hours)
+            .await
+            .map_err(internal("preparing billing review"))?
+            .into_iter()
+            .collect(),
+        None => billing_review::prepare_reviews(&state.db_pool, through, minimum_hours)
+            .await
+            .map_err(internal("preparing billing reviews"))?,
+    };
+
+    Ok(Json(PrepareResult { review_ids }))
+}
+
+async fn get_review(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ReviewDetail>, StatusCode> {
+    let review = fetch_review(&state, id).await?;
+    let items = billing_review::load_items(&state.db_pool, id)
+        .await
+        .map_err(internal("fetching billing review items"))?;
+
+    Ok(Json(ReviewDetail { totals: billing_review::review_totals(&items), review, items }))
+}
+
+/// Rewords, adjusts, writes off or defers one line. Held recurring lines can
+/// only be reworded.
+async fn update_item(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path((id, item_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<UpdateItemRequest>,
+) -> Result<Json<ReviewItem>, StatusCode> {
+    let item = fetch_pending_item(&state, id, item_id).await?;
+
+    let description = payload.description.as_deref().map(str::trim);
+    if description.is_some_and(|d| d.is_empty())
+        || payload.quantity.is_some_and(|q| q < Decimal::ZERO)
+        || payload.unit_price.is_some_and(|p| p < Decimal::ZERO)
+        || payload.action.as_deref().is_some_and(|a| !ITEM_ACTIONS.contains(&a))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if item.is_recurring()
+        && (payload.quantity.is_some() || payload.unit_price.is_some() || payload.action.is_some())
+    {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let mut tx = state.db_pool.begin().await.map_err(internal("starting transaction"))?;
+
+    // A line that stops being billed takes itself out of its group and breaks up its own
+    if payload.action.as_deref().is_some_and(|a| a != "bill") {
+        sqlx::query("UPDATE billing_review_items SET merged_into = NULL WHERE merged_into = $1")
+            .bind(item_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(internal("unmerging billing review items"))?;
+    }
+
+    let updated = sqlx::query_as::<_, ReviewItem>(&format!(
+        "UPDATE billing_review_items SET
+             description = COALESCE($2, description),
+             quantity = COALESCE($3, quantity),
+             unit_price = COALESCE($4, unit_price),
+             action = COALESCE($5, action),
+             merged_into = CASE WHEN COALESCE($5, action) = 'bill' THEN merged_into ELSE NULL END,
+             action_reason = COALESCE($6, action_reason),
+             deferred_until = CASE WHEN COALESCE($5, action) = 'defer' THEN COALESCE($7, deferred_until) ELSE NULL END,
+             updated_by = $8,
+             updated_at = NOW()
+         WHERE id = $1
+         RETURNING {}",
+        ITEM_COLUMNS
+    ))
+    .bind(item_id)
+    .bind(description)
+    .bind(payload.quantity)
+    .bind(payload.unit_price)
+    .bind(&payload.action)
+    .bind(&payload.action_reason)
+    .bind(payload.deferred_until)
+    .bind(auth.0.id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal("updating billing review item"))?;
+
+    tx.commit().await.map_err(internal("committing transaction"))?;
+
+    Ok(Json(updated))
+}
+
+/// Folds lines into one invoice line, e.g. a day's worth of small printer tickets.
+async fn merge_items(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<MergeRequest>,
+) -> Result<Json<Vec<ReviewItem>>, StatusCode> {
+    fetch_pending_review(&state, id).await?;
+    let items = billing_review::load_items(&state.db_pool, id)
+        .await
+        .map_err(internal("fetching billing review items"))?;
+
+    let target = items.iter().find(|i| i.id == payload.into).ok_or(StatusCode::NOT_FOUND)?;
+    for item_id in &payload.item_ids {
+        let item = items.iter().find(|i| i.id == *item_id).ok_or(StatusCode::NOT_FOUND)?;
+        if let Some(reason) = billing_review::merge_error(target, item) {
+            tracing::warn!("Cannot merge billing review item {} into {}: {}", item.id, target.id, reason);
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        }
+    }
+    if payload.description.as_deref().is_some_and(|d| d.trim().is_empty()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut tx = state.db_pool.begin().await.map_err(internal("starting transaction"))?;
+
+    // Members of a merged item follow it into the new group
+    sqlx::query(
+        "UPDATE billing_review_items SET merged_into = $2, updated_by = $3, updated_at = NOW()
+         WHERE review_id = $4 AND (id = ANY($1) OR merged_into = ANY($1))",
+    )
+    .bind(&payload.item_ids)
+    .bind(payload.into)
+    .bind(auth.0.id)
+    .bind(id)
+    .execute(&mut *tx)
+    .await
+    .map_err(internal("merging billing review items"))?;
+
+    if let Some(description) = &payload.description {
+        sqlx::query("UPDATE billing_review_items SET description = $2, updated_by = $3, updated_at = NOW() WHERE id = $1")
+            .bind(payload.into)
+            .bind(description.trim())
+            .bind(auth.0.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(internal("rewording merged billing review item"))?;
+    }
+
+    tx.commit().await.map_err(internal("committing transaction"))?;
+
+    let items = billing_review::load_items(&state.db_pool, id)
+        .await
+        .map_err(internal("fetching billing review items"))?;
+    Ok(Json(items.into_iter().filter(|i| i.id == payload.into || i.merged_into == Some(payload.into)).collect()))
+}
+
+async fn unmerge_items(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UnmergeRequest>,
+) -> Result<StatusCode, StatusCode> {
+    fetch_pending_review(&state, id).await?;
+
+    sqlx::query(
+        "UPDATE billing_review_items SET merged_into = NULL, updated_by = $3, updated_at = NOW()
+         WHERE review_id = $1 AND id = ANY($2)",
+    )
+    .bind(id)
+    .bind(&payload.item_ids)
+    .bind(auth.0.id)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("unmerging billing review items"))?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Drops a review; its work goes back to unbilled and is staged again next time.
+async fn cancel_review(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query(
+        "UPDATE billing_reviews SET status = 'cancelled', updated_at = NOW() WHERE id = $1 AND status = 'pending'",
+    )
+    .bind(id)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("cancelling billing review"))?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Approves reviews in a batch. Each is approved on its own, so one failure
+/// doesn't hold up the rest.
+async fn approve_reviews(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<ApproveRequest>,
+) -> Result<Json<ApproveResult>, StatusCode> {
+    if payload.review_ids.is_empty() || payload.payment_terms_days.is_some_and(|d| d < 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let terms = payload.payment_terms_days.unwrap_or(DEFAULT_PAYMENT_TERMS_DAYS);
+
+    let mut result = ApproveResult { approved: Vec::new(), failed: Vec::new() };
+    for review_id in payload.review_ids {
+        match billing_review::approve_review(&state.db_pool, review_id, auth.0.id, terms).await {
+            Ok(approved) => result.approved.push(approved),
+            Err(e) => {
+                tracing::warn!("Failed to approve billing review {}: {}", review_id, e);
+                result.failed.push(ApprovalFailure { review_id, error: e.to_string() });
+            }
+        }
+    }
+
+    Ok(Json(result))
+}
+
+async fn list_write_offs(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<WriteOffQuery>,
+) -> Result<Json<Vec<WriteOff>>, StatusCode> {
+    let write_offs = sqlx::query_as::<_, WriteOff>(&format!(
+        "SELECT {} FROM billing_write_offs w JOIN clients c ON c.id = w.client_id
+         WHERE ($1::DATE IS NULL OR w.work_date >= $1) AND ($2::DATE IS NULL OR w.work_date <= $2)
+           AND ($3::UUID IS NULL OR w.client_id = $3)
+         ORDER BY w.work_date DESC, c.name
+         LIMIT 1000",
+        WRITE_OFF_COLUMNS
+    ))
+    .bind(params.from)
+    .bind(params.to)
+    .bind(params.client_id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching write-offs"))?;
+
+    Ok(Json(write_offs))
+}
+
+async fn fetch_review(state: &AppState, id: Uuid) -> Result<BillingReview, StatusCode> {
+    sqlx::query_as::<_, BillingReview>(&format!(
+        "SELECT {} FROM billing_reviews r JOIN clients c ON c.id = r.client_id WHERE r.id = $1",
+        REVIEW_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(internal("fetching billing review"))?
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Approved and cancelled reviews are read-only.
+async fn fetch_pending_review(state: &AppState, id: Uuid) -> Result<BillingReview, StatusCode> {
+    let review = fetch_review(state, id).await?;
+    if review.status != "pending" {
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(review)
+}
+
+async fn fetch_pending_item(state: &AppState, review_id: Uuid, item_id: Uuid) -> Result<ReviewItem, StatusCode> {
+    fetch_pending_review(state, review_id).await?;
+    sqlx::query_as::<_, ReviewItem>(&format!(
+        "SELECT {} FROM billing_review_items WHERE id = $1 AND review_id = $2",
+        ITEM_COLUMNS
+    ))
+    .bind(item_id)
+    .bind(review_id)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(internal("fetching billing review item"))?
+    .ok_or(StatusCode::NOT_FOUND)
+}
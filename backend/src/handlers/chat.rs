@@ -0,0 +1,768 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+    routing::{get, post, put},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::notifications::{enqueue_notification, QueuedNotification};
+use crate::websocket::WsMessage;
+use crate::AppState;
+
+pub fn chat_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/channels", get(list_channels).post(create_channel))
+        .route("/channels/:id", get(get_channel))
+        .route("/channels/:id/messages", get(list_messages).post(post_message))
+        .route("/channels/:id/read", post(mark_channel_read))
+        .route("/channels/client/:client_id", get(get_client_channel))
+        .route("/channels/ticket/:ticket_id", get(get_ticket_channel))
+        .route("/messages/:id", put(edit_message).delete(delete_message))
+        .route("/unread", get(get_unread_counts))
+        .route("/presence", get(get_presence))
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ChatChannel {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub channel_type: Option<String>,
+    pub client_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub ticket_id: Option<Uuid>,
+    pub is_private: Option<bool>,
+    pub is_archived: Option<bool>,
+    pub members: Option<Vec<Uuid>>,
+    pub created_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ChatChannelWithUnread {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub channel_type: Option<String>,
+    pub client_id: Option<Uuid>,
+    pub ticket_id: Option<Uuid>,
+    pub is_private: Option<bool>,
+    pub last_message_at: Option<DateTime<Utc>>,
+    pub unread_count: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatChannelCreate {
+    pub name: String,
+    pub description: Option<String>,
+    pub channel_type: Option<String>, // global, client, ticket
+    pub client_id: Option<Uuid>,
+    pub ticket_id: Option<Uuid>,
+    pub is_private: Option<bool>,
+    pub members: Option<Vec<Uuid>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ChatMessage {
+    pub id: Uuid,
+    pub channel_id: Uuid,
+    pub user_id: Uuid,
+    pub user_name: Option<String>,
+    pub message: String,
+    pub message_type: Option<String>,
+    pub parent_message_id: Option<Uuid>,
+    pub thread_count: Option<i32>,
+    pub attachments: Option<serde_json::Value>,
+    pub reactions: Option<serde_json::Value>,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatMessageCreate {
+    pub message: String,
+    pub parent_message_id: Option<Uuid>,
+    pub attachments: Option<serde_json::Value>,
+    pub mentions: Option<Vec<Uuid>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatMessageUpdate {
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatMessageQuery {
+    pub before: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatMessagePage {
+    pub messages: Vec<ChatMessage>,
+    pub has_more: bool,
+    pub next_before: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatUnreadCounts {
+    pub total: i64,
+    pub channels: Vec<ChatChannelUnread>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ChatChannelUnread {
+    pub channel_id: Uuid,
+    pub unread_count: i64,
+}
+
+const CHANNEL_COLUMNS: &str = "id, name, description, channel_type, client_id, project_id, ticket_id, \
+     is_private, is_archived, members, created_by, created_at, updated_at";
+
+const MESSAGE_SELECT: &str = r#"
+    SELECT m.id, m.channel_id, m.user_id, u.first_name || ' ' || u.last_name as user_name,
+           m.message, m.message_type, m.parent_message_id, m.thread_count,
+           m.attachments, m.reactions, m.edited_at, m.created_at
+    FROM chat_messages m
+    JOIN users u ON u.id = m.user_id
+"#;
+
+// Private channels are limited to their members; everything else is visible to staff.
+const VISIBLE_TO_USER: &str = "(COALESCE(c.is_private, false) = false OR $1 = ANY(c.members) OR c.created_by = $1)";
+
+async fn list_channels(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+) -> Result<Json<Vec<ChatChannelWithUnread>>, StatusCode> {
+    let sql = format!(
+        r#"
+        SELECT c.id, c.name, c.description, c.channel_type, c.client_id, c.ticket_id, c.is_private,
+               (SELECT MAX(created_at) FROM chat_messages WHERE channel_id = c.id) as last_message_at,
+               (SELECT COUNT(*) FROM chat_messages m
+                WHERE m.channel_id = c.id AND m.is_deleted = false AND m.user_id <> $1
+                AND m.created_at > COALESCE(r.last_read_at, '-infinity'::TIMESTAMPTZ)) as unread_count
+        FROM chat_channels c
+        LEFT JOIN chat_channel_reads r ON r.channel_id = c.id AND r.user_id = $1
+        WHERE COALESCE(c.is_archived, false) = false AND {}
+        ORDER BY last_message_at DESC NULLS LAST, c.name
+        "#,
+        VISIBLE_TO_USER
+    );
+
+    let channels = sqlx::query_as::<_, ChatChannelWithUnread>(&sql)
+        .bind(auth.0.id)
+        .fetch_all(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching chat channels: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(channels))
+}
+
+async fn create_channel(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<ChatChannelCreate>,
+) -> Result<(StatusCode, Json<ChatChannel>), StatusCode> {
+    let channel_type = payload.channel_type.unwrap_or_else(|| "global".to_string());
+
+    match channel_type.as_str() {
+        "global" => {}
+        "client" if payload.client_id.is_some() => {}
+        "ticket" if payload.ticket_id.is_some() => {}
+        _ => return Err(StatusCode::BAD_REQUEST),
+    }
+
+    let mut members = payload.members.unwrap_or_default();
+    if !members.contains(&auth.0.id) {
+        members.push(auth.0.id);
+    }
+
+    let channel = sqlx::query_as::<_, ChatChannel>(&format!(
+        r#"
+        INSERT INTO chat_channels (name, description, channel_type, client_id, ticket_id,
+                                   is_private, members, admins, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, ARRAY[$8]::UUID[], $8)
+        RETURNING {}
+        "#,
+        CHANNEL_COLUMNS
+    ))
+    .bind(&payload.name)
+    .bind(&payload.description)
+    .bind(&channel_type)
+    .bind(payload.client_id)
+    .bind(payload.ticket_id)
+    .bind(payload.is_private.unwrap_or(false))
+    .bind(&members)
+    .bind(auth.0.id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match &e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => StatusCode::CONFLICT,
+        _ => {
+            tracing::error!("Error creating chat channel: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok((StatusCode::CREATED, Json(channel)))
+}
+
+async fn get_channel(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    auth: AuthUser,
+) -> Result<Json<ChatChannel>, StatusCode> {
+    let channel = load_visible_channel(&state, id, auth.0.id).await?;
+    Ok(Json(channel))
+}
+
+async fn get_client_channel(
+    State(state): State<Arc<AppState>>,
+    Path(client_id): Path<Uuid>,
+    auth: AuthUser,
+) -> Result<Json<ChatChannel>, StatusCode> {
+    let name: String = sqlx::query_scalar("SELECT name FROM clients WHERE id = $1")
+        .bind(client_id)
+        .fetch_one(&state.db_pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+
+    let channel = get_or_create_scoped_channel(&state, "client", client_id, &name, auth.0.id).await?;
+    Ok(Json(channel))
+}
+
+async fn get_ticket_channel(
+    State(state): State<Arc<AppState>>,
+    Path(ticket_id): Path<Uuid>,
+    auth: AuthUser,
+) -> Result<Json<ChatChannel>, StatusCode> {
+    let ticket_number: i32 = sqlx::query_scalar("SELECT number FROM tickets WHERE id = $1")
+        .bind(ticket_id)
+        .fetch_one(&state.db_pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+
+    let name = format!("Ticket #{}", ticket_number);
+    let channel = get_or_create_scoped_channel(&state, "ticket", ticket_id, &name, auth.0.id).await?;
+    Ok(Json(channel))
+}
+
+// Client and ticket channels are created on first access, one per entity.
+async fn get_or_create_scoped_channel(
+    state: &Arc<AppState>,
+    channel_type: &str,
+    entity_id: Uuid,
+    name: &str,
+    user_id: Uuid,
+) -> Result<ChatChannel, StatusCode> {
+    let column = if channel_type == "ticket" { "ticket_id" } else { "client_id" };
+
+    let insert = format!(
+        r#"
+        INSERT INTO chat_channels (name, channel_type, {column}, members, created_by)
+        VALUES ($1, $2, $3, ARRAY[$4]::UUID[], $4)
+        ON CONFLICT ({column}) WHERE channel_type = '{channel_type}' AND {column} IS NOT NULL DO NOTHING
+        "#
+    );
+
+    sqlx::query(&insert)
+        .bind(name)
+        .bind(channel_type)
+        .bind(entity_id)
+        .bind(user_id)
+        .execute(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error creating {} chat channel: {}", channel_type, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    sqlx::query_as::<_, ChatChannel>(&format!(
+        "SELECT {} FROM chat_channels WHERE channel_type = $1 AND {} = $2",
+        CHANNEL_COLUMNS, column
+    ))
+    .bind(channel_type)
+    .bind(entity_id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching {} chat channel: {}", channel_type, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+async fn load_visible_channel(
+    state: &Arc<AppState>,
+    channel_id: Uuid,
+    user_id: Uuid,
+) -> Result<ChatChannel, StatusCode> {
+    let columns = CHANNEL_COLUMNS
+        .split(", ")
+        .map(|c| format!("c.{}", c.trim()))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    sqlx::query_as::<_, ChatChannel>(&format!(
+        "SELECT {} FROM chat_channels c WHERE c.id = $2 AND {}",
+        columns, VISIBLE_TO_USER
+    ))
+    .bind(user_id)
+    .bind(channel_id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+        _ => {
+            tracing::error!("Error fetching chat channel: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })
+}
+
+async fn list_messages(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ChatMessageQuery>,
+    auth: AuthUser,
+) -> Result<Json<ChatMessagePage>, StatusCode> {
+    load_visible_channel(&state, id, auth.0.id).await?;
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 200);
+
+    let mut messages = sqlx::query_as::<_, ChatMessage>(&format!(
+        r#"
+        {}
+        WHERE m.channel_id = $1 AND m.is_deleted = false
+        AND ($2::TIMESTAMPTZ IS NULL OR m.created_at < $2)
+        ORDER BY m.created_at DESC
+        LIMIT $3
+        "#,
+        MESSAGE_SELECT
+    ))
+    .bind(id)
+    .bind(query.before)
+    .bind(limit + 1)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching chat messages: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let has_more = messages.len() as i64 > limit;
+    messages.truncate(limit as usize);
+    let next_before = if has_more { messages.last().and_then(|m| m.created_at) } else { None };
+
+    Ok(Json(ChatMessagePage {
+        messages,
+        has_more,
+        next_before,
+    }))
+}
+
+async fn post_message(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    auth: AuthUser,
+    Json(payload): Json<ChatMessageCreate>,
+) -> Result<(StatusCode, Json<ChatMessage>), StatusCode> {
+    if payload.message.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let channel = load_visible_channel(&state, id, auth.0.id).await?;
+    if channel.is_archived.unwrap_or(false) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    // Threads stay within their channel
+    if let Some(parent_id) = payload.parent_message_id {
+        let parent_in_channel: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM chat_messages WHERE id = $1 AND channel_id = $2 AND is_deleted = false)"
+        )
+        .bind(parent_id)
+        .bind(id)
+        .fetch_one(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error checking parent chat message: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        if !parent_in_channel {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    let mut tx = state.db_pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let message_id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO chat_messages (channel_id, user_id, message, parent_message_id, attachments)
+        VALUES ($1, $2, $3, $4, COALESCE($5, '[]'::JSONB))
+        RETURNING id
+        "#
+    )
+    .bind(id)
+    .bind(auth.0.id)
+    .bind(&payload.message)
+    .bind(payload.parent_message_id)
+    .bind(&payload.attachments)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error creating chat message: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if let Some(parent_id) = payload.parent_message_id {
+        sqlx::query("UPDATE chat_messages SET thread_count = COALESCE(thread_count, 0) + 1 WHERE id = $1")
+            .bind(parent_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    // Mentions: explicit user ids plus @handles matching the email local part. Only people
+    // who can see the channel are notified, since the notification carries the message.
+    let handles = extract_mention_handles(&payload.message);
+    let mentioned: Vec<Uuid> = sqlx::query_scalar(
+        r#"
+        SELECT u.id FROM users u
+        JOIN chat_channels c ON c.id = $4
+        WHERE u.is_active = true AND u.id <> $3
+        AND (u.id = ANY($1) OR LOWER(split_part(u.email, '@', 1)) = ANY($2))
+        AND (COALESCE(c.is_private, false) = false OR u.id = ANY(c.members) OR c.created_by = u.id)
+        "#
+    )
+    .bind(payload.mentions.clone().unwrap_or_default())
+    .bind(&handles)
+    .bind(auth.0.id)
+    .bind(id)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error resolving chat mentions: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    for user_id in &mentioned {
+        sqlx::query("INSERT INTO chat_message_mentions (message_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .bind(message_id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    // Posting counts as reading the channel up to this message
+    sqlx::query(
+        r#"
+        INSERT INTO chat_channel_reads (channel_id, user_id, last_read_at, last_read_message_id)
+        VALUES ($1, $2, NOW(), $3)
+        ON CONFLICT (channel_id, user_id)
+        DO UPDATE SET last_read_at = NOW(), last_read_message_id = $3, updated_at = NOW()
+        "#
+    )
+    .bind(id)
+    .bind(auth.0.id)
+    .bind(message_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let message = load_message(&state, message_id).await?;
+
+    for user_id in &mentioned {
+        let notification = QueuedNotification::for_user(
+            *user_id,
+            "chat_mention",
+            format!("{} mentioned you in {}", message.user_name.clone().unwrap_or_default(), channel.name),
+            payload.message.clone(),
+        )
+        .with_entity("chat_channel", channel.id);
+
+        if let Err(e) = enqueue_notification(&state.db_pool, notification).await {
+            tracing::error!("Error queueing chat mention notification: {}", e);
+        }
+    }
+
+    let event = serde_json::to_value(&message).unwrap_or_default();
+    send_to_channel_audience(&state, &channel, "chat_message", event, None).await;
+
+    Ok((StatusCode::CREATED, Json(message)))
+}
+
+async fn load_message(state: &Arc<AppState>, id: Uuid) -> Result<ChatMessage, StatusCode> {
+    sqlx::query_as::<_, ChatMessage>(&format!("{} WHERE m.id = $1", MESSAGE_SELECT))
+        .bind(id)
+        .fetch_one(&state.db_pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+            _ => {
+                tracing::error!("Error fetching chat message: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })
+}
+
+async fn edit_message(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    auth: AuthUser,
+    Json(payload): Json<ChatMessageUpdate>,
+) -> Result<Json<ChatMessage>, StatusCode> {
+    if payload.message.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let channel_id: Uuid = sqlx::query_scalar(
+        r#"
+        UPDATE chat_messages
+        SET original_message = COALESCE(original_message, message),
+            message = $3, edited_at = NOW()
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        RETURNING channel_id
+        "#
+    )
+    .bind(id)
+    .bind(auth.0.id)
+    .bind(&payload.message)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+        _ => {
+            tracing::error!("Error editing chat message: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    let message = load_message(&state, id).await?;
+    let channel = load_visible_channel(&state, channel_id, auth.0.id).await?;
+    let event = serde_json::to_value(&message).unwrap_or_default();
+    send_to_channel_audience(&state, &channel, "chat_message_updated", event, None).await;
+
+    Ok(Json(message))
+}
+
+async fn delete_message(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    auth: AuthUser,
+) -> Result<StatusCode, StatusCode> {
+    let channel_id: Uuid = sqlx::query_scalar(
+        r#"
+        UPDATE chat_messages SET is_deleted = true, deleted_at = NOW()
+        WHERE id = $1 AND user_id = $2 AND is_deleted = false
+        RETURNING channel_id
+        "#
+    )
+    .bind(id)
+    .bind(auth.0.id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+        _ => {
+            tracing::error!("Error deleting chat message: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    let channel = load_visible_channel(&state, channel_id, auth.0.id).await?;
+    send_to_channel_audience(
+        &state,
+        &channel,
+        "chat_message_deleted",
+        serde_json::json!({ "id": id, "channel_id": channel_id }),
+        None,
+    ).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn mark_channel_read(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    auth: AuthUser,
+) -> Result<StatusCode, StatusCode> {
+    load_visible_channel(&state, id, auth.0.id).await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO chat_channel_reads (channel_id, user_id, last_read_at, last_read_message_id)
+        VALUES ($1, $2, NOW(),
+                (SELECT id FROM chat_messages WHERE channel_id = $1 ORDER BY created_at DESC LIMIT 1))
+        ON CONFLICT (channel_id, user_id)
+        DO UPDATE SET last_read_at = EXCLUDED.last_read_at,
+                      last_read_message_id = EXCLUDED.last_read_message_id,
+                      updated_at = NOW()
+        "#
+    )
+    .bind(id)
+    .bind(auth.0.id)
+    .execute(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error marking chat channel read: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_unread_counts(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+) -> Result<Json<ChatUnreadCounts>, StatusCode> {
+    let sql = format!(
+        r#"
+        SELECT c.id as channel_id, COUNT(m.id) as unread_count
+        FROM chat_channels c
+        LEFT JOIN chat_channel_reads r ON r.channel_id = c.id AND r.user_id = $1
+        JOIN chat_messages m ON m.channel_id = c.id
+            AND m.is_deleted = false AND m.user_id <> $1
+            AND m.created_at > COALESCE(r.last_read_at, '-infinity'::TIMESTAMPTZ)
+        WHERE COALESCE(c.is_archived, false) = false
+        AND COALESCE(r.muted, false) = false
+        AND {}
+        GROUP BY c.id
+        "#,
+        VISIBLE_TO_USER
+    );
+
+    let channels = sqlx::query_as::<_, ChatChannelUnread>(&sql)
+        .bind(auth.0.id)
+        .fetch_all(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching chat unread counts: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let total = channels.iter().map(|c| c.unread_count).sum();
+
+    Ok(Json(ChatUnreadCounts { total, channels }))
+}
+
+async fn get_presence(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let online = state.ws_manager.online_user_ids().await;
+    Ok(Json(serde_json::json!({ "online_user_ids": online })))
+}
+
+// Public channels reach every connected staff member; private ones only their members.
+async fn send_to_channel_audience(
+    state: &Arc<AppState>,
+    channel: &ChatChannel,
+    event_type: &str,
+    payload: serde_json::Value,
+    exclude_user: Option<Uuid>,
+) {
+    let message = WsMessage::new(event_type, payload);
+
+    if channel.is_private.unwrap_or(false) {
+        let mut members = channel.members.clone().unwrap_or_default();
+        if let Some(creator) = channel.created_by {
+            members.push(creator);
+        }
+        members.retain(|m| Some(*m) != exclude_user);
+        members.sort_unstable();
+        members.dedup();
+        state.ws_manager.broadcast_to_users(&members, message).await;
+    } else {
+        state.ws_manager.broadcast_to_staff(message, exclude_user).await;
+    }
+}
+
+// Relays a typing indicator received over /ws to the rest of the channel.
+pub(crate) async fn relay_typing(state: &Arc<AppState>, user_id: Uuid, payload: &serde_json::Value) {
+    let Some(channel_id) = payload
+        .get("channel_id")
+        .and_then(|v| v.as_str())
+        .and_then(|s| Uuid::parse_str(s).ok())
+    else {
+        return;
+    };
+
+    let Ok(channel) = load_visible_channel(state, channel_id, user_id).await else {
+        return;
+    };
+
+    let is_typing = payload.get("is_typing").and_then(|v| v.as_bool()).unwrap_or(true);
+
+    send_to_channel_audience(
+        state,
+        &channel,
+        "chat_typing",
+        serde_json::json!({
+            "channel_id": channel_id,
+            "user_id": user_id,
+            "is_typing": is_typing,
+        }),
+        Some(user_id),
+    ).await;
+}
+
+/// Collects lowercase `@handle` tokens from a message.
+pub fn extract_mention_handles(message: &str) -> Vec<String> {
+    let mut handles = Vec::new();
+
+    for (index, _) in message.match_indices('@') {
+        // Skip email addresses such as "ops@example.com"
+        let preceded_by_word = message[..index]
+            .chars()
+            .next_back()
+            .map(|c| c.is_alphanumeric())
+            .unwrap_or(false);
+        if preceded_by_word {
+            continue;
+        }
+
+        let handle: String = message[index + 1..]
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || matches!(c, '.' | '_' | '-'))
+            .collect();
+        let handle = handle.trim_end_matches('.').to_lowercase();
+
+        if !handle.is_empty() && !handles.contains(&handle) {
+            handles.push(handle);
+        }
+    }
+
+    handles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_mention_handles() {
+        let handles = extract_mention_handles("@jane.doe can you check this? cc @Bob, not ops@example.com. Thanks @jane.doe.");
+        assert_eq!(handles, vec!["jane.doe".to_string(), "bob".to_string()]);
+        assert!(extract_mention_handles("no mentions here").is_empty());
+    }
+}
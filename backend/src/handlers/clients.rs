@@ -21,6 +21,7 @@ pub struct ClientCreate {
     pub zip: Option<String>,
     pub billing_address: Option<String>,
     pub notes: Option<String>,
+    pub currency: Option<String>, // billing currency; defaults to the base currency
 }
 
 #[derive(Serialize, Deserialize)]
@@ -34,6 +35,7 @@ pub struct ClientUpdate {
     pub zip: Option<String>,
     pub billing_address: Option<String>,
     pub notes: Option<String>,
+    pub currency: Option<String>, // applies to invoices raised from now on
 }
 
 #[derive(Serialize, Deserialize)]
@@ -63,7 +65,7 @@ async fn list_clients(
         sqlx::query_as!(
             ghosthub_shared::Client,
             "SELECT id, name, email, phone, address, city, state, zip, billing_address, notes, 
-             created_at, updated_at, archived_at 
+             currency, created_at, updated_at, archived_at 
              FROM clients 
              WHERE name ILIKE $1 OR email ILIKE $1
              ORDER BY name 
@@ -76,7 +78,7 @@ async fn list_clients(
         sqlx::query_as!(
             ghosthub_shared::Client,
             "SELECT id, name, email, phone, address, city, state, zip, billing_address, notes, 
-             created_at, updated_at, archived_at 
+             currency, created_at, updated_at, archived_at 
              FROM clients 
              ORDER BY name 
              LIMIT $1 OFFSET $2",
@@ -99,10 +101,11 @@ async fn create_client(
     
     match sqlx::query_as!(
         ghosthub_shared::Client,
-        "INSERT INTO clients (id, name, email, phone, address, city, state, zip, billing_address, notes)
-         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        "INSERT INTO clients (id, name, email, phone, address, city, state, zip, billing_address, notes, currency)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10,
+                 COALESCE($11, (SELECT code FROM currencies WHERE is_base = true), 'USD'))
          RETURNING id, name, email, phone, address, city, state, zip, billing_address, notes, 
-                   created_at, updated_at, archived_at",
+                   currency, created_at, updated_at, archived_at",
         client_id,
         payload.name,
         payload.email,
@@ -112,7 +115,8 @@ async fn create_client(
         payload.state,
         payload.zip,
         payload.billing_address,
-        payload.notes
+        payload.notes,
+        payload.currency
     )
     .fetch_one(&state.db_pool)
     .await
@@ -129,7 +133,7 @@ async fn get_client(
     match sqlx::query_as!(
         ghosthub_shared::Client,
         "SELECT id, name, email, phone, address, city, state, zip, billing_address, notes, 
-         created_at, updated_at, archived_at 
+         currency, created_at, updated_at, archived_at 
          FROM clients WHERE id = $1",
         id
     )
@@ -160,10 +164,11 @@ async fn update_client(
          zip = COALESCE($8, zip),
          billing_address = COALESCE($9, billing_address),
          notes = COALESCE($10, notes),
+         currency = COALESCE($11, currency),
          updated_at = NOW()
          WHERE id = $1
          RETURNING id, name, email, phone, address, city, state, zip, billing_address, notes, 
-                   created_at, updated_at, archived_at",
+                   currency, created_at, updated_at, archived_at",
         id,
         payload.name,
         payload.email,
@@ -173,7 +178,8 @@ async fn update_client(
         payload.state,
         payload.zip,
         payload.billing_address,
-        payload.notes
+        payload.notes,
+        payload.currency
     )
     .fetch_one(&state.db_pool)
     .await
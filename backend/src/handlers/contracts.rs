@@ -0,0 +1,426 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Months, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::services::prepaid_blocks::{BlockType, BLOCK_REMAINING_SQL};
+use crate::AppState;
+
+pub fn contract_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_contracts).post(create_contract))
+        .route("/:id", get(get_contract).put(update_contract))
+        .route("/:id/blocks", get(list_blocks).post(create_block))
+        .route("/blocks/:block_id/drawdowns", get(list_drawdowns))
+}
+
+const CONTRACT_COLUMNS: &str = "ct.id, ct.client_id, c.name as client_name, ct.name, ct.contract_type, ct.start_date,
+    ct.end_date, ct.monthly_value, ct.hourly_rate, ct.included_hours, ct.overage_rate, ct.status, ct.terms,
+    ct.auto_renew, ct.block_rollover_policy, ct.block_rollover_cap, ct.block_rollover_months,
+    ct.block_validity_months, ct.low_balance_percent, ct.created_at, ct.updated_at";
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ContractWithClient {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub client_name: Option<String>,
+    pub name: String,
+    pub contract_type: Option<String>,
+    pub start_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+    pub monthly_value: Option<Decimal>,
+    pub hourly_rate: Option<Decimal>,
+    pub included_hours: Option<i32>,
+    pub overage_rate: Option<Decimal>,
+    pub status: Option<String>,
+    pub terms: Option<String>,
+    pub auto_renew: Option<bool>,
+    pub block_rollover_policy: Option<String>,
+    pub block_rollover_cap: Option<Decimal>,
+    pub block_rollover_months: Option<i32>,
+    pub block_validity_months: Option<i32>,
+    pub low_balance_percent: Option<i32>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContractQuery {
+    pub client_id: Option<Uuid>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContractCreate {
+    pub client_id: Uuid,
+    pub name: String,
+    pub contract_type: Option<String>,
+    pub start_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+    pub monthly_value: Option<Decimal>,
+    pub hourly_rate: Option<Decimal>,
+    pub included_hours: Option<i32>,
+    pub overage_rate: Option<Decimal>,
+    pub terms: Option<String>,
+    pub auto_renew: Option<bool>,
+    pub block_rollover_policy: Option<String>,
+    pub block_rollover_cap: Option<Decimal>,
+    pub block_rollover_months: Option<i32>,
+    pub block_validity_months: Option<i32>,
+    pub low_balance_percent: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContractUpdate {
+    pub name: Option<String>,
+    pub end_date: Option<NaiveDate>,
+    pub monthly_value: Option<Decimal>,
+    pub hourly_rate: Option<Decimal>,
+    pub included_hours: Option<i32>,
+    pub overage_rate: Option<Decimal>,
+    pub status: Option<String>,
+    pub terms: Option<String>,
+    pub auto_renew: Option<bool>,
+    pub block_rollover_policy: Option<String>,
+    pub block_rollover_cap: Option<Decimal>,
+    pub block_rollover_months: Option<i32>,
+    pub block_validity_months: Option<i32>,
+    pub low_balance_percent: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct PrepaidBlock {
+    pub id: Uuid,
+    pub contract_id: Uuid,
+    pub client_id: Uuid,
+    pub block_type: String,
+    pub quantity: Decimal,
+    pub remaining: Decimal,
+    pub price: Option<Decimal>,
+    pub purchased_on: NaiveDate,
+    pub expires_on: Option<NaiveDate>,
+    pub status: Option<String>,
+    pub expired_quantity: Option<Decimal>,
+    pub rolled_over_from: Option<Uuid>,
+    pub invoice_id: Option<Uuid>,
+    pub low_balance_alerted_at: Option<DateTime<Utc>>,
+    pub notes: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlockCreate {
+    pub block_type: String,
+    pub quantity: Decimal,
+    pub price: Option<Decimal>,
+    pub purchased_on: Option<NaiveDate>,
+    pub expires_on: Option<NaiveDate>,
+    pub invoice_id: Option<Uuid>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct BlockDrawdown {
+    pub id: Uuid,
+    pub time_entry_id: Uuid,
+    pub hours: Decimal,
+    pub amount: Decimal,
+    pub work_date: Option<NaiveDate>,
+    pub description: Option<String>,
+    pub user_name: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+const ROLLOVER_POLICIES: [&str; 3] = ["none", "full", "capped"];
+
+pub(crate) fn block_columns() -> String {
+    format!(
+        "b.id, b.contract_id, b.client_id, b.block_type, b.quantity, GREATEST({}, 0) as remaining, b.price,
+         b.purchased_on, b.expires_on, b.status, b.expired_quantity, b.rolled_over_from, b.invoice_id,
+         b.low_balance_alerted_at, b.notes, b.created_at",
+        BLOCK_REMAINING_SQL
+    )
+}
+
+async fn list_contracts(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<ContractQuery>,
+) -> Result<Json<Vec<ContractWithClient>>, StatusCode> {
+    let contracts = sqlx::query_as::<_, ContractWithClient>(&format!(
+        "SELECT {} FROM contracts ct
+         LEFT JOIN clients c ON c.id = ct.client_id
+         WHERE ($1::UUID IS NULL OR ct.client_id = $1) AND ($2::TEXT IS NULL OR ct.status = $2)
+         ORDER BY c.name, ct.start_date DESC",
+        CONTRACT_COLUMNS
+    ))
+    .bind(params.client_id)
+    .bind(params.status)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching contracts: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(contracts))
+}
+
+async fn get_contract(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ContractWithClient>, StatusCode> {
+    Ok(Json(get_contract_by_id(&state, id).await?))
+}
+
+async fn create_contract(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Json(payload): Json<ContractCreate>,
+) -> Result<(StatusCode, Json<ContractWithClient>), StatusCode> {
+    if payload
+        .block_rollover_policy
+        .as_deref()
+        .is_some_and(|p| !ROLLOVER_POLICIES.contains(&p))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO contracts (client_id, name, contract_type, start_date, end_date, monthly_value, hourly_rate,
+                               included_hours, overage_rate, terms, auto_renew, block_rollover_policy,
+                               block_rollover_cap, block_rollover_months, block_validity_months, low_balance_percent)
+        VALUES ($1, $2, COALESCE($3, 'monthly'), $4, $5, $6, $7, $8, $9, $10, COALESCE($11, false),
+                COALESCE($12, 'none'), $13, COALESCE($14, 3), COALESCE($15, 12), COALESCE($16, 20))
+        RETURNING id
+        "#,
+    )
+    .bind(payload.client_id)
+    .bind(&payload.name)
+    .bind(&payload.contract_type)
+    .bind(payload.start_date)
+    .bind(payload.end_date)
+    .bind(payload.monthly_value)
+    .bind(payload.hourly_rate)
+    .bind(payload.included_hours)
+    .bind(payload.overage_rate)
+    .bind(&payload.terms)
+    .bind(payload.auto_renew)
+    .bind(&payload.block_rollover_policy)
+    .bind(payload.block_rollover_cap)
+    .bind(payload.block_rollover_months)
+    .bind(payload.block_validity_months)
+    .bind(payload.low_balance_percent)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error creating contract: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::CREATED, Json(get_contract_by_id(&state, id).await?)))
+}
+
+async fn update_contract(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<ContractUpdate>,
+) -> Result<Json<ContractWithClient>, StatusCode> {
+    if payload
+        .block_rollover_policy
+        .as_deref()
+        .is_some_and(|p| !ROLLOVER_POLICIES.contains(&p))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let result = sqlx::query(
+        r#"
+        UPDATE contracts SET
+            name = COALESCE($2, name),
+            end_date = COALESCE($3, end_date),
+            monthly_value = COALESCE($4, monthly_value),
+            hourly_rate = COALESCE($5, hourly_rate),
+            included_hours = COALESCE($6, included_hours),
+            overage_rate = COALESCE($7, overage_rate),
+            status = COALESCE($8, status),
+            terms = COALESCE($9, terms),
+            auto_renew = COALESCE($10, auto_renew),
+            block_rollover_policy = COALESCE($11, block_rollover_policy),
+            block_rollover_cap = COALESCE($12, block_rollover_cap),
+            block_rollover_months = COALESCE($13, block_rollover_months),
+            block_validity_months = COALESCE($14, block_validity_months),
+            low_balance_percent = COALESCE($15, low_balance_percent),
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(payload.name)
+    .bind(payload.end_date)
+    .bind(payload.monthly_value)
+    .bind(payload.hourly_rate)
+    .bind(payload.included_hours)
+    .bind(payload.overage_rate)
+    .bind(payload.status)
+    .bind(payload.terms)
+    .bind(payload.auto_renew)
+    .bind(payload.block_rollover_policy)
+    .bind(payload.block_rollover_cap)
+    .bind(payload.block_rollover_months)
+    .bind(payload.block_validity_months)
+    .bind(payload.low_balance_percent)
+    .execute(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error updating contract: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(Json(get_contract_by_id(&state, id).await?))
+}
+
+async fn list_blocks(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<PrepaidBlock>>, StatusCode> {
+    let blocks = sqlx::query_as::<_, PrepaidBlock>(&format!(
+        "SELECT {} FROM contract_prepaid_blocks b WHERE b.contract_id = $1 ORDER BY b.purchased_on DESC, b.created_at DESC",
+        block_columns()
+    ))
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching prepaid blocks: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(blocks))
+}
+
+/// Sells a block of hours or dollars against a contract. Expiry defaults to
+/// the contract's block validity.
+async fn create_block(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<BlockCreate>,
+) -> Result<(StatusCode, Json<PrepaidBlock>), StatusCode> {
+    if BlockType::parse(&payload.block_type).is_none() || payload.quantity <= Decimal::ZERO {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let contract = get_contract_by_id(&state, id).await?;
+    let purchased_on = payload.purchased_on.unwrap_or_else(|| Utc::now().date_naive());
+    let expires_on = payload.expires_on.or_else(|| {
+        contract
+            .block_validity_months
+            .filter(|m| *m > 0)
+            .and_then(|m| purchased_on.checked_add_months(Months::new(m as u32)))
+    });
+
+    let block_id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO contract_prepaid_blocks (contract_id, client_id, block_type, quantity, price, purchased_on,
+                                             expires_on, invoice_id, notes, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        RETURNING id
+        "#,
+    )
+    .bind(contract.id)
+    .bind(contract.client_id)
+    .bind(&payload.block_type)
+    .bind(payload.quantity)
+    .bind(payload.price)
+    .bind(purchased_on)
+    .bind(expires_on)
+    .bind(payload.invoice_id)
+    .bind(&payload.notes)
+    .bind(auth.0.id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error creating prepaid block: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let block = sqlx::query_as::<_, PrepaidBlock>(&format!(
+        "SELECT {} FROM contract_prepaid_blocks b WHERE b.id = $1",
+        block_columns()
+    ))
+    .bind(block_id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching prepaid block: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::CREATED, Json(block)))
+}
+
+async fn list_drawdowns(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(block_id): Path<Uuid>,
+) -> Result<Json<Vec<BlockDrawdown>>, StatusCode> {
+    let drawdowns = sqlx::query_as::<_, BlockDrawdown>(
+        r#"
+        SELECT d.id, d.time_entry_id, d.hours, d.amount, te.start_time::date as work_date,
+               COALESCE(te.description, t.subject, p.name) as description,
+               u.first_name || ' ' || u.last_name as user_name, d.created_at
+        FROM prepaid_block_drawdowns d
+        JOIN time_entries te ON te.id = d.time_entry_id
+        LEFT JOIN tickets t ON t.id = te.ticket_id
+        LEFT JOIN projects p ON p.id = te.project_id
+        LEFT JOIN users u ON u.id = te.user_id
+        WHERE d.block_id = $1
+        ORDER BY te.start_time DESC
+        "#,
+    )
+    .bind(block_id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching block drawdowns: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(drawdowns))
+}
+
+async fn get_contract_by_id(state: &AppState, id: Uuid) -> Result<ContractWithClient, StatusCode> {
+    sqlx::query_as::<_, ContractWithClient>(&format!(
+        "SELECT {} FROM contracts ct LEFT JOIN clients c ON c.id = ct.client_id WHERE ct.id = $1",
+        CONTRACT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Error fetching contract: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })
+}
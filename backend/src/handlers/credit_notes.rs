@@ -0,0 +1,404 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::services::credit_notes::{
+    self, CreditApplication, CreditNote, Refund, CREDIT_APPLICATION_COLUMNS, CREDIT_NOTE_COLUMNS,
+    REFUND_COLUMNS,
+};
+use crate::AppState;
+
+pub fn credit_note_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_credit_notes).post(create_credit_note))
+        .route("/balance/:client_id", get(get_client_credit_balance))
+        .route("/:id", get(get_credit_note))
+        .route("/:id/apply", post(apply_credit_note))
+        .route("/:id/refund", post(refund_credit_note))
+        .route("/:id/void", post(void_credit_note))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreditNoteQuery {
+    pub client_id: Option<Uuid>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreditNoteDetails {
+    #[serde(flatten)]
+    pub credit_note: CreditNote,
+    pub applications: Vec<CreditApplication>,
+    pub refunds: Vec<Refund>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClientCreditBalance {
+    pub client_id: Uuid,
+    pub currency: String, // the client's billing currency; credit in other currencies isn't counted
+    pub available_credit: Decimal,
+    pub open_credit_notes: i64,
+}
+
+/// Account credit not tied to an invoice, e.g. a goodwill credit or an overpayment.
+#[derive(Debug, Deserialize)]
+pub struct CreditNoteCreate {
+    pub client_id: Uuid,
+    pub amount: Decimal,
+    pub reason: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreditNoteApply {
+    pub invoice_id: Uuid,
+    pub amount: Option<Decimal>, // defaults to as much as both sides allow
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreditNoteRefund {
+    pub amount: Decimal,
+    pub processor: Option<String>,
+    pub reference_number: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreditNoteVoid {
+    pub reason: String,
+}
+
+async fn list_credit_notes(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<CreditNoteQuery>,
+) -> Result<Json<Vec<CreditNote>>, StatusCode> {
+    let notes = sqlx::query_as::<_, CreditNote>(&format!(
+        "SELECT {} FROM credit_notes cn JOIN clients c ON c.id = cn.client_id
+         WHERE ($1::UUID IS NULL OR cn.client_id = $1) AND ($2::TEXT IS NULL OR cn.status = $2)
+         ORDER BY cn.issue_date DESC NULLS LAST, cn.credit_note_number DESC",
+        CREDIT_NOTE_COLUMNS
+    ))
+    .bind(params.client_id)
+    .bind(params.status)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching credit notes: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(notes))
+}
+
+async fn get_credit_note(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<CreditNoteDetails>, StatusCode> {
+    Ok(Json(load_credit_note(&state, id).await?))
+}
+
+async fn create_credit_note(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<CreditNoteCreate>,
+) -> Result<(StatusCode, Json<CreditNoteDetails>), StatusCode> {
+    if payload.amount <= Decimal::ZERO || payload.reason.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut tx = state.db_pool.begin().await.map_err(|e| {
+        tracing::error!("Error starting transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let id = credit_notes::issue_credit_note(
+        &mut tx,
+        payload.client_id,
+        None,
+        "account",
+        payload.amount,
+        payload.reason.trim(),
+        payload.description.as_deref(),
+        Some(auth.0.id),
+    )
+    .await
+    .map_err(|e| {
+        tracing::error!("Error issuing credit note: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Error committing transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::CREATED, Json(load_credit_note(&state, id).await?)))
+}
+
+async fn get_client_credit_balance(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(client_id): Path<Uuid>,
+) -> Result<Json<ClientCreditBalance>, StatusCode> {
+    let (currency, available_credit, open_credit_notes): (String, Decimal, i64) = sqlx::query_as(
+        "SELECT c.currency, COALESCE(SUM(COALESCE(cn.remaining_amount, cn.amount)), 0), COUNT(cn.id)
+         FROM clients c
+         LEFT JOIN credit_notes cn ON cn.client_id = c.id AND cn.currency = c.currency
+              AND cn.status IN ('issued', 'partially_applied') AND COALESCE(cn.remaining_amount, cn.amount) > 0
+         WHERE c.id = $1
+         GROUP BY c.currency",
+    )
+    .bind(client_id)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching client credit balance: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(ClientCreditBalance { client_id, currency, available_credit, open_credit_notes }))
+}
+
+async fn apply_credit_note(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<CreditNoteApply>,
+) -> Result<Json<CreditNoteDetails>, StatusCode> {
+    let mut tx = state.db_pool.begin().await.map_err(|e| {
+        tracing::error!("Error starting transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let (client_id, currency, remaining) = lock_open_credit_note(&mut tx, id).await?;
+
+    let invoice = credit_notes::lock_invoice(&mut tx, payload.invoice_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error locking invoice: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if invoice.client_id != client_id || invoice.currency != currency {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if invoice.is_void() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let amount = payload.amount.unwrap_or_else(|| remaining.min(invoice.balance));
+    if amount <= Decimal::ZERO {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if amount > remaining || amount > invoice.balance {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    credit_notes::apply_credit(&mut tx, id, &invoice, amount, Some(auth.0.id))
+        .await
+        .map_err(|e| {
+            tracing::error!("Error applying credit note: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Error committing transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(load_credit_note(&state, id).await?))
+}
+
+/// Pays out unused credit to the client.
+async fn refund_credit_note(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<CreditNoteRefund>,
+) -> Result<(StatusCode, Json<Refund>), StatusCode> {
+    if payload.amount <= Decimal::ZERO {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut tx = state.db_pool.begin().await.map_err(|e| {
+        tracing::error!("Error starting transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let (client_id, _, remaining) = lock_open_credit_note(&mut tx, id).await?;
+    if payload.amount > remaining {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let refund = credit_notes::insert_refund(
+        &mut tx,
+        client_id,
+        None,
+        None,
+        Some(id),
+        payload.amount,
+        payload.processor.as_deref().unwrap_or("manual"),
+        payload.reference_number.as_deref(),
+        payload.notes.as_deref(),
+        Some(auth.0.id),
+    )
+    .await
+    .map_err(|e| {
+        tracing::error!("Error recording credit refund: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    sqlx::query(
+        r#"
+        UPDATE credit_notes SET
+            refunded_amount = refunded_amount + $2,
+            remaining_amount = COALESCE(remaining_amount, amount) - $2,
+            status = CASE
+                WHEN COALESCE(remaining_amount, amount) - $2 > 0 THEN status
+                WHEN COALESCE(applied_amount, 0) > 0 THEN 'fully_applied'
+                ELSE 'refunded'
+            END,
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(payload.amount)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error updating refunded credit note: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Error committing transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::CREATED, Json(refund)))
+}
+
+/// Voids a credit note that hasn't been used. Used credit has to be reversed
+/// by voiding the invoice it was applied to.
+async fn void_credit_note(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<CreditNoteVoid>,
+) -> Result<Json<CreditNoteDetails>, StatusCode> {
+    if payload.reason.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let result = sqlx::query(
+        r#"
+        UPDATE credit_notes SET status = 'void', remaining_amount = 0, void_reason = $2,
+                                voided_at = NOW(), voided_by = $3, updated_at = NOW()
+        WHERE id = $1 AND status IN ('draft', 'issued')
+          AND COALESCE(applied_amount, 0) = 0 AND refunded_amount = 0
+        "#,
+    )
+    .bind(id)
+    .bind(payload.reason.trim())
+    .bind(auth.0.id)
+    .execute(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error voiding credit note: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if result.rows_affected() == 0 {
+        // Distinguish a missing note from one that can no longer be voided
+        load_credit_note(&state, id).await?;
+        return Err(StatusCode::CONFLICT);
+    }
+
+    Ok(Json(load_credit_note(&state, id).await?))
+}
+
+/// Locks a credit note that still has credit to use, returning its client and remaining amount.
+async fn lock_open_credit_note(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    id: Uuid,
+) -> Result<(Uuid, String, Decimal), StatusCode> {
+    let (client_id, status, currency, remaining): (Uuid, Option<String>, String, Decimal) = sqlx::query_as(
+        "SELECT client_id, status, currency, COALESCE(remaining_amount, amount) FROM credit_notes WHERE id = $1 FOR UPDATE",
+    )
+    .bind(id)
+    .fetch_optional(&mut **tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error locking credit note: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    if !matches!(status.as_deref(), Some("issued") | Some("partially_applied")) || remaining <= Decimal::ZERO {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    Ok((client_id, currency, remaining))
+}
+
+async fn load_credit_note(state: &AppState, id: Uuid) -> Result<CreditNoteDetails, StatusCode> {
+    let credit_note = sqlx::query_as::<_, CreditNote>(&format!(
+        "SELECT {} FROM credit_notes cn JOIN clients c ON c.id = cn.client_id WHERE cn.id = $1",
+        CREDIT_NOTE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Error fetching credit note: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    let applications = sqlx::query_as::<_, CreditApplication>(&format!(
+        "SELECT {} FROM credit_note_applications a
+         JOIN credit_notes cn ON cn.id = a.credit_note_id
+         JOIN invoices i ON i.id = a.invoice_id
+         WHERE a.credit_note_id = $1
+         ORDER BY a.applied_at",
+        CREDIT_APPLICATION_COLUMNS
+    ))
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching credit note applications: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let refunds = sqlx::query_as::<_, Refund>(&format!(
+        "SELECT {} FROM payment_transactions
+         WHERE credit_note_id = $1 AND transaction_type = 'refund'
+         ORDER BY processed_at",
+        REFUND_COLUMNS
+    ))
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching credit note refunds: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(CreditNoteDetails { credit_note, applications, refunds })
+}
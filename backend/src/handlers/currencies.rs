@@ -0,0 +1,431 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+    routing::{delete, get, post, put},
+    Router,
+};
+use chrono::{NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::services::currency::{self, Currency, ExchangeRate, CURRENCY_COLUMNS, RATE_COLUMNS};
+use crate::services::{ExchangeRateConfig, ExchangeRateService};
+use crate::AppState;
+
+pub fn currency_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_currencies).post(create_currency))
+        .route("/:code", put(update_currency))
+        .route("/rates", get(list_rates).post(set_rate))
+        .route("/rates/sync", post(sync_rates))
+        .route("/rates/:id", delete(delete_rate))
+        .route("/convert", get(convert_amount))
+        .route("/report", get(currency_report))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CurrencyCreate {
+    pub code: String,
+    pub name: String,
+    pub symbol: String,
+    pub minor_units: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CurrencyUpdate {
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RateQuery {
+    pub from_currency: Option<String>,
+    pub to_currency: Option<String>,
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+}
+
+/// A rate entered by hand. Replaces any rate already stored for the day.
+#[derive(Debug, Deserialize)]
+pub struct RateCreate {
+    pub from_currency: String,
+    pub to_currency: String,
+    pub rate: Decimal,
+    pub rate_date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConvertQuery {
+    pub amount: Decimal,
+    pub from_currency: String,
+    pub to_currency: Option<String>, // defaults to the base currency
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Conversion {
+    pub amount: Decimal,
+    pub from_currency: String,
+    pub converted: Decimal,
+    pub to_currency: String,
+    pub rate: Decimal,
+    pub rate_date: NaiveDate,
+    pub formatted: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportQuery {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+
+/// Invoicing for a period per currency, with base-currency equivalents. The
+/// invoiced and collected columns use the rates fixed on each document;
+/// outstanding is also revalued at today's rate to show unrealised exposure.
+#[derive(Debug, Serialize, FromRow)]
+pub struct CurrencyReportRow {
+    pub currency: String,
+    pub base_currency: String,
+    pub invoice_count: i64,
+    pub invoiced: Decimal,
+    pub invoiced_base: Decimal,
+    pub collected: Decimal,
+    pub collected_base: Decimal,
+    pub realised_gain: Decimal, // collected_base less the same payments at the invoice rate
+    pub outstanding: Decimal,
+    pub outstanding_base: Decimal,
+    #[sqlx(skip)]
+    pub outstanding_base_today: Option<Decimal>, // None when no current rate is on file
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncResult {
+    pub rates_stored: usize,
+}
+
+async fn list_currencies(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+) -> Result<Json<Vec<Currency>>, StatusCode> {
+    let currencies = sqlx::query_as::<_, Currency>(&format!(
+        "SELECT {} FROM currencies ORDER BY is_base DESC, is_active DESC, code",
+        CURRENCY_COLUMNS
+    ))
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching currencies: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(currencies))
+}
+
+async fn create_currency(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Json(payload): Json<CurrencyCreate>,
+) -> Result<(StatusCode, Json<Currency>), StatusCode> {
+    let code = payload.code.trim().to_uppercase();
+    let minor_units = payload.minor_units.unwrap_or(2);
+    if code.len() != 3
+        || !code.chars().all(|c| c.is_ascii_uppercase())
+        || payload.name.trim().is_empty()
+        || !(0..=4).contains(&minor_units)
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let currency = sqlx::query_as::<_, Currency>(&format!(
+        "INSERT INTO currencies (code, name, symbol, minor_units) VALUES ($1, $2, $3, $4)
+         ON CONFLICT (code) DO NOTHING
+         RETURNING {}",
+        CURRENCY_COLUMNS
+    ))
+    .bind(&code)
+    .bind(payload.name.trim())
+    .bind(payload.symbol.trim())
+    .bind(minor_units)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error creating currency: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::CONFLICT)?;
+
+    Ok((StatusCode::CREATED, Json(currency)))
+}
+
+/// The base currency can't be deactivated, nor can a currency clients are still billed in.
+async fn update_currency(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(code): Path<String>,
+    Json(payload): Json<CurrencyUpdate>,
+) -> Result<Json<Currency>, StatusCode> {
+    let code = code.to_uppercase();
+
+    if payload.is_active == Some(false) {
+        let in_use: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM currencies WHERE code = $1 AND is_base = true)
+                 OR EXISTS (SELECT 1 FROM clients WHERE currency = $1 AND archived_at IS NULL)",
+        )
+        .bind(&code)
+        .fetch_one(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error checking currency usage: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        if in_use {
+            return Err(StatusCode::CONFLICT);
+        }
+    }
+
+    let currency = sqlx::query_as::<_, Currency>(&format!(
+        "UPDATE currencies SET
+            name = COALESCE($2, name),
+            symbol = COALESCE($3, symbol),
+            is_active = COALESCE($4, is_active)
+         WHERE code = $1
+         RETURNING {}",
+        CURRENCY_COLUMNS
+    ))
+    .bind(&code)
+    .bind(payload.name)
+    .bind(payload.symbol)
+    .bind(payload.is_active)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error updating currency: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(currency))
+}
+
+async fn list_rates(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<RateQuery>,
+) -> Result<Json<Vec<ExchangeRate>>, StatusCode> {
+    let rates = sqlx::query_as::<_, ExchangeRate>(&format!(
+        "SELECT {} FROM exchange_rates
+         WHERE ($1::TEXT IS NULL OR from_currency = UPPER($1))
+           AND ($2::TEXT IS NULL OR to_currency = UPPER($2))
+           AND ($3::DATE IS NULL OR rate_date >= $3)
+           AND ($4::DATE IS NULL OR rate_date <= $4)
+         ORDER BY rate_date DESC, from_currency, to_currency
+         LIMIT 500",
+        RATE_COLUMNS
+    ))
+    .bind(params.from_currency)
+    .bind(params.to_currency)
+    .bind(params.start_date)
+    .bind(params.end_date)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching exchange rates: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(rates))
+}
+
+async fn set_rate(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<RateCreate>,
+) -> Result<(StatusCode, Json<ExchangeRate>), StatusCode> {
+    let from_currency = payload.from_currency.to_uppercase();
+    let to_currency = payload.to_currency.to_uppercase();
+    if from_currency == to_currency || payload.rate <= Decimal::ZERO {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // A manual rate replaces a provider rate for the day but never the reverse
+    let rate = sqlx::query_as::<_, ExchangeRate>(&format!(
+        "INSERT INTO exchange_rates (from_currency, to_currency, rate, rate_date, source, created_by)
+         VALUES ($1, $2, $3, $4, 'manual', $5)
+         ON CONFLICT (from_currency, to_currency, rate_date) DO UPDATE SET
+            rate = EXCLUDED.rate, source = 'manual', created_by = EXCLUDED.created_by, created_at = NOW()
+         RETURNING {}",
+        RATE_COLUMNS
+    ))
+    .bind(&from_currency)
+    .bind(&to_currency)
+    .bind(payload.rate.round_dp(8))
+    .bind(payload.rate_date.unwrap_or_else(|| Utc::now().date_naive()))
+    .bind(auth.0.id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_foreign_key_violation() => StatusCode::BAD_REQUEST,
+        e => {
+            tracing::error!("Error saving exchange rate: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok((StatusCode::CREATED, Json(rate)))
+}
+
+/// Removes a stored rate. Invoices already raised keep their snapshot.
+async fn delete_rate(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query("DELETE FROM exchange_rates WHERE id = $1")
+        .bind(id)
+        .execute(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error deleting exchange rate: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetches today's rates from the configured provider now.
+async fn sync_rates(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+) -> Result<Json<SyncResult>, StatusCode> {
+    let service = ExchangeRateService::new(ExchangeRateConfig::default(), state.db_pool.clone());
+
+    let rates_stored = service.sync_rates().await.map_err(|e| {
+        tracing::error!("Error syncing exchange rates: {}", e);
+        if e.to_string().contains("No exchange rate provider") {
+            StatusCode::SERVICE_UNAVAILABLE
+        } else {
+            StatusCode::BAD_GATEWAY
+        }
+    })?;
+
+    Ok(Json(SyncResult { rates_stored }))
+}
+
+async fn convert_amount(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<ConvertQuery>,
+) -> Result<Json<Conversion>, StatusCode> {
+    let mut conn = state.db_pool.acquire().await.map_err(|e| {
+        tracing::error!("Error acquiring connection: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let from_currency = params.from_currency.to_uppercase();
+    let to_currency = match params.to_currency {
+        Some(code) => code.to_uppercase(),
+        None => currency::base_currency(&mut conn).await.map_err(|e| {
+            tracing::error!("Error fetching base currency: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?,
+    };
+    let date = params.date.unwrap_or_else(|| Utc::now().date_naive());
+
+    let (rate, rate_date) = currency::rate_on(&mut conn, &from_currency, &to_currency, date)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching exchange rate: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let converted = currency::convert(params.amount, rate);
+    Ok(Json(Conversion {
+        amount: params.amount,
+        from_currency,
+        formatted: currency::format_money(converted, &to_currency),
+        converted,
+        to_currency,
+        rate,
+        rate_date,
+    }))
+}
+
+async fn currency_report(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<ReportQuery>,
+) -> Result<Json<Vec<CurrencyReportRow>>, StatusCode> {
+    if params.end_date < params.start_date {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut conn = state.db_pool.acquire().await.map_err(|e| {
+        tracing::error!("Error acquiring connection: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let mut rows = sqlx::query_as::<_, CurrencyReportRow>(
+        r#"
+        WITH issued AS (
+            SELECT id, currency, base_currency, exchange_rate, total, balance,
+                   COALESCE(base_total, ROUND(total * exchange_rate, 2)) as base_total
+            FROM invoices
+            WHERE date BETWEEN $1 AND $2 AND status NOT IN ('draft', 'void')
+        ),
+        collected AS (
+            SELECT p.invoice_id,
+                   SUM(p.amount - p.refunded_amount) as amount,
+                   SUM(COALESCE(p.base_amount, ROUND(p.amount * p.exchange_rate, 2))
+                       - ROUND(p.refunded_amount * p.exchange_rate, 2)) as base_amount
+            FROM payments p
+            JOIN issued i ON i.id = p.invoice_id
+            GROUP BY p.invoice_id
+        )
+        SELECT i.currency, i.base_currency,
+               COUNT(*) as invoice_count,
+               COALESCE(SUM(i.total), 0) as invoiced,
+               COALESCE(SUM(i.base_total), 0) as invoiced_base,
+               COALESCE(SUM(c.amount), 0) as collected,
+               COALESCE(SUM(c.base_amount), 0) as collected_base,
+               COALESCE(SUM(c.base_amount - ROUND(c.amount * i.exchange_rate, 2)), 0) as realised_gain,
+               COALESCE(SUM(i.balance), 0) as outstanding,
+               COALESCE(SUM(ROUND(i.balance * i.exchange_rate, 2)), 0) as outstanding_base
+        FROM issued i
+        LEFT JOIN collected c ON c.invoice_id = i.id
+        GROUP BY i.currency, i.base_currency
+        ORDER BY i.currency
+        "#,
+    )
+    .bind(params.start_date)
+    .bind(params.end_date)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error building currency report: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let today = Utc::now().date_naive();
+    for row in &mut rows {
+        let rate = currency::rate_on(&mut conn, &row.currency, &row.base_currency, today)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error fetching exchange rate: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        row.outstanding_base_today = rate.map(|(rate, _)| currency::convert(row.outstanding, rate));
+    }
+
+    Ok(Json(rows))
+}
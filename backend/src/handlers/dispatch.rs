@@ -0,0 +1,871 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
+    routing::{delete, get, post},
+    Router,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::handlers::{ensure_client_contact, internal};
+use crate::notifications::{enqueue_notification, QueuedNotification};
+use crate::services::dispatch::{
+    self, Appointment, AvailabilityWindow, DispatchBoard, DispatchConflict, ScheduledAppointment, Slot,
+    TechnicianHours, TimeOff, ACTIVE_STATUSES, APPOINTMENT_COLUMNS, APPOINTMENT_JOINS, APPOINTMENT_STATUSES,
+    TIME_OFF_COLUMNS, TIME_OFF_KINDS,
+};
+use crate::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct BoardQuery {
+    pub date: Option<NaiveDate>,
+    pub days: Option<i64>,
+    pub technician_ids: Option<String>, // comma-separated
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AppointmentQuery {
+    pub technician_id: Option<Uuid>,
+    pub client_id: Option<Uuid>,
+    pub ticket_id: Option<Uuid>,
+    pub task_id: Option<Uuid>,
+    pub status: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AppointmentCreate {
+    pub ticket_id: Option<Uuid>,
+    pub task_id: Option<Uuid>,
+    pub technician_id: Uuid,
+    pub location_id: Option<Uuid>,
+    pub contact_id: Option<Uuid>,
+    pub title: Option<String>,
+    pub notes: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub send_confirmation: Option<bool>, // default true
+}
+
+/// Dragging a card on the board sends the new technician and times.
+#[derive(Debug, Deserialize)]
+pub struct AppointmentUpdate {
+    pub technician_id: Option<Uuid>,
+    pub location_id: Option<Uuid>,
+    pub clear_location: Option<bool>, // switch to remote work
+    pub contact_id: Option<Uuid>,
+    pub title: Option<String>,
+    pub notes: Option<String>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub notify_client: Option<bool>, // default true when the time changes
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CancelAppointment {
+    pub reason: Option<String>,
+    pub notify_client: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatusUpdate {
+    pub status: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SlotCheck {
+    pub appointment_id: Option<Uuid>,
+    pub technician_id: Uuid,
+    pub location_id: Option<Uuid>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AvailabilityUpdate {
+    pub windows: Vec<AvailabilityWindow>, // empty to fall back to working days
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimeOffCreate {
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub kind: Option<String>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimeOffQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TravelTime {
+    pub from_location_id: Uuid,
+    pub to_location_id: Uuid,
+    pub minutes: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TravelTimeQuery {
+    pub client_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CalendarFeed {
+    pub token: String,
+    pub url: String,
+}
+
+pub fn dispatch_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/board", get(get_board))
+        .route("/check", post(check_slot))
+        .route("/appointments", get(list_appointments).post(create_appointment))
+        .route("/appointments/:id", get(get_appointment).put(update_appointment))
+        .route("/appointments/:id/cancel", post(cancel_appointment))
+        .route("/appointments/:id/status", post(update_status))
+        .route("/technicians/:id/availability", get(get_availability).put(update_availability))
+        .route("/technicians/:id/time-off", get(list_time_off).post(create_time_off))
+        .route("/technicians/:id/calendar-token", post(regenerate_calendar_token))
+        .route("/time-off/:id", delete(delete_time_off))
+        .route("/travel-times", get(list_travel_times).put(set_travel_time))
+        // Subscribed to from calendar apps, so authorised by the token alone
+        .route("/feeds/:token", get(calendar_feed))
+}
+
+async fn get_board(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<BoardQuery>,
+) -> Result<Json<DispatchBoard>, StatusCode> {
+    let days = params.days.unwrap_or(1);
+    if !(1..=31).contains(&days) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let date = params.date.unwrap_or_else(|| Utc::now().date_naive());
+    let from = date.and_hms_opt(0, 0, 0).unwrap().and_utc();
+    let to = from + Duration::days(days);
+
+    let technician_ids = match params.technician_ids {
+        Some(ids) => Some(
+            ids.split(',')
+                .filter(|id| !id.trim().is_empty())
+                .map(|id| id.trim().parse::<Uuid>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| StatusCode::BAD_REQUEST)?,
+        ),
+        None => None,
+    };
+
+    let board = dispatch::board(&state.db_pool, from, to, technician_ids.as_deref())
+        .await
+        .map_err(internal("building dispatch board"))?;
+    Ok(Json(board))
+}
+
+async fn check_slot(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Json(payload): Json<SlotCheck>,
+) -> Result<Json<Vec<DispatchConflict>>, StatusCode> {
+    if payload.end_time <= payload.start_time {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let slot = Slot {
+        id: payload.appointment_id,
+        start: payload.start_time,
+        end: payload.end_time,
+        location_id: payload.location_id,
+    };
+    let conflicts = dispatch::check_slot(&state.db_pool, payload.technician_id, &slot)
+        .await
+        .map_err(internal("checking slot"))?;
+    Ok(Json(conflicts))
+}
+
+async fn list_appointments(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<AppointmentQuery>,
+) -> Result<Json<Vec<Appointment>>, StatusCode> {
+    let appointments = sqlx::query_as::<_, Appointment>(&format!(
+        "SELECT {} {}
+         WHERE ($1::uuid IS NULL OR a.technician_id = $1)
+           AND ($2::uuid IS NULL OR a.client_id = $2)
+           AND ($3::uuid IS NULL OR a.ticket_id = $3)
+           AND ($4::uuid IS NULL OR a.task_id = $4)
+           AND ($5::text IS NULL OR a.status = $5)
+           AND ($6::timestamptz IS NULL OR a.end_time > $6)
+           AND ($7::timestamptz IS NULL OR a.start_time < $7)
+         ORDER BY a.start_time
+         LIMIT 500",
+        APPOINTMENT_COLUMNS, APPOINTMENT_JOINS
+    ))
+    .bind(params.technician_id)
+    .bind(params.client_id)
+    .bind(params.ticket_id)
+    .bind(params.task_id)
+    .bind(params.status)
+    .bind(params.from)
+    .bind(params.to)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("listing appointments"))?;
+    Ok(Json(appointments))
+}
+
+async fn get_appointment(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ScheduledAppointment>, StatusCode> {
+    let appointment = load_appointment(&state, id).await?;
+    let conflicts = if ACTIVE_STATUSES.contains(&appointment.status.as_str()) {
+        dispatch::check_slot(&state.db_pool, appointment.technician_id, &appointment.slot())
+            .await
+            .map_err(internal("checking appointment conflicts"))?
+    } else {
+        Vec::new()
+    };
+    Ok(Json(ScheduledAppointment { appointment, conflicts }))
+}
+
+/// Conflicts are returned with the booking rather than refusing it; the
+/// dispatcher may knowingly double-book or send someone in early.
+async fn create_appointment(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<AppointmentCreate>,
+) -> Result<(StatusCode, Json<ScheduledAppointment>), StatusCode> {
+    if payload.end_time <= payload.start_time {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // The visit is for the ticket's client, or the client of the task's project
+    let (client_id, default_title): (Uuid, String) = match (payload.ticket_id, payload.task_id) {
+        (Some(ticket_id), None) => sqlx::query_as("SELECT client_id, subject FROM tickets WHERE id = $1")
+            .bind(ticket_id)
+            .fetch_optional(&state.db_pool)
+            .await
+            .map_err(internal("fetching ticket"))?
+            .ok_or(StatusCode::NOT_FOUND)?,
+        (None, Some(task_id)) => sqlx::query_as(
+            "SELECT p.client_id, t.name FROM tasks t JOIN projects p ON p.id = t.project_id WHERE t.id = $1",
+        )
+        .bind(task_id)
+        .fetch_optional(&state.db_pool)
+        .await
+        .map_err(internal("fetching task"))?
+        .ok_or(StatusCode::NOT_FOUND)?,
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+    ensure_technician(&state, payload.technician_id).await?;
+    if let Some(location_id) = payload.location_id {
+        ensure_client_location(&state, client_id, location_id).await?;
+    }
+    let contact_id = match payload.contact_id {
+        Some(contact_id) => {
+            ensure_client_contact(&state.db_pool, client_id, contact_id).await?;
+            Some(contact_id)
+        }
+        None => primary_contact(&state, client_id).await?,
+    };
+    let title = payload
+        .title
+        .filter(|t| !t.trim().is_empty())
+        .map(|t| t.trim().to_string())
+        .unwrap_or(default_title);
+
+    let id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO appointments (client_id, ticket_id, task_id, technician_id, location_id, contact_id, title,
+                                  notes, start_time, end_time, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        RETURNING id
+        "#,
+    )
+    .bind(client_id)
+    .bind(payload.ticket_id)
+    .bind(payload.task_id)
+    .bind(payload.technician_id)
+    .bind(payload.location_id)
+    .bind(contact_id)
+    .bind(&title)
+    .bind(&payload.notes)
+    .bind(payload.start_time)
+    .bind(payload.end_time)
+    .bind(auth.0.id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(internal("creating appointment"))?;
+
+    let appointment = load_appointment(&state, id).await?;
+    let conflicts = dispatch::check_slot(&state.db_pool, appointment.technician_id, &appointment.slot())
+        .await
+        .map_err(internal("checking appointment conflicts"))?;
+
+    if payload.send_confirmation.unwrap_or(true) {
+        notify_client(&state, &appointment, ClientNotice::Confirmation).await?;
+    }
+    notify_technician(&state, &appointment, auth.0.id).await;
+
+    let appointment = load_appointment(&state, id).await?;
+    Ok((StatusCode::CREATED, Json(ScheduledAppointment { appointment, conflicts })))
+}
+
+async fn update_appointment(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<AppointmentUpdate>,
+) -> Result<Json<ScheduledAppointment>, StatusCode> {
+    let current = load_appointment(&state, id).await?;
+    if !ACTIVE_STATUSES.contains(&current.status.as_str()) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let technician_id = payload.technician_id.unwrap_or(current.technician_id);
+    let start_time = payload.start_time.unwrap_or(current.start_time);
+    let end_time = payload.end_time.unwrap_or(current.end_time);
+    if end_time <= start_time {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let location_id = if payload.clear_location.unwrap_or(false) {
+        None
+    } else {
+        payload.location_id.or(current.location_id)
+    };
+    if technician_id != current.technician_id {
+        ensure_technician(&state, technician_id).await?;
+    }
+    if let Some(location_id) = payload.location_id {
+        ensure_client_location(&state, current.client_id, location_id).await?;
+    }
+    if let Some(contact_id) = payload.contact_id {
+        ensure_client_contact(&state.db_pool, current.client_id, contact_id).await?;
+    }
+
+    let rescheduled = start_time != current.start_time || end_time != current.end_time;
+    let moved = rescheduled || technician_id != current.technician_id || location_id != current.location_id;
+
+    sqlx::query(
+        r#"
+        UPDATE appointments
+        SET technician_id = $2, location_id = $3, contact_id = COALESCE($4, contact_id),
+            title = COALESCE($5, title), notes = COALESCE($6, notes), start_time = $7, end_time = $8,
+            sequence = sequence + CASE WHEN $9 THEN 1 ELSE 0 END,
+            status = CASE WHEN $10 AND status = 'confirmed' THEN 'scheduled' ELSE status END,
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(technician_id)
+    .bind(location_id)
+    .bind(payload.contact_id)
+    .bind(payload.title.filter(|t| !t.trim().is_empty()))
+    .bind(payload.notes)
+    .bind(start_time)
+    .bind(end_time)
+    .bind(moved)
+    .bind(rescheduled)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("updating appointment"))?;
+
+    let appointment = load_appointment(&state, id).await?;
+    if rescheduled && payload.notify_client.unwrap_or(true) {
+        notify_client(&state, &appointment, ClientNotice::Rescheduled).await?;
+    }
+    if technician_id != current.technician_id || rescheduled {
+        notify_technician(&state, &appointment, auth.0.id).await;
+    }
+
+    let appointment = load_appointment(&state, id).await?;
+    let conflicts = dispatch::check_slot(&state.db_pool, appointment.technician_id, &appointment.slot())
+        .await
+        .map_err(internal("checking appointment conflicts"))?;
+    Ok(Json(ScheduledAppointment { appointment, conflicts }))
+}
+
+async fn cancel_appointment(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<CancelAppointment>,
+) -> Result<Json<Appointment>, StatusCode> {
+    let updated = sqlx::query(
+        "UPDATE appointments
+         SET status = 'cancelled', cancelled_at = NOW(), cancellation_reason = $2, sequence = sequence + 1,
+             updated_at = NOW()
+         WHERE id = $1 AND status = ANY($3)",
+    )
+    .bind(id)
+    .bind(payload.reason.filter(|r| !r.trim().is_empty()))
+    .bind(ACTIVE_STATUSES)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("cancelling appointment"))?;
+    if updated.rows_affected() == 0 {
+        load_appointment(&state, id).await?;
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let appointment = load_appointment(&state, id).await?;
+    if payload.notify_client.unwrap_or(true) && appointment.end_time > Utc::now() {
+        notify_client(&state, &appointment, ClientNotice::Cancelled).await?;
+    }
+    Ok(Json(appointment))
+}
+
+async fn update_status(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<StatusUpdate>,
+) -> Result<Json<Appointment>, StatusCode> {
+    // Cancelling goes through /cancel so the client hears about it
+    if !APPOINTMENT_STATUSES.contains(&payload.status.as_str()) || payload.status == "cancelled" {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let current = load_appointment(&state, id).await?;
+    if current.status == "cancelled" {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    sqlx::query("UPDATE appointments SET status = $2, updated_at = NOW() WHERE id = $1")
+        .bind(id)
+        .bind(&payload.status)
+        .execute(&state.db_pool)
+        .await
+        .map_err(internal("updating appointment status"))?;
+
+    Ok(Json(load_appointment(&state, id).await?))
+}
+
+async fn get_availability(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<TechnicianHours>, StatusCode> {
+    let hours = dispatch::technician_hours(&state.db_pool, user_id)
+        .await
+        .map_err(internal("fetching technician hours"))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(hours))
+}
+
+async fn update_availability(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<AvailabilityUpdate>,
+) -> Result<Json<TechnicianHours>, StatusCode> {
+    ensure_technician(&state, user_id).await?;
+    if payload
+        .windows
+        .iter()
+        .any(|w| !(1..=7).contains(&w.weekday) || w.end_time <= w.start_time)
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut tx = state.db_pool.begin().await.map_err(internal("starting transaction"))?;
+    sqlx::query("DELETE FROM technician_availability WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal("clearing availability"))?;
+    for window in &payload.windows {
+        sqlx::query(
+            "INSERT INTO technician_availability (user_id, weekday, start_time, end_time) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(user_id)
+        .bind(window.weekday)
+        .bind(window.start_time)
+        .bind(window.end_time)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal("storing availability"))?;
+    }
+    tx.commit().await.map_err(internal("committing availability"))?;
+
+    let hours = dispatch::technician_hours(&state.db_pool, user_id)
+        .await
+        .map_err(internal("fetching technician hours"))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(hours))
+}
+
+async fn list_time_off(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(user_id): Path<Uuid>,
+    Query(params): Query<TimeOffQuery>,
+) -> Result<Json<Vec<TimeOff>>, StatusCode> {
+    let time_off = sqlx::query_as::<_, TimeOff>(&format!(
+        "SELECT {} FROM technician_time_off
+         WHERE user_id = $1 AND ends_at > COALESCE($2, NOW()) AND ($3::timestamptz IS NULL OR starts_at < $3)
+         ORDER BY starts_at",
+        TIME_OFF_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(params.from)
+    .bind(params.to)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("listing time off"))?;
+    Ok(Json(time_off))
+}
+
+async fn create_time_off(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(user_id): Path<Uuid>,
+    Json(payload): Json<TimeOffCreate>,
+) -> Result<(StatusCode, Json<TimeOff>), StatusCode> {
+    let kind = payload.kind.unwrap_or_else(|| "vacation".to_string());
+    if payload.ends_at <= payload.starts_at || !TIME_OFF_KINDS.contains(&kind.as_str()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    ensure_technician(&state, user_id).await?;
+
+    let time_off = sqlx::query_as::<_, TimeOff>(&format!(
+        "INSERT INTO technician_time_off (user_id, starts_at, ends_at, kind, reason, created_by)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         RETURNING {}",
+        TIME_OFF_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(payload.starts_at)
+    .bind(payload.ends_at)
+    .bind(&kind)
+    .bind(payload.reason)
+    .bind(auth.0.id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(internal("creating time off"))?;
+    Ok((StatusCode::CREATED, Json(time_off)))
+}
+
+async fn delete_time_off(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = sqlx::query("DELETE FROM technician_time_off WHERE id = $1")
+        .bind(id)
+        .execute(&state.db_pool)
+        .await
+        .map_err(internal("deleting time off"))?;
+    if deleted.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_travel_times(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<TravelTimeQuery>,
+) -> Result<Json<Vec<TravelTime>>, StatusCode> {
+    let travel_times = sqlx::query_as::<_, TravelTime>(
+        "SELECT tt.from_location_id, tt.to_location_id, tt.minutes
+         FROM location_travel_times tt
+         JOIN locations f ON f.id = tt.from_location_id
+         JOIN locations t ON t.id = tt.to_location_id
+         WHERE $1::uuid IS NULL OR f.client_id = $1 OR t.client_id = $1
+         ORDER BY tt.from_location_id, tt.to_location_id",
+    )
+    .bind(params.client_id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("listing travel times"))?;
+    Ok(Json(travel_times))
+}
+
+async fn set_travel_time(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Json(payload): Json<TravelTime>,
+) -> Result<Json<TravelTime>, StatusCode> {
+    if payload.from_location_id == payload.to_location_id || payload.minutes < 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let (from, to) = dispatch::ordered(payload.from_location_id, payload.to_location_id);
+
+    let travel_time = sqlx::query_as::<_, TravelTime>(
+        "INSERT INTO location_travel_times (from_location_id, to_location_id, minutes)
+         VALUES ($1, $2, $3)
+         ON CONFLICT (from_location_id, to_location_id) DO UPDATE SET minutes = $3, updated_at = NOW()
+         RETURNING from_location_id, to_location_id, minutes",
+    )
+    .bind(from)
+    .bind(to)
+    .bind(payload.minutes)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_foreign_key_violation() => StatusCode::NOT_FOUND,
+        e => internal("storing travel time")(e),
+    })?;
+    Ok(Json(travel_time))
+}
+
+/// Issues a new feed URL; subscriptions to the old one stop updating.
+async fn regenerate_calendar_token(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<CalendarFeed>, StatusCode> {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+
+    let updated = sqlx::query("UPDATE users SET calendar_token = $2 WHERE id = $1")
+        .bind(user_id)
+        .bind(&token)
+        .execute(&state.db_pool)
+        .await
+        .map_err(internal("storing calendar token"))?;
+    if updated.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let url = format!("{}/api/v1/dispatch/feeds/{}", crate::config::app_base_url(), token);
+    Ok(Json(CalendarFeed { token, url }))
+}
+
+async fn calendar_feed(
+    State(state): State<Arc<AppState>>,
+    Path(token): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let token = token.trim_end_matches(".ics");
+    let (user_id, name): (Uuid, String) = sqlx::query_as(
+        "SELECT id, first_name || ' ' || last_name FROM users
+         WHERE calendar_token = $1 AND COALESCE(is_active, true)",
+    )
+    .bind(token)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(internal("looking up calendar token"))?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let calendar = dispatch::calendar_feed(&state.db_pool, user_id, &name)
+        .await
+        .map_err(internal("rendering calendar feed"))?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/calendar; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, "inline; filename=\"dispatch.ics\"".to_string()),
+        ],
+        calendar,
+    ))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ClientNotice {
+    Confirmation,
+    Rescheduled,
+    Cancelled,
+}
+
+/// Emails the site contact, with the visit time in the site's timezone, or
+/// the technician's for remote work.
+async fn notify_client(state: &AppState, appointment: &Appointment, notice: ClientNotice) -> Result<(), StatusCode> {
+    let Some(contact_id) = appointment.contact_id else {
+        return Ok(());
+    };
+    let (starts, ends, timezone) = local_times(state, appointment.id, true)
+        .await
+        .map_err(internal("formatting appointment time"))?;
+
+    let when = format!("{} to {} ({})", starts, ends, timezone);
+    let place = appointment
+        .location_name
+        .as_ref()
+        .map(|name| format!("at {}", name))
+        .unwrap_or_else(|| "remotely".to_string());
+    let (notification_type, subject, message) = match notice {
+        ClientNotice::Confirmation => (
+            "appointment_confirmation",
+            format!("Visit booked: {}", when),
+            format!(
+                "{} will work on \"{}\" {} on {}.",
+                appointment.technician_name, appointment.title, place, when
+            ),
+        ),
+        ClientNotice::Rescheduled => (
+            "appointment_rescheduled",
+            format!("Visit rescheduled: {}", when),
+            format!(
+                "Your appointment for \"{}\" has moved. {} will now work on it {} on {}.",
+                appointment.title, appointment.technician_name, place, when
+            ),
+        ),
+        ClientNotice::Cancelled => (
+            "appointment_cancelled",
+            format!("Visit cancelled: {}", appointment.title),
+            format!(
+                "Your appointment for \"{}\" on {} has been cancelled.{}",
+                appointment.title,
+                when,
+                appointment
+                    .cancellation_reason
+                    .as_ref()
+                    .map(|r| format!(" Reason: {}", r))
+                    .unwrap_or_default()
+            ),
+        ),
+    };
+
+    let notification = QueuedNotification::for_contact(contact_id, notification_type, subject, message)
+        .with_entity("appointment", appointment.id)
+        .with_variables(serde_json::json!({
+            "title": appointment.title,
+            "technician_name": appointment.technician_name,
+            "location_name": appointment.location_name,
+            "ticket_number": appointment.ticket_number,
+            "starts": starts,
+            "ends": ends,
+            "timezone": timezone,
+            "start_time": appointment.start_time,
+            "end_time": appointment.end_time,
+            "cancellation_reason": appointment.cancellation_reason,
+        }));
+    if let Err(e) = enqueue_notification(&state.db_pool, notification).await {
+        tracing::warn!("Failed to queue {} for appointment {}: {}", notification_type, appointment.id, e);
+        return Ok(());
+    }
+
+    if !matches!(notice, ClientNotice::Cancelled) {
+        sqlx::query("UPDATE appointments SET confirmation_sent_at = NOW() WHERE id = $1")
+            .bind(appointment.id)
+            .execute(&state.db_pool)
+            .await
+            .map_err(internal("marking confirmation sent"))?;
+    }
+    Ok(())
+}
+
+/// Lets the technician know, unless they booked the visit themselves.
+async fn notify_technician(state: &AppState, appointment: &Appointment, actor_id: Uuid) {
+    if appointment.technician_id == actor_id {
+        return;
+    }
+    let (starts, ends, timezone) = match local_times(state, appointment.id, false).await {
+        Ok(times) => times,
+        Err(e) => {
+            tracing::warn!("Failed to format appointment {} for its technician: {}", appointment.id, e);
+            return;
+        }
+    };
+    let notification = QueuedNotification::for_user(
+        appointment.technician_id,
+        "appointment_assigned",
+        format!("Appointment: {} - {}", appointment.client_name, appointment.title),
+        format!(
+            "You are booked for \"{}\" with {}{} on {} to {} ({}).",
+            appointment.title,
+            appointment.client_name,
+            appointment
+                .location_name
+                .as_ref()
+                .map(|name| format!(" at {}", name))
+                .unwrap_or_default(),
+            starts,
+            ends,
+            timezone
+        ),
+    )
+    .with_entity("appointment", appointment.id)
+    .with_variables(serde_json::json!({
+        "title": appointment.title,
+        "client_name": appointment.client_name,
+        "location_name": appointment.location_name,
+        "starts": starts,
+        "ends": ends,
+        "timezone": timezone,
+        "start_time": appointment.start_time,
+        "end_time": appointment.end_time,
+    }));
+    if let Err(e) = enqueue_notification(&state.db_pool, notification).await {
+        tracing::warn!("Failed to queue assignment notice for appointment {}: {}", appointment.id, e);
+    }
+}
+
+/// The visit's start and end as local wall-clock text, with the zone used.
+/// Clients see the site's timezone when there is one; technicians always see
+/// their own. Names Postgres doesn't know are skipped rather than failing.
+async fn local_times(state: &AppState, appointment_id: Uuid, site_time: bool) -> Result<(String, String, String), sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT to_char(a.start_time AT TIME ZONE z.tz, 'FMDay FMDD FMMonth YYYY, HH24:MI'),
+               to_char(a.end_time AT TIME ZONE z.tz, 'HH24:MI'),
+               z.tz
+        FROM appointments a
+        JOIN users u ON u.id = a.technician_id
+        LEFT JOIN locations l ON l.id = a.location_id AND $2
+        CROSS JOIN LATERAL (
+            SELECT COALESCE(
+                (SELECT name FROM pg_timezone_names WHERE name = l.timezone),
+                (SELECT name FROM pg_timezone_names WHERE name = u.timezone),
+                'UTC') as tz
+        ) z
+        WHERE a.id = $1
+        "#,
+    )
+    .bind(appointment_id)
+    .bind(site_time)
+    .fetch_one(&state.db_pool)
+    .await
+}
+
+async fn load_appointment(state: &AppState, id: Uuid) -> Result<Appointment, StatusCode> {
+    dispatch::load_appointment(&state.db_pool, id)
+        .await
+        .map_err(internal("fetching appointment"))?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn ensure_technician(state: &AppState, user_id: Uuid) -> Result<(), StatusCode> {
+    let active: Option<bool> = sqlx::query_scalar("SELECT COALESCE(is_active, true) FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&state.db_pool)
+        .await
+        .map_err(internal("fetching technician"))?;
+    match active {
+        Some(true) => Ok(()),
+        Some(false) => Err(StatusCode::BAD_REQUEST),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+async fn ensure_client_location(state: &AppState, client_id: Uuid, location_id: Uuid) -> Result<(), StatusCode> {
+    let found: Option<Uuid> = sqlx::query_scalar("SELECT id FROM locations WHERE id = $1 AND client_id = $2")
+        .bind(location_id)
+        .bind(client_id)
+        .fetch_optional(&state.db_pool)
+        .await
+        .map_err(internal("fetching location"))?;
+    found.map(|_| ()).ok_or(StatusCode::BAD_REQUEST)
+}
+
+async fn primary_contact(state: &AppState, client_id: Uuid) -> Result<Option<Uuid>, StatusCode> {
+    sqlx::query_scalar(
+        "SELECT id FROM contacts WHERE client_id = $1 AND archived_at IS NULL AND email IS NOT NULL
+         ORDER BY is_primary DESC NULLS LAST, created_at LIMIT 1",
+    )
+    .bind(client_id)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(internal("looking up client contact"))
+}
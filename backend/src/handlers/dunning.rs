@@ -0,0 +1,632 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+    routing::{get, post, put},
+    Router,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::handlers::internal;
+use crate::services::dunning::{
+    self, DunningConfig, DunningEvent, DunningRunSummary, DunningSequence, DunningService, DunningStage,
+    LateFee, LateFeeRule, PaymentPromise, EVENT_COLUMNS, LATE_FEE_RULE_COLUMNS, PROMISE_COLUMNS, PROMISE_STATUSES,
+    SEQUENCE_COLUMNS,
+};
+use crate::AppState;
+
+const NOTIFICATION_PRIORITIES: &[&str] = &["low", "normal", "high", "urgent"];
+
+pub fn dunning_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/sequences", get(list_sequences).post(create_sequence))
+        .route("/sequences/:id", get(get_sequence).put(update_sequence).delete(delete_sequence))
+        .route("/late-fee-rules", get(list_late_fee_rules).post(create_late_fee_rule))
+        .route("/late-fee-rules/:id", put(update_late_fee_rule))
+        .route("/clients/:id/sequence", put(assign_client_sequence))
+        .route("/contracts/:id/sequence", put(assign_contract_sequence))
+        .route("/credit-holds", get(list_credit_holds))
+        .route("/clients/:id/credit-hold", post(place_credit_hold).delete(release_credit_hold))
+        .route("/invoices/:id/events", get(list_invoice_events))
+        .route("/promises", get(list_promises).post(create_promise))
+        .route("/promises/:id/cancel", post(cancel_promise))
+        .route("/run", post(run_dunning))
+}
+
+#[derive(Debug, Serialize)]
+pub struct SequenceWithStages {
+    #[serde(flatten)]
+    pub sequence: DunningSequence,
+    pub stages: Vec<DunningStage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StageInput {
+    pub stage_number: i32,
+    pub days_overdue: i32,
+    pub subject_template: String,
+    pub body_template: String,
+    pub priority: Option<String>,
+    pub late_fee_rule_id: Option<Uuid>,
+    pub place_credit_hold: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SequenceInput {
+    pub name: String,
+    pub description: Option<String>,
+    pub is_default: Option<bool>,
+    pub is_active: Option<bool>,
+    pub stages: Vec<StageInput>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LateFeeRuleInput {
+    pub name: String,
+    pub description: Option<String>,
+    pub fee: LateFee,
+    pub apply_to_all_clients: Option<bool>,
+    pub client_ids: Option<Vec<Uuid>>,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SequenceAssignment {
+    pub sequence_id: Option<Uuid>, // None falls back to the client's or the default sequence
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreditHoldRequest {
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct CreditHold {
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub credit_hold_reason: Option<String>,
+    pub credit_hold_source: Option<String>,
+    pub credit_hold_at: Option<DateTime<Utc>>,
+    pub overdue_balance: Decimal,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PromiseQuery {
+    pub client_id: Option<Uuid>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePromise {
+    pub client_id: Uuid,
+    pub invoice_id: Option<Uuid>, // leave out to pause dunning for the whole account
+    pub promised_amount: Decimal,
+    pub promised_date: NaiveDate,
+    pub notes: Option<String>,
+}
+
+async fn list_sequences(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+) -> Result<Json<Vec<DunningSequence>>, StatusCode> {
+    let sequences = sqlx::query_as::<_, DunningSequence>(&format!(
+        "SELECT {} FROM dunning_sequences ORDER BY is_default DESC, name",
+        SEQUENCE_COLUMNS
+    ))
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching dunning sequences"))?;
+
+    Ok(Json(sequences))
+}
+
+async fn get_sequence(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SequenceWithStages>, StatusCode> {
+    Ok(Json(load_sequence(&state, id).await?))
+}
+
+async fn create_sequence(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<SequenceInput>,
+) -> Result<(StatusCode, Json<SequenceWithStages>), StatusCode> {
+    validate_sequence(&state, &payload).await?;
+
+    let mut tx = state.db_pool.begin().await.map_err(internal("starting transaction"))?;
+    if payload.is_default.unwrap_or(false) {
+        clear_default(&mut tx).await?;
+    }
+
+    let id: Uuid = sqlx::query_scalar(
+        "INSERT INTO dunning_sequences (name, description, is_default, is_active, created_by)
+         VALUES ($1, $2, $3, $4, $5) RETURNING id",
+    )
+    .bind(payload.name.trim())
+    .bind(&payload.description)
+    .bind(payload.is_default.unwrap_or(false))
+    .bind(payload.is_active.unwrap_or(true))
+    .bind(auth.0.id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal("creating dunning sequence"))?;
+
+    insert_stages(&mut tx, id, &payload.stages).await?;
+    tx.commit().await.map_err(internal("committing dunning sequence"))?;
+
+    Ok((StatusCode::CREATED, Json(load_sequence(&state, id).await?)))
+}
+
+/// Replaces a sequence and its stages. Invoices keep the stage numbers they
+/// have reached, so renumbering stages of a sequence in use is best avoided.
+async fn update_sequence(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<SequenceInput>,
+) -> Result<Json<SequenceWithStages>, StatusCode> {
+    validate_sequence(&state, &payload).await?;
+
+    let mut tx = state.db_pool.begin().await.map_err(internal("starting transaction"))?;
+    if payload.is_default.unwrap_or(false) {
+        clear_default(&mut tx).await?;
+    }
+
+    let result = sqlx::query(
+        "UPDATE dunning_sequences SET name = $2, description = $3, is_default = $4, is_active = $5, updated_at = NOW()
+         WHERE id = $1",
+    )
+    .bind(id)
+    .bind(payload.name.trim())
+    .bind(&payload.description)
+    .bind(payload.is_default.unwrap_or(false))
+    .bind(payload.is_active.unwrap_or(true))
+    .execute(&mut *tx)
+    .await
+    .map_err(internal("updating dunning sequence"))?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    sqlx::query("DELETE FROM dunning_stages WHERE sequence_id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal("replacing dunning stages"))?;
+    insert_stages(&mut tx, id, &payload.stages).await?;
+    tx.commit().await.map_err(internal("committing dunning sequence"))?;
+
+    Ok(Json(load_sequence(&state, id).await?))
+}
+
+/// Sequences invoices have already been through can only be deactivated.
+async fn delete_sequence(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query("DELETE FROM dunning_sequences WHERE id = $1")
+        .bind(id)
+        .execute(&state.db_pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db) if db.is_foreign_key_violation() => StatusCode::CONFLICT,
+            e => internal("deleting dunning sequence")(e),
+        })?;
+
+    if result.rows_affected() == 0 {
+        Err(StatusCode::NOT_FOUND)
+    } else {
+        Ok(StatusCode::NO_CONTENT)
+    }
+}
+
+async fn list_late_fee_rules(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+) -> Result<Json<Vec<LateFeeRule>>, StatusCode> {
+    let rules = sqlx::query_as::<_, LateFeeRule>(&format!(
+        "SELECT {} FROM billing_automation_rules WHERE action_type = 'apply_late_fee' ORDER BY name",
+        LATE_FEE_RULE_COLUMNS
+    ))
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching late fee rules"))?;
+
+    Ok(Json(rules))
+}
+
+async fn create_late_fee_rule(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<LateFeeRuleInput>,
+) -> Result<(StatusCode, Json<LateFeeRule>), StatusCode> {
+    if payload.name.trim().is_empty() || !payload.fee.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let rule = sqlx::query_as::<_, LateFeeRule>(&format!(
+        "INSERT INTO billing_automation_rules (name, description, rule_type, trigger_conditions, action_type,
+                                               action_parameters, apply_to_all_clients, client_ids, is_active, created_by)
+         VALUES ($1, $2, 'time_based', '{{\"event\": \"dunning_stage\"}}', 'apply_late_fee', $3, $4, $5, $6, $7)
+         RETURNING {}",
+        LATE_FEE_RULE_COLUMNS
+    ))
+    .bind(payload.name.trim())
+    .bind(&payload.description)
+    .bind(serde_json::to_value(&payload.fee).map_err(internal("encoding late fee"))?)
+    .bind(payload.apply_to_all_clients.unwrap_or(true))
+    .bind(payload.client_ids.unwrap_or_default())
+    .bind(payload.is_active.unwrap_or(true))
+    .bind(auth.0.id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(internal("creating late fee rule"))?;
+
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+async fn update_late_fee_rule(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<LateFeeRuleInput>,
+) -> Result<Json<LateFeeRule>, StatusCode> {
+    if payload.name.trim().is_empty() || !payload.fee.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let rule = sqlx::query_as::<_, LateFeeRule>(&format!(
+        "UPDATE billing_automation_rules SET name = $2, description = $3, action_parameters = $4,
+                apply_to_all_clients = $5, client_ids = $6, is_active = $7, updated_at = NOW()
+         WHERE id = $1 AND action_type = 'apply_late_fee'
+         RETURNING {}",
+        LATE_FEE_RULE_COLUMNS
+    ))
+    .bind(id)
+    .bind(payload.name.trim())
+    .bind(&payload.description)
+    .bind(serde_json::to_value(&payload.fee).map_err(internal("encoding late fee"))?)
+    .bind(payload.apply_to_all_clients.unwrap_or(true))
+    .bind(payload.client_ids.unwrap_or_default())
+    .bind(payload.is_active.unwrap_or(true))
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(internal("updating late fee rule"))?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(rule))
+}
+
+async fn assign_client_sequence(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(client_id): Path<Uuid>,
+    Json(payload): Json<SequenceAssignment>,
+) -> Result<StatusCode, StatusCode> {
+    assign_sequence(&state, "clients", client_id, payload.sequence_id).await
+}
+
+async fn assign_contract_sequence(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(contract_id): Path<Uuid>,
+    Json(payload): Json<SequenceAssignment>,
+) -> Result<StatusCode, StatusCode> {
+    assign_sequence(&state, "contracts", contract_id, payload.sequence_id).await
+}
+
+async fn list_credit_holds(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+) -> Result<Json<Vec<CreditHold>>, StatusCode> {
+    let holds = sqlx::query_as::<_, CreditHold>(
+        "SELECT c.id as client_id, c.name as client_name, c.credit_hold_reason, c.credit_hold_source, c.credit_hold_at,
+                COALESCE((SELECT SUM(i.balance) FROM invoices i
+                          WHERE i.client_id = c.id AND i.due_date < CURRENT_DATE
+                            AND i.status IN ('sent', 'partial', 'overdue')), 0) as overdue_balance
+         FROM clients c
+         WHERE c.credit_hold
+         ORDER BY c.credit_hold_at",
+    )
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching credit holds"))?;
+
+    Ok(Json(holds))
+}
+
+async fn place_credit_hold(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(client_id): Path<Uuid>,
+    Json(payload): Json<CreditHoldRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let reason = payload.reason.trim();
+    if reason.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let placed = dunning::place_credit_hold(&state.db_pool, client_id, reason, "manual")
+        .await
+        .map_err(internal("placing credit hold"))?;
+    if !placed {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn release_credit_hold(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(client_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let released = dunning::release_credit_hold(&state.db_pool, client_id)
+        .await
+        .map_err(internal("releasing credit hold"))?;
+    if !released {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_invoice_events(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(invoice_id): Path<Uuid>,
+) -> Result<Json<Vec<DunningEvent>>, StatusCode> {
+    let events = sqlx::query_as::<_, DunningEvent>(&format!(
+        "SELECT {} FROM dunning_events e JOIN invoices i ON i.id = e.invoice_id
+         WHERE e.invoice_id = $1
+         ORDER BY e.created_at",
+        EVENT_COLUMNS
+    ))
+    .bind(invoice_id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching dunning events"))?;
+
+    Ok(Json(events))
+}
+
+async fn list_promises(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<PromiseQuery>,
+) -> Result<Json<Vec<PaymentPromise>>, StatusCode> {
+    if params.status.as_deref().is_some_and(|s| !PROMISE_STATUSES.contains(&s)) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let promises = sqlx::query_as::<_, PaymentPromise>(&format!(
+        "SELECT {} FROM payment_promises p
+         JOIN clients c ON c.id = p.client_id
+         LEFT JOIN invoices i ON i.id = p.invoice_id
+         WHERE ($1::UUID IS NULL OR p.client_id = $1) AND ($2::TEXT IS NULL OR p.status = $2)
+         ORDER BY p.promised_date DESC
+         LIMIT 500",
+        PROMISE_COLUMNS
+    ))
+    .bind(params.client_id)
+    .bind(params.status)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching payment promises"))?;
+
+    Ok(Json(promises))
+}
+
+/// Logs a promise to pay, which pauses dunning until the promised date.
+async fn create_promise(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<CreatePromise>,
+) -> Result<(StatusCode, Json<PaymentPromise>), StatusCode> {
+    if payload.promised_amount <= Decimal::ZERO || payload.promised_date < Utc::now().date_naive() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if let Some(invoice_id) = payload.invoice_id {
+        let invoice_client: Uuid = sqlx::query_scalar("SELECT client_id FROM invoices WHERE id = $1")
+            .bind(invoice_id)
+            .fetch_optional(&state.db_pool)
+            .await
+            .map_err(internal("checking invoice"))?
+            .ok_or(StatusCode::NOT_FOUND)?;
+        if invoice_client != payload.client_id {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    let id: Uuid = sqlx::query_scalar(
+        "INSERT INTO payment_promises (client_id, invoice_id, promised_amount, promised_date, notes, created_by)
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+    )
+    .bind(payload.client_id)
+    .bind(payload.invoice_id)
+    .bind(payload.promised_amount)
+    .bind(payload.promised_date)
+    .bind(&payload.notes)
+    .bind(auth.0.id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_foreign_key_violation() => StatusCode::NOT_FOUND,
+        e => internal("creating payment promise")(e),
+    })?;
+
+    Ok((StatusCode::CREATED, Json(load_promise(&state, id).await?)))
+}
+
+async fn cancel_promise(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PaymentPromise>, StatusCode> {
+    let result = sqlx::query(
+        "UPDATE payment_promises SET status = 'cancelled', resolved_at = NOW() WHERE id = $1 AND status = 'open'",
+    )
+    .bind(id)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("cancelling payment promise"))?;
+
+    let promise = load_promise(&state, id).await?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    Ok(Json(promise))
+}
+
+/// Runs the dunning worker now instead of waiting for its next pass.
+async fn run_dunning(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+) -> Result<Json<DunningRunSummary>, StatusCode> {
+    let service = DunningService::new(DunningConfig::default(), state.db_pool.clone());
+    let summary = service
+        .run(Utc::now().date_naive())
+        .await
+        .map_err(internal("running dunning"))?;
+
+    Ok(Json(summary))
+}
+
+async fn load_sequence(state: &AppState, id: Uuid) -> Result<SequenceWithStages, StatusCode> {
+    let sequence = sqlx::query_as::<_, DunningSequence>(&format!(
+        "SELECT {} FROM dunning_sequences WHERE id = $1",
+        SEQUENCE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(internal("fetching dunning sequence"))?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let stages = dunning::load_stages(&state.db_pool, id)
+        .await
+        .map_err(internal("fetching dunning stages"))?;
+
+    Ok(SequenceWithStages { sequence, stages })
+}
+
+async fn load_promise(state: &AppState, id: Uuid) -> Result<PaymentPromise, StatusCode> {
+    sqlx::query_as::<_, PaymentPromise>(&format!(
+        "SELECT {} FROM payment_promises p
+         JOIN clients c ON c.id = p.client_id
+         LEFT JOIN invoices i ON i.id = p.invoice_id
+         WHERE p.id = $1",
+        PROMISE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(internal("fetching payment promise"))?
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn validate_sequence(state: &AppState, payload: &SequenceInput) -> Result<(), StatusCode> {
+    let numbering: Vec<(i32, i32)> = payload.stages.iter().map(|s| (s.stage_number, s.days_overdue)).collect();
+    let stages_valid = payload.stages.iter().all(|s| {
+        !s.subject_template.trim().is_empty()
+            && !s.body_template.trim().is_empty()
+            && s.priority.as_deref().is_none_or(|p| NOTIFICATION_PRIORITIES.contains(&p))
+    });
+    if payload.name.trim().is_empty() || !stages_valid || !dunning::validate_stages(&numbering) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let rule_ids: Vec<Uuid> = payload.stages.iter().filter_map(|s| s.late_fee_rule_id).collect();
+    if !rule_ids.is_empty() {
+        let found: i64 = sqlx::query_scalar(
+            "SELECT COUNT(DISTINCT id) FROM billing_automation_rules WHERE id = ANY($1) AND action_type = 'apply_late_fee'",
+        )
+        .bind(&rule_ids)
+        .fetch_one(&state.db_pool)
+        .await
+        .map_err(internal("checking late fee rules"))?;
+
+        let mut unique = rule_ids.clone();
+        unique.sort();
+        unique.dedup();
+        if found != unique.len() as i64 {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    Ok(())
+}
+
+async fn clear_default(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<(), StatusCode> {
+    sqlx::query("UPDATE dunning_sequences SET is_default = false, updated_at = NOW() WHERE is_default")
+        .execute(&mut **tx)
+        .await
+        .map_err(internal("clearing default dunning sequence"))?;
+
+    Ok(())
+}
+
+async fn insert_stages(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    sequence_id: Uuid,
+    stages: &[StageInput],
+) -> Result<(), StatusCode> {
+    for stage in stages {
+        sqlx::query(
+            r#"
+            INSERT INTO dunning_stages (sequence_id, stage_number, days_overdue, subject_template, body_template,
+                                        priority, late_fee_rule_id, place_credit_hold)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+        )
+        .bind(sequence_id)
+        .bind(stage.stage_number)
+        .bind(stage.days_overdue)
+        .bind(stage.subject_template.trim())
+        .bind(stage.body_template.trim())
+        .bind(stage.priority.as_deref().unwrap_or("normal"))
+        .bind(stage.late_fee_rule_id)
+        .bind(stage.place_credit_hold.unwrap_or(false))
+        .execute(&mut **tx)
+        .await
+        .map_err(internal("creating dunning stage"))?;
+    }
+
+    Ok(())
+}
+
+async fn assign_sequence(
+    state: &AppState,
+    table: &'static str,
+    id: Uuid,
+    sequence_id: Option<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query(&format!(
+        "UPDATE {} SET dunning_sequence_id = $2, updated_at = NOW() WHERE id = $1",
+        table
+    ))
+    .bind(id)
+    .bind(sequence_id)
+    .execute(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_foreign_key_violation() => StatusCode::BAD_REQUEST,
+        e => internal("assigning dunning sequence")(e),
+    })?;
+
+    if result.rows_affected() == 0 {
+        Err(StatusCode::NOT_FOUND)
+    } else {
+        Ok(StatusCode::NO_CONTENT)
+    }
+}
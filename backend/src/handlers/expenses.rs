@@ -0,0 +1,885 @@
+use axum::{
+    extract::{Multipart, Path, Query, State},
+    http::StatusCode,
+    response::Json,
+    routing::{get, post, put},
+    Router,
+};
+use chrono::{NaiveDate, Utc};
+use ghosthub_shared::{Expense, ExpenseCategory};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::files::{store_file, FileLinks};
+use crate::services::credit_notes;
+use crate::services::currency;
+use crate::services::expenses::{self, CATEGORY_COLUMNS, EXPENSE_COLUMNS};
+use crate::services::recurring_billing::insert_invoice;
+use crate::AppState;
+
+pub fn expense_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_expenses).post(create_expense))
+        .route("/unbilled", get(list_unbilled))
+        .route("/bill", post(bill_expenses))
+        .route("/categories", get(list_categories).post(create_category))
+        .route("/categories/:id", put(update_category))
+        .route("/:id", get(get_expense).put(update_expense).delete(delete_expense))
+        .route("/:id/receipt", post(upload_receipt))
+        .route("/:id/approve", post(approve_expense))
+        .route("/:id/reject", post(reject_expense))
+        .route("/:id/reimburse", post(reimburse_expense))
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ExpenseWithDetails {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub expense: Expense,
+    pub client_name: Option<String>,
+    pub project_name: Option<String>,
+    pub ticket_number: Option<i32>,
+    pub created_by_name: Option<String>,
+    pub invoice_number: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExpenseQuery {
+    pub client_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub ticket_id: Option<Uuid>,
+    pub category_id: Option<Uuid>,
+    pub status: Option<String>,
+    pub billable: Option<bool>,
+    pub unbilled: Option<bool>,
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExpenseCreate {
+    pub client_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub ticket_id: Option<Uuid>,
+    pub category_id: Uuid,
+    pub vendor: Option<String>,
+    pub vendor_id: Option<Uuid>,
+    pub description: String,
+    pub amount: Decimal,
+    pub currency: Option<String>,     // what it was paid in; defaults to the base currency
+    pub tax_amount: Option<Decimal>,
+    pub expense_date: Option<NaiveDate>,
+    pub is_billable: Option<bool>,    // defaults from the category
+    pub is_reimbursable: Option<bool>,
+    pub markup_percent: Option<Decimal>, // defaults from the category
+    pub payment_method: Option<String>,
+    pub receipt_file_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExpenseUpdate {
+    pub client_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub ticket_id: Option<Uuid>,
+    pub category_id: Option<Uuid>,
+    pub vendor: Option<String>,
+    pub vendor_id: Option<Uuid>,
+    pub description: Option<String>,
+    pub amount: Option<Decimal>,
+    pub currency: Option<String>,
+    pub tax_amount: Option<Decimal>,
+    pub expense_date: Option<NaiveDate>,
+    pub is_billable: Option<bool>,
+    pub is_reimbursable: Option<bool>,
+    pub markup_percent: Option<Decimal>,
+    pub payment_method: Option<String>,
+    pub receipt_file_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExpenseDecision {
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryCreate {
+    pub name: String,
+    pub description: Option<String>,
+    pub tax_deductible: Option<bool>,
+    pub markup_percent: Option<Decimal>,
+    pub billable_by_default: Option<bool>,
+    pub approval_threshold: Option<Decimal>,
+    pub tax_category: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryUpdate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub tax_deductible: Option<bool>,
+    pub markup_percent: Option<Decimal>,
+    pub billable_by_default: Option<bool>,
+    pub approval_threshold: Option<Decimal>,
+    pub clear_approval_threshold: Option<bool>,
+    pub tax_category: Option<String>,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryQuery {
+    pub include_inactive: Option<bool>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct UnbilledSummary {
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub currency: String, // one row per currency the client's expenses were paid in
+    pub expense_count: i64,
+    pub awaiting_approval: i64,
+    pub cost: Decimal,
+    pub billable_amount: Decimal,
+    pub oldest_expense_date: NaiveDate,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BillExpenses {
+    pub client_id: Uuid,
+    pub through_date: Option<NaiveDate>,
+    pub send_invoice: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExpenseBillingResult {
+    pub invoice_id: Uuid,
+    pub expense_count: usize,
+    pub total: Decimal,
+}
+
+const EXPENSE_FROM: &str = "FROM expenses e
+    LEFT JOIN clients c ON c.id = e.client_id
+    LEFT JOIN projects p ON p.id = e.project_id
+    LEFT JOIN tickets t ON t.id = e.ticket_id
+    LEFT JOIN users u ON u.id = e.created_by
+    LEFT JOIN invoices i ON i.id = e.invoice_id";
+
+fn detail_columns() -> String {
+    format!(
+        "{}, c.name as client_name, p.name as project_name, t.number as ticket_number,
+         CONCAT(u.first_name, ' ', u.last_name) as created_by_name, i.number as invoice_number",
+        EXPENSE_COLUMNS
+    )
+}
+
+async fn list_expenses(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<ExpenseQuery>,
+) -> Result<Json<Vec<ExpenseWithDetails>>, StatusCode> {
+    let limit = params.limit.unwrap_or(100).min(500);
+    let offset = params.offset.unwrap_or(0);
+
+    let expenses = sqlx::query_as::<_, ExpenseWithDetails>(&format!(
+        "SELECT {} {}
+         WHERE ($1::UUID IS NULL OR e.client_id = $1)
+           AND ($2::UUID IS NULL OR e.project_id = $2)
+           AND ($3::UUID IS NULL OR e.ticket_id = $3)
+           AND ($4::UUID IS NULL OR e.category_id = $4)
+           AND ($5::TEXT IS NULL OR e.status = $5)
+           AND ($6::BOOLEAN IS NULL OR e.is_billable = $6)
+           AND ($7::BOOLEAN IS NOT TRUE OR (e.is_billable = true AND e.invoice_id IS NULL))
+           AND ($8::DATE IS NULL OR e.expense_date >= $8)
+           AND ($9::DATE IS NULL OR e.expense_date <= $9)
+         ORDER BY e.expense_date DESC, e.created_at DESC
+         LIMIT $10 OFFSET $11",
+        detail_columns(),
+        EXPENSE_FROM
+    ))
+    .bind(params.client_id)
+    .bind(params.project_id)
+    .bind(params.ticket_id)
+    .bind(params.category_id)
+    .bind(params.status)
+    .bind(params.billable)
+    .bind(params.unbilled)
+    .bind(params.start_date)
+    .bind(params.end_date)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching expenses: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(expenses))
+}
+
+async fn get_expense(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ExpenseWithDetails>, StatusCode> {
+    Ok(Json(load_expense(&state, id).await?))
+}
+
+async fn create_expense(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<ExpenseCreate>,
+) -> Result<(StatusCode, Json<ExpenseWithDetails>), StatusCode> {
+    let draft = ExpenseDraft {
+        client_id: payload.client_id,
+        project_id: payload.project_id,
+        ticket_id: payload.ticket_id,
+        category_id: payload.category_id,
+        vendor: payload.vendor,
+        vendor_id: payload.vendor_id,
+        description: payload.description,
+        amount: payload.amount,
+        currency: payload.currency,
+        tax_amount: payload.tax_amount.unwrap_or_default(),
+        expense_date: payload.expense_date.unwrap_or_else(|| Utc::now().date_naive()),
+        is_billable: payload.is_billable,
+        is_reimbursable: payload.is_reimbursable.unwrap_or(false),
+        markup_percent: payload.markup_percent,
+        payment_method: payload.payment_method,
+        receipt_file_id: payload.receipt_file_id,
+    };
+    let resolved = resolve_draft(&state, draft).await?;
+
+    let id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO expenses (client_id, project_id, ticket_id, category, category_id, vendor, vendor_id,
+                              description, amount, tax_amount, expense_date, is_billable, is_reimbursable,
+                              markup_percent, payment_method, receipt_file_id, requires_approval, status,
+                              approved_at, created_by, currency)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18,
+                CASE WHEN $18 = 'approved' THEN NOW() END, $19, $20)
+        RETURNING id
+        "#,
+    )
+    .bind(resolved.client_id)
+    .bind(resolved.draft.project_id)
+    .bind(resolved.draft.ticket_id)
+    .bind(&resolved.category.name)
+    .bind(resolved.category.id)
+    .bind(&resolved.vendor)
+    .bind(resolved.draft.vendor_id)
+    .bind(resolved.draft.description.trim())
+    .bind(resolved.draft.amount)
+    .bind(resolved.draft.tax_amount)
+    .bind(resolved.draft.expense_date)
+    .bind(resolved.is_billable)
+    .bind(resolved.draft.is_reimbursable)
+    .bind(resolved.markup_percent)
+    .bind(&resolved.draft.payment_method)
+    .bind(resolved.draft.receipt_file_id)
+    .bind(resolved.requires_approval)
+    .bind(resolved.status())
+    .bind(auth.0.id)
+    .bind(&resolved.currency)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error creating expense: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::CREATED, Json(load_expense(&state, id).await?)))
+}
+
+/// Edits a pending or rejected expense. The edited expense goes back through
+/// approval; invoiced expenses can't be changed.
+async fn update_expense(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<ExpenseUpdate>,
+) -> Result<Json<ExpenseWithDetails>, StatusCode> {
+    let existing = load_expense(&state, id).await?.expense;
+    if existing.invoice_id.is_some() || !matches!(existing.status.as_str(), "pending" | "rejected") {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let draft = ExpenseDraft {
+        client_id: payload.client_id.or(existing.client_id),
+        project_id: payload.project_id.or(existing.project_id),
+        ticket_id: payload.ticket_id.or(existing.ticket_id),
+        category_id: payload.category_id.or(existing.category_id).ok_or(StatusCode::BAD_REQUEST)?,
+        vendor: payload.vendor.or(Some(existing.vendor)),
+        vendor_id: payload.vendor_id.or(existing.vendor_id),
+        description: payload.description.unwrap_or(existing.description),
+        amount: payload.amount.unwrap_or(existing.amount),
+        currency: Some(payload.currency.unwrap_or(existing.currency)),
+        tax_amount: payload.tax_amount.unwrap_or(existing.tax_amount),
+        expense_date: payload.expense_date.unwrap_or(existing.expense_date),
+        is_billable: Some(payload.is_billable.unwrap_or(existing.is_billable)),
+        is_reimbursable: payload.is_reimbursable.unwrap_or(existing.is_reimbursable),
+        markup_percent: Some(payload.markup_percent.unwrap_or(existing.markup_percent)),
+        payment_method: payload.payment_method.or(existing.payment_method),
+        receipt_file_id: payload.receipt_file_id.or(existing.receipt_file_id),
+    };
+    let resolved = resolve_draft(&state, draft).await?;
+
+    sqlx::query(
+        r#"
+        UPDATE expenses SET
+            client_id = $2, project_id = $3, ticket_id = $4, category = $5, category_id = $6,
+            vendor = $7, vendor_id = $8, description = $9, amount = $10, tax_amount = $11,
+            expense_date = $12, is_billable = $13, is_reimbursable = $14, markup_percent = $15,
+            payment_method = $16, receipt_file_id = $17, requires_approval = $18, status = $19,
+            approved_by = NULL, approved_at = CASE WHEN $19 = 'approved' THEN NOW() END,
+            rejected_by = NULL, rejected_at = NULL, currency = $20, updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(resolved.client_id)
+    .bind(resolved.draft.project_id)
+    .bind(resolved.draft.ticket_id)
+    .bind(&resolved.category.name)
+    .bind(resolved.category.id)
+    .bind(&resolved.vendor)
+    .bind(resolved.draft.vendor_id)
+    .bind(resolved.draft.description.trim())
+    .bind(resolved.draft.amount)
+    .bind(resolved.draft.tax_amount)
+    .bind(resolved.draft.expense_date)
+    .bind(resolved.is_billable)
+    .bind(resolved.draft.is_reimbursable)
+    .bind(resolved.markup_percent)
+    .bind(&resolved.draft.payment_method)
+    .bind(resolved.draft.receipt_file_id)
+    .bind(resolved.requires_approval)
+    .bind(resolved.status())
+    .bind(&resolved.currency)
+    .execute(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error updating expense: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(load_expense(&state, id).await?))
+}
+
+async fn delete_expense(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query("DELETE FROM expenses WHERE id = $1 AND invoice_id IS NULL AND status <> 'reimbursed'")
+        .bind(id)
+        .execute(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error deleting expense: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if result.rows_affected() == 0 {
+        load_expense(&state, id).await?;
+        return Err(StatusCode::CONFLICT);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Stores a receipt through the file store, linked to the expense's client,
+/// project and ticket, and attaches it to the expense.
+async fn upload_receipt(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    mut multipart: Multipart,
+) -> Result<Json<ExpenseWithDetails>, StatusCode> {
+    let expense = load_expense(&state, id).await?.expense;
+
+    let mut upload = None;
+    while let Some(field) = multipart.next_field().await.map_err(|_| StatusCode::BAD_REQUEST)? {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let filename = field.file_name().unwrap_or("receipt").to_string();
+        let mime_type = field.content_type().unwrap_or("application/octet-stream").to_string();
+        let data = field.bytes().await.map_err(|_| StatusCode::BAD_REQUEST)?;
+        upload = Some((filename, mime_type, data));
+    }
+    let (filename, mime_type, data) = upload.ok_or(StatusCode::BAD_REQUEST)?;
+    if data.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let links = FileLinks {
+        client_id: expense.client_id,
+        ticket_id: expense.ticket_id,
+        project_id: expense.project_id,
+        ..FileLinks::default()
+    };
+    let (file_id, _) = store_file(&state.db_pool, auth.0.id, &links, &filename, &mime_type, &data).await?;
+
+    sqlx::query("UPDATE expenses SET receipt_file_id = $2, updated_at = NOW() WHERE id = $1")
+        .bind(id)
+        .bind(file_id)
+        .execute(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error attaching expense receipt: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(load_expense(&state, id).await?))
+}
+
+async fn approve_expense(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<ExpenseDecision>,
+) -> Result<Json<ExpenseWithDetails>, StatusCode> {
+    transition(
+        &state,
+        id,
+        "UPDATE expenses SET status = 'approved', approved_by = $2, approved_at = NOW(), approval_notes = $3,
+         updated_at = NOW()
+         WHERE id = $1 AND status = 'pending'",
+        auth.0.id,
+        payload.notes,
+    )
+    .await
+}
+
+async fn reject_expense(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<ExpenseDecision>,
+) -> Result<Json<ExpenseWithDetails>, StatusCode> {
+    transition(
+        &state,
+        id,
+        "UPDATE expenses SET status = 'rejected', rejected_by = $2, rejected_at = NOW(), approval_notes = $3,
+         updated_at = NOW()
+         WHERE id = $1 AND status = 'pending'",
+        auth.0.id,
+        payload.notes,
+    )
+    .await
+}
+
+/// Marks an approved out-of-pocket expense as paid back to the employee.
+async fn reimburse_expense(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<ExpenseDecision>,
+) -> Result<Json<ExpenseWithDetails>, StatusCode> {
+    transition(
+        &state,
+        id,
+        "UPDATE expenses SET status = 'reimbursed', reimbursed_at = NOW(),
+         approval_notes = COALESCE($3, approval_notes), updated_at = NOW()
+         WHERE id = $1 AND status = 'approved' AND is_reimbursable = true AND $2::UUID IS NOT NULL",
+        auth.0.id,
+        payload.notes,
+    )
+    .await
+}
+
+/// Billable expenses that haven't reached an invoice yet, by client.
+async fn list_unbilled(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+) -> Result<Json<Vec<UnbilledSummary>>, StatusCode> {
+    let summaries = sqlx::query_as::<_, UnbilledSummary>(
+        r#"
+        SELECT e.client_id, c.name as client_name, e.currency,
+               COUNT(*) as expense_count,
+               COUNT(*) FILTER (WHERE e.status = 'pending') as awaiting_approval,
+               SUM(e.amount) as cost,
+               SUM(ROUND(e.amount * (1 + COALESCE(e.markup_percent, 0) / 100), 2)) as billable_amount,
+               MIN(e.expense_date) as oldest_expense_date
+        FROM expenses e
+        JOIN clients c ON c.id = e.client_id
+        WHERE e.is_billable = true AND e.invoice_id IS NULL AND e.status IN ('pending', 'approved', 'reimbursed')
+        GROUP BY e.client_id, c.name, e.currency
+        ORDER BY MIN(e.expense_date)
+        "#,
+    )
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching unbilled expenses: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(summaries))
+}
+
+/// Invoices a client's approved billable expenses now, for clients that have
+/// no recurring billing run to pick them up.
+async fn bill_expenses(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<BillExpenses>,
+) -> Result<(StatusCode, Json<ExpenseBillingResult>), StatusCode> {
+    let today = Utc::now().date_naive();
+
+    let mut tx = state.db_pool.begin().await.map_err(|e| {
+        tracing::error!("Error starting transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let lines = expenses::unbilled_lines(&mut tx, payload.client_id, payload.through_date.unwrap_or(today))
+        .await
+        .map_err(|e| {
+            tracing::error!("Error collecting unbilled expenses: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if lines.is_empty() {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let draft_lines: Vec<_> = lines.iter().map(|(line, _)| line.clone()).collect();
+
+    let invoice = insert_invoice(
+        &mut tx,
+        payload.client_id,
+        None,
+        today,
+        30,
+        &draft_lines,
+        payload.send_invoice.unwrap_or(false),
+        "Rebilled expenses",
+    )
+    .await
+    .map_err(|e| {
+        tracing::error!("Error creating expense invoice: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let invoice_id = invoice.id;
+
+    expenses::mark_billed(&mut tx, &lines, invoice_id).await.map_err(|e| {
+        tracing::error!("Error marking expenses billed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    credit_notes::apply_available_credits(&mut tx, invoice_id, Some(auth.0.id))
+        .await
+        .map_err(|e| {
+            tracing::error!("Error applying credits to expense invoice: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Error committing transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ExpenseBillingResult { invoice_id, expense_count: lines.len(), total: invoice.total }),
+    ))
+}
+
+async fn list_categories(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<CategoryQuery>,
+) -> Result<Json<Vec<ExpenseCategory>>, StatusCode> {
+    let categories = sqlx::query_as::<_, ExpenseCategory>(&format!(
+        "SELECT {} FROM expense_categories WHERE ($1 OR is_active = true) ORDER BY name",
+        CATEGORY_COLUMNS
+    ))
+    .bind(params.include_inactive.unwrap_or(false))
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching expense categories: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(categories))
+}
+
+async fn create_category(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Json(payload): Json<CategoryCreate>,
+) -> Result<(StatusCode, Json<ExpenseCategory>), StatusCode> {
+    let markup = payload.markup_percent.unwrap_or_default();
+    if payload.name.trim().is_empty() || markup < Decimal::ZERO || payload.approval_threshold.is_some_and(|t| t < Decimal::ZERO) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let category = sqlx::query_as::<_, ExpenseCategory>(&format!(
+        "INSERT INTO expense_categories (name, description, tax_deductible, markup_percent, billable_by_default,
+                                         approval_threshold, tax_category)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         RETURNING {}",
+        CATEGORY_COLUMNS
+    ))
+    .bind(payload.name.trim())
+    .bind(payload.description)
+    .bind(payload.tax_deductible.unwrap_or(false))
+    .bind(markup)
+    .bind(payload.billable_by_default.unwrap_or(false))
+    .bind(payload.approval_threshold)
+    .bind(payload.tax_category)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error creating expense category: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::CREATED, Json(category)))
+}
+
+/// Rule changes apply to expenses entered afterwards; existing expenses keep their markup.
+async fn update_category(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<CategoryUpdate>,
+) -> Result<Json<ExpenseCategory>, StatusCode> {
+    if payload.markup_percent.is_some_and(|m| m < Decimal::ZERO) || payload.approval_threshold.is_some_and(|t| t < Decimal::ZERO) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let category = sqlx::query_as::<_, ExpenseCategory>(&format!(
+        "UPDATE expense_categories SET
+            name = COALESCE($2, name),
+            description = COALESCE($3, description),
+            tax_deductible = COALESCE($4, tax_deductible),
+            markup_percent = COALESCE($5, markup_percent),
+            billable_by_default = COALESCE($6, billable_by_default),
+            approval_threshold = CASE WHEN $8 THEN NULL ELSE COALESCE($7, approval_threshold) END,
+            is_active = COALESCE($9, is_active),
+            tax_category = COALESCE($10, tax_category)
+         WHERE id = $1
+         RETURNING {}",
+        CATEGORY_COLUMNS
+    ))
+    .bind(id)
+    .bind(payload.name.as_deref().map(str::trim).filter(|n| !n.is_empty()))
+    .bind(payload.description)
+    .bind(payload.tax_deductible)
+    .bind(payload.markup_percent)
+    .bind(payload.billable_by_default)
+    .bind(payload.approval_threshold)
+    .bind(payload.clear_approval_threshold.unwrap_or(false))
+    .bind(payload.is_active)
+    .bind(payload.tax_category)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Error updating expense category: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok(Json(category))
+}
+
+/// Expense fields as entered, before category rules are applied.
+struct ExpenseDraft {
+    client_id: Option<Uuid>,
+    project_id: Option<Uuid>,
+    ticket_id: Option<Uuid>,
+    category_id: Uuid,
+    vendor: Option<String>,
+    vendor_id: Option<Uuid>,
+    description: String,
+    amount: Decimal,
+    currency: Option<String>,
+    tax_amount: Decimal,
+    expense_date: NaiveDate,
+    is_billable: Option<bool>,
+    is_reimbursable: bool,
+    markup_percent: Option<Decimal>,
+    payment_method: Option<String>,
+    receipt_file_id: Option<Uuid>,
+}
+
+struct ResolvedExpense {
+    draft: ExpenseDraft,
+    category: ExpenseCategory,
+    client_id: Option<Uuid>,
+    vendor: String,
+    currency: String,
+    is_billable: bool,
+    markup_percent: Decimal,
+    requires_approval: bool,
+}
+
+impl ResolvedExpense {
+    fn status(&self) -> &'static str {
+        if self.requires_approval { "pending" } else { "approved" }
+    }
+}
+
+/// Validates an expense and fills in the client, vendor name, currency,
+/// billability, markup and approval requirement from its project/ticket and
+/// category.
+async fn resolve_draft(state: &AppState, draft: ExpenseDraft) -> Result<ResolvedExpense, StatusCode> {
+    if draft.amount <= Decimal::ZERO
+        || draft.tax_amount < Decimal::ZERO
+        || draft.description.trim().is_empty()
+        || draft.markup_percent.is_some_and(|m| m < Decimal::ZERO)
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let category = sqlx::query_as::<_, ExpenseCategory>(&format!(
+        "SELECT {} FROM expense_categories WHERE id = $1 AND is_active = true",
+        CATEGORY_COLUMNS
+    ))
+    .bind(draft.category_id)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching expense category: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::BAD_REQUEST)?;
+
+    // The project and ticket must belong to the same client as the expense
+    let (project_client, ticket_client): (Option<Uuid>, Option<Uuid>) = sqlx::query_as(
+        "SELECT (SELECT client_id FROM projects WHERE id = $1), (SELECT client_id FROM tickets WHERE id = $2)",
+    )
+    .bind(draft.project_id)
+    .bind(draft.ticket_id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error resolving expense client: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if (draft.project_id.is_some() && project_client.is_none()) || (draft.ticket_id.is_some() && ticket_client.is_none()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut clients = [draft.client_id, project_client, ticket_client].into_iter().flatten();
+    let client_id = clients.next();
+    if clients.any(|other| Some(other) != client_id) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let vendor = match draft.vendor_id {
+        Some(vendor_id) => sqlx::query_scalar::<_, String>("SELECT name FROM vendors WHERE id = $1")
+            .bind(vendor_id)
+            .fetch_optional(&state.db_pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error fetching vendor: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::BAD_REQUEST)?,
+        None => draft
+            .vendor
+            .as_deref()
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .ok_or(StatusCode::BAD_REQUEST)?
+            .to_string(),
+    };
+
+    let is_billable = draft.is_billable.unwrap_or(category.billable_by_default);
+    if is_billable && client_id.is_none() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // Approval thresholds are in the base currency; without a rate the expense goes to review
+    let mut conn = state.db_pool.acquire().await.map_err(|e| {
+        tracing::error!("Error acquiring connection: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let base_currency = currency::base_currency(&mut conn).await.map_err(|e| {
+        tracing::error!("Error fetching base currency: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let expense_currency = draft.currency.clone().unwrap_or_else(|| base_currency.clone());
+    let known: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM currencies WHERE code = $1 AND is_active = true)")
+        .bind(&expense_currency)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error checking currency: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !known {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let base_amount = currency::rate_on(&mut conn, &expense_currency, &base_currency, draft.expense_date)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching exchange rate: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .map(|(rate, _)| currency::convert(draft.amount, rate));
+
+    Ok(ResolvedExpense {
+        client_id,
+        vendor,
+        currency: expense_currency,
+        is_billable,
+        markup_percent: draft.markup_percent.unwrap_or(category.markup_percent),
+        requires_approval: base_amount.is_none_or(|amount| expenses::needs_approval(amount, category.approval_threshold)),
+        category,
+        draft,
+    })
+}
+
+async fn transition(
+    state: &AppState,
+    id: Uuid,
+    sql: &str,
+    user_id: Uuid,
+    notes: Option<String>,
+) -> Result<Json<ExpenseWithDetails>, StatusCode> {
+    let result = sqlx::query(sql)
+        .bind(id)
+        .bind(user_id)
+        .bind(notes)
+        .execute(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error updating expense status: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if result.rows_affected() == 0 {
+        load_expense(state, id).await?;
+        return Err(StatusCode::CONFLICT);
+    }
+
+    Ok(Json(load_expense(state, id).await?))
+}
+
+async fn load_expense(state: &AppState, id: Uuid) -> Result<ExpenseWithDetails, StatusCode> {
+    sqlx::query_as::<_, ExpenseWithDetails>(&format!(
+        "SELECT {} {} WHERE e.id = $1",
+        detail_columns(),
+        EXPENSE_FROM
+    ))
+    .bind(id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Error fetching expense: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })
+}
@@ -1,6 +1,6 @@
 use axum::{
     extract::{Path, Query, State},
-    http::{StatusCode, HeaderMap},
+    http::{header, StatusCode, HeaderMap},
     response::Json,
     routing::{get, post, put, patch},
     Router,
@@ -13,6 +13,17 @@ use std::sync::Arc;
 use uuid::Uuid;
 use crate::AppState;
 use crate::auth::{extract_token, verify_token};
+use crate::auth::middleware::AuthUser;
+use crate::services::currency::{self, RateSnapshot};
+use crate::services::expenses;
+use crate::services::invoice_pdf::{self, InvoicePdfLine, InvoiceSummary};
+use crate::services::payments::{self, NewPayment};
+use crate::services::recurring_billing::DraftLine;
+use crate::services::sales_tax::{self, LineTax, TaxContext, LINE_TAX_COLUMNS};
+use crate::services::credit_notes::{
+    self, Adjustment, CreditApplication, CreditNote, InvoiceAdjustment, Refund,
+    CREDIT_APPLICATION_COLUMNS, CREDIT_NOTE_COLUMNS, REFUND_COLUMNS,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InvoiceCreate {
@@ -26,6 +37,7 @@ pub struct InvoiceCreate {
     pub notes: Option<String>,
     pub terms: Option<String>,
     pub line_items: Vec<InvoiceLineItemCreate>,
+    pub include_expenses: Option<bool>, // approved unbilled expenses up to the invoice date, default true
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -33,7 +45,8 @@ pub struct InvoiceLineItemCreate {
     pub description: String,
     pub quantity: Decimal,
     pub unit_price: Decimal,
-    pub tax_rate: Option<Decimal>,
+    pub tax_rate: Option<Decimal>,         // replaces the jurisdiction rates for this line
+    pub tax_category: Option<String>,      // hardware, software, labor, ...; untaxed when absent
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -72,6 +85,11 @@ pub struct InvoiceWithDetails {
     pub tax_amount: Decimal,
     pub total: Decimal,
     pub balance: Decimal,
+    pub currency: String,
+    pub base_currency: String,
+    pub exchange_rate: Decimal,            // one unit of currency in base_currency, fixed when raised
+    pub exchange_rate_date: Option<NaiveDate>,
+    pub base_total: Option<Decimal>,
     pub status: String,
     pub payment_terms: String,
     pub late_fee_percentage: Option<Decimal>,
@@ -94,6 +112,49 @@ pub struct InvoiceLineItem {
     pub line_total: Decimal,
     pub tax_rate: Option<Decimal>,
     pub tax_amount: Option<Decimal>,
+    pub tax_category: Option<String>,
+    pub voided_at: Option<chrono::DateTime<Utc>>,
+    pub void_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct InvoiceLineTax {
+    pub invoice_line_item_id: Uuid,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub tax: LineTax,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct InvoiceTaxSummary {
+    pub jurisdiction_id: Option<Uuid>,
+    pub jurisdiction_name: String,
+    pub level: String,
+    pub state_code: Option<String>,
+    pub rate: Decimal,
+    pub taxable_amount: Decimal,
+    pub exempt_amount: Decimal,
+    pub tax_amount: Decimal,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InvoiceTaxes {
+    pub jurisdictions: Vec<InvoiceTaxSummary>, // open lines only
+    pub lines: Vec<InvoiceLineTax>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct InvoiceMetricSnapshot {
+    pub id: Uuid,
+    pub invoice_line_item_id: Uuid,
+    pub recurring_billing_item_id: Option<Uuid>,
+    pub metric_type: String,
+    pub metric_filter: Option<serde_json::Value>,
+    pub measured_quantity: Decimal,
+    pub billed_quantity: Decimal,
+    pub previous_quantity: Option<Decimal>,
+    pub evidence: Option<serde_json::Value>,
+    pub captured_at: Option<chrono::DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -114,15 +175,55 @@ pub struct Payment {
     pub payment_method: Option<String>,
     pub reference_number: Option<String>,
     pub notes: Option<String>,
+    pub refunded_amount: Decimal,
+    pub currency: String,
+    pub exchange_rate: Decimal,            // on the payment date; differs from the invoice rate by the realised gain or loss
+    pub base_amount: Option<Decimal>,
     pub created_at: chrono::DateTime<Utc>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvoiceVoid {
+    pub reason: String,
+    pub credit_payments: Option<bool>, // move payments already received to account credit
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvoiceLineVoid {
+    pub line_item_ids: Vec<Uuid>,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvoiceCreditCreate {
+    pub amount: Decimal,
+    pub reason: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefundCreate {
+    pub payment_id: Uuid,
+    pub amount: Decimal,
+    pub processor: Option<String>,
+    pub reference_number: Option<String>,
+    pub notes: Option<String>,
+}
+
 pub fn invoice_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/", get(list_invoices).post(create_invoice))
         .route("/:id", get(get_invoice).put(update_invoice))
         .route("/:id/line-items", get(get_invoice_line_items))
+        .route("/:id/taxes", get(get_invoice_taxes))
+        .route("/:id/metric-snapshots", get(get_invoice_metric_snapshots))
         .route("/:id/payments", get(get_invoice_payments).post(add_payment))
+        .route("/:id/refunds", get(get_invoice_refunds).post(refund_payment))
+        .route("/:id/credits", get(get_invoice_credits).post(credit_invoice))
+        .route("/:id/apply-credits", post(apply_credits))
+        .route("/:id/void", post(void_invoice))
+        .route("/:id/void-lines", post(void_invoice_lines))
+        .route("/:id/adjustments", get(get_invoice_adjustments))
         .route("/:id/send", patch(send_invoice))
         .route("/:id/pdf", get(generate_invoice_pdf))
         .route("/stats", get(get_invoice_stats))
@@ -142,10 +243,11 @@ async fn list_invoices(
             i.contract_id, i.project_id, p.name as project_name,
             i.number, i.date, i.due_date,
             i.subtotal, i.tax_amount, i.total, i.balance,
+            i.currency, i.base_currency, i.exchange_rate, i.exchange_rate_date, i.base_total,
             i.status, i.payment_terms,
             i.late_fee_percentage, i.discount_percentage, i.discount_amount,
             i.notes, i.terms,
-            CASE WHEN i.due_date < CURRENT_DATE AND i.status != 'paid' 
+            CASE WHEN i.due_date < CURRENT_DATE AND i.status NOT IN ('paid', 'void') 
                  THEN EXTRACT(days FROM CURRENT_DATE - i.due_date)::int
                  ELSE NULL END as days_overdue,
             i.created_at, i.updated_at
@@ -178,20 +280,22 @@ async fn create_invoice(
     let invoice_id = Uuid::new_v4();
     let now = Utc::now();
     
-    // Calculate totals from line items
-    let mut subtotal = Decimal::ZERO;
-    let mut tax_amount = Decimal::ZERO;
-    
-    for item in &payload.line_items {
-        let line_total = item.quantity * item.unit_price;
-        subtotal += line_total;
-        
-        if let Some(tax_rate) = item.tax_rate {
-            tax_amount += line_total * (tax_rate / Decimal::from(100));
-        }
-    }
-    
-    let total = subtotal + tax_amount;
+    let mut lines: Vec<DraftLine> = payload
+        .line_items
+        .into_iter()
+        .map(|item| DraftLine {
+            description: item.description,
+            quantity: item.quantity,
+            unit_price: item.unit_price,
+            tax_rate: item.tax_rate,
+            tax_category: item.tax_category,
+            source_type: "manual".to_string(),
+            source_id: None,
+            period_start: None,
+            period_end: None,
+            metric: None,
+        })
+        .collect();
     
     // Start transaction
     let mut tx = state.db_pool.begin().await.map_err(|e| {
@@ -199,13 +303,46 @@ async fn create_invoice(
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
     
+    // Rebill the client's approved expenses on this invoice
+    let expense_lines = if payload.include_expenses.unwrap_or(true) {
+        expenses::unbilled_lines(&mut tx, payload.client_id, payload.date)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error collecting unbilled expenses: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+    } else {
+        Vec::new()
+    };
+    lines.extend(expense_lines.iter().map(|(line, _)| line.clone()));
+    
+    // Tax each line for the client's jurisdictions
+    let tax_context = TaxContext::load(&mut *tx, payload.client_id, payload.date)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error loading tax rates: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let line_taxes: Vec<_> = lines
+        .iter()
+        .map(|l| tax_context.line_taxes(l.line_total(), l.tax_category.as_deref(), l.tax_rate))
+        .collect();
+    
+    let subtotal: Decimal = lines.iter().map(|l| l.line_total()).sum();
+    let tax_amount: Decimal = line_taxes.iter().map(|t| sales_tax::total_tax(t)).sum();
+    let total = subtotal + tax_amount;
+    
+    // Invoiced in the client's currency at the rate on the invoice date
+    let rate = rate_snapshot(&mut tx, payload.client_id, payload.date).await?;
+    
     // Insert invoice
     sqlx::query(
         "INSERT INTO invoices (
             id, client_id, contract_id, project_id, number, date, due_date,
             subtotal, tax_amount, total, balance, status, payment_terms,
-            notes, terms, created_at
-        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)"
+            notes, terms, created_at, currency, base_currency, exchange_rate,
+            exchange_rate_date, base_total
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21)"
     )
     .bind(invoice_id)
     .bind(payload.client_id)
@@ -223,6 +360,11 @@ async fn create_invoice(
     .bind(payload.notes)
     .bind(payload.terms)
     .bind(now)
+    .bind(&rate.currency)
+    .bind(&rate.base_currency)
+    .bind(rate.rate)
+    .bind(rate.rate_date)
+    .bind(rate.to_base(total))
     .execute(&mut *tx)
     .await
     .map_err(|e| {
@@ -231,37 +373,50 @@ async fn create_invoice(
     })?;
     
     // Insert line items
-    for item in payload.line_items {
+    for (line, taxes) in lines.iter().zip(&line_taxes) {
         let line_item_id = Uuid::new_v4();
-        let line_total = item.quantity * item.unit_price;
-        let item_tax_amount = if let Some(tax_rate) = item.tax_rate {
-            Some(line_total * (tax_rate / Decimal::from(100)))
-        } else {
-            None
-        };
         
         sqlx::query(
             "INSERT INTO invoice_line_items (
-                id, invoice_id, description, quantity, unit_price, 
-                line_total, tax_rate, tax_amount
-            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"
+                id, invoice_id, description, quantity, unit_price, line_total,
+                tax_rate, tax_amount, tax_category, source_type, source_id, period_start, period_end
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)"
         )
         .bind(line_item_id)
         .bind(invoice_id)
-        .bind(item.description)
-        .bind(item.quantity)
-        .bind(item.unit_price)
-        .bind(line_total)
-        .bind(item.tax_rate)
-        .bind(item_tax_amount)
+        .bind(&line.description)
+        .bind(line.quantity)
+        .bind(line.unit_price)
+        .bind(line.line_total())
+        .bind(sales_tax::effective_rate(line.line_total(), taxes))
+        .bind(sales_tax::total_tax(taxes))
+        .bind(&line.tax_category)
+        .bind(&line.source_type)
+        .bind(line.source_id)
+        .bind(line.period_start)
+        .bind(line.period_end)
         .execute(&mut *tx)
         .await
         .map_err(|e| {
             tracing::error!("Error creating invoice line item: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
+        
+        sales_tax::record_line_taxes(&mut *tx, invoice_id, line_item_id, taxes)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error recording line taxes: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
     }
     
+    expenses::mark_billed(&mut tx, &expense_lines, invoice_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error marking expenses billed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    
     tx.commit().await.map_err(|e| {
         tracing::error!("Error committing transaction: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
@@ -285,11 +440,17 @@ async fn update_invoice(
     Path(id): Path<Uuid>,
     Json(payload): Json<InvoiceUpdate>,
 ) -> Result<Json<InvoiceWithDetails>, StatusCode> {
+    // Paid and partial follow the balance, and voiding goes through /void so
+    // credits and payments are unwound; none of them can be set by hand
+    if matches!(payload.status.as_deref(), Some("void" | "paid" | "partial")) {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
     sqlx::query(
         "UPDATE invoices SET 
          date = COALESCE($2, date),
          due_date = COALESCE($3, due_date),
-         status = COALESCE($4, status),
+         status = CASE WHEN status = 'void' THEN status ELSE COALESCE($4, status) END,
          payment_terms = COALESCE($5, payment_terms),
          notes = COALESCE($6, notes),
          terms = COALESCE($7, terms),
@@ -320,7 +481,7 @@ async fn get_invoice_line_items(
 ) -> Result<Json<Vec<InvoiceLineItem>>, StatusCode> {
     let line_items = sqlx::query_as::<_, InvoiceLineItem>(
         "SELECT id, invoice_id, description, quantity, unit_price, 
-         line_total, tax_rate, tax_amount
+         line_total, tax_rate, tax_amount, tax_category, voided_at, void_reason
          FROM invoice_line_items 
          WHERE invoice_id = $1 
          ORDER BY created_at"
@@ -336,13 +497,74 @@ async fn get_invoice_line_items(
     Ok(Json(line_items))
 }
 
+/// Tax by jurisdiction for the invoice, and the per-line detail behind it.
+async fn get_invoice_taxes(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<InvoiceTaxes>, StatusCode> {
+    let jurisdictions = sqlx::query_as::<_, InvoiceTaxSummary>(
+        "SELECT t.jurisdiction_id, t.jurisdiction_name, t.level, t.state_code, t.rate,
+                SUM(t.taxable_amount) as taxable_amount, SUM(t.exempt_amount) as exempt_amount,
+                SUM(t.tax_amount) as tax_amount
+         FROM invoice_line_taxes t
+         JOIN invoice_line_items li ON li.id = t.invoice_line_item_id
+         WHERE t.invoice_id = $1 AND li.voided_at IS NULL
+         GROUP BY t.jurisdiction_id, t.jurisdiction_name, t.level, t.state_code, t.rate
+         ORDER BY CASE t.level WHEN 'state' THEN 0 WHEN 'county' THEN 1 WHEN 'city' THEN 2 ELSE 3 END,
+                  t.jurisdiction_name"
+    )
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching invoice tax summary: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    
+    let lines = sqlx::query_as::<_, InvoiceLineTax>(&format!(
+        "SELECT invoice_line_item_id, {} FROM invoice_line_taxes WHERE invoice_id = $1 ORDER BY created_at",
+        LINE_TAX_COLUMNS
+    ))
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching invoice line taxes: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    
+    Ok(Json(InvoiceTaxes { jurisdictions, lines }))
+}
+
+async fn get_invoice_metric_snapshots(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<InvoiceMetricSnapshot>>, StatusCode> {
+    let snapshots = sqlx::query_as::<_, InvoiceMetricSnapshot>(
+        "SELECT id, invoice_line_item_id, recurring_billing_item_id, metric_type, metric_filter,
+         measured_quantity, billed_quantity, previous_quantity, evidence, captured_at
+         FROM invoice_line_metric_snapshots
+         WHERE invoice_id = $1
+         ORDER BY captured_at"
+    )
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching invoice metric snapshots: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(snapshots))
+}
+
 async fn get_invoice_payments(
     State(state): State<Arc<AppState>>,
     Path(id): Path<Uuid>,
 ) -> Result<Json<Vec<Payment>>, StatusCode> {
     let payments = sqlx::query_as::<_, Payment>(
         "SELECT id, invoice_id, amount, payment_date, payment_method,
-         reference_number, notes, created_at
+         reference_number, notes, refunded_amount, currency, exchange_rate, base_amount, created_at
          FROM payments 
          WHERE invoice_id = $1 
          ORDER BY payment_date DESC"
@@ -367,56 +589,71 @@ async fn add_payment(
     // Extract user from token
     let token = extract_token(&headers)
         .ok_or(StatusCode::UNAUTHORIZED)?;
-    let _token_data = verify_token(&token)
+    let claims = verify_token(&token)
         .map_err(|_| StatusCode::UNAUTHORIZED)?;
     
-    let payment_id = Uuid::new_v4();
-    let now = Utc::now();
-    
+    if payload.amount <= Decimal::ZERO {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
     // Start transaction
     let mut tx = state.db_pool.begin().await.map_err(|e| {
         tracing::error!("Error starting transaction: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
+
+    // Only what is owed pays the invoice down; the rest is recorded as account credit
+    let invoice = credit_notes::lock_invoice(&mut tx, id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error locking invoice: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if invoice.is_void() {
+        return Err(StatusCode::CONFLICT);
+    }
+    let (applied, credited) = payments::split_payment(payload.amount, Some(&invoice), &invoice.currency);
+    if applied <= Decimal::ZERO {
+        // Nothing is owed, so there is no payment to record against this invoice
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
     
-    // Insert payment
-    sqlx::query(
-        "INSERT INTO payments (
-            id, invoice_id, amount, payment_date, payment_method,
-            reference_number, notes, created_at
-        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"
+    let payment_id = payments::record_payment(
+        &mut tx,
+        &invoice,
+        NewPayment {
+            amount: applied,
+            payment_date: payload.payment_date,
+            payment_method: payload.payment_method.as_deref(),
+            reference_number: payload.reference_number.as_deref(),
+            notes: payload.notes.as_deref(),
+        },
     )
-    .bind(payment_id)
-    .bind(id)
-    .bind(payload.amount)
-    .bind(payload.payment_date)
-    .bind(payload.payment_method)
-    .bind(payload.reference_number)
-    .bind(payload.notes)
-    .bind(now)
-    .execute(&mut *tx)
     .await
     .map_err(|e| {
         tracing::error!("Error adding payment: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
-    
-    // Update invoice balance
-    sqlx::query(
-        "UPDATE invoices SET 
-         balance = balance - $2,
-         status = CASE WHEN balance - $2 <= 0 THEN 'paid' ELSE 'partial' END,
-         updated_at = NOW()
-         WHERE id = $1"
-    )
-    .bind(id)
-    .bind(payload.amount)
-    .execute(&mut *tx)
-    .await
-    .map_err(|e| {
-        tracing::error!("Error updating invoice balance: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+
+    if credited > Decimal::ZERO {
+        let description = format!("Payment of {} exceeded the invoice balance", payload.amount);
+        credit_notes::issue_credit_note(
+            &mut tx,
+            invoice.client_id,
+            None,
+            "account",
+            credited,
+            "Overpayment",
+            Some(&description),
+            Some(claims.sub),
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!("Error crediting overpayment: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    }
     
     tx.commit().await.map_err(|e| {
         tracing::error!("Error committing transaction: {}", e);
@@ -426,7 +663,7 @@ async fn add_payment(
     // Fetch the created payment
     let payment = sqlx::query_as::<_, Payment>(
         "SELECT id, invoice_id, amount, payment_date, payment_method,
-         reference_number, notes, created_at
+         reference_number, notes, refunded_amount, currency, exchange_rate, base_amount, created_at
          FROM payments WHERE id = $1"
     )
     .bind(payment_id)
@@ -460,14 +697,35 @@ async fn send_invoice(
 }
 
 async fn generate_invoice_pdf(
-    State(_state): State<Arc<AppState>>,
-    Path(_id): Path<Uuid>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    // TODO: Implement PDF generation
-    Ok(Json(serde_json::json!({
-        "message": "PDF generation not yet implemented",
-        "url": "/api/v1/invoices/{}/pdf"
-    })))
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<([(header::HeaderName, String); 2], Vec<u8>), StatusCode> {
+    let (invoice, lines) = invoice_pdf::load(&state.db_pool, id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error loading invoice for PDF: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(invoice_pdf_response(&invoice, &lines))
+}
+
+/// The rendered invoice, shared with the client portal download.
+pub fn invoice_pdf_response(
+    invoice: &InvoiceSummary,
+    lines: &[InvoicePdfLine],
+) -> ([(header::HeaderName, String); 2], Vec<u8>) {
+    (
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"{}.pdf\"", invoice.number),
+            ),
+        ],
+        invoice_pdf::render_pdf(invoice, lines),
+    )
 }
 
 #[derive(Debug, Serialize)]
@@ -477,7 +735,7 @@ pub struct InvoiceStats {
     pub sent_invoices: i64,
     pub paid_invoices: i64,
     pub overdue_invoices: i64,
-    pub total_outstanding: Decimal,
+    pub total_outstanding: Decimal, // in the base currency at each invoice's rate
     pub total_overdue: Decimal,
     pub average_days_to_pay: Option<i32>,
 }
@@ -491,9 +749,9 @@ async fn get_invoice_stats(
             COUNT(*) FILTER (WHERE status = 'draft') as draft_invoices,
             COUNT(*) FILTER (WHERE status = 'sent') as sent_invoices,
             COUNT(*) FILTER (WHERE status = 'paid') as paid_invoices,
-            COUNT(*) FILTER (WHERE due_date < CURRENT_DATE AND status != 'paid') as overdue_invoices,
-            COALESCE(SUM(balance) FILTER (WHERE status != 'paid'), 0) as total_outstanding,
-            COALESCE(SUM(balance) FILTER (WHERE due_date < CURRENT_DATE AND status != 'paid'), 0) as total_overdue
+            COUNT(*) FILTER (WHERE due_date < CURRENT_DATE AND status NOT IN ('paid', 'void')) as overdue_invoices,
+            COALESCE(SUM(ROUND(balance * exchange_rate, 2)) FILTER (WHERE status NOT IN ('paid', 'void')), 0) as total_outstanding,
+            COALESCE(SUM(ROUND(balance * exchange_rate, 2)) FILTER (WHERE due_date < CURRENT_DATE AND status NOT IN ('paid', 'void')), 0) as total_overdue
          FROM invoices"
     )
     .fetch_one(&state.db_pool)
@@ -526,6 +784,7 @@ async fn get_overdue_invoices(
             i.contract_id, i.project_id, p.name as project_name,
             i.number, i.date, i.due_date,
             i.subtotal, i.tax_amount, i.total, i.balance,
+            i.currency, i.base_currency, i.exchange_rate, i.exchange_rate_date, i.base_total,
             i.status, i.payment_terms,
             i.late_fee_percentage, i.discount_percentage, i.discount_amount,
             i.notes, i.terms,
@@ -534,7 +793,7 @@ async fn get_overdue_invoices(
          FROM invoices i
          LEFT JOIN clients c ON i.client_id = c.id
          LEFT JOIN projects p ON i.project_id = p.id
-         WHERE i.due_date < CURRENT_DATE AND i.status != 'paid'
+         WHERE i.due_date < CURRENT_DATE AND i.status NOT IN ('paid', 'void')
          ORDER BY i.due_date ASC"
     )
     .fetch_all(&state.db_pool)
@@ -547,7 +806,528 @@ async fn get_overdue_invoices(
     Ok(Json(invoices))
 }
 
+async fn get_invoice_refunds(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<Refund>>, StatusCode> {
+    let refunds = sqlx::query_as::<_, Refund>(&format!(
+        "SELECT {} FROM payment_transactions
+         WHERE invoice_id = $1 AND transaction_type = 'refund'
+         ORDER BY processed_at DESC",
+        REFUND_COLUMNS
+    ))
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching invoice refunds: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(refunds))
+}
+
+/// Refunds part or all of a recorded payment. The refunded amount is owed again
+/// on the invoice; to pay back credit instead, refund the credit note.
+async fn refund_payment(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<RefundCreate>,
+) -> Result<(StatusCode, Json<Refund>), StatusCode> {
+    if payload.amount <= Decimal::ZERO {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut tx = state.db_pool.begin().await.map_err(|e| {
+        tracing::error!("Error starting transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let invoice = credit_notes::lock_invoice(&mut tx, id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error locking invoice: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if invoice.is_void() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let refundable: Decimal = sqlx::query_scalar(
+        "SELECT amount - refunded_amount FROM payments WHERE id = $1 AND invoice_id = $2 FOR UPDATE"
+    )
+    .bind(payload.payment_id)
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching payment: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    if payload.amount > refundable {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let refund = credit_notes::insert_refund(
+        &mut tx,
+        invoice.client_id,
+        Some(id),
+        Some(payload.payment_id),
+        None,
+        payload.amount,
+        payload.processor.as_deref().unwrap_or("manual"),
+        payload.reference_number.as_deref(),
+        payload.notes.as_deref(),
+        Some(auth.0.id),
+    )
+    .await
+    .map_err(|e| {
+        tracing::error!("Error recording refund: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    sqlx::query("UPDATE payments SET refunded_amount = refunded_amount + $2 WHERE id = $1")
+        .bind(payload.payment_id)
+        .bind(payload.amount)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error updating refunded payment: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    credit_notes::adjust_balance(
+        &mut tx,
+        &invoice,
+        invoice.balance + payload.amount,
+        Adjustment {
+            adjustment_type: "refund",
+            amount: payload.amount,
+            reason: payload.notes.as_deref(),
+            credit_note_id: None,
+            payment_transaction_id: Some(refund.id),
+            created_by: Some(auth.0.id),
+        },
+    )
+    .await
+    .map_err(|e| {
+        tracing::error!("Error updating invoice balance: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Error committing transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::CREATED, Json(refund)))
+}
+
+async fn get_invoice_credits(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<CreditApplication>>, StatusCode> {
+    let applications = sqlx::query_as::<_, CreditApplication>(&format!(
+        "SELECT {} FROM credit_note_applications a
+         JOIN credit_notes cn ON cn.id = a.credit_note_id
+         JOIN invoices i ON i.id = a.invoice_id
+         WHERE a.invoice_id = $1
+         ORDER BY a.applied_at",
+        CREDIT_APPLICATION_COLUMNS
+    ))
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching invoice credits: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(applications))
+}
+
+/// Issues a credit note against an invoice and applies it. Any part of the
+/// credit larger than the open balance stays on the note as account credit.
+async fn credit_invoice(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<InvoiceCreditCreate>,
+) -> Result<(StatusCode, Json<CreditNote>), StatusCode> {
+    if payload.amount <= Decimal::ZERO || payload.reason.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut tx = state.db_pool.begin().await.map_err(|e| {
+        tracing::error!("Error starting transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let invoice = credit_notes::lock_invoice(&mut tx, id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error locking invoice: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    // Drafts are edited directly rather than credited
+    if invoice.is_void() || invoice.status.as_deref() == Some("draft") {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let credit_note_id = credit_notes::issue_credit_note(
+        &mut tx,
+        invoice.client_id,
+        Some(id),
+        "invoice",
+        payload.amount,
+        payload.reason.trim(),
+        payload.description.as_deref(),
+        Some(auth.0.id),
+    )
+    .await
+    .map_err(|e| {
+        tracing::error!("Error issuing credit note: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let applied = payload.amount.min(invoice.balance);
+    if applied > Decimal::ZERO {
+        credit_notes::apply_credit(&mut tx, credit_note_id, &invoice, applied, Some(auth.0.id))
+            .await
+            .map_err(|e| {
+                tracing::error!("Error applying credit note: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    }
+
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Error committing transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let credit_note = sqlx::query_as::<_, CreditNote>(&format!(
+        "SELECT {} FROM credit_notes cn JOIN clients c ON c.id = cn.client_id WHERE cn.id = $1",
+        CREDIT_NOTE_COLUMNS
+    ))
+    .bind(credit_note_id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching credit note: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::CREATED, Json(credit_note)))
+}
+
+/// Applies the client's open account credit to this invoice.
+async fn apply_credits(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<InvoiceWithDetails>, StatusCode> {
+    let mut tx = state.db_pool.begin().await.map_err(|e| {
+        tracing::error!("Error starting transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    credit_notes::apply_available_credits(&mut tx, id, Some(auth.0.id))
+        .await
+        .map_err(|e| {
+            tracing::error!("Error applying credits: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Error committing transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let invoice = get_invoice_by_id(&state, id).await?;
+    Ok(Json(invoice))
+}
+
+/// Voids an invoice. The invoice and its lines are kept for the audit trail;
+/// applied credit goes back to its credit notes, and payments already received
+/// must either be refunded first or moved to account credit.
+async fn void_invoice(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<InvoiceVoid>,
+) -> Result<Json<InvoiceWithDetails>, StatusCode> {
+    if payload.reason.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut tx = state.db_pool.begin().await.map_err(|e| {
+        tracing::error!("Error starting transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let invoice = credit_notes::lock_invoice(&mut tx, id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error locking invoice: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if invoice.is_void() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let paid: Decimal = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(amount - refunded_amount), 0) FROM payments WHERE invoice_id = $1"
+    )
+    .bind(id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error summing invoice payments: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if paid > Decimal::ZERO {
+        if !payload.credit_payments.unwrap_or(false) {
+            return Err(StatusCode::CONFLICT);
+        }
+        credit_notes::issue_credit_note(
+            &mut tx,
+            invoice.client_id,
+            Some(id),
+            "account",
+            paid,
+            "Payment received on voided invoice",
+            Some(payload.reason.trim()),
+            Some(auth.0.id),
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!("Error crediting payments on voided invoice: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    }
+
+    credit_notes::reverse_credits(&mut tx, id).await.map_err(|e| {
+        tracing::error!("Error reversing invoice credits: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    sqlx::query(
+        "UPDATE invoice_line_items SET voided_at = NOW(), void_reason = $2
+         WHERE invoice_id = $1 AND voided_at IS NULL"
+    )
+    .bind(id)
+    .bind(payload.reason.trim())
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error voiding invoice lines: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    sqlx::query(
+        "UPDATE invoices SET status = 'void', balance = 0, voided_amount = voided_amount + total,
+         voided_at = NOW(), voided_by = $2, void_reason = $3, updated_at = NOW()
+         WHERE id = $1"
+    )
+    .bind(id)
+    .bind(auth.0.id)
+    .bind(payload.reason.trim())
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error voiding invoice: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    credit_notes::record_adjustment(
+        &mut tx,
+        id,
+        invoice.balance,
+        Decimal::ZERO,
+        Adjustment {
+            adjustment_type: "void",
+            amount: invoice.total,
+            reason: Some(payload.reason.trim()),
+            credit_note_id: None,
+            payment_transaction_id: None,
+            created_by: Some(auth.0.id),
+        },
+    )
+    .await
+    .map_err(|e| {
+        tracing::error!("Error recording invoice void: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Error committing transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let invoice = get_invoice_by_id(&state, id).await?;
+    Ok(Json(invoice))
+}
+
+/// Voids individual lines, reducing the invoice total. Only the unpaid part of
+/// an invoice can be voided; anything already paid needs a credit note.
+async fn void_invoice_lines(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<InvoiceLineVoid>,
+) -> Result<Json<InvoiceWithDetails>, StatusCode> {
+    let mut line_ids = payload.line_item_ids.clone();
+    line_ids.sort();
+    line_ids.dedup();
+    if line_ids.is_empty() || payload.reason.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut tx = state.db_pool.begin().await.map_err(|e| {
+        tracing::error!("Error starting transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let invoice = credit_notes::lock_invoice(&mut tx, id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error locking invoice: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if invoice.is_void() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let (matched, open_lines, subtotal, tax): (i64, i64, Decimal, Decimal) = sqlx::query_as(
+        "SELECT COUNT(*) FILTER (WHERE id = ANY($2)),
+                COUNT(*),
+                COALESCE(SUM(line_total) FILTER (WHERE id = ANY($2)), 0),
+                COALESCE(SUM(COALESCE(tax_amount, 0)) FILTER (WHERE id = ANY($2)), 0)
+         FROM invoice_line_items WHERE invoice_id = $1 AND voided_at IS NULL"
+    )
+    .bind(id)
+    .bind(&line_ids)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching invoice lines: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if matched != line_ids.len() as i64 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    // Voiding every remaining line is a full void
+    let amount = subtotal + tax;
+    if matched == open_lines || amount > invoice.balance {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    sqlx::query(
+        "UPDATE invoice_line_items SET voided_at = NOW(), void_reason = $2 WHERE id = ANY($1)"
+    )
+    .bind(&line_ids)
+    .bind(payload.reason.trim())
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error voiding invoice lines: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    sqlx::query(
+        "UPDATE invoices SET subtotal = subtotal - $2, tax_amount = tax_amount - $3,
+         total = total - $4, voided_amount = voided_amount + $4
+         WHERE id = $1"
+    )
+    .bind(id)
+    .bind(subtotal)
+    .bind(tax)
+    .bind(amount)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error updating invoice totals: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let reduced = credit_notes::InvoiceBalance { total: invoice.total - amount, ..invoice.clone() };
+    credit_notes::adjust_balance(
+        &mut tx,
+        &reduced,
+        invoice.balance - amount,
+        Adjustment {
+            adjustment_type: "partial_void",
+            amount,
+            reason: Some(payload.reason.trim()),
+            credit_note_id: None,
+            payment_transaction_id: None,
+            created_by: Some(auth.0.id),
+        },
+    )
+    .await
+    .map_err(|e| {
+        tracing::error!("Error updating invoice balance: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Error committing transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let invoice = get_invoice_by_id(&state, id).await?;
+    Ok(Json(invoice))
+}
+
+async fn get_invoice_adjustments(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<InvoiceAdjustment>>, StatusCode> {
+    let adjustments = sqlx::query_as::<_, InvoiceAdjustment>(
+        "SELECT id, invoice_id, adjustment_type, amount, balance_before, balance_after, reason,
+         credit_note_id, payment_transaction_id, created_by, created_at
+         FROM invoice_adjustments
+         WHERE invoice_id = $1
+         ORDER BY created_at"
+    )
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching invoice adjustments: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(adjustments))
+}
+
 // Helper functions
+/// Fixes the client's currency and today's rate for a new invoice. Refuses
+/// the invoice when the rate is missing or stale rather than guessing.
+async fn rate_snapshot(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    client_id: Uuid,
+    date: NaiveDate,
+) -> Result<RateSnapshot, StatusCode> {
+    let client_currency = currency::client_currency(&mut **tx, client_id).await.map_err(|e| {
+        tracing::error!("Error fetching client currency: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    currency::snapshot(&mut **tx, &client_currency, date).await.map_err(|e| {
+        tracing::warn!("Cannot invoice client {}: {}", client_id, e);
+        StatusCode::UNPROCESSABLE_ENTITY
+    })
+}
+
 async fn get_invoice_by_id(state: &AppState, id: Uuid) -> Result<InvoiceWithDetails, StatusCode> {
     sqlx::query_as::<_, InvoiceWithDetails>(
         "SELECT 
@@ -555,10 +1335,11 @@ async fn get_invoice_by_id(state: &AppState, id: Uuid) -> Result<InvoiceWithDeta
             i.contract_id, i.project_id, p.name as project_name,
             i.number, i.date, i.due_date,
             i.subtotal, i.tax_amount, i.total, i.balance,
+            i.currency, i.base_currency, i.exchange_rate, i.exchange_rate_date, i.base_total,
             i.status, i.payment_terms,
             i.late_fee_percentage, i.discount_percentage, i.discount_amount,
             i.notes, i.terms,
-            CASE WHEN i.due_date < CURRENT_DATE AND i.status != 'paid' 
+            CASE WHEN i.due_date < CURRENT_DATE AND i.status NOT IN ('paid', 'void') 
                  THEN EXTRACT(days FROM CURRENT_DATE - i.due_date)::int
                  ELSE NULL END as days_overdue,
             i.created_at, i.updated_at
@@ -23,6 +23,25 @@ pub mod forticloud;
 pub mod license_alerts;
 pub mod documentation;
 pub mod reporting;
+pub mod sms;
+pub mod chat;
+pub mod portal_messages;
+pub mod recurring_billing;
+pub mod contracts;
+pub mod rate_cards;
+pub mod quotes;
+pub mod credit_notes;
+pub mod expenses;
+pub mod sales_tax;
+pub mod currencies;
+pub mod payments;
+pub mod statements;
+pub mod dunning;
+pub mod accounting;
+pub mod billing_review;
+pub mod project_templates;
+pub mod project_budgets;
+pub mod dispatch;
 
 pub use clients::client_routes;
 pub use tickets::ticket_routes;
@@ -41,6 +60,50 @@ pub use forticloud::forticloud_routes;
 pub use license_alerts::license_alert_routes;
 pub use documentation::documentation_routes;
 pub use reporting::reporting_routes;
+pub use sms::sms_routes;
+pub use chat::chat_routes;
+pub use portal_messages::portal_inbox_routes;
+pub use recurring_billing::recurring_billing_routes;
+pub use contracts::contract_routes;
+pub use rate_cards::rate_card_routes;
+pub use quotes::quote_routes;
+pub use credit_notes::credit_note_routes;
+pub use expenses::expense_routes;
+pub use sales_tax::sales_tax_routes;
+pub use currencies::currency_routes;
+pub use payments::payment_routes;
+pub use statements::statement_routes;
+pub use dunning::dunning_routes;
+pub use accounting::accounting_routes;
+pub use billing_review::billing_review_routes;
+pub use project_templates::project_template_routes;
+pub use dispatch::dispatch_routes;
+
+/// Logs a failure with what was being attempted and maps it to a 500, for
+/// `.map_err(internal("loading invoice"))?`.
+pub(crate) fn internal<E: std::fmt::Display>(context: &'static str) -> impl Fn(E) -> StatusCode {
+    move |e| {
+        tracing::error!("Error {}: {}", context, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// Rejects a contact that isn't a current contact of the client, so quotes and
+/// notices with client figures can't be sent to another client's people.
+pub(crate) async fn ensure_client_contact(
+    pool: &sqlx::PgPool,
+    client_id: uuid::Uuid,
+    contact_id: uuid::Uuid,
+) -> Result<(), StatusCode> {
+    let found: Option<uuid::Uuid> =
+        sqlx::query_scalar("SELECT id FROM contacts WHERE id = $1 AND client_id = $2 AND archived_at IS NULL")
+            .bind(contact_id)
+            .bind(client_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(internal("fetching contact"))?;
+    found.map(|_| ()).ok_or(StatusCode::BAD_REQUEST)
+}
 
 // Add user routes function
 pub fn user_routes() -> axum::Router<std::sync::Arc<crate::AppState>> {
@@ -187,9 +187,7 @@ pub async fn create_password_share(
     };
 
     let password_manager = PasswordManagerService::new(pool.clone(), encryption_service);
-    let base_url = std::env::var("APP_BASE_URL").unwrap_or_else(|_| "https://ghosthub.local".to_string());
-
-    match password_manager.create_password_share(request, claims.sub, &base_url).await {
+    match password_manager.create_password_share(request, claims.sub, crate::config::app_base_url()).await {
         Ok(share) => {
             info!("Password share created: {}", share.id);
             Ok(Json(ApiResponse::success(share)))
@@ -0,0 +1,518 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+    routing::{delete, get, post},
+    Router,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::handlers::portal::{extract_portal_token, verify_token};
+use crate::integrations::stripe::{self, CheckoutRequest, PaymentIntentRequest, StripeClient, WebhookEvent};
+use crate::services::payments::{
+    self, CollectionSummary, PaymentCollectionConfig, PaymentCollectionService, PaymentTransaction,
+    SavedPaymentMethod, PAYMENT_METHOD_COLUMNS, TRANSACTION_COLUMNS,
+};
+use crate::AppState;
+
+/// How long a hosted checkout link stays usable.
+const CHECKOUT_SESSION_HOURS: i64 = 24;
+
+pub fn payment_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/transactions", get(list_transactions))
+        .route("/methods", get(list_payment_methods))
+        .route("/auto-charge/run", post(run_auto_charge))
+        // Stripe callbacks, authenticated by request signature rather than a session
+        .route("/webhooks/stripe", post(stripe_webhook))
+}
+
+/// Client portal routes for saved cards, nested under /api/v1/portal/payment-methods.
+pub fn portal_payment_method_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_portal_payment_methods))
+        .route("/:id", delete(remove_portal_payment_method))
+        .route("/:id/default", post(set_default_payment_method))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransactionQuery {
+    pub client_id: Option<Uuid>,
+    pub invoice_id: Option<Uuid>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PaymentMethodQuery {
+    pub client_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PayInvoiceRequest {
+    pub mode: Option<String>,             // checkout (hosted page, default) or payment_intent (embedded form)
+    pub payment_method_id: Option<Uuid>,  // charge a saved card instead of entering one
+    pub save_payment_method: Option<bool>, // keep the card for future and automatic payments
+}
+
+#[derive(Debug, Serialize)]
+pub struct PayInvoiceResponse {
+    pub payment_transaction_id: Uuid,
+    pub amount: Decimal,
+    pub currency: String,
+    pub status: String,
+    pub checkout_url: Option<String>,
+    pub client_secret: Option<String>,
+    pub publishable_key: String,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct PayableInvoice {
+    number: String,
+    status: Option<String>,
+    currency: String,
+    balance: Decimal,
+}
+
+async fn list_transactions(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<TransactionQuery>,
+) -> Result<Json<Vec<PaymentTransaction>>, StatusCode> {
+    let transactions = sqlx::query_as::<_, PaymentTransaction>(&format!(
+        "SELECT {} FROM payment_transactions
+         WHERE ($1::UUID IS NULL OR client_id = $1) AND ($2::UUID IS NULL OR invoice_id = $2)
+           AND ($3::TEXT IS NULL OR status = $3)
+         ORDER BY initiated_at DESC
+         LIMIT 500",
+        TRANSACTION_COLUMNS
+    ))
+    .bind(params.client_id)
+    .bind(params.invoice_id)
+    .bind(params.status)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching payment transactions: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(transactions))
+}
+
+async fn list_payment_methods(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<PaymentMethodQuery>,
+) -> Result<Json<Vec<SavedPaymentMethod>>, StatusCode> {
+    let methods = sqlx::query_as::<_, SavedPaymentMethod>(&format!(
+        "SELECT {} FROM payment_methods
+         WHERE removed_at IS NULL AND ($1::UUID IS NULL OR client_id = $1)
+         ORDER BY client_id, is_default DESC NULLS LAST, created_at DESC",
+        PAYMENT_METHOD_COLUMNS
+    ))
+    .bind(params.client_id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching payment methods: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(methods))
+}
+
+/// Runs the auto-charge worker now instead of waiting for its next pass.
+async fn run_auto_charge(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+) -> Result<Json<CollectionSummary>, StatusCode> {
+    let service = PaymentCollectionService::new(PaymentCollectionConfig::default(), state.db_pool.clone());
+    let summary = service.collect_pending().await.map_err(|e| {
+        tracing::error!("Error running auto-charge: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(summary))
+}
+
+async fn stripe_webhook(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, StatusCode> {
+    let stripe = stripe_client(&state).await?;
+
+    // Unsigned deliveries are never accepted, so the endpoint secret is required
+    let secret = stripe.webhook_secret().ok_or_else(|| {
+        tracing::error!("Stripe webhook received but no endpoint secret is configured");
+        StatusCode::SERVICE_UNAVAILABLE
+    })?;
+    let signature = headers
+        .get("Stripe-Signature")
+        .and_then(|v| v.to_str().ok())
+        .ok_or(StatusCode::FORBIDDEN)?;
+    if !stripe::verify_webhook_signature(secret, signature, &body, chrono::Utc::now().timestamp()) {
+        tracing::warn!("Rejected Stripe webhook with invalid signature");
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let event: WebhookEvent = serde_json::from_slice(&body).map_err(|e| {
+        tracing::warn!("Malformed Stripe webhook: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    // A failure here is returned to Stripe, which retries the delivery
+    payments::handle_stripe_event(&state.db_pool, &stripe, &event)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error handling Stripe event {} ({}): {}", event.id, event.event_type, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(StatusCode::OK)
+}
+
+/// "Pay now" on a portal invoice: starts a Stripe Checkout session or a
+/// PaymentIntent for the outstanding balance. The payment is recorded when
+/// Stripe reports it through the webhook.
+pub async fn pay_portal_invoice(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(invoice_id): Path<Uuid>,
+    Json(payload): Json<PayInvoiceRequest>,
+) -> Result<(StatusCode, Json<PayInvoiceResponse>), StatusCode> {
+    let (contact_id, client_id) = portal_client(&state, &headers).await?;
+
+    let mode = payload.mode.as_deref().unwrap_or("checkout");
+    if !["checkout", "payment_intent"].contains(&mode) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let save_payment_method = payload.save_payment_method.unwrap_or(false);
+
+    let invoice = sqlx::query_as::<_, PayableInvoice>(
+        "SELECT number, status, currency, COALESCE(balance, 0) as balance
+         FROM invoices WHERE id = $1 AND client_id = $2",
+    )
+    .bind(invoice_id)
+    .bind(client_id)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching invoice for payment: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    if matches!(invoice.status.as_deref(), Some("draft") | Some("void")) || invoice.balance <= Decimal::ZERO {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let saved_method = match payload.payment_method_id {
+        Some(id) => {
+            let stripe_id: Option<String> = sqlx::query_scalar(
+                "SELECT stripe_payment_method_id FROM payment_methods
+                 WHERE id = $1 AND client_id = $2 AND removed_at IS NULL",
+            )
+            .bind(id)
+            .bind(client_id)
+            .fetch_optional(&state.db_pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error fetching saved payment method: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::NOT_FOUND)?;
+            Some((id, stripe_id.ok_or(StatusCode::UNPROCESSABLE_ENTITY)?))
+        }
+        None => None,
+    };
+
+    let stripe = stripe_client(&state).await?;
+    let customer_id = payments::ensure_stripe_customer(&state.db_pool, &stripe, client_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error creating Stripe customer for client {}: {}", client_id, e);
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    let transaction_id: Uuid = sqlx::query_scalar(
+        "INSERT INTO payment_transactions (client_id, invoice_id, payment_method_id, transaction_type, amount,
+                                           currency, processor, status, reference_number, notes)
+         VALUES ($1, $2, $3, 'payment', $4, $5, 'stripe', 'pending', $6, 'Client portal payment')
+         RETURNING id",
+    )
+    .bind(client_id)
+    .bind(invoice_id)
+    .bind(saved_method.as_ref().map(|(id, _)| *id))
+    .bind(invoice.balance)
+    .bind(&invoice.currency)
+    .bind(&invoice.number)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error recording payment transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let description = format!("Invoice {}", invoice.number);
+    let mut response = PayInvoiceResponse {
+        payment_transaction_id: transaction_id,
+        amount: invoice.balance,
+        currency: invoice.currency.clone(),
+        status: "pending".to_string(),
+        checkout_url: None,
+        client_secret: None,
+        publishable_key: stripe.publishable_key().to_string(),
+    };
+
+    // A saved card is charged directly; otherwise the payer enters one on Stripe's page
+    if mode == "checkout" && saved_method.is_none() {
+        let invoice_url = payments::portal_invoice_url(invoice_id);
+        let session = stripe
+            .create_checkout_session(&CheckoutRequest {
+                customer_id: &customer_id,
+                payment_transaction_id: transaction_id,
+                description: &description,
+                amount: invoice.balance,
+                currency: &invoice.currency,
+                success_url: &format!("{}?payment=success", invoice_url),
+                cancel_url: &format!("{}?payment=cancelled", invoice_url),
+                save_payment_method,
+            })
+            .await;
+        let session = match session {
+            Ok(session) => session,
+            Err(e) => return Err(processor_failure(&state, transaction_id, e).await),
+        };
+
+        sqlx::query(
+            "INSERT INTO payment_portal_sessions (client_id, contact_id, session_token, invoice_id,
+                                                  payment_transaction_id, checkout_url, can_update_payment_methods,
+                                                  expires_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, NOW() + make_interval(hours => $8))",
+        )
+        .bind(client_id)
+        .bind(contact_id)
+        .bind(&session.id)
+        .bind(invoice_id)
+        .bind(transaction_id)
+        .bind(&session.url)
+        .bind(save_payment_method)
+        .bind(CHECKOUT_SESSION_HOURS as i32)
+        .execute(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error recording checkout session: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        response.checkout_url = session.url;
+        return Ok((StatusCode::CREATED, Json(response)));
+    }
+
+    let intent = stripe
+        .create_payment_intent(&PaymentIntentRequest {
+            customer_id: &customer_id,
+            payment_transaction_id: transaction_id,
+            description: &description,
+            amount: invoice.balance,
+            currency: &invoice.currency,
+            payment_method: saved_method.as_ref().map(|(_, stripe_id)| stripe_id.as_str()),
+            off_session: false,
+            save_payment_method: save_payment_method && saved_method.is_none(),
+        })
+        .await;
+    let intent = match intent {
+        Ok(intent) => intent,
+        Err(e) => return Err(processor_failure(&state, transaction_id, e).await),
+    };
+
+    sqlx::query("UPDATE payment_transactions SET processor_transaction_id = $2 WHERE id = $1")
+        .bind(transaction_id)
+        .bind(&intent.id)
+        .execute(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error storing PaymentIntent reference: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    // Saved cards usually succeed straight away; record it now rather than waiting for the webhook
+    if intent.status == "succeeded" {
+        let received = stripe::from_minor_units(
+            intent.amount_received.unwrap_or(intent.amount),
+            &intent.currency.to_uppercase(),
+        );
+        let mut tx = state.db_pool.begin().await.map_err(|e| {
+            tracing::error!("Error starting transaction: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        let completed = payments::complete_stripe_payment(&mut tx, transaction_id, &intent.id, received, None)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error recording Stripe payment {}: {}", intent.id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        tx.commit().await.map_err(|e| {
+            tracing::error!("Error committing transaction: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        if let Some(completed) = completed {
+            payments::notify_payment_received(&state.db_pool, &completed).await;
+        }
+        response.status = "completed".to_string();
+    } else {
+        response.client_secret = intent.client_secret;
+        response.status = intent.status;
+    }
+
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+async fn list_portal_payment_methods(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<SavedPaymentMethod>>, StatusCode> {
+    let (_, client_id) = portal_client(&state, &headers).await?;
+
+    let methods = sqlx::query_as::<_, SavedPaymentMethod>(&format!(
+        "SELECT {} FROM payment_methods
+         WHERE client_id = $1 AND removed_at IS NULL
+         ORDER BY is_default DESC NULLS LAST, created_at DESC",
+        PAYMENT_METHOD_COLUMNS
+    ))
+    .bind(client_id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching portal payment methods: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(methods))
+}
+
+/// Detaches the card from the Stripe customer and hides it. Recurring
+/// profiles still pointing at it fail their next auto-charge and notify the client.
+async fn remove_portal_payment_method(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let (_, client_id) = portal_client(&state, &headers).await?;
+
+    let (stripe_id, was_default): (Option<String>, bool) = sqlx::query_as(
+        "SELECT stripe_payment_method_id, COALESCE(is_default, false) FROM payment_methods
+         WHERE id = $1 AND client_id = $2 AND removed_at IS NULL",
+    )
+    .bind(id)
+    .bind(client_id)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching payment method: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    if let Some(stripe_id) = stripe_id {
+        let stripe = stripe_client(&state).await?;
+        stripe.detach_payment_method(&stripe_id).await.map_err(|e| {
+            tracing::error!("Error detaching Stripe payment method {}: {}", stripe_id, e);
+            StatusCode::BAD_GATEWAY
+        })?;
+    }
+
+    let mut tx = state.db_pool.begin().await.map_err(|e| {
+        tracing::error!("Error starting transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    sqlx::query("UPDATE payment_methods SET removed_at = NOW(), is_default = false, updated_at = NOW() WHERE id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error removing payment method: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    // The most recently added remaining card takes over as default
+    if was_default {
+        sqlx::query(
+            "UPDATE payment_methods SET is_default = true, updated_at = NOW()
+             WHERE id = (SELECT id FROM payment_methods WHERE client_id = $1 AND removed_at IS NULL
+                         ORDER BY created_at DESC LIMIT 1)",
+        )
+        .bind(client_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error promoting default payment method: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    }
+
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Error committing transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_default_payment_method(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let (_, client_id) = portal_client(&state, &headers).await?;
+
+    let updated = sqlx::query(
+        "UPDATE payment_methods SET is_default = (id = $1), updated_at = NOW()
+         WHERE client_id = $2 AND removed_at IS NULL
+           AND EXISTS (SELECT 1 FROM payment_methods WHERE id = $1 AND client_id = $2 AND removed_at IS NULL)",
+    )
+    .bind(id)
+    .bind(client_id)
+    .execute(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error setting default payment method: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if updated.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn portal_client(state: &Arc<AppState>, headers: &HeaderMap) -> Result<(Uuid, Uuid), StatusCode> {
+    let token = extract_portal_token(headers)?;
+    verify_token(state, &token).await
+}
+
+async fn stripe_client(state: &AppState) -> Result<StripeClient, StatusCode> {
+    StripeClient::from_integration(&state.db_pool).await.map_err(|e| {
+        tracing::error!("Stripe is not available: {}", e);
+        StatusCode::SERVICE_UNAVAILABLE
+    })
+}
+
+async fn processor_failure(
+    state: &AppState,
+    transaction_id: Uuid,
+    error: Box<dyn std::error::Error + Send + Sync>,
+) -> StatusCode {
+    tracing::error!("Stripe rejected payment transaction {}: {}", transaction_id, error);
+    if let Err(e) = payments::fail_transaction(&state.db_pool, transaction_id, &error.to_string()).await {
+        tracing::error!("Error marking payment transaction {} failed: {}", transaction_id, e);
+    }
+    StatusCode::BAD_GATEWAY
+}
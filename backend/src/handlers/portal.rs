@@ -1,6 +1,6 @@
 use axum::{
     extract::{Path, Query, State},
-    http::{StatusCode, HeaderMap},
+    http::{header, StatusCode, HeaderMap},
     response::Json,
     routing::{get, post, put},
     Router,
@@ -12,6 +12,9 @@ use std::sync::Arc;
 use uuid::Uuid;
 use bcrypt::{hash, verify, DEFAULT_COST};
 use crate::AppState;
+use crate::handlers::invoices::invoice_pdf_response;
+use crate::services::invoice_pdf;
+use crate::services::prepaid_blocks::BLOCK_REMAINING_SQL;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PortalLoginRequest {
@@ -82,6 +85,7 @@ pub struct PortalInvoice {
     pub due_date: chrono::NaiveDate,
     pub total: rust_decimal::Decimal,
     pub balance: rust_decimal::Decimal,
+    pub currency: String,
     pub status: String,
     pub pdf_url: Option<String>,
 }
@@ -112,6 +116,16 @@ pub struct PortalTimeEntry {
     pub created_at: chrono::DateTime<Utc>,
 }
 
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct PortalPrepaidBalance {
+    pub id: Uuid,
+    pub contract_name: String,
+    pub block_type: String,
+    pub quantity: rust_decimal::Decimal,
+    pub remaining: rust_decimal::Decimal,
+    pub expires_on: Option<chrono::NaiveDate>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GuestLoginRequest {
     pub token: String,
@@ -135,6 +149,7 @@ pub struct PortalDashboard {
     pub recent_tickets: Vec<PortalTicket>,
     pub recent_invoices: Vec<PortalInvoice>,
     pub recent_time_entries: Vec<PortalTimeEntry>,
+    pub prepaid_balances: Vec<PortalPrepaidBalance>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -164,6 +179,9 @@ pub fn portal_routes() -> Router<Arc<AppState>> {
         .route("/invoices", get(list_portal_invoices))
         .route("/invoices/:id", get(get_portal_invoice))
         .route("/invoices/:id/pdf", get(download_invoice_pdf))
+        .route("/invoices/:id/pay", post(super::payments::pay_portal_invoice))
+        .nest("/payment-methods", super::payments::portal_payment_method_routes())
+        .nest("/statements", super::statements::portal_statement_routes())
         
         // Assets
         .route("/assets", get(list_portal_assets))
@@ -171,6 +189,7 @@ pub fn portal_routes() -> Router<Arc<AppState>> {
         
         // Time Tracking
         .route("/time-entries", get(list_portal_time_entries))
+        .route("/prepaid-blocks", get(list_portal_prepaid_blocks))
         
         // Guest Access
         .route("/guest/login", post(guest_login))
@@ -180,6 +199,15 @@ pub fn portal_routes() -> Router<Arc<AppState>> {
         // Profile
         .route("/profile", get(get_portal_profile).put(update_portal_profile))
         .route("/profile/password", put(change_portal_password))
+        
+        // Messages
+        .nest("/messages", super::portal_messages::portal_message_routes())
+
+        // Quotes
+        .nest("/quotes", super::quotes::portal_quote_routes())
+
+        // Project change orders
+        .nest("/change-orders", super::project_budgets::portal_change_order_routes())
 }
 
 async fn portal_login(
@@ -414,7 +442,7 @@ async fn get_portal_dashboard(
     
     // Get recent invoices
     let recent_invoices = sqlx::query_as::<_, PortalInvoice>(
-        "SELECT id, number, date, due_date, total, balance, status, NULL as pdf_url
+        "SELECT id, number, date, due_date, total, balance, currency, status, NULL as pdf_url
          FROM invoices 
          WHERE client_id = $1 
          ORDER BY created_at DESC 
@@ -449,6 +477,8 @@ async fn get_portal_dashboard(
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
     
+    let prepaid_balances = fetch_prepaid_balances(&state, client_id).await?;
+    
     Ok(Json(PortalDashboard {
         open_tickets,
         pending_invoices,
@@ -459,9 +489,43 @@ async fn get_portal_dashboard(
         recent_tickets,
         recent_invoices,
         recent_time_entries,
+        prepaid_balances,
     }))
 }
 
+async fn list_portal_prepaid_blocks(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<PortalPrepaidBalance>>, StatusCode> {
+    let token = extract_portal_token(&headers)?;
+    let (_contact_id, client_id) = verify_token(&state, &token).await?;
+    
+    Ok(Json(fetch_prepaid_balances(&state, client_id).await?))
+}
+
+/// Active prepaid hour/dollar blocks with what is left on each, soonest expiry first.
+async fn fetch_prepaid_balances(
+    state: &AppState,
+    client_id: Uuid,
+) -> Result<Vec<PortalPrepaidBalance>, StatusCode> {
+    sqlx::query_as::<_, PortalPrepaidBalance>(&format!(
+        "SELECT b.id, ct.name as contract_name, b.block_type, b.quantity,
+         GREATEST({}, 0) as remaining, b.expires_on
+         FROM contract_prepaid_blocks b
+         JOIN contracts ct ON ct.id = b.contract_id
+         WHERE b.client_id = $1 AND b.status = 'active'
+         ORDER BY b.expires_on ASC NULLS LAST, b.purchased_on",
+        BLOCK_REMAINING_SQL
+    ))
+    .bind(client_id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching prepaid balances: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
 async fn list_portal_tickets(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
@@ -711,7 +775,7 @@ async fn list_portal_invoices(
     let (_contact_id, client_id) = verify_token(&state, &token).await?;
     
     let invoices = sqlx::query_as::<_, PortalInvoice>(
-        "SELECT id, number, date, due_date, total, balance, status, NULL as pdf_url
+        "SELECT id, number, date, due_date, total, balance, currency, status, NULL as pdf_url
          FROM invoices 
          WHERE client_id = $1
          ORDER BY created_at DESC"
@@ -736,7 +800,7 @@ async fn get_portal_invoice(
     let (_contact_id, client_id) = verify_token(&state, &token).await?;
     
     let invoice = sqlx::query_as::<_, PortalInvoice>(
-        "SELECT id, number, date, due_date, total, balance, status, NULL as pdf_url
+        "SELECT id, number, date, due_date, total, balance, currency, status, NULL as pdf_url
          FROM invoices 
          WHERE id = $1 AND client_id = $2"
     )
@@ -756,16 +820,26 @@ async fn get_portal_invoice(
 }
 
 async fn download_invoice_pdf(
-    State(_state): State<Arc<AppState>>,
+    State(state): State<Arc<AppState>>,
     headers: HeaderMap,
-    Path(_id): Path<Uuid>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    let _token = extract_portal_token(&headers)?;
-    
-    // TODO: Implement PDF generation
-    Ok(Json(serde_json::json!({
-        "message": "PDF download not yet implemented"
-    })))
+    Path(id): Path<Uuid>,
+) -> Result<([(header::HeaderName, String); 2], Vec<u8>), StatusCode> {
+    let token = extract_portal_token(&headers)?;
+    let (_contact_id, client_id) = verify_token(&state, &token).await?;
+
+    let (invoice, lines) = invoice_pdf::load(&state.db_pool, id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error loading invoice for PDF: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    // Drafts are not the client's to see until they are sent
+    if invoice.client_id != client_id || invoice.status == "draft" {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(invoice_pdf_response(&invoice, &lines))
 }
 
 async fn list_portal_assets(
@@ -1042,7 +1116,7 @@ async fn list_guest_time_entries(
 }
 
 // Helper functions
-fn extract_portal_token(headers: &HeaderMap) -> Result<String, StatusCode> {
+pub(crate) fn extract_portal_token(headers: &HeaderMap) -> Result<String, StatusCode> {
     headers
         .get("X-Portal-Token")
         .and_then(|v| v.to_str().ok())
@@ -1050,7 +1124,7 @@ fn extract_portal_token(headers: &HeaderMap) -> Result<String, StatusCode> {
         .ok_or(StatusCode::UNAUTHORIZED)
 }
 
-async fn verify_token(state: &Arc<AppState>, token: &str) -> Result<(Uuid, Uuid), StatusCode> {
+pub(crate) async fn verify_token(state: &Arc<AppState>, token: &str) -> Result<(Uuid, Uuid), StatusCode> {
     let result = sqlx::query!(
         "SELECT pat.contact_id, c.client_id 
          FROM portal_access_tokens pat
@@ -0,0 +1,1245 @@
+use axum::{
+    extract::{Multipart, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json},
+    routing::{get, post, put},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::handlers::portal::{extract_portal_token, verify_token};
+use crate::notifications::{enqueue_notification, QueuedNotification};
+use crate::AppState;
+
+// Contact side, nested under /api/v1/portal/messages
+pub fn portal_message_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_contact_conversations).post(create_conversation))
+        .route("/:id", get(get_contact_conversation))
+        .route("/:id/messages", post(send_contact_message))
+        .route("/:id/read", post(mark_read_by_contact))
+        .route("/:id/close", post(close_by_contact))
+        .route("/:id/attachments", post(upload_contact_attachment))
+        .route("/attachments/:id", get(download_contact_attachment))
+}
+
+// Staff inbox, nested under /api/v1/portal-inbox
+pub fn portal_inbox_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_inbox))
+        .route("/unread", get(get_inbox_counts))
+        .route("/:id", get(get_staff_conversation))
+        .route("/:id/messages", post(send_staff_message))
+        .route("/:id/read", post(mark_read_by_staff))
+        .route("/:id/assign", put(assign_conversation))
+        .route("/:id/status", put(update_status))
+        .route("/:id/convert", post(convert_to_ticket))
+        .route("/:id/attachments", post(upload_staff_attachment))
+        .route("/attachments/:id", get(download_staff_attachment))
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct PortalConversation {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub client_name: Option<String>,
+    pub contact_id: Uuid,
+    pub contact_name: Option<String>,
+    pub subject: String,
+    pub category: Option<String>,
+    pub priority: Option<String>,
+    pub status: Option<String>,
+    pub assigned_to: Option<Uuid>,
+    pub assigned_name: Option<String>,
+    pub ticket_id: Option<Uuid>,
+    pub last_message_at: Option<DateTime<Utc>>,
+    pub unread_count: i64,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PortalConversationMessage {
+    pub id: Uuid,
+    pub conversation_id: Uuid,
+    pub sender_type: String,
+    pub contact_id: Option<Uuid>,
+    pub user_id: Option<Uuid>,
+    pub sender_name: Option<String>,
+    pub message: String,
+    pub is_internal_note: bool,
+    pub attachments: Option<serde_json::Value>,
+    pub read_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PortalConversationDetail {
+    pub conversation: PortalConversation,
+    pub messages: Vec<PortalConversationMessage>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct PortalMessageAttachment {
+    pub id: Uuid,
+    pub conversation_id: Uuid,
+    pub message_id: Option<Uuid>,
+    pub original_filename: String,
+    pub mime_type: String,
+    pub file_size: i64,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConversationCreate {
+    pub subject: String,
+    pub message: String,
+    pub category: Option<String>,
+    pub priority: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PortalMessageCreate {
+    pub message: String,
+    pub is_internal_note: Option<bool>, // staff only
+    pub attachment_ids: Option<Vec<Uuid>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InboxQuery {
+    pub status: Option<String>,
+    pub client_id: Option<Uuid>,
+    pub assigned_to: Option<Uuid>,
+    pub unassigned: Option<bool>,
+    pub mine: Option<bool>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssignRequest {
+    pub assigned_to: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatusUpdate {
+    pub status: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConvertRequest {
+    pub priority: Option<String>,
+    pub assigned_to: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct PortalInboxCounts {
+    pub unread_conversations: i64,
+    pub unassigned: i64,
+    pub assigned_to_me: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConvertedConversation {
+    pub conversation_id: Uuid,
+    pub ticket_id: Uuid,
+    pub ticket_number: i32,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Participant {
+    Contact(Uuid),
+    Staff(Uuid),
+}
+
+#[derive(Debug, FromRow)]
+struct StoredAttachment {
+    original_filename: String,
+    mime_type: String,
+    file_size: i64,
+    file_path: String,
+}
+
+// Unread counts are per side: contacts see staff replies, staff see contact messages.
+fn conversation_select(viewer: Participant) -> String {
+    let unread = match viewer {
+        Participant::Contact(_) => {
+            "m.sender_type <> 'contact' AND COALESCE(m.is_internal_note, false) = false"
+        }
+        Participant::Staff(_) => "m.sender_type = 'contact'",
+    };
+
+    format!(
+        r#"
+        SELECT pc.id, pc.client_id, cl.name as client_name, pc.contact_id, ct.name as contact_name,
+               pc.subject, pc.category, pc.priority, pc.status, pc.assigned_to,
+               u.first_name || ' ' || u.last_name as assigned_name, pc.ticket_id,
+               pc.last_message_at,
+               (SELECT COUNT(*) FROM portal_messages m
+                WHERE m.conversation_id = pc.id AND m.read_at IS NULL AND {}) as unread_count,
+               pc.created_at, pc.updated_at
+        FROM portal_conversations pc
+        JOIN clients cl ON cl.id = pc.client_id
+        JOIN contacts ct ON ct.id = pc.contact_id
+        LEFT JOIN users u ON u.id = pc.assigned_to
+        "#,
+        unread
+    )
+}
+
+const MESSAGE_SELECT: &str = r#"
+    SELECT m.id, m.conversation_id, m.sender_type, m.contact_id, m.user_id,
+           COALESCE(ct.name, u.first_name || ' ' || u.last_name) as sender_name,
+           m.message, COALESCE(m.is_internal_note, false) as is_internal_note,
+           m.attachments, m.read_at, m.created_at
+    FROM portal_messages m
+    LEFT JOIN contacts ct ON ct.id = m.contact_id
+    LEFT JOIN users u ON u.id = m.user_id
+"#;
+
+async fn authenticate_contact(state: &Arc<AppState>, headers: &HeaderMap) -> Result<(Uuid, Uuid), StatusCode> {
+    let token = extract_portal_token(headers)?;
+    verify_token(state, &token).await
+}
+
+async fn load_conversation(
+    state: &Arc<AppState>,
+    id: Uuid,
+    viewer: Participant,
+) -> Result<PortalConversation, StatusCode> {
+    // Contacts only ever see their own conversations
+    let contact_id = match viewer {
+        Participant::Contact(contact_id) => Some(contact_id),
+        Participant::Staff(_) => None,
+    };
+    let sql = format!(
+        "{} WHERE pc.id = $1 AND ($2::UUID IS NULL OR pc.contact_id = $2)",
+        conversation_select(viewer)
+    );
+
+    sqlx::query_as::<_, PortalConversation>(&sql)
+        .bind(id)
+        .bind(contact_id)
+        .fetch_one(&state.db_pool)
+        .await
+        .map_err(|e| match e {
+        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+        _ => {
+            tracing::error!("Error fetching portal conversation: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })
+}
+
+async fn load_messages(
+    state: &Arc<AppState>,
+    conversation_id: Uuid,
+    include_internal: bool,
+) -> Result<Vec<PortalConversationMessage>, StatusCode> {
+    let mut sql = format!("{} WHERE m.conversation_id = $1", MESSAGE_SELECT);
+    if !include_internal {
+        sql.push_str(" AND COALESCE(m.is_internal_note, false) = false");
+    }
+    sql.push_str(" ORDER BY m.created_at ASC");
+
+    sqlx::query_as::<_, PortalConversationMessage>(&sql)
+        .bind(conversation_id)
+        .fetch_all(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching portal messages: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn insert_message(
+    state: &Arc<AppState>,
+    conversation: &PortalConversation,
+    sender: Participant,
+    message: &str,
+    is_internal_note: bool,
+    attachment_ids: &[Uuid],
+) -> Result<PortalConversationMessage, StatusCode> {
+    if message.trim().is_empty() && attachment_ids.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let (sender_type, contact_id, user_id) = match sender {
+        Participant::Contact(id) => ("contact", Some(id), None),
+        Participant::Staff(id) => ("staff", None, Some(id)),
+    };
+
+    let db_error = |e: sqlx::Error| {
+        tracing::error!("Error saving portal message: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let mut tx = state.db_pool.begin().await.map_err(db_error)?;
+
+    let message_id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO portal_messages (client_id, contact_id, conversation_id, subject, message,
+                                     message_type, priority, sender_type, user_id, is_internal_note, status)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, 'unread')
+        RETURNING id
+        "#,
+    )
+    .bind(conversation.client_id)
+    .bind(contact_id)
+    .bind(conversation.id)
+    .bind(&conversation.subject)
+    .bind(message)
+    .bind(&conversation.category)
+    .bind(&conversation.priority)
+    .bind(sender_type)
+    .bind(user_id)
+    .bind(is_internal_note)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    // Attach previously uploaded files that belong to this conversation
+    if !attachment_ids.is_empty() {
+        let attached = sqlx::query_as::<_, PortalMessageAttachment>(
+            r#"
+            UPDATE portal_message_attachments SET message_id = $1
+            WHERE id = ANY($2) AND conversation_id = $3 AND message_id IS NULL
+            RETURNING id, conversation_id, message_id, original_filename, mime_type, file_size, created_at
+            "#,
+        )
+        .bind(message_id)
+        .bind(attachment_ids)
+        .bind(conversation.id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+        sqlx::query("UPDATE portal_messages SET attachments = $2 WHERE id = $1")
+            .bind(message_id)
+            .bind(serde_json::to_value(&attached).unwrap_or_default())
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+    }
+
+    match sender {
+        Participant::Contact(_) => {
+            // A new contact message always puts the conversation back in the staff queue
+            sqlx::query(
+                "UPDATE portal_conversations SET status = 'open', closed_at = NULL,
+                 last_message_at = NOW(), updated_at = NOW()
+                 WHERE id = $1 AND status <> 'converted'",
+            )
+            .bind(conversation.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        }
+        Participant::Staff(user_id) if !is_internal_note => {
+            sqlx::query(
+                "UPDATE portal_conversations SET
+                 status = CASE WHEN status = 'open' THEN 'awaiting_client' ELSE status END,
+                 last_message_at = NOW(), updated_at = NOW()
+                 WHERE id = $1",
+            )
+            .bind(conversation.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+
+            sqlx::query(
+                "UPDATE portal_messages SET status = 'responded', responded_at = NOW(), responded_by = $2
+                 WHERE conversation_id = $1 AND sender_type = 'contact' AND responded_at IS NULL",
+            )
+            .bind(conversation.id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        }
+        Participant::Staff(_) => {}
+    }
+
+    tx.commit().await.map_err(db_error)?;
+
+    sqlx::query_as::<_, PortalConversationMessage>(&format!("{} WHERE m.id = $1", MESSAGE_SELECT))
+        .bind(message_id)
+        .fetch_one(&state.db_pool)
+        .await
+        .map_err(db_error)
+}
+
+// Live delivery to whoever is on the other side of the conversation
+async fn deliver_message(
+    state: &Arc<AppState>,
+    conversation: &PortalConversation,
+    message: &PortalConversationMessage,
+) {
+    let payload = serde_json::json!({
+        "conversation_id": conversation.id,
+        "subject": conversation.subject,
+        "message": message,
+    });
+
+    if message.sender_type == "contact" {
+        notify_staff(state, conversation, "portal_message", payload).await;
+        return;
+    }
+
+    if message.is_internal_note {
+        if let Some(assigned_to) = conversation.assigned_to {
+            if message.user_id != Some(assigned_to) {
+                state.notify_user(assigned_to, "portal_message", payload).await;
+            }
+        }
+        return;
+    }
+
+    state.notify_contact(conversation.contact_id, "portal_message", payload).await;
+
+    let notification = QueuedNotification::for_contact(
+        conversation.contact_id,
+        "portal_message_reply",
+        format!("New reply: {}", conversation.subject),
+        message_excerpt(&message.message, 200),
+    )
+    .with_entity("portal_conversation", conversation.id);
+
+    if let Err(e) = enqueue_notification(&state.db_pool, notification).await {
+        tracing::error!("Error queueing portal reply notification: {}", e);
+    }
+}
+
+// The assigned technician gets conversation events directly; unassigned ones go to the whole team.
+async fn notify_staff(
+    state: &Arc<AppState>,
+    conversation: &PortalConversation,
+    event_type: &str,
+    payload: serde_json::Value,
+) {
+    match conversation.assigned_to {
+        Some(user_id) => state.notify_user(user_id, event_type, payload).await,
+        None => state.broadcast_notification(event_type, payload).await,
+    }
+}
+
+async fn mark_read(
+    state: &Arc<AppState>,
+    conversation: &PortalConversation,
+    reader: Participant,
+) -> Result<Vec<Uuid>, StatusCode> {
+    let db_error = |e: sqlx::Error| {
+        tracing::error!("Error marking portal messages read: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let (message_ids, reader_json) = match reader {
+        Participant::Contact(contact_id) => {
+            let ids: Vec<Uuid> = sqlx::query_scalar(
+                "UPDATE portal_messages SET read_at = NOW(), status = 'read'
+                 WHERE conversation_id = $1 AND sender_type <> 'contact'
+                 AND COALESCE(is_internal_note, false) = false AND read_at IS NULL
+                 RETURNING id",
+            )
+            .bind(conversation.id)
+            .fetch_all(&state.db_pool)
+            .await
+            .map_err(db_error)?;
+
+            sqlx::query("UPDATE portal_conversations SET contact_last_read_at = NOW() WHERE id = $1")
+                .bind(conversation.id)
+                .execute(&state.db_pool)
+                .await
+                .map_err(db_error)?;
+
+            (ids, serde_json::json!({ "contact_id": contact_id }))
+        }
+        Participant::Staff(user_id) => {
+            let ids: Vec<Uuid> = sqlx::query_scalar(
+                "UPDATE portal_messages SET read_at = NOW(), read_by_user_id = $2,
+                 status = CASE WHEN status = 'unread' THEN 'read' ELSE status END
+                 WHERE conversation_id = $1 AND sender_type = 'contact' AND read_at IS NULL
+                 RETURNING id",
+            )
+            .bind(conversation.id)
+            .bind(user_id)
+            .fetch_all(&state.db_pool)
+            .await
+            .map_err(db_error)?;
+
+            sqlx::query("UPDATE portal_conversations SET staff_last_read_at = NOW() WHERE id = $1")
+                .bind(conversation.id)
+                .execute(&state.db_pool)
+                .await
+                .map_err(db_error)?;
+
+            (ids, serde_json::json!({ "user_id": user_id }))
+        }
+    };
+
+    if !message_ids.is_empty() {
+        let receipt = serde_json::json!({
+            "conversation_id": conversation.id,
+            "message_ids": message_ids,
+            "read_by": reader_json,
+            "read_at": Utc::now(),
+        });
+
+        match reader {
+            Participant::Contact(_) => notify_staff(state, conversation, "portal_message_read", receipt).await,
+            Participant::Staff(_) => {
+                state.notify_contact(conversation.contact_id, "portal_message_read", receipt).await
+            }
+        }
+    }
+
+    Ok(message_ids)
+}
+
+async fn store_attachment(
+    state: &Arc<AppState>,
+    conversation_id: Uuid,
+    uploader: Participant,
+    mut multipart: Multipart,
+) -> Result<PortalMessageAttachment, StatusCode> {
+    let mut file_data = Vec::new();
+    let mut original_filename = String::new();
+    let mut mime_type = "application/octet-stream".to_string();
+
+    while let Some(field) = multipart.next_field().await.map_err(|_| StatusCode::BAD_REQUEST)? {
+        if field.name() == Some("file") {
+            original_filename = field.file_name().unwrap_or("attachment").to_string();
+            if let Some(content_type) = field.content_type() {
+                mime_type = content_type.to_string();
+            }
+            file_data = field.bytes().await.map_err(|_| StatusCode::BAD_REQUEST)?.to_vec();
+        }
+    }
+
+    if file_data.is_empty() || original_filename.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let attachment_id = Uuid::new_v4();
+    let extension = std::path::Path::new(&original_filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("");
+    let filename = if extension.is_empty() {
+        attachment_id.to_string()
+    } else {
+        format!("{}.{}", attachment_id, extension)
+    };
+
+    let upload_dir = format!("{}/portal-messages", crate::files::get_upload_directory());
+    tokio::fs::create_dir_all(&upload_dir).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let file_path = format!("{}/{}", upload_dir, filename);
+    let mut file = tokio::fs::File::create(&file_path).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    file.write_all(&file_data).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let (contact_id, user_id) = match uploader {
+        Participant::Contact(id) => (Some(id), None),
+        Participant::Staff(id) => (None, Some(id)),
+    };
+
+    sqlx::query_as::<_, PortalMessageAttachment>(
+        r#"
+        INSERT INTO portal_message_attachments (id, conversation_id, original_filename, file_path, mime_type,
+                                                file_size, uploaded_by_contact_id, uploaded_by_user_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING id, conversation_id, message_id, original_filename, mime_type, file_size, created_at
+        "#,
+    )
+    .bind(attachment_id)
+    .bind(conversation_id)
+    .bind(&original_filename)
+    .bind(&file_path)
+    .bind(&mime_type)
+    .bind(file_data.len() as i64)
+    .bind(contact_id)
+    .bind(user_id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error saving portal attachment: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+async fn attachment_response(attachment: StoredAttachment) -> Result<impl IntoResponse, StatusCode> {
+    let content = tokio::fs::read(&attachment.file_path).await.map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        attachment.mime_type.parse().unwrap_or_else(|_| "application/octet-stream".parse().unwrap()),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        format!("attachment; filename=\"{}\"", attachment.original_filename.replace('"', ""))
+            .parse()
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+    );
+    headers.insert(header::CONTENT_LENGTH, attachment.file_size.to_string().parse().unwrap());
+
+    Ok((headers, content))
+}
+
+// Contact handlers
+
+async fn list_contact_conversations(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<PortalConversation>>, StatusCode> {
+    let (contact_id, _client_id) = authenticate_contact(&state, &headers).await?;
+    let viewer = Participant::Contact(contact_id);
+
+    let sql = format!(
+        "{} WHERE pc.contact_id = $1 ORDER BY pc.last_message_at DESC NULLS LAST",
+        conversation_select(viewer)
+    );
+
+    let conversations = sqlx::query_as::<_, PortalConversation>(&sql)
+        .bind(contact_id)
+        .fetch_all(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching portal conversations: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(conversations))
+}
+
+async fn create_conversation(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<ConversationCreate>,
+) -> Result<(StatusCode, Json<PortalConversationDetail>), StatusCode> {
+    let (contact_id, client_id) = authenticate_contact(&state, &headers).await?;
+
+    if payload.subject.trim().is_empty() || payload.message.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let conversation_id: Uuid = sqlx::query_scalar(
+        "INSERT INTO portal_conversations (client_id, contact_id, subject, category, priority)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING id",
+    )
+    .bind(client_id)
+    .bind(contact_id)
+    .bind(payload.subject.trim())
+    .bind(payload.category.unwrap_or_else(|| "general".to_string()))
+    .bind(payload.priority.unwrap_or_else(|| "normal".to_string()))
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error creating portal conversation: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let viewer = Participant::Contact(contact_id);
+    let conversation = load_conversation(&state, conversation_id, viewer).await?;
+    let message = insert_message(&state, &conversation, viewer, &payload.message, false, &[]).await?;
+
+    deliver_message(&state, &conversation, &message).await;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(PortalConversationDetail { conversation, messages: vec![message] }),
+    ))
+}
+
+async fn get_contact_conversation(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PortalConversationDetail>, StatusCode> {
+    let (contact_id, _client_id) = authenticate_contact(&state, &headers).await?;
+    let viewer = Participant::Contact(contact_id);
+
+    let conversation = load_conversation(&state, id, viewer).await?;
+    mark_read(&state, &conversation, viewer).await?;
+
+    let conversation = load_conversation(&state, id, viewer).await?;
+    let messages = load_messages(&state, id, false).await?;
+
+    Ok(Json(PortalConversationDetail { conversation, messages }))
+}
+
+async fn send_contact_message(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<PortalMessageCreate>,
+) -> Result<(StatusCode, Json<PortalConversationMessage>), StatusCode> {
+    let (contact_id, _client_id) = authenticate_contact(&state, &headers).await?;
+    let viewer = Participant::Contact(contact_id);
+
+    let conversation = load_conversation(&state, id, viewer).await?;
+    if conversation.status.as_deref() == Some("converted") {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let attachment_ids = payload.attachment_ids.unwrap_or_default();
+    let message = insert_message(&state, &conversation, viewer, &payload.message, false, &attachment_ids).await?;
+
+    deliver_message(&state, &conversation, &message).await;
+
+    Ok((StatusCode::CREATED, Json(message)))
+}
+
+async fn mark_read_by_contact(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let (contact_id, _client_id) = authenticate_contact(&state, &headers).await?;
+    let viewer = Participant::Contact(contact_id);
+
+    let conversation = load_conversation(&state, id, viewer).await?;
+    mark_read(&state, &conversation, viewer).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn close_by_contact(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let (contact_id, _client_id) = authenticate_contact(&state, &headers).await?;
+    let conversation = load_conversation(&state, id, Participant::Contact(contact_id)).await?;
+
+    sqlx::query(
+        "UPDATE portal_conversations SET status = 'closed', closed_at = NOW(), updated_at = NOW()
+         WHERE id = $1 AND status <> 'converted'",
+    )
+    .bind(id)
+    .execute(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error closing portal conversation: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    notify_staff(
+        &state,
+        &conversation,
+        "portal_conversation_updated",
+        serde_json::json!({ "conversation_id": id, "status": "closed" }),
+    )
+    .await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn upload_contact_attachment(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    multipart: Multipart,
+) -> Result<(StatusCode, Json<PortalMessageAttachment>), StatusCode> {
+    let (contact_id, _client_id) = authenticate_contact(&state, &headers).await?;
+    let uploader = Participant::Contact(contact_id);
+
+    let conversation = load_conversation(&state, id, uploader).await?;
+    let attachment = store_attachment(&state, conversation.id, uploader, multipart).await?;
+
+    Ok((StatusCode::CREATED, Json(attachment)))
+}
+
+async fn download_contact_attachment(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let (contact_id, _client_id) = authenticate_contact(&state, &headers).await?;
+
+    // Files on internal notes stay hidden; unsent uploads are only visible to their uploader
+    let attachment = sqlx::query_as::<_, StoredAttachment>(
+        r#"
+        SELECT a.original_filename, a.mime_type, a.file_size, a.file_path
+        FROM portal_message_attachments a
+        JOIN portal_conversations pc ON pc.id = a.conversation_id
+        LEFT JOIN portal_messages m ON m.id = a.message_id
+        WHERE a.id = $1 AND pc.contact_id = $2
+          AND ((m.id IS NOT NULL AND COALESCE(m.is_internal_note, false) = false)
+               OR a.uploaded_by_contact_id = $2)
+        "#,
+    )
+    .bind(id)
+    .bind(contact_id)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching portal attachment: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    attachment_response(attachment).await
+}
+
+// Staff handlers
+
+async fn list_inbox(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Query(params): Query<InboxQuery>,
+) -> Result<Json<Vec<PortalConversation>>, StatusCode> {
+    let assigned_to = if params.mine.unwrap_or(false) {
+        Some(auth.0.id)
+    } else {
+        params.assigned_to
+    };
+
+    let sql = format!(
+        r#"{}
+        WHERE ($1::TEXT IS NULL OR pc.status = $1)
+          AND ($2::UUID IS NULL OR pc.client_id = $2)
+          AND ($3::UUID IS NULL OR pc.assigned_to = $3)
+          AND ($4 = false OR pc.assigned_to IS NULL)
+        ORDER BY pc.last_message_at DESC NULLS LAST
+        LIMIT $5 OFFSET $6
+        "#,
+        conversation_select(Participant::Staff(auth.0.id))
+    );
+
+    let conversations = sqlx::query_as::<_, PortalConversation>(&sql)
+        .bind(params.status)
+        .bind(params.client_id)
+        .bind(assigned_to)
+        .bind(params.unassigned.unwrap_or(false))
+        .bind(params.limit.unwrap_or(50).clamp(1, 200))
+        .bind(params.offset.unwrap_or(0).max(0))
+        .fetch_all(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching portal inbox: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(conversations))
+}
+
+async fn get_inbox_counts(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+) -> Result<Json<PortalInboxCounts>, StatusCode> {
+    let counts = sqlx::query_as::<_, PortalInboxCounts>(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE EXISTS (
+                SELECT 1 FROM portal_messages m
+                WHERE m.conversation_id = pc.id AND m.sender_type = 'contact' AND m.read_at IS NULL
+            )) as unread_conversations,
+            COUNT(*) FILTER (WHERE pc.assigned_to IS NULL) as unassigned,
+            COUNT(*) FILTER (WHERE pc.assigned_to = $1) as assigned_to_me
+        FROM portal_conversations pc
+        WHERE pc.status IN ('open', 'awaiting_client')
+        "#,
+    )
+    .bind(auth.0.id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching portal inbox counts: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(counts))
+}
+
+async fn get_staff_conversation(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PortalConversationDetail>, StatusCode> {
+    let viewer = Participant::Staff(auth.0.id);
+
+    let conversation = load_conversation(&state, id, viewer).await?;
+    mark_read(&state, &conversation, viewer).await?;
+
+    let conversation = load_conversation(&state, id, viewer).await?;
+    let messages = load_messages(&state, id, true).await?;
+
+    Ok(Json(PortalConversationDetail { conversation, messages }))
+}
+
+async fn send_staff_message(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<PortalMessageCreate>,
+) -> Result<(StatusCode, Json<PortalConversationMessage>), StatusCode> {
+    let sender = Participant::Staff(auth.0.id);
+    let conversation = load_conversation(&state, id, sender).await?;
+
+    let is_internal_note = payload.is_internal_note.unwrap_or(false);
+    let attachment_ids = payload.attachment_ids.unwrap_or_default();
+    let message = insert_message(&state, &conversation, sender, &payload.message, is_internal_note, &attachment_ids).await?;
+
+    deliver_message(&state, &conversation, &message).await;
+
+    Ok((StatusCode::CREATED, Json(message)))
+}
+
+async fn mark_read_by_staff(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let viewer = Participant::Staff(auth.0.id);
+
+    let conversation = load_conversation(&state, id, viewer).await?;
+    mark_read(&state, &conversation, viewer).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn assign_conversation(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<AssignRequest>,
+) -> Result<Json<PortalConversation>, StatusCode> {
+    let viewer = Participant::Staff(auth.0.id);
+
+    let result = sqlx::query(
+        "UPDATE portal_conversations SET assigned_to = $2,
+         assigned_at = CASE WHEN $2::UUID IS NULL THEN NULL ELSE NOW() END, updated_at = NOW()
+         WHERE id = $1",
+    )
+    .bind(id)
+    .bind(payload.assigned_to)
+    .execute(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error assigning portal conversation: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let conversation = load_conversation(&state, id, viewer).await?;
+
+    if let Some(assignee) = payload.assigned_to.filter(|a| *a != auth.0.id) {
+        let notification = QueuedNotification::for_user(
+            assignee,
+            "portal_conversation_assigned",
+            format!("Portal conversation assigned: {}", conversation.subject),
+            format!(
+                "{} from {} has been assigned to you.",
+                conversation.contact_name.as_deref().unwrap_or("A contact"),
+                conversation.client_name.as_deref().unwrap_or("a client"),
+            ),
+        )
+        .with_entity("portal_conversation", conversation.id);
+
+        if let Err(e) = enqueue_notification(&state.db_pool, notification).await {
+            tracing::error!("Error queueing portal assignment notification: {}", e);
+        }
+    }
+
+    state
+        .broadcast_notification(
+            "portal_conversation_updated",
+            serde_json::json!({ "conversation_id": id, "assigned_to": payload.assigned_to }),
+        )
+        .await;
+
+    Ok(Json(conversation))
+}
+
+async fn update_status(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<StatusUpdate>,
+) -> Result<Json<PortalConversation>, StatusCode> {
+    // Conversion has its own endpoint so a ticket is always created
+    if !matches!(payload.status.as_str(), "open" | "awaiting_client" | "closed") {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let result = sqlx::query(
+        "UPDATE portal_conversations SET status = $2,
+         closed_at = CASE WHEN $2 = 'closed' THEN NOW() ELSE NULL END, updated_at = NOW()
+         WHERE id = $1 AND status <> 'converted'",
+    )
+    .bind(id)
+    .bind(&payload.status)
+    .execute(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error updating portal conversation status: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let conversation = load_conversation(&state, id, Participant::Staff(auth.0.id)).await?;
+
+    state
+        .notify_contact(
+            conversation.contact_id,
+            "portal_conversation_updated",
+            serde_json::json!({ "conversation_id": id, "status": payload.status }),
+        )
+        .await;
+
+    Ok(Json(conversation))
+}
+
+async fn convert_to_ticket(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<ConvertRequest>,
+) -> Result<(StatusCode, Json<ConvertedConversation>), StatusCode> {
+    let viewer = Participant::Staff(auth.0.id);
+    let conversation = load_conversation(&state, id, viewer).await?;
+
+    if conversation.ticket_id.is_some() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let messages = load_messages(&state, id, false).await?;
+    let details = conversation_transcript(&messages);
+    let priority = payload.priority.unwrap_or_else(|| ticket_priority(conversation.priority.as_deref()).to_string());
+    let assigned_to = payload.assigned_to.or(conversation.assigned_to);
+
+    let db_error = |e: sqlx::Error| {
+        tracing::error!("Error converting portal conversation: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let mut tx = state.db_pool.begin().await.map_err(db_error)?;
+
+    let ticket_number: i32 = sqlx::query_scalar("SELECT COALESCE(MAX(number), 0) + 1 FROM tickets")
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+    let ticket_id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO tickets (number, client_id, contact_id, assigned_to, opened_by, subject, details,
+                             status, priority, source)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, 'open', $8, 'portal')
+        RETURNING id
+        "#,
+    )
+    .bind(ticket_number)
+    .bind(conversation.client_id)
+    .bind(conversation.contact_id)
+    .bind(assigned_to)
+    .bind(auth.0.id)
+    .bind(&conversation.subject)
+    .bind(&details)
+    .bind(&priority)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    // Carry shared attachments over to the ticket's files
+    sqlx::query(
+        r#"
+        INSERT INTO files (client_id, ticket_id, filename, original_filename, mime_type, file_size,
+                           file_path, uploaded_by)
+        SELECT $2, $3, regexp_replace(a.file_path, '^.*/', ''), a.original_filename, a.mime_type,
+               a.file_size, a.file_path, $4
+        FROM portal_message_attachments a
+        JOIN portal_messages m ON m.id = a.message_id
+        WHERE a.conversation_id = $1 AND COALESCE(m.is_internal_note, false) = false
+        "#,
+    )
+    .bind(id)
+    .bind(conversation.client_id)
+    .bind(ticket_id)
+    .bind(auth.0.id)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    sqlx::query(
+        "UPDATE portal_conversations SET status = 'converted', ticket_id = $2, converted_at = NOW(),
+         converted_by = $3, updated_at = NOW(), last_message_at = NOW()
+         WHERE id = $1",
+    )
+    .bind(id)
+    .bind(ticket_id)
+    .bind(auth.0.id)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    sqlx::query(
+        "INSERT INTO portal_messages (client_id, conversation_id, ticket_id, subject, message,
+                                      sender_type, user_id, status)
+         VALUES ($1, $2, $3, $4, $5, 'system', $6, 'unread')",
+    )
+    .bind(conversation.client_id)
+    .bind(id)
+    .bind(ticket_id)
+    .bind(&conversation.subject)
+    .bind(format!("This conversation has been converted to ticket #{}.", ticket_number))
+    .bind(auth.0.id)
+    .execute(&mut *tx)
+    .await
+    .map_err(db_error)?;
+
+    tx.commit().await.map_err(db_error)?;
+
+    let converted = ConvertedConversation { conversation_id: id, ticket_id, ticket_number };
+
+    state
+        .notify_contact(
+            conversation.contact_id,
+            "portal_conversation_converted",
+            serde_json::to_value(&converted).unwrap_or_default(),
+        )
+        .await;
+    state
+        .broadcast_notification(
+            "portal_conversation_converted",
+            serde_json::to_value(&converted).unwrap_or_default(),
+        )
+        .await;
+
+    Ok((StatusCode::CREATED, Json(converted)))
+}
+
+async fn upload_staff_attachment(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    multipart: Multipart,
+) -> Result<(StatusCode, Json<PortalMessageAttachment>), StatusCode> {
+    let uploader = Participant::Staff(auth.0.id);
+
+    let conversation = load_conversation(&state, id, uploader).await?;
+    let attachment = store_attachment(&state, conversation.id, uploader, multipart).await?;
+
+    Ok((StatusCode::CREATED, Json(attachment)))
+}
+
+async fn download_staff_attachment(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let attachment = sqlx::query_as::<_, StoredAttachment>(
+        "SELECT original_filename, mime_type, file_size, file_path
+         FROM portal_message_attachments WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching portal attachment: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    attachment_response(attachment).await
+}
+
+// Portal conversations use normal/high; tickets use the low..critical scale.
+fn ticket_priority(conversation_priority: Option<&str>) -> &'static str {
+    match conversation_priority {
+        Some("low") => "low",
+        Some("high") => "high",
+        Some("urgent") => "critical",
+        _ => "medium",
+    }
+}
+
+pub fn message_excerpt(message: &str, max_chars: usize) -> String {
+    let trimmed = message.trim();
+    if trimmed.chars().count() <= max_chars {
+        return trimmed.to_string();
+    }
+    let mut excerpt: String = trimmed.chars().take(max_chars).collect();
+    excerpt.push('…');
+    excerpt
+}
+
+// Plain-text history used as the details of a converted ticket
+pub fn conversation_transcript(messages: &[PortalConversationMessage]) -> String {
+    messages
+        .iter()
+        .filter(|m| !m.is_internal_note && m.sender_type != "system")
+        .map(|m| {
+            let when = m
+                .created_at
+                .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+                .unwrap_or_default();
+            let who = m.sender_name.as_deref().unwrap_or(if m.sender_type == "contact" { "Client" } else { "Staff" });
+            format!("[{}] {}:\n{}", when, who, m.message.trim())
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn message(sender_type: &str, name: &str, text: &str, internal: bool) -> PortalConversationMessage {
+        PortalConversationMessage {
+            id: Uuid::new_v4(),
+            conversation_id: Uuid::nil(),
+            sender_type: sender_type.to_string(),
+            contact_id: None,
+            user_id: None,
+            sender_name: Some(name.to_string()),
+            message: text.to_string(),
+            is_internal_note: internal,
+            attachments: None,
+            read_at: None,
+            created_at: Some(Utc.with_ymd_and_hms(2024, 3, 1, 9, 30, 0).unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_transcript_skips_internal_and_system_messages() {
+        let messages = vec![
+            message("contact", "Jane Doe", "Can we add a user? ", false),
+            message("staff", "Sam Tech", "Check licensing first", true),
+            message("staff", "Sam Tech", "Sure, which name?", false),
+            message("system", "Sam Tech", "Converted", false),
+        ];
+
+        assert_eq!(
+            conversation_transcript(&messages),
+            "[2024-03-01 09:30 UTC] Jane Doe:\nCan we add a user?\n\n[2024-03-01 09:30 UTC] Sam Tech:\nSure, which name?"
+        );
+    }
+
+    #[test]
+    fn test_message_excerpt() {
+        assert_eq!(message_excerpt("  short  ", 10), "short");
+        assert_eq!(message_excerpt("abcdefghij", 4), "abcd…");
+    }
+
+    #[test]
+    fn test_ticket_priority_mapping() {
+        assert_eq!(ticket_priority(Some("normal")), "medium");
+        assert_eq!(ticket_priority(Some("high")), "high");
+        assert_eq!(ticket_priority(None), "medium");
+    }
+}
@@ -0,0 +1,572 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use chrono::Utc;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::handlers::{ensure_client_contact, internal};
+use crate::handlers::portal::{extract_portal_token, verify_token};
+use crate::notifications::{enqueue_notification, QueuedNotification};
+use crate::services::currency::{self, format_money};
+use crate::services::project_budgets::{
+    self, ChangeOrder, ProjectBudget, BILLING_TYPES, CHANGE_ORDER_COLUMNS,
+};
+use crate::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct BudgetUpdate {
+    pub billing_type: String,
+    pub budget: Option<Decimal>,
+    pub budget_hours: Option<Decimal>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangeOrderInput {
+    pub title: String,
+    pub description: Option<String>,
+    pub amount_change: Option<Decimal>,
+    pub hours_change: Option<Decimal>,
+    pub schedule_days_change: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangeOrderQuery {
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SendChangeOrder {
+    pub contact_id: Option<Uuid>,
+    pub message: Option<String>,
+}
+
+/// Approval recorded by staff, e.g. given by phone or email.
+#[derive(Debug, Deserialize)]
+pub struct RecordApproval {
+    pub approved_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApproveChangeOrder {
+    pub signed_name: String,
+    pub accept_terms: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RejectChangeOrder {
+    pub reason: Option<String>,
+}
+
+/// Merged into the project routes, under /api/v1/projects.
+pub fn project_budget_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/:id/budget", get(get_project_budget).put(update_project_budget))
+        .route("/:id/change-orders", get(list_change_orders).post(create_change_order))
+        .route("/change-orders/:change_order_id", get(get_change_order).put(update_change_order))
+        .route("/change-orders/:change_order_id/send", post(send_change_order))
+        .route("/change-orders/:change_order_id/approve", post(record_approval))
+        .route("/change-orders/:change_order_id/cancel", post(cancel_change_order))
+}
+
+/// Client portal routes, nested under /api/v1/portal/change-orders.
+pub fn portal_change_order_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_portal_change_orders))
+        .route("/:id", get(get_portal_change_order))
+        .route("/:id/approve", post(approve_change_order))
+        .route("/:id/reject", post(reject_change_order))
+}
+
+async fn get_project_budget(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ProjectBudget>, StatusCode> {
+    let budget = project_budgets::project_budget(&state.db_pool, id, Utc::now().date_naive())
+        .await
+        .map_err(internal("fetching project budget"))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(budget))
+}
+
+async fn update_project_budget(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<BudgetUpdate>,
+) -> Result<Json<ProjectBudget>, StatusCode> {
+    let negative = |value: Option<Decimal>| value.is_some_and(|v| v < Decimal::ZERO);
+    if !BILLING_TYPES.contains(&payload.billing_type.as_str()) || negative(payload.budget) || negative(payload.budget_hours) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let result = sqlx::query(
+        "UPDATE projects SET billing_type = $2, budget = $3, budget_hours = $4, updated_at = NOW() WHERE id = $1",
+    )
+    .bind(id)
+    .bind(&payload.billing_type)
+    .bind(payload.budget)
+    .bind(payload.budget_hours)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("updating project budget"))?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    project_budgets::rebaseline_alerts(&state.db_pool, id)
+        .await
+        .map_err(internal("rebaselining budget alerts"))?;
+
+    get_project_budget(State(state), Path(id)).await
+}
+
+async fn list_change_orders(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<ChangeOrderQuery>,
+) -> Result<Json<Vec<ChangeOrder>>, StatusCode> {
+    let change_orders = sqlx::query_as::<_, ChangeOrder>(&format!(
+        "SELECT {} FROM project_change_orders co JOIN projects p ON p.id = co.project_id
+         WHERE co.project_id = $1 AND ($2::text IS NULL OR co.status = $2)
+         ORDER BY co.number",
+        CHANGE_ORDER_COLUMNS
+    ))
+    .bind(id)
+    .bind(&params.status)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("listing change orders"))?;
+    Ok(Json(change_orders))
+}
+
+async fn create_change_order(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<ChangeOrderInput>,
+) -> Result<(StatusCode, Json<ChangeOrder>), StatusCode> {
+    if payload.title.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut tx = state.db_pool.begin().await.map_err(internal("starting transaction"))?;
+    // Serialises numbering per project
+    sqlx::query_scalar::<_, Uuid>("SELECT id FROM projects WHERE id = $1 FOR UPDATE")
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(internal("locking project"))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let change_order_id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO project_change_orders
+            (project_id, number, title, description, amount_change, hours_change, schedule_days_change, created_by)
+        VALUES ($1, (SELECT COALESCE(MAX(number), 0) + 1 FROM project_change_orders WHERE project_id = $1),
+                $2, $3, $4, $5, $6, $7)
+        RETURNING id
+        "#,
+    )
+    .bind(id)
+    .bind(payload.title.trim())
+    .bind(&payload.description)
+    .bind(payload.amount_change.unwrap_or_default())
+    .bind(payload.hours_change.unwrap_or_default())
+    .bind(payload.schedule_days_change.unwrap_or(0))
+    .bind(auth.0.id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal("creating change order"))?;
+    tx.commit().await.map_err(internal("committing change order"))?;
+
+    Ok((StatusCode::CREATED, Json(load_change_order(&state, change_order_id).await?)))
+}
+
+async fn get_change_order(
+    State(state): State<Arc<AppState>>,
+    Path(change_order_id): Path<Uuid>,
+) -> Result<Json<ChangeOrder>, StatusCode> {
+    Ok(Json(load_change_order(&state, change_order_id).await?))
+}
+
+/// Only drafts can be edited; a sent change order is withdrawn by cancelling it.
+async fn update_change_order(
+    State(state): State<Arc<AppState>>,
+    Path(change_order_id): Path<Uuid>,
+    Json(payload): Json<ChangeOrderInput>,
+) -> Result<Json<ChangeOrder>, StatusCode> {
+    if payload.title.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    load_change_order(&state, change_order_id).await?;
+
+    let result = sqlx::query(
+        "UPDATE project_change_orders SET title = $2, description = $3, amount_change = $4, hours_change = $5,
+         schedule_days_change = $6, updated_at = NOW()
+         WHERE id = $1 AND status = 'draft'",
+    )
+    .bind(change_order_id)
+    .bind(payload.title.trim())
+    .bind(&payload.description)
+    .bind(payload.amount_change.unwrap_or_default())
+    .bind(payload.hours_change.unwrap_or_default())
+    .bind(payload.schedule_days_change.unwrap_or(0))
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("updating change order"))?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    Ok(Json(load_change_order(&state, change_order_id).await?))
+}
+
+/// Emails a client contact a link to approve the change order in the portal.
+async fn send_change_order(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(change_order_id): Path<Uuid>,
+    Json(payload): Json<SendChangeOrder>,
+) -> Result<Json<ChangeOrder>, StatusCode> {
+    let existing = load_change_order(&state, change_order_id).await?;
+    if !matches!(existing.status.as_str(), "draft" | "sent") {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let contact_id = match payload.contact_id.or(existing.contact_id) {
+        Some(contact_id) => {
+            ensure_client_contact(&state.db_pool, existing.client_id, contact_id).await?;
+            Some(contact_id)
+        }
+        None => sqlx::query_scalar(
+            "SELECT id FROM contacts WHERE client_id = $1 AND archived_at IS NULL AND email IS NOT NULL
+             ORDER BY is_primary DESC NULLS LAST, created_at LIMIT 1",
+        )
+        .bind(existing.client_id)
+        .fetch_optional(&state.db_pool)
+        .await
+        .map_err(internal("looking up change order contact"))?,
+    };
+    let contact_id = contact_id.ok_or(StatusCode::BAD_REQUEST)?;
+
+    sqlx::query(
+        "UPDATE project_change_orders SET status = 'sent', sent_at = NOW(), contact_id = $2, updated_at = NOW()
+         WHERE id = $1",
+    )
+    .bind(change_order_id)
+    .bind(contact_id)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("marking change order sent"))?;
+
+    let change_order = load_change_order(&state, change_order_id).await?;
+    let currency = client_currency(&state, change_order.client_id).await?;
+    let portal_url = portal_change_order_url(change_order.id);
+    let mut message = format!(
+        "Change order {} for {}: {}. {}",
+        change_order.reference(),
+        change_order.project_name,
+        change_order.title,
+        describe_changes(&change_order, &currency)
+    );
+    message.push_str(&format!(" Review and approve it in your client portal: {}", portal_url));
+    if let Some(note) = payload.message.filter(|m| !m.trim().is_empty()) {
+        message = format!("{}\n\n{}", note.trim(), message);
+    }
+
+    let notification = QueuedNotification::for_contact(
+        contact_id,
+        "change_order_sent",
+        format!("Change order {}: {}", change_order.reference(), change_order.title),
+        message,
+    )
+    .with_entity("project_change_order", change_order.id)
+    .with_variables(serde_json::json!({
+        "reference": change_order.reference(),
+        "project_name": change_order.project_name,
+        "title": change_order.title,
+        "amount_change": change_order.amount_change,
+        "hours_change": change_order.hours_change,
+        "schedule_days_change": change_order.schedule_days_change,
+        "currency": currency,
+        "portal_url": portal_url,
+    }));
+    if let Err(e) = enqueue_notification(&state.db_pool, notification).await {
+        tracing::warn!("Failed to queue change order {}: {}", change_order.id, e);
+    }
+
+    Ok(Json(change_order))
+}
+
+async fn record_approval(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(change_order_id): Path<Uuid>,
+    Json(payload): Json<RecordApproval>,
+) -> Result<Json<ChangeOrder>, StatusCode> {
+    let approved_name = payload.approved_name.trim();
+    if approved_name.is_empty() || approved_name.len() > 255 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    load_change_order(&state, change_order_id).await?;
+
+    let result = sqlx::query(
+        "UPDATE project_change_orders SET status = 'approved', approved_at = NOW(), approved_name = $2,
+         approved_by_user_id = $3, updated_at = NOW()
+         WHERE id = $1 AND status IN ('draft', 'sent')",
+    )
+    .bind(change_order_id)
+    .bind(approved_name)
+    .bind(auth.0.id)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("approving change order"))?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let change_order = load_change_order(&state, change_order_id).await?;
+    apply_change_order(&state, &change_order).await?;
+    Ok(Json(change_order))
+}
+
+async fn cancel_change_order(
+    State(state): State<Arc<AppState>>,
+    Path(change_order_id): Path<Uuid>,
+) -> Result<Json<ChangeOrder>, StatusCode> {
+    load_change_order(&state, change_order_id).await?;
+    let result = sqlx::query(
+        "UPDATE project_change_orders SET status = 'cancelled', updated_at = NOW()
+         WHERE id = $1 AND status IN ('draft', 'sent')",
+    )
+    .bind(change_order_id)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("cancelling change order"))?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(Json(load_change_order(&state, change_order_id).await?))
+}
+
+async fn list_portal_change_orders(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<ChangeOrder>>, StatusCode> {
+    let (_contact_id, client_id) = portal_client(&state, &headers).await?;
+    let change_orders = sqlx::query_as::<_, ChangeOrder>(&format!(
+        "SELECT {} FROM project_change_orders co JOIN projects p ON p.id = co.project_id
+         WHERE p.client_id = $1 AND co.status IN ('sent', 'approved', 'rejected')
+         ORDER BY co.sent_at DESC NULLS LAST",
+        CHANGE_ORDER_COLUMNS
+    ))
+    .bind(client_id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching portal change orders"))?;
+    Ok(Json(change_orders))
+}
+
+async fn get_portal_change_order(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ChangeOrder>, StatusCode> {
+    let (_contact_id, client_id) = portal_client(&state, &headers).await?;
+    Ok(Json(load_portal_change_order(&state, id, client_id).await?))
+}
+
+/// Approves a change order with a typed-name signature; the project's budget
+/// moves by the change order's amounts from then on.
+async fn approve_change_order(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<ApproveChangeOrder>,
+) -> Result<Json<ChangeOrder>, StatusCode> {
+    let (contact_id, client_id) = portal_client(&state, &headers).await?;
+    let signed_name = payload.signed_name.trim();
+    if !payload.accept_terms || signed_name.is_empty() || signed_name.len() > 255 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    load_portal_change_order(&state, id, client_id).await?;
+
+    let ip = headers
+        .get("x-forwarded-for")
+        .or_else(|| headers.get("x-real-ip"))
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(',').next())
+        .map(|v| v.trim().to_string());
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+
+    let result = sqlx::query(
+        r#"
+        UPDATE project_change_orders SET status = 'approved', approved_at = NOW(), approved_name = $2,
+                                         approved_by_contact_id = $3, approved_ip = $4, approved_user_agent = $5,
+                                         updated_at = NOW()
+        WHERE id = $1 AND status = 'sent'
+        "#,
+    )
+    .bind(id)
+    .bind(signed_name)
+    .bind(contact_id)
+    .bind(ip)
+    .bind(user_agent)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("approving change order"))?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let change_order = load_portal_change_order(&state, id, client_id).await?;
+    apply_change_order(&state, &change_order).await?;
+    Ok(Json(change_order))
+}
+
+async fn reject_change_order(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<RejectChangeOrder>,
+) -> Result<Json<ChangeOrder>, StatusCode> {
+    let (_contact_id, client_id) = portal_client(&state, &headers).await?;
+    load_portal_change_order(&state, id, client_id).await?;
+
+    let result = sqlx::query(
+        "UPDATE project_change_orders SET status = 'rejected', rejected_at = NOW(), rejection_reason = $2,
+         updated_at = NOW()
+         WHERE id = $1 AND status = 'sent'",
+    )
+    .bind(id)
+    .bind(&payload.reason)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("rejecting change order"))?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let change_order = load_portal_change_order(&state, id, client_id).await?;
+    let notification = QueuedNotification::for_user(
+        change_order.created_by,
+        "change_order_rejected",
+        format!("Change order {} on {} declined", change_order.reference(), change_order.project_name),
+        match payload.reason.as_deref().filter(|r| !r.trim().is_empty()) {
+            Some(reason) => format!("{} was declined: {}", change_order.title, reason),
+            None => format!("{} was declined.", change_order.title),
+        },
+    )
+    .with_entity("project_change_order", change_order.id);
+    if let Err(e) = enqueue_notification(&state.db_pool, notification).await {
+        tracing::warn!("Failed to queue rejection notice for change order {}: {}", change_order.id, e);
+    }
+
+    Ok(Json(change_order))
+}
+
+/// Re-arms budget alerts against the new budget, pushes the project end
+/// date out by any schedule change, and lets the author know.
+async fn apply_change_order(state: &AppState, change_order: &ChangeOrder) -> Result<(), StatusCode> {
+    if change_order.schedule_days_change != 0 {
+        sqlx::query(
+            "UPDATE projects SET end_date = end_date + $2, updated_at = NOW() WHERE id = $1 AND end_date IS NOT NULL",
+        )
+        .bind(change_order.project_id)
+        .bind(change_order.schedule_days_change)
+        .execute(&state.db_pool)
+        .await
+        .map_err(internal("moving project end date"))?;
+    }
+    project_budgets::rebaseline_alerts(&state.db_pool, change_order.project_id)
+        .await
+        .map_err(internal("rebaselining budget alerts"))?;
+
+    let currency = client_currency(state, change_order.client_id).await?;
+    let notification = QueuedNotification::for_user(
+        change_order.created_by,
+        "change_order_approved",
+        format!("Change order {} on {} approved", change_order.reference(), change_order.project_name),
+        format!(
+            "{} approved {}. {}",
+            change_order.approved_name.as_deref().unwrap_or("The client"),
+            change_order.title,
+            describe_changes(change_order, &currency)
+        ),
+    )
+    .with_priority("high")
+    .with_entity("project_change_order", change_order.id);
+    if let Err(e) = enqueue_notification(&state.db_pool, notification).await {
+        tracing::warn!("Failed to queue approval notice for change order {}: {}", change_order.id, e);
+    }
+    Ok(())
+}
+
+fn describe_changes(change_order: &ChangeOrder, currency: &str) -> String {
+    let signed = |value: String, negative: bool| if negative { value } else { format!("+{}", value) };
+    let mut changes = Vec::new();
+    if !change_order.amount_change.is_zero() {
+        changes.push(format!(
+            "budget {}",
+            signed(format_money(change_order.amount_change, currency), change_order.amount_change < Decimal::ZERO)
+        ));
+    }
+    if !change_order.hours_change.is_zero() {
+        changes.push(format!(
+            "{} hours",
+            signed(change_order.hours_change.normalize().to_string(), change_order.hours_change < Decimal::ZERO)
+        ));
+    }
+    if change_order.schedule_days_change != 0 {
+        changes.push(format!(
+            "{} days to the schedule",
+            signed(change_order.schedule_days_change.to_string(), change_order.schedule_days_change < 0)
+        ));
+    }
+    if changes.is_empty() {
+        return "No change to budget or schedule.".to_string();
+    }
+    format!("Changes: {}.", changes.join(", "))
+}
+
+async fn load_change_order(state: &AppState, id: Uuid) -> Result<ChangeOrder, StatusCode> {
+    project_budgets::get_change_order(&state.db_pool, id)
+        .await
+        .map_err(internal("fetching change order"))?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Change orders are only visible to the client once they've been sent.
+async fn load_portal_change_order(state: &AppState, id: Uuid, client_id: Uuid) -> Result<ChangeOrder, StatusCode> {
+    let change_order = load_change_order(state, id).await?;
+    let visible = matches!(change_order.status.as_str(), "sent" | "approved" | "rejected");
+    if change_order.client_id != client_id || !visible {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(change_order)
+}
+
+async fn portal_client(state: &Arc<AppState>, headers: &HeaderMap) -> Result<(Uuid, Uuid), StatusCode> {
+    let token = extract_portal_token(headers)?;
+    verify_token(state, &token).await
+}
+
+async fn client_currency(state: &AppState, client_id: Uuid) -> Result<String, StatusCode> {
+    let mut conn = state.db_pool.acquire().await.map_err(internal("acquiring connection"))?;
+    currency::client_currency(&mut conn, client_id)
+        .await
+        .map_err(internal("looking up client currency"))
+}
+
+fn portal_change_order_url(change_order_id: Uuid) -> String {
+    format!("{}/portal/change-orders/{}", crate::config::app_base_url(), change_order_id)
+}
@@ -0,0 +1,497 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+    routing::{delete, get, post, put},
+    Router,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::handlers::internal;
+use crate::handlers::projects::{insert_project, ProjectCreate};
+use crate::services::project_schedule::{
+    self, PlanDependency, ScheduledTask, TemplateTask, TEMPLATE_TASK_COLUMNS,
+};
+use crate::AppState;
+
+const TEMPLATE_COLUMNS: &str = "t.id, t.name, t.description, t.category, t.estimated_hours, t.default_hourly_rate,
+    COALESCE(t.is_active, true) as is_active, t.created_by, t.created_at, t.updated_at,
+    (SELECT COUNT(*) FROM project_template_tasks tt WHERE tt.template_id = t.id) as task_count";
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ProjectTemplate {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    pub estimated_hours: Option<Decimal>,
+    pub default_hourly_rate: Option<Decimal>,
+    pub is_active: bool,
+    pub created_by: Uuid,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub task_count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TemplateDetail {
+    #[serde(flatten)]
+    pub template: ProjectTemplate,
+    pub tasks: Vec<TemplateTask>,
+    pub dependencies: Vec<PlanDependency>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TemplateQuery {
+    pub category: Option<String>,
+    pub include_inactive: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TemplateInput {
+    pub name: String,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    pub default_hourly_rate: Option<Decimal>,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TemplateTaskInput {
+    pub name: String,
+    pub description: Option<String>,
+    pub sort_order: Option<i32>,
+    pub start_offset_days: Option<i32>,
+    pub duration_days: Option<i32>,
+    pub estimated_hours: Option<Decimal>,
+    pub is_milestone: Option<bool>,
+    pub priority: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TemplateDependencyInput {
+    pub task_id: Uuid,
+    pub depends_on_id: Uuid,
+    pub lag_days: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewQuery {
+    pub start_date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TaskAssignment {
+    pub template_task_id: Uuid,
+    pub user_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InstantiateTemplate {
+    pub client_id: Uuid,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub start_date: NaiveDate,
+    pub budget: Option<Decimal>,
+    pub hourly_rate: Option<Decimal>,
+    pub project_manager_id: Option<Uuid>,
+    pub rate_card_id: Option<Uuid>,
+    pub default_assignee: Option<Uuid>,
+    #[serde(default)]
+    pub assignments: Vec<TaskAssignment>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InstantiatedProject {
+    pub project_id: Uuid,
+    pub tasks_created: usize,
+    pub end_date: Option<NaiveDate>,
+    pub schedule: Vec<ScheduledTask>,
+}
+
+pub fn project_template_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_templates).post(create_template))
+        .route("/:id", get(get_template).put(update_template).delete(deactivate_template))
+        .route("/:id/tasks", post(create_template_task))
+        .route("/:id/dependencies", post(create_template_dependency))
+        .route("/:id/preview", get(preview_template))
+        .route("/:id/instantiate", post(instantiate_template))
+        .route("/tasks/:task_id", put(update_template_task).delete(delete_template_task))
+        .route("/dependencies/:dependency_id", delete(delete_template_dependency))
+}
+
+async fn list_templates(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<TemplateQuery>,
+) -> Result<Json<Vec<ProjectTemplate>>, StatusCode> {
+    let templates = sqlx::query_as::<_, ProjectTemplate>(&format!(
+        "SELECT {} FROM project_templates t
+         WHERE ($1::text IS NULL OR t.category = $1)
+           AND ($2 OR COALESCE(t.is_active, true))
+         ORDER BY t.name",
+        TEMPLATE_COLUMNS
+    ))
+    .bind(&params.category)
+    .bind(params.include_inactive.unwrap_or(false))
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("listing project templates"))?;
+    Ok(Json(templates))
+}
+
+async fn create_template(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<TemplateInput>,
+) -> Result<(StatusCode, Json<TemplateDetail>), StatusCode> {
+    if payload.name.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let id: Uuid = sqlx::query_scalar(
+        "INSERT INTO project_templates (name, description, category, default_hourly_rate, is_active, created_by)
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+    )
+    .bind(payload.name.trim())
+    .bind(&payload.description)
+    .bind(&payload.category)
+    .bind(payload.default_hourly_rate)
+    .bind(payload.is_active.unwrap_or(true))
+    .bind(auth.0.id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(internal("creating project template"))?;
+
+    Ok((StatusCode::CREATED, Json(load_template(&state, id).await?)))
+}
+
+async fn get_template(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<TemplateDetail>, StatusCode> {
+    Ok(Json(load_template(&state, id).await?))
+}
+
+async fn update_template(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<TemplateInput>,
+) -> Result<Json<TemplateDetail>, StatusCode> {
+    if payload.name.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let result = sqlx::query(
+        "UPDATE project_templates SET name = $2, description = $3, category = $4, default_hourly_rate = $5,
+         is_active = COALESCE($6, is_active), updated_at = NOW()
+         WHERE id = $1",
+    )
+    .bind(id)
+    .bind(payload.name.trim())
+    .bind(&payload.description)
+    .bind(&payload.category)
+    .bind(payload.default_hourly_rate)
+    .bind(payload.is_active)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("updating project template"))?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(Json(load_template(&state, id).await?))
+}
+
+/// Templates stay behind for the projects made from them; deleting one
+/// only retires it.
+async fn deactivate_template(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query("UPDATE project_templates SET is_active = false, updated_at = NOW() WHERE id = $1")
+        .bind(id)
+        .execute(&state.db_pool)
+        .await
+        .map_err(internal("deactivating project template"))?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn create_template_task(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<TemplateTaskInput>,
+) -> Result<(StatusCode, Json<TemplateTask>), StatusCode> {
+    validate_task(&payload)?;
+    load_template(&state, id).await?;
+
+    let is_milestone = payload.is_milestone.unwrap_or(false);
+    let task = sqlx::query_as::<_, TemplateTask>(&format!(
+        "INSERT INTO project_template_tasks
+            (template_id, sort_order, name, description, start_offset_days, duration_days, estimated_hours,
+             is_milestone, priority)
+         VALUES ($1, COALESCE($2, (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM project_template_tasks WHERE template_id = $1)),
+                 $3, $4, $5, $6, $7, $8, $9)
+         RETURNING {}",
+        TEMPLATE_TASK_COLUMNS
+    ))
+    .bind(id)
+    .bind(payload.sort_order)
+    .bind(payload.name.trim())
+    .bind(&payload.description)
+    .bind(payload.start_offset_days.unwrap_or(0))
+    .bind(if is_milestone { 0 } else { payload.duration_days.unwrap_or(1) })
+    .bind(payload.estimated_hours)
+    .bind(is_milestone)
+    .bind(payload.priority.as_deref().unwrap_or("medium"))
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(internal("creating template task"))?;
+
+    touch_template(&state, id).await?;
+    Ok((StatusCode::CREATED, Json(task)))
+}
+
+async fn update_template_task(
+    State(state): State<Arc<AppState>>,
+    Path(task_id): Path<Uuid>,
+    Json(payload): Json<TemplateTaskInput>,
+) -> Result<Json<TemplateTask>, StatusCode> {
+    validate_task(&payload)?;
+
+    let is_milestone = payload.is_milestone.unwrap_or(false);
+    let task = sqlx::query_as::<_, TemplateTask>(&format!(
+        "UPDATE project_template_tasks SET
+            sort_order = COALESCE($2, sort_order), name = $3, description = $4,
+            start_offset_days = COALESCE($5, start_offset_days), duration_days = $6,
+            estimated_hours = $7, is_milestone = $8, priority = COALESCE($9, priority)
+         WHERE id = $1
+         RETURNING {}",
+        TEMPLATE_TASK_COLUMNS
+    ))
+    .bind(task_id)
+    .bind(payload.sort_order)
+    .bind(payload.name.trim())
+    .bind(&payload.description)
+    .bind(payload.start_offset_days)
+    .bind(if is_milestone { 0 } else { payload.duration_days.unwrap_or(1) })
+    .bind(payload.estimated_hours)
+    .bind(is_milestone)
+    .bind(&payload.priority)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(internal("updating template task"))?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    touch_template(&state, task.template_id).await?;
+    Ok(Json(task))
+}
+
+async fn delete_template_task(
+    State(state): State<Arc<AppState>>,
+    Path(task_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let template_id: Uuid = sqlx::query_scalar("DELETE FROM project_template_tasks WHERE id = $1 RETURNING template_id")
+        .bind(task_id)
+        .fetch_optional(&state.db_pool)
+        .await
+        .map_err(internal("deleting template task"))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    touch_template(&state, template_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn create_template_dependency(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<TemplateDependencyInput>,
+) -> Result<(StatusCode, Json<PlanDependency>), StatusCode> {
+    if payload.task_id == payload.depends_on_id {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let (tasks, mut dependencies) = project_schedule::load_template_plan(&state.db_pool, id)
+        .await
+        .map_err(internal("loading template plan"))?;
+    let in_template = |task_id: Uuid| tasks.iter().any(|t| t.id == task_id);
+    if !in_template(payload.task_id) || !in_template(payload.depends_on_id) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if dependencies
+        .iter()
+        .any(|d| d.task_id == payload.task_id && d.depends_on == payload.depends_on_id)
+    {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let dependency = PlanDependency {
+        id: Uuid::new_v4(),
+        task_id: payload.task_id,
+        depends_on: payload.depends_on_id,
+        lag_days: payload.lag_days.unwrap_or(0),
+    };
+
+    // Refuse links that would close a loop
+    dependencies.push(dependency.clone());
+    let today = Utc::now().date_naive();
+    if project_schedule::schedule(today, &project_schedule::template_plan(&tasks), &dependencies).is_err() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    sqlx::query(
+        "INSERT INTO project_template_dependencies (id, template_id, task_id, depends_on_id, lag_days)
+         VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(dependency.id)
+    .bind(id)
+    .bind(dependency.task_id)
+    .bind(dependency.depends_on)
+    .bind(dependency.lag_days)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("creating template dependency"))?;
+
+    touch_template(&state, id).await?;
+    Ok((StatusCode::CREATED, Json(dependency)))
+}
+
+async fn delete_template_dependency(
+    State(state): State<Arc<AppState>>,
+    Path(dependency_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query("DELETE FROM project_template_dependencies WHERE id = $1")
+        .bind(dependency_id)
+        .execute(&state.db_pool)
+        .await
+        .map_err(internal("deleting template dependency"))?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The dates a project started on `start_date` would get.
+async fn preview_template(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<PreviewQuery>,
+) -> Result<Json<Vec<ScheduledTask>>, StatusCode> {
+    load_template(&state, id).await?;
+    let (tasks, dependencies) = project_schedule::load_template_plan(&state.db_pool, id)
+        .await
+        .map_err(internal("loading template plan"))?;
+    let start = params.start_date.unwrap_or_else(|| Utc::now().date_naive());
+    let scheduled = project_schedule::schedule(start, &project_schedule::template_plan(&tasks), &dependencies)
+        .map_err(|_| StatusCode::CONFLICT)?;
+    Ok(Json(scheduled))
+}
+
+async fn instantiate_template(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<InstantiateTemplate>,
+) -> Result<(StatusCode, Json<InstantiatedProject>), StatusCode> {
+    let template = load_template(&state, id).await?.template;
+    if !template.is_active {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let project_id = Uuid::new_v4();
+    let project = ProjectCreate {
+        client_id: payload.client_id,
+        name: payload.name.clone().unwrap_or_else(|| template.name.clone()),
+        description: payload.description.clone().or_else(|| template.description.clone()),
+        start_date: Some(payload.start_date),
+        end_date: None,
+        budget: payload.budget,
+        hourly_rate: payload.hourly_rate.or(template.default_hourly_rate),
+        project_manager_id: payload.project_manager_id.or(Some(auth.0.id)),
+        rate_card_id: payload.rate_card_id,
+    };
+    let assignments: HashMap<Uuid, Uuid> =
+        payload.assignments.iter().map(|a| (a.template_task_id, a.user_id)).collect();
+
+    let mut tx = state.db_pool.begin().await.map_err(internal("starting transaction"))?;
+    insert_project(&mut tx, project_id, &project)
+        .await
+        .map_err(internal("creating project from template"))?;
+    sqlx::query("UPDATE projects SET template_id = $2 WHERE id = $1")
+        .bind(project_id)
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal("linking project to template"))?;
+    let tasks_created = project_schedule::create_tasks_from_template(
+        &mut tx,
+        id,
+        project_id,
+        payload.start_date,
+        &assignments,
+        payload.default_assignee,
+    )
+    .await
+    .map_err(internal("creating tasks from template"))?;
+    tx.commit().await.map_err(internal("committing template instantiation"))?;
+
+    let schedule = project_schedule::reschedule(&state.db_pool, project_id)
+        .await
+        .map_err(internal("scheduling project from template"))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(InstantiatedProject {
+            project_id,
+            tasks_created,
+            end_date: schedule.iter().map(|t| t.finish_date).max(),
+            schedule,
+        }),
+    ))
+}
+
+async fn load_template(state: &AppState, id: Uuid) -> Result<TemplateDetail, StatusCode> {
+    let template = sqlx::query_as::<_, ProjectTemplate>(&format!(
+        "SELECT {} FROM project_templates t WHERE t.id = $1",
+        TEMPLATE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(internal("fetching project template"))?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let (tasks, dependencies) = project_schedule::load_template_plan(&state.db_pool, id)
+        .await
+        .map_err(internal("loading template plan"))?;
+
+    Ok(TemplateDetail { template, tasks, dependencies })
+}
+
+/// Keeps the template's estimate in step with its tasks.
+async fn touch_template(state: &AppState, id: Uuid) -> Result<(), StatusCode> {
+    sqlx::query(
+        "UPDATE project_templates SET updated_at = NOW(),
+         estimated_hours = (SELECT SUM(estimated_hours) FROM project_template_tasks WHERE template_id = $1)
+         WHERE id = $1",
+    )
+    .bind(id)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("updating project template"))?;
+    Ok(())
+}
+
+fn validate_task(payload: &TemplateTaskInput) -> Result<(), StatusCode> {
+    let negative = |value: Option<i32>| value.is_some_and(|v| v < 0);
+    if payload.name.trim().is_empty() || negative(payload.start_offset_days) || negative(payload.duration_days) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(())
+}
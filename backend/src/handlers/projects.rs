@@ -10,6 +10,10 @@ use std::sync::Arc;
 use uuid::Uuid;
 use chrono::{DateTime, Utc, NaiveDate};
 use rust_decimal::Decimal;
+use crate::handlers::internal;
+use crate::services::project_schedule::{
+    self, CapacityConflict, Gantt, GanttTask, PlanDependency, ScheduleError, ScheduledTask,
+};
 use crate::AppState;
 
 #[derive(Serialize, Deserialize)]
@@ -22,6 +26,7 @@ pub struct ProjectCreate {
     pub budget: Option<Decimal>,
     pub hourly_rate: Option<Decimal>,
     pub project_manager_id: Option<Uuid>,
+    pub rate_card_id: Option<Uuid>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -34,6 +39,7 @@ pub struct ProjectUpdate {
     pub budget: Option<Decimal>,
     pub hourly_rate: Option<Decimal>,
     pub project_manager_id: Option<Uuid>,
+    pub rate_card_id: Option<Uuid>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -46,6 +52,9 @@ pub struct TaskCreate {
     pub priority: Option<String>,
     pub estimated_hours: Option<Decimal>,
     pub due_date: Option<NaiveDate>,
+    pub start_constraint: Option<NaiveDate>,
+    pub duration_days: Option<i32>,
+    pub is_milestone: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -58,6 +67,16 @@ pub struct TaskUpdate {
     pub estimated_hours: Option<Decimal>,
     pub actual_hours: Option<Decimal>,
     pub due_date: Option<NaiveDate>,
+    pub start_constraint: Option<NaiveDate>,
+    pub duration_days: Option<i32>,
+    pub is_milestone: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DependencyCreate {
+    pub task_id: Uuid,            // successor
+    pub depends_on_task_id: Uuid, // predecessor
+    pub lag_days: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -110,6 +129,10 @@ pub struct TaskWithDetails {
     pub estimated_hours: Option<Decimal>,
     pub actual_hours: Option<Decimal>,
     pub time_logged: Option<Decimal>,
+    pub start_date: Option<NaiveDate>,
+    pub start_constraint: Option<NaiveDate>,
+    pub duration_days: Option<i32>,
+    pub is_milestone: bool,
     pub due_date: Option<NaiveDate>,
     pub completed_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
@@ -124,6 +147,13 @@ pub fn project_routes() -> Router<Arc<AppState>> {
         .route("/:id/time-entries", get(get_project_time_entries))
         .route("/:id/stats", get(get_project_stats))
         .route("/tasks/:task_id", get(get_task).put(update_task).delete(delete_task))
+        .route("/:id/gantt", get(get_project_gantt))
+        .route("/:id/schedule", post(schedule_project))
+        .route("/:id/milestones", get(get_project_milestones))
+        .route("/:id/capacity", get(get_project_capacity))
+        .route("/:id/dependencies", get(list_dependencies).post(create_dependency))
+        .route("/dependencies/:dependency_id", delete(delete_dependency))
+        .merge(super::project_budgets::project_budget_routes())
 }
 
 async fn list_projects(
@@ -199,7 +229,7 @@ async fn list_projects(
          LEFT JOIN (
             SELECT 
                 p.id as project_id,
-                COUNT(t.*) as open_tickets
+                COUNT(DISTINCT t.id) as open_tickets
             FROM projects p
             LEFT JOIN tasks task ON p.id = task.project_id
             LEFT JOIN tickets t ON task.ticket_id = t.id AND t.status NOT IN ('closed', 'resolved')
@@ -227,24 +257,12 @@ async fn create_project(
 ) -> Result<(StatusCode, Json<ProjectWithDetails>), StatusCode> {
     let project_id = Uuid::new_v4();
     
-    match sqlx::query!(
-        "INSERT INTO projects (
-            id, client_id, name, description, start_date, end_date,
-            budget, hourly_rate, project_manager_id
-        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
-        project_id,
-        payload.client_id,
-        payload.name,
-        payload.description,
-        payload.start_date,
-        payload.end_date,
-        payload.budget,
-        payload.hourly_rate,
-        payload.project_manager_id
-    )
-    .execute(&state.db_pool)
-    .await
-    {
+    let mut conn = state.db_pool.acquire().await.map_err(|e| {
+        tracing::error!("Error acquiring connection: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    
+    match insert_project(&mut conn, project_id, &payload).await {
         Ok(_) => {
             match get_project_by_id(&state, project_id).await {
                 Ok(project) => Ok((StatusCode::CREATED, Json(project))),
@@ -258,6 +276,35 @@ async fn create_project(
     }
 }
 
+/// Inserts a project row. Shared with quote conversion, which creates the
+/// project inside its own transaction.
+pub(crate) async fn insert_project(
+    conn: &mut sqlx::PgConnection,
+    project_id: Uuid,
+    payload: &ProjectCreate,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO projects (
+            id, client_id, name, description, start_date, end_date,
+            budget, hourly_rate, project_manager_id, rate_card_id
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+        project_id,
+        payload.client_id,
+        payload.name,
+        payload.description,
+        payload.start_date,
+        payload.end_date,
+        payload.budget,
+        payload.hourly_rate,
+        payload.project_manager_id,
+        payload.rate_card_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    
+    Ok(())
+}
+
 async fn get_project(
     State(state): State<Arc<AppState>>,
     Path(id): Path<Uuid>,
@@ -284,6 +331,7 @@ async fn update_project(
          budget = COALESCE($7, budget),
          hourly_rate = COALESCE($8, hourly_rate),
          project_manager_id = COALESCE($9, project_manager_id),
+         rate_card_id = COALESCE($10, rate_card_id),
          updated_at = NOW()
          WHERE id = $1",
         id,
@@ -294,7 +342,8 @@ async fn update_project(
         payload.end_date,
         payload.budget,
         payload.hourly_rate,
-        payload.project_manager_id
+        payload.project_manager_id,
+        payload.rate_card_id
     )
     .execute(&state.db_pool)
     .await
@@ -353,6 +402,7 @@ async fn get_project_tasks(
             t.status, t.priority,
             t.estimated_hours, t.actual_hours,
             COALESCE(te_stats.time_logged, 0) as time_logged,
+            t.start_date, t.start_constraint, t.duration_days, t.is_milestone,
             t.due_date, t.completed_at,
             t.created_at, t.updated_at
          FROM tasks t
@@ -392,8 +442,9 @@ async fn create_task(
     match sqlx::query!(
         "INSERT INTO tasks (
             id, project_id, ticket_id, name, description,
-            assigned_to, priority, estimated_hours, due_date
-        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+            assigned_to, priority, estimated_hours, due_date,
+            start_constraint, duration_days, is_milestone
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
         task_id,
         payload.project_id,
         payload.ticket_id,
@@ -402,7 +453,10 @@ async fn create_task(
         payload.assigned_to,
         priority,
         payload.estimated_hours,
-        payload.due_date
+        payload.due_date,
+        payload.start_constraint,
+        payload.duration_days,
+        payload.is_milestone.unwrap_or(false)
     )
     .execute(&state.db_pool)
     .await
@@ -454,6 +508,9 @@ async fn update_task(
          actual_hours = COALESCE($8, actual_hours),
          due_date = COALESCE($9, due_date),
          completed_at = COALESCE($10, completed_at),
+         start_constraint = COALESCE($11, start_constraint),
+         duration_days = COALESCE($12, duration_days),
+         is_milestone = COALESCE($13, is_milestone),
          updated_at = NOW()
          WHERE id = $1",
         task_id,
@@ -465,7 +522,10 @@ async fn update_task(
         payload.estimated_hours,
         payload.actual_hours,
         payload.due_date,
-        completed_at
+        completed_at,
+        payload.start_constraint,
+        payload.duration_days,
+        payload.is_milestone
     )
     .execute(&state.db_pool)
     .await
@@ -526,6 +586,7 @@ async fn get_project_time_entries(
             te.start_time, te.end_time, te.duration_minutes,
             te.description, te.billable, te.billed,
             te.hourly_rate, te.total_amount,
+            te.work_type, te.billable_minutes, te.rate_multiplier,
             te.created_at, te.updated_at
          FROM time_entries te
          LEFT JOIN users u ON te.user_id = u.id
@@ -644,7 +705,7 @@ async fn get_project_by_id(state: &AppState, id: Uuid) -> Result<ProjectWithDeta
             COALESCE(te_stats.total_cost, 0) as total_cost,
             COALESCE(task_stats.task_count, 0) as task_count,
             COALESCE(task_stats.completed_tasks, 0) as completed_tasks,
-            0 as open_tickets, -- TODO: Implement proper ticket counting
+            COALESCE(ticket_stats.open_tickets, 0) as open_tickets,
             CASE WHEN COALESCE(task_stats.task_count, 0) > 0 
                  THEN (COALESCE(task_stats.completed_tasks, 0) * 100 / task_stats.task_count)::int
                  ELSE 0 END as progress_percentage,
@@ -671,6 +732,15 @@ async fn get_project_by_id(state: &AppState, id: Uuid) -> Result<ProjectWithDeta
             WHERE project_id = $1
             GROUP BY project_id
          ) task_stats ON p.id = task_stats.project_id
+         LEFT JOIN (
+            SELECT 
+                task.project_id,
+                COUNT(DISTINCT t.id) as open_tickets
+            FROM tasks task
+            JOIN tickets t ON task.ticket_id = t.id AND t.status NOT IN ('closed', 'resolved')
+            WHERE task.project_id = $1
+            GROUP BY task.project_id
+         ) ticket_stats ON p.id = ticket_stats.project_id
          WHERE p.id = $1",
         id
     )
@@ -698,6 +768,7 @@ async fn get_task_by_id(state: &AppState, id: Uuid) -> Result<TaskWithDetails, S
             t.status, t.priority,
             t.estimated_hours, t.actual_hours,
             COALESCE(te_stats.time_logged, 0) as time_logged,
+            t.start_date, t.start_constraint, t.duration_days, t.is_milestone,
             t.due_date, t.completed_at,
             t.created_at, t.updated_at
          FROM tasks t
@@ -725,4 +796,138 @@ async fn get_task_by_id(state: &AppState, id: Uuid) -> Result<TaskWithDetails, S
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
-}
\ No newline at end of file
+}
+// Scheduling
+
+/// A dependency loop is the plan's problem, not the server's.
+fn schedule_error(context: &'static str) -> impl Fn(Box<dyn std::error::Error + Send + Sync>) -> StatusCode {
+    move |e| {
+        if e.downcast_ref::<ScheduleError>().is_some() {
+            return StatusCode::CONFLICT;
+        }
+        tracing::error!("Error {}: {}", context, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+async fn get_project_gantt(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Gantt>, StatusCode> {
+    get_project_by_id(&state, id).await?;
+    let gantt = project_schedule::gantt(&state.db_pool, id)
+        .await
+        .map_err(schedule_error("building gantt"))?;
+    Ok(Json(gantt))
+}
+
+async fn get_project_milestones(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<GanttTask>>, StatusCode> {
+    get_project_by_id(&state, id).await?;
+    let gantt = project_schedule::gantt(&state.db_pool, id)
+        .await
+        .map_err(schedule_error("fetching milestones"))?;
+    Ok(Json(gantt.milestones))
+}
+
+/// Stores the computed dates on each task and moves the project end date.
+async fn schedule_project(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<ScheduledTask>>, StatusCode> {
+    get_project_by_id(&state, id).await?;
+    let scheduled = project_schedule::reschedule(&state.db_pool, id)
+        .await
+        .map_err(schedule_error("scheduling project"))?;
+    Ok(Json(scheduled))
+}
+
+async fn get_project_capacity(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<CapacityConflict>>, StatusCode> {
+    get_project_by_id(&state, id).await?;
+    let conflicts = project_schedule::project_capacity(&state.db_pool, id)
+        .await
+        .map_err(internal("checking project capacity"))?;
+    Ok(Json(conflicts))
+}
+
+async fn list_dependencies(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<PlanDependency>>, StatusCode> {
+    let (_, dependencies) = project_schedule::load_plan(&state.db_pool, id)
+        .await
+        .map_err(internal("fetching task dependencies"))?;
+    Ok(Json(dependencies))
+}
+
+async fn create_dependency(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<DependencyCreate>,
+) -> Result<(StatusCode, Json<PlanDependency>), StatusCode> {
+    if payload.task_id == payload.depends_on_task_id {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let (tasks, mut dependencies) = project_schedule::load_plan(&state.db_pool, id)
+        .await
+        .map_err(internal("fetching task dependencies"))?;
+    let in_project = |task_id: Uuid| tasks.iter().any(|t| t.id == task_id);
+    if !in_project(payload.task_id) || !in_project(payload.depends_on_task_id) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if dependencies
+        .iter()
+        .any(|d| d.task_id == payload.task_id && d.depends_on == payload.depends_on_task_id)
+    {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let dependency = PlanDependency {
+        id: Uuid::new_v4(),
+        task_id: payload.task_id,
+        depends_on: payload.depends_on_task_id,
+        lag_days: payload.lag_days.unwrap_or(0),
+    };
+
+    // Refuse links that would close a loop
+    dependencies.push(dependency.clone());
+    let start = project_schedule::project_start(&state.db_pool, id, &tasks)
+        .await
+        .map_err(internal("fetching project start"))?;
+    if project_schedule::schedule(start, &project_schedule::plan_tasks(start, &tasks), &dependencies).is_err() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    sqlx::query(
+        "INSERT INTO task_dependencies (id, task_id, depends_on_task_id, lag_days) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(dependency.id)
+    .bind(dependency.task_id)
+    .bind(dependency.depends_on)
+    .bind(dependency.lag_days)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("creating task dependency"))?;
+
+    Ok((StatusCode::CREATED, Json(dependency)))
+}
+
+async fn delete_dependency(
+    State(state): State<Arc<AppState>>,
+    Path(dependency_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query("DELETE FROM task_dependencies WHERE id = $1")
+        .bind(dependency_id)
+        .execute(&state.db_pool)
+        .await
+        .map_err(internal("deleting task dependency"))?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
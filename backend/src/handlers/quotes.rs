@@ -0,0 +1,943 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json},
+    routing::{get, post},
+    Router,
+};
+use chrono::{Duration, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::handlers::{ensure_client_contact, internal};
+use crate::handlers::portal::{extract_portal_token, verify_token};
+use crate::handlers::projects::{insert_project, ProjectCreate};
+use crate::handlers::recurring_billing::{replace_items, ItemInput, FREQUENCIES};
+use crate::notifications::{enqueue_notification, QueuedNotification};
+use crate::services::currency::format_money;
+use crate::services::email::EmailAttachment;
+use crate::services::quotes::{
+    self, Quote, QuoteLine, QuoteLineInput, LINE_COLUMNS, LINE_ITEM_TYPES, QUOTE_COLUMNS,
+};
+use crate::services::recurring_billing::{insert_invoice, DraftLine};
+use crate::AppState;
+
+const DEFAULT_VALID_DAYS: i64 = 30;
+
+pub fn quote_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_quotes).post(create_quote))
+        .route("/:id", get(get_quote).put(update_quote))
+        .route("/:id/pdf", get(download_quote_pdf))
+        .route("/:id/send", post(send_quote))
+        .route("/:id/revise", post(revise_quote))
+        .route("/:id/convert", post(convert_quote))
+}
+
+/// Client portal routes, nested under /api/v1/portal/quotes.
+pub fn portal_quote_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_portal_quotes))
+        .route("/:id", get(get_portal_quote))
+        .route("/:id/pdf", get(download_portal_quote_pdf))
+        .route("/:id/accept", post(accept_quote))
+        .route("/:id/reject", post(reject_quote))
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuoteWithLines {
+    #[serde(flatten)]
+    pub quote: Quote,
+    pub lines: Vec<QuoteLine>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QuoteQuery {
+    pub client_id: Option<Uuid>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QuoteCreate {
+    pub client_id: Uuid,
+    pub contact_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub title: String,
+    pub date: Option<NaiveDate>,
+    pub expiry_date: Option<NaiveDate>,
+    pub discount_percentage: Option<Decimal>,
+    pub discount_amount: Option<Decimal>,
+    pub notes: Option<String>,
+    pub terms: Option<String>,
+    pub lines: Vec<QuoteLineInput>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QuoteUpdate {
+    pub contact_id: Option<Uuid>,
+    pub title: Option<String>,
+    pub expiry_date: Option<NaiveDate>,
+    pub discount_percentage: Option<Decimal>,
+    pub discount_amount: Option<Decimal>,
+    pub notes: Option<String>,
+    pub terms: Option<String>,
+    pub lines: Option<Vec<QuoteLineInput>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SendQuote {
+    pub contact_id: Option<Uuid>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConvertQuote {
+    pub create_project: Option<bool>,
+    pub create_invoice: Option<bool>,
+    pub create_recurring: Option<bool>,
+    pub project_name: Option<String>,
+    pub start_date: Option<NaiveDate>,
+    pub send_invoice: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcceptQuote {
+    pub signed_name: String,
+    pub accept_terms: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RejectQuote {
+    pub reason: Option<String>,
+}
+
+async fn list_quotes(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<QuoteQuery>,
+) -> Result<Json<Vec<Quote>>, StatusCode> {
+    let quotes = sqlx::query_as::<_, Quote>(&format!(
+        "SELECT {} FROM quotes q JOIN clients c ON c.id = q.client_id
+         WHERE ($1::UUID IS NULL OR q.client_id = $1) AND ($2::TEXT IS NULL OR q.status = $2)
+         ORDER BY q.date DESC, q.number DESC",
+        QUOTE_COLUMNS
+    ))
+    .bind(params.client_id)
+    .bind(params.status)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching quotes: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(quotes))
+}
+
+async fn get_quote(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<QuoteWithLines>, StatusCode> {
+    Ok(Json(load_quote(&state, id).await?))
+}
+
+async fn create_quote(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<QuoteCreate>,
+) -> Result<(StatusCode, Json<QuoteWithLines>), StatusCode> {
+    validate_lines(&payload.lines)?;
+
+    let date = payload.date.unwrap_or_else(|| Utc::now().date_naive());
+    let expiry_date = payload.expiry_date.unwrap_or(date + Duration::days(DEFAULT_VALID_DAYS));
+    if expiry_date < date {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let (discount_percentage, discount_amount) = discount_inputs(payload.discount_percentage, payload.discount_amount, None);
+
+    if let Some(contact_id) = payload.contact_id {
+        ensure_client_contact(&state.db_pool, payload.client_id, contact_id).await?;
+    }
+
+    let mut tx = begin(&state).await?;
+
+    let number = quotes::next_quote_number(&mut tx).await.map_err(internal("generating quote number"))?;
+    let id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO quotes (client_id, contact_id, project_id, number, title, date, expiry_date,
+                            discount_percentage, notes, terms, status, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, 'draft', $11)
+        RETURNING id
+        "#,
+    )
+    .bind(payload.client_id)
+    .bind(payload.contact_id)
+    .bind(payload.project_id)
+    .bind(&number)
+    .bind(&payload.title)
+    .bind(date)
+    .bind(expiry_date)
+    .bind(discount_percentage)
+    .bind(&payload.notes)
+    .bind(&payload.terms)
+    .bind(auth.0.id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal("creating quote"))?;
+
+    quotes::save_lines(&mut tx, id, &payload.lines, discount_percentage, discount_amount)
+        .await
+        .map_err(internal("saving quote lines"))?;
+
+    commit(tx).await?;
+
+    Ok((StatusCode::CREATED, Json(load_quote(&state, id).await?)))
+}
+
+/// Edits a draft. Sent quotes are changed by issuing a revision instead.
+async fn update_quote(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<QuoteUpdate>,
+) -> Result<Json<QuoteWithLines>, StatusCode> {
+    let existing = load_quote(&state, id).await?;
+    if existing.quote.status.as_deref() != Some("draft") {
+        return Err(StatusCode::CONFLICT);
+    }
+    if let Some(lines) = &payload.lines {
+        validate_lines(lines)?;
+    }
+
+    let lines: Vec<QuoteLineInput> = match payload.lines {
+        Some(lines) => lines,
+        None => existing.lines.iter().map(QuoteLineInput::from).collect(),
+    };
+    if let Some(contact_id) = payload.contact_id {
+        ensure_client_contact(&state.db_pool, existing.quote.client_id, contact_id).await?;
+    }
+    let (discount_percentage, discount_amount) =
+        discount_inputs(payload.discount_percentage, payload.discount_amount, Some(&existing.quote));
+
+    let mut tx = begin(&state).await?;
+
+    sqlx::query(
+        r#"
+        UPDATE quotes SET
+            contact_id = COALESCE($2, contact_id),
+            title = COALESCE($3, title),
+            expiry_date = COALESCE($4, expiry_date),
+            discount_percentage = $5,
+            notes = COALESCE($6, notes),
+            terms = COALESCE($7, terms),
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(payload.contact_id)
+    .bind(payload.title)
+    .bind(payload.expiry_date)
+    .bind(discount_percentage)
+    .bind(payload.notes)
+    .bind(payload.terms)
+    .execute(&mut *tx)
+    .await
+    .map_err(internal("updating quote"))?;
+
+    quotes::save_lines(&mut tx, id, &lines, discount_percentage, discount_amount)
+        .await
+        .map_err(internal("saving quote lines"))?;
+
+    commit(tx).await?;
+
+    Ok(Json(load_quote(&state, id).await?))
+}
+
+async fn download_quote_pdf(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let quote = load_quote(&state, id).await?;
+    Ok(pdf_response(&quote))
+}
+
+/// Emails the quote PDF to a client contact with a link to accept it in the portal.
+async fn send_quote(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<SendQuote>,
+) -> Result<Json<QuoteWithLines>, StatusCode> {
+    let existing = load_quote(&state, id).await?;
+    if !matches!(existing.quote.status.as_deref(), Some("draft") | Some("sent")) {
+        return Err(StatusCode::CONFLICT);
+    }
+    if existing.lines.is_empty()
+        || existing.quote.expiry_date.is_some_and(|d| d < Utc::now().date_naive())
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let contact_id = match payload.contact_id.or(existing.quote.contact_id) {
+        Some(contact_id) => {
+            ensure_client_contact(&state.db_pool, existing.quote.client_id, contact_id).await?;
+            Some(contact_id)
+        }
+        None => sqlx::query_scalar(
+            "SELECT id FROM contacts WHERE client_id = $1 AND archived_at IS NULL AND email IS NOT NULL
+             ORDER BY is_primary DESC NULLS LAST, created_at LIMIT 1",
+        )
+        .bind(existing.quote.client_id)
+        .fetch_optional(&state.db_pool)
+        .await
+        .map_err(internal("looking up quote contact"))?,
+    };
+    let contact_id = contact_id.ok_or(StatusCode::BAD_REQUEST)?;
+
+    sqlx::query(
+        "UPDATE quotes SET status = 'sent', sent_at = NOW(), contact_id = $2, updated_at = NOW() WHERE id = $1",
+    )
+    .bind(id)
+    .bind(contact_id)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("marking quote sent"))?;
+
+    let sent = load_quote(&state, id).await?;
+    let pdf_path = store_pdf(&state, &sent).await?;
+
+    let quote = &sent.quote;
+    let portal_url = portal_quote_url(quote.id);
+    let mut message = format!(
+        "Please find quote {} for {} attached, totalling {}.",
+        quote.number,
+        quote.title,
+        format_money(quote.total, &quote.currency)
+    );
+    if !quote.recurring_total.is_zero() {
+        message.push_str(&format!(" Recurring charges: {}.", format_money(quote.recurring_total, &quote.currency)));
+    }
+    if let Some(expiry) = quote.expiry_date {
+        message.push_str(&format!(" The quote is valid until {}.", expiry.format("%Y-%m-%d")));
+    }
+    message.push_str(&format!(" Review and accept it in your client portal: {}", portal_url));
+    if let Some(note) = payload.message.filter(|m| !m.trim().is_empty()) {
+        message = format!("{}\n\n{}", note.trim(), message);
+    }
+
+    let notification = QueuedNotification::for_contact(
+        contact_id,
+        "quote_sent",
+        format!("Quote {}: {}", quote.number, quote.title),
+        message,
+    )
+    .with_entity("quote", quote.id)
+    .with_variables(serde_json::json!({
+        "quote_number": quote.number,
+        "title": quote.title,
+        "total": quote.total,
+        "recurring_total": quote.recurring_total,
+        "currency": quote.currency,
+        "expiry_date": quote.expiry_date,
+        "portal_url": portal_url,
+    }))
+    .with_attachment(EmailAttachment {
+        filename: format!("{}.pdf", quote.number),
+        content_type: "application/pdf".to_string(),
+        file_path: pdf_path,
+    });
+
+    enqueue_notification(&state.db_pool, notification)
+        .await
+        .map_err(internal("queueing quote email"))?;
+
+    Ok(Json(sent))
+}
+
+/// Issues a new draft version of a sent, rejected or expired quote. The
+/// previous version is marked superseded and can no longer be accepted.
+async fn revise_quote(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<(StatusCode, Json<QuoteWithLines>), StatusCode> {
+    let existing = load_quote(&state, id).await?;
+    if !matches!(existing.quote.status.as_deref(), Some("sent") | Some("rejected") | Some("expired")) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let previous = &existing.quote;
+    let root_id = previous.root_quote_id.unwrap_or(previous.id);
+    let today = Utc::now().date_naive();
+    let valid_days = previous
+        .expiry_date
+        .map(|expiry| (expiry - previous.date).num_days())
+        .filter(|days| *days > 0)
+        .unwrap_or(DEFAULT_VALID_DAYS);
+
+    let mut tx = begin(&state).await?;
+
+    let (root_number, version): (String, i32) = sqlx::query_as(
+        "SELECT (SELECT number FROM quotes WHERE id = $1), COALESCE(MAX(version), 1) + 1
+         FROM quotes WHERE id = $1 OR root_quote_id = $1",
+    )
+    .bind(root_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal("numbering quote revision"))?;
+
+    let new_id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO quotes (client_id, contact_id, project_id, number, title, date, expiry_date,
+                            discount_percentage, notes, terms, status, version, root_quote_id, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, 'draft', $11, $12, $13)
+        RETURNING id
+        "#,
+    )
+    .bind(previous.client_id)
+    .bind(previous.contact_id)
+    .bind(previous.project_id)
+    .bind(quotes::revision_number(&root_number, version))
+    .bind(&previous.title)
+    .bind(today)
+    .bind(today + Duration::days(valid_days))
+    .bind(previous.discount_percentage)
+    .bind(&previous.notes)
+    .bind(&previous.terms)
+    .bind(version)
+    .bind(root_id)
+    .bind(auth.0.id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(internal("creating quote revision"))?;
+
+    let lines: Vec<QuoteLineInput> = existing.lines.iter().map(QuoteLineInput::from).collect();
+    let (discount_percentage, discount_amount) = discount_inputs(None, None, Some(previous));
+    quotes::save_lines(&mut tx, new_id, &lines, discount_percentage, discount_amount)
+        .await
+        .map_err(internal("copying quote lines"))?;
+
+    sqlx::query("UPDATE quotes SET status = 'superseded', superseded_by = $2, updated_at = NOW() WHERE id = $1")
+        .bind(previous.id)
+        .bind(new_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(internal("superseding quote"))?;
+
+    commit(tx).await?;
+
+    Ok((StatusCode::CREATED, Json(load_quote(&state, new_id).await?)))
+}
+
+/// Turns an accepted quote into a project (labor lines become tasks), an
+/// invoice for the one-off lines and recurring billing profiles for the
+/// recurring lines. Each target is created at most once per quote.
+async fn convert_quote(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<ConvertQuote>,
+) -> Result<Json<QuoteWithLines>, StatusCode> {
+    let mut tx = begin(&state).await?;
+
+    let status: Option<String> = sqlx::query_scalar("SELECT status FROM quotes WHERE id = $1 FOR UPDATE")
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(internal("locking quote"))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if status.as_deref() != Some("accepted") {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let QuoteWithLines { quote, lines } = load_quote(&state, id).await?;
+    let today = Utc::now().date_naive();
+    let start_date = payload.start_date.unwrap_or(today);
+    let (recurring, one_off): (Vec<&QuoteLine>, Vec<&QuoteLine>) =
+        lines.iter().partition(|l| l.item_type == "recurring");
+    let labor: Vec<&QuoteLine> = one_off.iter().copied().filter(|l| l.item_type == "labor").collect();
+
+    let mut project_id = quote.converted_project_id;
+    if project_id.is_none() && payload.create_project.unwrap_or(!labor.is_empty()) {
+        let target = match quote.project_id {
+            Some(existing) => existing,
+            None => {
+                let new_id = Uuid::new_v4();
+                let project = ProjectCreate {
+                    client_id: quote.client_id,
+                    name: payload.project_name.clone().unwrap_or_else(|| quote.title.clone()),
+                    description: quote.notes.clone(),
+                    start_date: Some(start_date),
+                    end_date: None,
+                    budget: Some(quote.subtotal - quote.discount_amount.unwrap_or_default()),
+                    hourly_rate: None,
+                    project_manager_id: Some(auth.0.id),
+                    rate_card_id: None,
+                };
+                insert_project(&mut tx, new_id, &project).await.map_err(internal("creating project from quote"))?;
+                new_id
+            }
+        };
+
+        for line in &labor {
+            sqlx::query(
+                "INSERT INTO tasks (project_id, name, description, estimated_hours) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(target)
+            .bind(line.description.chars().take(255).collect::<String>())
+            .bind(format!("From quote {}", quote.number))
+            .bind(line.quantity)
+            .execute(&mut *tx)
+            .await
+            .map_err(internal("creating project task from quote"))?;
+        }
+        project_id = Some(target);
+    }
+
+    let mut invoice_id = quote.converted_invoice_id;
+    if invoice_id.is_none() && !one_off.is_empty() && payload.create_invoice.unwrap_or(true) {
+        let mut draft_lines: Vec<DraftLine> = one_off
+            .iter()
+            .map(|line| DraftLine {
+                description: line.description.clone(),
+                quantity: line.quantity.unwrap_or(Decimal::ONE),
+                unit_price: line.unit_price,
+                tax_rate: line.tax_rate,
+                tax_category: None, // Quote lines keep the rates they were signed with
+                source_type: "quote_line".to_string(),
+                source_id: Some(line.id),
+                period_start: None,
+                period_end: None,
+                metric: None,
+            })
+            .collect();
+        if let Some(discount) = quote.discount_amount.filter(|d| !d.is_zero()) {
+            // The quote spreads its discount across lines before tax, so the
+            // discount line reduces tax at the lines' average rate
+            let gross: Decimal = draft_lines.iter().map(|l| l.line_total()).sum();
+            let taxed: Decimal = draft_lines
+                .iter()
+                .map(|l| l.line_total() * l.tax_rate.unwrap_or_default())
+                .sum();
+            let average_rate = (!gross.is_zero()).then(|| (taxed / gross).round_dp(4));
+            draft_lines.push(DraftLine {
+                description: format!("Discount (quote {})", quote.number),
+                quantity: Decimal::ONE,
+                unit_price: -discount,
+                tax_rate: average_rate,
+                tax_category: None,
+                source_type: "quote".to_string(),
+                source_id: Some(quote.id),
+                period_start: None,
+                period_end: None,
+                metric: None,
+            });
+        }
+
+        let created = insert_invoice(
+            &mut tx,
+            quote.client_id,
+            None,
+            today,
+            30,
+            &draft_lines,
+            payload.send_invoice.unwrap_or(false),
+            &format!("Quote {}", quote.number),
+        )
+        .await
+        .map_err(internal("creating invoice from quote"))?
+        .id;
+
+        sqlx::query("UPDATE invoices SET project_id = $2 WHERE id = $1")
+            .bind(created)
+            .bind(project_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(internal("linking invoice to project"))?;
+        invoice_id = Some(created);
+    }
+
+    let mut recurring_id = quote.converted_recurring_billing_id;
+    if recurring_id.is_none() && !recurring.is_empty() && payload.create_recurring.unwrap_or(true) {
+        let mut by_frequency: BTreeMap<&str, Vec<&QuoteLine>> = BTreeMap::new();
+        for line in &recurring {
+            by_frequency.entry(line.frequency.as_deref().unwrap_or("monthly")).or_default().push(line);
+        }
+        let split = by_frequency.len() > 1;
+
+        for (frequency, group) in by_frequency {
+            let amount: Decimal = group.iter().map(|l| l.total_price).sum();
+            let name = if split { format!("{} ({})", quote.title, frequency) } else { quote.title.clone() };
+
+            let profile_id: Uuid = sqlx::query_scalar(
+                r#"
+                INSERT INTO recurring_billing (client_id, name, description, billing_type, amount, quantity,
+                                               frequency, start_date, next_billing_date, send_invoice,
+                                               payment_terms_days, prorate, created_by, currency)
+                VALUES ($1, $2, $3, 'fixed', $4, 1, $5, $6, $6, true, 30, true, $7, $8)
+                RETURNING id
+                "#,
+            )
+            .bind(quote.client_id)
+            .bind(&name)
+            .bind(format!("From quote {}", quote.number))
+            .bind(amount)
+            .bind(frequency)
+            .bind(start_date)
+            .bind(auth.0.id)
+            .bind(&quote.currency)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(internal("creating recurring billing from quote"))?;
+
+            let items: Vec<ItemInput> = group
+                .iter()
+                .map(|line| ItemInput {
+                    item_type: Some("service".to_string()),
+                    name: line.description.clone(),
+                    description: None,
+                    quantity: line.quantity,
+                    unit_price: line.unit_price,
+                    discount_percent: None,
+                    tax_rate: line.tax_rate,
+                    tax_category: None,
+                    metric_type: None,
+                    metric_filter: None,
+                    min_quantity: None,
+                })
+                .collect();
+            replace_items(&mut tx, profile_id, &items).await?;
+
+            recurring_id.get_or_insert(profile_id);
+        }
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE quotes SET converted_at = NOW(), converted_project_id = $2, converted_invoice_id = $3,
+                          converted_recurring_billing_id = $4, project_id = COALESCE(project_id, $2),
+                          updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(project_id)
+    .bind(invoice_id)
+    .bind(recurring_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(internal("recording quote conversion"))?;
+
+    commit(tx).await?;
+
+    Ok(Json(load_quote(&state, id).await?))
+}
+
+async fn list_portal_quotes(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Quote>>, StatusCode> {
+    let (_contact_id, client_id) = portal_client(&state, &headers).await?;
+
+    let quotes = sqlx::query_as::<_, Quote>(&format!(
+        "SELECT {} FROM quotes q JOIN clients c ON c.id = q.client_id
+         WHERE q.client_id = $1 AND q.status IN ('sent', 'accepted', 'rejected', 'expired')
+         ORDER BY q.date DESC, q.number DESC",
+        QUOTE_COLUMNS
+    ))
+    .bind(client_id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching portal quotes"))?;
+
+    Ok(Json(quotes))
+}
+
+async fn get_portal_quote(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<QuoteWithLines>, StatusCode> {
+    let (_contact_id, client_id) = portal_client(&state, &headers).await?;
+    let quote = load_portal_quote(&state, id, client_id).await?;
+
+    if quote.quote.viewed_at.is_none() {
+        sqlx::query("UPDATE quotes SET viewed_at = NOW() WHERE id = $1 AND viewed_at IS NULL")
+            .bind(id)
+            .execute(&state.db_pool)
+            .await
+            .map_err(internal("recording quote view"))?;
+    }
+
+    Ok(Json(quote))
+}
+
+async fn download_portal_quote_pdf(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let (_contact_id, client_id) = portal_client(&state, &headers).await?;
+    let quote = load_portal_quote(&state, id, client_id).await?;
+    Ok(pdf_response(&quote))
+}
+
+/// Accepts a quote with a typed-name signature. The signer, their IP and user
+/// agent are stored with the quote and the signed PDF is emailed back.
+async fn accept_quote(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<AcceptQuote>,
+) -> Result<Json<QuoteWithLines>, StatusCode> {
+    let (contact_id, client_id) = portal_client(&state, &headers).await?;
+    let signed_name = payload.signed_name.trim();
+    if !payload.accept_terms || signed_name.is_empty() || signed_name.len() > 255 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let quote = load_portal_quote(&state, id, client_id).await?.quote;
+    if quote.expiry_date.is_some_and(|d| d < Utc::now().date_naive()) {
+        return Err(StatusCode::GONE);
+    }
+
+    let ip = headers
+        .get("x-forwarded-for")
+        .or_else(|| headers.get("x-real-ip"))
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(',').next())
+        .map(|v| v.trim().to_string());
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+
+    let result = sqlx::query(
+        r#"
+        UPDATE quotes SET status = 'accepted', accepted_at = NOW(), accepted_name = $3,
+                          accepted_by_contact_id = $4, accepted_ip = $5, accepted_user_agent = $6,
+                          updated_at = NOW()
+        WHERE id = $1 AND client_id = $2 AND status = 'sent'
+        "#,
+    )
+    .bind(id)
+    .bind(client_id)
+    .bind(signed_name)
+    .bind(contact_id)
+    .bind(ip)
+    .bind(user_agent)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("accepting quote"))?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let accepted = load_portal_quote(&state, id, client_id).await?;
+    let pdf_path = store_pdf(&state, &accepted).await?;
+
+    let author_notice = QueuedNotification::for_user(
+        quote.created_by,
+        "quote_accepted",
+        format!("Quote {} accepted", quote.number),
+        format!(
+            "{} accepted quote {} ({}) for {}.",
+            signed_name,
+            quote.number,
+            quote.title,
+            format_money(quote.total, &quote.currency)
+        ),
+    )
+    .with_priority("high")
+    .with_entity("quote", quote.id);
+
+    let confirmation = QueuedNotification::for_contact(
+        contact_id,
+        "quote_accepted_confirmation",
+        format!("Quote {} accepted", quote.number),
+        format!("Thank you. A signed copy of quote {} is attached for your records.", quote.number),
+    )
+    .with_entity("quote", quote.id)
+    .with_attachment(EmailAttachment {
+        filename: format!("{}-signed.pdf", quote.number),
+        content_type: "application/pdf".to_string(),
+        file_path: pdf_path,
+    });
+
+    for notification in [author_notice, confirmation] {
+        if let Err(e) = enqueue_notification(&state.db_pool, notification).await {
+            tracing::warn!("Failed to queue acceptance notice for quote {}: {}", quote.number, e);
+        }
+    }
+
+    Ok(Json(accepted))
+}
+
+async fn reject_quote(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<RejectQuote>,
+) -> Result<Json<QuoteWithLines>, StatusCode> {
+    let (_contact_id, client_id) = portal_client(&state, &headers).await?;
+
+    let result = sqlx::query(
+        r#"
+        UPDATE quotes SET status = 'rejected', rejected_at = NOW(), rejection_reason = $3, updated_at = NOW()
+        WHERE id = $1 AND client_id = $2 AND status = 'sent'
+        "#,
+    )
+    .bind(id)
+    .bind(client_id)
+    .bind(&payload.reason)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("rejecting quote"))?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let rejected = load_portal_quote(&state, id, client_id).await?;
+    let quote = &rejected.quote;
+    let notification = QueuedNotification::for_user(
+        quote.created_by,
+        "quote_rejected",
+        format!("Quote {} declined", quote.number),
+        match payload.reason.as_deref().filter(|r| !r.trim().is_empty()) {
+            Some(reason) => format!("Quote {} was declined: {}", quote.number, reason),
+            None => format!("Quote {} was declined.", quote.number),
+        },
+    )
+    .with_entity("quote", quote.id);
+
+    if let Err(e) = enqueue_notification(&state.db_pool, notification).await {
+        tracing::warn!("Failed to queue rejection notice for quote {}: {}", quote.number, e);
+    }
+
+    Ok(Json(rejected))
+}
+
+async fn load_quote(state: &AppState, id: Uuid) -> Result<QuoteWithLines, StatusCode> {
+    let quote = sqlx::query_as::<_, Quote>(&format!(
+        "SELECT {} FROM quotes q JOIN clients c ON c.id = q.client_id WHERE q.id = $1",
+        QUOTE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Error fetching quote: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    let lines = sqlx::query_as::<_, QuoteLine>(&format!(
+        "SELECT {} FROM quote_line_items WHERE quote_id = $1 ORDER BY sort_order, created_at",
+        LINE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching quote lines"))?;
+
+    Ok(QuoteWithLines { quote, lines })
+}
+
+/// Quotes are only visible to the client once they've been sent.
+async fn load_portal_quote(state: &AppState, id: Uuid, client_id: Uuid) -> Result<QuoteWithLines, StatusCode> {
+    let quote = load_quote(state, id).await?;
+    let visible = matches!(
+        quote.quote.status.as_deref(),
+        Some("sent") | Some("accepted") | Some("rejected") | Some("expired")
+    );
+    if quote.quote.client_id != client_id || !visible {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(quote)
+}
+
+async fn portal_client(state: &Arc<AppState>, headers: &HeaderMap) -> Result<(Uuid, Uuid), StatusCode> {
+    let token = extract_portal_token(headers)?;
+    verify_token(state, &token).await
+}
+
+async fn store_pdf(state: &AppState, quote: &QuoteWithLines) -> Result<String, StatusCode> {
+    let path = quotes::write_pdf(&quote.quote, &quote.lines)
+        .await
+        .map_err(internal("writing quote PDF"))?;
+
+    sqlx::query("UPDATE quotes SET pdf_path = $2 WHERE id = $1")
+        .bind(quote.quote.id)
+        .bind(&path)
+        .execute(&state.db_pool)
+        .await
+        .map_err(internal("storing quote PDF path"))?;
+
+    Ok(path)
+}
+
+fn pdf_response(quote: &QuoteWithLines) -> ([(header::HeaderName, String); 2], Vec<u8>) {
+    (
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"{}.pdf\"", quote.quote.number),
+            ),
+        ],
+        quotes::render_pdf(&quote.quote, &quote.lines),
+    )
+}
+
+fn portal_quote_url(quote_id: Uuid) -> String {
+    format!("{}/portal/quotes/{}", crate::config::app_base_url(), quote_id)
+}
+
+fn validate_lines(lines: &[QuoteLineInput]) -> Result<(), StatusCode> {
+    let valid = lines.iter().all(|line| {
+        LINE_ITEM_TYPES.contains(&line.item_type())
+            && !line.description.trim().is_empty()
+            && line.quantity.is_none_or(|q| q > Decimal::ZERO)
+            && line
+                .frequency
+                .as_deref()
+                .is_none_or(|f| FREQUENCIES.contains(&f))
+    });
+
+    if valid { Ok(()) } else { Err(StatusCode::BAD_REQUEST) }
+}
+
+/// A fixed discount replaces a percentage and vice versa; with neither given
+/// the quote keeps whichever it already has.
+fn discount_inputs(
+    percentage: Option<Decimal>,
+    amount: Option<Decimal>,
+    existing: Option<&Quote>,
+) -> (Option<Decimal>, Option<Decimal>) {
+    match (percentage, amount, existing) {
+        (_, Some(amount), _) => (None, Some(amount)),
+        (Some(percentage), None, _) => (Some(percentage), None),
+        (None, None, Some(quote)) if quote.discount_percentage.is_some() => (quote.discount_percentage, None),
+        (None, None, Some(quote)) => (None, quote.discount_amount),
+        (None, None, None) => (None, None),
+    }
+}
+
+async fn begin(state: &AppState) -> Result<sqlx::Transaction<'static, sqlx::Postgres>, StatusCode> {
+    state.db_pool.begin().await.map_err(internal("starting transaction"))
+}
+
+async fn commit(tx: sqlx::Transaction<'_, sqlx::Postgres>) -> Result<(), StatusCode> {
+    tx.commit().await.map_err(internal("committing quote"))
+}
@@ -0,0 +1,494 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+    routing::{delete, get, post},
+    Router,
+};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::services::rate_cards::{
+    self, PricedTime, RateCard, RateCardRate, Rounding, WorkType, RATE_CARD_COLUMNS,
+};
+use crate::AppState;
+
+pub fn rate_card_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_rate_cards).post(create_rate_card))
+        .route("/preview", post(preview_rate))
+        .route("/reprice", post(reprice_entries))
+        .route("/:id", get(get_rate_card).put(update_rate_card).delete(deactivate_rate_card))
+        .route("/:id/rates", post(add_rate))
+        .route("/rates/:rate_id", delete(delete_rate))
+}
+
+#[derive(Debug, Serialize)]
+pub struct RateCardWithRates {
+    #[serde(flatten)]
+    pub card: RateCard,
+    pub rates: Vec<RateCardRate>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RateCardQuery {
+    pub client_id: Option<Uuid>,
+    pub include_inactive: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RateInput {
+    pub work_type: Option<String>,
+    pub technician_role: Option<String>,
+    pub hourly_rate: Decimal,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RateCardCreate {
+    pub name: String,
+    pub description: Option<String>,
+    pub client_id: Option<Uuid>,
+    pub is_default: Option<bool>,
+    pub base_rate: Decimal,
+    pub business_hours_start: Option<NaiveTime>,
+    pub business_hours_end: Option<NaiveTime>,
+    pub after_hours_multiplier: Option<Decimal>,
+    pub weekend_multiplier: Option<Decimal>,
+    pub minimum_minutes: Option<i32>,
+    pub increment_minutes: Option<i32>,
+    pub rounding: Option<String>,
+    #[serde(default)]
+    pub rates: Vec<RateInput>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RateCardUpdate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub is_default: Option<bool>,
+    pub base_rate: Option<Decimal>,
+    pub business_hours_start: Option<NaiveTime>,
+    pub business_hours_end: Option<NaiveTime>,
+    pub after_hours_multiplier: Option<Decimal>,
+    pub weekend_multiplier: Option<Decimal>,
+    pub minimum_minutes: Option<i32>,
+    pub increment_minutes: Option<i32>,
+    pub rounding: Option<String>,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewRequest {
+    pub client_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub work_type: String,
+    pub technician_role: Option<String>,
+    /// Start of the work in the client's local time
+    pub local_start: NaiveDateTime,
+    pub minutes: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RepriceRequest {
+    pub client_id: Option<Uuid>,
+    pub since: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepriceSummary {
+    pub entries_repriced: usize,
+    pub entries_failed: usize,
+}
+
+async fn list_rate_cards(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<RateCardQuery>,
+) -> Result<Json<Vec<RateCard>>, StatusCode> {
+    let cards = sqlx::query_as::<_, RateCard>(&format!(
+        "SELECT {} FROM rate_cards rc
+         WHERE ($1::UUID IS NULL OR rc.client_id = $1) AND ($2 OR rc.is_active = true)
+         ORDER BY rc.is_default DESC, rc.client_id NULLS FIRST, rc.name",
+        RATE_CARD_COLUMNS
+    ))
+    .bind(params.client_id)
+    .bind(params.include_inactive.unwrap_or(false))
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching rate cards: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(cards))
+}
+
+async fn get_rate_card(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<RateCardWithRates>, StatusCode> {
+    Ok(Json(load_rate_card(&state, id).await?))
+}
+
+async fn create_rate_card(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<RateCardCreate>,
+) -> Result<(StatusCode, Json<RateCardWithRates>), StatusCode> {
+    if !valid_rounding(payload.rounding.as_deref())
+        || payload.increment_minutes.is_some_and(|m| m <= 0)
+        || payload.rates.iter().any(|r| !valid_rate(r))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut tx = state.db_pool.begin().await.map_err(|e| {
+        tracing::error!("Error starting transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if payload.is_default.unwrap_or(false) {
+        clear_default(&mut tx).await?;
+    }
+
+    let id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO rate_cards (name, description, client_id, is_default, base_rate, business_hours_start,
+                                business_hours_end, after_hours_multiplier, weekend_multiplier, minimum_minutes,
+                                increment_minutes, rounding, created_by)
+        VALUES ($1, $2, $3, COALESCE($4, false), $5, COALESCE($6, '08:00'), COALESCE($7, '18:00'),
+                COALESCE($8, 1.5), COALESCE($9, 2.0), COALESCE($10, 0), COALESCE($11, 1), COALESCE($12, 'up'), $13)
+        RETURNING id
+        "#,
+    )
+    .bind(&payload.name)
+    .bind(&payload.description)
+    .bind(payload.client_id)
+    .bind(payload.is_default)
+    .bind(payload.base_rate)
+    .bind(payload.business_hours_start)
+    .bind(payload.business_hours_end)
+    .bind(payload.after_hours_multiplier)
+    .bind(payload.weekend_multiplier)
+    .bind(payload.minimum_minutes)
+    .bind(payload.increment_minutes)
+    .bind(&payload.rounding)
+    .bind(auth.0.id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error creating rate card: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    for rate in &payload.rates {
+        insert_rate(&mut tx, id, rate).await?;
+    }
+
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Error committing rate card: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::CREATED, Json(load_rate_card(&state, id).await?)))
+}
+
+async fn update_rate_card(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<RateCardUpdate>,
+) -> Result<Json<RateCardWithRates>, StatusCode> {
+    if !valid_rounding(payload.rounding.as_deref()) || payload.increment_minutes.is_some_and(|m| m <= 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut tx = state.db_pool.begin().await.map_err(|e| {
+        tracing::error!("Error starting transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if payload.is_default == Some(true) {
+        clear_default(&mut tx).await?;
+    }
+
+    let result = sqlx::query(
+        r#"
+        UPDATE rate_cards SET
+            name = COALESCE($2, name),
+            description = COALESCE($3, description),
+            is_default = COALESCE($4, is_default),
+            base_rate = COALESCE($5, base_rate),
+            business_hours_start = COALESCE($6, business_hours_start),
+            business_hours_end = COALESCE($7, business_hours_end),
+            after_hours_multiplier = COALESCE($8, after_hours_multiplier),
+            weekend_multiplier = COALESCE($9, weekend_multiplier),
+            minimum_minutes = COALESCE($10, minimum_minutes),
+            increment_minutes = COALESCE($11, increment_minutes),
+            rounding = COALESCE($12, rounding),
+            is_active = COALESCE($13, is_active),
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(payload.name)
+    .bind(payload.description)
+    .bind(payload.is_default)
+    .bind(payload.base_rate)
+    .bind(payload.business_hours_start)
+    .bind(payload.business_hours_end)
+    .bind(payload.after_hours_multiplier)
+    .bind(payload.weekend_multiplier)
+    .bind(payload.minimum_minutes)
+    .bind(payload.increment_minutes)
+    .bind(payload.rounding)
+    .bind(payload.is_active)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error updating rate card: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Error committing rate card: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(load_rate_card(&state, id).await?))
+}
+
+/// Cards stay on file for the entries already priced with them.
+async fn deactivate_rate_card(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query(
+        "UPDATE rate_cards SET is_active = false, is_default = false, updated_at = NOW() WHERE id = $1",
+    )
+    .bind(id)
+    .execute(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error deactivating rate card: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn add_rate(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<RateInput>,
+) -> Result<(StatusCode, Json<RateCardWithRates>), StatusCode> {
+    if !valid_rate(&payload) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut tx = state.db_pool.begin().await.map_err(|e| {
+        tracing::error!("Error starting transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    insert_rate(&mut tx, id, &payload).await?;
+
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Error committing rate: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::CREATED, Json(load_rate_card(&state, id).await?)))
+}
+
+async fn delete_rate(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(rate_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query("DELETE FROM rate_card_rates WHERE id = $1")
+        .bind(rate_id)
+        .execute(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error deleting rate: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Shows what a piece of work would be billed at, e.g. when quoting a project budget.
+async fn preview_rate(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Json(payload): Json<PreviewRequest>,
+) -> Result<Json<PricedTime>, StatusCode> {
+    let work_type = WorkType::parse(&payload.work_type).ok_or(StatusCode::BAD_REQUEST)?;
+
+    let mut conn = state.db_pool.acquire().await.map_err(|e| {
+        tracing::error!("Error acquiring connection: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let (card, rates) = rate_cards::card_for(&mut conn, payload.client_id, payload.project_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error loading rate card: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(rate_cards::price(
+        &card,
+        &rates,
+        work_type,
+        payload.technician_role.as_deref(),
+        payload.local_start,
+        payload.minutes,
+    )))
+}
+
+/// Re-prices unbilled time after a card change.
+async fn reprice_entries(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Json(payload): Json<RepriceRequest>,
+) -> Result<Json<RepriceSummary>, StatusCode> {
+    let entry_ids: Vec<Uuid> = sqlx::query_scalar(
+        r#"
+        SELECT te.id FROM time_entries te
+        LEFT JOIN tickets t ON t.id = te.ticket_id
+        LEFT JOIN projects p ON p.id = te.project_id
+        WHERE te.billed = false AND te.invoice_id IS NULL AND te.duration_minutes IS NOT NULL
+          AND ($1::UUID IS NULL OR COALESCE(t.client_id, p.client_id) = $1)
+          AND ($2::DATE IS NULL OR te.start_time::date >= $2)
+        "#,
+    )
+    .bind(payload.client_id)
+    .bind(payload.since)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching unbilled time entries: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let mut summary = RepriceSummary {
+        entries_repriced: 0,
+        entries_failed: 0,
+    };
+    for id in entry_ids {
+        match rate_cards::price_time_entry(&state.db_pool, id).await {
+            Ok(_) => summary.entries_repriced += 1,
+            Err(e) => {
+                tracing::error!("Error pricing time entry {}: {}", id, e);
+                summary.entries_failed += 1;
+            }
+        }
+    }
+
+    Ok(Json(summary))
+}
+
+async fn load_rate_card(state: &AppState, id: Uuid) -> Result<RateCardWithRates, StatusCode> {
+    let card = sqlx::query_as::<_, RateCard>(&format!(
+        "SELECT {} FROM rate_cards rc WHERE rc.id = $1",
+        RATE_CARD_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Error fetching rate card: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    let rates = sqlx::query_as::<_, RateCardRate>(
+        "SELECT id, rate_card_id, work_type, technician_role, hourly_rate, created_at
+         FROM rate_card_rates WHERE rate_card_id = $1
+         ORDER BY work_type NULLS LAST, technician_role NULLS LAST",
+    )
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching rate card rates: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(RateCardWithRates { card, rates })
+}
+
+async fn clear_default(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<(), StatusCode> {
+    sqlx::query("UPDATE rate_cards SET is_default = false, updated_at = NOW() WHERE is_default = true")
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error clearing default rate card: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(())
+}
+
+/// Adds a rate row, replacing any existing row for the same work type and role.
+async fn insert_rate(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    rate_card_id: Uuid,
+    rate: &RateInput,
+) -> Result<(), StatusCode> {
+    sqlx::query(
+        r#"
+        INSERT INTO rate_card_rates (rate_card_id, work_type, technician_role, hourly_rate)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (rate_card_id, COALESCE(work_type, ''), COALESCE(technician_role, ''))
+        DO UPDATE SET hourly_rate = EXCLUDED.hourly_rate
+        "#,
+    )
+    .bind(rate_card_id)
+    .bind(&rate.work_type)
+    .bind(&rate.technician_role)
+    .bind(rate.hourly_rate)
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_foreign_key_violation() => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Error saving rate: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+    Ok(())
+}
+
+fn valid_rounding(rounding: Option<&str>) -> bool {
+    rounding.is_none_or(|r| Rounding::parse(r).is_some())
+}
+
+fn valid_rate(rate: &RateInput) -> bool {
+    rate.hourly_rate >= Decimal::ZERO
+        && rate.work_type.as_deref().is_none_or(|w| WorkType::parse(w).is_some())
+}
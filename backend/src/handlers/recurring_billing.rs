@@ -0,0 +1,661 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::services::billing_metrics::{self, BillingMetric, MetricReading};
+use crate::services::recurring_billing::{
+    base_period_amount, mid_cycle_adjustment, BillingRunSummary, PriceTier, RecurringItem, RecurringProfile,
+    ITEM_COLUMNS,
+};
+use crate::services::{RecurringBillingConfig, RecurringBillingService};
+use crate::AppState;
+
+pub fn recurring_billing_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_profiles).post(create_profile))
+        .route("/runs", get(list_runs).post(trigger_run))
+        .route("/:id", get(get_profile).put(update_profile))
+        .route("/backup-usage", post(report_backup_usage))
+        .route("/:id/usage", post(record_usage))
+        .route("/:id/metrics", get(preview_metrics))
+}
+
+const PROFILE_COLUMNS: &str = "id, client_id, name, description, billing_type, amount, quantity, unit_price,
+    frequency, billing_day, start_date, end_date, next_billing_date, last_billed_date,
+    payment_method_id, auto_charge, send_invoice, payment_terms_days, status, pricing_tiers, prorate, currency";
+
+#[derive(Debug, Serialize)]
+pub struct ProfileWithItems {
+    #[serde(flatten)]
+    pub profile: RecurringProfile,
+    pub items: Vec<RecurringItem>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProfileQuery {
+    pub client_id: Option<Uuid>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ItemInput {
+    pub item_type: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+    pub quantity: Option<Decimal>,
+    pub unit_price: Decimal,
+    pub discount_percent: Option<Decimal>,
+    pub tax_rate: Option<Decimal>,
+    /// Sales tax category; defaults to the item type
+    pub tax_category: Option<String>,
+    /// Bills the live value of this metric instead of `quantity`
+    pub metric_type: Option<String>,
+    pub metric_filter: Option<serde_json::Value>,
+    pub min_quantity: Option<Decimal>,
+}
+
+impl ItemInput {
+    fn total(&self) -> Decimal {
+        let gross = self.quantity.unwrap_or(Decimal::ONE) * self.unit_price;
+        let discount = gross * self.discount_percent.unwrap_or_default() / Decimal::from(100);
+        (gross - discount).round_dp(2)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProfileCreate {
+    pub client_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub billing_type: String,
+    pub amount: Option<Decimal>,
+    pub quantity: Option<i32>,
+    pub unit_price: Option<Decimal>,
+    pub frequency: String,
+    pub billing_day: Option<i32>,
+    pub start_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+    pub payment_method_id: Option<Uuid>,
+    pub auto_charge: Option<bool>,
+    pub send_invoice: Option<bool>,
+    pub payment_terms_days: Option<i32>,
+    pub contract_id: Option<Uuid>,
+    pub pricing_tiers: Option<Vec<PriceTier>>,
+    pub prorate: Option<bool>,
+    pub currency: Option<String>, // must match the client's currency; defaults to it
+    pub items: Option<Vec<ItemInput>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProfileUpdate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub amount: Option<Decimal>,
+    pub quantity: Option<i32>,
+    pub unit_price: Option<Decimal>,
+    pub end_date: Option<NaiveDate>,
+    pub payment_method_id: Option<Uuid>,
+    pub auto_charge: Option<bool>,
+    pub send_invoice: Option<bool>,
+    pub payment_terms_days: Option<i32>,
+    pub status: Option<String>,
+    pub pricing_tiers: Option<Vec<PriceTier>>,
+    pub prorate: Option<bool>,
+    pub items: Option<Vec<ItemInput>>,
+    /// When a price or quantity change takes effect; defaults to today
+    pub effective_date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageCreate {
+    pub usage_date: Option<NaiveDate>,
+    pub quantity: Decimal,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct UsageRecord {
+    pub id: Uuid,
+    pub recurring_billing_id: Uuid,
+    pub usage_date: NaiveDate,
+    pub quantity: Decimal,
+    pub description: Option<String>,
+    pub invoice_id: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct BillingRun {
+    pub id: Uuid,
+    pub run_date: NaiveDate,
+    pub status: Option<String>,
+    pub clients_processed: Option<i32>,
+    pub invoices_created: Option<i32>,
+    pub total_amount: Option<Decimal>,
+    pub error_message: Option<String>,
+    pub triggered_by: Option<Uuid>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RunRequest {
+    pub run_date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BackupUsageReport {
+    pub client_id: Uuid,
+    pub asset_id: Option<Uuid>,
+    pub provider: String,
+    pub protected_item: String,
+    pub storage_gb: Decimal,
+    pub recorded_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ItemMetricPreview {
+    pub item_id: Uuid,
+    pub name: String,
+    pub current_quantity: Option<Decimal>,
+    pub reading: MetricReading,
+}
+
+#[derive(Debug, FromRow)]
+struct BilledPeriodRow {
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+    days_in_cycle: i32,
+}
+
+const BILLING_TYPES: [&str; 4] = ["fixed", "usage_based", "tiered", "per_user"];
+pub(crate) const FREQUENCIES: [&str; 7] = ["daily", "weekly", "biweekly", "monthly", "quarterly", "semi_annually", "annually"];
+
+async fn list_profiles(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<ProfileQuery>,
+) -> Result<Json<Vec<RecurringProfile>>, StatusCode> {
+    let profiles = sqlx::query_as::<_, RecurringProfile>(&format!(
+        "SELECT {} FROM recurring_billing
+         WHERE ($1::UUID IS NULL OR client_id = $1) AND ($2::TEXT IS NULL OR status = $2)
+         ORDER BY next_billing_date, name",
+        PROFILE_COLUMNS
+    ))
+    .bind(params.client_id)
+    .bind(params.status)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching recurring billing profiles: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(profiles))
+}
+
+async fn get_profile(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ProfileWithItems>, StatusCode> {
+    Ok(Json(load_profile(&state, id).await?))
+}
+
+async fn create_profile(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<ProfileCreate>,
+) -> Result<(StatusCode, Json<ProfileWithItems>), StatusCode> {
+    if !BILLING_TYPES.contains(&payload.billing_type.as_str()) || !FREQUENCIES.contains(&payload.frequency.as_str()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if payload.end_date.map(|end| end < payload.start_date).unwrap_or(false) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let pricing_tiers = serde_json::to_value(payload.pricing_tiers.unwrap_or_default()).unwrap_or_default();
+
+    let mut tx = state.db_pool.begin().await.map_err(|e| {
+        tracing::error!("Error starting transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    // Recurring invoices are raised in the client's currency, so the profile is priced in it too
+    let client_currency: String = sqlx::query_scalar("SELECT currency FROM clients WHERE id = $1")
+        .bind(payload.client_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching client currency: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    if payload.currency.as_ref().is_some_and(|c| *c != client_currency) {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    // The first run bills from the start date, prorating up to the billing day if off-cycle
+    let id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO recurring_billing (client_id, name, description, billing_type, amount, quantity, unit_price,
+                                       frequency, billing_day, start_date, end_date, next_billing_date,
+                                       payment_method_id, auto_charge, send_invoice, payment_terms_days,
+                                       contract_id, pricing_tiers, prorate, created_by, currency)
+        VALUES ($1, $2, $3, $4, $5, COALESCE($6, 1), $7, $8, $9, $10, $11, $10, $12, COALESCE($13, false),
+                COALESCE($14, true), COALESCE($15, 30), $16, $17, COALESCE($18, true), $19, $20)
+        RETURNING id
+        "#,
+    )
+    .bind(payload.client_id)
+    .bind(&payload.name)
+    .bind(&payload.description)
+    .bind(&payload.billing_type)
+    .bind(payload.amount)
+    .bind(payload.quantity)
+    .bind(payload.unit_price)
+    .bind(&payload.frequency)
+    .bind(payload.billing_day)
+    .bind(payload.start_date)
+    .bind(payload.end_date)
+    .bind(payload.payment_method_id)
+    .bind(payload.auto_charge)
+    .bind(payload.send_invoice)
+    .bind(payload.payment_terms_days)
+    .bind(payload.contract_id)
+    .bind(pricing_tiers)
+    .bind(payload.prorate)
+    .bind(auth.0.id)
+    .bind(&client_currency)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error creating recurring billing profile: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if let Some(items) = &payload.items {
+        replace_items(&mut tx, id, items).await?;
+    }
+
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Error committing recurring billing profile: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::CREATED, Json(load_profile(&state, id).await?)))
+}
+
+/// Updates a profile. A price or quantity change that lands inside a period
+/// that has already been invoiced records a prorated adjustment, which the
+/// next billing run charges or credits.
+async fn update_profile(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<ProfileUpdate>,
+) -> Result<Json<ProfileWithItems>, StatusCode> {
+    let before = load_profile(&state, id).await?;
+    let previous_amount = base_period_amount(&before.profile, &before.items);
+    let effective_date = payload.effective_date.unwrap_or_else(|| Utc::now().date_naive());
+    let pricing_tiers = payload
+        .pricing_tiers
+        .as_ref()
+        .map(|tiers| serde_json::to_value(tiers).unwrap_or_default());
+
+    let mut tx = state.db_pool.begin().await.map_err(|e| {
+        tracing::error!("Error starting transaction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let updated = sqlx::query_as::<_, RecurringProfile>(&format!(
+        r#"
+        UPDATE recurring_billing SET
+            name = COALESCE($2, name),
+            description = COALESCE($3, description),
+            amount = COALESCE($4, amount),
+            quantity = COALESCE($5, quantity),
+            unit_price = COALESCE($6, unit_price),
+            end_date = COALESCE($7, end_date),
+            payment_method_id = COALESCE($8, payment_method_id),
+            auto_charge = COALESCE($9, auto_charge),
+            send_invoice = COALESCE($10, send_invoice),
+            payment_terms_days = COALESCE($11, payment_terms_days),
+            status = COALESCE($12, status),
+            pricing_tiers = COALESCE($13, pricing_tiers),
+            prorate = COALESCE($14, prorate),
+            updated_at = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        PROFILE_COLUMNS
+    ))
+    .bind(id)
+    .bind(payload.name)
+    .bind(payload.description)
+    .bind(payload.amount)
+    .bind(payload.quantity)
+    .bind(payload.unit_price)
+    .bind(payload.end_date)
+    .bind(payload.payment_method_id)
+    .bind(payload.auto_charge)
+    .bind(payload.send_invoice)
+    .bind(payload.payment_terms_days)
+    .bind(payload.status)
+    .bind(pricing_tiers)
+    .bind(payload.prorate)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error updating recurring billing profile: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let items = match &payload.items {
+        Some(items) => replace_items(&mut tx, id, items).await?,
+        None => before.items,
+    };
+
+    let new_amount = base_period_amount(&updated, &items);
+
+    if new_amount != previous_amount && updated.prorate.unwrap_or(true) {
+        let billed = sqlx::query_as::<_, BilledPeriodRow>(
+            "SELECT period_start, period_end, days_in_cycle FROM recurring_billing_periods
+             WHERE recurring_billing_id = $1 AND period_start <= $2 AND period_end >= $2",
+        )
+        .bind(id)
+        .bind(effective_date)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching billed period: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        if let Some(period) = billed {
+            let prorated_amount = mid_cycle_adjustment(
+                previous_amount,
+                new_amount,
+                effective_date,
+                period.period_end,
+                period.days_in_cycle as i64,
+            );
+
+            if !prorated_amount.is_zero() {
+                sqlx::query(
+                    r#"
+                    INSERT INTO recurring_billing_adjustments (recurring_billing_id, effective_date, period_end,
+                                                               previous_amount, new_amount, prorated_amount,
+                                                               description, created_by)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                    "#,
+                )
+                .bind(id)
+                .bind(effective_date)
+                .bind(period.period_end)
+                .bind(previous_amount)
+                .bind(new_amount)
+                .bind(prorated_amount)
+                .bind(format!(
+                    "{} - change from {} to {} effective {} (period {} to {})",
+                    updated.name,
+                    previous_amount,
+                    new_amount,
+                    effective_date.format("%Y-%m-%d"),
+                    period.period_start.format("%Y-%m-%d"),
+                    period.period_end.format("%Y-%m-%d")
+                ))
+                .bind(auth.0.id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| {
+                    tracing::error!("Error recording billing adjustment: {}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+            }
+        }
+    }
+
+    tx.commit().await.map_err(|e| {
+        tracing::error!("Error committing recurring billing profile: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(load_profile(&state, id).await?))
+}
+
+async fn record_usage(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UsageCreate>,
+) -> Result<(StatusCode, Json<UsageRecord>), StatusCode> {
+    let usage = sqlx::query_as::<_, UsageRecord>(
+        r#"
+        INSERT INTO recurring_billing_usage (recurring_billing_id, usage_date, quantity, description, created_by)
+        SELECT id, COALESCE($2, CURRENT_DATE), $3, $4, $5 FROM recurring_billing
+        WHERE id = $1 AND billing_type = 'usage_based'
+        RETURNING id, recurring_billing_id, usage_date, quantity, description, invoice_id, created_at
+        "#,
+    )
+    .bind(id)
+    .bind(payload.usage_date)
+    .bind(payload.quantity)
+    .bind(payload.description)
+    .bind(auth.0.id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Error recording usage: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok((StatusCode::CREATED, Json(usage)))
+}
+
+/// Live metric readings for a profile's metered items, compared with the quantity last billed.
+async fn preview_metrics(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<ItemMetricPreview>>, StatusCode> {
+    let ProfileWithItems { profile, items } = load_profile(&state, id).await?;
+
+    let mut conn = state.db_pool.acquire().await.map_err(|e| {
+        tracing::error!("Error acquiring connection: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let mut previews = Vec::new();
+    for item in items {
+        let Some(metric) = item.metric_type.as_deref().and_then(BillingMetric::parse) else {
+            continue;
+        };
+
+        let reading = billing_metrics::read(&mut conn, profile.client_id, metric, &item)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error measuring billing metric: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        previews.push(ItemMetricPreview {
+            item_id: item.id,
+            name: item.name,
+            current_quantity: item.quantity,
+            reading,
+        });
+    }
+
+    Ok(Json(previews))
+}
+
+async fn report_backup_usage(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Json(payload): Json<Vec<BackupUsageReport>>,
+) -> Result<StatusCode, StatusCode> {
+    for report in payload {
+        if report.storage_gb < Decimal::ZERO {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+
+        sqlx::query(
+            "INSERT INTO backup_storage_usage (client_id, asset_id, provider, protected_item, storage_gb, recorded_at)
+             VALUES ($1, $2, $3, $4, $5, COALESCE($6, NOW()))",
+        )
+        .bind(report.client_id)
+        .bind(report.asset_id)
+        .bind(&report.provider)
+        .bind(&report.protected_item)
+        .bind(report.storage_gb)
+        .bind(report.recorded_at)
+        .execute(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error recording backup usage: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    }
+
+    Ok(StatusCode::CREATED)
+}
+
+async fn list_runs(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+) -> Result<Json<Vec<BillingRun>>, StatusCode> {
+    let runs = sqlx::query_as::<_, BillingRun>(
+        "SELECT id, run_date, status, clients_processed, invoices_created, total_amount, error_message,
+                triggered_by, started_at, completed_at
+         FROM billing_runs ORDER BY run_date DESC LIMIT 100",
+    )
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching billing runs: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(runs))
+}
+
+/// Runs billing now. Safe to repeat: periods already invoiced are skipped.
+async fn trigger_run(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<RunRequest>,
+) -> Result<Json<BillingRunSummary>, StatusCode> {
+    let run_date = payload.run_date.unwrap_or_else(|| Utc::now().date_naive());
+    let service = RecurringBillingService::new(RecurringBillingConfig::default(), state.db_pool.clone());
+
+    service.run_billing(run_date, Some(auth.0.id)).await.map(Json).map_err(|e| {
+        tracing::error!("Error running recurring billing: {}", e);
+        if e.to_string().contains("already in progress") {
+            StatusCode::CONFLICT
+        } else {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })
+}
+
+async fn load_profile(state: &AppState, id: Uuid) -> Result<ProfileWithItems, StatusCode> {
+    let profile = sqlx::query_as::<_, RecurringProfile>(&format!(
+        "SELECT {} FROM recurring_billing WHERE id = $1",
+        PROFILE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Error fetching recurring billing profile: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    let items = sqlx::query_as::<_, RecurringItem>(&format!(
+        "SELECT {} FROM recurring_billing_items WHERE recurring_billing_id = $1 ORDER BY created_at",
+        ITEM_COLUMNS
+    ))
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching recurring billing items: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(ProfileWithItems { profile, items })
+}
+
+pub(crate) async fn replace_items(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    profile_id: Uuid,
+    items: &[ItemInput],
+) -> Result<Vec<RecurringItem>, StatusCode> {
+    sqlx::query("DELETE FROM recurring_billing_items WHERE recurring_billing_id = $1")
+        .bind(profile_id)
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error clearing recurring billing items: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let mut saved = Vec::with_capacity(items.len());
+    for item in items {
+        if item.metric_type.as_deref().is_some_and(|m| BillingMetric::parse(m).is_none()) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+
+        let row = sqlx::query_as::<_, RecurringItem>(&format!(
+            r#"
+            INSERT INTO recurring_billing_items (recurring_billing_id, item_type, name, description, quantity,
+                                                 unit_price, discount_percent, tax_rate, total,
+                                                 metric_type, metric_filter, min_quantity, tax_category)
+            VALUES ($1, COALESCE($2, 'service'), $3, $4, COALESCE($5, 1), $6, COALESCE($7, 0), COALESCE($8, 0), $9,
+                    $10, COALESCE($11, '{{}}'::jsonb), $12, $13)
+            RETURNING {}
+            "#,
+            ITEM_COLUMNS
+        ))
+        .bind(profile_id)
+        .bind(&item.item_type)
+        .bind(&item.name)
+        .bind(&item.description)
+        .bind(item.quantity)
+        .bind(item.unit_price)
+        .bind(item.discount_percent)
+        .bind(item.tax_rate)
+        .bind(item.total())
+        .bind(&item.metric_type)
+        .bind(&item.metric_filter)
+        .bind(item.min_quantity)
+        .bind(&item.tax_category)
+        .fetch_one(&mut **tx)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error saving recurring billing item: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        saved.push(row);
+    }
+
+    Ok(saved)
+}
@@ -2,16 +2,23 @@ use axum::{
     extract::{Path, Query, State},
     http::StatusCode,
     response::Json,
-    routing::{get, post},
+    routing::{get, post, put},
     Router,
 };
-use chrono::Utc;
+use chrono::{NaiveDate, Utc};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use std::sync::Arc;
 use uuid::Uuid;
 use crate::AppState;
 use crate::auth::middleware::AuthUser;
+use crate::handlers::internal;
+use crate::services::profitability::{
+    self, BudgetVariance, ClientBudget, ClientProfitability, ContractProfitability, ProfitabilityConfig,
+    ProfitabilityRunSummary, ProfitabilityService, BUDGET_CATEGORIES, BUDGET_COLUMNS, BUDGET_TYPES,
+    CONTRACT_PROFITABILITY_COLUMNS, PROFITABILITY_COLUMNS,
+};
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Report {
@@ -121,6 +128,78 @@ pub fn reporting_routes() -> Router<Arc<AppState>> {
         .route("/client-health/:client_id", get(get_client_health_score))
         .route("/dashboard/stats", get(get_dashboard_stats))
         .route("/dashboard/widgets", get(get_dashboard_widgets))
+        .route("/profitability", get(list_profitability))
+        .route("/profitability/summary", get(get_profitability_summary))
+        .route("/profitability/agreements", get(list_agreement_profitability))
+        .route("/profitability/run", post(run_profitability))
+        .route("/budgets", get(list_budgets).post(create_budget))
+        .route("/budgets/variance", get(get_budget_variance))
+        .route("/budgets/:id", put(update_budget))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProfitabilityQuery {
+    pub period: Option<NaiveDate>, // any day in the month; the latest calculated month when omitted
+    pub client_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProfitabilitySummaryQuery {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub client_id: Option<Uuid>,
+}
+
+/// A client's profitability totalled over a date range.
+#[derive(Debug, Serialize, FromRow)]
+pub struct ProfitabilitySummary {
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub months: i64,
+    pub total_revenue: Decimal,
+    pub labor_cost: Decimal,
+    pub expense_cost: Decimal,
+    pub license_cost: Decimal,
+    pub total_cost: Decimal,
+    pub gross_profit: Decimal,
+    pub gross_margin_percent: Decimal,
+    pub hours_worked: Decimal,
+    pub effective_hourly_rate: Option<Decimal>,
+    pub write_off_amount: Decimal,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RunProfitabilityRequest {
+    pub month: Option<NaiveDate>, // the current and previous month when omitted
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BudgetQuery {
+    pub client_id: Option<Uuid>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBudgetRequest {
+    pub client_id: Uuid,
+    pub name: String,
+    pub budget_type: String,
+    pub category: Option<String>,
+    pub amount: Decimal,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub alert_threshold_percent: Option<i32>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateBudgetRequest {
+    pub name: Option<String>,
+    pub amount: Option<Decimal>,
+    pub end_date: Option<NaiveDate>,
+    pub alert_threshold_percent: Option<i32>,
+    pub status: Option<String>,
+    pub notes: Option<String>,
 }
 
 async fn list_reports(
@@ -480,4 +559,280 @@ async fn get_dashboard_widgets(
     ];
 
     Ok(Json(widgets))
-}
\ No newline at end of file
+}
+
+/// Monthly profitability per client, loss-making clients first.
+async fn list_profitability(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<ProfitabilityQuery>,
+) -> Result<Json<Vec<ClientProfitability>>, StatusCode> {
+    let period = match params.period {
+        Some(date) => Some(profitability::month_bounds(date).0),
+        None => sqlx::query_scalar::<_, Option<NaiveDate>>("SELECT MAX(period_start) FROM client_profitability")
+            .fetch_one(&state.db_pool)
+            .await
+            .map_err(internal("fetching latest profitability period"))?,
+    };
+    let Some(period) = period else {
+        return Ok(Json(Vec::new()));
+    };
+
+    let rows = sqlx::query_as::<_, ClientProfitability>(&format!(
+        "SELECT {} FROM client_profitability p JOIN clients c ON c.id = p.client_id
+         WHERE p.period_start = $1 AND ($2::UUID IS NULL OR p.client_id = $2)
+         ORDER BY p.gross_margin_percent ASC, c.name",
+        PROFITABILITY_COLUMNS
+    ))
+    .bind(period)
+    .bind(params.client_id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching client profitability"))?;
+
+    Ok(Json(rows))
+}
+
+async fn get_profitability_summary(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<ProfitabilitySummaryQuery>,
+) -> Result<Json<Vec<ProfitabilitySummary>>, StatusCode> {
+    if params.to < params.from {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let rows = sqlx::query_as::<_, ProfitabilitySummary>(
+        r#"
+        SELECT p.client_id, c.name as client_name, COUNT(*) as months,
+               COALESCE(SUM(p.total_revenue), 0) as total_revenue,
+               COALESCE(SUM(p.labor_cost), 0) as labor_cost,
+               COALESCE(SUM(p.expense_cost), 0) as expense_cost,
+               COALESCE(SUM(p.license_cost), 0) as license_cost,
+               COALESCE(SUM(p.total_cost), 0) as total_cost,
+               COALESCE(SUM(p.gross_profit), 0) as gross_profit,
+               CASE WHEN COALESCE(SUM(p.total_revenue), 0) = 0 THEN 0
+                    ELSE ROUND(SUM(p.gross_profit) * 100 / SUM(p.total_revenue), 2) END as gross_margin_percent,
+               COALESCE(SUM(p.hours_worked), 0) as hours_worked,
+               CASE WHEN COALESCE(SUM(p.hours_worked), 0) = 0 THEN NULL
+                    ELSE ROUND((SUM(p.total_revenue) - SUM(p.expense_cost) - SUM(COALESCE(p.license_cost, 0)))
+                               / SUM(p.hours_worked), 2) END as effective_hourly_rate,
+               COALESCE(SUM(p.write_off_amount), 0) as write_off_amount
+        FROM client_profitability p
+        JOIN clients c ON c.id = p.client_id
+        WHERE p.period_start >= DATE_TRUNC('month', $1::DATE)::DATE AND p.period_start <= $2
+          AND ($3::UUID IS NULL OR p.client_id = $3)
+        GROUP BY p.client_id, c.name
+        ORDER BY gross_margin_percent ASC, c.name
+        "#,
+    )
+    .bind(params.from)
+    .bind(params.to)
+    .bind(params.client_id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("summarising client profitability"))?;
+
+    Ok(Json(rows))
+}
+
+/// Effective hourly rate per agreement, lowest first, against the agreement's own rate.
+async fn list_agreement_profitability(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<ProfitabilityQuery>,
+) -> Result<Json<Vec<ContractProfitability>>, StatusCode> {
+    let period = match params.period {
+        Some(date) => Some(profitability::month_bounds(date).0),
+        None => sqlx::query_scalar::<_, Option<NaiveDate>>("SELECT MAX(period_start) FROM contract_profitability")
+            .fetch_one(&state.db_pool)
+            .await
+            .map_err(internal("fetching latest agreement profitability period"))?,
+    };
+    let Some(period) = period else {
+        return Ok(Json(Vec::new()));
+    };
+
+    let rows = sqlx::query_as::<_, ContractProfitability>(&format!(
+        "SELECT {} FROM contract_profitability cp
+         JOIN contracts ct ON ct.id = cp.contract_id
+         JOIN clients c ON c.id = cp.client_id
+         WHERE cp.period_start = $1 AND ($2::UUID IS NULL OR cp.client_id = $2)
+         ORDER BY cp.effective_hourly_rate ASC NULLS LAST, c.name",
+        CONTRACT_PROFITABILITY_COLUMNS
+    ))
+    .bind(period)
+    .bind(params.client_id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching agreement profitability"))?;
+
+    Ok(Json(rows))
+}
+
+/// Recalculates now rather than waiting for the nightly run.
+async fn run_profitability(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Json(payload): Json<RunProfitabilityRequest>,
+) -> Result<Json<ProfitabilityRunSummary>, StatusCode> {
+    let service = ProfitabilityService::new(ProfitabilityConfig::default(), state.db_pool.clone());
+    let today = Utc::now().date_naive();
+
+    let summary = match payload.month {
+        Some(month) => {
+            let mut summary = service.calculate_month(month).await.map_err(internal("calculating profitability"))?;
+            service
+                .update_budgets(today, &mut summary)
+                .await
+                .map_err(internal("updating client budgets"))?;
+            summary
+        }
+        None => service.run(today).await.map_err(internal("calculating profitability"))?,
+    };
+
+    Ok(Json(summary))
+}
+
+async fn list_budgets(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<BudgetQuery>,
+) -> Result<Json<Vec<ClientBudget>>, StatusCode> {
+    let budgets = sqlx::query_as::<_, ClientBudget>(&format!(
+        "SELECT {} FROM client_budgets b JOIN clients c ON c.id = b.client_id
+         WHERE ($1::UUID IS NULL OR b.client_id = $1) AND ($2::TEXT IS NULL OR b.status = $2)
+         ORDER BY b.end_date DESC, c.name",
+        BUDGET_COLUMNS
+    ))
+    .bind(params.client_id)
+    .bind(params.status)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching client budgets"))?;
+
+    Ok(Json(budgets))
+}
+
+async fn create_budget(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<CreateBudgetRequest>,
+) -> Result<Json<ClientBudget>, StatusCode> {
+    if payload.name.trim().is_empty()
+        || !BUDGET_TYPES.contains(&payload.budget_type.as_str())
+        || payload.category.as_deref().is_some_and(|c| !BUDGET_CATEGORIES.contains(&c))
+        || payload.amount <= Decimal::ZERO
+        || payload.end_date < payload.start_date
+        || payload.alert_threshold_percent.is_some_and(|p| !(1..=100).contains(&p))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let id: Uuid = sqlx::query_scalar(
+        "INSERT INTO client_budgets (client_id, name, budget_type, category, amount, start_date, end_date,
+             remaining_amount, alert_threshold_percent, notes, created_by)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $5, COALESCE($8, 80), $9, $10)
+         RETURNING id",
+    )
+    .bind(payload.client_id)
+    .bind(payload.name.trim())
+    .bind(&payload.budget_type)
+    .bind(&payload.category)
+    .bind(payload.amount)
+    .bind(payload.start_date)
+    .bind(payload.end_date)
+    .bind(payload.alert_threshold_percent)
+    .bind(&payload.notes)
+    .bind(auth.0.id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(internal("creating client budget"))?;
+
+    fetch_budget(&state, id).await.map(Json)
+}
+
+/// Raising the amount or threshold re-arms the alerts it invalidates; the next
+/// run sends them again if they still apply.
+async fn update_budget(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateBudgetRequest>,
+) -> Result<Json<ClientBudget>, StatusCode> {
+    if payload.amount.is_some_and(|a| a <= Decimal::ZERO)
+        || payload.alert_threshold_percent.is_some_and(|p| !(1..=100).contains(&p))
+        || payload.status.as_deref().is_some_and(|s| !["active", "exceeded", "closed"].contains(&s))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let result = sqlx::query(
+        "UPDATE client_budgets SET
+             name = COALESCE($2, name),
+             amount = COALESCE($3, amount),
+             remaining_amount = COALESCE($3, amount) - COALESCE(spent_amount, 0),
+             end_date = COALESCE($4, end_date),
+             alert_threshold_percent = COALESCE($5, alert_threshold_percent),
+             status = COALESCE($6, status),
+             notes = COALESCE($7, notes),
+             alert_sent = CASE WHEN $3 IS NOT NULL OR $5 IS NOT NULL THEN false ELSE alert_sent END,
+             exceeded_alert_sent_at = CASE WHEN $3 IS NOT NULL THEN NULL ELSE exceeded_alert_sent_at END,
+             updated_at = NOW()
+         WHERE id = $1 AND COALESCE($4, end_date) >= start_date",
+    )
+    .bind(id)
+    .bind(payload.name.as_deref().map(str::trim).filter(|n| !n.is_empty()))
+    .bind(payload.amount)
+    .bind(payload.end_date)
+    .bind(payload.alert_threshold_percent)
+    .bind(&payload.status)
+    .bind(&payload.notes)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("updating client budget"))?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    fetch_budget(&state, id).await.map(Json)
+}
+
+/// Budget versus actual for open budgets, with spend projected to the end of each budget.
+async fn get_budget_variance(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<BudgetQuery>,
+) -> Result<Json<Vec<BudgetVariance>>, StatusCode> {
+    let budgets = sqlx::query_as::<_, ClientBudget>(&format!(
+        "SELECT {} FROM client_budgets b JOIN clients c ON c.id = b.client_id
+         WHERE ($1::UUID IS NULL OR b.client_id = $1)
+           AND CASE WHEN $2::TEXT IS NULL THEN COALESCE(b.status, 'active') IN ('active', 'exceeded')
+                    ELSE b.status = $2 END
+         ORDER BY b.end_date, c.name",
+        BUDGET_COLUMNS
+    ))
+    .bind(params.client_id)
+    .bind(params.status)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching client budgets"))?;
+
+    let today = Utc::now().date_naive();
+    let mut variances: Vec<BudgetVariance> = budgets.into_iter().map(|b| BudgetVariance::new(b, today)).collect();
+    variances.sort_by(|a, b| a.projected_variance.cmp(&b.projected_variance));
+
+    Ok(Json(variances))
+}
+
+async fn fetch_budget(state: &AppState, id: Uuid) -> Result<ClientBudget, StatusCode> {
+    sqlx::query_as::<_, ClientBudget>(&format!(
+        "SELECT {} FROM client_budgets b JOIN clients c ON c.id = b.client_id WHERE b.id = $1",
+        BUDGET_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(internal("fetching client budget"))?
+    .ok_or(StatusCode::NOT_FOUND)
+}
@@ -0,0 +1,805 @@
+use axum::{
+    extract::{Multipart, Path, Query, State},
+    http::StatusCode,
+    response::Json,
+    routing::{get, post, put},
+    Router,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::files::{store_file, FileLinks};
+use crate::services::sales_tax::{
+    self, ExemptionCertificate, LineTax, TaxContext, TaxJurisdiction, CERTIFICATE_COLUMNS, JURISDICTION_COLUMNS,
+};
+use crate::AppState;
+
+pub fn sales_tax_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/jurisdictions", get(list_jurisdictions).post(create_jurisdiction))
+        .route("/jurisdictions/:id", get(get_jurisdiction).put(update_jurisdiction))
+        .route("/jurisdictions/:id/rules", put(set_category_rule))
+        .route("/categories", get(list_categories).post(create_category))
+        .route("/categories/:code", put(update_category))
+        .route("/clients/:client_id", get(get_client_tax).put(update_client_profile))
+        .route("/certificates", get(list_certificates).post(create_certificate))
+        .route("/certificates/:id/revoke", post(revoke_certificate))
+        .route("/certificates/:id/file", post(upload_certificate_file))
+        .route("/preview", post(preview_tax))
+        .route("/liability", get(liability_report))
+}
+
+#[derive(Debug, Serialize)]
+pub struct JurisdictionWithRules {
+    #[serde(flatten)]
+    pub jurisdiction: TaxJurisdiction,
+    pub rules: Vec<CategoryRule>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct CategoryRule {
+    pub category_code: String,
+    pub is_taxable: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JurisdictionQuery {
+    pub state_code: Option<String>,
+    pub include_inactive: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JurisdictionCreate {
+    pub name: String,
+    pub level: String,
+    pub state_code: String,
+    pub county: Option<String>,
+    pub city: Option<String>,
+    pub rate: Decimal,
+    pub is_compound: Option<bool>,
+    pub priority: Option<i32>,
+    pub effective_from: Option<NaiveDate>,
+    pub effective_to: Option<NaiveDate>,
+}
+
+/// Rate changes should be entered as a new jurisdiction row with
+/// `effective_from`, ending the old one, so past invoices stay explainable.
+#[derive(Debug, Deserialize)]
+pub struct JurisdictionUpdate {
+    pub name: Option<String>,
+    pub rate: Option<Decimal>,
+    pub is_compound: Option<bool>,
+    pub priority: Option<i32>,
+    pub effective_to: Option<NaiveDate>,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct TaxCategory {
+    pub code: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub is_taxable: bool,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryCreate {
+    pub code: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub is_taxable: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryUpdate {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub is_taxable: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ClientTaxProfile {
+    pub client_id: Uuid,
+    pub state_code: Option<String>,
+    pub county: Option<String>,
+    pub city: Option<String>,
+    pub postal_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClientTaxProfileUpdate {
+    pub state_code: Option<String>,
+    pub county: Option<String>,
+    pub city: Option<String>,
+    pub postal_code: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClientTax {
+    pub profile: Option<ClientTaxProfile>,
+    pub jurisdictions: Vec<TaxJurisdiction>, // those that apply today
+    pub certificates: Vec<ExemptionCertificate>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CertificateQuery {
+    pub client_id: Option<Uuid>,
+    pub include_revoked: Option<bool>,
+    pub expiring_within_days: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CertificateCreate {
+    pub client_id: Uuid,
+    pub certificate_number: String,
+    pub state_code: String,
+    pub jurisdiction_id: Option<Uuid>,
+    pub category_codes: Option<Vec<String>>,
+    pub exemption_reason: String,
+    pub issued_date: NaiveDate,
+    pub expiry_date: Option<NaiveDate>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewRequest {
+    pub client_id: Uuid,
+    pub date: Option<NaiveDate>,
+    pub lines: Vec<PreviewLine>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewLine {
+    pub description: Option<String>,
+    pub amount: Decimal,
+    pub tax_category: Option<String>,
+    pub tax_rate: Option<Decimal>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreviewLineResult {
+    pub description: Option<String>,
+    pub amount: Decimal,
+    pub tax_amount: Decimal,
+    pub taxes: Vec<LineTax>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreviewResult {
+    pub subtotal: Decimal,
+    pub tax_amount: Decimal,
+    pub total: Decimal,
+    pub lines: Vec<PreviewLineResult>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LiabilityQuery {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub state_code: Option<String>,
+    pub period: Option<String>, // month (default), quarter, year
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct LiabilityRow {
+    pub period_start: NaiveDate,
+    pub jurisdiction_id: Option<Uuid>,
+    pub jurisdiction_name: String,
+    pub level: String,
+    pub state_code: Option<String>,
+    pub currency: String, // returns are filed in the currency the tax was collected in
+    pub invoice_count: i64,
+    pub taxable_sales: Decimal,
+    pub non_taxable_sales: Decimal,
+    pub exempt_sales: Decimal, // covered by a client certificate
+    pub tax_collected: Decimal,
+}
+
+async fn list_jurisdictions(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<JurisdictionQuery>,
+) -> Result<Json<Vec<TaxJurisdiction>>, StatusCode> {
+    let jurisdictions = sqlx::query_as::<_, TaxJurisdiction>(&format!(
+        "SELECT {} FROM tax_jurisdictions j
+         WHERE ($1::TEXT IS NULL OR j.state_code = UPPER($1)) AND ($2 OR j.is_active = true)
+         ORDER BY j.state_code, CASE j.level WHEN 'state' THEN 0 WHEN 'county' THEN 1 WHEN 'city' THEN 2 ELSE 3 END,
+                  j.county NULLS FIRST, j.city NULLS FIRST, j.effective_from DESC",
+        JURISDICTION_COLUMNS
+    ))
+    .bind(params.state_code)
+    .bind(params.include_inactive.unwrap_or(false))
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching tax jurisdictions: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(jurisdictions))
+}
+
+async fn get_jurisdiction(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<JurisdictionWithRules>, StatusCode> {
+    Ok(Json(load_jurisdiction(&state, id).await?))
+}
+
+async fn create_jurisdiction(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Json(payload): Json<JurisdictionCreate>,
+) -> Result<(StatusCode, Json<JurisdictionWithRules>), StatusCode> {
+    let county = payload.county.as_deref().map(str::trim).filter(|c| !c.is_empty());
+    let city = payload.city.as_deref().map(str::trim).filter(|c| !c.is_empty());
+    let located = match payload.level.as_str() {
+        "state" => county.is_none() && city.is_none(),
+        "county" => county.is_some() && city.is_none(),
+        "city" => city.is_some(),
+        "special" => true,
+        _ => false,
+    };
+    if !located
+        || payload.name.trim().is_empty()
+        || payload.state_code.trim().len() != 2
+        || payload.rate < Decimal::ZERO
+        || payload.rate >= Decimal::from(100)
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO tax_jurisdictions (name, level, state_code, county, city, rate, is_compound, priority,
+                                       effective_from, effective_to)
+        VALUES ($1, $2, UPPER($3), $4, $5, $6, $7, $8, COALESCE($9, CURRENT_DATE), $10)
+        RETURNING id
+        "#,
+    )
+    .bind(payload.name.trim())
+    .bind(&payload.level)
+    .bind(payload.state_code.trim())
+    .bind(county)
+    .bind(city)
+    .bind(payload.rate)
+    .bind(payload.is_compound.unwrap_or(false))
+    .bind(payload.priority.unwrap_or(0))
+    .bind(payload.effective_from)
+    .bind(payload.effective_to)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error creating tax jurisdiction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::CREATED, Json(load_jurisdiction(&state, id).await?)))
+}
+
+async fn update_jurisdiction(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<JurisdictionUpdate>,
+) -> Result<Json<JurisdictionWithRules>, StatusCode> {
+    if payload.rate.is_some_and(|r| r < Decimal::ZERO || r >= Decimal::from(100)) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let result = sqlx::query(
+        r#"
+        UPDATE tax_jurisdictions SET
+            name = COALESCE($2, name),
+            rate = COALESCE($3, rate),
+            is_compound = COALESCE($4, is_compound),
+            priority = COALESCE($5, priority),
+            effective_to = COALESCE($6, effective_to),
+            is_active = COALESCE($7, is_active),
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(payload.name.as_deref().map(str::trim).filter(|n| !n.is_empty()))
+    .bind(payload.rate)
+    .bind(payload.is_compound)
+    .bind(payload.priority)
+    .bind(payload.effective_to)
+    .bind(payload.is_active)
+    .execute(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error updating tax jurisdiction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(Json(load_jurisdiction(&state, id).await?))
+}
+
+/// Overrides whether a category is taxable in one jurisdiction. Sending
+/// `is_taxable: null` removes the override.
+async fn set_category_rule(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<CategoryRuleUpdate>,
+) -> Result<Json<JurisdictionWithRules>, StatusCode> {
+    let query = match payload.is_taxable {
+        Some(is_taxable) => sqlx::query(
+            "INSERT INTO tax_category_rules (jurisdiction_id, category_code, is_taxable) VALUES ($1, $2, $3)
+             ON CONFLICT (jurisdiction_id, category_code) DO UPDATE SET is_taxable = EXCLUDED.is_taxable",
+        )
+        .bind(id)
+        .bind(&payload.category_code)
+        .bind(is_taxable),
+        None => sqlx::query("DELETE FROM tax_category_rules WHERE jurisdiction_id = $1 AND category_code = $2")
+            .bind(id)
+            .bind(&payload.category_code),
+    };
+
+    query.execute(&state.db_pool).await.map_err(|e| match e {
+        // Unknown jurisdiction or category
+        sqlx::Error::Database(db) if db.is_foreign_key_violation() => StatusCode::BAD_REQUEST,
+        e => {
+            tracing::error!("Error saving tax category rule: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok(Json(load_jurisdiction(&state, id).await?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryRuleUpdate {
+    pub category_code: String,
+    pub is_taxable: Option<bool>,
+}
+
+async fn list_categories(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+) -> Result<Json<Vec<TaxCategory>>, StatusCode> {
+    let categories = sqlx::query_as::<_, TaxCategory>(
+        "SELECT code, name, description, is_taxable, created_at FROM tax_categories ORDER BY name",
+    )
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching tax categories: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(categories))
+}
+
+async fn create_category(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Json(payload): Json<CategoryCreate>,
+) -> Result<(StatusCode, Json<TaxCategory>), StatusCode> {
+    let code = payload.code.trim().to_lowercase();
+    if code.is_empty() || payload.name.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let category = sqlx::query_as::<_, TaxCategory>(
+        "INSERT INTO tax_categories (code, name, description, is_taxable) VALUES ($1, $2, $3, $4)
+         RETURNING code, name, description, is_taxable, created_at",
+    )
+    .bind(code)
+    .bind(payload.name.trim())
+    .bind(payload.description)
+    .bind(payload.is_taxable)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => StatusCode::CONFLICT,
+        e => {
+            tracing::error!("Error creating tax category: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok((StatusCode::CREATED, Json(category)))
+}
+
+async fn update_category(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(code): Path<String>,
+    Json(payload): Json<CategoryUpdate>,
+) -> Result<Json<TaxCategory>, StatusCode> {
+    let category = sqlx::query_as::<_, TaxCategory>(
+        "UPDATE tax_categories SET name = COALESCE($2, name), description = COALESCE($3, description),
+                is_taxable = COALESCE($4, is_taxable)
+         WHERE code = $1
+         RETURNING code, name, description, is_taxable, created_at",
+    )
+    .bind(code)
+    .bind(payload.name.as_deref().map(str::trim).filter(|n| !n.is_empty()))
+    .bind(payload.description)
+    .bind(payload.is_taxable)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Error updating tax category: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok(Json(category))
+}
+
+/// The client's tax location, the jurisdictions it resolves to today and its
+/// certificates.
+async fn get_client_tax(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(client_id): Path<Uuid>,
+) -> Result<Json<ClientTax>, StatusCode> {
+    let today = Utc::now().date_naive();
+
+    let mut conn = state.db_pool.acquire().await.map_err(|e| {
+        tracing::error!("Error acquiring connection: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM clients WHERE id = $1)")
+        .bind(client_id)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching client: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !exists {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let profile = sqlx::query_as::<_, ClientTaxProfile>(
+        "SELECT client_id, state_code, county, city, postal_code FROM client_tax_profiles WHERE client_id = $1",
+    )
+    .bind(client_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching client tax profile: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let context = TaxContext::load(&mut *conn, client_id, today).await.map_err(|e| {
+        tracing::error!("Error loading client tax rates: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let certificates = sqlx::query_as::<_, ExemptionCertificate>(&format!(
+        "SELECT {} FROM tax_exemption_certificates WHERE client_id = $1 ORDER BY issued_date DESC",
+        CERTIFICATE_COLUMNS
+    ))
+    .bind(client_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching exemption certificates: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(ClientTax {
+        profile,
+        jurisdictions: context.jurisdictions().to_vec(),
+        certificates,
+    }))
+}
+
+async fn update_client_profile(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(client_id): Path<Uuid>,
+    Json(payload): Json<ClientTaxProfileUpdate>,
+) -> Result<Json<ClientTax>, StatusCode> {
+    if payload.state_code.as_deref().is_some_and(|s| s.trim().len() != 2) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO client_tax_profiles (client_id, state_code, county, city, postal_code)
+        VALUES ($1, UPPER($2), $3, $4, $5)
+        ON CONFLICT (client_id) DO UPDATE SET
+            state_code = EXCLUDED.state_code, county = EXCLUDED.county, city = EXCLUDED.city,
+            postal_code = EXCLUDED.postal_code, updated_at = NOW()
+        "#,
+    )
+    .bind(client_id)
+    .bind(payload.state_code.as_deref().map(str::trim))
+    .bind(payload.county.as_deref().map(str::trim).filter(|c| !c.is_empty()))
+    .bind(payload.city.as_deref().map(str::trim).filter(|c| !c.is_empty()))
+    .bind(payload.postal_code.as_deref().map(str::trim).filter(|c| !c.is_empty()))
+    .execute(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_foreign_key_violation() => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Error saving client tax profile: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    get_client_tax(State(state), auth, Path(client_id)).await
+}
+
+async fn list_certificates(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<CertificateQuery>,
+) -> Result<Json<Vec<ExemptionCertificate>>, StatusCode> {
+    let certificates = sqlx::query_as::<_, ExemptionCertificate>(&format!(
+        "SELECT {} FROM tax_exemption_certificates
+         WHERE ($1::UUID IS NULL OR client_id = $1)
+           AND ($2 OR revoked_at IS NULL)
+           AND ($3::INT IS NULL OR (expiry_date IS NOT NULL AND expiry_date <= CURRENT_DATE + $3))
+         ORDER BY expiry_date NULLS LAST, issued_date DESC",
+        CERTIFICATE_COLUMNS
+    ))
+    .bind(params.client_id)
+    .bind(params.include_revoked.unwrap_or(false))
+    .bind(params.expiring_within_days)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching exemption certificates: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(certificates))
+}
+
+async fn create_certificate(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<CertificateCreate>,
+) -> Result<(StatusCode, Json<ExemptionCertificate>), StatusCode> {
+    if payload.certificate_number.trim().is_empty()
+        || payload.state_code.trim().len() != 2
+        || payload.exemption_reason.trim().is_empty()
+        || payload.expiry_date.is_some_and(|d| d < payload.issued_date)
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let certificate = sqlx::query_as::<_, ExemptionCertificate>(&format!(
+        r#"
+        INSERT INTO tax_exemption_certificates (client_id, certificate_number, state_code, jurisdiction_id,
+                                                category_codes, exemption_reason, issued_date, expiry_date,
+                                                notes, created_by)
+        VALUES ($1, $2, UPPER($3), $4, $5, $6, $7, $8, $9, $10)
+        RETURNING {}
+        "#,
+        CERTIFICATE_COLUMNS
+    ))
+    .bind(payload.client_id)
+    .bind(payload.certificate_number.trim())
+    .bind(payload.state_code.trim())
+    .bind(payload.jurisdiction_id)
+    .bind(payload.category_codes.filter(|c| !c.is_empty()))
+    .bind(payload.exemption_reason.trim())
+    .bind(payload.issued_date)
+    .bind(payload.expiry_date)
+    .bind(payload.notes)
+    .bind(auth.0.id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_foreign_key_violation() => StatusCode::BAD_REQUEST,
+        e => {
+            tracing::error!("Error creating exemption certificate: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok((StatusCode::CREATED, Json(certificate)))
+}
+
+/// Revoking stops the certificate applying to new invoices; invoices already
+/// issued under it keep their exemption.
+async fn revoke_certificate(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ExemptionCertificate>, StatusCode> {
+    sqlx::query_as::<_, ExemptionCertificate>(&format!(
+        "UPDATE tax_exemption_certificates SET revoked_at = NOW(), revoked_by = $2
+         WHERE id = $1 AND revoked_at IS NULL
+         RETURNING {}",
+        CERTIFICATE_COLUMNS
+    ))
+    .bind(id)
+    .bind(auth.0.id)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error revoking exemption certificate: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .map(Json)
+    .ok_or(StatusCode::CONFLICT)
+}
+
+/// Stores the signed certificate document in the client's files.
+async fn upload_certificate_file(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    mut multipart: Multipart,
+) -> Result<Json<ExemptionCertificate>, StatusCode> {
+    let client_id: Uuid = sqlx::query_scalar("SELECT client_id FROM tax_exemption_certificates WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&state.db_pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error fetching exemption certificate: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut upload = None;
+    while let Some(field) = multipart.next_field().await.map_err(|_| StatusCode::BAD_REQUEST)? {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let filename = field.file_name().unwrap_or("certificate").to_string();
+        let mime_type = field.content_type().unwrap_or("application/octet-stream").to_string();
+        let data = field.bytes().await.map_err(|_| StatusCode::BAD_REQUEST)?;
+        upload = Some((filename, mime_type, data));
+    }
+    let (filename, mime_type, data) = upload.ok_or(StatusCode::BAD_REQUEST)?;
+    if data.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let links = FileLinks { client_id: Some(client_id), ..FileLinks::default() };
+    let (file_id, _) = store_file(&state.db_pool, auth.0.id, &links, &filename, &mime_type, &data).await?;
+
+    let certificate = sqlx::query_as::<_, ExemptionCertificate>(&format!(
+        "UPDATE tax_exemption_certificates SET file_id = $2 WHERE id = $1 RETURNING {}",
+        CERTIFICATE_COLUMNS
+    ))
+    .bind(id)
+    .bind(file_id)
+    .fetch_one(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error attaching exemption certificate file: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(certificate))
+}
+
+/// Taxes a set of lines for a client without writing anything, e.g. while a
+/// quote or invoice is being drafted.
+async fn preview_tax(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Json(payload): Json<PreviewRequest>,
+) -> Result<Json<PreviewResult>, StatusCode> {
+    let date = payload.date.unwrap_or_else(|| Utc::now().date_naive());
+
+    let mut conn = state.db_pool.acquire().await.map_err(|e| {
+        tracing::error!("Error acquiring connection: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let context = TaxContext::load(&mut *conn, payload.client_id, date).await.map_err(|e| {
+        tracing::error!("Error loading client tax rates: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let lines: Vec<PreviewLineResult> = payload
+        .lines
+        .into_iter()
+        .map(|line| {
+            let taxes = context.line_taxes(line.amount, line.tax_category.as_deref(), line.tax_rate);
+            PreviewLineResult {
+                description: line.description,
+                amount: line.amount,
+                tax_amount: sales_tax::total_tax(&taxes),
+                taxes,
+            }
+        })
+        .collect();
+
+    let subtotal: Decimal = lines.iter().map(|l| l.amount).sum();
+    let tax_amount: Decimal = lines.iter().map(|l| l.tax_amount).sum();
+
+    Ok(Json(PreviewResult { subtotal, tax_amount, total: subtotal + tax_amount, lines }))
+}
+
+/// Sales, exempt sales and tax collected per jurisdiction and period, from
+/// issued invoices. Voided invoices and lines are left out.
+async fn liability_report(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<LiabilityQuery>,
+) -> Result<Json<Vec<LiabilityRow>>, StatusCode> {
+    let period = params.period.as_deref().unwrap_or("month");
+    if !matches!(period, "month" | "quarter" | "year") || params.end_date < params.start_date {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let rows = sqlx::query_as::<_, LiabilityRow>(
+        r#"
+        SELECT DATE_TRUNC($3, i.date)::date as period_start,
+               t.jurisdiction_id, t.jurisdiction_name, t.level, t.state_code, i.currency,
+               COUNT(DISTINCT i.id) as invoice_count,
+               COALESCE(SUM(li.line_total) FILTER (WHERE t.exempt_reason IS NULL), 0) as taxable_sales,
+               COALESCE(SUM(t.exempt_amount) FILTER (WHERE t.exempt_reason = 'category'), 0) as non_taxable_sales,
+               COALESCE(SUM(t.exempt_amount) FILTER (WHERE t.exempt_reason = 'certificate'), 0) as exempt_sales,
+               COALESCE(SUM(t.tax_amount), 0) as tax_collected
+        FROM invoice_line_taxes t
+        JOIN invoice_line_items li ON li.id = t.invoice_line_item_id
+        JOIN invoices i ON i.id = t.invoice_id
+        WHERE i.date BETWEEN $1 AND $2
+          AND i.status NOT IN ('draft', 'void')
+          AND li.voided_at IS NULL
+          AND ($4::TEXT IS NULL OR t.state_code = UPPER($4))
+        GROUP BY 1, t.jurisdiction_id, t.jurisdiction_name, t.level, t.state_code, i.currency
+        ORDER BY 1, t.state_code NULLS LAST,
+                 CASE t.level WHEN 'state' THEN 0 WHEN 'county' THEN 1 WHEN 'city' THEN 2 ELSE 3 END,
+                 t.jurisdiction_name, i.currency
+        "#,
+    )
+    .bind(params.start_date)
+    .bind(params.end_date)
+    .bind(period)
+    .bind(params.state_code)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error building tax liability report: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(rows))
+}
+
+async fn load_jurisdiction(state: &AppState, id: Uuid) -> Result<JurisdictionWithRules, StatusCode> {
+    let jurisdiction = sqlx::query_as::<_, TaxJurisdiction>(&format!(
+        "SELECT {} FROM tax_jurisdictions j WHERE j.id = $1",
+        JURISDICTION_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching tax jurisdiction: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let rules = sqlx::query_as::<_, CategoryRule>(
+        "SELECT category_code, is_taxable FROM tax_category_rules WHERE jurisdiction_id = $1 ORDER BY category_code",
+    )
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching tax category rules: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(JurisdictionWithRules { jurisdiction, rules })
+}
@@ -0,0 +1,356 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json},
+    routing::{get, post},
+    Form, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::services::sms::{verify_twilio_signature, InboundSms, OutboundSms, SmsService};
+use crate::services::EncryptionService;
+use crate::AppState;
+
+pub fn sms_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/providers", get(list_providers).post(create_provider))
+        .route("/messages", get(list_messages))
+        .route("/send", post(send_sms))
+        // Provider callbacks, authenticated by request signature rather than a session
+        .route("/webhooks/inbound", post(inbound_webhook))
+        .route("/webhooks/status", post(status_webhook))
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct SmsProviderSummary {
+    pub id: Uuid,
+    pub name: String,
+    pub provider_type: String,
+    pub api_endpoint: Option<String>,
+    pub sender_number: Option<String>,
+    pub is_active: Option<bool>,
+    pub is_default: Option<bool>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SmsProviderCreate {
+    pub name: String,
+    pub provider_type: String,
+    pub api_endpoint: Option<String>,
+    pub api_key: String,
+    pub api_secret: String,
+    pub sender_number: String,
+    pub is_default: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct SmsMessage {
+    pub id: Uuid,
+    pub direction: Option<String>,
+    pub to_number: String,
+    pub from_number: Option<String>,
+    pub message: String,
+    pub client_id: Option<Uuid>,
+    pub contact_id: Option<Uuid>,
+    pub ticket_id: Option<Uuid>,
+    pub user_id: Option<Uuid>,
+    pub status: Option<String>,
+    pub error_message: Option<String>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub received_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SmsMessageQuery {
+    pub ticket_id: Option<Uuid>,
+    pub contact_id: Option<Uuid>,
+    pub page: Option<u32>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SendSmsRequest {
+    pub to_number: String,
+    pub message: String,
+    pub ticket_id: Option<Uuid>,
+    pub contact_id: Option<Uuid>,
+    pub client_id: Option<Uuid>,
+}
+
+async fn list_providers(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+) -> Result<Json<Vec<SmsProviderSummary>>, StatusCode> {
+    let providers = sqlx::query_as::<_, SmsProviderSummary>(
+        r#"
+        SELECT id, name, provider_type, api_endpoint, sender_number, is_active, is_default, created_at
+        FROM sms_providers
+        ORDER BY is_default DESC, name
+        "#
+    )
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching SMS providers: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(providers))
+}
+
+async fn create_provider(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Json(payload): Json<SmsProviderCreate>,
+) -> Result<(StatusCode, Json<SmsProviderSummary>), StatusCode> {
+    let encryption = EncryptionService::new().map_err(|e| {
+        tracing::error!("Encryption unavailable: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let api_key = encryption
+        .encrypt(&payload.api_key)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let api_secret = encryption
+        .encrypt(&payload.api_secret)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let is_default = payload.is_default.unwrap_or(false);
+
+    let mut tx = state.db_pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if is_default {
+        sqlx::query("UPDATE sms_providers SET is_default = false WHERE is_default = true")
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    let provider = sqlx::query_as::<_, SmsProviderSummary>(
+        r#"
+        INSERT INTO sms_providers (name, provider_type, api_endpoint, api_key, api_secret,
+                                   sender_number, is_default)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id, name, provider_type, api_endpoint, sender_number, is_active, is_default, created_at
+        "#
+    )
+    .bind(&payload.name)
+    .bind(&payload.provider_type)
+    .bind(&payload.api_endpoint)
+    .bind(&api_key)
+    .bind(&api_secret)
+    .bind(&payload.sender_number)
+    .bind(is_default)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error creating SMS provider: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((StatusCode::CREATED, Json(provider)))
+}
+
+async fn list_messages(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SmsMessageQuery>,
+    _auth: AuthUser,
+) -> Result<Json<Vec<SmsMessage>>, StatusCode> {
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query.limit.unwrap_or(50).min(200);
+    let offset = (page - 1) * limit;
+
+    let messages = sqlx::query_as::<_, SmsMessage>(
+        r#"
+        SELECT id, direction, to_number, from_number, message, client_id, contact_id,
+               ticket_id, user_id, status, error_message, sent_at, delivered_at,
+               received_at, created_at
+        FROM sms_messages
+        WHERE ($1::UUID IS NULL OR ticket_id = $1)
+        AND ($2::UUID IS NULL OR contact_id = $2)
+        ORDER BY created_at DESC
+        LIMIT $3 OFFSET $4
+        "#
+    )
+    .bind(query.ticket_id)
+    .bind(query.contact_id)
+    .bind(limit as i64)
+    .bind(offset as i64)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Error fetching SMS messages: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(messages))
+}
+
+async fn send_sms(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<SendSmsRequest>,
+) -> Result<(StatusCode, Json<serde_json::Value>), StatusCode> {
+    if payload.message.trim().is_empty() || payload.to_number.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let sms_service = SmsService::new(state.db_pool.clone());
+    let message_id = sms_service
+        .send(OutboundSms {
+            to_number: payload.to_number,
+            message: payload.message,
+            client_id: payload.client_id,
+            contact_id: payload.contact_id,
+            ticket_id: payload.ticket_id,
+            user_id: Some(auth.0.id),
+            notification_queue_id: None,
+        })
+        .await
+        .map_err(|e| {
+            tracing::error!("Error sending SMS: {}", e);
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    Ok((StatusCode::CREATED, Json(serde_json::json!({ "id": message_id }))))
+}
+
+// Twilio signs webhooks against the public URL it was configured with, taken from
+// SMS_WEBHOOK_BASE_URL. Without it no webhook can be verified, so all are refused.
+async fn verify_webhook_signature(
+    state: &Arc<AppState>,
+    headers: &HeaderMap,
+    path: &str,
+    params: &[(String, String)],
+) -> Result<(), StatusCode> {
+    let Ok(base_url) = std::env::var("SMS_WEBHOOK_BASE_URL") else {
+        tracing::error!("Refusing SMS webhook: SMS_WEBHOOK_BASE_URL is not set");
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    };
+
+    let sms_service = SmsService::new(state.db_pool.clone());
+    let provider = sms_service.default_provider().await.map_err(|e| {
+        tracing::error!("No SMS provider for webhook verification: {}", e);
+        StatusCode::SERVICE_UNAVAILABLE
+    })?;
+    let auth_token = provider
+        .api_secret
+        .as_deref()
+        .and_then(|secret| EncryptionService::new().ok()?.decrypt(secret).ok())
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let signature = headers
+        .get("X-Twilio-Signature")
+        .and_then(|v| v.to_str().ok())
+        .ok_or(StatusCode::FORBIDDEN)?;
+
+    let url = format!("{}{}", base_url.trim_end_matches('/'), path);
+    if !verify_twilio_signature(&auth_token, &url, params, signature) {
+        tracing::warn!("Rejected SMS webhook with invalid signature");
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(())
+}
+
+fn empty_twiml() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/xml")],
+        r#"<?xml version="1.0" encoding="UTF-8"?><Response></Response>"#,
+    )
+}
+
+async fn inbound_webhook(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Form(params): Form<Vec<(String, String)>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    verify_webhook_signature(&state, &headers, "/api/v1/sms/webhooks/inbound", &params).await?;
+
+    let fields: HashMap<String, String> = params.into_iter().collect();
+    let from_number = fields.get("From").cloned().ok_or(StatusCode::BAD_REQUEST)?;
+    let body = fields.get("Body").cloned().unwrap_or_default();
+
+    let sms_service = SmsService::new(state.db_pool.clone());
+    let result = sms_service
+        .receive(InboundSms {
+            from_number,
+            to_number: fields.get("To").cloned().unwrap_or_default(),
+            body: body.clone(),
+            external_id: fields.get("MessageSid").cloned(),
+        })
+        .await
+        .map_err(|e| {
+            tracing::error!("Error processing inbound SMS: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if result.duplicate {
+        return Ok(empty_twiml());
+    }
+
+    if let (Some(ticket_id), Some(client_id)) = (result.ticket_id, result.client_id) {
+        let action = if result.created_ticket { "Created" } else { "Updated" };
+        if let Err(e) = crate::notifications::notify_ticket_update(
+            &state.db_pool,
+            ticket_id,
+            client_id,
+            action,
+            &format!("SMS reply: {}", body),
+        )
+        .await
+        {
+            tracing::error!("Error queueing SMS reply notifications: {}", e);
+        }
+
+        state.broadcast_notification(
+            "ticket_sms_reply",
+            serde_json::json!({
+                "ticket_id": ticket_id,
+                "reply_id": result.ticket_reply_id,
+                "contact_id": result.contact_id,
+                "message_id": result.message_id,
+            }),
+        ).await;
+    } else {
+        tracing::info!("Inbound SMS {} did not match a contact", result.message_id);
+    }
+
+    Ok(empty_twiml())
+}
+
+async fn status_webhook(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Form(params): Form<Vec<(String, String)>>,
+) -> Result<StatusCode, StatusCode> {
+    verify_webhook_signature(&state, &headers, "/api/v1/sms/webhooks/status", &params).await?;
+
+    let fields: HashMap<String, String> = params.into_iter().collect();
+    let external_id = fields.get("MessageSid").ok_or(StatusCode::BAD_REQUEST)?;
+    let status = fields.get("MessageStatus").ok_or(StatusCode::BAD_REQUEST)?;
+
+    let sms_service = SmsService::new(state.db_pool.clone());
+    sms_service
+        .update_delivery_status(
+            external_id,
+            status,
+            fields.get("ErrorMessage").map(|s| s.as_str()),
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!("Error updating SMS status: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
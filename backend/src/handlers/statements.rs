@@ -0,0 +1,323 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+    routing::{get, post},
+    Router,
+};
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthUser;
+use crate::handlers::internal;
+use crate::handlers::portal::{extract_portal_token, verify_token};
+use crate::services::statements::{
+    self, AgingReport, Statement, StatementConfig, StatementRunSummary, StatementService, STATEMENT_COLUMNS,
+    STATEMENT_FREQUENCIES,
+};
+use crate::AppState;
+
+pub fn statement_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_statements).post(generate_statement))
+        .route("/aging", get(aging_report))
+        .route("/run", post(run_statements))
+        .route("/schedule/:client_id", get(get_schedule).put(update_schedule))
+        .route("/:id", get(get_statement))
+        .route("/:id/pdf", get(download_statement_pdf))
+        .route("/:id/send", post(send_statement))
+}
+
+/// Client portal routes, nested under /api/v1/portal/statements.
+pub fn portal_statement_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_portal_statements))
+        .route("/:id", get(get_portal_statement))
+        .route("/:id/pdf", get(download_portal_statement_pdf))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatementQuery {
+    pub client_id: Option<Uuid>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GenerateStatement {
+    pub client_id: Uuid,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub send: Option<bool>, // email it to the billing contact straight away
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AgingQuery {
+    pub as_of: Option<NaiveDate>, // defaults to today
+    pub client_id: Option<Uuid>,
+    pub format: Option<String>, // json (default) or csv
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatementSchedule {
+    pub statement_frequency: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SendResult {
+    pub statement_id: Uuid,
+    pub sent_to: Vec<String>,
+}
+
+async fn list_statements(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<StatementQuery>,
+) -> Result<Json<Vec<Statement>>, StatusCode> {
+    let statements = sqlx::query_as::<_, Statement>(&format!(
+        "SELECT {} FROM billing_statements s JOIN clients c ON c.id = s.client_id
+         WHERE ($1::UUID IS NULL OR s.client_id = $1) AND ($2::TEXT IS NULL OR s.status = $2)
+         ORDER BY s.period_end DESC, c.name
+         LIMIT 500",
+        STATEMENT_COLUMNS
+    ))
+    .bind(params.client_id)
+    .bind(params.status)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching statements"))?;
+
+    Ok(Json(statements))
+}
+
+/// Generates a statement for any period, replacing an unsent one for the same
+/// period. A period whose statement was already sent is a conflict.
+async fn generate_statement(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<GenerateStatement>,
+) -> Result<(StatusCode, Json<Statement>), StatusCode> {
+    if payload.period_end < payload.period_start {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let client_exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM clients WHERE id = $1)")
+        .bind(payload.client_id)
+        .fetch_one(&state.db_pool)
+        .await
+        .map_err(internal("checking client"))?;
+    if !client_exists {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let mut tx = state.db_pool.begin().await.map_err(internal("starting transaction"))?;
+    let draft = statements::compute_statement(&mut tx, payload.client_id, payload.period_start, payload.period_end)
+        .await
+        .map_err(internal("computing statement"))?;
+    let id = statements::save_statement(&mut tx, &draft, Some(auth.0.id))
+        .await
+        .map_err(internal("saving statement"))?
+        .ok_or(StatusCode::CONFLICT)?;
+    tx.commit().await.map_err(internal("committing statement"))?;
+
+    let mut statement = load_statement(&state, id).await?;
+    if payload.send.unwrap_or(false) {
+        statements::send_statement(&state.db_pool, &statement)
+            .await
+            .map_err(internal("sending statement"))?;
+        statement = load_statement(&state, id).await?;
+    }
+
+    Ok((StatusCode::CREATED, Json(statement)))
+}
+
+async fn get_statement(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Statement>, StatusCode> {
+    Ok(Json(load_statement(&state, id).await?))
+}
+
+async fn download_statement_pdf(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<([(header::HeaderName, String); 2], Vec<u8>), StatusCode> {
+    let statement = load_statement(&state, id).await?;
+    Ok(pdf_response(&statement))
+}
+
+async fn send_statement(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SendResult>, StatusCode> {
+    let statement = load_statement(&state, id).await?;
+    let sent_to = statements::send_statement(&state.db_pool, &statement)
+        .await
+        .map_err(internal("sending statement"))?;
+    if sent_to.is_empty() {
+        // No contact with an email address to send it to
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    Ok(Json(SendResult { statement_id: id, sent_to }))
+}
+
+/// Outstanding receivables by age, as JSON or as a CSV download.
+async fn aging_report(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Query(params): Query<AgingQuery>,
+) -> Result<Response, StatusCode> {
+    let as_of = params.as_of.unwrap_or_else(|| Utc::now().date_naive());
+    let report: AgingReport = statements::aging_report(&state.db_pool, as_of, params.client_id)
+        .await
+        .map_err(internal("building aging report"))?;
+
+    match params.format.as_deref() {
+        None | Some("json") => Ok(Json(report).into_response()),
+        Some("csv") => Ok((
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"ar-aging-{}.csv\"", as_of.format("%Y-%m-%d")),
+                ),
+            ],
+            statements::aging_csv(&report),
+        )
+            .into_response()),
+        Some(_) => Err(StatusCode::BAD_REQUEST),
+    }
+}
+
+/// Runs the statement worker now for the period just ended.
+async fn run_statements(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+) -> Result<Json<StatementRunSummary>, StatusCode> {
+    let service = StatementService::new(StatementConfig::default(), state.db_pool.clone());
+    let summary = service
+        .generate_due_statements(Utc::now().date_naive())
+        .await
+        .map_err(internal("running statements"))?;
+
+    Ok(Json(summary))
+}
+
+async fn get_schedule(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(client_id): Path<Uuid>,
+) -> Result<Json<StatementSchedule>, StatusCode> {
+    let statement_frequency: String = sqlx::query_scalar("SELECT statement_frequency FROM clients WHERE id = $1")
+        .bind(client_id)
+        .fetch_optional(&state.db_pool)
+        .await
+        .map_err(internal("fetching statement schedule"))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(StatementSchedule { statement_frequency }))
+}
+
+async fn update_schedule(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(client_id): Path<Uuid>,
+    Json(payload): Json<StatementSchedule>,
+) -> Result<Json<StatementSchedule>, StatusCode> {
+    if !STATEMENT_FREQUENCIES.contains(&payload.statement_frequency.as_str()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let result = sqlx::query("UPDATE clients SET statement_frequency = $2, updated_at = NOW() WHERE id = $1")
+        .bind(client_id)
+        .bind(&payload.statement_frequency)
+        .execute(&state.db_pool)
+        .await
+        .map_err(internal("updating statement schedule"))?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(Json(payload))
+}
+
+async fn list_portal_statements(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Statement>>, StatusCode> {
+    let (_contact_id, client_id) = portal_client(&state, &headers).await?;
+
+    let statements = sqlx::query_as::<_, Statement>(&format!(
+        "SELECT {} FROM billing_statements s JOIN clients c ON c.id = s.client_id
+         WHERE s.client_id = $1 AND s.status IN ('final', 'sent')
+         ORDER BY s.period_end DESC",
+        STATEMENT_COLUMNS
+    ))
+    .bind(client_id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("fetching portal statements"))?;
+
+    Ok(Json(statements))
+}
+
+async fn get_portal_statement(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Statement>, StatusCode> {
+    let (_contact_id, client_id) = portal_client(&state, &headers).await?;
+    Ok(Json(load_portal_statement(&state, id, client_id).await?))
+}
+
+async fn download_portal_statement_pdf(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<([(header::HeaderName, String); 2], Vec<u8>), StatusCode> {
+    let (_contact_id, client_id) = portal_client(&state, &headers).await?;
+    let statement = load_portal_statement(&state, id, client_id).await?;
+    Ok(pdf_response(&statement))
+}
+
+async fn load_statement(state: &AppState, id: Uuid) -> Result<Statement, StatusCode> {
+    statements::load_statement(&state.db_pool, id)
+        .await
+        .map_err(internal("fetching statement"))?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// A statement the portal client may see: their own, and not a draft.
+async fn load_portal_statement(state: &AppState, id: Uuid, client_id: Uuid) -> Result<Statement, StatusCode> {
+    let statement = load_statement(state, id).await?;
+    let visible = statement.client_id == client_id
+        && matches!(statement.status.as_deref(), Some("final") | Some("sent"));
+    if !visible {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(statement)
+}
+
+async fn portal_client(state: &Arc<AppState>, headers: &HeaderMap) -> Result<(Uuid, Uuid), StatusCode> {
+    let token = extract_portal_token(headers)?;
+    verify_token(state, &token).await
+}
+
+fn pdf_response(statement: &Statement) -> ([(header::HeaderName, String); 2], Vec<u8>) {
+    (
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"{}.pdf\"", statement.statement_number),
+            ),
+        ],
+        statements::render_pdf(statement),
+    )
+}
@@ -64,6 +64,8 @@ pub struct TicketWithDetails {
     pub number: i32,
     pub client_id: Uuid,
     pub client_name: String,
+    pub client_credit_hold: bool, // billing has put the client on hold; only urgent work should proceed
+    pub client_credit_hold_reason: Option<String>,
     pub contact_id: Option<Uuid>,
     pub contact_name: Option<String>,
     pub asset_id: Option<Uuid>,
@@ -197,6 +199,7 @@ async fn list_tickets(
         TicketWithDetails,
         "SELECT 
             t.id, t.number, t.client_id, c.name as client_name,
+            COALESCE(c.credit_hold, false) as \"client_credit_hold!\", c.credit_hold_reason as client_credit_hold_reason,
             t.contact_id, ct.name as contact_name,
             t.asset_id, a.name as asset_name,
             t.assigned_to, 
@@ -317,6 +320,7 @@ async fn get_ticket_by_id(state: &AppState, id: Uuid) -> Result<TicketWithDetail
         TicketWithDetails,
         "SELECT 
             t.id, t.number, t.client_id, c.name as client_name,
+            COALESCE(c.credit_hold, false) as \"client_credit_hold!\", c.credit_hold_reason as client_credit_hold_reason,
             t.contact_id, ct.name as contact_name,
             t.asset_id, a.name as asset_name,
             t.assigned_to, 
@@ -384,7 +388,15 @@ async fn update_ticket(
         Ok(result) => {
             if result.rows_affected() > 0 {
                 match get_ticket_by_id(&state, id).await {
-                    Ok(ticket) => Ok(Json(ticket)),
+                    Ok(ticket) => {
+                        publish_ticket_event(&state, &ticket, "ticket_updated", serde_json::json!({
+                            "ticket_id": ticket.id,
+                            "number": ticket.number,
+                            "status": ticket.status,
+                            "priority": ticket.priority,
+                        })).await;
+                        Ok(Json(ticket))
+                    }
                     Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
                 }
             } else {
@@ -474,6 +486,17 @@ async fn escalate_ticket(
     }
 }
 
+// Live updates for clients subscribed to the ticket or its client
+async fn publish_ticket_event(
+    state: &Arc<AppState>,
+    ticket: &TicketWithDetails,
+    event_type: &str,
+    payload: serde_json::Value,
+) {
+    state.notify_topic(&format!("ticket:{}", ticket.id), event_type, payload.clone()).await;
+    state.notify_topic(&format!("client:{}", ticket.client_id), event_type, payload).await;
+}
+
 async fn get_ticket_replies(
     State(state): State<Arc<AppState>>,
     Path(id): Path<Uuid>,
@@ -558,7 +581,16 @@ async fn add_reply(
             .fetch_one(&state.db_pool)
             .await
             {
-                Ok(reply) => Ok((StatusCode::CREATED, Json(reply))),
+                Ok(reply) => {
+                    if let Ok(ticket) = get_ticket_by_id(&state, id).await {
+                        publish_ticket_event(&state, &ticket, "ticket_reply", serde_json::json!({
+                            "ticket_id": id,
+                            "reply_id": reply.id,
+                            "reply_type": reply.reply_type,
+                        })).await;
+                    }
+                    Ok((StatusCode::CREATED, Json(reply)))
+                }
                 Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
             }
         }
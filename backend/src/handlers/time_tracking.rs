@@ -8,9 +8,15 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use uuid::Uuid;
-use chrono::{DateTime, Utc, Duration};
+use chrono::{DateTime, NaiveDate, Utc, Duration};
 use rust_decimal::Decimal;
 use crate::AppState;
+use crate::auth::middleware::AuthUser;
+use crate::handlers::internal;
+use crate::services::prepaid_blocks;
+use crate::services::rate_cards::{self, WorkType};
+use crate::services::timer_reconciliation::{self, Reconciliation, TimerReconciliationConfig, TimerSyncEvent, TimerSyncResult};
+use crate::services::timesheets::{self, MissingTime, Timesheet, WeeklyTimesheet, MISSING_TIME_TOLERANCE_MINUTES};
 
 #[derive(Serialize, Deserialize)]
 pub struct TimeEntryCreate {
@@ -19,6 +25,7 @@ pub struct TimeEntryCreate {
     pub task_id: Option<Uuid>,
     pub description: Option<String>,
     pub billable: Option<bool>,
+    pub work_type: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -31,6 +38,8 @@ pub struct TimeEntryUpdate {
     pub start_time: Option<DateTime<Utc>>,
     pub end_time: Option<DateTime<Utc>>,
     pub duration_minutes: Option<i32>,
+    pub work_type: Option<String>,
+    pub hourly_rate: Option<Decimal>, // overrides the rate card for this entry
 }
 
 #[derive(Serialize, Deserialize)]
@@ -42,6 +51,7 @@ pub struct ManualTimeEntry {
     pub start_time: DateTime<Utc>,
     pub end_time: DateTime<Utc>,
     pub billable: bool,
+    pub work_type: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -56,6 +66,62 @@ pub struct TimeEntryQuery {
     pub end_date: Option<String>,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct TimesheetQuery {
+    pub user_id: Option<Uuid>, // the caller when omitted
+    pub week: Option<NaiveDate>, // any day of the week; this week when omitted
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TimesheetListQuery {
+    pub status: Option<String>, // submitted when omitted
+    pub user_id: Option<Uuid>,
+    pub team: Option<bool>, // only the caller's direct reports
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MissingTimeQuery {
+    pub week: Option<NaiveDate>,
+    pub team: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TimesheetSubmit {
+    pub week: Option<NaiveDate>,
+    pub note: Option<String>, // e.g. why hours are short
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TimesheetReview {
+    pub comment: Option<String>, // required when rejecting
+}
+
+/// Corrects a locked entry. Reductions correct hours only; amounts already
+/// billed are corrected with a credit note.
+#[derive(Serialize, Deserialize)]
+pub struct TimeAdjustment {
+    pub duration_minutes: i32, // minutes added, or removed when negative
+    pub reason: String,
+    pub description: Option<String>,
+    pub billable: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TimerSyncRequest {
+    pub events: Vec<TimerSyncEvent>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TimeEntryConfirm {
+    pub end_time: Option<DateTime<Utc>>, // when the work really ended, if not when the timer was stopped
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReconciliationQuery {
+    pub user_id: Option<Uuid>,
+    pub all: Option<bool>, // every technician rather than the caller
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct TimeEntryWithDetails {
     pub id: Uuid,
@@ -78,6 +144,9 @@ pub struct TimeEntryWithDetails {
     pub billed: bool,
     pub hourly_rate: Option<Decimal>,
     pub total_amount: Option<Decimal>,
+    pub work_type: Option<String>,
+    pub billable_minutes: Option<i32>,
+    pub rate_multiplier: Option<Decimal>,
     pub created_at: DateTime<Utc>,
     pub updated_at: Option<DateTime<Utc>>,
 }
@@ -117,6 +186,15 @@ pub fn time_tracking_routes() -> Router<Arc<AppState>> {
         .route("/timer/switch", post(switch_timer))
         .route("/stats", get(get_time_stats))
         .route("/timesheet", get(get_timesheet))
+        .route("/timesheet/submit", post(submit_timesheet))
+        .route("/timesheets", get(list_timesheets))
+        .route("/timesheets/missing", get(get_missing_time))
+        .route("/timesheets/:id/approve", post(approve_timesheet))
+        .route("/timesheets/:id/reject", post(reject_timesheet))
+        .route("/entries/:id/adjust", post(adjust_time_entry))
+        .route("/entries/:id/confirm", post(confirm_time_entry))
+        .route("/timer/sync", post(sync_timer_events))
+        .route("/reconciliation", get(get_reconciliation))
 }
 
 async fn list_time_entries(
@@ -139,6 +217,7 @@ async fn list_time_entries(
             te.start_time, te.end_time, te.duration_minutes,
             te.description, te.billable, te.billed,
             te.hourly_rate, te.total_amount,
+            te.work_type, te.billable_minutes, te.rate_multiplier,
             te.created_at, te.updated_at
          FROM time_entries te
          LEFT JOIN users u ON te.user_id = u.id
@@ -164,13 +243,24 @@ async fn list_time_entries(
 
 async fn start_timer(
     State(state): State<Arc<AppState>>,
+    auth: AuthUser,
     Json(payload): Json<TimeEntryCreate>,
 ) -> Result<(StatusCode, Json<ActiveTimer>), StatusCode> {
     let entry_id = Uuid::new_v4();
-    // TODO: Get current user from auth context
-    let current_user_id = Uuid::new_v4();
+    let current_user_id = auth.0.id;
+    
+    if !valid_work_type(payload.work_type.as_deref()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
     
     let now = Utc::now();
+    // Submitted and approved weeks only change through adjustments
+    let week_status = timesheets::week_status(&state.db_pool, current_user_id, now)
+        .await
+        .map_err(internal("checking timesheet week"))?;
+    if !timesheets::week_is_open(week_status.as_deref()) {
+        return Err(StatusCode::CONFLICT);
+    }
     let billable = payload.billable.unwrap_or(true);
     
     // Stop any existing active timer for this user
@@ -188,8 +278,8 @@ async fn start_timer(
     match sqlx::query!(
         "INSERT INTO time_entries (
             id, user_id, ticket_id, project_id, task_id, 
-            start_time, description, billable
-        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            start_time, description, billable, work_type
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
         entry_id,
         current_user_id,
         payload.ticket_id,
@@ -197,7 +287,8 @@ async fn start_timer(
         payload.task_id,
         now,
         payload.description,
-        billable
+        billable,
+        payload.work_type
     )
     .execute(&state.db_pool)
     .await
@@ -218,10 +309,10 @@ async fn start_timer(
 
 async fn stop_timer(
     State(state): State<Arc<AppState>>,
+    auth: AuthUser,
     Json(payload): Json<serde_json::Value>,
 ) -> Result<Json<TimeEntryWithDetails>, StatusCode> {
-    // TODO: Get current user from auth context
-    let current_user_id = Uuid::new_v4();
+    let current_user_id = auth.0.id;
     
     let timer_id = payload.get("timer_id")
         .and_then(|v| v.as_str())
@@ -256,7 +347,9 @@ async fn stop_timer(
     match query.fetch_optional(&state.db_pool).await {
         Ok(Some(row)) => {
             // Calculate billable amount
-            let _ = calculate_and_update_billing(&state, row.id).await;
+            if let Err(e) = calculate_and_update_billing(&state, row.id).await {
+                tracing::error!("Error pricing time entry {}: {}", row.id, e);
+            }
             
             // Fetch the updated entry
             match get_time_entry_by_id(&state, row.id).await {
@@ -274,9 +367,9 @@ async fn stop_timer(
 
 async fn get_active_timers(
     State(state): State<Arc<AppState>>,
+    auth: AuthUser,
 ) -> Result<Json<Vec<ActiveTimer>>, StatusCode> {
-    // TODO: Get current user from auth context or return all active timers for admins
-    let current_user_id = Uuid::new_v4();
+    let current_user_id = auth.0.id;
     
     match sqlx::query!(
         "SELECT 
@@ -321,10 +414,17 @@ async fn get_active_timers(
 
 async fn switch_timer(
     State(state): State<Arc<AppState>>,
+    auth: AuthUser,
     Json(payload): Json<TimeEntryCreate>,
 ) -> Result<Json<ActiveTimer>, StatusCode> {
-    // TODO: Get current user from auth context
-    let current_user_id = Uuid::new_v4();
+    let current_user_id = auth.0.id;
+    
+    let week_status = timesheets::week_status(&state.db_pool, current_user_id, Utc::now())
+        .await
+        .map_err(internal("checking timesheet week"))?;
+    if !timesheets::week_is_open(week_status.as_deref()) {
+        return Err(StatusCode::CONFLICT);
+    }
     
     // Stop current timer if any
     let _ = sqlx::query!(
@@ -374,11 +474,23 @@ async fn switch_timer(
 
 async fn create_manual_entry(
     State(state): State<Arc<AppState>>,
+    auth: AuthUser,
     Json(payload): Json<ManualTimeEntry>,
 ) -> Result<(StatusCode, Json<TimeEntryWithDetails>), StatusCode> {
+    if !valid_work_type(payload.work_type.as_deref()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    
     let entry_id = Uuid::new_v4();
-    // TODO: Get current user from auth context
-    let current_user_id = Uuid::new_v4();
+    let current_user_id = auth.0.id;
+    
+    // Submitted and approved weeks only change through adjustments
+    let week_status = timesheets::week_status(&state.db_pool, current_user_id, payload.start_time)
+        .await
+        .map_err(internal("checking timesheet week"))?;
+    if !timesheets::week_is_open(week_status.as_deref()) {
+        return Err(StatusCode::CONFLICT);
+    }
     
     let duration = payload.end_time.signed_duration_since(payload.start_time);
     let duration_minutes = duration.num_minutes() as i32;
@@ -386,8 +498,8 @@ async fn create_manual_entry(
     match sqlx::query!(
         "INSERT INTO time_entries (
             id, user_id, ticket_id, project_id, task_id,
-            start_time, end_time, duration_minutes, description, billable
-        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            start_time, end_time, duration_minutes, description, billable, work_type
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
         entry_id,
         current_user_id,
         payload.ticket_id,
@@ -397,14 +509,17 @@ async fn create_manual_entry(
         payload.end_time,
         duration_minutes,
         payload.description,
-        payload.billable
+        payload.billable,
+        payload.work_type
     )
     .execute(&state.db_pool)
     .await
     {
         Ok(_) => {
             // Calculate billing
-            let _ = calculate_and_update_billing(&state, entry_id).await;
+            if let Err(e) = calculate_and_update_billing(&state, entry_id).await {
+                tracing::error!("Error pricing time entry {}: {}", entry_id, e);
+            }
             
             match get_time_entry_by_id(&state, entry_id).await {
                 Ok(entry) => Ok((StatusCode::CREATED, Json(entry))),
@@ -434,6 +549,24 @@ async fn update_time_entry(
     Path(id): Path<Uuid>,
     Json(payload): Json<TimeEntryUpdate>,
 ) -> Result<Json<TimeEntryWithDetails>, StatusCode> {
+    if !valid_work_type(payload.work_type.as_deref()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    
+    // Billed, written-off and reviewed time is corrected with an adjustment entry
+    if timesheets::entry_lock(&state.db_pool, id).await.map_err(internal("checking time entry lock"))?.is_some() {
+        return Err(StatusCode::CONFLICT);
+    }
+    if let Some(start_time) = payload.start_time {
+        let entry = get_time_entry_by_id(&state, id).await?;
+        let week_status = timesheets::week_status(&state.db_pool, entry.user_id, start_time)
+            .await
+            .map_err(internal("checking timesheet week"))?;
+        if !timesheets::week_is_open(week_status.as_deref()) {
+            return Err(StatusCode::CONFLICT);
+        }
+    }
+    
     // Calculate duration if start and end times are provided
     let duration = if let (Some(start), Some(end)) = (&payload.start_time, &payload.end_time) {
         Some(end.signed_duration_since(*start).num_minutes() as i32)
@@ -451,6 +584,10 @@ async fn update_time_entry(
          start_time = COALESCE($7, start_time),
          end_time = COALESCE($8, end_time),
          duration_minutes = COALESCE($9, duration_minutes),
+         work_type = COALESCE($10, work_type),
+         hourly_rate = COALESCE($11, hourly_rate),
+         rate_source = CASE WHEN $11::DECIMAL IS NULL THEN rate_source ELSE 'manual' END,
+         pending_confirmation = false,
          updated_at = NOW()
          WHERE id = $1",
         id,
@@ -461,7 +598,9 @@ async fn update_time_entry(
         payload.billable,
         payload.start_time,
         payload.end_time,
-        duration
+        duration,
+        payload.work_type,
+        payload.hourly_rate
     )
     .execute(&state.db_pool)
     .await
@@ -469,7 +608,9 @@ async fn update_time_entry(
         Ok(result) => {
             if result.rows_affected() > 0 {
                 // Recalculate billing
-                let _ = calculate_and_update_billing(&state, id).await;
+                if let Err(e) = calculate_and_update_billing(&state, id).await {
+                    tracing::error!("Error pricing time entry {}: {}", id, e);
+                }
                 
                 match get_time_entry_by_id(&state, id).await {
                     Ok(entry) => Ok(Json(entry)),
@@ -490,6 +631,19 @@ async fn delete_time_entry(
     State(state): State<Arc<AppState>>,
     Path(id): Path<Uuid>,
 ) -> Result<StatusCode, StatusCode> {
+    if timesheets::entry_lock(&state.db_pool, id).await.map_err(internal("checking time entry lock"))?.is_some() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    // Drawdowns go with the entry; blocks it emptied become usable again
+    let _ = sqlx::query(
+        "UPDATE contract_prepaid_blocks SET status = 'active', updated_at = NOW()
+         WHERE status = 'depleted' AND id IN (SELECT block_id FROM prepaid_block_drawdowns WHERE time_entry_id = $1)",
+    )
+    .bind(id)
+    .execute(&state.db_pool)
+    .await;
+
     match sqlx::query!("DELETE FROM time_entries WHERE id = $1", id)
         .execute(&state.db_pool)
         .await
@@ -510,9 +664,9 @@ async fn delete_time_entry(
 
 async fn get_time_stats(
     State(state): State<Arc<AppState>>,
+    auth: AuthUser,
 ) -> Result<Json<TimeStats>, StatusCode> {
-    // TODO: Get current user from auth context
-    let current_user_id = Uuid::new_v4();
+    let current_user_id = auth.0.id;
     
     let stats = match sqlx::query!(
         "SELECT 
@@ -546,12 +700,225 @@ async fn get_time_stats(
     Ok(Json(stats))
 }
 
+/// A technician's week grouped by day, client and ticket, with its approval
+/// state and any missing time so far.
 async fn get_timesheet(
     State(state): State<Arc<AppState>>,
-    Query(params): Query<TimeEntryQuery>,
-) -> Result<Json<Vec<TimeEntryWithDetails>>, StatusCode> {
-    // TODO: Implement timesheet view with date grouping
-    list_time_entries(State(state), Query(params)).await
+    auth: AuthUser,
+    Query(params): Query<TimesheetQuery>,
+) -> Result<Json<WeeklyTimesheet>, StatusCode> {
+    let today = Utc::now().date_naive();
+    let schedule = timesheets::load_schedule(&state.db_pool, params.user_id.unwrap_or(auth.0.id))
+        .await
+        .map_err(internal("loading work schedule"))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let week = timesheets::load_week(
+        &state.db_pool,
+        &schedule,
+        params.week.unwrap_or(today),
+        today,
+        MISSING_TIME_TOLERANCE_MINUTES,
+    )
+    .await
+    .map_err(internal("loading timesheet"))?;
+
+    Ok(Json(week))
+}
+
+async fn submit_timesheet(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<TimesheetSubmit>,
+) -> Result<Json<Timesheet>, StatusCode> {
+    let today = Utc::now().date_naive();
+    let week_date = payload.week.unwrap_or(today);
+    if timesheets::week_start(week_date) > today {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let schedule = timesheets::load_schedule(&state.db_pool, auth.0.id)
+        .await
+        .map_err(internal("loading work schedule"))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let week = timesheets::load_week(&state.db_pool, &schedule, week_date, today, MISSING_TIME_TOLERANCE_MINUTES)
+        .await
+        .map_err(internal("loading timesheet"))?;
+
+    // Running timers have no duration yet, and auto-stopped ones may be wrong
+    if !timesheets::week_is_open(Some(week.status.as_str())) || week.running_timers > 0 || week.unconfirmed_entries > 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let note = payload.note.as_deref().map(str::trim).filter(|n| !n.is_empty());
+    let timesheet = timesheets::submit_week(&state.db_pool, &week, auth.0.id, note)
+        .await
+        .map_err(internal("submitting timesheet"))?
+        .ok_or(StatusCode::CONFLICT)?;
+
+    Ok(Json(timesheet))
+}
+
+async fn list_timesheets(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Query(params): Query<TimesheetListQuery>,
+) -> Result<Json<Vec<Timesheet>>, StatusCode> {
+    let status = params.status.unwrap_or_else(|| "submitted".to_string());
+    let manager_id = params.team.unwrap_or(false).then_some(auth.0.id);
+
+    let timesheets = sqlx::query_as::<_, Timesheet>(&format!(
+        "SELECT {} FROM timesheets ts
+         JOIN users u ON u.id = ts.user_id
+         WHERE ts.status = $1
+           AND ($2::uuid IS NULL OR ts.user_id = $2)
+           AND ($3::uuid IS NULL OR u.manager_id = $3)
+         ORDER BY ts.week_start DESC, user_name
+         LIMIT 200",
+        timesheets::TIMESHEET_COLUMNS
+    ))
+    .bind(&status)
+    .bind(params.user_id)
+    .bind(manager_id)
+    .fetch_all(&state.db_pool)
+    .await
+    .map_err(internal("listing timesheets"))?;
+
+    Ok(Json(timesheets))
+}
+
+async fn get_missing_time(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Query(params): Query<MissingTimeQuery>,
+) -> Result<Json<Vec<MissingTime>>, StatusCode> {
+    let today = Utc::now().date_naive();
+    let week = params.week.unwrap_or(today);
+    let manager_id = params.team.unwrap_or(false).then_some(auth.0.id);
+
+    let missing = timesheets::team_missing_time(&state.db_pool, manager_id, week, today, MISSING_TIME_TOLERANCE_MINUTES)
+        .await
+        .map_err(internal("checking missing time"))?;
+
+    Ok(Json(missing))
+}
+
+async fn approve_timesheet(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<TimesheetReview>,
+) -> Result<Json<Timesheet>, StatusCode> {
+    review_timesheet(&state, &auth, id, true, payload.comment).await
+}
+
+async fn reject_timesheet(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<TimesheetReview>,
+) -> Result<Json<Timesheet>, StatusCode> {
+    if payload.comment.as_deref().is_none_or(|c| c.trim().is_empty()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    review_timesheet(&state, &auth, id, false, payload.comment).await
+}
+
+async fn review_timesheet(
+    state: &AppState,
+    auth: &AuthUser,
+    id: Uuid,
+    approve: bool,
+    comment: Option<String>,
+) -> Result<Json<Timesheet>, StatusCode> {
+    let timesheet = timesheets::get_timesheet(&state.db_pool, id)
+        .await
+        .map_err(internal("loading timesheet"))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if timesheet.status != "submitted" {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let manager_id: Option<Uuid> = sqlx::query_scalar("SELECT manager_id FROM users WHERE id = $1")
+        .bind(timesheet.user_id)
+        .fetch_optional(&state.db_pool)
+        .await
+        .map_err(internal("loading timesheet manager"))?
+        .flatten();
+    if !timesheets::can_review(timesheet.user_id, manager_id, auth.0.id) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let comment = comment.as_deref().map(str::trim).filter(|c| !c.is_empty());
+    let timesheet = timesheets::review_timesheet(&state.db_pool, id, auth.0.id, approve, comment)
+        .await
+        .map_err(internal("reviewing timesheet"))?
+        .ok_or(StatusCode::CONFLICT)?;
+
+    Ok(Json(timesheet))
+}
+
+/// Records a correction to a locked entry as a new entry in the current week,
+/// leaving the billed or approved original as it was.
+async fn adjust_time_entry(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<TimeAdjustment>,
+) -> Result<(StatusCode, Json<TimeEntryWithDetails>), StatusCode> {
+    if payload.duration_minutes == 0 || payload.reason.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let original = get_time_entry_by_id(&state, id).await?;
+    // Unlocked entries are edited directly
+    if timesheets::entry_lock(&state.db_pool, id).await.map_err(internal("checking time entry lock"))?.is_none() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if original.duration_minutes.unwrap_or(0) + payload.duration_minutes < 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let now = Utc::now();
+    let week_status = timesheets::week_status(&state.db_pool, original.user_id, now)
+        .await
+        .map_err(internal("checking timesheet week"))?;
+    if !timesheets::week_is_open(week_status.as_deref()) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let entry_id = Uuid::new_v4();
+    let reduction = payload.duration_minutes < 0;
+    sqlx::query(
+        r#"
+        INSERT INTO time_entries (id, user_id, ticket_id, project_id, task_id, start_time, end_time, duration_minutes,
+                                  description, billable, work_type, adjusts_entry_id, adjustment_reason)
+        SELECT $1, user_id, ticket_id, project_id, task_id, $2, $2, $3,
+               COALESCE($4, 'Adjustment: ' || COALESCE(description, '')), $5 AND COALESCE($6, billable, false),
+               work_type, id, $7
+        FROM time_entries WHERE id = $8
+        "#,
+    )
+    .bind(entry_id)
+    .bind(now)
+    .bind(payload.duration_minutes)
+    .bind(&payload.description)
+    .bind(!reduction)
+    .bind(payload.billable)
+    .bind(payload.reason.trim())
+    .bind(id)
+    .execute(&state.db_pool)
+    .await
+    .map_err(internal("creating time adjustment"))?;
+
+    if !reduction {
+        if let Err(e) = calculate_and_update_billing(&state, entry_id).await {
+            tracing::error!("Error pricing time adjustment {}: {}", entry_id, e);
+        }
+    }
+
+    let entry = get_time_entry_by_id(&state, entry_id).await?;
+    Ok((StatusCode::CREATED, Json(entry)))
 }
 
 // Helper functions
@@ -569,6 +936,7 @@ async fn get_time_entry_by_id(state: &AppState, id: Uuid) -> Result<TimeEntryWit
             te.start_time, te.end_time, te.duration_minutes,
             te.description, te.billable, te.billed,
             te.hourly_rate, te.total_amount,
+            te.work_type, te.billable_minutes, te.rate_multiplier,
             te.created_at, te.updated_at
          FROM time_entries te
          LEFT JOIN users u ON te.user_id = u.id
@@ -629,22 +997,82 @@ async fn get_active_timer_by_id(state: &AppState, id: Uuid) -> Result<ActiveTime
     }
 }
 
-async fn calculate_and_update_billing(state: &AppState, entry_id: Uuid) -> Result<(), sqlx::Error> {
-    // TODO: Get user's hourly rate or project/client rate
-    let default_rate = Decimal::from(75); // $75/hour default
-    
-    sqlx::query!(
-        "UPDATE time_entries SET 
-         hourly_rate = COALESCE(hourly_rate, $2),
-         total_amount = CASE WHEN billable THEN 
-                           COALESCE(hourly_rate, $2) * (duration_minutes::decimal / 60)
-                        ELSE 0 END
-         WHERE id = $1",
-        entry_id,
-        default_rate
-    )
-    .execute(&state.db_pool)
-    .await?;
+async fn calculate_and_update_billing(state: &AppState, entry_id: Uuid) -> rate_cards::RateResult<()> {
+    // Rate, billable minutes and amount come from the entry's rate card
+    rate_cards::price_time_entry(&state.db_pool, entry_id).await?;
+
+    // Prepaid blocks are drawn down as soon as the duration is known
+    if let Err(e) = prepaid_blocks::draw_down_time_entry(&state.db_pool, entry_id).await {
+        tracing::warn!("Error applying time entry {} to prepaid blocks: {}", entry_id, e);
+    }
     
     Ok(())
-}
\ No newline at end of file
+}
+
+fn valid_work_type(work_type: Option<&str>) -> bool {
+    work_type.is_none_or(|w| WorkType::parse(w).is_some())
+}
+
+/// Applies timer events a client queued while offline. Safe to retry: events
+/// already applied come back as duplicates.
+async fn sync_timer_events(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Json(payload): Json<TimerSyncRequest>,
+) -> Result<Json<Vec<TimerSyncResult>>, StatusCode> {
+    // Bad events are answered one by one so the client can drop them and keep the rest
+    let (events, invalid): (Vec<TimerSyncEvent>, Vec<TimerSyncEvent>) =
+        payload.events.into_iter().partition(|e| valid_work_type(e.work_type.as_deref()));
+
+    let mut results: Vec<TimerSyncResult> = invalid
+        .into_iter()
+        .map(|e| TimerSyncResult {
+            client_event_id: e.client_event_id,
+            status: "rejected".to_string(),
+            time_entry_id: None,
+            message: Some("unknown work type".to_string()),
+        })
+        .collect();
+    results.extend(
+        timer_reconciliation::apply_sync_events(&state.db_pool, auth.0.id, events, Utc::now())
+            .await
+            .map_err(internal("syncing timer events"))?,
+    );
+
+    Ok(Json(results))
+}
+
+async fn confirm_time_entry(
+    State(state): State<Arc<AppState>>,
+    _auth: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<TimeEntryConfirm>,
+) -> Result<Json<TimeEntryWithDetails>, StatusCode> {
+    let confirmed = timer_reconciliation::confirm_entry(&state.db_pool, id, payload.end_time)
+        .await
+        .map_err(internal("confirming time entry"))?;
+    if !confirmed {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let entry = get_time_entry_by_id(&state, id).await?;
+    Ok(Json(entry))
+}
+
+async fn get_reconciliation(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Query(params): Query<ReconciliationQuery>,
+) -> Result<Json<Reconciliation>, StatusCode> {
+    let user_id = if params.all.unwrap_or(false) { None } else { Some(params.user_id.unwrap_or(auth.0.id)) };
+
+    let reconciliation = timer_reconciliation::reconciliation(
+        &state.db_pool,
+        user_id,
+        TimerReconciliationConfig::default().long_running_minutes,
+    )
+    .await
+    .map_err(internal("loading timer reconciliation"))?;
+
+    Ok(Json(reconciliation))
+}
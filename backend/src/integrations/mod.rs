@@ -2,6 +2,7 @@ pub mod azure;
 pub mod cloudflare;
 pub mod github;
 pub mod google;
+pub mod quickbooks;
 pub mod stripe;
 
 use axum::{
@@ -0,0 +1,376 @@
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+use std::time::Duration;
+
+use crate::services::accounting::{
+    AccountingApi, AccountingResult, Customer, ExternalRef, JournalEntry, TARGET_QUICKBOOKS_ONLINE,
+};
+use super::decrypt_json;
+
+const QUICKBOOKS_DEFAULT_API_BASE: &str = "https://quickbooks.api.intuit.com";
+const QUICKBOOKS_MINOR_VERSION: &str = "65";
+
+/// QuickBooks caps DocNumber at 21 characters.
+const DOC_NUMBER_MAX_LEN: usize = 21;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuickBooksCredentials {
+    pub access_token: String,
+}
+
+// QuickBooks Online accounting API. `config.api_base` on the integration
+// points it at the sandbox or a local stand-in; `config.realm_id` is the company.
+pub struct QuickBooksClient {
+    client: reqwest::Client,
+    api_base: String,
+    realm_id: String,
+    credentials: QuickBooksCredentials,
+    sync_from: Option<NaiveDate>,
+}
+
+#[derive(Debug, Deserialize)]
+struct QuickBooksFaultResponse {
+    #[serde(rename = "Fault")]
+    fault: QuickBooksFault,
+}
+
+#[derive(Debug, Deserialize)]
+struct QuickBooksFault {
+    #[serde(rename = "Error", default)]
+    errors: Vec<QuickBooksError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct QuickBooksError {
+    #[serde(rename = "Message")]
+    message: Option<String>,
+    #[serde(rename = "Detail")]
+    detail: Option<String>,
+}
+
+impl QuickBooksClient {
+    pub fn new(api_base: Option<String>, realm_id: String, credentials: QuickBooksCredentials) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            client,
+            api_base: api_base
+                .filter(|b| !b.is_empty())
+                .unwrap_or_else(|| QUICKBOOKS_DEFAULT_API_BASE.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            realm_id,
+            credentials,
+            sync_from: None,
+        }
+    }
+
+    /// Client for the enabled QuickBooks Online integration.
+    pub async fn from_integration(db_pool: &PgPool) -> AccountingResult<Self> {
+        let (config, credentials): (serde_json::Value, serde_json::Value) = sqlx::query_as(
+            "SELECT config, credentials FROM integrations
+             WHERE integration_type = 'quickbooks_online' AND enabled = true
+             ORDER BY created_at LIMIT 1",
+        )
+        .fetch_optional(db_pool)
+        .await?
+        .ok_or("No enabled QuickBooks Online integration")?;
+
+        let credentials = decrypt_json(&credentials).map_err(|e| format!("Decrypting QuickBooks credentials: {}", e))?;
+        let credentials: QuickBooksCredentials = serde_json::from_value(credentials)?;
+        let realm_id = config
+            .get("realm_id")
+            .and_then(|v| v.as_str())
+            .ok_or("QuickBooks integration has no realm_id")?
+            .to_string();
+        let api_base = config.get("api_base").and_then(|v| v.as_str()).map(String::from);
+
+        let mut client = Self::new(api_base, realm_id, credentials);
+        client.sync_from = config
+            .get("sync_from")
+            .and_then(|v| v.as_str())
+            .and_then(|v| NaiveDate::parse_from_str(v, "%Y-%m-%d").ok());
+        Ok(client)
+    }
+
+    /// Earliest document date to sync, from `config.sync_from`.
+    pub fn sync_from(&self) -> Option<NaiveDate> {
+        self.sync_from
+    }
+
+    async fn post(&self, entity: &str, body: &serde_json::Value, operation: Option<&str>) -> AccountingResult<serde_json::Value> {
+        let mut query = vec![("minorversion", QUICKBOOKS_MINOR_VERSION)];
+        if let Some(operation) = operation {
+            query.push(("operation", operation));
+        }
+
+        let response = self
+            .client
+            .post(format!("{}/v3/company/{}/{}", self.api_base, self.realm_id, entity))
+            .bearer_auth(&self.credentials.access_token)
+            .header("Accept", "application/json")
+            .query(&query)
+            .json(body)
+            .send()
+            .await?;
+
+        parse_response(response).await
+    }
+
+    /// Saves an entity and returns the Id and SyncToken QuickBooks assigned.
+    async fn save(&self, entity: &str, body: serde_json::Value) -> AccountingResult<ExternalRef> {
+        let response = self.post(&entity.to_lowercase(), &body, None).await?;
+        let saved = response.get(entity).ok_or_else(|| format!("QuickBooks response has no {}", entity))?;
+
+        Ok(ExternalRef {
+            id: saved
+                .get("Id")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| format!("QuickBooks {} has no Id", entity))?
+                .to_string(),
+            version: saved.get("SyncToken").and_then(|v| v.as_str()).map(String::from),
+        })
+    }
+}
+
+async fn parse_response(response: reqwest::Response) -> AccountingResult<serde_json::Value> {
+    let status = response.status();
+    let body = response.bytes().await?;
+
+    // Validation faults may come back with a 200 as well as a 400
+    let fault = serde_json::from_slice::<QuickBooksFaultResponse>(&body).ok().map(|f| {
+        f.fault
+            .errors
+            .into_iter()
+            .map(|e| match (e.message, e.detail) {
+                (Some(message), Some(detail)) => format!("{}: {}", message, detail),
+                (message, detail) => message.or(detail).unwrap_or_else(|| "unknown error".to_string()),
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    });
+    if !status.is_success() || fault.is_some() {
+        return Err(format!("QuickBooks returned {}: {}", status, fault.unwrap_or_else(|| "unknown error".to_string())).into());
+    }
+
+    Ok(serde_json::from_slice(&body)?)
+}
+
+pub fn customer_body(customer: &Customer, existing: Option<&ExternalRef>) -> serde_json::Value {
+    let mut body = json!({
+        "DisplayName": customer.name,
+        "CurrencyRef": { "value": customer.currency },
+    });
+    if let Some(email) = &customer.email {
+        body["PrimaryEmailAddr"] = json!({ "Address": email });
+    }
+    if let Some(existing) = existing {
+        body["Id"] = json!(existing.id);
+        body["SyncToken"] = json!(existing.version.clone().unwrap_or_else(|| "0".to_string()));
+        body["sparse"] = json!(true);
+    }
+    body
+}
+
+/// Every account must be mapped to a QuickBooks account id for the API.
+pub fn journal_body(
+    entry: &JournalEntry,
+    customer: &ExternalRef,
+    existing: Option<&ExternalRef>,
+) -> AccountingResult<serde_json::Value> {
+    let mut lines = Vec::new();
+    for line in &entry.lines {
+        let account_id = line
+            .account
+            .external_id
+            .as_ref()
+            .ok_or_else(|| format!("Account '{}' is not mapped to a QuickBooks account id", line.account.name))?;
+        let (posting_type, amount) = if line.debit > line.credit { ("Debit", line.debit) } else { ("Credit", line.credit) };
+
+        lines.push(json!({
+            "DetailType": "JournalEntryLineDetail",
+            "Amount": amount,
+            "Description": line.description,
+            "JournalEntryLineDetail": {
+                "PostingType": posting_type,
+                "AccountRef": { "value": account_id },
+                "Entity": { "Type": "Customer", "EntityRef": { "value": customer.id } },
+            },
+        }));
+    }
+
+    let mut body = json!({
+        "DocNumber": entry.reference.chars().take(DOC_NUMBER_MAX_LEN).collect::<String>(),
+        "TxnDate": entry.date.format("%Y-%m-%d").to_string(),
+        "PrivateNote": entry.memo,
+        "CurrencyRef": { "value": entry.currency },
+        "ExchangeRate": entry.exchange_rate,
+        "Line": lines,
+    });
+    if let Some(existing) = existing {
+        body["Id"] = json!(existing.id);
+        body["SyncToken"] = json!(existing.version.clone().unwrap_or_else(|| "0".to_string()));
+    }
+    Ok(body)
+}
+
+#[async_trait]
+impl AccountingApi for QuickBooksClient {
+    fn target(&self) -> &str {
+        TARGET_QUICKBOOKS_ONLINE
+    }
+
+    async fn upsert_customer(&self, customer: &Customer, existing: Option<&ExternalRef>) -> AccountingResult<ExternalRef> {
+        self.save("Customer", customer_body(customer, existing)).await
+    }
+
+    async fn upsert_journal(
+        &self,
+        entry: &JournalEntry,
+        customer: &ExternalRef,
+        existing: Option<&ExternalRef>,
+    ) -> AccountingResult<ExternalRef> {
+        self.save("JournalEntry", journal_body(entry, customer, existing)?).await
+    }
+
+    async fn delete_journal(&self, existing: &ExternalRef) -> AccountingResult<()> {
+        let body = json!({
+            "Id": existing.id,
+            "SyncToken": existing.version.clone().unwrap_or_else(|| "0".to_string()),
+        });
+        self.post("journalentry", &body, Some("delete")).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::accounting::{AccountRef, JournalLine};
+    use rust_decimal::Decimal;
+    use uuid::Uuid;
+    use wiremock::matchers::{body_partial_json, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn test_client(api_base: String) -> QuickBooksClient {
+        QuickBooksClient::new(
+            Some(api_base),
+            "4620816365".to_string(),
+            QuickBooksCredentials { access_token: "qb_token".to_string() },
+        )
+    }
+
+    fn account(name: &str, external_id: Option<&str>) -> AccountRef {
+        AccountRef { name: name.to_string(), code: None, external_id: external_id.map(String::from) }
+    }
+
+    fn entry(receivable_id: Option<&str>) -> JournalEntry {
+        JournalEntry {
+            entity_type: "invoice".to_string(),
+            entity_id: Uuid::new_v4(),
+            reference: "INV-1001".to_string(),
+            date: NaiveDate::from_ymd_opt(2024, 3, 5).unwrap(),
+            client_id: Uuid::new_v4(),
+            customer: "Acme".to_string(),
+            currency: "USD".to_string(),
+            exchange_rate: Decimal::ONE,
+            memo: "Invoice INV-1001".to_string(),
+            lines: vec![
+                JournalLine::signed(account("Accounts Receivable", receivable_id), Decimal::new(11000, 2), "Invoice INV-1001"),
+                JournalLine::signed(account("Services", Some("79")), Decimal::new(-11000, 2), "Invoice INV-1001"),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_journal_body_requires_mapped_accounts() {
+        let customer = ExternalRef { id: "58".to_string(), version: None };
+
+        let body = journal_body(&entry(Some("84")), &customer, None).unwrap();
+        assert_eq!(body["DocNumber"], "INV-1001");
+        assert_eq!(body["TxnDate"], "2024-03-05");
+        assert_eq!(body["Line"][0]["JournalEntryLineDetail"]["PostingType"], "Debit");
+        assert_eq!(body["Line"][0]["JournalEntryLineDetail"]["AccountRef"]["value"], "84");
+        assert_eq!(body["Line"][1]["JournalEntryLineDetail"]["PostingType"], "Credit");
+        assert_eq!(body["Line"][1]["JournalEntryLineDetail"]["Entity"]["EntityRef"]["value"], "58");
+        assert!(body.get("Id").is_none());
+
+        let existing = ExternalRef { id: "301".to_string(), version: Some("2".to_string()) };
+        let body = journal_body(&entry(Some("84")), &customer, Some(&existing)).unwrap();
+        assert_eq!(body["Id"], "301");
+        assert_eq!(body["SyncToken"], "2");
+
+        let err = journal_body(&entry(None), &customer, None).unwrap_err();
+        assert!(err.to_string().contains("Accounts Receivable"));
+    }
+
+    #[tokio::test]
+    async fn test_upsert_journal() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/v3/company/4620816365/journalentry"))
+            .and(query_param("minorversion", "65"))
+            .and(header("Authorization", "Bearer qb_token"))
+            .and(body_partial_json(json!({ "DocNumber": "INV-1001", "PrivateNote": "Invoice INV-1001" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "JournalEntry": { "Id": "301", "SyncToken": "0", "DocNumber": "INV-1001" },
+                "time": "2024-03-05T10:00:00-08:00"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = test_client(server.uri());
+        let customer = ExternalRef { id: "58".to_string(), version: Some("0".to_string()) };
+        let saved = client.upsert_journal(&entry(Some("84")), &customer, None).await.unwrap();
+        assert_eq!(saved, ExternalRef { id: "301".to_string(), version: Some("0".to_string()) });
+    }
+
+    #[tokio::test]
+    async fn test_fault_and_delete() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/v3/company/4620816365/customer"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+                "Fault": {
+                    "Error": [{ "Message": "Duplicate Name Exists Error", "Detail": "The name supplied already exists." }],
+                    "type": "ValidationFault"
+                }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v3/company/4620816365/journalentry"))
+            .and(query_param("operation", "delete"))
+            .and(body_partial_json(json!({ "Id": "301", "SyncToken": "3" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "JournalEntry": { "Id": "301", "status": "Deleted" }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = test_client(server.uri());
+        let customer = Customer {
+            client_id: Uuid::new_v4(),
+            name: "Acme".to_string(),
+            email: Some("billing@acme.test".to_string()),
+            currency: "USD".to_string(),
+        };
+        let err = client.upsert_customer(&customer, None).await.unwrap_err();
+        assert!(err.to_string().contains("Duplicate Name Exists Error: The name supplied already exists."));
+
+        client
+            .delete_journal(&ExternalRef { id: "301".to_string(), version: Some("3".to_string()) })
+            .await
+            .unwrap();
+    }
+}
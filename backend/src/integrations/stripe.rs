@@ -5,15 +5,27 @@ use axum::{
     routing::{get, post},
     Router,
 };
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
 use crate::auth::middleware::AuthUser;
+use crate::services::currency::minor_units;
 use crate::AppState;
 use ghosthub_shared::Integration;
 use super::decrypt_json;
 
+pub type StripeResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+const STRIPE_DEFAULT_API_BASE: &str = "https://api.stripe.com";
+
+/// How old a webhook timestamp may be before the delivery is treated as a replay.
+pub const WEBHOOK_TOLERANCE_SECONDS: i64 = 300;
+
 pub fn stripe_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/customers", get(list_stripe_customers))
@@ -31,6 +43,325 @@ pub struct StripeCredentials {
     pub webhook_endpoint_secret: Option<String>,
 }
 
+// Stripe REST API over form-encoded requests. `config.api_base` on the
+// integration points it at stripe-mock or another stand-in.
+pub struct StripeClient {
+    client: reqwest::Client,
+    api_base: String,
+    credentials: StripeCredentials,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckoutSession {
+    pub id: String,
+    pub url: Option<String>,
+    pub payment_intent: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentIntent {
+    pub id: String,
+    pub status: String,
+    pub amount: i64,
+    pub amount_received: Option<i64>,
+    pub currency: String,
+    pub client_secret: Option<String>,
+    pub customer: Option<String>,
+    pub payment_method: Option<String>,
+    #[serde(default)]
+    pub metadata: std::collections::HashMap<String, String>,
+    pub last_payment_error: Option<StripeErrorDetail>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookEvent {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub data: WebhookEventData,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookEventData {
+    pub object: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StripeErrorDetail {
+    pub message: Option<String>,
+    pub code: Option<String>,
+    pub decline_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StripeErrorResponse {
+    error: StripeErrorDetail,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StripePaymentMethod {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub method_type: String,
+    pub customer: Option<String>,
+    pub card: Option<StripeCard>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StripeCard {
+    pub brand: Option<String>,
+    pub last4: Option<String>,
+    pub exp_month: Option<i32>,
+    pub exp_year: Option<i32>,
+}
+
+/// A hosted Checkout page for one invoice payment.
+pub struct CheckoutRequest<'a> {
+    pub customer_id: &'a str,
+    pub payment_transaction_id: Uuid,
+    pub description: &'a str,
+    pub amount: Decimal,
+    pub currency: &'a str,
+    pub success_url: &'a str,
+    pub cancel_url: &'a str,
+    pub save_payment_method: bool,
+}
+
+/// A PaymentIntent for the portal's embedded card form, or an off-session
+/// charge of a saved card when `off_session` is set.
+pub struct PaymentIntentRequest<'a> {
+    pub customer_id: &'a str,
+    pub payment_transaction_id: Uuid,
+    pub description: &'a str,
+    pub amount: Decimal,
+    pub currency: &'a str,
+    pub payment_method: Option<&'a str>,
+    pub off_session: bool,
+    pub save_payment_method: bool,
+}
+
+impl StripeClient {
+    pub fn new(api_base: Option<String>, credentials: StripeCredentials) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            client,
+            api_base: api_base
+                .filter(|b| !b.is_empty())
+                .unwrap_or_else(|| STRIPE_DEFAULT_API_BASE.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            credentials,
+        }
+    }
+
+    /// Client for the enabled Stripe integration.
+    pub async fn from_integration(db_pool: &PgPool) -> StripeResult<Self> {
+        let (config, credentials): (serde_json::Value, serde_json::Value) = sqlx::query_as(
+            "SELECT config, credentials FROM integrations
+             WHERE integration_type = 'stripe' AND enabled = true
+             ORDER BY created_at LIMIT 1",
+        )
+        .fetch_optional(db_pool)
+        .await?
+        .ok_or("No enabled Stripe integration")?;
+
+        Self::from_parts(&config, &credentials)
+    }
+
+    fn from_parts(config: &serde_json::Value, credentials: &serde_json::Value) -> StripeResult<Self> {
+        let credentials = decrypt_json(credentials).map_err(|e| format!("Decrypting Stripe credentials: {}", e))?;
+        let credentials: StripeCredentials = serde_json::from_value(credentials)?;
+        let api_base = config.get("api_base").and_then(|v| v.as_str()).map(String::from);
+
+        Ok(Self::new(api_base, credentials))
+    }
+
+    pub fn publishable_key(&self) -> &str {
+        &self.credentials.publishable_key
+    }
+
+    pub fn webhook_secret(&self) -> Option<&str> {
+        self.credentials.webhook_endpoint_secret.as_deref().filter(|s| !s.is_empty())
+    }
+
+    pub async fn create_customer(&self, client_id: Uuid, name: &str, email: Option<&str>) -> StripeResult<String> {
+        let mut params = vec![
+            ("name".to_string(), name.to_string()),
+            ("metadata[client_id]".to_string(), client_id.to_string()),
+        ];
+        if let Some(email) = email {
+            params.push(("email".to_string(), email.to_string()));
+        }
+
+        let customer: serde_json::Value = self.post("/v1/customers", &params, None).await?;
+        customer
+            .get("id")
+            .and_then(|v| v.as_str())
+            .map(String::from)
+            .ok_or_else(|| "Stripe customer response has no id".into())
+    }
+
+    pub async fn create_checkout_session(&self, request: &CheckoutRequest<'_>) -> StripeResult<CheckoutSession> {
+        let transaction_id = request.payment_transaction_id.to_string();
+        let mut params = vec![
+            ("mode".to_string(), "payment".to_string()),
+            ("customer".to_string(), request.customer_id.to_string()),
+            ("client_reference_id".to_string(), transaction_id.clone()),
+            ("success_url".to_string(), request.success_url.to_string()),
+            ("cancel_url".to_string(), request.cancel_url.to_string()),
+            ("line_items[0][quantity]".to_string(), "1".to_string()),
+            ("line_items[0][price_data][currency]".to_string(), request.currency.to_lowercase()),
+            (
+                "line_items[0][price_data][unit_amount]".to_string(),
+                to_minor_units(request.amount, request.currency)?.to_string(),
+            ),
+            ("line_items[0][price_data][product_data][name]".to_string(), request.description.to_string()),
+            ("metadata[payment_transaction_id]".to_string(), transaction_id.clone()),
+            // The payment is recorded from the PaymentIntent events, so it carries the reference too
+            ("payment_intent_data[metadata][payment_transaction_id]".to_string(), transaction_id.clone()),
+            ("payment_intent_data[description]".to_string(), request.description.to_string()),
+        ];
+        if request.save_payment_method {
+            params.push(("payment_intent_data[setup_future_usage]".to_string(), "off_session".to_string()));
+            params.push(("payment_intent_data[metadata][save_payment_method]".to_string(), "true".to_string()));
+        }
+
+        self.post("/v1/checkout/sessions", &params, Some(&transaction_id)).await
+    }
+
+    /// Retried requests for the same transaction reuse its id as the
+    /// idempotency key, so a card is never charged twice for one transaction.
+    pub async fn create_payment_intent(&self, request: &PaymentIntentRequest<'_>) -> StripeResult<PaymentIntent> {
+        let transaction_id = request.payment_transaction_id.to_string();
+        let mut params = vec![
+            ("amount".to_string(), to_minor_units(request.amount, request.currency)?.to_string()),
+            ("currency".to_string(), request.currency.to_lowercase()),
+            ("customer".to_string(), request.customer_id.to_string()),
+            ("description".to_string(), request.description.to_string()),
+            ("metadata[payment_transaction_id]".to_string(), transaction_id.clone()),
+        ];
+        if let Some(payment_method) = request.payment_method {
+            params.push(("payment_method".to_string(), payment_method.to_string()));
+            params.push(("confirm".to_string(), "true".to_string()));
+        } else {
+            params.push(("automatic_payment_methods[enabled]".to_string(), "true".to_string()));
+        }
+        if request.off_session {
+            params.push(("off_session".to_string(), "true".to_string()));
+        } else if request.save_payment_method {
+            params.push(("setup_future_usage".to_string(), "off_session".to_string()));
+            params.push(("metadata[save_payment_method]".to_string(), "true".to_string()));
+        }
+
+        self.post("/v1/payment_intents", &params, Some(&transaction_id)).await
+    }
+
+    pub async fn retrieve_payment_method(&self, payment_method_id: &str) -> StripeResult<StripePaymentMethod> {
+        self.get(&format!("/v1/payment_methods/{}", payment_method_id)).await
+    }
+
+    pub async fn detach_payment_method(&self, payment_method_id: &str) -> StripeResult<()> {
+        let _: serde_json::Value = self
+            .post(&format!("/v1/payment_methods/{}/detach", payment_method_id), &[], None)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn retrieve_balance(&self) -> StripeResult<serde_json::Value> {
+        self.get("/v1/balance").await
+    }
+
+    async fn post<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        params: &[(String, String)],
+        idempotency_key: Option<&str>,
+    ) -> StripeResult<T> {
+        let mut request = self
+            .client
+            .post(format!("{}{}", self.api_base, path))
+            .bearer_auth(&self.credentials.secret_key)
+            .form(params);
+        if let Some(key) = idempotency_key {
+            request = request.header("Idempotency-Key", key);
+        }
+
+        parse_response(request.send().await?).await
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> StripeResult<T> {
+        let response = self
+            .client
+            .get(format!("{}{}", self.api_base, path))
+            .bearer_auth(&self.credentials.secret_key)
+            .send()
+            .await?;
+
+        parse_response(response).await
+    }
+}
+
+async fn parse_response<T: serde::de::DeserializeOwned>(response: reqwest::Response) -> StripeResult<T> {
+    let status = response.status();
+    let body = response.bytes().await?;
+
+    if !status.is_success() {
+        let message = serde_json::from_slice::<StripeErrorResponse>(&body)
+            .ok()
+            .and_then(|e| e.error.message)
+            .unwrap_or_else(|| "unknown error".to_string());
+        return Err(format!("Stripe returned {}: {}", status, message).into());
+    }
+
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// Stripe amounts are integers in the currency's smallest unit.
+pub fn to_minor_units(amount: Decimal, currency: &str) -> StripeResult<i64> {
+    let scale = Decimal::from(10i64.pow(minor_units(currency)));
+    (amount * scale)
+        .round()
+        .to_i64()
+        .ok_or_else(|| format!("Amount {} is out of range", amount).into())
+}
+
+pub fn from_minor_units(amount: i64, currency: &str) -> Decimal {
+    Decimal::new(amount, minor_units(currency))
+}
+
+/// Checks a `Stripe-Signature` header (`t=<timestamp>,v1=<hex hmac>,...`)
+/// against HMAC-SHA256 of `"{t}.{payload}"` under the endpoint secret.
+pub fn verify_webhook_signature(secret: &str, header: &str, payload: &[u8], now: i64) -> bool {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.extend(hex::decode(value).ok()),
+            _ => {}
+        }
+    }
+
+    let Some(timestamp) = timestamp else {
+        return false;
+    };
+    if (now - timestamp).abs() > WEBHOOK_TOLERANCE_SECONDS {
+        return false;
+    }
+
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+    let mut signed = format!("{}.", timestamp).into_bytes();
+    signed.extend_from_slice(payload);
+
+    signatures
+        .iter()
+        .any(|signature| ring::hmac::verify(&key, &signed, signature).is_ok())
+}
+
 async fn list_stripe_customers(_state: State<Arc<AppState>>, _query: Query<serde_json::Value>, _auth: AuthUser) -> Result<impl IntoResponse, StatusCode> {
     Ok(Json(serde_json::json!([])))
 }
@@ -63,7 +394,131 @@ pub async fn sync_stripe_integration(
 }
 
 pub async fn test_stripe_connection(
-    _integration: &Integration,
+    integration: &Integration,
 ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
-    Ok(serde_json::json!({"status": "placeholder"}))
+    let client = StripeClient::from_parts(&integration.config, &integration.credentials)?;
+    let balance = client.retrieve_balance().await?;
+
+    Ok(serde_json::json!({
+        "status": "connected",
+        "livemode": balance.get("livemode"),
+        "webhook_secret_configured": client.webhook_secret().is_some(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_string_contains, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn sign(secret: &str, timestamp: i64, payload: &[u8]) -> String {
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+        let mut signed = format!("{}.", timestamp).into_bytes();
+        signed.extend_from_slice(payload);
+        format!("t={},v1={}", timestamp, hex::encode(ring::hmac::sign(&key, &signed).as_ref()))
+    }
+
+    fn test_client(api_base: String) -> StripeClient {
+        StripeClient::new(
+            Some(api_base),
+            StripeCredentials {
+                secret_key: "sk_test_123".to_string(),
+                publishable_key: "pk_test_123".to_string(),
+                webhook_endpoint_secret: Some("whsec_test".to_string()),
+            },
+        )
+    }
+
+    #[test]
+    fn test_verify_webhook_signature() {
+        let payload = br#"{"id":"evt_1","type":"payment_intent.succeeded"}"#;
+        let header = sign("whsec_test", 1_700_000_000, payload);
+
+        assert!(verify_webhook_signature("whsec_test", &header, payload, 1_700_000_100));
+        // Any of several v1 signatures may match, e.g. during secret rotation
+        let rotated = format!("{},v1={}", header, "00".repeat(32));
+        assert!(verify_webhook_signature("whsec_test", &rotated, payload, 1_700_000_000));
+
+        assert!(!verify_webhook_signature("whsec_other", &header, payload, 1_700_000_000));
+        assert!(!verify_webhook_signature("whsec_test", &header, b"{}", 1_700_000_000));
+        assert!(!verify_webhook_signature("whsec_test", &header, payload, 1_700_000_000 + 301));
+        assert!(!verify_webhook_signature("whsec_test", "v1=abc", payload, 1_700_000_000));
+    }
+
+    #[test]
+    fn test_minor_units() {
+        assert_eq!(to_minor_units(Decimal::new(12345, 2), "USD").unwrap(), 12345);
+        assert_eq!(to_minor_units(Decimal::new(5000, 0), "JPY").unwrap(), 5000);
+        assert_eq!(from_minor_units(12345, "GBP"), Decimal::new(12345, 2));
+        assert_eq!(from_minor_units(5000, "JPY"), Decimal::new(5000, 0));
+    }
+
+    #[tokio::test]
+    async fn test_create_checkout_session() {
+        let server = MockServer::start().await;
+        let transaction_id = Uuid::new_v4();
+
+        Mock::given(method("POST"))
+            .and(path("/v1/checkout/sessions"))
+            .and(header("Authorization", "Bearer sk_test_123"))
+            .and(header("Idempotency-Key", transaction_id.to_string().as_str()))
+            .and(body_string_contains("line_items%5B0%5D%5Bprice_data%5D%5Bunit_amount%5D=25050"))
+            .and(body_string_contains("setup_future_usage%5D=off_session"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "cs_test_1",
+                "url": "https://checkout.stripe.com/c/pay/cs_test_1",
+                "payment_intent": null,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let session = test_client(server.uri())
+            .create_checkout_session(&CheckoutRequest {
+                customer_id: "cus_1",
+                payment_transaction_id: transaction_id,
+                description: "Invoice INV-1001",
+                amount: Decimal::new(25050, 2),
+                currency: "EUR",
+                success_url: "https://portal.example/paid",
+                cancel_url: "https://portal.example/invoices",
+                save_payment_method: true,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(session.id, "cs_test_1");
+        assert_eq!(session.url.as_deref(), Some("https://checkout.stripe.com/c/pay/cs_test_1"));
+    }
+
+    #[tokio::test]
+    async fn test_declined_payment_intent_is_an_error() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/v1/payment_intents"))
+            .and(body_string_contains("off_session=true"))
+            .respond_with(ResponseTemplate::new(402).set_body_json(serde_json::json!({
+                "error": { "type": "card_error", "code": "card_declined", "message": "Your card was declined." }
+            })))
+            .mount(&server)
+            .await;
+
+        let err = test_client(server.uri())
+            .create_payment_intent(&PaymentIntentRequest {
+                customer_id: "cus_1",
+                payment_transaction_id: Uuid::new_v4(),
+                description: "Invoice INV-1002",
+                amount: Decimal::new(9900, 2),
+                currency: "USD",
+                payment_method: Some("pm_card_visa"),
+                off_session: true,
+                save_payment_method: false,
+            })
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("Your card was declined."));
+    }
 }
\ No newline at end of file
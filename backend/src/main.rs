@@ -42,9 +42,128 @@ async fn main() -> anyhow::Result<()> {
     
     database::migrate(&db_pool).await?;
 
-    let ws_manager = websocket::WsManager::new();
+    let ws_manager = match &config.redis_url {
+        Some(redis_url) => match websocket::WsManager::with_redis(redis_url).await {
+            Ok(manager) => manager,
+            Err(e) => {
+                tracing::error!("Redis unavailable, WebSocket events limited to this instance: {}", e);
+                websocket::WsManager::new()
+            }
+        },
+        None => websocket::WsManager::new(),
+    };
     let app_state = Arc::new(AppState { db_pool, ws_manager });
 
+    match services::EmailService::new(&config.smtp).await {
+        Ok(email_service) => {
+            let notification_worker = services::NotificationQueueService::new(
+                services::NotificationQueueConfig::default(),
+                app_state.clone(),
+                email_service,
+            );
+            if let Err(e) = notification_worker.start().await {
+                tracing::error!("Failed to start notification queue worker: {}", e);
+            }
+        }
+        Err(e) => tracing::error!("Notification queue worker disabled, email unavailable: {}", e),
+    }
+
+    let recurring_billing = services::RecurringBillingService::new(
+        services::RecurringBillingConfig::default(),
+        app_state.db_pool.clone(),
+    );
+    if let Err(e) = recurring_billing.start().await {
+        tracing::error!("Failed to start recurring billing engine: {}", e);
+    }
+
+    let prepaid_blocks = services::PrepaidBlockService::new(
+        services::PrepaidBlockConfig::default(),
+        app_state.db_pool.clone(),
+    );
+    if let Err(e) = prepaid_blocks.start().await {
+        tracing::error!("Failed to start prepaid block worker: {}", e);
+    }
+
+    let quotes = services::QuoteService::new(
+        services::QuoteConfig::default(),
+        app_state.db_pool.clone(),
+    );
+    if let Err(e) = quotes.start().await {
+        tracing::error!("Failed to start quote expiry worker: {}", e);
+    }
+
+    let exchange_rates = services::ExchangeRateService::new(
+        services::ExchangeRateConfig::default(),
+        app_state.db_pool.clone(),
+    );
+    if let Err(e) = exchange_rates.start().await {
+        tracing::error!("Failed to start exchange rate sync: {}", e);
+    }
+
+    let payment_collection = services::PaymentCollectionService::new(
+        services::PaymentCollectionConfig::default(),
+        app_state.db_pool.clone(),
+    );
+    if let Err(e) = payment_collection.start().await {
+        tracing::error!("Failed to start payment collection: {}", e);
+    }
+
+    let statements = services::StatementService::new(
+        services::StatementConfig::default(),
+        app_state.db_pool.clone(),
+    );
+    if let Err(e) = statements.start().await {
+        tracing::error!("Failed to start statement worker: {}", e);
+    }
+
+    let dunning = services::DunningService::new(
+        services::DunningConfig::default(),
+        app_state.db_pool.clone(),
+    );
+    if let Err(e) = dunning.start().await {
+        tracing::error!("Failed to start dunning worker: {}", e);
+    }
+
+    let accounting_sync = services::AccountingSyncService::new(
+        services::AccountingSyncConfig::default(),
+        app_state.db_pool.clone(),
+    );
+    if let Err(e) = accounting_sync.start().await {
+        tracing::error!("Failed to start accounting sync worker: {}", e);
+    }
+
+    let profitability = services::ProfitabilityService::new(
+        services::ProfitabilityConfig::default(),
+        app_state.db_pool.clone(),
+    );
+    if let Err(e) = profitability.start().await {
+        tracing::error!("Failed to start profitability worker: {}", e);
+    }
+
+    let timesheets = services::TimesheetService::new(
+        services::TimesheetConfig::default(),
+        app_state.db_pool.clone(),
+    );
+    if let Err(e) = timesheets.start().await {
+        tracing::error!("Failed to start timesheet worker: {}", e);
+    }
+
+    let timer_reconciliation = services::TimerReconciliationService::new(
+        services::TimerReconciliationConfig::default(),
+        app_state.db_pool.clone(),
+    );
+    if let Err(e) = timer_reconciliation.start().await {
+        tracing::error!("Failed to start timer reconciliation worker: {}", e);
+    }
+
+    let project_budgets = services::ProjectBudgetService::new(
+        services::ProjectBudgetConfig::default(),
+        app_state.db_pool.clone(),
+    );
+    if let Err(e) = project_budgets.start().await {
+        tracing::error!("Failed to start project budget worker: {}", e);
+    }
+
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
@@ -77,6 +196,24 @@ async fn main() -> anyhow::Result<()> {
         .nest("/api/v1/licenses", handlers::license_alert_routes())
         .nest("/api/v1/documentation", handlers::documentation_routes())
         .nest("/api/v1/reporting", handlers::reporting_routes())
+        .nest("/api/v1/sms", handlers::sms_routes())
+        .nest("/api/v1/chat", handlers::chat_routes())
+        .nest("/api/v1/portal-inbox", handlers::portal_inbox_routes())
+        .nest("/api/v1/recurring-billing", handlers::recurring_billing_routes())
+        .nest("/api/v1/contracts", handlers::contract_routes())
+        .nest("/api/v1/rate-cards", handlers::rate_card_routes())
+        .nest("/api/v1/quotes", handlers::quote_routes())
+        .nest("/api/v1/credit-notes", handlers::credit_note_routes())
+        .nest("/api/v1/expenses", handlers::expense_routes())
+        .nest("/api/v1/sales-tax", handlers::sales_tax_routes())
+        .nest("/api/v1/currencies", handlers::currency_routes())
+        .nest("/api/v1/payments", handlers::payment_routes())
+        .nest("/api/v1/statements", handlers::statement_routes())
+        .nest("/api/v1/dunning", handlers::dunning_routes())
+        .nest("/api/v1/accounting", handlers::accounting_routes())
+        .nest("/api/v1/billing-review", handlers::billing_review_routes())
+        .nest("/api/v1/project-templates", handlers::project_template_routes())
+        .nest("/api/v1/dispatch", handlers::dispatch_routes())
         .route("/ws", get(websocket::websocket_handler))
         .layer(ServiceBuilder::new().layer(cors))
         .with_state(app_state);
@@ -254,6 +254,277 @@ pub async fn create_notifications_for_users(
     Ok(notification_ids)
 }
 
+// Outbound notification routed through notification_queue. Delivery happens in
+// services::notification_queue, which applies quiet hours, digests and retries.
+#[derive(Debug, Clone)]
+pub struct QueuedNotification {
+    pub recipient_type: String, // user or contact
+    pub recipient_id: Uuid,
+    pub notification_type: String,
+    pub title: String,
+    pub message: String,
+    pub priority: String, // low, normal, high, critical
+    pub variables: serde_json::Value,
+    pub entity_type: Option<String>,
+    pub entity_id: Option<Uuid>,
+    pub attachments: Vec<crate::services::email::EmailAttachment>, // sent with the email channel only
+}
+
+impl QueuedNotification {
+    pub fn for_user(user_id: Uuid, notification_type: &str, title: String, message: String) -> Self {
+        Self {
+            recipient_type: "user".to_string(),
+            recipient_id: user_id,
+            notification_type: notification_type.to_string(),
+            title,
+            message,
+            priority: "normal".to_string(),
+            variables: serde_json::json!({}),
+            entity_type: None,
+            entity_id: None,
+            attachments: Vec::new(),
+        }
+    }
+
+    pub fn for_contact(contact_id: Uuid, notification_type: &str, title: String, message: String) -> Self {
+        Self {
+            recipient_type: "contact".to_string(),
+            ..Self::for_user(contact_id, notification_type, title, message)
+        }
+    }
+
+    pub fn with_priority(mut self, priority: &str) -> Self {
+        self.priority = priority.to_string();
+        self
+    }
+
+    pub fn with_entity(mut self, entity_type: &str, entity_id: Uuid) -> Self {
+        self.entity_type = Some(entity_type.to_string());
+        self.entity_id = Some(entity_id);
+        self
+    }
+
+    pub fn with_variables(mut self, variables: serde_json::Value) -> Self {
+        self.variables = variables;
+        self
+    }
+
+    pub fn with_attachment(mut self, attachment: crate::services::email::EmailAttachment) -> Self {
+        self.attachments.push(attachment);
+        self
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct MatchedRule {
+    id: Uuid,
+    template_id: Option<Uuid>,
+    channels: Option<Vec<String>>,
+    delay_minutes: Option<i32>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct MatchedTemplate {
+    id: Uuid,
+    subject: Option<String>,
+    email_template: Option<String>,
+    sms_template: Option<String>,
+    push_template: Option<String>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct EmailPreferences {
+    email_tickets: Option<bool>,
+    email_mentions: Option<bool>,
+    email_projects: Option<bool>,
+    email_billing: Option<bool>,
+    email_reports: Option<bool>,
+}
+
+const DEFAULT_CHANNELS: [&str; 3] = ["in_app", "websocket", "email"];
+
+// Queues one row per delivery channel. Channels come from the first active
+// notification_rule whose trigger_type matches, falling back to the defaults.
+pub async fn enqueue_notification(
+    db_pool: &sqlx::PgPool,
+    notification: QueuedNotification,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let rule = sqlx::query_as::<_, MatchedRule>(
+        r#"
+        SELECT id, template_id, channels, delay_minutes
+        FROM notification_rules
+        WHERE trigger_type = $1 AND is_active = true
+        AND (recipient_type IS NULL OR recipient_type = $2)
+        ORDER BY created_at
+        LIMIT 1
+        "#
+    )
+    .bind(&notification.notification_type)
+    .bind(&notification.recipient_type)
+    .fetch_optional(db_pool)
+    .await?;
+
+    let template = sqlx::query_as::<_, MatchedTemplate>(
+        r#"
+        SELECT id, subject, email_template, sms_template, push_template
+        FROM notification_templates
+        WHERE is_active = true AND (id = $1 OR name = $2)
+        ORDER BY (id = $1) DESC
+        LIMIT 1
+        "#
+    )
+    .bind(rule.as_ref().and_then(|r| r.template_id))
+    .bind(&notification.notification_type)
+    .fetch_optional(db_pool)
+    .await?;
+
+    let mut channels: Vec<String> = rule
+        .as_ref()
+        .and_then(|r| r.channels.clone())
+        .filter(|c| !c.is_empty())
+        .unwrap_or_else(|| DEFAULT_CHANNELS.iter().map(|c| c.to_string()).collect());
+
+    if notification.recipient_type == "user" && channels.iter().any(|c| c == "email") {
+        if !email_enabled_for(db_pool, notification.recipient_id, &notification.notification_type).await? {
+            channels.retain(|c| c != "email");
+        }
+    }
+
+    if notification.recipient_type == "user" && channels.iter().any(|c| c == "sms") {
+        if !sms_enabled_for(db_pool, notification.recipient_id, &notification.priority).await? {
+            channels.retain(|c| c != "sms");
+        }
+    }
+
+    let mut variables = notification.variables.clone();
+    if let Some(map) = variables.as_object_mut() {
+        map.entry("title").or_insert_with(|| serde_json::json!(notification.title));
+        map.entry("message").or_insert_with(|| serde_json::json!(notification.message));
+    }
+
+    let render = |template: Option<&String>, fallback: &str| {
+        template
+            .map(|t| crate::services::notification_queue::render_template(t, &variables))
+            .unwrap_or_else(|| fallback.to_string())
+    };
+
+    let subject = render(template.as_ref().and_then(|t| t.subject.as_ref()), &notification.title);
+    let delay_minutes = rule.as_ref().and_then(|r| r.delay_minutes).unwrap_or(0);
+    let metadata = serde_json::json!({
+        "entity_type": notification.entity_type,
+        "entity_id": notification.entity_id,
+        "variables": variables.clone(),
+        "attachments": notification.attachments,
+    });
+
+    let mut queued_ids = Vec::new();
+
+    for channel in channels {
+        let body_template = template.as_ref().and_then(|t| match channel.as_str() {
+            "email" => t.email_template.as_ref(),
+            "sms" => t.sms_template.as_ref().or(t.push_template.as_ref()),
+            _ => t.push_template.as_ref().or(t.email_template.as_ref()),
+        });
+        let content = render(body_template, &notification.message);
+
+        let id: Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO notification_queue (
+                rule_id, template_id, recipient_type, recipient_id, channel,
+                notification_type, priority, subject, content, metadata, scheduled_for
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10,
+                    NOW() + ($11 || ' minutes')::INTERVAL)
+            RETURNING id
+            "#
+        )
+        .bind(rule.as_ref().map(|r| r.id))
+        .bind(template.as_ref().map(|t| t.id))
+        .bind(&notification.recipient_type)
+        .bind(notification.recipient_id)
+        .bind(&channel)
+        .bind(&notification.notification_type)
+        .bind(&notification.priority)
+        .bind(&subject)
+        .bind(&content)
+        .bind(&metadata)
+        .bind(delay_minutes.to_string())
+        .fetch_one(db_pool)
+        .await?;
+
+        queued_ids.push(id);
+    }
+
+    Ok(queued_ids)
+}
+
+async fn email_enabled_for(
+    db_pool: &sqlx::PgPool,
+    user_id: Uuid,
+    notification_type: &str,
+) -> Result<bool, sqlx::Error> {
+    let preferences = sqlx::query_as::<_, EmailPreferences>(
+        r#"
+        SELECT email_tickets, email_mentions, email_projects, email_billing, email_reports
+        FROM notification_preferences
+        WHERE user_id = $1
+        "#
+    )
+    .bind(user_id)
+    .fetch_optional(db_pool)
+    .await?;
+
+    let Some(preferences) = preferences else {
+        return Ok(true);
+    };
+
+    let enabled = if notification_type.starts_with("ticket") || notification_type.starts_with("sla") {
+        preferences.email_tickets
+    } else if notification_type.contains("mention") {
+        preferences.email_mentions
+    } else if notification_type.starts_with("project") || notification_type.starts_with("task") {
+        preferences.email_projects
+    } else if notification_type.starts_with("invoice")
+        || notification_type.starts_with("payment")
+        || notification_type.starts_with("billing")
+    {
+        preferences.email_billing
+    } else if notification_type.starts_with("report") {
+        preferences.email_reports
+    } else {
+        Some(true)
+    };
+
+    Ok(enabled.unwrap_or(true))
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct SmsPreferences {
+    sms_enabled: Option<bool>,
+    sms_critical_only: Option<bool>,
+}
+
+// SMS is opt-in for staff; critical-only users still get on-call alerts.
+async fn sms_enabled_for(
+    db_pool: &sqlx::PgPool,
+    user_id: Uuid,
+    priority: &str,
+) -> Result<bool, sqlx::Error> {
+    let preferences = sqlx::query_as::<_, SmsPreferences>(
+        "SELECT sms_enabled, sms_critical_only FROM notification_preferences WHERE user_id = $1"
+    )
+    .bind(user_id)
+    .fetch_optional(db_pool)
+    .await?;
+
+    Ok(match preferences {
+        Some(p) if p.sms_enabled.unwrap_or(false) => {
+            !p.sms_critical_only.unwrap_or(true) || priority == "critical"
+        }
+        _ => false,
+    })
+}
+
 // Helper to create ticket-related notifications
 pub async fn notify_ticket_update(
     db_pool: &sqlx::PgPool,
@@ -283,15 +554,19 @@ pub async fn notify_ticket_update(
     let title = format!("Ticket {}", action);
     let message = format!("Ticket has been {}. {}", action.to_lowercase(), details);
 
-    create_notifications_for_users(
-        db_pool,
-        user_ids,
-        title,
-        message,
-        "ticket_update".to_string(),
-        Some("ticket".to_string()),
-        Some(ticket_id),
-    ).await?;
+    for user_id in user_ids {
+        enqueue_notification(
+            db_pool,
+            QueuedNotification::for_user(user_id, "ticket_update", title.clone(), message.clone())
+                .with_entity("ticket", ticket_id)
+                .with_variables(serde_json::json!({
+                    "ticket_id": ticket_id,
+                    "client_id": client_id,
+                    "action": action,
+                    "details": details,
+                })),
+        ).await?;
+    }
 
     Ok(())
 }
@@ -333,15 +608,14 @@ pub async fn notify_expiring_items(
         .fetch_all(db_pool)
         .await?;
 
-        create_notifications_for_users(
-            db_pool,
-            admin_user_ids,
-            title,
-            message,
-            "domain_expiry".to_string(),
-            Some("domain".to_string()),
-            Some(domain.id),
-        ).await?;
+        for user_id in admin_user_ids {
+            enqueue_notification(
+                db_pool,
+                QueuedNotification::for_user(user_id, "domain_expiry", title.clone(), message.clone())
+                    .with_priority("low")
+                    .with_entity("domain", domain.id),
+            ).await?;
+        }
     }
 
     // Notify about expiring SSL certificates
@@ -378,15 +652,14 @@ pub async fn notify_expiring_items(
         .fetch_all(db_pool)
         .await?;
 
-        create_notifications_for_users(
-            db_pool,
-            admin_user_ids,
-            title,
-            message,
-            "ssl_expiry".to_string(),
-            Some("ssl_certificate".to_string()),
-            Some(cert.id),
-        ).await?;
+        for user_id in admin_user_ids {
+            enqueue_notification(
+                db_pool,
+                QueuedNotification::for_user(user_id, "ssl_expiry", title.clone(), message.clone())
+                    .with_priority("low")
+                    .with_entity("ssl_certificate", cert.id),
+            ).await?;
+        }
     }
 
     Ok(())
@@ -0,0 +1,1444 @@
+use crate::services::currency;
+use crate::services::statements::csv_field;
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection, PgPool};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+pub type AccountingResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+pub const TARGET_QUICKBOOKS_ONLINE: &str = "quickbooks_online";
+
+/// Journal file formats for offline import.
+pub const EXPORT_FORMATS: [&str; 3] = ["quickbooks_iif", "quickbooks_csv", "xero_csv"];
+
+pub const MAPPING_TYPES: [&str; 6] = [
+    "customer",
+    "income_account",
+    "deposit_account",
+    "receivable_account",
+    "tax_account",
+    "credit_account",
+];
+
+pub const ENTITY_TYPES: [&str; 5] = ["client", "invoice", "payment", "refund", "credit_note"];
+
+pub const MAPPING_COLUMNS: &str =
+    "id, mapping_type, source_key, account_name, account_code, external_id, created_at, updated_at";
+
+pub const SYNC_RECORD_COLUMNS: &str = "id, target, entity_type, entity_id, external_id, external_version,
+    content_hash, last_payload, status, last_error, synced_at, updated_at";
+
+pub const EXPORT_COLUMNS: &str =
+    "id, format, period_start, period_end, changes_only, entry_count, file_path, created_by, created_at";
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AccountingMapping {
+    pub id: Uuid,
+    pub mapping_type: String,
+    pub source_key: String,
+    pub account_name: String,
+    pub account_code: Option<String>,
+    pub external_id: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SyncRecord {
+    pub id: Uuid,
+    pub target: String,
+    pub entity_type: String,
+    pub entity_id: Uuid,
+    pub external_id: Option<String>,
+    pub external_version: Option<String>,
+    pub content_hash: Option<String>,
+    pub last_payload: Option<serde_json::Value>,
+    pub status: String,
+    pub last_error: Option<String>,
+    pub synced_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl SyncRecord {
+    fn external_ref(&self) -> Option<ExternalRef> {
+        if self.status == "removed" {
+            return None;
+        }
+        self.external_id.as_ref().map(|id| ExternalRef { id: id.clone(), version: self.external_version.clone() })
+    }
+
+    /// The journal entry last sent or exported, which is reversed when the record changes.
+    fn previous_entry(&self) -> Option<JournalEntry> {
+        if self.status != "synced" {
+            return None;
+        }
+        self.last_payload.clone().and_then(|payload| serde_json::from_value(payload).ok())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AccountingExport {
+    pub id: Uuid,
+    pub format: String,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub changes_only: bool,
+    pub entry_count: i32,
+    pub file_path: String,
+    pub created_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// An account (or customer) in the books.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountRef {
+    pub name: String,
+    pub code: Option<String>,
+    pub external_id: Option<String>,
+}
+
+impl AccountRef {
+    pub fn named(name: &str) -> Self {
+        Self { name: name.to_string(), code: None, external_id: None }
+    }
+}
+
+/// Account mappings by type and source key. Unmapped keys fall back to the
+/// type's 'default' mapping and then to a standard chart-of-accounts name.
+#[derive(Debug, Clone, Default)]
+pub struct AccountMap {
+    mappings: HashMap<(String, String), AccountRef>,
+}
+
+impl AccountMap {
+    pub fn new(mappings: Vec<AccountingMapping>) -> Self {
+        Self {
+            mappings: mappings
+                .into_iter()
+                .map(|m| {
+                    (
+                        (m.mapping_type, m.source_key),
+                        AccountRef { name: m.account_name, code: m.account_code, external_id: m.external_id },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    pub async fn load(conn: &mut PgConnection) -> AccountingResult<Self> {
+        let mappings = sqlx::query_as::<_, AccountingMapping>(&format!(
+            "SELECT {} FROM accounting_mappings",
+            MAPPING_COLUMNS
+        ))
+        .fetch_all(conn)
+        .await?;
+
+        Ok(Self::new(mappings))
+    }
+
+    pub fn resolve(&self, mapping_type: &str, key: Option<&str>) -> AccountRef {
+        key.and_then(|k| self.mappings.get(&(mapping_type.to_string(), k.to_string())))
+            .or_else(|| self.mappings.get(&(mapping_type.to_string(), "default".to_string())))
+            .cloned()
+            .unwrap_or_else(|| AccountRef::named(default_account_name(mapping_type)))
+    }
+
+    /// The client's name in the books, which may differ from GhostHub's.
+    pub fn customer_name(&self, client_id: Uuid, name: &str) -> String {
+        self.mappings
+            .get(&("customer".to_string(), client_id.to_string()))
+            .map(|m| m.name.clone())
+            .unwrap_or_else(|| name.to_string())
+    }
+}
+
+fn default_account_name(mapping_type: &str) -> &'static str {
+    match mapping_type {
+        "deposit_account" => "Undeposited Funds",
+        "receivable_account" => "Accounts Receivable",
+        "tax_account" => "Sales Tax Payable",
+        "credit_account" => "Discounts and Refunds",
+        _ => "Services",
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Customer {
+    pub client_id: Uuid,
+    pub name: String,
+    pub email: Option<String>,
+    pub currency: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalLine {
+    pub account: AccountRef,
+    pub debit: Decimal,
+    pub credit: Decimal,
+    pub description: String,
+}
+
+impl JournalLine {
+    /// A positive amount is a debit, a negative one a credit.
+    pub fn signed(account: AccountRef, amount: Decimal, description: &str) -> Self {
+        Self {
+            account,
+            debit: amount.max(Decimal::ZERO),
+            credit: (-amount).max(Decimal::ZERO),
+            description: description.to_string(),
+        }
+    }
+
+    pub fn signed_amount(&self) -> Decimal {
+        self.debit - self.credit
+    }
+}
+
+/// One GhostHub document as a double-entry journal, in the document's currency.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub entity_type: String,
+    pub entity_id: Uuid,
+    pub reference: String,
+    pub date: NaiveDate,
+    pub client_id: Uuid,
+    pub customer: String,
+    pub currency: String,
+    pub exchange_rate: Decimal,
+    pub memo: String,
+    pub lines: Vec<JournalLine>,
+}
+
+impl JournalEntry {
+    pub fn is_balanced(&self) -> bool {
+        let debits: Decimal = self.lines.iter().map(|l| l.debit).sum();
+        let credits: Decimal = self.lines.iter().map(|l| l.credit).sum();
+        !self.lines.is_empty() && debits == credits
+    }
+
+    /// The entry that cancels this one, posted on `date`.
+    pub fn reversal(&self, date: NaiveDate) -> Self {
+        Self {
+            reference: format!("{}-REV", self.reference),
+            date,
+            memo: format!("Reversal of {}", self.memo),
+            lines: self
+                .lines
+                .iter()
+                .map(|l| JournalLine { debit: l.credit, credit: l.debit, ..l.clone() })
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    /// The entry converted at its exchange rate. Rounding differences go on
+    /// the largest line after the first, so the receivable or deposit amount
+    /// matches the converted document total and the entry stays balanced.
+    pub fn in_base(&self, base_currency: &str) -> Self {
+        if self.currency == base_currency {
+            return self.clone();
+        }
+
+        let mut lines: Vec<JournalLine> = self
+            .lines
+            .iter()
+            .map(|l| JournalLine {
+                debit: currency::convert(l.debit, self.exchange_rate),
+                credit: currency::convert(l.credit, self.exchange_rate),
+                ..l.clone()
+            })
+            .collect();
+
+        let difference: Decimal = lines.iter().map(|l| l.debit - l.credit).sum();
+        if let Some(largest) = lines.iter_mut().skip(1).max_by_key(|l| l.debit.max(l.credit)) {
+            if largest.debit > Decimal::ZERO {
+                largest.debit -= difference;
+            } else {
+                largest.credit += difference;
+            }
+        }
+
+        Self { currency: base_currency.to_string(), exchange_rate: Decimal::ONE, lines, ..self.clone() }
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct InvoiceSource {
+    pub id: Uuid,
+    pub number: String,
+    pub client_id: Uuid,
+    pub date: NaiveDate,
+    pub status: Option<String>,
+    pub currency: String,
+    pub exchange_rate: Decimal,
+    pub total: Decimal,
+    pub tax_amount: Decimal,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct InvoiceLineSource {
+    pub invoice_id: Uuid,
+    pub category: Option<String>, // tax category, else the line's source type
+    pub amount: Decimal,
+}
+
+/// A payment received, or a refund paid out.
+#[derive(Debug, Clone, FromRow)]
+pub struct CashSource {
+    pub id: Uuid,
+    pub entity_type: String,
+    pub client_id: Uuid,
+    pub invoice_number: Option<String>,
+    pub date: NaiveDate,
+    pub method: Option<String>, // payment method or refund processor
+    pub amount: Decimal,
+    pub currency: String,
+    pub exchange_rate: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct CreditNoteSource {
+    pub id: Uuid,
+    pub number: String,
+    pub client_id: Uuid,
+    pub date: NaiveDate,
+    pub status: Option<String>,
+    pub currency: String,
+    pub amount: Decimal,
+    pub reason: String,
+}
+
+/// Dr receivables, Cr income by category and Cr sales tax. Drafts, voided
+/// invoices and invoices credited down to nothing have no entry.
+pub fn invoice_entry(
+    invoice: &InvoiceSource,
+    lines: &[InvoiceLineSource],
+    customer: &str,
+    accounts: &AccountMap,
+) -> Option<JournalEntry> {
+    if matches!(invoice.status.as_deref(), None | Some("draft") | Some("void")) || invoice.total <= Decimal::ZERO {
+        return None;
+    }
+    let memo = format!("Invoice {}", invoice.number);
+
+    let mut income: BTreeMap<String, (AccountRef, Decimal)> = BTreeMap::new();
+    for line in lines {
+        let account = accounts.resolve("income_account", line.category.as_deref());
+        income.entry(account.name.clone()).or_insert((account, Decimal::ZERO)).1 += line.amount;
+    }
+
+    let mut journal = vec![JournalLine::signed(accounts.resolve("receivable_account", None), invoice.total, &memo)];
+    let net = invoice.total - invoice.tax_amount;
+    let difference = net - income.values().map(|(_, amount)| *amount).sum::<Decimal>();
+    for (account, amount) in income.into_values() {
+        if !amount.is_zero() {
+            journal.push(JournalLine::signed(account, -amount, &memo));
+        }
+    }
+    // Invoice-level discounts, or lines recorded outside invoice_line_items
+    if difference > Decimal::ZERO {
+        journal.push(JournalLine::signed(accounts.resolve("income_account", None), -difference, &memo));
+    } else if difference < Decimal::ZERO {
+        journal.push(JournalLine::signed(accounts.resolve("credit_account", None), -difference, &memo));
+    }
+    if !invoice.tax_amount.is_zero() {
+        journal.push(JournalLine::signed(accounts.resolve("tax_account", None), -invoice.tax_amount, &memo));
+    }
+
+    Some(JournalEntry {
+        entity_type: "invoice".to_string(),
+        entity_id: invoice.id,
+        reference: invoice.number.clone(),
+        date: invoice.date,
+        client_id: invoice.client_id,
+        customer: customer.to_string(),
+        currency: invoice.currency.clone(),
+        exchange_rate: invoice.exchange_rate,
+        memo,
+        lines: journal,
+    })
+}
+
+/// A payment is Dr deposit account by method, Cr receivables; a refund the reverse.
+pub fn cash_entry(cash: &CashSource, exchange_rate: Decimal, customer: &str, accounts: &AccountMap) -> Option<JournalEntry> {
+    if cash.amount <= Decimal::ZERO {
+        return None;
+    }
+    let (prefix, label, sign) = match cash.entity_type.as_str() {
+        "refund" => ("RFD", "Refund", -Decimal::ONE),
+        _ => ("PMT", "Payment", Decimal::ONE),
+    };
+    let memo = match &cash.invoice_number {
+        Some(number) => format!("{} on invoice {}", label, number),
+        None => format!("{} to {}", label, customer),
+    };
+    let deposit = accounts.resolve("deposit_account", cash.method.as_deref());
+    let receivable = accounts.resolve("receivable_account", None);
+
+    Some(JournalEntry {
+        entity_type: cash.entity_type.clone(),
+        entity_id: cash.id,
+        reference: format!("{}-{}", prefix, &cash.id.simple().to_string()[..8]),
+        date: cash.date,
+        client_id: cash.client_id,
+        customer: customer.to_string(),
+        currency: cash.currency.clone(),
+        exchange_rate,
+        lines: vec![
+            JournalLine::signed(deposit, sign * cash.amount, &memo),
+            JournalLine::signed(receivable, -sign * cash.amount, &memo),
+        ],
+        memo,
+    })
+}
+
+/// Dr discounts and refunds, Cr receivables. Draft and voided notes have no entry.
+pub fn credit_note_entry(
+    note: &CreditNoteSource,
+    exchange_rate: Decimal,
+    customer: &str,
+    accounts: &AccountMap,
+) -> Option<JournalEntry> {
+    if matches!(note.status.as_deref(), None | Some("draft") | Some("void")) || note.amount <= Decimal::ZERO {
+        return None;
+    }
+    let memo = format!("Credit note {}: {}", note.number, note.reason);
+
+    Some(JournalEntry {
+        entity_type: "credit_note".to_string(),
+        entity_id: note.id,
+        reference: note.number.clone(),
+        date: note.date,
+        client_id: note.client_id,
+        customer: customer.to_string(),
+        currency: note.currency.clone(),
+        exchange_rate,
+        lines: vec![
+            JournalLine::signed(accounts.resolve("credit_account", None), note.amount, &memo),
+            JournalLine::signed(accounts.resolve("receivable_account", None), -note.amount, &memo),
+        ],
+        memo,
+    })
+}
+
+/// A document to sync; `entry` is None when it should not be in the books
+/// (a draft or a void), so any earlier sync is removed.
+#[derive(Debug, Clone)]
+pub struct SourceRecord {
+    pub entity_type: String,
+    pub entity_id: Uuid,
+    pub client_id: Uuid,
+    pub entry: Option<JournalEntry>,
+}
+
+/// Invoices, payments, refunds and credit notes dated in the range, with the
+/// customers they belong to.
+pub async fn load_sources(
+    conn: &mut PgConnection,
+    accounts: &AccountMap,
+    from: NaiveDate,
+    to: Option<NaiveDate>,
+) -> AccountingResult<(Vec<SourceRecord>, HashMap<Uuid, Customer>)> {
+    let invoices = sqlx::query_as::<_, InvoiceSource>(
+        "SELECT id, number, client_id, date, status, currency, exchange_rate,
+                COALESCE(total, 0) as total, COALESCE(tax_amount, 0) as tax_amount
+         FROM invoices
+         WHERE date >= $1 AND ($2::DATE IS NULL OR date <= $2)
+         ORDER BY date, number",
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut lines: HashMap<Uuid, Vec<InvoiceLineSource>> = HashMap::new();
+    for line in sqlx::query_as::<_, InvoiceLineSource>(
+        "SELECT li.invoice_id, COALESCE(li.tax_category, li.source_type) as category,
+                COALESCE(li.line_total, li.total_price, li.quantity * li.unit_price, 0) as amount
+         FROM invoice_line_items li
+         JOIN invoices i ON i.id = li.invoice_id
+         WHERE li.voided_at IS NULL AND i.date >= $1 AND ($2::DATE IS NULL OR i.date <= $2)",
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(&mut *conn)
+    .await?
+    {
+        lines.entry(line.invoice_id).or_default().push(line);
+    }
+
+    let cash = sqlx::query_as::<_, CashSource>(
+        "SELECT p.id, 'payment' as entity_type, i.client_id, i.number as invoice_number, p.payment_date as date,
+                p.payment_method as method, p.amount, p.currency, p.exchange_rate
+         FROM payments p
+         JOIN invoices i ON i.id = p.invoice_id
+         WHERE p.payment_date >= $1 AND ($2::DATE IS NULL OR p.payment_date <= $2)
+         UNION ALL
+         SELECT pt.id, 'refund', pt.client_id, i.number, COALESCE(pt.processed_at, pt.created_at)::date,
+                pt.processor, pt.amount, COALESCE(i.currency, cn.currency, pt.currency, 'USD'),
+                COALESCE(p.exchange_rate, i.exchange_rate)
+         FROM payment_transactions pt
+         LEFT JOIN invoices i ON i.id = pt.invoice_id
+         LEFT JOIN payments p ON p.id = pt.payment_id
+         LEFT JOIN credit_notes cn ON cn.id = pt.credit_note_id
+         WHERE pt.transaction_type = 'refund' AND pt.status = 'completed'
+           AND COALESCE(pt.processed_at, pt.created_at)::date >= $1
+           AND ($2::DATE IS NULL OR COALESCE(pt.processed_at, pt.created_at)::date <= $2)",
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let credit_notes = sqlx::query_as::<_, CreditNoteSource>(
+        "SELECT id, credit_note_number as number, client_id, COALESCE(issue_date, created_at::date) as date,
+                status, currency, amount, reason
+         FROM credit_notes
+         WHERE COALESCE(issue_date, created_at::date) >= $1
+           AND ($2::DATE IS NULL OR COALESCE(issue_date, created_at::date) <= $2)",
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let client_ids: Vec<Uuid> = invoices
+        .iter()
+        .map(|i| i.client_id)
+        .chain(cash.iter().map(|c| c.client_id))
+        .chain(credit_notes.iter().map(|n| n.client_id))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let customers = load_customers(&mut *conn, accounts, &client_ids).await?;
+    let customer_name = |client_id: Uuid| customers.get(&client_id).map(|c| c.name.clone()).unwrap_or_default();
+
+    let base_currency = currency::base_currency(&mut *conn).await?;
+    let mut sources = Vec::new();
+    for invoice in &invoices {
+        let entry = invoice_entry(
+            invoice,
+            lines.get(&invoice.id).map(Vec::as_slice).unwrap_or_default(),
+            &customer_name(invoice.client_id),
+            accounts,
+        );
+        sources.push(SourceRecord {
+            entity_type: "invoice".to_string(),
+            entity_id: invoice.id,
+            client_id: invoice.client_id,
+            entry,
+        });
+    }
+    for cash in &cash {
+        let rate = match cash.exchange_rate {
+            Some(rate) => rate,
+            None => exchange_rate(&mut *conn, &cash.currency, &base_currency, cash.date).await?,
+        };
+        sources.push(SourceRecord {
+            entity_type: cash.entity_type.clone(),
+            entity_id: cash.id,
+            client_id: cash.client_id,
+            entry: cash_entry(cash, rate, &customer_name(cash.client_id), accounts),
+        });
+    }
+    for note in &credit_notes {
+        let rate = exchange_rate(&mut *conn, &note.currency, &base_currency, note.date).await?;
+        sources.push(SourceRecord {
+            entity_type: "credit_note".to_string(),
+            entity_id: note.id,
+            client_id: note.client_id,
+            entry: credit_note_entry(note, rate, &customer_name(note.client_id), accounts),
+        });
+    }
+
+    Ok((sources, customers))
+}
+
+async fn exchange_rate(
+    conn: &mut PgConnection,
+    currency: &str,
+    base_currency: &str,
+    date: NaiveDate,
+) -> AccountingResult<Decimal> {
+    if currency == base_currency {
+        return Ok(Decimal::ONE);
+    }
+    Ok(currency::snapshot(conn, currency, date).await?.rate)
+}
+
+async fn load_customers(
+    conn: &mut PgConnection,
+    accounts: &AccountMap,
+    client_ids: &[Uuid],
+) -> AccountingResult<HashMap<Uuid, Customer>> {
+    let customers = sqlx::query_as::<_, Customer>(
+        "SELECT id as client_id, name, email, currency FROM clients WHERE id = ANY($1)",
+    )
+    .bind(client_ids)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(customers
+        .into_iter()
+        .map(|c| (c.client_id, Customer { name: accounts.customer_name(c.client_id, &c.name), ..c }))
+        .collect())
+}
+
+async fn load_records(conn: &mut PgConnection, target: &str) -> AccountingResult<HashMap<(String, Uuid), SyncRecord>> {
+    let records = sqlx::query_as::<_, SyncRecord>(&format!(
+        "SELECT {} FROM accounting_sync_records WHERE target = $1",
+        SYNC_RECORD_COLUMNS
+    ))
+    .bind(target)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(records.into_iter().map(|r| ((r.entity_type.clone(), r.entity_id), r)).collect())
+}
+
+/// Synced records whose document has since been deleted.
+async fn orphaned_records(conn: &mut PgConnection, target: &str) -> AccountingResult<Vec<SyncRecord>> {
+    Ok(sqlx::query_as::<_, SyncRecord>(&format!(
+        "SELECT {} FROM accounting_sync_records r
+         WHERE r.target = $1 AND r.status <> 'removed'
+           AND CASE r.entity_type
+                   WHEN 'invoice' THEN NOT EXISTS (SELECT 1 FROM invoices WHERE id = r.entity_id)
+                   WHEN 'payment' THEN NOT EXISTS (SELECT 1 FROM payments WHERE id = r.entity_id)
+                   WHEN 'refund' THEN NOT EXISTS (SELECT 1 FROM payment_transactions WHERE id = r.entity_id)
+                   WHEN 'credit_note' THEN NOT EXISTS (SELECT 1 FROM credit_notes WHERE id = r.entity_id)
+                   ELSE false
+               END",
+        SYNC_RECORD_COLUMNS
+    ))
+    .bind(target)
+    .fetch_all(conn)
+    .await?)
+}
+
+pub fn content_hash<T: Serialize>(value: &T) -> String {
+    let json = serde_json::to_vec(value).unwrap_or_default();
+    hex::encode(ring::digest::digest(&ring::digest::SHA256, &json).as_ref())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncAction {
+    Skip,
+    Push,
+    Remove,
+}
+
+/// A record is pushed when it is new, changed since its last successful sync,
+/// or failed last time; removed when it was synced but no longer belongs in the books.
+pub fn decide(hash: Option<&str>, record: Option<&SyncRecord>) -> SyncAction {
+    match (hash, record) {
+        (Some(hash), Some(r)) if r.status == "synced" && r.content_hash.as_deref() == Some(hash) => SyncAction::Skip,
+        (Some(_), _) => SyncAction::Push,
+        (None, Some(r)) if r.status != "removed" => SyncAction::Remove,
+        (None, _) => SyncAction::Skip,
+    }
+}
+
+/// A change to write back to accounting_sync_records.
+#[derive(Debug, Clone)]
+pub struct RecordUpdate {
+    pub entity_type: String,
+    pub entity_id: Uuid,
+    pub external: Option<ExternalRef>,
+    pub content_hash: Option<String>,
+    pub payload: Option<serde_json::Value>,
+    pub status: &'static str,
+    pub error: Option<String>,
+}
+
+impl RecordUpdate {
+    pub fn synced(
+        entity_type: &str,
+        entity_id: Uuid,
+        external: Option<ExternalRef>,
+        content_hash: String,
+        payload: Option<serde_json::Value>,
+    ) -> Self {
+        Self {
+            entity_type: entity_type.to_string(),
+            entity_id,
+            external,
+            content_hash: Some(content_hash),
+            payload,
+            status: "synced",
+            error: None,
+        }
+    }
+
+    pub fn removed(entity_type: &str, entity_id: Uuid) -> Self {
+        Self {
+            entity_type: entity_type.to_string(),
+            entity_id,
+            external: None,
+            content_hash: None,
+            payload: None,
+            status: "removed",
+            error: None,
+        }
+    }
+
+    pub fn failed(entity_type: &str, entity_id: Uuid, error: String) -> Self {
+        Self {
+            entity_type: entity_type.to_string(),
+            entity_id,
+            external: None,
+            content_hash: None,
+            payload: None,
+            status: "error",
+            error: Some(error),
+        }
+    }
+}
+
+/// Failures only record the error, keeping what was last synced successfully.
+async fn save_record(conn: &mut PgConnection, target: &str, update: &RecordUpdate) -> AccountingResult<()> {
+    sqlx::query(
+        "INSERT INTO accounting_sync_records (target, entity_type, entity_id, external_id, external_version,
+                                              content_hash, last_payload, status, last_error, synced_at, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, CASE WHEN $8 = 'error' THEN NULL ELSE NOW() END, NOW())
+         ON CONFLICT (target, entity_type, entity_id) DO UPDATE SET
+             external_id = CASE WHEN EXCLUDED.status = 'error' THEN accounting_sync_records.external_id
+                                ELSE EXCLUDED.external_id END,
+             external_version = CASE WHEN EXCLUDED.status = 'error' THEN accounting_sync_records.external_version
+                                     ELSE EXCLUDED.external_version END,
+             content_hash = CASE WHEN EXCLUDED.status = 'error' THEN accounting_sync_records.content_hash
+                                 ELSE EXCLUDED.content_hash END,
+             last_payload = CASE WHEN EXCLUDED.status = 'error' THEN accounting_sync_records.last_payload
+                                 ELSE EXCLUDED.last_payload END,
+             status = EXCLUDED.status,
+             last_error = EXCLUDED.last_error,
+             synced_at = COALESCE(EXCLUDED.synced_at, accounting_sync_records.synced_at),
+             updated_at = NOW()",
+    )
+    .bind(target)
+    .bind(&update.entity_type)
+    .bind(update.entity_id)
+    .bind(update.external.as_ref().map(|e| e.id.clone()))
+    .bind(update.external.as_ref().and_then(|e| e.version.clone()))
+    .bind(&update.content_hash)
+    .bind(&update.payload)
+    .bind(update.status)
+    .bind(&update.error)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+pub fn render_export(format: &str, entries: &[JournalEntry]) -> AccountingResult<String> {
+    match format {
+        "quickbooks_iif" => Ok(render_iif(entries)),
+        "quickbooks_csv" => Ok(render_quickbooks_csv(entries)),
+        "xero_csv" => Ok(render_xero_csv(entries)),
+        other => Err(format!("Unknown export format {}", other).into()),
+    }
+}
+
+pub fn export_extension(format: &str) -> &'static str {
+    if format == "quickbooks_iif" { "iif" } else { "csv" }
+}
+
+/// IIF fields are tab separated with no quoting.
+fn iif_field(value: &str) -> String {
+    value.replace(['\t', '\r', '\n'], " ")
+}
+
+/// QuickBooks Desktop import: customers first, then each entry as a general
+/// journal transaction with debits positive and credits negative.
+pub fn render_iif(entries: &[JournalEntry]) -> String {
+    let mut out = String::new();
+
+    let customers: BTreeSet<&str> = entries.iter().map(|e| e.customer.as_str()).collect();
+    if !customers.is_empty() {
+        out.push_str("!CUST\tNAME\n");
+        for customer in customers {
+            out.push_str(&format!("CUST\t{}\n", iif_field(customer)));
+        }
+    }
+
+    out.push_str("!TRNS\tTRNSTYPE\tDATE\tACCNT\tNAME\tAMOUNT\tDOCNUM\tMEMO\n");
+    out.push_str("!SPL\tTRNSTYPE\tDATE\tACCNT\tNAME\tAMOUNT\tDOCNUM\tMEMO\n");
+    out.push_str("!ENDTRNS\n");
+    for entry in entries {
+        for (i, line) in entry.lines.iter().enumerate() {
+            out.push_str(&format!(
+                "{}\tGENERAL JOURNAL\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                if i == 0 { "TRNS" } else { "SPL" },
+                entry.date.format("%m/%d/%Y"),
+                iif_field(&line.account.name),
+                iif_field(&entry.customer),
+                line.signed_amount(),
+                iif_field(&entry.reference),
+                iif_field(&line.description),
+            ));
+        }
+        out.push_str("ENDTRNS\n");
+    }
+
+    out
+}
+
+/// QuickBooks Online journal entry import.
+pub fn render_quickbooks_csv(entries: &[JournalEntry]) -> String {
+    let amount = |value: Decimal| if value.is_zero() { String::new() } else { value.to_string() };
+
+    let mut out = String::from("Journal No.,Journal Date,Account Name,Debits,Credits,Description,Name\n");
+    for entry in entries {
+        for line in &entry.lines {
+            let cells = [
+                csv_field(&entry.reference),
+                entry.date.format("%m/%d/%Y").to_string(),
+                csv_field(&line.account.name),
+                amount(line.debit),
+                amount(line.credit),
+                csv_field(&line.description),
+                csv_field(&entry.customer),
+            ];
+            out.push_str(&cells.join(","));
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+/// Xero manual journal import. Lines sharing a narration and date form one
+/// journal, so the narration leads with the entry's reference.
+pub fn render_xero_csv(entries: &[JournalEntry]) -> String {
+    let mut out = String::from("*Narration,*Date,Description,*AccountCode,*TaxRate,*Amount\n");
+    for entry in entries {
+        let narration = format!("{}: {}", entry.reference, entry.memo);
+        for line in &entry.lines {
+            let cells = [
+                csv_field(&narration),
+                entry.date.format("%d/%m/%Y").to_string(),
+                csv_field(&line.description),
+                csv_field(line.account.code.as_deref().unwrap_or(&line.account.name)),
+                "Tax Exempt".to_string(),
+                line.signed_amount().to_string(),
+            ];
+            out.push_str(&cells.join(","));
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+/// Writes a journal file in the base currency for documents dated in the
+/// period. With `changes_only`, documents already exported in this format are
+/// left out unless they changed: a changed one is exported as a reversal of
+/// what went out before plus its new entry, and a voided or deleted one as a
+/// reversal. Reversals are dated the day of the export.
+pub async fn export(
+    db_pool: &PgPool,
+    format: &str,
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+    changes_only: bool,
+    created_by: Option<Uuid>,
+) -> AccountingResult<(AccountingExport, String)> {
+    if !EXPORT_FORMATS.contains(&format) {
+        return Err(format!("Unknown export format {}", format).into());
+    }
+
+    let mut conn = db_pool.acquire().await?;
+    let base_currency = currency::base_currency(&mut conn).await?;
+    let accounts = AccountMap::load(&mut conn).await?;
+    let (sources, _) = load_sources(&mut conn, &accounts, period_start, Some(period_end)).await?;
+    let records = load_records(&mut conn, format).await?;
+    drop(conn);
+
+    let today = Utc::now().date_naive();
+    let mut entries = Vec::new();
+    let mut updates = Vec::new();
+    for source in sources {
+        let record = records.get(&(source.entity_type.clone(), source.entity_id));
+        let hash = source.entry.as_ref().map(content_hash);
+        let action = match (changes_only, &source.entry) {
+            (true, _) => decide(hash.as_deref(), record),
+            (false, Some(_)) => SyncAction::Push,
+            (false, None) => SyncAction::Skip,
+        };
+
+        match (action, source.entry, hash) {
+            (SyncAction::Push, Some(entry), Some(hash)) => {
+                if changes_only {
+                    entries.extend(record.and_then(SyncRecord::previous_entry).map(|prev| prev.reversal(today)));
+                }
+                let entry = entry.in_base(&base_currency);
+                let payload = serde_json::to_value(&entry)?;
+                entries.push(entry);
+                updates.push(RecordUpdate::synced(&source.entity_type, source.entity_id, None, hash, Some(payload)));
+            }
+            (SyncAction::Remove, _, _) => {
+                entries.extend(record.and_then(SyncRecord::previous_entry).map(|prev| prev.reversal(today)));
+                updates.push(RecordUpdate::removed(&source.entity_type, source.entity_id));
+            }
+            _ => {}
+        }
+    }
+
+    let mut tx = db_pool.begin().await?;
+    if changes_only {
+        for record in orphaned_records(&mut tx, format).await? {
+            entries.extend(record.previous_entry().map(|prev| prev.reversal(today)));
+            updates.push(RecordUpdate::removed(&record.entity_type, record.entity_id));
+        }
+    }
+
+    let contents = render_export(format, &entries)?;
+    let id = Uuid::new_v4();
+    let dir = format!("{}/accounting", crate::files::get_upload_directory());
+    tokio::fs::create_dir_all(&dir).await?;
+    let file_path = format!("{}/{}.{}", dir, id, export_extension(format));
+    tokio::fs::write(&file_path, &contents).await?;
+
+    let export = sqlx::query_as::<_, AccountingExport>(&format!(
+        "INSERT INTO accounting_exports (id, format, period_start, period_end, changes_only, entry_count,
+                                         file_path, created_by)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+         RETURNING {}",
+        EXPORT_COLUMNS
+    ))
+    .bind(id)
+    .bind(format)
+    .bind(period_start)
+    .bind(period_end)
+    .bind(changes_only)
+    .bind(entries.len() as i32)
+    .bind(&file_path)
+    .bind(created_by)
+    .fetch_one(&mut *tx)
+    .await?;
+    for update in &updates {
+        save_record(&mut tx, format, update).await?;
+    }
+    tx.commit().await?;
+
+    Ok((export, contents))
+}
+
+/// The id and version of a record in the accounting system.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExternalRef {
+    pub id: String,
+    pub version: Option<String>,
+}
+
+/// An accounting system that takes customers and journal entries over an API.
+#[async_trait]
+pub trait AccountingApi: Send + Sync {
+    /// Sync target name the records are tracked under.
+    fn target(&self) -> &str;
+
+    async fn upsert_customer(&self, customer: &Customer, existing: Option<&ExternalRef>) -> AccountingResult<ExternalRef>;
+
+    async fn upsert_journal(
+        &self,
+        entry: &JournalEntry,
+        customer: &ExternalRef,
+        existing: Option<&ExternalRef>,
+    ) -> AccountingResult<ExternalRef>;
+
+    async fn delete_journal(&self, existing: &ExternalRef) -> AccountingResult<()>;
+}
+
+pub async fn push_customer(api: &dyn AccountingApi, customer: &Customer, record: Option<&SyncRecord>) -> RecordUpdate {
+    let existing = record.and_then(SyncRecord::external_ref);
+    match api.upsert_customer(customer, existing.as_ref()).await {
+        Ok(external) => RecordUpdate::synced("client", customer.client_id, Some(external), content_hash(customer), None),
+        Err(e) => RecordUpdate::failed("client", customer.client_id, e.to_string()),
+    }
+}
+
+pub async fn push_entry(
+    api: &dyn AccountingApi,
+    entry: &JournalEntry,
+    customer: &ExternalRef,
+    record: Option<&SyncRecord>,
+) -> RecordUpdate {
+    let existing = record.and_then(SyncRecord::external_ref);
+    match api.upsert_journal(entry, customer, existing.as_ref()).await {
+        Ok(external) => RecordUpdate::synced(
+            &entry.entity_type,
+            entry.entity_id,
+            Some(external),
+            content_hash(entry),
+            serde_json::to_value(entry).ok(),
+        ),
+        Err(e) => RecordUpdate::failed(&entry.entity_type, entry.entity_id, e.to_string()),
+    }
+}
+
+pub async fn remove_entry(api: &dyn AccountingApi, record: &SyncRecord) -> RecordUpdate {
+    if let Some(existing) = record.external_ref() {
+        if let Err(e) = api.delete_journal(&existing).await {
+            return RecordUpdate::failed(&record.entity_type, record.entity_id, e.to_string());
+        }
+    }
+    RecordUpdate::removed(&record.entity_type, record.entity_id)
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncSummary {
+    pub customers: u32,
+    pub pushed: u32,
+    pub removed: u32,
+    pub skipped: u32,
+    pub failed: u32,
+}
+
+impl SyncSummary {
+    fn count(&mut self, update: &RecordUpdate) {
+        match update.status {
+            "error" => self.failed += 1,
+            "removed" => self.removed += 1,
+            _ if update.entity_type == "client" => self.customers += 1,
+            _ => self.pushed += 1,
+        }
+    }
+}
+
+/// Pushes documents dated from `from` onwards that are new or changed since
+/// their last sync, creating or updating customers as needed, and deletes
+/// ones that were voided or deleted. Each record's outcome is saved as it goes.
+pub async fn sync_api(db_pool: &PgPool, api: &dyn AccountingApi, from: NaiveDate) -> AccountingResult<SyncSummary> {
+    let target = api.target().to_string();
+    let mut conn = db_pool.acquire().await?;
+    let accounts = AccountMap::load(&mut conn).await?;
+    let (sources, customers) = load_sources(&mut conn, &accounts, from, None).await?;
+    let records = load_records(&mut conn, &target).await?;
+
+    let mut summary = SyncSummary::default();
+    let mut customer_refs: HashMap<Uuid, ExternalRef> = HashMap::new();
+    for source in sources {
+        let record = records.get(&(source.entity_type.clone(), source.entity_id));
+        let hash = source.entry.as_ref().map(content_hash);
+
+        let update = match (decide(hash.as_deref(), record), source.entry) {
+            (SyncAction::Push, Some(entry)) => {
+                let customer_ref = match customer_refs.get(&source.client_id) {
+                    Some(customer_ref) => customer_ref.clone(),
+                    None => {
+                        let Some(customer) = customers.get(&source.client_id) else {
+                            continue;
+                        };
+                        let customer_record = records.get(&("client".to_string(), source.client_id));
+                        let existing = customer_record.and_then(SyncRecord::external_ref);
+                        let customer_hash = content_hash(customer);
+                        let unchanged = decide(Some(customer_hash.as_str()), customer_record) == SyncAction::Skip;
+                        match existing.filter(|_| unchanged) {
+                            Some(existing) => existing,
+                            None => {
+                                let update = push_customer(api, customer, customer_record).await;
+                                save_record(&mut conn, &target, &update).await?;
+                                summary.count(&update);
+                                let Some(external) = update.external else {
+                                    let error = format!("Customer not synced: {}", update.error.unwrap_or_default());
+                                    let update = RecordUpdate::failed(&source.entity_type, source.entity_id, error);
+                                    save_record(&mut conn, &target, &update).await?;
+                                    summary.count(&update);
+                                    continue;
+                                };
+                                external
+                            }
+                        }
+                    }
+                };
+                customer_refs.insert(source.client_id, customer_ref.clone());
+                push_entry(api, &entry, &customer_ref, record).await
+            }
+            (SyncAction::Remove, _) => match record {
+                Some(record) => remove_entry(api, record).await,
+                None => continue,
+            },
+            _ => {
+                summary.skipped += 1;
+                continue;
+            }
+        };
+
+        if let Some(error) = &update.error {
+            warn!("Accounting sync of {} {} failed: {}", update.entity_type, update.entity_id, error);
+        }
+        save_record(&mut conn, &target, &update).await?;
+        summary.count(&update);
+    }
+
+    for record in orphaned_records(&mut conn, &target).await? {
+        let update = remove_entry(api, &record).await;
+        save_record(&mut conn, &target, &update).await?;
+        summary.count(&update);
+    }
+
+    Ok(summary)
+}
+
+/// Start of the current year, used when the integration has no sync_from date.
+pub fn default_sync_from(today: NaiveDate) -> NaiveDate {
+    NaiveDate::from_ymd_opt(today.year(), 1, 1).unwrap_or(today)
+}
+
+#[derive(Debug, Clone)]
+pub struct AccountingSyncConfig {
+    pub sync_interval_seconds: u64, // How often changes are pushed to the accounting API
+}
+
+impl Default for AccountingSyncConfig {
+    fn default() -> Self {
+        Self { sync_interval_seconds: 15 * 60 }
+    }
+}
+
+#[derive(Clone)]
+pub struct AccountingSyncService {
+    config: AccountingSyncConfig,
+    db_pool: PgPool,
+}
+
+impl AccountingSyncService {
+    pub fn new(config: AccountingSyncConfig, db_pool: PgPool) -> Self {
+        Self { config, db_pool }
+    }
+
+    pub async fn start(&self) -> AccountingResult<()> {
+        info!("Starting accounting sync worker");
+
+        let mut sync_interval = interval(Duration::from_secs(self.config.sync_interval_seconds));
+
+        tokio::spawn({
+            let service = self.clone();
+            async move {
+                loop {
+                    sync_interval.tick().await;
+
+                    if let Err(e) = service.run().await {
+                        error!("Error syncing to accounting: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Syncs to QuickBooks Online when the integration is enabled; None otherwise.
+    pub async fn run(&self) -> AccountingResult<Option<SyncSummary>> {
+        use crate::integrations::quickbooks::QuickBooksClient;
+
+        let Ok(client) = QuickBooksClient::from_integration(&self.db_pool).await else {
+            return Ok(None);
+        };
+        let from = client.sync_from().unwrap_or_else(|| default_sync_from(Utc::now().date_naive()));
+        let summary = sync_api(&self.db_pool, &client, from).await?;
+
+        if summary.pushed + summary.removed + summary.failed > 0 {
+            info!(
+                "Accounting sync: {} pushed, {} removed, {} failed",
+                summary.pushed, summary.removed, summary.failed
+            );
+        }
+
+        Ok(Some(summary))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn mapping(mapping_type: &str, source_key: &str, name: &str, code: &str) -> AccountingMapping {
+        AccountingMapping {
+            id: Uuid::new_v4(),
+            mapping_type: mapping_type.to_string(),
+            source_key: source_key.to_string(),
+            account_name: name.to_string(),
+            account_code: Some(code.to_string()),
+            external_id: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn invoice(total: i64, tax: i64) -> InvoiceSource {
+        InvoiceSource {
+            id: Uuid::new_v4(),
+            number: "INV-1001".to_string(),
+            client_id: Uuid::new_v4(),
+            date: NaiveDate::from_ymd_opt(2024, 3, 5).unwrap(),
+            status: Some("sent".to_string()),
+            currency: "USD".to_string(),
+            exchange_rate: Decimal::ONE,
+            total: Decimal::new(total, 2),
+            tax_amount: Decimal::new(tax, 2),
+        }
+    }
+
+    fn line(invoice_id: Uuid, category: Option<&str>, amount: i64) -> InvoiceLineSource {
+        InvoiceLineSource { invoice_id, category: category.map(String::from), amount: Decimal::new(amount, 2) }
+    }
+
+    fn record(status: &str, hash: Option<&str>, external_id: Option<&str>) -> SyncRecord {
+        SyncRecord {
+            id: Uuid::new_v4(),
+            target: TARGET_QUICKBOOKS_ONLINE.to_string(),
+            entity_type: "invoice".to_string(),
+            entity_id: Uuid::new_v4(),
+            external_id: external_id.map(String::from),
+            external_version: Some("0".to_string()),
+            content_hash: hash.map(String::from),
+            last_payload: None,
+            status: status.to_string(),
+            last_error: None,
+            synced_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_invoice_entry_maps_categories() {
+        let accounts = AccountMap::new(vec![
+            mapping("income_account", "default", "Managed Services", "4000"),
+            mapping("income_account", "hardware", "Hardware Sales", "4100"),
+            mapping("receivable_account", "default", "Trade Debtors", "1100"),
+        ]);
+        let inv = invoice(121000, 11000);
+        let lines = vec![
+            line(inv.id, Some("hardware"), 60000),
+            line(inv.id, Some("recurring"), 45000),
+            line(inv.id, None, 5000),
+        ];
+
+        let entry = invoice_entry(&inv, &lines, "Acme", &accounts).unwrap();
+        assert!(entry.is_balanced());
+        assert_eq!(entry.lines[0].account.name, "Trade Debtors");
+        assert_eq!(entry.lines[0].debit, Decimal::new(121000, 2));
+
+        let credit = |name: &str| entry.lines.iter().find(|l| l.account.name == name).map(|l| l.credit);
+        assert_eq!(credit("Hardware Sales"), Some(Decimal::new(60000, 2)));
+        assert_eq!(credit("Managed Services"), Some(Decimal::new(50000, 2)));
+        assert_eq!(credit("Sales Tax Payable"), Some(Decimal::new(11000, 2)));
+
+        // An invoice-level discount lands on the discounts account
+        let discounted = InvoiceSource { total: Decimal::new(116000, 2), ..inv.clone() };
+        let entry = invoice_entry(&discounted, &lines, "Acme", &accounts).unwrap();
+        assert!(entry.is_balanced());
+        let discount = entry.lines.iter().find(|l| l.account.name == "Discounts and Refunds").unwrap();
+        assert_eq!(discount.debit, Decimal::new(5000, 2));
+
+        let void = InvoiceSource { status: Some("void".to_string()), ..inv.clone() };
+        assert!(invoice_entry(&void, &lines, "Acme", &accounts).is_none());
+        let draft = InvoiceSource { status: Some("draft".to_string()), ..inv };
+        assert!(invoice_entry(&draft, &lines, "Acme", &accounts).is_none());
+    }
+
+    #[test]
+    fn test_cash_and_credit_entries() {
+        let accounts = AccountMap::new(vec![mapping("deposit_account", "stripe", "Stripe Clearing", "1210")]);
+        let payment = CashSource {
+            id: Uuid::new_v4(),
+            entity_type: "payment".to_string(),
+            client_id: Uuid::new_v4(),
+            invoice_number: Some("INV-1001".to_string()),
+            date: NaiveDate::from_ymd_opt(2024, 3, 20).unwrap(),
+            method: Some("stripe".to_string()),
+            amount: Decimal::new(50000, 2),
+            currency: "USD".to_string(),
+            exchange_rate: None,
+        };
+
+        let entry = cash_entry(&payment, Decimal::ONE, "Acme", &accounts).unwrap();
+        assert!(entry.is_balanced());
+        assert!(entry.reference.starts_with("PMT-"));
+        assert_eq!(entry.lines[0].account.name, "Stripe Clearing");
+        assert_eq!(entry.lines[0].debit, Decimal::new(50000, 2));
+        assert_eq!(entry.lines[1].account.name, "Accounts Receivable");
+
+        let refund = CashSource { entity_type: "refund".to_string(), method: Some("check".to_string()), ..payment };
+        let entry = cash_entry(&refund, Decimal::ONE, "Acme", &accounts).unwrap();
+        assert_eq!(entry.lines[0].account.name, "Undeposited Funds");
+        assert_eq!(entry.lines[0].credit, Decimal::new(50000, 2));
+        assert_eq!(entry.lines[1].debit, Decimal::new(50000, 2));
+
+        let note = CreditNoteSource {
+            id: Uuid::new_v4(),
+            number: "CN-0001".to_string(),
+            client_id: Uuid::new_v4(),
+            date: NaiveDate::from_ymd_opt(2024, 3, 21).unwrap(),
+            status: Some("issued".to_string()),
+            currency: "USD".to_string(),
+            amount: Decimal::new(2500, 2),
+            reason: "Outage credit".to_string(),
+        };
+        let entry = credit_note_entry(&note, Decimal::ONE, "Acme", &accounts).unwrap();
+        assert!(entry.is_balanced());
+        assert_eq!(entry.lines[0].account.name, "Discounts and Refunds");
+        let void = CreditNoteSource { status: Some("void".to_string()), ..note };
+        assert!(credit_note_entry(&void, Decimal::ONE, "Acme", &accounts).is_none());
+    }
+
+    #[test]
+    fn test_base_conversion_and_reversal() {
+        let accounts = AccountMap::new(vec![
+            mapping("income_account", "a", "Income A", "4001"),
+            mapping("income_account", "b", "Income B", "4002"),
+            mapping("income_account", "c", "Income C", "4003"),
+        ]);
+        let mut inv = invoice(10001, 0);
+        inv.currency = "EUR".to_string();
+        inv.exchange_rate = Decimal::new(10833, 4);
+        let lines = vec![line(inv.id, Some("a"), 3333), line(inv.id, Some("b"), 3334), line(inv.id, Some("c"), 3334)];
+
+        let entry = invoice_entry(&inv, &lines, "Acme", &accounts).unwrap();
+        let base = entry.in_base("USD");
+        assert_eq!(base.currency, "USD");
+        assert_eq!(base.exchange_rate, Decimal::ONE);
+        assert!(base.is_balanced());
+        // 36.11 + 36.12 + 36.12 converted; the extra cent comes off the largest income line
+        assert_eq!(base.lines[0].debit, Decimal::new(10834, 2));
+        let credits: Vec<Decimal> = base.lines[1..].iter().map(|l| l.credit).collect();
+        assert_eq!(credits, vec![Decimal::new(3611, 2), Decimal::new(3612, 2), Decimal::new(3611, 2)]);
+
+        let date = NaiveDate::from_ymd_opt(2024, 4, 1).unwrap();
+        let reversal = base.reversal(date);
+        assert_eq!(reversal.reference, "INV-1001-REV");
+        assert_eq!(reversal.date, date);
+        assert_eq!(reversal.lines[0].credit, base.lines[0].debit);
+        assert!(reversal.is_balanced());
+    }
+
+    #[test]
+    fn test_decide() {
+        assert_eq!(decide(Some("abc"), None), SyncAction::Push);
+        assert_eq!(decide(Some("abc"), Some(&record("synced", Some("abc"), None))), SyncAction::Skip);
+        assert_eq!(decide(Some("def"), Some(&record("synced", Some("abc"), None))), SyncAction::Push);
+        assert_eq!(decide(Some("abc"), Some(&record("error", Some("abc"), None))), SyncAction::Push);
+        assert_eq!(decide(Some("abc"), Some(&record("removed", None, None))), SyncAction::Push);
+        assert_eq!(decide(None, Some(&record("synced", Some("abc"), None))), SyncAction::Remove);
+        assert_eq!(decide(None, Some(&record("removed", None, None))), SyncAction::Skip);
+        assert_eq!(decide(None, None), SyncAction::Skip);
+    }
+
+    #[test]
+    fn test_export_formats() {
+        let accounts = AccountMap::new(vec![mapping("income_account", "default", "Services", "4000")]);
+        let entry = invoice_entry(&invoice(11000, 1000), &[], "Acme, Inc.", &accounts).unwrap();
+
+        let iif = render_iif(std::slice::from_ref(&entry));
+        let lines: Vec<&str> = iif.lines().collect();
+        assert_eq!(lines[0], "!CUST\tNAME");
+        assert_eq!(lines[1], "CUST\tAcme, Inc.");
+        assert_eq!(
+            lines[5],
+            "TRNS\tGENERAL JOURNAL\t03/05/2024\tAccounts Receivable\tAcme, Inc.\t110.00\tINV-1001\tInvoice INV-1001"
+        );
+        assert_eq!(lines[6], "SPL\tGENERAL JOURNAL\t03/05/2024\tServices\tAcme, Inc.\t-100.00\tINV-1001\tInvoice INV-1001");
+        assert_eq!(lines[8], "ENDTRNS");
+
+        let csv = render_quickbooks_csv(std::slice::from_ref(&entry));
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows[0], "Journal No.,Journal Date,Account Name,Debits,Credits,Description,Name");
+        assert_eq!(rows[1], "INV-1001,03/05/2024,Accounts Receivable,110.00,,Invoice INV-1001,\"Acme, Inc.\"");
+        assert_eq!(rows[3], "INV-1001,03/05/2024,Sales Tax Payable,,10.00,Invoice INV-1001,\"Acme, Inc.\"");
+
+        let xero = render_xero_csv(std::slice::from_ref(&entry));
+        let rows: Vec<&str> = xero.lines().collect();
+        assert_eq!(rows[0], "*Narration,*Date,Description,*AccountCode,*TaxRate,*Amount");
+        assert_eq!(rows[2], "INV-1001: Invoice INV-1001,05/03/2024,Invoice INV-1001,4000,Tax Exempt,-100.00");
+        // Accounts without a code fall back to their name
+        assert_eq!(rows[1], "INV-1001: Invoice INV-1001,05/03/2024,Invoice INV-1001,Accounts Receivable,Tax Exempt,110.00");
+    }
+
+    #[derive(Default)]
+    struct MockAccountingApi {
+        calls: Mutex<Vec<String>>,
+        fail_journals: bool,
+    }
+
+    #[async_trait]
+    impl AccountingApi for MockAccountingApi {
+        fn target(&self) -> &str {
+            "mock"
+        }
+
+        async fn upsert_customer(&self, customer: &Customer, existing: Option<&ExternalRef>) -> AccountingResult<ExternalRef> {
+            self.calls.lock().unwrap().push(format!("customer {} {:?}", customer.name, existing.map(|e| &e.id)));
+            Ok(ExternalRef { id: "C1".to_string(), version: Some("0".to_string()) })
+        }
+
+        async fn upsert_journal(
+            &self,
+            entry: &JournalEntry,
+            customer: &ExternalRef,
+            existing: Option<&ExternalRef>,
+        ) -> AccountingResult<ExternalRef> {
+            if self.fail_journals {
+                return Err("Account is inactive".into());
+            }
+            self.calls.lock().unwrap().push(format!("journal {} {} {:?}", entry.reference, customer.id, existing.map(|e| &e.id)));
+            let version = existing.and_then(|e| e.version.as_deref()).map_or(0, |v| v.parse::<u32>().unwrap() + 1);
+            Ok(ExternalRef { id: "J1".to_string(), version: Some(version.to_string()) })
+        }
+
+        async fn delete_journal(&self, existing: &ExternalRef) -> AccountingResult<()> {
+            self.calls.lock().unwrap().push(format!("delete {}", existing.id));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_push_and_remove_through_api() {
+        let api = MockAccountingApi::default();
+        let customer = Customer {
+            client_id: Uuid::new_v4(),
+            name: "Acme".to_string(),
+            email: None,
+            currency: "USD".to_string(),
+        };
+        let update = push_customer(&api, &customer, None).await;
+        assert_eq!(update.status, "synced");
+        let customer_ref = update.external.unwrap();
+
+        let entry = invoice_entry(&invoice(11000, 1000), &[], "Acme", &AccountMap::default()).unwrap();
+        let update = push_entry(&api, &entry, &customer_ref, None).await;
+        assert_eq!(update.status, "synced");
+        assert_eq!(update.content_hash.as_deref(), Some(content_hash(&entry).as_str()));
+        assert_eq!(update.external, Some(ExternalRef { id: "J1".to_string(), version: Some("0".to_string()) }));
+
+        // An edit updates the existing journal at its current version
+        let synced = record("synced", Some("old"), Some("J1"));
+        let update = push_entry(&api, &entry, &customer_ref, Some(&synced)).await;
+        assert_eq!(update.external.unwrap().version.as_deref(), Some("1"));
+
+        let update = remove_entry(&api, &synced).await;
+        assert_eq!(update.status, "removed");
+        assert!(update.external.is_none());
+
+        assert_eq!(
+            *api.calls.lock().unwrap(),
+            vec![
+                "customer Acme None".to_string(),
+                "journal INV-1001 C1 None".to_string(),
+                "journal INV-1001 C1 Some(\"J1\")".to_string(),
+                "delete J1".to_string(),
+            ]
+        );
+
+        let failing = MockAccountingApi { fail_journals: true, ..Default::default() };
+        let update = push_entry(&failing, &entry, &customer_ref, None).await;
+        assert_eq!(update.status, "error");
+        assert_eq!(update.error.as_deref(), Some("Account is inactive"));
+    }
+}
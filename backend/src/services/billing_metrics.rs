@@ -0,0 +1,242 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+use crate::services::recurring_billing::RecurringItem;
+
+type MetricResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Live quantities a recurring line item can be billed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BillingMetric {
+    /// Enabled M365 accounts holding at least one license (optionally a given SKU)
+    M365LicensedUsers,
+    /// Consumed units of one M365 SKU, as reported by the tenant
+    M365LicenseSku,
+    /// Active assets, optionally of one asset type
+    AssetCount,
+    /// Latest reported size of each protected backup item
+    BackupStorageGb,
+}
+
+impl BillingMetric {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "m365_licensed_users" => Some(Self::M365LicensedUsers),
+            "m365_license_sku" => Some(Self::M365LicenseSku),
+            "asset_count" => Some(Self::AssetCount),
+            "backup_storage_gb" => Some(Self::BackupStorageGb),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::M365LicensedUsers => "m365_licensed_users",
+            Self::M365LicenseSku => "m365_license_sku",
+            Self::AssetCount => "asset_count",
+            Self::BackupStorageGb => "backup_storage_gb",
+        }
+    }
+
+    pub fn unit_label(&self) -> &'static str {
+        match self {
+            Self::M365LicensedUsers => "licensed users",
+            Self::M365LicenseSku => "licenses",
+            Self::AssetCount => "assets",
+            Self::BackupStorageGb => "GB",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetricFilter {
+    pub sku_part_number: Option<String>,
+    pub sku_id: Option<String>,
+    pub asset_type: Option<String>,
+    pub provider: Option<String>,
+}
+
+/// One record counted towards a metric, kept as billing evidence.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct MetricEvidence {
+    pub id: Option<Uuid>,
+    pub label: String,
+    pub quantity: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricReading {
+    pub item_id: Option<Uuid>,
+    pub metric: BillingMetric,
+    pub filter: serde_json::Value,
+    pub measured_quantity: Decimal,
+    pub billed_quantity: Decimal,
+    pub previous_quantity: Option<Decimal>,
+    pub evidence: Vec<MetricEvidence>,
+}
+
+impl MetricReading {
+    pub fn changed(&self) -> bool {
+        self.previous_quantity.map(|q| q != self.billed_quantity).unwrap_or(false)
+    }
+}
+
+/// Measures `metric` for a client right now.
+///
+/// Returns the total together with the rows behind it so the invoice line can
+/// show exactly which users, assets or backup items were billed.
+pub async fn measure(
+    conn: &mut PgConnection,
+    client_id: Uuid,
+    metric: BillingMetric,
+    filter: &MetricFilter,
+) -> MetricResult<(Decimal, Vec<MetricEvidence>)> {
+    let evidence = match metric {
+        BillingMetric::M365LicensedUsers => {
+            sqlx::query_as::<_, MetricEvidence>(
+                r#"
+                SELECT u.id, u.user_principal_name as label, 1::DECIMAL as quantity
+                FROM m365_users u
+                JOIN m365_tenants t ON t.id = u.tenant_id
+                WHERE t.client_id = $1 AND t.status = 'active'
+                  AND u.account_enabled = true
+                  AND jsonb_array_length(COALESCE(u.assigned_licenses, '[]'::jsonb)) > 0
+                  AND (($2::TEXT IS NULL AND $3::TEXT IS NULL) OR EXISTS (
+                      SELECT 1 FROM jsonb_array_elements(u.assigned_licenses) lic
+                      LEFT JOIN m365_licenses l ON l.tenant_id = u.tenant_id AND l.sku_id = lic->>'skuId'
+                      WHERE lic->>'skuId' = $2 OR l.sku_part_number = $3))
+                ORDER BY u.user_principal_name
+                "#,
+            )
+            .bind(client_id)
+            .bind(filter.sku_id.as_deref())
+            .bind(filter.sku_part_number.as_deref())
+            .fetch_all(&mut *conn)
+            .await?
+        }
+        BillingMetric::M365LicenseSku => {
+            sqlx::query_as::<_, MetricEvidence>(
+                r#"
+                SELECT l.id, t.domain_name || ': ' || l.sku_part_number as label,
+                       COALESCE(l.consumed_units, 0)::DECIMAL as quantity
+                FROM m365_licenses l
+                JOIN m365_tenants t ON t.id = l.tenant_id
+                WHERE t.client_id = $1 AND t.status = 'active'
+                  AND ($2::TEXT IS NULL OR l.sku_part_number = $2)
+                  AND ($3::TEXT IS NULL OR l.sku_id = $3)
+                ORDER BY t.domain_name, l.sku_part_number
+                "#,
+            )
+            .bind(client_id)
+            .bind(filter.sku_part_number.as_deref())
+            .bind(filter.sku_id.as_deref())
+            .fetch_all(&mut *conn)
+            .await?
+        }
+        BillingMetric::AssetCount => {
+            sqlx::query_as::<_, MetricEvidence>(
+                r#"
+                SELECT id, name as label, 1::DECIMAL as quantity
+                FROM assets
+                WHERE client_id = $1 AND archived_at IS NULL AND status = 'active'
+                  AND ($2::TEXT IS NULL OR LOWER(asset_type) = LOWER($2))
+                ORDER BY name
+                "#,
+            )
+            .bind(client_id)
+            .bind(filter.asset_type.as_deref())
+            .fetch_all(&mut *conn)
+            .await?
+        }
+        BillingMetric::BackupStorageGb => {
+            sqlx::query_as::<_, MetricEvidence>(
+                r#"
+                SELECT DISTINCT ON (provider, protected_item)
+                       id, provider || ': ' || protected_item as label, storage_gb as quantity
+                FROM backup_storage_usage
+                WHERE client_id = $1 AND ($2::TEXT IS NULL OR provider = $2)
+                  AND recorded_at > NOW() - INTERVAL '35 days'
+                ORDER BY provider, protected_item, recorded_at DESC
+                "#,
+            )
+            .bind(client_id)
+            .bind(filter.provider.as_deref())
+            .fetch_all(&mut *conn)
+            .await?
+        }
+    };
+
+    let total = evidence.iter().map(|e| e.quantity).sum();
+    Ok((total, evidence))
+}
+
+/// Measures the metric a recurring item is bound to and works out the quantity to bill.
+pub async fn read(
+    conn: &mut PgConnection,
+    client_id: Uuid,
+    metric: BillingMetric,
+    item: &RecurringItem,
+) -> MetricResult<MetricReading> {
+    let filter = parse_filter(item.metric_filter.as_ref());
+    let (measured_quantity, evidence) = measure(conn, client_id, metric, &filter).await?;
+
+    Ok(MetricReading {
+        item_id: Some(item.id),
+        metric,
+        filter: item.metric_filter.clone().unwrap_or_else(|| serde_json::json!({})),
+        measured_quantity,
+        billed_quantity: billable_quantity(measured_quantity, item.min_quantity),
+        previous_quantity: item.last_metric_quantity,
+        evidence,
+    })
+}
+
+/// Quantity to bill for a measured value, honouring a contractual minimum.
+pub fn billable_quantity(measured: Decimal, min_quantity: Option<Decimal>) -> Decimal {
+    let quantity = match min_quantity {
+        Some(min) if measured < min => min,
+        _ => measured,
+    };
+    quantity.round_dp(2)
+}
+
+pub fn parse_filter(value: Option<&serde_json::Value>) -> MetricFilter {
+    value
+        .and_then(|v| serde_json::from_value::<MetricFilter>(v.clone()).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metric_names_round_trip() {
+        for metric in [
+            BillingMetric::M365LicensedUsers,
+            BillingMetric::M365LicenseSku,
+            BillingMetric::AssetCount,
+            BillingMetric::BackupStorageGb,
+        ] {
+            assert_eq!(BillingMetric::parse(metric.as_str()), Some(metric));
+        }
+        assert_eq!(BillingMetric::parse("seats"), None);
+    }
+
+    #[test]
+    fn test_billable_quantity_applies_minimum() {
+        assert_eq!(billable_quantity(Decimal::from(8), Some(Decimal::from(10))), Decimal::from(10));
+        assert_eq!(billable_quantity(Decimal::from(12), Some(Decimal::from(10))), Decimal::from(12));
+        assert_eq!(billable_quantity(Decimal::new(12346, 3), None), Decimal::new(1235, 2));
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        let filter = parse_filter(Some(&serde_json::json!({ "asset_type": "Workstation" })));
+        assert_eq!(filter.asset_type.as_deref(), Some("Workstation"));
+        assert!(parse_filter(None).sku_part_number.is_none());
+    }
+}
@@ -0,0 +1,753 @@
+use crate::services::credit_notes;
+use crate::services::currency;
+use crate::services::expenses;
+use crate::services::recurring_billing::{
+    insert_invoice, notify_invoice_ready, round_money, unbilled_time_lines, DraftLine,
+};
+use chrono::{DateTime, Months, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+pub type ReviewResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+pub const REVIEW_COLUMNS: &str = "r.id, r.client_id, c.name as client_name, r.through_date, r.status, r.invoice_id,
+    r.notes, r.approved_by, r.approved_at, r.created_at, r.updated_at";
+
+pub const ITEM_COLUMNS: &str = "id, review_id, source_type, source_id, work_date, original_description, description,
+    original_quantity, quantity, original_unit_price, unit_price, tax_category, action, action_reason,
+    deferred_until, merged_into, updated_by, created_at, updated_at";
+
+pub const WRITE_OFF_COLUMNS: &str = "w.id, w.client_id, c.name as client_name, w.review_id, w.source_type, w.source_id,
+    w.work_date, w.quantity, w.amount, w.currency, w.base_amount, w.reason, w.written_off_by, w.created_at";
+
+pub const ITEM_ACTIONS: [&str; 3] = ["bill", "write_off", "defer"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct BillingReview {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub through_date: NaiveDate,
+    pub status: String,
+    pub invoice_id: Option<Uuid>,
+    pub notes: Option<String>,
+    pub approved_by: Option<Uuid>,
+    pub approved_at: Option<DateTime<Utc>>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ReviewItem {
+    pub id: Uuid,
+    pub review_id: Uuid,
+    pub source_type: String,
+    pub source_id: Uuid,
+    pub work_date: Option<NaiveDate>,
+    pub original_description: String,
+    pub description: String,
+    pub original_quantity: Decimal,
+    pub quantity: Decimal,
+    pub original_unit_price: Decimal,
+    pub unit_price: Decimal,
+    pub tax_category: Option<String>,
+    pub action: String,
+    pub action_reason: Option<String>,
+    pub deferred_until: Option<NaiveDate>,
+    pub merged_into: Option<Uuid>,
+    pub updated_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct WriteOff {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub review_id: Option<Uuid>,
+    pub source_type: String,
+    pub source_id: Uuid,
+    pub work_date: NaiveDate,
+    pub quantity: Decimal,
+    pub amount: Decimal,
+    pub currency: String,
+    pub base_amount: Decimal,
+    pub reason: Option<String>,
+    pub written_off_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// A review's totals as they stand, for the queue.
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct ReviewTotals {
+    pub items: usize,
+    pub to_bill: Decimal,
+    pub written_off: Decimal,
+    pub deferred: Decimal,
+    pub hours: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApprovedReview {
+    pub review_id: Uuid,
+    pub invoice_id: Option<Uuid>,    // time and expenses
+    pub released_invoices: Vec<Uuid>, // held recurring invoices, now sent
+    pub written_off: Decimal,
+    pub deferred_items: usize,
+    pub skipped_items: usize, // billed elsewhere since they were staged
+}
+
+impl ReviewItem {
+    pub fn total(&self) -> Decimal {
+        round_money(self.quantity * self.unit_price)
+    }
+
+    pub fn original_total(&self) -> Decimal {
+        round_money(self.original_quantity * self.original_unit_price)
+    }
+
+    /// Held recurring lines are already on an invoice with tax worked out;
+    /// only their wording can change.
+    pub fn is_recurring(&self) -> bool {
+        self.source_type == "recurring"
+    }
+
+    /// Value given up: all of it when written off, the reduction when billed for less.
+    pub fn write_off_amount(&self) -> Decimal {
+        match self.action.as_str() {
+            "write_off" => self.original_total(),
+            "bill" => (self.original_total() - self.total()).max(Decimal::ZERO),
+            _ => Decimal::ZERO,
+        }
+    }
+}
+
+pub fn review_totals(items: &[ReviewItem]) -> ReviewTotals {
+    let mut totals = ReviewTotals { items: items.len(), ..Default::default() };
+    for item in items {
+        match item.action.as_str() {
+            "bill" => {
+                totals.to_bill += item.total();
+                if item.source_type == "time_entry" {
+                    totals.hours += item.quantity;
+                }
+            }
+            "defer" => totals.deferred += item.original_total(),
+            _ => {}
+        }
+        totals.written_off += item.write_off_amount();
+    }
+    totals
+}
+
+/// Why `item` can't be merged into `target`, if it can't.
+pub fn merge_error(target: &ReviewItem, item: &ReviewItem) -> Option<&'static str> {
+    if target.id == item.id {
+        return Some("an item can't be merged into itself");
+    }
+    if target.review_id != item.review_id {
+        return Some("items are in different reviews");
+    }
+    if target.is_recurring() || item.is_recurring() {
+        return Some("recurring lines are already invoiced");
+    }
+    if target.action != "bill" || item.action != "bill" {
+        return Some("only billed items can be merged");
+    }
+    if target.merged_into.is_some() {
+        return Some("the target is itself merged into another line");
+    }
+    if target.tax_category != item.tax_category {
+        return Some("items are taxed differently");
+    }
+    None
+}
+
+/// Invoice lines for the billed time and expenses, one per item or merged group.
+/// A group keeps its quantity when every member has the same price and is
+/// billed as a single amount otherwise.
+pub fn approved_lines(items: &[ReviewItem]) -> Vec<DraftLine> {
+    let mut groups: BTreeMap<(Option<NaiveDate>, Uuid), Vec<&ReviewItem>> = BTreeMap::new();
+    let targets: HashMap<Uuid, &ReviewItem> = items.iter().map(|i| (i.id, i)).collect();
+
+    for item in items.iter().filter(|i| i.action == "bill" && !i.is_recurring()) {
+        let target = item
+            .merged_into
+            .and_then(|id| targets.get(&id))
+            .filter(|t| t.action == "bill")
+            .copied()
+            .unwrap_or(item);
+        groups.entry((target.work_date, target.id)).or_default().push(item);
+    }
+
+    groups
+        .into_iter()
+        .map(|((_, target_id), members)| {
+            let target = targets[&target_id];
+            let same_price = members.iter().all(|m| m.unit_price == target.unit_price);
+            let (quantity, unit_price) = if same_price {
+                (members.iter().map(|m| m.quantity).sum(), target.unit_price)
+            } else {
+                (Decimal::ONE, members.iter().map(|m| m.total()).sum())
+            };
+
+            DraftLine {
+                description: target.description.clone(),
+                quantity,
+                unit_price,
+                tax_rate: None,
+                tax_category: target.tax_category.clone(),
+                source_type: target.source_type.clone(),
+                source_id: Some(target.source_id),
+                period_start: members.iter().filter_map(|m| m.work_date).min(),
+                period_end: members.iter().filter_map(|m| m.work_date).max(),
+                metric: None,
+            }
+        })
+        .collect()
+}
+
+/// Deferred work comes back in the first review after the next billing month.
+pub fn default_deferral(through: NaiveDate) -> NaiveDate {
+    through + Months::new(1)
+}
+
+/// Stages unbilled work for every client that has any, into each client's
+/// open review. Returns the reviews that were created or added to.
+pub async fn prepare_reviews(pool: &PgPool, through: NaiveDate, minimum_hours: Decimal) -> ReviewResult<Vec<Uuid>> {
+    let client_ids: Vec<Uuid> = sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(t.client_id, p.client_id) FROM time_entries te
+        LEFT JOIN tickets t ON t.id = te.ticket_id
+        LEFT JOIN projects p ON p.id = te.project_id
+        WHERE te.billable = true AND te.billed = false AND te.invoice_id IS NULL AND te.written_off_at IS NULL
+          AND te.pending_confirmation = false AND te.duration_minutes > 0 AND te.start_time::date <= $1
+          AND COALESCE(t.client_id, p.client_id) IS NOT NULL
+        UNION
+        SELECT client_id FROM expenses
+        WHERE is_billable = true AND invoice_id IS NULL AND written_off_at IS NULL
+          AND status IN ('approved', 'reimbursed') AND expense_date <= $1
+        UNION
+        SELECT client_id FROM invoices WHERE approval_status = 'pending'
+        "#,
+    )
+    .bind(through)
+    .fetch_all(pool)
+    .await?;
+
+    let mut reviews = Vec::new();
+    for client_id in client_ids {
+        match prepare_review(pool, client_id, through, minimum_hours).await {
+            Ok(Some(review_id)) => reviews.push(review_id),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to prepare billing review for client {}: {}", client_id, e),
+        }
+    }
+
+    info!("Prepared {} billing reviews through {}", reviews.len(), through);
+    Ok(reviews)
+}
+
+/// Adds the client's unbilled time, expenses and held recurring invoice lines
+/// that aren't already under review. Clients with nothing but a little time
+/// are left until they reach `minimum_hours`.
+pub async fn prepare_review(
+    pool: &PgPool,
+    client_id: Uuid,
+    through: NaiveDate,
+    minimum_hours: Decimal,
+) -> ReviewResult<Option<Uuid>> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext('billing_review:' || $1::text))")
+        .bind(client_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let open: Option<Uuid> =
+        sqlx::query_scalar("SELECT id FROM billing_reviews WHERE client_id = $1 AND status = 'pending'")
+            .bind(client_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+    let staged: HashSet<(String, Uuid)> = match open {
+        Some(review_id) => sqlx::query_as::<_, (String, Uuid)>(
+            "SELECT source_type, source_id FROM billing_review_items WHERE review_id = $1",
+        )
+        .bind(review_id)
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .collect(),
+        None => HashSet::new(),
+    };
+
+    let mut lines: Vec<DraftLine> = Vec::new();
+    lines.extend(unbilled_time_lines(&mut tx, client_id, through).await?.into_iter().map(|(line, _)| line));
+    lines.extend(expenses::unbilled_lines(&mut tx, client_id, through).await?.into_iter().map(|(line, _)| line));
+    lines.extend(held_invoice_lines(&mut tx, client_id).await?);
+    lines.retain(|l| l.source_id.is_some_and(|id| !staged.contains(&(l.source_type.clone(), id))));
+
+    if lines.is_empty() {
+        return Ok(open);
+    }
+
+    let only_time = lines.iter().all(|l| l.source_type == "time_entry");
+    let hours: Decimal = lines.iter().filter(|l| l.source_type == "time_entry").map(|l| l.quantity).sum();
+    if open.is_none() && only_time && hours < minimum_hours {
+        return Ok(None);
+    }
+
+    let review_id = match open {
+        Some(review_id) => {
+            sqlx::query("UPDATE billing_reviews SET through_date = GREATEST(through_date, $2), updated_at = NOW() WHERE id = $1")
+                .bind(review_id)
+                .bind(through)
+                .execute(&mut *tx)
+                .await?;
+            review_id
+        }
+        None => {
+            sqlx::query_scalar("INSERT INTO billing_reviews (client_id, through_date) VALUES ($1, $2) RETURNING id")
+                .bind(client_id)
+                .bind(through)
+                .fetch_one(&mut *tx)
+                .await?
+        }
+    };
+
+    for line in &lines {
+        let source_type = if line.source_type == "time_entry" || line.source_type == "expense" {
+            line.source_type.as_str()
+        } else {
+            "recurring"
+        };
+        sqlx::query(
+            r#"
+            INSERT INTO billing_review_items (review_id, source_type, source_id, work_date, original_description,
+                                              description, original_quantity, quantity, original_unit_price,
+                                              unit_price, tax_category)
+            VALUES ($1, $2, $3, $4, $5, $5, $6, $6, $7, $7, $8)
+            "#,
+        )
+        .bind(review_id)
+        .bind(source_type)
+        .bind(line.source_id)
+        .bind(line.period_start)
+        .bind(&line.description)
+        .bind(line.quantity)
+        .bind(line.unit_price)
+        .bind(&line.tax_category)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(Some(review_id))
+}
+
+/// Lines of recurring invoices held for review, keyed by the line itself.
+async fn held_invoice_lines(tx: &mut Transaction<'_, Postgres>, client_id: Uuid) -> ReviewResult<Vec<DraftLine>> {
+    let rows = sqlx::query_as::<_, (Uuid, String, Decimal, Decimal, Option<String>, Option<NaiveDate>, NaiveDate)>(
+        r#"
+        SELECT li.id, li.description, COALESCE(li.quantity, 1), COALESCE(li.unit_price, 0), li.tax_category,
+               li.period_start, i.date
+        FROM invoice_line_items li
+        JOIN invoices i ON i.id = li.invoice_id
+        WHERE i.client_id = $1 AND i.approval_status = 'pending' AND li.voided_at IS NULL
+        ORDER BY i.date, li.created_at
+        "#,
+    )
+    .bind(client_id)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(id, description, quantity, unit_price, tax_category, period_start, invoice_date)| DraftLine {
+            description,
+            quantity,
+            unit_price,
+            tax_rate: None,
+            tax_category,
+            source_type: "recurring".to_string(),
+            source_id: Some(id),
+            period_start: Some(period_start.unwrap_or(invoice_date)),
+            period_end: None,
+            metric: None,
+        })
+        .collect())
+}
+
+pub async fn load_items(pool: &PgPool, review_id: Uuid) -> ReviewResult<Vec<ReviewItem>> {
+    Ok(sqlx::query_as::<_, ReviewItem>(&format!(
+        "SELECT {} FROM billing_review_items WHERE review_id = $1 ORDER BY work_date NULLS LAST, created_at",
+        ITEM_COLUMNS
+    ))
+    .bind(review_id)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Invoices the billed time and expenses, records write-offs, defers the
+/// rest, and sends held recurring invoices with any rewording applied.
+/// Work billed elsewhere since it was staged is skipped.
+pub async fn approve_review(
+    pool: &PgPool,
+    review_id: Uuid,
+    approved_by: Uuid,
+    payment_terms_days: i32,
+) -> ReviewResult<ApprovedReview> {
+    let today = Utc::now().date_naive();
+    let mut tx = pool.begin().await?;
+
+    let review: Option<(Uuid, String, NaiveDate)> =
+        sqlx::query_as("SELECT client_id, status, through_date FROM billing_reviews WHERE id = $1 FOR UPDATE")
+            .bind(review_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    let Some((client_id, status, through)) = review else {
+        return Err(format!("Billing review {} not found", review_id).into());
+    };
+    if status != "pending" {
+        return Err(format!("Billing review {} is already {}", review_id, status).into());
+    }
+
+    let mut items = sqlx::query_as::<_, ReviewItem>(&format!(
+        "SELECT {} FROM billing_review_items WHERE review_id = $1 ORDER BY work_date NULLS LAST, created_at",
+        ITEM_COLUMNS
+    ))
+    .bind(review_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    // Sources that were billed by another route while the review was open
+    let time_ids: Vec<Uuid> = items.iter().filter(|i| i.source_type == "time_entry").map(|i| i.source_id).collect();
+    let open_time: HashSet<Uuid> = sqlx::query_scalar(
+        "SELECT id FROM time_entries WHERE id = ANY($1) AND billed = false AND invoice_id IS NULL
+         AND written_off_at IS NULL AND pending_confirmation = false FOR UPDATE",
+    )
+    .bind(&time_ids)
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .collect();
+    let expense_ids: Vec<Uuid> = items.iter().filter(|i| i.source_type == "expense").map(|i| i.source_id).collect();
+    let open_expenses: HashSet<Uuid> = sqlx::query_scalar(
+        "SELECT id FROM expenses WHERE id = ANY($1) AND invoice_id IS NULL AND written_off_at IS NULL FOR UPDATE",
+    )
+    .bind(&expense_ids)
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .collect();
+
+    let before = items.len();
+    items.retain(|i| match i.source_type.as_str() {
+        "time_entry" => open_time.contains(&i.source_id),
+        "expense" => open_expenses.contains(&i.source_id),
+        _ => true,
+    });
+    let skipped_items = before - items.len();
+
+    let client_currency = currency::client_currency(&mut *tx, client_id).await?;
+    let lines = approved_lines(&items);
+    let invoice = if lines.iter().any(|l| !l.line_total().is_zero()) {
+        let invoice =
+            insert_invoice(&mut tx, client_id, None, today, payment_terms_days, &lines, true, "Approved in billing review")
+                .await?;
+        credit_notes::apply_available_credits(&mut tx, invoice.id, Some(approved_by)).await?;
+        sqlx::query("UPDATE invoices SET approval_status = 'approved', approved_by = $2, approved_at = NOW() WHERE id = $1")
+            .bind(invoice.id)
+            .bind(approved_by)
+            .execute(&mut *tx)
+            .await?;
+        Some(invoice)
+    } else {
+        None
+    };
+    let invoice_id = invoice.map(|i| i.id);
+
+    // A time entry split into prepaid and overage lines is billed if either line is
+    let billed: HashSet<Uuid> = items.iter().filter(|i| i.action == "bill").map(|i| i.source_id).collect();
+    if let Some(invoice_id) = invoice_id {
+        for item in items.iter().filter(|i| i.action == "bill") {
+            match item.source_type.as_str() {
+                "time_entry" => {
+                    sqlx::query("UPDATE time_entries SET billed = true, invoice_id = $2 WHERE id = $1")
+                        .bind(item.source_id)
+                        .bind(invoice_id)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                "expense" => {
+                    sqlx::query("UPDATE expenses SET invoice_id = $2, billed_amount = $3, updated_at = NOW() WHERE id = $1")
+                        .bind(item.source_id)
+                        .bind(invoice_id)
+                        .bind(item.total())
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let rate = currency::snapshot(&mut *tx, &client_currency, today).await?;
+    let mut written_off = Decimal::ZERO;
+    let mut deferred_items = 0;
+    for item in &items {
+        let amount = item.write_off_amount();
+        if amount > Decimal::ZERO {
+            sqlx::query(
+                r#"
+                INSERT INTO billing_write_offs (client_id, review_id, source_type, source_id, work_date, quantity,
+                                                amount, currency, base_amount, reason, written_off_by)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                "#,
+            )
+            .bind(client_id)
+            .bind(review_id)
+            .bind(&item.source_type)
+            .bind(item.source_id)
+            .bind(item.work_date.unwrap_or(today))
+            .bind(if item.action == "write_off" { item.original_quantity } else { item.original_quantity - item.quantity })
+            .bind(amount)
+            .bind(&client_currency)
+            .bind(rate.to_base(amount))
+            .bind(item.action_reason.as_deref().unwrap_or(if item.action == "bill" {
+                "Adjusted in billing review"
+            } else {
+                "Written off in billing review"
+            }))
+            .bind(approved_by)
+            .execute(&mut *tx)
+            .await?;
+            written_off += amount;
+        }
+
+        if billed.contains(&item.source_id) || item.is_recurring() {
+            continue;
+        }
+        let table = if item.source_type == "time_entry" { "time_entries" } else { "expenses" };
+        match item.action.as_str() {
+            "write_off" => {
+                sqlx::query(&format!("UPDATE {} SET written_off_at = NOW() WHERE id = $1", table))
+                    .bind(item.source_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            "defer" => {
+                sqlx::query(&format!("UPDATE {} SET deferred_until = $2 WHERE id = $1", table))
+                    .bind(item.source_id)
+                    .bind(item.deferred_until.unwrap_or_else(|| default_deferral(through)))
+                    .execute(&mut *tx)
+                    .await?;
+                deferred_items += 1;
+            }
+            _ => {}
+        }
+    }
+
+    // Held recurring invoices go out as they are, with reviewed wording
+    for item in items.iter().filter(|i| i.is_recurring() && i.description != i.original_description) {
+        sqlx::query("UPDATE invoice_line_items SET description = $2 WHERE id = $1")
+            .bind(item.source_id)
+            .bind(&item.description)
+            .execute(&mut *tx)
+            .await?;
+    }
+    let line_ids: Vec<Uuid> = items.iter().filter(|i| i.is_recurring()).map(|i| i.source_id).collect();
+    let released: Vec<(Uuid, Decimal)> = sqlx::query_as(
+        r#"
+        UPDATE invoices SET status = 'sent', approval_status = 'approved', approved_by = $2, approved_at = NOW(),
+                            updated_at = NOW()
+        WHERE approval_status = 'pending'
+          AND id IN (SELECT invoice_id FROM invoice_line_items WHERE id = ANY($1))
+        RETURNING id, COALESCE(balance, total)
+        "#,
+    )
+    .bind(&line_ids)
+    .bind(approved_by)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    // Auto-charge was held back with the invoice
+    let payment_method_id: Option<Uuid> = sqlx::query_scalar(
+        "SELECT payment_method_id FROM recurring_billing
+         WHERE client_id = $1 AND status = 'active' AND auto_charge = true AND payment_method_id IS NOT NULL
+         ORDER BY name LIMIT 1",
+    )
+    .bind(client_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .flatten();
+    if let Some(payment_method_id) = payment_method_id {
+        for (released_id, amount_due) in released.iter().filter(|(_, due)| *due > Decimal::ZERO) {
+            sqlx::query(
+                "INSERT INTO payment_transactions (client_id, invoice_id, payment_method_id, transaction_type, amount,
+                                                   currency, status)
+                 VALUES ($1, $2, $3, 'payment', $4, $5, 'pending')",
+            )
+            .bind(client_id)
+            .bind(released_id)
+            .bind(payment_method_id)
+            .bind(amount_due)
+            .bind(&client_currency)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    sqlx::query(
+        "UPDATE billing_reviews SET status = 'approved', invoice_id = $2, approved_by = $3, approved_at = NOW(),
+                updated_at = NOW()
+         WHERE id = $1",
+    )
+    .bind(review_id)
+    .bind(invoice_id)
+    .bind(approved_by)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    if let Some(invoice) = invoice {
+        notify_invoice_ready(pool, client_id, invoice.id, &currency::format_money(invoice.total, &client_currency)).await;
+    }
+    for (released_id, amount_due) in &released {
+        notify_invoice_ready(pool, client_id, *released_id, &currency::format_money(*amount_due, &client_currency)).await;
+    }
+
+    info!("Approved billing review {} for client {}", review_id, client_id);
+
+    Ok(ApprovedReview {
+        review_id,
+        invoice_id,
+        released_invoices: released.into_iter().map(|(id, _)| id).collect(),
+        written_off,
+        deferred_items,
+        skipped_items,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(description: &str, quantity: i64, unit_price: i64) -> ReviewItem {
+        ReviewItem {
+            id: Uuid::new_v4(),
+            review_id: Uuid::nil(),
+            source_type: "time_entry".to_string(),
+            source_id: Uuid::new_v4(),
+            work_date: NaiveDate::from_ymd_opt(2024, 3, 4),
+            original_description: description.to_string(),
+            description: description.to_string(),
+            original_quantity: Decimal::from(quantity),
+            quantity: Decimal::from(quantity),
+            original_unit_price: Decimal::from(unit_price),
+            unit_price: Decimal::from(unit_price),
+            tax_category: Some("labor".to_string()),
+            action: "bill".to_string(),
+            action_reason: None,
+            deferred_until: None,
+            merged_into: None,
+            updated_by: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_write_off_amounts() {
+        let billed = item("Printer", 2, 150);
+        assert_eq!(billed.write_off_amount(), Decimal::ZERO);
+
+        let reduced = ReviewItem { quantity: Decimal::new(15, 1), ..billed.clone() };
+        assert_eq!(reduced.write_off_amount(), Decimal::from(75));
+
+        let written_off = ReviewItem { action: "write_off".to_string(), quantity: Decimal::ONE, ..billed.clone() };
+        assert_eq!(written_off.write_off_amount(), Decimal::from(300));
+
+        let deferred = ReviewItem { action: "defer".to_string(), ..billed.clone() };
+        assert_eq!(deferred.write_off_amount(), Decimal::ZERO);
+
+        // Billing for more than was logged isn't negative write-off
+        let raised = ReviewItem { unit_price: Decimal::from(175), ..billed };
+        assert_eq!(raised.write_off_amount(), Decimal::ZERO);
+
+        let totals = review_totals(&[reduced, written_off, deferred]);
+        assert_eq!(totals.to_bill, Decimal::from(225));
+        assert_eq!(totals.written_off, Decimal::from(375));
+        assert_eq!(totals.deferred, Decimal::from(300));
+        assert_eq!(totals.hours, Decimal::new(15, 1));
+    }
+
+    #[test]
+    fn test_approved_lines_merge_groups() {
+        let target = ReviewItem { description: "Printer support".to_string(), ..item("fixed his dumb printer", 1, 150) };
+        let same_rate = ReviewItem {
+            merged_into: Some(target.id),
+            work_date: NaiveDate::from_ymd_opt(2024, 3, 6),
+            ..item("printer again", 2, 150)
+        };
+        let other = ReviewItem { work_date: NaiveDate::from_ymd_opt(2024, 3, 1), ..item("Server patching", 3, 150) };
+        let written_off = ReviewItem { action: "write_off".to_string(), ..item("learning the firewall", 4, 150) };
+        let recurring = ReviewItem { source_type: "recurring".to_string(), ..item("Managed services", 1, 900) };
+
+        let lines = approved_lines(&[target.clone(), same_rate.clone(), other, written_off, recurring]);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].description, "Server patching");
+        assert_eq!(lines[1].description, "Printer support");
+        assert_eq!(lines[1].quantity, Decimal::from(3));
+        assert_eq!(lines[1].unit_price, Decimal::from(150));
+        assert_eq!(lines[1].source_id, Some(target.source_id));
+        assert_eq!(lines[1].period_start, NaiveDate::from_ymd_opt(2024, 3, 4));
+        assert_eq!(lines[1].period_end, NaiveDate::from_ymd_opt(2024, 3, 6));
+
+        // Different rates collapse to one amount
+        let after_hours = ReviewItem { merged_into: Some(target.id), ..item("after hours", 1, 225) };
+        let lines = approved_lines(&[target.clone(), same_rate, after_hours]);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].quantity, Decimal::ONE);
+        assert_eq!(lines[0].unit_price, Decimal::from(675));
+
+        // Members of a target that was written off stand on their own
+        let dropped = ReviewItem { action: "write_off".to_string(), ..target.clone() };
+        let member = ReviewItem { merged_into: Some(target.id), ..item("printer", 1, 150) };
+        let lines = approved_lines(&[dropped, member]);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].description, "printer");
+    }
+
+    #[test]
+    fn test_merge_error() {
+        let target = item("a", 1, 150);
+        let other = item("b", 1, 150);
+        assert_eq!(merge_error(&target, &other), None);
+        assert!(merge_error(&target, &target).is_some());
+
+        let expense = ReviewItem { tax_category: None, ..item("c", 1, 40) };
+        assert!(merge_error(&target, &expense).is_some());
+
+        let recurring = ReviewItem { source_type: "recurring".to_string(), ..item("d", 1, 900) };
+        assert!(merge_error(&target, &recurring).is_some());
+
+        let nested = ReviewItem { merged_into: Some(Uuid::new_v4()), ..item("e", 1, 150) };
+        assert!(merge_error(&nested, &other).is_some());
+    }
+
+    #[test]
+    fn test_default_deferral() {
+        assert_eq!(
+            default_deferral(NaiveDate::from_ymd_opt(2024, 1, 31).unwrap()),
+            NaiveDate::from_ymd_opt(2024, 2, 29).unwrap()
+        );
+    }
+}
@@ -0,0 +1,438 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, Transaction};
+use uuid::Uuid;
+
+pub type CreditResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+pub const CREDIT_NOTE_COLUMNS: &str = "cn.id, cn.client_id, c.name as client_name, cn.invoice_id,
+    cn.credit_note_number, cn.credit_type, cn.reason, cn.description, cn.amount, cn.currency,
+    COALESCE(cn.applied_amount, 0) as applied_amount, cn.refunded_amount,
+    COALESCE(cn.remaining_amount, cn.amount) as remaining_amount, cn.status, cn.issue_date,
+    cn.void_reason, cn.voided_at, cn.created_by, cn.created_at";
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CreditNote {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub invoice_id: Option<Uuid>,
+    pub credit_note_number: String,
+    pub credit_type: String,
+    pub reason: String,
+    pub description: Option<String>,
+    pub amount: Decimal,
+    pub currency: String,
+    pub applied_amount: Decimal,
+    pub refunded_amount: Decimal,
+    pub remaining_amount: Decimal,
+    pub status: Option<String>,
+    pub issue_date: Option<NaiveDate>,
+    pub void_reason: Option<String>,
+    pub voided_at: Option<DateTime<Utc>>,
+    pub created_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CreditApplication {
+    pub id: Uuid,
+    pub credit_note_id: Uuid,
+    pub credit_note_number: String,
+    pub invoice_id: Uuid,
+    pub invoice_number: String,
+    pub amount: Decimal,
+    pub applied_by: Option<Uuid>,
+    pub applied_at: Option<DateTime<Utc>>,
+    pub reversed_at: Option<DateTime<Utc>>,
+}
+
+pub const CREDIT_APPLICATION_COLUMNS: &str = "a.id, a.credit_note_id, cn.credit_note_number, a.invoice_id,
+    i.number as invoice_number, a.amount, a.applied_by, a.applied_at, a.reversed_at";
+
+/// A refund recorded in payment_transactions, against either a payment or a credit note.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Refund {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub invoice_id: Option<Uuid>,
+    pub payment_id: Option<Uuid>,
+    pub credit_note_id: Option<Uuid>,
+    pub amount: Decimal,
+    pub processor: Option<String>,
+    pub status: String,
+    pub reference_number: Option<String>,
+    pub notes: Option<String>,
+    pub created_by: Option<Uuid>,
+    pub processed_at: Option<DateTime<Utc>>,
+}
+
+pub const REFUND_COLUMNS: &str = "id, client_id, invoice_id, payment_id, credit_note_id, amount, processor,
+    status, reference_number, notes, created_by, processed_at";
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct InvoiceAdjustment {
+    pub id: Uuid,
+    pub invoice_id: Uuid,
+    pub adjustment_type: String,
+    pub amount: Decimal,
+    pub balance_before: Decimal,
+    pub balance_after: Decimal,
+    pub reason: Option<String>,
+    pub credit_note_id: Option<Uuid>,
+    pub payment_transaction_id: Option<Uuid>,
+    pub created_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// The parts of an invoice that balance changes work from, read under a row lock.
+#[derive(Debug, Clone, FromRow)]
+pub struct InvoiceBalance {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub status: Option<String>,
+    pub currency: String,
+    pub total: Decimal,
+    pub balance: Decimal,
+}
+
+impl InvoiceBalance {
+    pub fn is_void(&self) -> bool {
+        self.status.as_deref() == Some("void")
+    }
+}
+
+/// A new balance-changing entry for the invoice audit trail.
+pub struct Adjustment<'a> {
+    pub adjustment_type: &'a str,
+    pub amount: Decimal,
+    pub reason: Option<&'a str>,
+    pub credit_note_id: Option<Uuid>,
+    pub payment_transaction_id: Option<Uuid>,
+    pub created_by: Option<Uuid>,
+}
+
+pub async fn lock_invoice(tx: &mut Transaction<'_, Postgres>, invoice_id: Uuid) -> CreditResult<Option<InvoiceBalance>> {
+    let invoice = sqlx::query_as::<_, InvoiceBalance>(
+        "SELECT id, client_id, status, currency, COALESCE(total, 0) as total, COALESCE(balance, 0) as balance
+         FROM invoices WHERE id = $1 FOR UPDATE",
+    )
+    .bind(invoice_id)
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(invoice)
+}
+
+/// Moves an invoice to `new_balance`, updating its status and recording the change.
+pub async fn adjust_balance(
+    tx: &mut Transaction<'_, Postgres>,
+    invoice: &InvoiceBalance,
+    new_balance: Decimal,
+    adjustment: Adjustment<'_>,
+) -> CreditResult<()> {
+    let status = invoice_status(invoice.status.as_deref().unwrap_or("draft"), invoice.total, new_balance);
+
+    sqlx::query("UPDATE invoices SET balance = $2, status = $3, updated_at = NOW() WHERE id = $1")
+        .bind(invoice.id)
+        .bind(new_balance)
+        .bind(status)
+        .execute(&mut **tx)
+        .await?;
+
+    record_adjustment(tx, invoice.id, invoice.balance, new_balance, adjustment).await
+}
+
+pub async fn record_adjustment(
+    tx: &mut Transaction<'_, Postgres>,
+    invoice_id: Uuid,
+    balance_before: Decimal,
+    balance_after: Decimal,
+    adjustment: Adjustment<'_>,
+) -> CreditResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO invoice_adjustments (invoice_id, adjustment_type, amount, balance_before, balance_after,
+                                         reason, credit_note_id, payment_transaction_id, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        "#,
+    )
+    .bind(invoice_id)
+    .bind(adjustment.adjustment_type)
+    .bind(adjustment.amount)
+    .bind(balance_before)
+    .bind(balance_after)
+    .bind(adjustment.reason)
+    .bind(adjustment.credit_note_id)
+    .bind(adjustment.payment_transaction_id)
+    .bind(adjustment.created_by)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn next_credit_note_number(tx: &mut Transaction<'_, Postgres>) -> CreditResult<String> {
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext('credit_note_number'))")
+        .execute(&mut **tx)
+        .await?;
+
+    let next: i32 = sqlx::query_scalar(
+        "SELECT COALESCE(MAX(CAST(SUBSTRING(credit_note_number FROM '^CN-(\\d+)$') AS INTEGER)), 0) + 1
+         FROM credit_notes WHERE credit_note_number ~ '^CN-\\d+$'",
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(format!("CN-{:06}", next))
+}
+
+/// Credit is in the currency of the invoice it is raised against, or the
+/// client's currency for account credit.
+pub async fn issue_credit_note(
+    tx: &mut Transaction<'_, Postgres>,
+    client_id: Uuid,
+    invoice_id: Option<Uuid>,
+    credit_type: &str,
+    amount: Decimal,
+    reason: &str,
+    description: Option<&str>,
+    created_by: Option<Uuid>,
+) -> CreditResult<Uuid> {
+    let number = next_credit_note_number(tx).await?;
+
+    let id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO credit_notes (client_id, invoice_id, credit_note_number, credit_type, reason, description,
+                                  amount, applied_amount, remaining_amount, status, issue_date, created_by, currency)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, 0, $7, 'issued', CURRENT_DATE, $8,
+                COALESCE((SELECT currency FROM invoices WHERE id = $2), (SELECT currency FROM clients WHERE id = $1)))
+        RETURNING id
+        "#,
+    )
+    .bind(client_id)
+    .bind(invoice_id)
+    .bind(number)
+    .bind(credit_type)
+    .bind(reason)
+    .bind(description)
+    .bind(amount)
+    .bind(created_by)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(id)
+}
+
+/// Applies part of a credit note to an invoice. The caller holds locks on
+/// both rows and has checked the amount against what is left on each.
+pub async fn apply_credit(
+    tx: &mut Transaction<'_, Postgres>,
+    credit_note_id: Uuid,
+    invoice: &InvoiceBalance,
+    amount: Decimal,
+    applied_by: Option<Uuid>,
+) -> CreditResult<()> {
+    sqlx::query("INSERT INTO credit_note_applications (credit_note_id, invoice_id, amount, applied_by) VALUES ($1, $2, $3, $4)")
+        .bind(credit_note_id)
+        .bind(invoice.id)
+        .bind(amount)
+        .bind(applied_by)
+        .execute(&mut **tx)
+        .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE credit_notes SET
+            applied_amount = COALESCE(applied_amount, 0) + $2,
+            remaining_amount = COALESCE(remaining_amount, amount) - $2,
+            status = CASE WHEN COALESCE(remaining_amount, amount) - $2 <= 0 THEN 'fully_applied' ELSE 'partially_applied' END,
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(credit_note_id)
+    .bind(amount)
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query("UPDATE invoices SET credited_amount = credited_amount + $2 WHERE id = $1")
+        .bind(invoice.id)
+        .bind(amount)
+        .execute(&mut **tx)
+        .await?;
+
+    adjust_balance(
+        tx,
+        invoice,
+        invoice.balance - amount,
+        Adjustment {
+            adjustment_type: "credit",
+            amount,
+            reason: None,
+            credit_note_id: Some(credit_note_id),
+            payment_transaction_id: None,
+            created_by: applied_by,
+        },
+    )
+    .await
+}
+
+pub async fn insert_refund(
+    tx: &mut Transaction<'_, Postgres>,
+    client_id: Uuid,
+    invoice_id: Option<Uuid>,
+    payment_id: Option<Uuid>,
+    credit_note_id: Option<Uuid>,
+    amount: Decimal,
+    processor: &str,
+    reference_number: Option<&str>,
+    notes: Option<&str>,
+    created_by: Option<Uuid>,
+) -> CreditResult<Refund> {
+    let refund = sqlx::query_as::<_, Refund>(&format!(
+        r#"
+        INSERT INTO payment_transactions (client_id, invoice_id, payment_id, credit_note_id, transaction_type, amount,
+                                          processor, status, processed_at, reference_number, notes, created_by)
+        VALUES ($1, $2, $3, $4, 'refund', $5, $6, 'completed', NOW(), $7, $8, $9)
+        RETURNING {}
+        "#,
+        REFUND_COLUMNS
+    ))
+    .bind(client_id)
+    .bind(invoice_id)
+    .bind(payment_id)
+    .bind(credit_note_id)
+    .bind(amount)
+    .bind(processor)
+    .bind(reference_number)
+    .bind(notes)
+    .bind(created_by)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(refund)
+}
+
+/// Returns credit applied to an invoice back to the credit notes it came
+/// from. The application rows are kept and marked reversed.
+pub async fn reverse_credits(tx: &mut Transaction<'_, Postgres>, invoice_id: Uuid) -> CreditResult<Decimal> {
+    let applications: Vec<(Uuid, Uuid, Decimal)> = sqlx::query_as(
+        "UPDATE credit_note_applications SET reversed_at = NOW()
+         WHERE invoice_id = $1 AND reversed_at IS NULL
+         RETURNING id, credit_note_id, amount",
+    )
+    .bind(invoice_id)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let mut reversed = Decimal::ZERO;
+    for (_, credit_note_id, amount) in applications {
+        sqlx::query(
+            r#"
+            UPDATE credit_notes SET
+                applied_amount = COALESCE(applied_amount, 0) - $2,
+                remaining_amount = COALESCE(remaining_amount, amount) + $2,
+                status = CASE WHEN COALESCE(applied_amount, 0) - $2 > 0 THEN 'partially_applied' ELSE 'issued' END,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(credit_note_id)
+        .bind(amount)
+        .execute(&mut **tx)
+        .await?;
+        reversed += amount;
+    }
+
+    sqlx::query("UPDATE invoices SET credited_amount = credited_amount - $2 WHERE id = $1")
+        .bind(invoice_id)
+        .bind(reversed)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(reversed)
+}
+
+/// Uses a client's open credit notes in the invoice currency, oldest first,
+/// against a new invoice. Returns the amount applied.
+pub async fn apply_available_credits(
+    tx: &mut Transaction<'_, Postgres>,
+    invoice_id: Uuid,
+    applied_by: Option<Uuid>,
+) -> CreditResult<Decimal> {
+    let Some(mut invoice) = lock_invoice(tx, invoice_id).await? else {
+        return Ok(Decimal::ZERO);
+    };
+    if invoice.is_void() {
+        return Ok(Decimal::ZERO);
+    }
+
+    let credits: Vec<(Uuid, Decimal)> = sqlx::query_as(
+        r#"
+        SELECT id, COALESCE(remaining_amount, amount) FROM credit_notes
+        WHERE client_id = $1 AND currency = $2 AND status IN ('issued', 'partially_applied')
+          AND COALESCE(remaining_amount, amount) > 0
+        ORDER BY issue_date, created_at
+        FOR UPDATE
+        "#,
+    )
+    .bind(invoice.client_id)
+    .bind(&invoice.currency)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let mut applied = Decimal::ZERO;
+    for (credit_note_id, remaining) in credits {
+        let amount = remaining.min(invoice.balance);
+        if amount <= Decimal::ZERO {
+            break;
+        }
+        apply_credit(tx, credit_note_id, &invoice, amount, applied_by).await?;
+        invoice.status = Some(invoice_status(invoice.status.as_deref().unwrap_or("draft"), invoice.total, invoice.balance - amount).to_string());
+        invoice.balance -= amount;
+        applied += amount;
+    }
+
+    Ok(applied)
+}
+
+/// Status an invoice should have once its balance changes. Draft invoices
+/// stay drafts until they are sent; void invoices never change.
+pub fn invoice_status(current: &str, total: Decimal, balance: Decimal) -> &str {
+    match current {
+        "void" | "draft" => current,
+        _ if balance <= Decimal::ZERO => "paid",
+        _ if balance < total => "partial",
+        "paid" | "partial" => "sent",
+        _ => current,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invoice_status_follows_balance() {
+        let total = Decimal::from(100);
+        assert_eq!(invoice_status("sent", total, Decimal::ZERO), "paid");
+        assert_eq!(invoice_status("sent", total, Decimal::from(40)), "partial");
+        assert_eq!(invoice_status("overdue", total, total), "overdue");
+    }
+
+    #[test]
+    fn test_refund_reopens_paid_invoice() {
+        let total = Decimal::from(100);
+        assert_eq!(invoice_status("paid", total, total), "sent");
+        assert_eq!(invoice_status("paid", total, Decimal::from(30)), "partial");
+    }
+
+    #[test]
+    fn test_draft_and_void_are_kept() {
+        let total = Decimal::from(100);
+        assert_eq!(invoice_status("draft", total, Decimal::ZERO), "draft");
+        assert_eq!(invoice_status("void", total, Decimal::ZERO), "void");
+    }
+}
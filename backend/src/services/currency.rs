@@ -0,0 +1,369 @@
+use crate::services::recurring_billing::round_money;
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection, PgPool};
+use std::collections::HashMap;
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+pub type CurrencyResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+pub const CURRENCY_COLUMNS: &str = "code, name, symbol, minor_units, is_base, is_active";
+
+pub const RATE_COLUMNS: &str = "id, from_currency, to_currency, rate, rate_date, source, created_by, created_at";
+
+// Invoices won't be raised against a rate older than this; weekends and bank holidays fit inside it
+pub const MAX_RATE_AGE_DAYS: i64 = 7;
+
+const FALLBACK_BASE_CURRENCY: &str = "USD";
+
+#[derive(Debug, Clone)]
+pub struct ExchangeRateConfig {
+    pub provider_url: Option<String>, // Frankfurter-compatible endpoint, e.g. https://api.frankfurter.app/latest
+    pub check_interval_seconds: u64,  // How often rates are fetched
+}
+
+impl Default for ExchangeRateConfig {
+    fn default() -> Self {
+        Self {
+            provider_url: std::env::var("EXCHANGE_RATE_PROVIDER_URL").ok().filter(|u| !u.is_empty()),
+            check_interval_seconds: 6 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ExchangeRateService {
+    config: ExchangeRateConfig,
+    db_pool: PgPool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Currency {
+    pub code: String,
+    pub name: String,
+    pub symbol: String,
+    pub minor_units: i32,
+    pub is_base: bool,
+    pub is_active: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ExchangeRate {
+    pub id: Uuid,
+    pub from_currency: String,
+    pub to_currency: String,
+    pub rate: Decimal,
+    pub rate_date: NaiveDate,
+    pub source: String,
+    pub created_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// The conversion stored on an invoice or payment when it is recorded.
+#[derive(Debug, Clone, Serialize)]
+pub struct RateSnapshot {
+    pub currency: String,
+    pub base_currency: String,
+    pub rate: Decimal,
+    pub rate_date: NaiveDate,
+}
+
+impl RateSnapshot {
+    pub fn to_base(&self, amount: Decimal) -> Decimal {
+        convert(amount, self.rate)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ProviderRates {
+    date: NaiveDate,
+    rates: HashMap<String, Decimal>,
+}
+
+impl ExchangeRateService {
+    pub fn new(config: ExchangeRateConfig, db_pool: PgPool) -> Self {
+        Self { config, db_pool }
+    }
+
+    pub async fn start(&self) -> CurrencyResult<()> {
+        if self.config.provider_url.is_none() {
+            info!("No exchange rate provider configured; rates must be entered manually");
+            return Ok(());
+        }
+
+        info!("Starting exchange rate sync");
+
+        let mut check_interval = interval(Duration::from_secs(self.config.check_interval_seconds));
+
+        tokio::spawn({
+            let service = self.clone();
+            async move {
+                loop {
+                    check_interval.tick().await;
+
+                    if let Err(e) = service.sync_rates().await {
+                        error!("Error syncing exchange rates: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Fetches the latest base-currency rates for every active currency.
+    /// Rates entered by hand for the same day are left alone.
+    pub async fn sync_rates(&self) -> CurrencyResult<usize> {
+        let Some(provider_url) = self.config.provider_url.as_deref() else {
+            return Err("No exchange rate provider configured".into());
+        };
+
+        let mut conn = self.db_pool.acquire().await?;
+        let base = base_currency(&mut conn).await?;
+        let targets: Vec<String> = sqlx::query_scalar(
+            "SELECT code FROM currencies WHERE is_active = true AND code <> $1 ORDER BY code",
+        )
+        .bind(&base)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        if targets.is_empty() {
+            return Ok(0);
+        }
+
+        let response = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(15))
+            .build()?
+            .get(provider_url)
+            .query(&[("from", base.as_str()), ("to", targets.join(",").as_str())])
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(format!("Exchange rate provider returned {}", response.status()).into());
+        }
+
+        let fetched: ProviderRates = response.json().await?;
+
+        let mut stored = 0;
+        for code in &targets {
+            let Some(rate) = fetched.rates.get(code).filter(|r| **r > Decimal::ZERO) else {
+                warn!("Exchange rate provider has no {} rate for {}", code, fetched.date);
+                continue;
+            };
+
+            let result = sqlx::query(
+                r#"
+                INSERT INTO exchange_rates (from_currency, to_currency, rate, rate_date, source)
+                VALUES ($1, $2, $3, $4, 'provider')
+                ON CONFLICT (from_currency, to_currency, rate_date) DO UPDATE SET rate = EXCLUDED.rate
+                WHERE exchange_rates.source <> 'manual'
+                "#,
+            )
+            .bind(&base)
+            .bind(code)
+            .bind(rate)
+            .bind(fetched.date)
+            .execute(&mut *conn)
+            .await?;
+            stored += result.rows_affected() as usize;
+        }
+
+        info!("Stored {} exchange rates for {}", stored, fetched.date);
+        Ok(stored)
+    }
+}
+
+pub async fn base_currency(conn: &mut PgConnection) -> CurrencyResult<String> {
+    let code: Option<String> = sqlx::query_scalar("SELECT code FROM currencies WHERE is_base = true")
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    Ok(code.unwrap_or_else(|| FALLBACK_BASE_CURRENCY.to_string()))
+}
+
+pub async fn client_currency(conn: &mut PgConnection, client_id: Uuid) -> CurrencyResult<String> {
+    let code: Option<String> = sqlx::query_scalar("SELECT currency FROM clients WHERE id = $1")
+        .bind(client_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    code.ok_or_else(|| format!("Client {} not found", client_id).into())
+}
+
+/// The most recent rate on or before `date` converting `from` into `to`,
+/// with the date it was published. Stored rates are used directly, inverted,
+/// or crossed through the base currency, in that order.
+pub async fn rate_on(
+    conn: &mut PgConnection,
+    from: &str,
+    to: &str,
+    date: NaiveDate,
+) -> CurrencyResult<Option<(Decimal, NaiveDate)>> {
+    if from == to {
+        return Ok(Some((Decimal::ONE, date)));
+    }
+
+    if let Some(rate) = stored_rate(conn, from, to, date).await? {
+        return Ok(Some(rate));
+    }
+
+    let base = base_currency(conn).await?;
+    if from == base || to == base {
+        return Ok(None);
+    }
+
+    let (Some((from_base, from_date)), Some((base_to, to_date))) = (
+        stored_rate(conn, from, &base, date).await?,
+        stored_rate(conn, &base, to, date).await?,
+    ) else {
+        return Ok(None);
+    };
+
+    Ok(Some((cross_rate(from_base, base_to), from_date.min(to_date))))
+}
+
+async fn stored_rate(
+    conn: &mut PgConnection,
+    from: &str,
+    to: &str,
+    date: NaiveDate,
+) -> CurrencyResult<Option<(Decimal, NaiveDate)>> {
+    let row: Option<(String, Decimal, NaiveDate)> = sqlx::query_as(
+        r#"
+        SELECT from_currency, rate, rate_date FROM exchange_rates
+        WHERE ((from_currency = $1 AND to_currency = $2) OR (from_currency = $2 AND to_currency = $1))
+          AND rate_date <= $3
+        ORDER BY rate_date DESC, (from_currency = $1) DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(from)
+    .bind(to)
+    .bind(date)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(row.map(|(stored_from, rate, rate_date)| {
+        if stored_from == from {
+            (rate, rate_date)
+        } else {
+            (invert(rate), rate_date)
+        }
+    }))
+}
+
+/// Fixes the rate from `currency` to the base currency for a document dated
+/// `date`. Fails rather than guessing when no recent rate is on file.
+pub async fn snapshot(conn: &mut PgConnection, currency: &str, date: NaiveDate) -> CurrencyResult<RateSnapshot> {
+    let base_currency = base_currency(conn).await?;
+
+    let Some((rate, rate_date)) = rate_on(conn, currency, &base_currency, date).await? else {
+        return Err(format!("No {} to {} exchange rate on or before {}", currency, base_currency, date).into());
+    };
+    if (date - rate_date).num_days() > MAX_RATE_AGE_DAYS {
+        return Err(format!(
+            "Latest {} to {} exchange rate is from {}, more than {} days before {}",
+            currency, base_currency, rate_date, MAX_RATE_AGE_DAYS, date
+        )
+        .into());
+    }
+
+    Ok(RateSnapshot { currency: currency.to_string(), base_currency, rate, rate_date })
+}
+
+pub fn convert(amount: Decimal, rate: Decimal) -> Decimal {
+    round_money(amount * rate)
+}
+
+pub fn invert(rate: Decimal) -> Decimal {
+    (Decimal::ONE / rate).round_dp(8)
+}
+
+pub fn cross_rate(from_base: Decimal, base_to: Decimal) -> Decimal {
+    (from_base * base_to).round_dp(8)
+}
+
+/// Symbol shown in front of amounts; codes without one are shown as the code.
+pub fn symbol(currency: &str) -> Option<&'static str> {
+    match currency {
+        "USD" => Some("$"),
+        "CAD" => Some("CA$"),
+        "GBP" => Some("£"),
+        "EUR" => Some("€"),
+        "AUD" => Some("A$"),
+        "NZD" => Some("NZ$"),
+        "JPY" => Some("¥"),
+        _ => None,
+    }
+}
+
+pub fn minor_units(currency: &str) -> u32 {
+    match currency {
+        "JPY" | "KRW" | "ISK" => 0,
+        _ => 2,
+    }
+}
+
+/// Formats an amount for emails and documents, e.g. `£1,234.50` or `CHF 80.00`.
+pub fn format_money(amount: Decimal, currency: &str) -> String {
+    let digits = format!("{:.*}", minor_units(currency) as usize, amount.abs());
+    let (whole, fraction) = match digits.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (digits.as_str(), None),
+    };
+
+    let mut grouped = String::with_capacity(whole.len() + whole.len() / 3);
+    for (i, c) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    if let Some(fraction) = fraction {
+        grouped.push('.');
+        grouped.push_str(fraction);
+    }
+
+    let sign = if amount.is_sign_negative() && !amount.is_zero() { "-" } else { "" };
+    match symbol(currency) {
+        Some(symbol) => format!("{}{}{}", sign, symbol, grouped),
+        None => format!("{}{} {}", sign, currency, grouped),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn dec(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_format_money() {
+        assert_eq!(format_money(dec("1234.5"), "USD"), "$1,234.50");
+        assert_eq!(format_money(dec("1234567.891"), "GBP"), "£1,234,567.89");
+        assert_eq!(format_money(dec("-80"), "CAD"), "-CA$80.00");
+        assert_eq!(format_money(dec("999"), "CHF"), "CHF 999.00");
+        assert_eq!(format_money(dec("1500.4"), "JPY"), "¥1,500");
+        assert_eq!(format_money(Decimal::ZERO, "EUR"), "€0.00");
+    }
+
+    #[test]
+    fn test_rates_convert_both_ways() {
+        // 1 USD = 1.3500 CAD stored; a CAD invoice converts back with the inverse
+        let cad_to_usd = invert(dec("1.35"));
+        assert_eq!(cad_to_usd, dec("0.74074074"));
+        assert_eq!(convert(dec("1350"), cad_to_usd), dec("1000.00"));
+
+        // GBP -> USD -> CAD
+        let gbp_to_cad = cross_rate(dec("1.27"), dec("1.35"));
+        assert_eq!(gbp_to_cad, dec("1.7145"));
+        assert_eq!(convert(dec("100"), gbp_to_cad), dec("171.45"));
+    }
+}
@@ -0,0 +1,699 @@
+use chrono::{DateTime, Datelike, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub type DispatchResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+pub const APPOINTMENT_STATUSES: &[&str] = &["scheduled", "confirmed", "in_progress", "completed", "cancelled", "no_show"];
+
+/// Statuses that still hold a technician's time.
+pub const ACTIVE_STATUSES: &[&str] = &["scheduled", "confirmed", "in_progress"];
+
+pub const TIME_OFF_KINDS: &[&str] = &["vacation", "sick", "training", "holiday", "other"];
+
+pub const APPOINTMENT_COLUMNS: &str = "a.id, a.client_id, c.name as client_name, a.ticket_id, tk.number as ticket_number,
+    a.task_id, tsk.name as task_name, a.technician_id, u.first_name || ' ' || u.last_name as technician_name,
+    a.location_id, l.name as location_name, a.contact_id, a.title, a.notes, a.start_time, a.end_time, a.status,
+    a.sequence, a.confirmation_sent_at, a.cancelled_at, a.cancellation_reason, a.created_by, a.created_at, a.updated_at";
+
+pub const APPOINTMENT_JOINS: &str = "FROM appointments a
+    JOIN clients c ON c.id = a.client_id
+    JOIN users u ON u.id = a.technician_id
+    LEFT JOIN tickets tk ON tk.id = a.ticket_id
+    LEFT JOIN tasks tsk ON tsk.id = a.task_id
+    LEFT JOIN locations l ON l.id = a.location_id";
+
+pub const TIME_OFF_COLUMNS: &str = "id, user_id, starts_at, ends_at, kind, reason, created_by, created_at";
+
+/// Travel time assumed between two client sites with no recorded time, from
+/// DISPATCH_DEFAULT_TRAVEL_MINUTES.
+pub fn default_travel_minutes() -> i64 {
+    std::env::var("DISPATCH_DEFAULT_TRAVEL_MINUTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30)
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Appointment {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub ticket_id: Option<Uuid>,
+    pub ticket_number: Option<i32>,
+    pub task_id: Option<Uuid>,
+    pub task_name: Option<String>,
+    pub technician_id: Uuid,
+    pub technician_name: String,
+    pub location_id: Option<Uuid>,
+    pub location_name: Option<String>,
+    pub contact_id: Option<Uuid>,
+    pub title: String,
+    pub notes: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub status: String,
+    pub sequence: i32,
+    pub confirmation_sent_at: Option<DateTime<Utc>>,
+    pub cancelled_at: Option<DateTime<Utc>>,
+    pub cancellation_reason: Option<String>,
+    pub created_by: Uuid,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl Appointment {
+    pub fn slot(&self) -> Slot {
+        Slot {
+            id: Some(self.id),
+            start: self.start_time,
+            end: self.end_time,
+            location_id: self.location_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledAppointment {
+    #[serde(flatten)]
+    pub appointment: Appointment,
+    pub conflicts: Vec<DispatchConflict>,
+}
+
+/// A technician's time booked at a place; `id` is None for a proposed booking.
+#[derive(Debug, Clone, FromRow)]
+pub struct Slot {
+    pub id: Option<Uuid>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub location_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct TimeOff {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub kind: String,
+    pub reason: Option<String>,
+    pub created_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Working hours on one weekday, in the technician's timezone.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct AvailabilityWindow {
+    pub weekday: i16, // ISO, Monday = 1
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TechnicianHours {
+    pub user_id: Uuid,
+    pub name: String,
+    pub timezone: String,
+    pub windows: Vec<AvailabilityWindow>,
+    pub custom: bool, // false when falling back to the user's working days
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    Overlap,
+    TravelTime,
+    TimeOff,
+    OutsideHours,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DispatchConflict {
+    pub kind: ConflictKind,
+    pub appointment_id: Option<Uuid>, // the other appointment
+    pub time_off_id: Option<Uuid>,
+    pub minutes: Option<i64>, // overlap, or travel time short
+    pub message: String,
+}
+
+/// Recorded drive times between locations, falling back to a default for
+/// pairs without one. Remote work and visits to the same site need none.
+#[derive(Debug, Clone)]
+pub struct TravelTimes {
+    pairs: HashMap<(Uuid, Uuid), i64>,
+    default_minutes: i64,
+}
+
+impl TravelTimes {
+    pub fn new(default_minutes: i64) -> Self {
+        Self { pairs: HashMap::new(), default_minutes }
+    }
+
+    pub fn insert(&mut self, a: Uuid, b: Uuid, minutes: i64) {
+        self.pairs.insert(ordered(a, b), minutes);
+    }
+
+    pub fn between(&self, from: Option<Uuid>, to: Option<Uuid>) -> i64 {
+        match (from, to) {
+            (Some(a), Some(b)) if a != b => self.pairs.get(&ordered(a, b)).copied().unwrap_or(self.default_minutes),
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BoardTechnician {
+    pub hours: TechnicianHours,
+    pub appointments: Vec<ScheduledAppointment>,
+    pub time_off: Vec<TimeOff>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct UnscheduledTicket {
+    pub id: Uuid,
+    pub number: i32,
+    pub subject: String,
+    pub priority: String,
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub assigned_to: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DispatchBoard {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub technicians: Vec<BoardTechnician>,
+    pub unscheduled: Vec<UnscheduledTicket>,
+}
+
+/// One VEVENT in a technician's feed.
+#[derive(Debug, Clone)]
+pub struct CalendarEvent {
+    pub uid: Uuid,
+    pub sequence: i32,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub summary: String,
+    pub description: String,
+    pub location: Option<String>,
+    pub cancelled: bool,
+    pub updated: DateTime<Utc>,
+}
+
+/// Location pairs are stored lower id first.
+pub fn ordered(a: Uuid, b: Uuid) -> (Uuid, Uuid) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+pub fn default_windows(working_days: &[i16], start: NaiveTime, end: NaiveTime) -> Vec<AvailabilityWindow> {
+    working_days
+        .iter()
+        .map(|&weekday| AvailabilityWindow { weekday, start_time: start, end_time: end })
+        .collect()
+}
+
+/// Whether a booking, in the technician's local time, fits inside one of
+/// their working-hour windows.
+pub fn within_hours(local_start: NaiveDateTime, local_end: NaiveDateTime, windows: &[AvailabilityWindow]) -> bool {
+    if local_start.date() != local_end.date() {
+        return false;
+    }
+    let weekday = local_start.weekday().number_from_monday() as i16;
+    windows
+        .iter()
+        .any(|w| w.weekday == weekday && w.start_time <= local_start.time() && local_end.time() <= w.end_time)
+}
+
+/// Double bookings, too little time to drive from the previous visit or on
+/// to the next one, time off, and work outside hours.
+pub fn detect_conflicts(
+    slot: &Slot,
+    others: &[Slot],
+    time_off: &[TimeOff],
+    travel: &TravelTimes,
+    in_hours: bool,
+) -> Vec<DispatchConflict> {
+    let others: Vec<&Slot> = others.iter().filter(|o| o.id.is_none() || o.id != slot.id).collect();
+    let mut conflicts = Vec::new();
+
+    for other in &others {
+        if other.start < slot.end && slot.start < other.end {
+            let minutes = (slot.end.min(other.end) - slot.start.max(other.start)).num_minutes();
+            conflicts.push(DispatchConflict {
+                kind: ConflictKind::Overlap,
+                appointment_id: other.id,
+                time_off_id: None,
+                minutes: Some(minutes),
+                message: format!("Overlaps another appointment by {} minutes", minutes),
+            });
+        }
+    }
+
+    let previous = others.iter().filter(|o| o.end <= slot.start).max_by_key(|o| o.end);
+    if let Some(previous) = previous {
+        let gap = (slot.start - previous.end).num_minutes();
+        let needed = travel.between(previous.location_id, slot.location_id);
+        if gap < needed {
+            conflicts.push(DispatchConflict {
+                kind: ConflictKind::TravelTime,
+                appointment_id: previous.id,
+                time_off_id: None,
+                minutes: Some(needed - gap),
+                message: format!("{} minutes to travel from the previous visit, {} allowed", needed, gap),
+            });
+        }
+    }
+    let next = others.iter().filter(|o| o.start >= slot.end).min_by_key(|o| o.start);
+    if let Some(next) = next {
+        let gap = (next.start - slot.end).num_minutes();
+        let needed = travel.between(slot.location_id, next.location_id);
+        if gap < needed {
+            conflicts.push(DispatchConflict {
+                kind: ConflictKind::TravelTime,
+                appointment_id: next.id,
+                time_off_id: None,
+                minutes: Some(needed - gap),
+                message: format!("{} minutes to travel to the next visit, {} allowed", needed, gap),
+            });
+        }
+    }
+
+    for off in time_off {
+        if off.starts_at < slot.end && slot.start < off.ends_at {
+            conflicts.push(DispatchConflict {
+                kind: ConflictKind::TimeOff,
+                appointment_id: None,
+                time_off_id: Some(off.id),
+                minutes: None,
+                message: format!("Technician is off ({})", off.kind),
+            });
+        }
+    }
+
+    if !in_hours {
+        conflicts.push(DispatchConflict {
+            kind: ConflictKind::OutsideHours,
+            appointment_id: None,
+            time_off_id: None,
+            minutes: None,
+            message: "Outside the technician's working hours".to_string(),
+        });
+    }
+
+    conflicts
+}
+
+fn escape_text(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Lines longer than 75 octets continue on the next line after a space.
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 8);
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded
+}
+
+fn ical_time(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// An iCalendar (RFC 5545) feed of the given events.
+pub fn render_calendar(name: &str, events: &[CalendarEvent]) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//GhostHub//Dispatch//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        format!("X-WR-CALNAME:{}", escape_text(name)),
+    ];
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}@ghosthub", event.uid));
+        lines.push(format!("SEQUENCE:{}", event.sequence));
+        lines.push(format!("DTSTAMP:{}", ical_time(event.updated)));
+        lines.push(format!("DTSTART:{}", ical_time(event.start)));
+        lines.push(format!("DTEND:{}", ical_time(event.end)));
+        lines.push(format!("SUMMARY:{}", escape_text(&event.summary)));
+        if !event.description.is_empty() {
+            lines.push(format!("DESCRIPTION:{}", escape_text(&event.description)));
+        }
+        if let Some(location) = &event.location {
+            lines.push(format!("LOCATION:{}", escape_text(location)));
+        }
+        lines.push(format!("STATUS:{}", if event.cancelled { "CANCELLED" } else { "CONFIRMED" }));
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|l| fold_line(l)).collect::<Vec<_>>().join("\r\n") + "\r\n"
+}
+
+pub async fn load_appointment(pool: &PgPool, id: Uuid) -> DispatchResult<Option<Appointment>> {
+    Ok(sqlx::query_as::<_, Appointment>(&format!("SELECT {} {} WHERE a.id = $1", APPOINTMENT_COLUMNS, APPOINTMENT_JOINS))
+        .bind(id)
+        .fetch_optional(pool)
+        .await?)
+}
+
+pub async fn technician_hours(pool: &PgPool, user_id: Uuid) -> DispatchResult<Option<TechnicianHours>> {
+    let user: Option<(String, String, Vec<i16>, NaiveTime, NaiveTime)> = sqlx::query_as(
+        "SELECT first_name || ' ' || last_name,
+                COALESCE((SELECT name FROM pg_timezone_names WHERE name = users.timezone), 'UTC'),
+                COALESCE(working_days, '{1,2,3,4,5}'), COALESCE(workday_start, '09:00'), COALESCE(workday_end, '18:00')
+         FROM users WHERE id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some((name, timezone, working_days, start, end)) = user else {
+        return Ok(None);
+    };
+
+    let windows = sqlx::query_as::<_, AvailabilityWindow>(
+        "SELECT weekday, start_time, end_time FROM technician_availability WHERE user_id = $1 ORDER BY weekday, start_time",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    let custom = !windows.is_empty();
+
+    Ok(Some(TechnicianHours {
+        user_id,
+        name,
+        timezone,
+        windows: if custom { windows } else { default_windows(&working_days, start, end) },
+        custom,
+    }))
+}
+
+pub async fn travel_times(pool: &PgPool, location_ids: &[Uuid]) -> DispatchResult<TravelTimes> {
+    let mut travel = TravelTimes::new(default_travel_minutes());
+    if location_ids.is_empty() {
+        return Ok(travel);
+    }
+    let pairs: Vec<(Uuid, Uuid, i32)> = sqlx::query_as(
+        "SELECT from_location_id, to_location_id, minutes FROM location_travel_times
+         WHERE from_location_id = ANY($1) AND to_location_id = ANY($1)",
+    )
+    .bind(location_ids)
+    .fetch_all(pool)
+    .await?;
+    for (from, to, minutes) in pairs {
+        travel.insert(from, to, minutes as i64);
+    }
+    Ok(travel)
+}
+
+async fn active_slots(pool: &PgPool, technician_id: Uuid, from: DateTime<Utc>, to: DateTime<Utc>) -> DispatchResult<Vec<Slot>> {
+    Ok(sqlx::query_as::<_, Slot>(
+        r#"SELECT id, start_time as start, end_time as "end", location_id FROM appointments
+         WHERE technician_id = $1 AND status = ANY($4) AND start_time < $3 AND end_time > $2
+         ORDER BY start_time"#,
+    )
+    .bind(technician_id)
+    .bind(from)
+    .bind(to)
+    .bind(ACTIVE_STATUSES)
+    .fetch_all(pool)
+    .await?)
+}
+
+async fn time_off_between(pool: &PgPool, user_id: Uuid, from: DateTime<Utc>, to: DateTime<Utc>) -> DispatchResult<Vec<TimeOff>> {
+    Ok(sqlx::query_as::<_, TimeOff>(&format!(
+        "SELECT {} FROM technician_time_off WHERE user_id = $1 AND starts_at < $3 AND ends_at > $2 ORDER BY starts_at",
+        TIME_OFF_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Whether a booking falls in working hours, converting it to the
+/// technician's timezone in the database.
+async fn slot_in_hours(pool: &PgPool, hours: &TechnicianHours, slot: &Slot) -> DispatchResult<bool> {
+    let (local_start, local_end): (NaiveDateTime, NaiveDateTime) =
+        sqlx::query_as("SELECT $1::timestamptz AT TIME ZONE $3, $2::timestamptz AT TIME ZONE $3")
+            .bind(slot.start)
+            .bind(slot.end)
+            .bind(&hours.timezone)
+            .fetch_one(pool)
+            .await?;
+    Ok(within_hours(local_start, local_end, &hours.windows))
+}
+
+/// Conflicts for booking a technician into `slot`, against their other
+/// appointments the day either side.
+pub async fn check_slot(pool: &PgPool, technician_id: Uuid, slot: &Slot) -> DispatchResult<Vec<DispatchConflict>> {
+    let Some(hours) = technician_hours(pool, technician_id).await? else {
+        return Ok(Vec::new());
+    };
+    let day = chrono::Duration::days(1);
+    let others = active_slots(pool, technician_id, slot.start - day, slot.end + day).await?;
+    let time_off = time_off_between(pool, technician_id, slot.start, slot.end).await?;
+    let locations: Vec<Uuid> = others.iter().chain([slot]).filter_map(|s| s.location_id).collect();
+    let travel = travel_times(pool, &locations).await?;
+    let in_hours = slot_in_hours(pool, &hours, slot).await?;
+    Ok(detect_conflicts(slot, &others, &time_off, &travel, in_hours))
+}
+
+/// Each technician's appointments, with conflicts, and time off between
+/// `from` and `to`, plus open tickets with nothing booked.
+pub async fn board(
+    pool: &PgPool,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    technician_ids: Option<&[Uuid]>,
+) -> DispatchResult<DispatchBoard> {
+    let ids: Vec<Uuid> = sqlx::query_scalar(
+        "SELECT id FROM users WHERE COALESCE(is_active, true) AND ($1::uuid[] IS NULL OR id = ANY($1))
+         ORDER BY first_name, last_name",
+    )
+    .bind(technician_ids)
+    .fetch_all(pool)
+    .await?;
+
+    let mut technicians = Vec::with_capacity(ids.len());
+    for id in ids {
+        let Some(hours) = technician_hours(pool, id).await? else { continue };
+        let day = chrono::Duration::days(1);
+        let appointments = sqlx::query_as::<_, Appointment>(&format!(
+            "SELECT {} {} WHERE a.technician_id = $1 AND a.start_time < $3 AND a.end_time > $2 ORDER BY a.start_time",
+            APPOINTMENT_COLUMNS, APPOINTMENT_JOINS
+        ))
+        .bind(id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+        let others = active_slots(pool, id, from - day, to + day).await?;
+        let time_off = time_off_between(pool, id, from - day, to + day).await?;
+        let locations: Vec<Uuid> = others.iter().filter_map(|s| s.location_id).collect();
+        let travel = travel_times(pool, &locations).await?;
+
+        let mut scheduled = Vec::with_capacity(appointments.len());
+        for appointment in appointments {
+            let conflicts = if ACTIVE_STATUSES.contains(&appointment.status.as_str()) {
+                let slot = appointment.slot();
+                let in_hours = slot_in_hours(pool, &hours, &slot).await?;
+                detect_conflicts(&slot, &others, &time_off, &travel, in_hours)
+            } else {
+                Vec::new()
+            };
+            scheduled.push(ScheduledAppointment { appointment, conflicts });
+        }
+
+        technicians.push(BoardTechnician {
+            hours,
+            appointments: scheduled,
+            time_off: time_off.into_iter().filter(|t| t.starts_at < to && t.ends_at > from).collect(),
+        });
+    }
+
+    let unscheduled = sqlx::query_as::<_, UnscheduledTicket>(
+        r#"
+        SELECT t.id, t.number, t.subject, COALESCE(t.priority, 'medium') as priority, t.client_id,
+               c.name as client_name, t.assigned_to, t.created_at
+        FROM tickets t
+        JOIN clients c ON c.id = t.client_id
+        WHERE COALESCE(t.status, 'open') NOT IN ('closed', 'resolved')
+          AND NOT EXISTS (
+              SELECT 1 FROM appointments a
+              WHERE a.ticket_id = t.id AND a.status = ANY($1) AND a.end_time >= NOW()
+          )
+        ORDER BY CASE t.priority WHEN 'critical' THEN 0 WHEN 'high' THEN 1 WHEN 'medium' THEN 2 ELSE 3 END, t.created_at
+        LIMIT 100
+        "#,
+    )
+    .bind(ACTIVE_STATUSES)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(DispatchBoard { from, to, technicians, unscheduled })
+}
+
+/// A technician's feed: the last 30 days and everything ahead, cancellations
+/// included so subscribed calendars drop them.
+pub async fn calendar_feed(pool: &PgPool, technician_id: Uuid, name: &str) -> DispatchResult<String> {
+    let appointments = sqlx::query_as::<_, Appointment>(&format!(
+        "SELECT {} {} WHERE a.technician_id = $1 AND a.end_time >= NOW() - INTERVAL '30 days' ORDER BY a.start_time",
+        APPOINTMENT_COLUMNS, APPOINTMENT_JOINS
+    ))
+    .bind(technician_id)
+    .fetch_all(pool)
+    .await?;
+
+    let addresses: HashMap<Uuid, String> = {
+        let ids: Vec<Uuid> = appointments.iter().filter_map(|a| a.location_id).collect();
+        let rows: Vec<(Uuid, String)> = sqlx::query_as(
+            "SELECT id, CONCAT_WS(', ', NULLIF(name, ''), NULLIF(address, ''), NULLIF(city, ''), NULLIF(state, ''), NULLIF(zip, ''))
+             FROM locations WHERE id = ANY($1)",
+        )
+        .bind(&ids)
+        .fetch_all(pool)
+        .await?;
+        rows.into_iter().collect()
+    };
+
+    let events: Vec<CalendarEvent> = appointments
+        .iter()
+        .map(|a| {
+            let reference = match (a.ticket_number, &a.task_name) {
+                (Some(number), _) => format!("Ticket #{}", number),
+                (None, Some(task)) => format!("Task: {}", task),
+                _ => String::new(),
+            };
+            CalendarEvent {
+                uid: a.id,
+                sequence: a.sequence,
+                start: a.start_time,
+                end: a.end_time,
+                summary: format!("{} - {}", a.client_name, a.title),
+                description: [reference, a.notes.clone().unwrap_or_default()]
+                    .into_iter()
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                location: a.location_id.and_then(|id| addresses.get(&id).cloned()),
+                cancelled: matches!(a.status.as_str(), "cancelled" | "no_show"),
+                updated: a.updated_at.or(a.created_at).unwrap_or_else(Utc::now),
+            }
+        })
+        .collect();
+
+    Ok(render_calendar(name, &events))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, day, hour, minute, 0).unwrap()
+    }
+
+    fn slot(start: DateTime<Utc>, end: DateTime<Utc>, location_id: Option<Uuid>) -> Slot {
+        Slot { id: Some(Uuid::new_v4()), start, end, location_id }
+    }
+
+    #[test]
+    fn test_within_hours() {
+        let nine = NaiveTime::from_hms_opt(9, 0, 0).unwrap();
+        let five = NaiveTime::from_hms_opt(17, 0, 0).unwrap();
+        let windows = default_windows(&[1, 2, 3, 4, 5], nine, five);
+        let local = |day: u32, hour: u32| at(day, hour, 0).naive_utc();
+
+        // 2024-03-04 is a Monday
+        assert!(within_hours(local(4, 9), local(4, 17), &windows));
+        assert!(!within_hours(local(4, 8), local(4, 10), &windows));
+        assert!(!within_hours(local(9, 10), local(9, 12), &windows));
+        assert!(!within_hours(local(4, 16), local(5, 10), &windows));
+    }
+
+    #[test]
+    fn test_detect_conflicts() {
+        let (office, warehouse) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut travel = TravelTimes::new(30);
+        travel.insert(warehouse, office, 45);
+
+        let morning = slot(at(4, 9, 0), at(4, 11, 0), Some(office));
+        let lunch = slot(at(4, 12, 0), at(4, 13, 0), None);
+        let others = vec![morning.clone(), lunch.clone()];
+
+        // Half an hour after the office visit isn't long enough to reach the warehouse
+        let proposed = Slot { id: None, start: at(4, 11, 30), end: at(4, 11, 45), location_id: Some(warehouse) };
+        let conflicts = detect_conflicts(&proposed, &others, &[], &travel, true);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].kind, ConflictKind::TravelTime);
+        assert_eq!(conflicts[0].appointment_id, morning.id);
+        assert_eq!(conflicts[0].minutes, Some(15));
+
+        // Remote work right after needs no travel; overlapping lunch and time off do conflict
+        let off = TimeOff {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            starts_at: at(4, 12, 30),
+            ends_at: at(4, 18, 0),
+            kind: "training".to_string(),
+            reason: None,
+            created_by: None,
+            created_at: None,
+        };
+        let proposed = Slot { id: None, start: at(4, 11, 0), end: at(4, 12, 30), location_id: None };
+        let kinds: Vec<ConflictKind> =
+            detect_conflicts(&proposed, &others, &[off], &travel, false).into_iter().map(|c| c.kind).collect();
+        assert_eq!(kinds, vec![ConflictKind::Overlap, ConflictKind::OutsideHours]);
+
+        // An appointment doesn't conflict with itself
+        assert!(detect_conflicts(&morning, &others, &[], &travel, true).is_empty());
+    }
+
+    #[test]
+    fn test_render_calendar() {
+        let event = CalendarEvent {
+            uid: Uuid::nil(),
+            sequence: 2,
+            start: at(4, 14, 0),
+            end: at(4, 15, 30),
+            summary: "Acme, Inc. - Replace switch; rack 2".to_string(),
+            description: "Ticket #1042\nBring spare SFPs".to_string(),
+            location: Some("Head office, 1 Main St".to_string()),
+            cancelled: false,
+            updated: at(1, 8, 0),
+        };
+        let calendar = render_calendar("Sam Tech", &[event]);
+
+        assert!(calendar.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+        assert!(calendar.contains("\r\nUID:00000000-0000-0000-0000-000000000000@ghosthub\r\n"));
+        assert!(calendar.contains("\r\nDTSTART:20240304T140000Z\r\nDTEND:20240304T153000Z\r\n"));
+        assert!(calendar.contains("\r\nSUMMARY:Acme\\, Inc. - Replace switch\\; rack 2\r\n"));
+        assert!(calendar.contains("\r\nDESCRIPTION:Ticket #1042\\nBring spare SFPs\r\n"));
+        assert!(calendar.contains("\r\nSTATUS:CONFIRMED\r\n"));
+
+        let folded = fold_line(&"x".repeat(160));
+        assert!(folded.split("\r\n").all(|l| l.len() <= 75));
+        assert_eq!(folded.replace("\r\n ", ""), "x".repeat(160));
+    }
+}
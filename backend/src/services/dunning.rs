@@ -0,0 +1,711 @@
+use crate::notifications::{enqueue_notification, QueuedNotification};
+use crate::services::credit_notes::{self, Adjustment, InvoiceBalance};
+use crate::services::currency::{self, format_money};
+use crate::services::payments;
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+pub type DunningResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+pub const SEQUENCE_COLUMNS: &str = "id, name, description, is_default, is_active, created_at, updated_at";
+
+pub const STAGE_COLUMNS: &str = "id, sequence_id, stage_number, days_overdue, subject_template, body_template,
+    priority, late_fee_rule_id, place_credit_hold";
+
+pub const EVENT_COLUMNS: &str = "e.id, e.invoice_id, i.number as invoice_number, e.sequence_id, e.stage_number,
+    e.days_overdue, e.contact_id, e.late_fee_amount, e.credit_hold_placed, e.created_at";
+
+pub const PROMISE_COLUMNS: &str = "p.id, p.client_id, c.name as client_name, p.invoice_id, i.number as invoice_number,
+    p.promised_amount, p.promised_date, p.notes, p.status, p.created_by, p.created_at, p.resolved_at";
+
+pub const LATE_FEE_RULE_COLUMNS: &str = "id, name, description, action_parameters, apply_to_all_clients,
+    client_ids, COALESCE(is_active, true) as is_active, last_executed, COALESCE(execution_count, 0) as execution_count";
+
+pub const PROMISE_STATUSES: &[&str] = &["open", "kept", "broken", "cancelled"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DunningSequence {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub is_default: bool,
+    pub is_active: bool,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DunningStage {
+    pub id: Uuid,
+    pub sequence_id: Uuid,
+    pub stage_number: i32,
+    pub days_overdue: i32,
+    pub subject_template: String,
+    pub body_template: String,
+    pub priority: String,
+    pub late_fee_rule_id: Option<Uuid>,
+    pub place_credit_hold: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DunningEvent {
+    pub id: Uuid,
+    pub invoice_id: Uuid,
+    pub invoice_number: String,
+    pub sequence_id: Uuid,
+    pub stage_number: i32,
+    pub days_overdue: i32,
+    pub contact_id: Option<Uuid>,
+    pub late_fee_amount: Decimal,
+    pub credit_hold_placed: bool,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PaymentPromise {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub invoice_id: Option<Uuid>, // None covers the whole account
+    pub invoice_number: Option<String>,
+    pub promised_amount: Decimal,
+    pub promised_date: NaiveDate,
+    pub notes: Option<String>,
+    pub status: String,
+    pub created_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+/// A billing_automation_rules row with action_type 'apply_late_fee'.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct LateFeeRule {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub action_parameters: serde_json::Value,
+    pub apply_to_all_clients: Option<bool>,
+    pub client_ids: Option<Vec<Uuid>>,
+    pub is_active: bool,
+    pub last_executed: Option<DateTime<Utc>>,
+    pub execution_count: i32,
+}
+
+impl LateFeeRule {
+    pub fn applies_to(&self, client_id: Uuid) -> bool {
+        self.apply_to_all_clients.unwrap_or(false)
+            || self.client_ids.as_ref().is_some_and(|ids| ids.contains(&client_id))
+    }
+
+    pub fn fee(&self) -> Option<LateFee> {
+        serde_json::from_value(self.action_parameters.clone()).ok()
+    }
+}
+
+/// action_parameters of a late fee rule.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LateFee {
+    pub fee_type: String, // flat or percentage (of the outstanding balance)
+    pub amount: Decimal,
+    pub minimum_fee: Option<Decimal>,
+    pub maximum_fee: Option<Decimal>,
+    pub description: Option<String>, // invoice line text, defaults to "Late fee"
+}
+
+impl LateFee {
+    pub fn is_valid(&self) -> bool {
+        matches!(self.fee_type.as_str(), "flat" | "percentage")
+            && self.amount > Decimal::ZERO
+            && self.minimum_fee.is_none_or(|m| m >= Decimal::ZERO)
+            && self.maximum_fee.is_none_or(|m| m > Decimal::ZERO && self.minimum_fee.is_none_or(|min| min <= m))
+    }
+
+    /// Fee on an outstanding balance, rounded to the currency's minor unit.
+    pub fn amount_for(&self, balance: Decimal, currency: &str) -> Decimal {
+        let mut fee = match self.fee_type.as_str() {
+            "percentage" => balance * self.amount / Decimal::from(100),
+            _ => self.amount,
+        };
+        if let Some(minimum) = self.minimum_fee {
+            fee = fee.max(minimum);
+        }
+        if let Some(maximum) = self.maximum_fee {
+            fee = fee.min(maximum);
+        }
+        fee.round_dp(currency::minor_units(currency)).max(Decimal::ZERO)
+    }
+}
+
+/// The stage an invoice should move to: the furthest one it is overdue enough
+/// for, if that is past the last stage it reached. Stages skipped over (for
+/// example when a sequence is assigned to an already late invoice) don't run.
+pub fn next_stage(stages: &[DunningStage], last_stage_number: i32, days_overdue: i64) -> Option<&DunningStage> {
+    stages
+        .iter()
+        .filter(|s| i64::from(s.days_overdue) <= days_overdue)
+        .max_by_key(|s| s.stage_number)
+        .filter(|s| s.stage_number > last_stage_number)
+}
+
+/// Fills {{name}} placeholders; unknown ones are left as they are.
+pub fn render_template(template: &str, variables: &[(&str, String)]) -> String {
+    variables.iter().fold(template.to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{{{}}}}}", name), value)
+    })
+}
+
+/// Stages must be numbered from 1 with days overdue rising alongside.
+pub fn validate_stages(stages: &[(i32, i32)]) -> bool {
+    let mut sorted = stages.to_vec();
+    sorted.sort();
+    sorted.iter().enumerate().all(|(i, (number, days))| {
+        *number == i as i32 + 1 && *days >= 0 && (i == 0 || *days > sorted[i - 1].1)
+    })
+}
+
+pub async fn load_stages(db_pool: &PgPool, sequence_id: Uuid) -> DunningResult<Vec<DunningStage>> {
+    Ok(sqlx::query_as::<_, DunningStage>(&format!(
+        "SELECT {} FROM dunning_stages WHERE sequence_id = $1 ORDER BY stage_number",
+        STAGE_COLUMNS
+    ))
+    .bind(sequence_id)
+    .fetch_all(db_pool)
+    .await?)
+}
+
+/// Adds a late fee line to the invoice and raises its total and balance to match.
+pub async fn apply_late_fee(
+    tx: &mut Transaction<'_, Postgres>,
+    invoice: &InvoiceBalance,
+    rule: &LateFeeRule,
+    fee: &LateFee,
+    amount: Decimal,
+) -> DunningResult<()> {
+    let description = fee.description.clone().unwrap_or_else(|| "Late fee".to_string());
+
+    sqlx::query(
+        r#"
+        INSERT INTO invoice_line_items (invoice_id, description, quantity, unit_price, line_total,
+                                        tax_rate, tax_amount, source_type, source_id)
+        VALUES ($1, $2, 1, $3, $3, 0, 0, 'late_fee', $4)
+        "#,
+    )
+    .bind(invoice.id)
+    .bind(&description)
+    .bind(amount)
+    .bind(rule.id)
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query(
+        "UPDATE invoices SET subtotal = subtotal + $2, total = total + $2,
+         base_total = base_total + ROUND($2 * exchange_rate, 2)
+         WHERE id = $1",
+    )
+    .bind(invoice.id)
+    .bind(amount)
+    .execute(&mut **tx)
+    .await?;
+
+    let raised = InvoiceBalance { total: invoice.total + amount, ..invoice.clone() };
+    credit_notes::adjust_balance(
+        tx,
+        &raised,
+        invoice.balance + amount,
+        Adjustment {
+            adjustment_type: "late_fee",
+            amount,
+            reason: Some(&description),
+            credit_note_id: None,
+            payment_transaction_id: None,
+            created_by: None,
+        },
+    )
+    .await?;
+
+    sqlx::query(
+        "UPDATE billing_automation_rules SET last_executed = NOW(), execution_count = COALESCE(execution_count, 0) + 1
+         WHERE id = $1",
+    )
+    .bind(rule.id)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn place_credit_hold(
+    db_pool: &PgPool,
+    client_id: Uuid,
+    reason: &str,
+    source: &str,
+) -> DunningResult<bool> {
+    // A manual hold is never downgraded to one dunning would lift by itself
+    let result = sqlx::query(
+        "UPDATE clients SET credit_hold = true, credit_hold_reason = $2, credit_hold_source = $3,
+                credit_hold_at = COALESCE(credit_hold_at, NOW()), updated_at = NOW()
+         WHERE id = $1 AND (NOT credit_hold OR $3 = 'manual')",
+    )
+    .bind(client_id)
+    .bind(reason)
+    .bind(source)
+    .execute(db_pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn release_credit_hold(db_pool: &PgPool, client_id: Uuid) -> DunningResult<bool> {
+    let result = sqlx::query(
+        "UPDATE clients SET credit_hold = false, credit_hold_reason = NULL, credit_hold_source = NULL,
+                credit_hold_at = NULL, updated_at = NOW()
+         WHERE id = $1 AND credit_hold",
+    )
+    .bind(client_id)
+    .execute(db_pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct OverdueInvoice {
+    invoice_id: Uuid,
+    client_id: Uuid,
+    client_name: String,
+    number: String,
+    currency: String,
+    due_date: NaiveDate,
+    sequence_id: Uuid,
+    last_stage: i32,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DunningRunSummary {
+    pub stages_applied: u32,
+    pub reminders_sent: u32,
+    pub late_fees_applied: u32,
+    pub credit_holds_placed: u32,
+    pub credit_holds_released: u32,
+    pub promises_kept: u32,
+    pub promises_broken: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct DunningConfig {
+    pub check_interval_seconds: u64, // How often overdue invoices are checked
+}
+
+impl Default for DunningConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_seconds: 60 * 60,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DunningService {
+    config: DunningConfig,
+    db_pool: PgPool,
+}
+
+impl DunningService {
+    pub fn new(config: DunningConfig, db_pool: PgPool) -> Self {
+        Self { config, db_pool }
+    }
+
+    pub async fn start(&self) -> DunningResult<()> {
+        info!("Starting dunning worker");
+
+        let mut check_interval = interval(Duration::from_secs(self.config.check_interval_seconds));
+
+        tokio::spawn({
+            let service = self.clone();
+            async move {
+                loop {
+                    check_interval.tick().await;
+
+                    if let Err(e) = service.run(Utc::now().date_naive()).await {
+                        error!("Error processing dunning: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Settles due payment promises, moves overdue invoices on to their next
+    /// stage and lifts dunning credit holds that are no longer needed.
+    pub async fn run(&self, today: NaiveDate) -> DunningResult<DunningRunSummary> {
+        let mut summary = DunningRunSummary::default();
+
+        self.resolve_promises(today, &mut summary).await?;
+        self.process_overdue(today, &mut summary).await?;
+        summary.credit_holds_released = self.release_settled_holds().await?;
+
+        if summary.stages_applied > 0 || summary.credit_holds_released > 0 {
+            info!(
+                "Dunning: {} stages applied, {} late fees, {} credit holds placed, {} released",
+                summary.stages_applied, summary.late_fees_applied, summary.credit_holds_placed, summary.credit_holds_released
+            );
+        }
+
+        Ok(summary)
+    }
+
+    async fn resolve_promises(&self, today: NaiveDate, summary: &mut DunningRunSummary) -> DunningResult<()> {
+        // Kept once the promised amount has come in, or the invoice has nothing left owing
+        let kept = sqlx::query(
+            r#"
+            UPDATE payment_promises p SET status = 'kept', resolved_at = NOW()
+            WHERE p.status = 'open' AND (
+                EXISTS (SELECT 1 FROM invoices i WHERE i.id = p.invoice_id
+                        AND (COALESCE(i.balance, 0) <= 0 OR i.status = 'void'))
+                OR (SELECT COALESCE(SUM(pay.amount), 0) FROM payments pay
+                    JOIN invoices i ON i.id = pay.invoice_id
+                    WHERE i.client_id = p.client_id AND (p.invoice_id IS NULL OR pay.invoice_id = p.invoice_id)
+                      AND pay.created_at >= p.created_at) >= p.promised_amount
+            )
+            "#,
+        )
+        .execute(&self.db_pool)
+        .await?;
+        summary.promises_kept = kept.rows_affected() as u32;
+
+        let broken: Vec<(Uuid, Option<Uuid>, String, NaiveDate)> = sqlx::query_as(
+            r#"
+            UPDATE payment_promises p SET status = 'broken', resolved_at = NOW()
+            FROM clients c
+            WHERE c.id = p.client_id AND p.status = 'open' AND p.promised_date < $1
+            RETURNING p.id, p.created_by, c.name, p.promised_date
+            "#,
+        )
+        .bind(today)
+        .fetch_all(&self.db_pool)
+        .await?;
+        summary.promises_broken = broken.len() as u32;
+
+        for (promise_id, created_by, client_name, promised_date) in broken {
+            let Some(user_id) = created_by else { continue };
+            let notification = QueuedNotification::for_user(
+                user_id,
+                "payment_promise_broken",
+                format!("Payment promise from {} was not kept", client_name),
+                format!(
+                    "{} promised payment by {} but it has not been received. Dunning has resumed.",
+                    client_name,
+                    promised_date.format("%Y-%m-%d")
+                ),
+            )
+            .with_entity("payment_promise", promise_id);
+
+            if let Err(e) = enqueue_notification(&self.db_pool, notification).await {
+                warn!("Failed to queue broken promise notice {}: {}", promise_id, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn process_overdue(&self, today: NaiveDate, summary: &mut DunningRunSummary) -> DunningResult<()> {
+        let invoices = sqlx::query_as::<_, OverdueInvoice>(
+            r#"
+            SELECT i.id as invoice_id, i.client_id, c.name as client_name, i.number, i.currency, i.due_date,
+                   s.id as sequence_id,
+                   COALESCE((SELECT MAX(e.stage_number) FROM dunning_events e
+                             WHERE e.invoice_id = i.id AND e.sequence_id = s.id), 0) as last_stage
+            FROM invoices i
+            JOIN clients c ON c.id = i.client_id
+            LEFT JOIN contracts ct ON ct.id = i.contract_id
+            JOIN dunning_sequences s ON s.id = COALESCE(ct.dunning_sequence_id, c.dunning_sequence_id,
+                                                        (SELECT id FROM dunning_sequences WHERE is_default))
+            WHERE s.is_active
+              AND i.status IN ('sent', 'partial', 'overdue')
+              AND COALESCE(i.balance, 0) > 0
+              AND i.due_date < $1
+              AND NOT EXISTS (
+                  SELECT 1 FROM payment_promises p
+                  WHERE p.status = 'open' AND p.promised_date >= $1 AND p.client_id = i.client_id
+                    AND (p.invoice_id IS NULL OR p.invoice_id = i.id)
+              )
+            ORDER BY i.due_date, i.number
+            "#,
+        )
+        .bind(today)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let mut stages_by_sequence: HashMap<Uuid, Vec<DunningStage>> = HashMap::new();
+        for invoice in invoices {
+            if !stages_by_sequence.contains_key(&invoice.sequence_id) {
+                let stages = load_stages(&self.db_pool, invoice.sequence_id).await?;
+                stages_by_sequence.insert(invoice.sequence_id, stages);
+            }
+
+            let days_overdue = (today - invoice.due_date).num_days();
+            let Some(stage) = next_stage(&stages_by_sequence[&invoice.sequence_id], invoice.last_stage, days_overdue)
+            else {
+                continue;
+            };
+
+            if let Err(e) = self.apply_stage(&invoice, stage, days_overdue, summary).await {
+                error!("Failed to apply dunning stage {} to invoice {}: {}", stage.stage_number, invoice.number, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn apply_stage(
+        &self,
+        invoice: &OverdueInvoice,
+        stage: &DunningStage,
+        days_overdue: i64,
+        summary: &mut DunningRunSummary,
+    ) -> DunningResult<()> {
+        let mut tx = self.db_pool.begin().await?;
+
+        // Claimed first, so an overlapping run can't apply the same stage twice
+        let event_id: Option<Uuid> = sqlx::query_scalar(
+            "INSERT INTO dunning_events (invoice_id, sequence_id, stage_number, days_overdue)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (invoice_id, sequence_id, stage_number) DO NOTHING
+             RETURNING id",
+        )
+        .bind(invoice.invoice_id)
+        .bind(invoice.sequence_id)
+        .bind(stage.stage_number)
+        .bind(days_overdue as i32)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(event_id) = event_id else {
+            return Ok(());
+        };
+
+        let Some(balance) = credit_notes::lock_invoice(&mut tx, invoice.invoice_id).await? else {
+            return Ok(());
+        };
+        if balance.is_void() || balance.balance <= Decimal::ZERO {
+            return Ok(());
+        }
+
+        let mut late_fee = Decimal::ZERO;
+        if let Some(rule_id) = stage.late_fee_rule_id {
+            let rule = sqlx::query_as::<_, LateFeeRule>(&format!(
+                "SELECT {} FROM billing_automation_rules
+                 WHERE id = $1 AND action_type = 'apply_late_fee' AND COALESCE(is_active, true)",
+                LATE_FEE_RULE_COLUMNS
+            ))
+            .bind(rule_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            if let Some((rule, fee)) = rule
+                .filter(|r| r.applies_to(invoice.client_id))
+                .and_then(|r| r.fee().filter(LateFee::is_valid).map(|f| (r, f)))
+            {
+                late_fee = fee.amount_for(balance.balance, &invoice.currency);
+                if late_fee > Decimal::ZERO {
+                    apply_late_fee(&mut tx, &balance, &rule, &fee, late_fee).await?;
+                    summary.late_fees_applied += 1;
+                }
+            }
+        }
+
+        sqlx::query("UPDATE invoices SET status = 'overdue', updated_at = NOW() WHERE id = $1 AND status = 'sent'")
+            .bind(invoice.invoice_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("UPDATE dunning_events SET late_fee_amount = $2, credit_hold_placed = $3 WHERE id = $1")
+            .bind(event_id)
+            .bind(late_fee)
+            .bind(stage.place_credit_hold)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        summary.stages_applied += 1;
+
+        if stage.place_credit_hold {
+            let reason = format!("Invoice {} is {} days overdue", invoice.number, days_overdue);
+            if place_credit_hold(&self.db_pool, invoice.client_id, &reason, "dunning").await? {
+                summary.credit_holds_placed += 1;
+                info!("Placed {} on credit hold: {}", invoice.client_name, reason);
+            }
+        }
+
+        let amount_due = balance.balance + late_fee;
+        match self.send_reminder(invoice, stage, days_overdue, amount_due, late_fee).await {
+            Ok(Some(contact_id)) => {
+                summary.reminders_sent += 1;
+                sqlx::query("UPDATE dunning_events SET contact_id = $2 WHERE id = $1")
+                    .bind(event_id)
+                    .bind(contact_id)
+                    .execute(&self.db_pool)
+                    .await?;
+            }
+            Ok(None) => warn!("No billing contact to send dunning reminder for invoice {}", invoice.number),
+            Err(e) => warn!("Failed to queue dunning reminder for invoice {}: {}", invoice.number, e),
+        }
+
+        Ok(())
+    }
+
+    async fn send_reminder(
+        &self,
+        invoice: &OverdueInvoice,
+        stage: &DunningStage,
+        days_overdue: i64,
+        amount_due: Decimal,
+        late_fee: Decimal,
+    ) -> DunningResult<Option<Uuid>> {
+        let contact_id: Option<Uuid> = sqlx::query_scalar(
+            "SELECT id FROM contacts WHERE client_id = $1 AND archived_at IS NULL AND email IS NOT NULL
+             ORDER BY is_primary DESC NULLS LAST, created_at LIMIT 1",
+        )
+        .bind(invoice.client_id)
+        .fetch_optional(&self.db_pool)
+        .await?;
+        let Some(contact_id) = contact_id else {
+            return Ok(None);
+        };
+
+        let variables = [
+            ("client_name", invoice.client_name.clone()),
+            ("invoice_number", invoice.number.clone()),
+            ("amount_due", format_money(amount_due, &invoice.currency)),
+            ("due_date", invoice.due_date.format("%Y-%m-%d").to_string()),
+            ("days_overdue", days_overdue.to_string()),
+            ("late_fee", format_money(late_fee, &invoice.currency)),
+            ("pay_url", payments::portal_invoice_url(invoice.invoice_id)),
+        ];
+
+        let notification = QueuedNotification::for_contact(
+            contact_id,
+            "dunning_reminder",
+            render_template(&stage.subject_template, &variables),
+            render_template(&stage.body_template, &variables),
+        )
+        .with_priority(&stage.priority)
+        .with_entity("invoice", invoice.invoice_id)
+        .with_variables(serde_json::json!({
+            "invoice_number": invoice.number,
+            "stage_number": stage.stage_number,
+            "days_overdue": days_overdue,
+            "amount_due": amount_due,
+            "late_fee": late_fee,
+            "currency": invoice.currency,
+        }));
+        enqueue_notification(&self.db_pool, notification).await?;
+
+        Ok(Some(contact_id))
+    }
+
+    /// Lifts holds placed by dunning once none of the invoices that caused one
+    /// still has a balance. Manual holds stay until someone releases them.
+    async fn release_settled_holds(&self) -> DunningResult<u32> {
+        let released: Vec<(Uuid, String)> = sqlx::query_as(
+            r#"
+            UPDATE clients c SET credit_hold = false, credit_hold_reason = NULL, credit_hold_source = NULL,
+                   credit_hold_at = NULL, updated_at = NOW()
+            WHERE c.credit_hold AND c.credit_hold_source = 'dunning'
+              AND NOT EXISTS (
+                  SELECT 1 FROM dunning_events e
+                  JOIN invoices i ON i.id = e.invoice_id
+                  WHERE i.client_id = c.id AND e.credit_hold_placed
+                    AND COALESCE(i.balance, 0) > 0 AND COALESCE(i.status, '') <> 'void'
+              )
+            RETURNING c.id, c.name
+            "#,
+        )
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        for (_, name) in &released {
+            info!("Released credit hold for {}", name);
+        }
+
+        Ok(released.len() as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(stage_number: i32, days_overdue: i32) -> DunningStage {
+        DunningStage {
+            id: Uuid::new_v4(),
+            sequence_id: Uuid::nil(),
+            stage_number,
+            days_overdue,
+            subject_template: String::new(),
+            body_template: String::new(),
+            priority: "normal".to_string(),
+            late_fee_rule_id: None,
+            place_credit_hold: false,
+        }
+    }
+
+    #[test]
+    fn test_next_stage_progression() {
+        let stages = vec![stage(1, 1), stage(2, 7), stage(3, 14), stage(4, 30)];
+
+        assert!(next_stage(&stages, 0, 0).is_none());
+        assert_eq!(next_stage(&stages, 0, 1).map(|s| s.stage_number), Some(1));
+        assert!(next_stage(&stages, 1, 6).is_none());
+        assert_eq!(next_stage(&stages, 1, 8).map(|s| s.stage_number), Some(2));
+        // A late start jumps straight to the furthest stage reached
+        assert_eq!(next_stage(&stages, 0, 45).map(|s| s.stage_number), Some(4));
+        assert!(next_stage(&stages, 4, 90).is_none());
+    }
+
+    #[test]
+    fn test_render_template() {
+        let text = render_template(
+            "Invoice {{invoice_number}} is {{days_overdue}} days overdue, {{unknown}}",
+            &[("invoice_number", "INV-7".to_string()), ("days_overdue", "14".to_string())],
+        );
+        assert_eq!(text, "Invoice INV-7 is 14 days overdue, {{unknown}}");
+    }
+
+    #[test]
+    fn test_late_fee_amounts() {
+        let percentage = LateFee {
+            fee_type: "percentage".to_string(),
+            amount: Decimal::new(15, 1),
+            minimum_fee: Some(Decimal::from(25)),
+            maximum_fee: Some(Decimal::from(100)),
+            description: None,
+        };
+        assert!(percentage.is_valid());
+        assert_eq!(percentage.amount_for(Decimal::from(1000), "USD"), Decimal::from(25));
+        assert_eq!(percentage.amount_for(Decimal::new(300033, 2), "USD"), Decimal::new(4500, 2));
+        assert_eq!(percentage.amount_for(Decimal::from(50000), "USD"), Decimal::from(100));
+        assert_eq!(percentage.amount_for(Decimal::from(3333), "JPY"), Decimal::from(50));
+
+        let flat = LateFee { fee_type: "flat".to_string(), amount: Decimal::from(35), ..percentage.clone() };
+        assert_eq!(flat.amount_for(Decimal::from(10), "USD"), Decimal::from(35));
+
+        let inverted = LateFee { minimum_fee: Some(Decimal::from(200)), ..percentage };
+        assert!(!inverted.is_valid());
+    }
+
+    #[test]
+    fn test_validate_stages() {
+        assert!(validate_stages(&[(1, 1), (2, 7), (3, 30)]));
+        assert!(validate_stages(&[(2, 7), (1, 0)]));
+        assert!(!validate_stages(&[(1, 1), (3, 7)]));
+        assert!(!validate_stages(&[(1, 7), (2, 7)]));
+        assert!(!validate_stages(&[(1, 14), (2, 7)]));
+    }
+}
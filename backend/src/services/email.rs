@@ -1,6 +1,6 @@
 use crate::config::SmtpConfig;
 use lettre::{
-    message::{header::ContentType, Mailbox},
+    message::{header::ContentType, Attachment, Mailbox, MultiPart, SinglePart},
     transport::smtp::{authentication::Credentials, PoolConfig},
     AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
 };
@@ -22,6 +22,14 @@ pub struct EmailTemplate {
     pub text_body: Option<String>,
 }
 
+/// A file sent along with an email, e.g. a quote or statement PDF.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailAttachment {
+    pub filename: String,
+    pub content_type: String,
+    pub file_path: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TicketNotificationTemplate {
     pub ticket_number: i32,
@@ -71,32 +79,95 @@ impl EmailService {
             to_email.parse::<Mailbox>()?
         };
 
-        let mut message_builder = Message::builder()
+        let message_builder = Message::builder()
             .from(from)
             .to(to)
             .subject(subject);
 
-        if let Some(text) = text_body {
-            message_builder = message_builder
+        let message = if let Some(text) = text_body {
+            message_builder
                 .multipart(
-                    lettre::message::MultiPart::alternative()
+                    MultiPart::alternative()
                         .singlepart(
-                            lettre::message::SinglePart::builder()
+                            SinglePart::builder()
                                 .header(ContentType::TEXT_PLAIN)
                                 .body(text.to_string()),
                         )
                         .singlepart(
-                            lettre::message::SinglePart::builder()
+                            SinglePart::builder()
                                 .header(ContentType::TEXT_HTML)
                                 .body(html_body.to_string()),
                         ),
-                )?;
+                )?
+        } else {
+            message_builder.body(html_body.to_string())?
+        };
+
+        self.deliver(message, to_email).await
+    }
+
+    pub async fn send_email_with_attachments(
+        &self,
+        to_email: &str,
+        to_name: Option<&str>,
+        subject: &str,
+        html_body: &str,
+        text_body: Option<&str>,
+        attachments: &[EmailAttachment],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if attachments.is_empty() {
+            return self.send_email(to_email, to_name, subject, html_body, text_body).await;
+        }
+
+        let from = format!("{} <{}>", self.from_name, self.from_email)
+            .parse::<Mailbox>()?;
+
+        let to = if let Some(name) = to_name {
+            format!("{} <{}>", name, to_email).parse::<Mailbox>()?
         } else {
-            message_builder = message_builder.body(html_body.to_string())?;
+            to_email.parse::<Mailbox>()?
+        };
+
+        let mut body = MultiPart::alternative().singlepart(
+            SinglePart::builder()
+                .header(ContentType::TEXT_HTML)
+                .body(html_body.to_string()),
+        );
+        if let Some(text) = text_body {
+            body = MultiPart::alternative()
+                .singlepart(
+                    SinglePart::builder()
+                        .header(ContentType::TEXT_PLAIN)
+                        .body(text.to_string()),
+                )
+                .singlepart(
+                    SinglePart::builder()
+                        .header(ContentType::TEXT_HTML)
+                        .body(html_body.to_string()),
+                );
         }
 
-        let message = message_builder;
+        let mut mixed = MultiPart::mixed().multipart(body);
+        for attachment in attachments {
+            let data = tokio::fs::read(&attachment.file_path).await?;
+            let content_type = ContentType::parse(&attachment.content_type)?;
+            mixed = mixed.singlepart(Attachment::new(attachment.filename.clone()).body(data, content_type));
+        }
 
+        let message = Message::builder()
+            .from(from)
+            .to(to)
+            .subject(subject)
+            .multipart(mixed)?;
+
+        self.deliver(message, to_email).await
+    }
+
+    async fn deliver(
+        &self,
+        message: Message,
+        to_email: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         match self.transport.send(message).await {
             Ok(_) => {
                 info!("Email sent successfully to {}", to_email);
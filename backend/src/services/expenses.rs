@@ -0,0 +1,156 @@
+use crate::services::currency;
+use crate::services::recurring_billing::{round_money, DraftLine};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use sqlx::{FromRow, Postgres, Transaction};
+use uuid::Uuid;
+
+type ExpenseResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Columns for `ghosthub_shared::Expense`, read from `expenses e`.
+pub const EXPENSE_COLUMNS: &str = "e.id, e.client_id, e.project_id, e.ticket_id, e.category, e.category_id,
+    e.vendor, e.vendor_id, e.description, e.amount, e.currency, COALESCE(e.tax_amount, 0) as tax_amount, e.expense_date,
+    COALESCE(e.is_billable, false) as is_billable, COALESCE(e.is_reimbursable, false) as is_reimbursable,
+    COALESCE(e.markup_percent, 0) as markup_percent, e.billed_amount, e.invoice_id, e.payment_method,
+    e.receipt_file_id, COALESCE(e.status, 'pending') as status,
+    COALESCE(e.requires_approval, false) as requires_approval, e.approved_by, e.approved_at,
+    e.approval_notes, e.created_by, COALESCE(e.created_at, NOW()) as created_at, e.updated_at";
+
+pub const CATEGORY_COLUMNS: &str = "id, name, description, COALESCE(tax_deductible, false) as tax_deductible,
+    markup_percent, billable_by_default, approval_threshold, tax_category, is_active, COALESCE(created_at, NOW()) as created_at";
+
+#[derive(Debug, FromRow)]
+struct UnbilledExpense {
+    id: Uuid,
+    expense_date: NaiveDate,
+    vendor: String,
+    description: String,
+    billed_amount: Decimal,
+    currency: String,
+    tax_category: Option<String>,
+}
+
+/// Amount charged to the client for an expense.
+pub fn billable_amount(amount: Decimal, markup_percent: Decimal) -> Decimal {
+    round_money(amount * (Decimal::ONE + markup_percent / Decimal::from(100)))
+}
+
+/// Expenses at or under the category threshold are approved on entry;
+/// categories without a threshold always need review.
+pub fn needs_approval(amount: Decimal, approval_threshold: Option<Decimal>) -> bool {
+    approval_threshold.is_none_or(|threshold| amount > threshold)
+}
+
+/// Approved billable expenses for a client that haven't been invoiced yet,
+/// as invoice lines in the client's currency. Expenses paid in another
+/// currency are converted at the rate on the expense date. Rows are locked
+/// until the caller's transaction ends.
+pub async fn unbilled_lines(
+    tx: &mut Transaction<'_, Postgres>,
+    client_id: Uuid,
+    through: NaiveDate,
+) -> ExpenseResult<Vec<(DraftLine, Uuid)>> {
+    let expenses = sqlx::query_as::<_, UnbilledExpense>(
+        r#"
+        SELECT e.id, e.expense_date, e.vendor, e.description,
+               COALESCE(e.billed_amount, ROUND(e.amount * (1 + COALESCE(e.markup_percent, 0) / 100), 2)) as billed_amount,
+               e.currency, ec.tax_category
+        FROM expenses e
+        LEFT JOIN expense_categories ec ON ec.id = e.category_id
+        WHERE e.client_id = $1 AND e.is_billable = true AND e.invoice_id IS NULL
+          AND e.status IN ('approved', 'reimbursed') AND e.expense_date <= $2
+          AND e.written_off_at IS NULL AND (e.deferred_until IS NULL OR e.deferred_until <= $2)
+        ORDER BY e.expense_date
+        FOR UPDATE OF e
+        "#,
+    )
+    .bind(client_id)
+    .bind(through)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let client_currency = currency::client_currency(&mut **tx, client_id).await?;
+
+    let mut lines = Vec::with_capacity(expenses.len());
+    for expense in expenses {
+        let mut description = format!(
+            "{} - {} ({})",
+            expense.expense_date.format("%Y-%m-%d"),
+            expense.description,
+            expense.vendor
+        );
+        let mut unit_price = expense.billed_amount;
+
+        if expense.currency != client_currency {
+            let Some((rate, _)) =
+                currency::rate_on(&mut **tx, &expense.currency, &client_currency, expense.expense_date).await?
+            else {
+                return Err(format!(
+                    "No {} to {} exchange rate for expense {} on {}",
+                    expense.currency, client_currency, expense.id, expense.expense_date
+                )
+                .into());
+            };
+            description.push_str(&format!(
+                ", {} at {}",
+                currency::format_money(expense.billed_amount, &expense.currency),
+                rate.normalize()
+            ));
+            unit_price = currency::convert(expense.billed_amount, rate);
+        }
+
+        let line = DraftLine {
+            description,
+            quantity: Decimal::ONE,
+            unit_price,
+            tax_rate: None,
+            tax_category: expense.tax_category,
+            source_type: "expense".to_string(),
+            source_id: Some(expense.id),
+            period_start: Some(expense.expense_date),
+            period_end: Some(expense.expense_date),
+            metric: None,
+        };
+        lines.push((line, expense.id));
+    }
+
+    Ok(lines)
+}
+
+/// Records the invoice on each expense along with what was charged, in the
+/// invoice currency.
+pub async fn mark_billed(
+    tx: &mut Transaction<'_, Postgres>,
+    lines: &[(DraftLine, Uuid)],
+    invoice_id: Uuid,
+) -> ExpenseResult<()> {
+    for (line, expense_id) in lines {
+        sqlx::query("UPDATE expenses SET invoice_id = $2, billed_amount = $3, updated_at = NOW() WHERE id = $1")
+            .bind(expense_id)
+            .bind(invoice_id)
+            .bind(line.line_total())
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_billable_amount_applies_markup() {
+        assert_eq!(billable_amount(Decimal::from(200), Decimal::from(15)), Decimal::from(230));
+        assert_eq!(billable_amount(Decimal::new(9999, 2), Decimal::ZERO), Decimal::new(9999, 2));
+        assert_eq!(billable_amount(Decimal::new(1999, 2), Decimal::new(125, 1)), Decimal::new(2249, 2));
+    }
+
+    #[test]
+    fn test_needs_approval() {
+        assert!(needs_approval(Decimal::from(10), None));
+        assert!(!needs_approval(Decimal::from(100), Some(Decimal::from(100))));
+        assert!(needs_approval(Decimal::new(10001, 2), Some(Decimal::from(100))));
+    }
+}
@@ -0,0 +1,177 @@
+use crate::services::currency::format_money;
+use crate::services::pdf::{Column, PdfDocument};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+pub type InvoicePdfResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// What an invoice PDF shows, read once for staff and portal downloads alike.
+#[derive(Debug, Clone, FromRow)]
+pub struct InvoiceSummary {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub number: String,
+    pub date: NaiveDate,
+    pub due_date: NaiveDate,
+    pub status: String,
+    pub payment_terms: Option<String>,
+    pub subtotal: Decimal,
+    pub discount_amount: Option<Decimal>,
+    pub tax_amount: Decimal,
+    pub total: Decimal,
+    pub balance: Decimal,
+    pub currency: String,
+    pub notes: Option<String>,
+    pub terms: Option<String>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct InvoicePdfLine {
+    pub description: String,
+    pub quantity: Decimal,
+    pub unit_price: Decimal,
+    pub line_total: Decimal,
+}
+
+/// Loads the invoice and its lines, leaving out voided lines.
+pub async fn load(db_pool: &PgPool, invoice_id: Uuid) -> InvoicePdfResult<Option<(InvoiceSummary, Vec<InvoicePdfLine>)>> {
+    let invoice = sqlx::query_as::<_, InvoiceSummary>(
+        r#"
+        SELECT i.id, i.client_id, c.name as client_name, i.number, i.date, i.due_date,
+               COALESCE(i.status, 'draft') as status, i.payment_terms,
+               i.subtotal, i.discount_amount, i.tax_amount, i.total, i.balance, i.currency, i.notes, i.terms
+        FROM invoices i
+        JOIN clients c ON c.id = i.client_id
+        WHERE i.id = $1
+        "#,
+    )
+    .bind(invoice_id)
+    .fetch_optional(db_pool)
+    .await?;
+
+    let Some(invoice) = invoice else {
+        return Ok(None);
+    };
+
+    let lines = sqlx::query_as::<_, InvoicePdfLine>(
+        "SELECT description, quantity, unit_price, line_total
+         FROM invoice_line_items
+         WHERE invoice_id = $1 AND voided_at IS NULL
+         ORDER BY created_at",
+    )
+    .bind(invoice_id)
+    .fetch_all(db_pool)
+    .await?;
+
+    Ok(Some((invoice, lines)))
+}
+
+pub fn render_pdf(invoice: &InvoiceSummary, lines: &[InvoicePdfLine]) -> Vec<u8> {
+    let money = |amount: Decimal| format_money(amount, &invoice.currency);
+
+    let mut doc = PdfDocument::new(&format!("Invoice {}", invoice.number));
+    doc.heading(&format!("Invoice {}", invoice.number));
+    if invoice.status == "void" {
+        doc.subheading("VOID");
+    }
+    doc.spacer(6.0)
+        .text(&format!("Bill to: {}", invoice.client_name))
+        .text(&format!("Date: {}", invoice.date.format("%Y-%m-%d")))
+        .text(&format!("Due: {}", invoice.due_date.format("%Y-%m-%d")));
+    if let Some(terms) = invoice.payment_terms.as_deref().filter(|t| !t.is_empty()) {
+        doc.text(&format!("Payment terms: {}", terms));
+    }
+    doc.spacer(12.0);
+
+    let row = |doc: &mut PdfDocument, cells: [&str; 4], bold: bool| {
+        doc.row(
+            &[
+                Column { text: cells[0], x: 0.0, right_align: false },
+                Column { text: cells[1], x: 370.0, right_align: true },
+                Column { text: cells[2], x: 440.0, right_align: true },
+                Column { text: cells[3], x: 512.0, right_align: true },
+            ],
+            bold,
+        );
+    };
+
+    row(&mut doc, ["Description", "Qty", "Price", "Total"], true);
+    for line in lines {
+        let description: String = line.description.chars().take(60).collect();
+        row(
+            &mut doc,
+            [
+                &description,
+                &line.quantity.normalize().to_string(),
+                &money(line.unit_price),
+                &money(line.line_total),
+            ],
+            false,
+        );
+    }
+    doc.spacer(8.0);
+
+    row(&mut doc, ["Subtotal", "", "", &money(invoice.subtotal)], false);
+    if let Some(discount) = invoice.discount_amount.filter(|d| !d.is_zero()) {
+        row(&mut doc, ["Discount", "", "", &money(-discount)], false);
+    }
+    row(&mut doc, ["Tax", "", "", &money(invoice.tax_amount)], false);
+    row(&mut doc, ["Total", "", "", &money(invoice.total)], true);
+    let received = invoice.total - invoice.balance;
+    if !received.is_zero() && invoice.status != "void" {
+        row(&mut doc, ["Payments and credits", "", "", &money(-received)], false);
+        row(&mut doc, ["Balance due", "", "", &money(invoice.balance)], true);
+    }
+
+    if let Some(terms) = invoice.terms.as_deref().filter(|t| !t.is_empty()) {
+        doc.spacer(12.0).subheading("Terms").text(terms);
+    }
+    if let Some(notes) = invoice.notes.as_deref().filter(|n| !n.is_empty()) {
+        doc.spacer(12.0).subheading("Notes").text(notes);
+    }
+
+    doc.to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_pdf_shows_lines_and_balance() {
+        let invoice = InvoiceSummary {
+            id: Uuid::new_v4(),
+            client_id: Uuid::new_v4(),
+            client_name: "Acme (East)".to_string(),
+            number: "INV-0042".to_string(),
+            date: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            due_date: NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(),
+            status: "partial".to_string(),
+            payment_terms: Some("Net 30".to_string()),
+            subtotal: Decimal::from(300),
+            discount_amount: None,
+            tax_amount: Decimal::ZERO,
+            total: Decimal::from(300),
+            balance: Decimal::from(100),
+            currency: "USD".to_string(),
+            notes: None,
+            terms: None,
+        };
+        let lines = vec![InvoicePdfLine {
+            description: "Managed services".to_string(),
+            quantity: Decimal::from(3),
+            unit_price: Decimal::from(100),
+            line_total: Decimal::from(300),
+        }];
+
+        let pdf = String::from_utf8_lossy(&render_pdf(&invoice, &lines)).into_owned();
+        assert!(pdf.starts_with("%PDF-1.4"));
+        assert!(pdf.contains("(Invoice INV-0042)"));
+        assert!(pdf.contains("Bill to: Acme \\(East\\)"));
+        assert!(pdf.contains("(Managed services)"));
+        assert!(pdf.contains("(Balance due)"));
+    }
+}
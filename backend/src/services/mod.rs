@@ -3,9 +3,46 @@ pub mod email_processor;
 pub mod bms_workflows;
 pub mod password_manager;
 pub mod encryption;
+pub mod notification_queue;
+pub mod sms;
+pub mod recurring_billing;
+pub mod billing_metrics;
+pub mod prepaid_blocks;
+pub mod rate_cards;
+pub mod pdf;
+pub mod quotes;
+pub mod invoice_pdf;
+pub mod credit_notes;
+pub mod expenses;
+pub mod sales_tax;
+pub mod currency;
+pub mod payments;
+pub mod statements;
+pub mod dunning;
+pub mod accounting;
+pub mod profitability;
+pub mod billing_review;
+pub mod timesheets;
+pub mod timer_reconciliation;
+pub mod project_schedule;
+pub mod project_budgets;
+pub mod dispatch;
 
 pub use email::EmailService;
 pub use email_processor::{EmailProcessor, EmailProcessorConfig};
 pub use bms_workflows::{BmsWorkflowService, BmsWorkflowConfig};
 pub use password_manager::PasswordManagerService;
-pub use encryption::EncryptionService;
\ No newline at end of file
+pub use encryption::EncryptionService;
+pub use notification_queue::{NotificationQueueService, NotificationQueueConfig};
+pub use recurring_billing::{RecurringBillingService, RecurringBillingConfig};
+pub use prepaid_blocks::{PrepaidBlockService, PrepaidBlockConfig};
+pub use quotes::{QuoteService, QuoteConfig};
+pub use currency::{ExchangeRateService, ExchangeRateConfig};
+pub use payments::{PaymentCollectionService, PaymentCollectionConfig};
+pub use statements::{StatementService, StatementConfig};
+pub use dunning::{DunningService, DunningConfig};
+pub use accounting::{AccountingSyncService, AccountingSyncConfig};
+pub use profitability::{ProfitabilityService, ProfitabilityConfig};
+pub use timesheets::{TimesheetService, TimesheetConfig};
+pub use timer_reconciliation::{TimerReconciliationService, TimerReconciliationConfig};
+pub use project_budgets::{ProjectBudgetService, ProjectBudgetConfig};
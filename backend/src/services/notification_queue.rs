@@ -0,0 +1,845 @@
+use crate::services::sms::{OutboundSms, SmsService};
+use crate::services::email::EmailAttachment;
+use crate::services::EmailService;
+use crate::AppState;
+use chrono::{DateTime, Duration as ChronoDuration, NaiveTime, Timelike, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::sync::Arc;
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct NotificationQueueConfig {
+    pub poll_interval_seconds: u64, // How often the queue is polled for due items
+    pub batch_size: i64,            // Items claimed per poll
+    pub base_retry_seconds: i64,    // First retry delay, doubled on each failure
+    pub max_retry_seconds: i64,     // Upper bound for the retry delay
+    pub stale_lock_minutes: i64,    // Claimed items older than this are released again
+}
+
+impl Default for NotificationQueueConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_seconds: 15,
+            batch_size: 50,
+            base_retry_seconds: 60,
+            max_retry_seconds: 60 * 60,
+            stale_lock_minutes: 10,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct NotificationQueueService {
+    config: NotificationQueueConfig,
+    app_state: Arc<AppState>,
+    email_service: EmailService,
+    sms_service: SmsService,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct QueuedItem {
+    pub id: Uuid,
+    pub recipient_type: String,
+    pub recipient_id: Uuid,
+    pub channel: String,
+    pub subject: Option<String>,
+    pub content: String,
+    pub metadata: Option<serde_json::Value>,
+    pub notification_type: Option<String>,
+    pub priority: Option<String>,
+    pub retry_count: Option<i32>,
+    pub max_attempts: Option<i32>,
+    pub digest_id: Option<Uuid>,
+}
+
+#[derive(Debug, FromRow)]
+struct RecipientSchedule {
+    local_time: NaiveTime,
+    local_dow: i32,
+    digest_frequency: Option<String>,
+    quiet_hours_start: Option<NaiveTime>,
+    quiet_hours_end: Option<NaiveTime>,
+    weekend_notifications: Option<bool>,
+    utc_offset_seconds: i64,
+}
+
+#[derive(Debug, FromRow)]
+struct RecipientAddress {
+    email: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(Debug, FromRow)]
+struct RecipientPhone {
+    phone: Option<String>,
+    client_id: Option<Uuid>,
+}
+
+#[derive(Debug, FromRow)]
+struct DueDigest {
+    id: Uuid,
+    recipient_type: String,
+    recipient_id: Uuid,
+    frequency: String,
+    retry_count: Option<i32>,
+}
+
+impl NotificationQueueService {
+    pub fn new(
+        config: NotificationQueueConfig,
+        app_state: Arc<AppState>,
+        email_service: EmailService,
+    ) -> Self {
+        let sms_service = SmsService::new(app_state.db_pool.clone());
+
+        Self {
+            config,
+            app_state,
+            email_service,
+            sms_service,
+        }
+    }
+
+    pub async fn start(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        info!("Starting notification queue worker");
+
+        let mut poll_interval = interval(Duration::from_secs(self.config.poll_interval_seconds));
+
+        tokio::spawn({
+            let service = self.clone();
+            async move {
+                loop {
+                    poll_interval.tick().await;
+
+                    if let Err(e) = service.run_once().await {
+                        error!("Error processing notification queue: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn run_once(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.release_stale_locks().await?;
+
+        let processed = self.process_due_notifications().await?;
+        if processed > 0 {
+            info!("Processed {} queued notifications", processed);
+        }
+
+        self.flush_due_digests().await?;
+
+        Ok(())
+    }
+
+    // Items left in 'processing' by a crashed worker go back to the queue
+    async fn release_stale_locks(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let released = sqlx::query(
+            r#"
+            UPDATE notification_queue
+            SET status = 'pending', locked_at = NULL
+            WHERE status = 'processing'
+            AND locked_at < NOW() - ($1 || ' minutes')::INTERVAL
+            "#
+        )
+        .bind(self.config.stale_lock_minutes.to_string())
+        .execute(&self.app_state.db_pool)
+        .await?;
+
+        if released.rows_affected() > 0 {
+            warn!("Released {} stale notification queue locks", released.rows_affected());
+        }
+
+        Ok(())
+    }
+
+    pub async fn process_due_notifications(&self) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        let items = sqlx::query_as::<_, QueuedItem>(
+            r#"
+            UPDATE notification_queue
+            SET status = 'processing', locked_at = NOW()
+            WHERE id IN (
+                SELECT id FROM notification_queue
+                WHERE status = 'pending' AND scheduled_for <= NOW()
+                ORDER BY
+                    CASE priority
+                        WHEN 'critical' THEN 0
+                        WHEN 'high' THEN 1
+                        WHEN 'normal' THEN 2
+                        ELSE 3
+                    END,
+                    scheduled_for
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, recipient_type, recipient_id, channel, subject, content, metadata,
+                      notification_type, priority, retry_count, max_attempts, digest_id
+            "#
+        )
+        .bind(self.config.batch_size)
+        .fetch_all(&self.app_state.db_pool)
+        .await?;
+
+        let count = items.len();
+
+        for item in items {
+            if let Err(e) = self.handle_item(&item).await {
+                error!("Failed to handle queued notification {}: {}", item.id, e);
+            }
+        }
+
+        Ok(count)
+    }
+
+    async fn handle_item(&self, item: &QueuedItem) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let priority = item.priority.as_deref().unwrap_or("normal");
+
+        // Quiet hours, weekends and digests only apply to staff with preferences on file, and
+        // only hold back channels that interrupt; in-app and websocket updates go out immediately
+        if item.recipient_type == "user" && priority != "critical" && is_interruptive(&item.channel) {
+            if let Some(schedule) = self.load_recipient_schedule(item.recipient_id).await? {
+                if let Some(delay_seconds) = deferral_seconds(&schedule) {
+                    return self.reschedule(item, delay_seconds).await;
+                }
+
+                if priority == "low" && item.channel == "email" {
+                    if let Some(frequency) = schedule.digest_frequency.as_deref() {
+                        if (frequency == "hourly" || frequency == "daily")
+                            && self.add_to_digest(item, frequency, schedule.utc_offset_seconds).await?
+                        {
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        }
+
+        match self.deliver(item).await {
+            Ok(()) => self.mark_sent(item.id).await,
+            Err(e) => self.mark_failed(item, &e.to_string()).await,
+        }
+    }
+
+    async fn load_recipient_schedule(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Option<RecipientSchedule>, Box<dyn std::error::Error + Send + Sync>> {
+        let schedule = sqlx::query_as::<_, RecipientSchedule>(
+            r#"
+            SELECT
+                (NOW() AT TIME ZONE tz.name)::TIME as local_time,
+                EXTRACT(ISODOW FROM NOW() AT TIME ZONE tz.name)::INTEGER as local_dow,
+                np.digest_frequency,
+                np.quiet_hours_start,
+                np.quiet_hours_end,
+                np.weekend_notifications,
+                EXTRACT(EPOCH FROM (NOW() AT TIME ZONE tz.name) - (NOW() AT TIME ZONE 'UTC'))::BIGINT as utc_offset_seconds
+            FROM users u
+            JOIN notification_preferences np ON np.user_id = u.id
+            CROSS JOIN LATERAL (SELECT COALESCE(NULLIF(u.timezone, ''), 'UTC') as name) tz
+            WHERE u.id = $1
+            LIMIT 1
+            "#
+        )
+        .bind(user_id)
+        .fetch_optional(&self.app_state.db_pool)
+        .await?;
+
+        Ok(schedule)
+    }
+
+    async fn reschedule(&self, item: &QueuedItem, delay_seconds: i64) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        sqlx::query(
+            r#"
+            UPDATE notification_queue
+            SET status = 'pending', locked_at = NULL,
+                scheduled_for = NOW() + ($2 || ' seconds')::INTERVAL
+            WHERE id = $1
+            "#
+        )
+        .bind(item.id)
+        .bind(delay_seconds.to_string())
+        .execute(&self.app_state.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Batches the item into the recipient's open digest for this period.
+    /// Returns false when that period's digest was already sent or gave up,
+    /// so the item is delivered on its own instead of being stranded.
+    async fn add_to_digest(
+        &self,
+        item: &QueuedItem,
+        frequency: &str,
+        utc_offset_seconds: i64,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let (period_start, period_end) = digest_period(Utc::now(), frequency, utc_offset_seconds);
+
+        let mut tx = self.app_state.db_pool.begin().await?;
+
+        let digest_id: Option<Uuid> = sqlx::query_scalar(
+            r#"
+            INSERT INTO notification_digests (
+                recipient_type, recipient_id, channel, frequency, period_start, period_end, item_count
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, 1)
+            ON CONFLICT (recipient_type, recipient_id, channel, frequency, period_start)
+            DO UPDATE SET item_count = notification_digests.item_count + 1
+            WHERE notification_digests.status = 'open'
+            RETURNING id
+            "#
+        )
+        .bind(&item.recipient_type)
+        .bind(item.recipient_id)
+        .bind(&item.channel)
+        .bind(frequency)
+        .bind(period_start)
+        .bind(period_end)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(digest_id) = digest_id else {
+            tx.rollback().await?;
+            return Ok(false);
+        };
+
+        sqlx::query(
+            "UPDATE notification_queue SET status = 'batched', locked_at = NULL, digest_id = $2 WHERE id = $1"
+        )
+        .bind(item.id)
+        .bind(digest_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn deliver(&self, item: &QueuedItem) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match item.channel.as_str() {
+            "email" => self.deliver_email(item).await,
+            "in_app" => self.deliver_in_app(item).await,
+            "sms" => self.deliver_sms(item).await,
+            "websocket" | "push" => {
+                self.deliver_websocket(item).await;
+                Ok(())
+            }
+            other => Err(format!("Unsupported notification channel: {}", other).into()),
+        }
+    }
+
+    async fn load_recipient_address(
+        &self,
+        recipient_type: &str,
+        recipient_id: Uuid,
+    ) -> Result<RecipientAddress, Box<dyn std::error::Error + Send + Sync>> {
+        let sql = match recipient_type {
+            "user" => "SELECT email, first_name || ' ' || last_name as name FROM users WHERE id = $1",
+            "contact" => "SELECT email, name FROM contacts WHERE id = $1",
+            other => return Err(format!("Unknown recipient type: {}", other).into()),
+        };
+
+        let address = sqlx::query_as::<_, RecipientAddress>(sql)
+            .bind(recipient_id)
+            .fetch_optional(&self.app_state.db_pool)
+            .await?
+            .ok_or("Recipient not found")?;
+
+        Ok(address)
+    }
+
+    async fn deliver_email(&self, item: &QueuedItem) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let address = self.load_recipient_address(&item.recipient_type, item.recipient_id).await?;
+        let email = address.email.filter(|e| !e.is_empty()).ok_or("Recipient has no email address")?;
+
+        let subject = item.subject.clone().unwrap_or_else(|| "GhostHub Notification".to_string());
+        let html_body = notification_email_html(&subject, &item.content);
+
+        let attachments: Vec<EmailAttachment> = item
+            .metadata
+            .as_ref()
+            .and_then(|m| m.get("attachments"))
+            .and_then(|a| serde_json::from_value(a.clone()).ok())
+            .unwrap_or_default();
+
+        self.email_service
+            .send_email_with_attachments(
+                &email,
+                address.name.as_deref(),
+                &subject,
+                &html_body,
+                Some(&item.content),
+                &attachments,
+            )
+            .await
+    }
+
+    async fn deliver_sms(&self, item: &QueuedItem) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let sql = match item.recipient_type.as_str() {
+            "user" => r#"
+                SELECT COALESCE(NULLIF(np.sms_number, ''), u.phone) as phone, NULL::UUID as client_id
+                FROM users u
+                LEFT JOIN notification_preferences np ON np.user_id = u.id
+                WHERE u.id = $1
+            "#,
+            "contact" => r#"
+                SELECT COALESCE(NULLIF(mobile, ''), phone) as phone, client_id
+                FROM contacts WHERE id = $1
+            "#,
+            other => return Err(format!("Unknown recipient type: {}", other).into()),
+        };
+
+        let recipient = sqlx::query_as::<_, RecipientPhone>(sql)
+            .bind(item.recipient_id)
+            .fetch_optional(&self.app_state.db_pool)
+            .await?
+            .ok_or("Recipient not found")?;
+        let phone = recipient.phone.filter(|p| !p.is_empty()).ok_or("Recipient has no phone number")?;
+        let (entity_type, entity_id) = metadata_entity(item.metadata.as_ref());
+
+        self.sms_service
+            .send(OutboundSms {
+                to_number: phone,
+                message: item.content.clone(),
+                client_id: recipient.client_id,
+                contact_id: (item.recipient_type == "contact").then_some(item.recipient_id),
+                ticket_id: entity_id.filter(|_| entity_type.as_deref() == Some("ticket")),
+                user_id: (item.recipient_type == "user").then_some(item.recipient_id),
+                notification_queue_id: Some(item.id),
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn deliver_in_app(&self, item: &QueuedItem) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let title = item.subject.clone().unwrap_or_else(|| "Notification".to_string());
+        let notification_type = item.notification_type.clone().unwrap_or_else(|| "general".to_string());
+        let (entity_type, entity_id) = metadata_entity(item.metadata.as_ref());
+
+        match item.recipient_type.as_str() {
+            "user" => {
+                crate::notifications::create_notification(
+                    &self.app_state.db_pool,
+                    item.recipient_id,
+                    title,
+                    item.content.clone(),
+                    notification_type,
+                    entity_type,
+                    entity_id,
+                ).await?;
+            }
+            "contact" => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO in_app_notifications (contact_id, type, title, message)
+                    VALUES ($1, $2, $3, $4)
+                    "#
+                )
+                .bind(item.recipient_id)
+                .bind(&notification_type)
+                .bind(&title)
+                .bind(&item.content)
+                .execute(&self.app_state.db_pool)
+                .await?;
+            }
+            other => return Err(format!("Unknown recipient type: {}", other).into()),
+        }
+
+        Ok(())
+    }
+
+    async fn deliver_websocket(&self, item: &QueuedItem) {
+        let (entity_type, entity_id) = metadata_entity(item.metadata.as_ref());
+        let payload = serde_json::json!({
+            "id": item.id,
+            "title": item.subject,
+            "message": item.content,
+            "notification_type": item.notification_type,
+            "priority": item.priority,
+            "entity_type": entity_type,
+            "entity_id": entity_id,
+        });
+
+        match item.recipient_type.as_str() {
+            "contact" => self.app_state.notify_contact(item.recipient_id, "notification", payload).await,
+            _ => self.app_state.notify_user(item.recipient_id, "notification", payload).await,
+        }
+    }
+
+    async fn mark_sent(&self, id: Uuid) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        sqlx::query(
+            r#"
+            UPDATE notification_queue
+            SET status = 'sent', sent_at = NOW(), last_attempt_at = NOW(),
+                locked_at = NULL, error_message = NULL
+            WHERE id = $1
+            "#
+        )
+        .bind(id)
+        .execute(&self.app_state.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn mark_failed(&self, item: &QueuedItem, error_message: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let attempts = item.retry_count.unwrap_or(0) + 1;
+        let max_attempts = item.max_attempts.unwrap_or(5);
+
+        if attempts >= max_attempts {
+            warn!("Notification {} failed permanently after {} attempts: {}", item.id, attempts, error_message);
+
+            sqlx::query(
+                r#"
+                UPDATE notification_queue
+                SET status = 'failed', retry_count = $2, error_message = $3,
+                    last_attempt_at = NOW(), locked_at = NULL
+                WHERE id = $1
+                "#
+            )
+            .bind(item.id)
+            .bind(attempts)
+            .bind(error_message)
+            .execute(&self.app_state.db_pool)
+            .await?;
+        } else {
+            let delay = retry_delay_seconds(
+                attempts,
+                self.config.base_retry_seconds,
+                self.config.max_retry_seconds,
+            );
+
+            sqlx::query(
+                r#"
+                UPDATE notification_queue
+                SET status = 'pending', retry_count = $2, error_message = $3,
+                    last_attempt_at = NOW(), locked_at = NULL,
+                    scheduled_for = NOW() + ($4 || ' seconds')::INTERVAL
+                WHERE id = $1
+                "#
+            )
+            .bind(item.id)
+            .bind(attempts)
+            .bind(error_message)
+            .bind(delay.to_string())
+            .execute(&self.app_state.db_pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn flush_due_digests(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let digests = sqlx::query_as::<_, DueDigest>(
+            r#"
+            SELECT id, recipient_type, recipient_id, frequency, retry_count
+            FROM notification_digests
+            WHERE status = 'open' AND period_end <= NOW()
+            ORDER BY period_end
+            LIMIT $1
+            "#
+        )
+        .bind(self.config.batch_size)
+        .fetch_all(&self.app_state.db_pool)
+        .await?;
+
+        for digest in digests {
+            if let Err(e) = self.send_digest(&digest).await {
+                error!("Failed to send notification digest {}: {}", digest.id, e);
+
+                let attempts = digest.retry_count.unwrap_or(0) + 1;
+                let status = if attempts >= 5 { "failed" } else { "open" };
+
+                sqlx::query(
+                    r#"
+                    UPDATE notification_digests
+                    SET retry_count = $2, status = $3, error_message = $4
+                    WHERE id = $1
+                    "#
+                )
+                .bind(digest.id)
+                .bind(attempts)
+                .bind(status)
+                .bind(e.to_string())
+                .execute(&self.app_state.db_pool)
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn send_digest(&self, digest: &DueDigest) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let items = sqlx::query_as::<_, QueuedItem>(
+            r#"
+            SELECT id, recipient_type, recipient_id, channel, subject, content, metadata,
+                   notification_type, priority, retry_count, max_attempts, digest_id
+            FROM notification_queue
+            WHERE digest_id = $1 AND status = 'batched'
+            ORDER BY created_at
+            "#
+        )
+        .bind(digest.id)
+        .fetch_all(&self.app_state.db_pool)
+        .await?;
+
+        if !items.is_empty() {
+            let address = self.load_recipient_address(&digest.recipient_type, digest.recipient_id).await?;
+            let email = address.email.filter(|e| !e.is_empty()).ok_or("Recipient has no email address")?;
+
+            let subject = format!(
+                "Your {} GhostHub digest ({} updates)",
+                digest.frequency,
+                items.len()
+            );
+
+            let text_body = items
+                .iter()
+                .map(|item| format!(
+                    "- {}\n  {}",
+                    item.subject.as_deref().unwrap_or("Notification"),
+                    item.content.replace('\n', "\n  ")
+                ))
+                .collect::<Vec<_>>()
+                .join("\n\n");
+
+            let html_body = notification_email_html(&subject, &text_body);
+
+            self.email_service
+                .send_email(&email, address.name.as_deref(), &subject, &html_body, Some(&text_body))
+                .await?;
+        }
+
+        let mut tx = self.app_state.db_pool.begin().await?;
+
+        sqlx::query(
+            "UPDATE notification_queue SET status = 'sent', sent_at = NOW() WHERE digest_id = $1 AND status = 'batched'"
+        )
+        .bind(digest.id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "UPDATE notification_digests SET status = 'sent', sent_at = NOW(), error_message = NULL WHERE id = $1"
+        )
+        .bind(digest.id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+/// Replaces `{{name}}` placeholders with values from a JSON object.
+/// Unknown placeholders are left untouched so missing data is visible.
+pub fn render_template(template: &str, variables: &serde_json::Value) -> String {
+    let mut rendered = template.to_string();
+
+    if let Some(map) = variables.as_object() {
+        for (key, value) in map {
+            let replacement = match value {
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Null => String::new(),
+                other => other.to_string(),
+            };
+            rendered = rendered.replace(&format!("{{{{{}}}}}", key), &replacement);
+            rendered = rendered.replace(&format!("{{{{ {} }}}}", key), &replacement);
+        }
+    }
+
+    // Templates are stored with literal "\n" sequences
+    rendered.replace("\\n", "\n")
+}
+
+/// Exponential backoff: base * 2^(attempt - 1), capped at max.
+pub fn retry_delay_seconds(attempt: i32, base_seconds: i64, max_seconds: i64) -> i64 {
+    let exponent = (attempt.max(1) - 1).min(20) as u32;
+    base_seconds.saturating_mul(2_i64.saturating_pow(exponent)).min(max_seconds)
+}
+
+/// True when `local` falls inside the quiet window. Windows may wrap past midnight.
+pub fn is_quiet_time(local: NaiveTime, start: NaiveTime, end: NaiveTime) -> bool {
+    if start == end {
+        false
+    } else if start < end {
+        local >= start && local < end
+    } else {
+        local >= start || local < end
+    }
+}
+
+/// Seconds from `from` until the next occurrence of `to` on the clock.
+pub fn seconds_until(from: NaiveTime, to: NaiveTime) -> i64 {
+    let diff = to.num_seconds_from_midnight() as i64 - from.num_seconds_from_midnight() as i64;
+    if diff > 0 { diff } else { diff + 24 * 60 * 60 }
+}
+
+/// Start and end of the digest window containing `now`, on the recipient's
+/// local clock (`utc_offset_seconds` east of UTC), so a daily digest covers
+/// their day rather than the UTC one.
+pub fn digest_period(now: DateTime<Utc>, frequency: &str, utc_offset_seconds: i64) -> (DateTime<Utc>, DateTime<Utc>) {
+    let offset = ChronoDuration::seconds(utc_offset_seconds);
+    let local = now + offset;
+    let hour_start = local
+        .with_minute(0)
+        .and_then(|t| t.with_second(0))
+        .and_then(|t| t.with_nanosecond(0))
+        .unwrap_or(local);
+
+    let (start, length) = match frequency {
+        "hourly" => (hour_start, ChronoDuration::hours(1)),
+        _ => (hour_start.with_hour(0).unwrap_or(hour_start), ChronoDuration::days(1)),
+    };
+    (start - offset, start - offset + length)
+}
+
+/// Email and SMS interrupt the recipient, so they wait out quiet hours and
+/// weekends; in-app and websocket notifications never do.
+fn is_interruptive(channel: &str) -> bool {
+    matches!(channel, "email" | "sms")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn deferral_seconds(schedule: &RecipientSchedule) -> Option<i64> {
+    let start = schedule.quiet_hours_start;
+    let end = schedule.quiet_hours_end;
+
+    if let (Some(start), Some(end)) = (start, end) {
+        if is_quiet_time(schedule.local_time, start, end) {
+            return Some(seconds_until(schedule.local_time, end));
+        }
+    }
+
+    // ISO day of week: 6 = Saturday, 7 = Sunday
+    let is_weekend = schedule.local_dow == Weekday::Sat.number_from_monday() as i32
+        || schedule.local_dow == Weekday::Sun.number_from_monday() as i32;
+
+    if is_weekend && !schedule.weekend_notifications.unwrap_or(true) {
+        let days_to_monday = 8 - schedule.local_dow as i64;
+        let resume_at = end.unwrap_or(NaiveTime::MIN);
+        let midnight = seconds_until(schedule.local_time, NaiveTime::MIN);
+        return Some(midnight + (days_to_monday - 1) * 24 * 60 * 60 + resume_at.num_seconds_from_midnight() as i64);
+    }
+
+    None
+}
+
+fn metadata_entity(metadata: Option<&serde_json::Value>) -> (Option<String>, Option<Uuid>) {
+    let entity_type = metadata
+        .and_then(|m| m.get("entity_type"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+    let entity_id = metadata
+        .and_then(|m| m.get("entity_id"))
+        .and_then(|v| v.as_str())
+        .and_then(|s| Uuid::parse_str(s).ok());
+
+    (entity_type, entity_id)
+}
+
+fn notification_email_html(title: &str, content: &str) -> String {
+    format!(
+        r#"
+        <html>
+        <body style="font-family: Arial, sans-serif; margin: 0; padding: 20px; background-color: #f5f5f5;">
+            <div style="max-width: 600px; margin: 0 auto; background: white; border-radius: 8px; padding: 30px;">
+                <h2 style="color: #2563eb;">{}</h2>
+                <p>{}</p>
+                <hr style="border: none; border-top: 1px solid #e5e7eb; margin: 20px 0;">
+                <p style="color: #666; font-size: 12px;">You can change how you receive notifications in your GhostHub preferences.</p>
+            </div>
+        </body>
+        </html>
+        "#,
+        escape_html(title),
+        escape_html(content).replace('\n', "<br>")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn t(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_render_template() {
+        let vars = serde_json::json!({ "ticket_number": 42, "ticket_subject": "Printer offline" });
+        let rendered = render_template("Ticket #{{ticket_number}}: {{ticket_subject}}\\n{{missing}}", &vars);
+        assert_eq!(rendered, "Ticket #42: Printer offline\n{{missing}}");
+    }
+
+    #[test]
+    fn test_retry_delay_backoff() {
+        assert_eq!(retry_delay_seconds(1, 60, 3600), 60);
+        assert_eq!(retry_delay_seconds(2, 60, 3600), 120);
+        assert_eq!(retry_delay_seconds(4, 60, 3600), 480);
+        assert_eq!(retry_delay_seconds(10, 60, 3600), 3600);
+    }
+
+    #[test]
+    fn test_quiet_hours_wrapping_midnight() {
+        assert!(is_quiet_time(t(23, 0), t(22, 0), t(8, 0)));
+        assert!(is_quiet_time(t(3, 0), t(22, 0), t(8, 0)));
+        assert!(!is_quiet_time(t(8, 0), t(22, 0), t(8, 0)));
+        assert!(!is_quiet_time(t(12, 0), t(22, 0), t(8, 0)));
+        assert!(is_quiet_time(t(13, 0), t(12, 0), t(14, 0)));
+        assert_eq!(seconds_until(t(23, 0), t(8, 0)), 9 * 60 * 60);
+    }
+
+    #[test]
+    fn test_digest_period() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 5, 14, 37, 12).unwrap();
+        let (start, end) = digest_period(now, "hourly", 0);
+        assert_eq!(start, Utc.with_ymd_and_hms(2024, 3, 5, 14, 0, 0).unwrap());
+        assert_eq!(end, Utc.with_ymd_and_hms(2024, 3, 5, 15, 0, 0).unwrap());
+
+        let (start, end) = digest_period(now, "daily", 0);
+        assert_eq!(start, Utc.with_ymd_and_hms(2024, 3, 5, 0, 0, 0).unwrap());
+        assert_eq!(end, Utc.with_ymd_and_hms(2024, 3, 6, 0, 0, 0).unwrap());
+
+        // 14:37 UTC is 09:37 in New York (UTC-5), still on the 5th locally
+        let (start, end) = digest_period(now, "daily", -5 * 3600);
+        assert_eq!(start, Utc.with_ymd_and_hms(2024, 3, 5, 5, 0, 0).unwrap());
+        assert_eq!(end, Utc.with_ymd_and_hms(2024, 3, 6, 5, 0, 0).unwrap());
+
+        // India is UTC+5:30, so its hours start at half past in UTC
+        let (start, _) = digest_period(now, "hourly", 5 * 3600 + 1800);
+        assert_eq!(start, Utc.with_ymd_and_hms(2024, 3, 5, 14, 30, 0).unwrap());
+    }
+
+    #[test]
+    fn test_only_interruptive_channels_deferred() {
+        assert!(is_interruptive("email"));
+        assert!(is_interruptive("sms"));
+        assert!(!is_interruptive("in_app"));
+        assert!(!is_interruptive("websocket"));
+    }
+
+    #[test]
+    fn test_email_html_escapes_content() {
+        let html = notification_email_html("Ticket <b>#1</b>", "Use <script>alert(1)</script> & \"quotes\"\nnext");
+        assert!(html.contains("Ticket &lt;b&gt;#1&lt;/b&gt;"));
+        assert!(html.contains("Use &lt;script&gt;alert(1)&lt;/script&gt; &amp; &quot;quotes&quot;<br>next"));
+        assert!(!html.contains("<script>"));
+    }
+}
@@ -0,0 +1,834 @@
+use crate::integrations::stripe::{
+    self, PaymentIntent, PaymentIntentRequest, StripeClient, StripePaymentMethod, WebhookEvent,
+};
+use crate::notifications::{enqueue_notification, QueuedNotification};
+use crate::services::credit_notes::{self, InvoiceBalance};
+use crate::services::currency::{self, format_money};
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+pub type PaymentResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+pub const TRANSACTION_COLUMNS: &str = "id, client_id, invoice_id, payment_method_id, payment_id, credit_note_id,
+    transaction_type, amount, COALESCE(currency, 'USD') as currency, processor, processor_transaction_id, status,
+    status_message, reference_number, initiated_at, processed_at";
+
+pub const PAYMENT_METHOD_COLUMNS: &str = "id, client_id, type as method_type, COALESCE(is_default, false) as is_default,
+    card_brand, card_last_four, card_exp_month, card_exp_year, stripe_payment_method_id, created_at";
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PaymentTransaction {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub invoice_id: Option<Uuid>,
+    pub payment_method_id: Option<Uuid>,
+    pub payment_id: Option<Uuid>,
+    pub credit_note_id: Option<Uuid>,
+    pub transaction_type: String,
+    pub amount: Decimal,
+    pub currency: String,
+    pub processor: Option<String>,
+    pub processor_transaction_id: Option<String>,
+    pub status: String,
+    pub status_message: Option<String>,
+    pub reference_number: Option<String>,
+    pub initiated_at: Option<DateTime<Utc>>,
+    pub processed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SavedPaymentMethod {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub method_type: String,
+    pub is_default: bool,
+    pub card_brand: Option<String>,
+    pub card_last_four: Option<String>,
+    pub card_exp_month: Option<i32>,
+    pub card_exp_year: Option<i32>,
+    pub stripe_payment_method_id: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// A payment to record against an invoice, as entered by staff or reported by a processor.
+pub struct NewPayment<'a> {
+    pub amount: Decimal,
+    pub payment_date: NaiveDate,
+    pub payment_method: Option<&'a str>,
+    pub reference_number: Option<&'a str>,
+    pub notes: Option<&'a str>,
+}
+
+/// What a completed processor charge did to the client's account.
+#[derive(Debug, Clone)]
+pub struct CompletedPayment {
+    pub transaction_id: Uuid,
+    pub client_id: Uuid,
+    pub invoice_id: Option<Uuid>,
+    pub payment_id: Option<Uuid>,
+    pub currency: String,
+    pub applied: Decimal,
+    pub credited: Decimal,
+}
+
+/// Records a payment against an invoice locked with `credit_notes::lock_invoice`.
+/// The caller has checked the invoice isn't void and the amount is within its balance.
+pub async fn record_payment(
+    tx: &mut Transaction<'_, Postgres>,
+    invoice: &InvoiceBalance,
+    payment: NewPayment<'_>,
+) -> PaymentResult<Uuid> {
+    // Paid in the invoice currency; without a rate for the payment date the invoice rate is kept
+    let exchange_rate = match currency::snapshot(&mut **tx, &invoice.currency, payment.payment_date).await {
+        Ok(rate) => rate.rate,
+        Err(e) => {
+            warn!("Using invoice exchange rate for payment on invoice {}: {}", invoice.id, e);
+            sqlx::query_scalar("SELECT exchange_rate FROM invoices WHERE id = $1")
+                .bind(invoice.id)
+                .fetch_one(&mut **tx)
+                .await?
+        }
+    };
+
+    let payment_id: Uuid = sqlx::query_scalar(
+        "INSERT INTO payments (
+            id, invoice_id, amount, payment_date, payment_method,
+            reference_number, notes, created_at, currency, exchange_rate, base_amount
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, NOW(), $8, $9, $10)
+        RETURNING id",
+    )
+    .bind(Uuid::new_v4())
+    .bind(invoice.id)
+    .bind(payment.amount)
+    .bind(payment.payment_date)
+    .bind(payment.payment_method)
+    .bind(payment.reference_number)
+    .bind(payment.notes)
+    .bind(&invoice.currency)
+    .bind(exchange_rate)
+    .bind(currency::convert(payment.amount, exchange_rate))
+    .fetch_one(&mut **tx)
+    .await?;
+
+    sqlx::query("UPDATE invoices SET balance = balance - $2, status = $3, updated_at = NOW() WHERE id = $1")
+        .bind(invoice.id)
+        .bind(payment.amount)
+        .bind(credit_notes::invoice_status(
+            invoice.status.as_deref().filter(|s| *s != "draft").unwrap_or("sent"),
+            invoice.total,
+            invoice.balance - payment.amount,
+        ))
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(payment_id)
+}
+
+/// Splits money received for an invoice into what pays it down and what is
+/// left over as account credit. Void invoices and currency mismatches take nothing.
+pub fn split_payment(received: Decimal, invoice: Option<&InvoiceBalance>, currency: &str) -> (Decimal, Decimal) {
+    let applied = invoice
+        .filter(|i| !i.is_void() && i.currency == currency)
+        .map(|i| received.min(i.balance.max(Decimal::ZERO)))
+        .unwrap_or(Decimal::ZERO);
+
+    (applied, received - applied)
+}
+
+async fn lock_transaction(tx: &mut Transaction<'_, Postgres>, id: Uuid) -> PaymentResult<Option<PaymentTransaction>> {
+    Ok(sqlx::query_as::<_, PaymentTransaction>(&format!(
+        "SELECT {} FROM payment_transactions WHERE id = $1 FOR UPDATE",
+        TRANSACTION_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&mut **tx)
+    .await?)
+}
+
+/// Records a successful Stripe charge against the transaction's invoice the
+/// same way a manual payment is recorded. Anything beyond the balance becomes
+/// account credit. Returns None when the transaction was already completed,
+/// so replayed webhooks and the charge worker can both report the same charge.
+pub async fn complete_stripe_payment(
+    tx: &mut Transaction<'_, Postgres>,
+    transaction_id: Uuid,
+    payment_intent_id: &str,
+    received: Decimal,
+    saved_method: Option<&StripePaymentMethod>,
+) -> PaymentResult<Option<CompletedPayment>> {
+    let transaction = lock_transaction(tx, transaction_id)
+        .await?
+        .ok_or_else(|| format!("Unknown payment transaction {}", transaction_id))?;
+    if transaction.status == "completed" {
+        return Ok(None);
+    }
+
+    let payment_method_id = match saved_method {
+        Some(method) => Some(save_payment_method(tx, transaction.client_id, method).await?),
+        None => transaction.payment_method_id,
+    };
+
+    let invoice = match transaction.invoice_id {
+        Some(invoice_id) => credit_notes::lock_invoice(tx, invoice_id).await?,
+        None => None,
+    };
+    let (applied, credited) = split_payment(received, invoice.as_ref(), &transaction.currency);
+
+    let payment_id = match invoice.as_ref().filter(|_| applied > Decimal::ZERO) {
+        Some(invoice) => Some(
+            record_payment(
+                tx,
+                invoice,
+                NewPayment {
+                    amount: applied,
+                    payment_date: Utc::now().date_naive(),
+                    payment_method: Some("stripe"),
+                    reference_number: Some(payment_intent_id),
+                    notes: transaction.reference_number.as_deref(),
+                },
+            )
+            .await?,
+        ),
+        None => None,
+    };
+
+    let credit_note_id = if credited > Decimal::ZERO {
+        let description = format!("Card payment {} exceeded the invoice balance", payment_intent_id);
+        Some(
+            credit_notes::issue_credit_note(
+                tx,
+                transaction.client_id,
+                None,
+                "account",
+                credited,
+                "Overpayment",
+                Some(&description),
+                None,
+            )
+            .await?,
+        )
+    } else {
+        None
+    };
+
+    sqlx::query(
+        "UPDATE payment_transactions SET status = 'completed', status_message = NULL, processor = 'stripe',
+         processor_transaction_id = $2, payment_id = $3, credit_note_id = $4, payment_method_id = $5,
+         amount = $6, processed_at = NOW()
+         WHERE id = $1",
+    )
+    .bind(transaction_id)
+    .bind(payment_intent_id)
+    .bind(payment_id)
+    .bind(credit_note_id)
+    .bind(payment_method_id)
+    .bind(received)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(Some(CompletedPayment {
+        transaction_id,
+        client_id: transaction.client_id,
+        invoice_id: transaction.invoice_id,
+        payment_id,
+        currency: transaction.currency,
+        applied,
+        credited,
+    }))
+}
+
+/// Marks an unfinished transaction failed. Returns it when the status changed.
+pub async fn fail_transaction(
+    db_pool: &PgPool,
+    transaction_id: Uuid,
+    message: &str,
+) -> PaymentResult<Option<PaymentTransaction>> {
+    Ok(sqlx::query_as::<_, PaymentTransaction>(&format!(
+        "UPDATE payment_transactions SET status = 'failed', status_message = $2, processed_at = NOW()
+         WHERE id = $1 AND status IN ('pending', 'processing')
+         RETURNING {}",
+        TRANSACTION_COLUMNS
+    ))
+    .bind(transaction_id)
+    .bind(message)
+    .fetch_optional(db_pool)
+    .await?)
+}
+
+/// Stores a card Stripe has attached to the client's customer. The first card
+/// saved becomes the default.
+pub async fn save_payment_method(
+    tx: &mut Transaction<'_, Postgres>,
+    client_id: Uuid,
+    method: &StripePaymentMethod,
+) -> PaymentResult<Uuid> {
+    let card = method.card.as_ref();
+
+    let id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO payment_methods (client_id, type, is_default, card_brand, card_last_four, card_exp_month,
+                                     card_exp_year, stripe_payment_method_id, is_verified, verified_at)
+        VALUES ($1, 'stripe',
+                NOT EXISTS (SELECT 1 FROM payment_methods WHERE client_id = $1 AND is_default AND removed_at IS NULL),
+                $2, $3, $4, $5, $6, true, NOW())
+        ON CONFLICT (stripe_payment_method_id) WHERE stripe_payment_method_id IS NOT NULL
+        DO UPDATE SET removed_at = NULL, card_exp_month = EXCLUDED.card_exp_month,
+                      card_exp_year = EXCLUDED.card_exp_year, updated_at = NOW()
+        RETURNING id
+        "#,
+    )
+    .bind(client_id)
+    .bind(card.and_then(|c| c.brand.as_deref()))
+    .bind(card.and_then(|c| c.last4.as_deref()))
+    .bind(card.and_then(|c| c.exp_month))
+    .bind(card.and_then(|c| c.exp_year))
+    .bind(&method.id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(id)
+}
+
+/// The client's Stripe customer, created on first use.
+pub async fn ensure_stripe_customer(db_pool: &PgPool, stripe: &StripeClient, client_id: Uuid) -> PaymentResult<String> {
+    let (name, email, customer_id): (String, Option<String>, Option<String>) =
+        sqlx::query_as("SELECT name, email, stripe_customer_id FROM clients WHERE id = $1")
+            .bind(client_id)
+            .fetch_one(db_pool)
+            .await?;
+    if let Some(customer_id) = customer_id {
+        return Ok(customer_id);
+    }
+
+    let customer_id = stripe.create_customer(client_id, &name, email.as_deref()).await?;
+
+    // A concurrent request may have created one first; keep whichever was stored
+    let stored: String = sqlx::query_scalar(
+        "UPDATE clients SET stripe_customer_id = COALESCE(stripe_customer_id, $2) WHERE id = $1
+         RETURNING stripe_customer_id",
+    )
+    .bind(client_id)
+    .bind(&customer_id)
+    .fetch_one(db_pool)
+    .await?;
+
+    Ok(stored)
+}
+
+/// Claims a webhook event id so each delivery is processed once.
+async fn claim_event(tx: &mut Transaction<'_, Postgres>, event: &WebhookEvent) -> PaymentResult<bool> {
+    let claimed: Option<String> = sqlx::query_scalar(
+        "INSERT INTO stripe_webhook_events (id, event_type) VALUES ($1, $2)
+         ON CONFLICT (id) DO NOTHING RETURNING id",
+    )
+    .bind(&event.id)
+    .bind(&event.event_type)
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(claimed.is_some())
+}
+
+fn transaction_reference(object: &serde_json::Value) -> Option<Uuid> {
+    object
+        .get("metadata")
+        .and_then(|m| m.get("payment_transaction_id"))
+        .and_then(|v| v.as_str())
+        .and_then(|s| Uuid::parse_str(s).ok())
+}
+
+/// Applies a verified Stripe webhook event. Events for charges GhostHub
+/// didn't start are acknowledged and ignored.
+pub async fn handle_stripe_event(db_pool: &PgPool, stripe: &StripeClient, event: &WebhookEvent) -> PaymentResult<()> {
+    let object = &event.data.object;
+
+    // Looked up before the transaction opens so no lock is held over the API call
+    let saved_method = match event.event_type.as_str() {
+        "payment_intent.succeeded" => {
+            let intent: PaymentIntent = serde_json::from_value(object.clone())?;
+            match intent.payment_method.as_deref() {
+                Some(pm) if intent.metadata.get("save_payment_method").map(String::as_str) == Some("true") => {
+                    match stripe.retrieve_payment_method(pm).await {
+                        Ok(method) => Some(method),
+                        Err(e) => {
+                            warn!("Could not fetch payment method {} to save it: {}", pm, e);
+                            None
+                        }
+                    }
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    let mut tx = db_pool.begin().await?;
+    if !claim_event(&mut tx, event).await? {
+        return Ok(());
+    }
+
+    let mut completed = None;
+    let mut failed = None;
+    match event.event_type.as_str() {
+        "payment_intent.succeeded" => {
+            if let Some(transaction_id) = transaction_reference(object) {
+                let intent: PaymentIntent = serde_json::from_value(object.clone())?;
+                let currency = intent.currency.to_uppercase();
+                let received = stripe::from_minor_units(intent.amount_received.unwrap_or(intent.amount), &currency);
+
+                completed =
+                    complete_stripe_payment(&mut tx, transaction_id, &intent.id, received, saved_method.as_ref()).await?;
+            }
+        }
+        "payment_intent.payment_failed" => {
+            if let Some(transaction_id) = transaction_reference(object) {
+                let intent: PaymentIntent = serde_json::from_value(object.clone())?;
+                let message = intent
+                    .last_payment_error
+                    .and_then(|e| e.message)
+                    .unwrap_or_else(|| "Payment failed".to_string());
+                failed = Some((transaction_id, message));
+            }
+        }
+        "checkout.session.completed" => {
+            let session_id = object.get("id").and_then(|v| v.as_str()).unwrap_or_default();
+            sqlx::query(
+                "UPDATE payment_portal_sessions SET completed_at = NOW(), is_active = false, last_activity = NOW()
+                 WHERE session_token = $1",
+            )
+            .bind(session_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        "checkout.session.expired" => {
+            let session_id = object.get("id").and_then(|v| v.as_str()).unwrap_or_default();
+            sqlx::query("UPDATE payment_portal_sessions SET is_active = false WHERE session_token = $1")
+                .bind(session_id)
+                .execute(&mut *tx)
+                .await?;
+            if let Some(transaction_id) = transaction_reference(object) {
+                sqlx::query(
+                    "UPDATE payment_transactions SET status = 'cancelled', status_message = 'Checkout expired',
+                     processed_at = NOW() WHERE id = $1 AND status = 'pending'",
+                )
+                .bind(transaction_id)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        _ => {}
+    }
+
+    tx.commit().await?;
+
+    if let Some((transaction_id, message)) = failed {
+        // Portal payers see the decline on the payment form, so no notification here
+        fail_transaction(db_pool, transaction_id, &message).await?;
+    }
+    if let Some(completed) = completed {
+        notify_payment_received(db_pool, &completed).await;
+    }
+
+    Ok(())
+}
+
+async fn billing_contact(db_pool: &PgPool, client_id: Uuid) -> Option<Uuid> {
+    sqlx::query_scalar(
+        "SELECT id FROM contacts WHERE client_id = $1 AND archived_at IS NULL AND email IS NOT NULL
+         ORDER BY is_primary DESC NULLS LAST, created_at LIMIT 1",
+    )
+    .bind(client_id)
+    .fetch_optional(db_pool)
+    .await
+    .unwrap_or(None)
+}
+
+pub fn portal_invoice_url(invoice_id: Uuid) -> String {
+    format!("{}/portal/invoices/{}", crate::config::app_base_url(), invoice_id)
+}
+
+async fn invoice_number(db_pool: &PgPool, invoice_id: Option<Uuid>) -> String {
+    let Some(invoice_id) = invoice_id else {
+        return String::new();
+    };
+
+    sqlx::query_scalar("SELECT number FROM invoices WHERE id = $1")
+        .bind(invoice_id)
+        .fetch_one(db_pool)
+        .await
+        .unwrap_or_default()
+}
+
+pub async fn notify_payment_received(db_pool: &PgPool, payment: &CompletedPayment) {
+    let Some(contact_id) = billing_contact(db_pool, payment.client_id).await else {
+        return;
+    };
+
+    let number = invoice_number(db_pool, payment.invoice_id).await;
+    let total = payment.applied + payment.credited;
+    let mut message = format!("We received your payment of {}", format_money(total, &payment.currency));
+    if !number.is_empty() {
+        message.push_str(&format!(" for invoice {}", number));
+    }
+    message.push('.');
+    if payment.credited > Decimal::ZERO {
+        message.push_str(&format!(
+            " {} more than was owed has been added to your account as credit.",
+            format_money(payment.credited, &payment.currency)
+        ));
+    }
+
+    let notification = QueuedNotification::for_contact(
+        contact_id,
+        "payment_received",
+        format!("Payment received{}", if number.is_empty() { String::new() } else { format!(" for {}", number) }),
+        message,
+    )
+    .with_entity("payment_transaction", payment.transaction_id)
+    .with_variables(serde_json::json!({
+        "invoice_number": number,
+        "amount": total,
+        "applied": payment.applied,
+        "credited": payment.credited,
+        "currency": payment.currency,
+    }));
+
+    if let Err(e) = enqueue_notification(db_pool, notification).await {
+        warn!("Failed to queue payment receipt for {}: {}", payment.transaction_id, e);
+    }
+}
+
+async fn notify_charge_failed(db_pool: &PgPool, transaction: &PaymentTransaction, reason: &str) {
+    let Some(contact_id) = billing_contact(db_pool, transaction.client_id).await else {
+        return;
+    };
+
+    let number = invoice_number(db_pool, transaction.invoice_id).await;
+    let mut message = format!(
+        "We couldn't charge your saved card {} for invoice {}: {}.",
+        format_money(transaction.amount, &transaction.currency),
+        number,
+        reason.trim_end_matches('.')
+    );
+    if let Some(invoice_id) = transaction.invoice_id {
+        message.push_str(&format!(" You can pay it in your client portal: {}", portal_invoice_url(invoice_id)));
+    }
+
+    let notification = QueuedNotification::for_contact(
+        contact_id,
+        "payment_failed",
+        format!("Payment for invoice {} failed", number),
+        message,
+    )
+    .with_priority("high")
+    .with_entity("payment_transaction", transaction.id)
+    .with_variables(serde_json::json!({
+        "invoice_number": number,
+        "amount": transaction.amount,
+        "currency": transaction.currency,
+        "reason": reason,
+    }));
+
+    if let Err(e) = enqueue_notification(db_pool, notification).await {
+        warn!("Failed to queue payment failure notice for {}: {}", transaction.id, e);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PaymentCollectionConfig {
+    pub check_interval_seconds: u64, // How often pending auto-charges are attempted
+    pub batch_size: i64,
+}
+
+impl Default for PaymentCollectionConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_seconds: 5 * 60,
+            batch_size: 25,
+        }
+    }
+}
+
+/// Charges saved cards for the pending transactions recurring billing
+/// queues on auto-charge profiles.
+#[derive(Clone)]
+pub struct PaymentCollectionService {
+    config: PaymentCollectionConfig,
+    db_pool: PgPool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CollectionSummary {
+    pub attempted: u32,
+    pub succeeded: u32,
+    pub pending: u32,
+    pub failed: u32,
+    pub cancelled: u32,
+}
+
+#[derive(Debug, FromRow)]
+struct ChargeTarget {
+    stripe_customer_id: Option<String>,
+    stripe_payment_method_id: Option<String>,
+    method_removed: bool,
+    invoice_number: Option<String>,
+    invoice_balance: Option<Decimal>,
+    invoice_status: Option<String>,
+}
+
+enum ChargeOutcome {
+    Succeeded,
+    Pending,
+    Failed,
+    Cancelled,
+}
+
+impl PaymentCollectionService {
+    pub fn new(config: PaymentCollectionConfig, db_pool: PgPool) -> Self {
+        Self { config, db_pool }
+    }
+
+    pub async fn start(&self) -> PaymentResult<()> {
+        info!("Starting payment collection worker");
+
+        let mut check_interval = interval(Duration::from_secs(self.config.check_interval_seconds));
+
+        tokio::spawn({
+            let service = self.clone();
+            async move {
+                loop {
+                    check_interval.tick().await;
+
+                    if let Err(e) = service.collect_pending().await {
+                        error!("Error collecting auto-charge payments: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Attempts every pending auto-charge with a saved Stripe card.
+    pub async fn collect_pending(&self) -> PaymentResult<CollectionSummary> {
+        let mut summary = CollectionSummary::default();
+
+        // Nothing is claimed while Stripe isn't set up, so charges wait for it
+        let stripe = match StripeClient::from_integration(&self.db_pool).await {
+            Ok(stripe) => stripe,
+            Err(e) => {
+                warn!("Skipping auto-charge run: {}", e);
+                return Ok(summary);
+            }
+        };
+
+        let claimed = sqlx::query_as::<_, PaymentTransaction>(&format!(
+            r#"
+            UPDATE payment_transactions SET status = 'processing', processor = 'stripe'
+            WHERE id IN (
+                SELECT t.id FROM payment_transactions t
+                JOIN payment_methods pm ON pm.id = t.payment_method_id
+                WHERE t.status = 'pending' AND t.transaction_type = 'payment' AND t.processor IS NULL
+                  AND pm.stripe_payment_method_id IS NOT NULL
+                ORDER BY t.initiated_at
+                LIMIT $1
+                FOR UPDATE OF t SKIP LOCKED
+            )
+            RETURNING {}
+            "#,
+            TRANSACTION_COLUMNS
+        ))
+        .bind(self.config.batch_size)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        for transaction in claimed {
+            summary.attempted += 1;
+            match self.charge(&stripe, &transaction).await {
+                Ok(ChargeOutcome::Succeeded) => summary.succeeded += 1,
+                Ok(ChargeOutcome::Pending) => summary.pending += 1,
+                Ok(ChargeOutcome::Failed) => summary.failed += 1,
+                Ok(ChargeOutcome::Cancelled) => summary.cancelled += 1,
+                Err(e) => {
+                    error!("Error charging payment transaction {}: {}", transaction.id, e);
+                    summary.failed += 1;
+                }
+            }
+        }
+
+        if summary.attempted > 0 {
+            info!(
+                "Auto-charge run: {} attempted, {} succeeded, {} pending, {} failed, {} cancelled",
+                summary.attempted, summary.succeeded, summary.pending, summary.failed, summary.cancelled
+            );
+        }
+
+        Ok(summary)
+    }
+
+    async fn charge(&self, stripe: &StripeClient, transaction: &PaymentTransaction) -> PaymentResult<ChargeOutcome> {
+        let target = sqlx::query_as::<_, ChargeTarget>(
+            r#"
+            SELECT c.stripe_customer_id, pm.stripe_payment_method_id, pm.removed_at IS NOT NULL as method_removed,
+                   i.number as invoice_number, i.balance as invoice_balance, i.status as invoice_status
+            FROM payment_transactions t
+            JOIN clients c ON c.id = t.client_id
+            JOIN payment_methods pm ON pm.id = t.payment_method_id
+            LEFT JOIN invoices i ON i.id = t.invoice_id
+            WHERE t.id = $1
+            "#,
+        )
+        .bind(transaction.id)
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        // Paid, credited or voided since the charge was queued
+        let balance = target.invoice_balance.unwrap_or(transaction.amount);
+        if balance <= Decimal::ZERO || target.invoice_status.as_deref() == Some("void") {
+            sqlx::query(
+                "UPDATE payment_transactions SET status = 'cancelled', status_message = 'Invoice already settled',
+                 processed_at = NOW() WHERE id = $1",
+            )
+            .bind(transaction.id)
+            .execute(&self.db_pool)
+            .await?;
+            return Ok(ChargeOutcome::Cancelled);
+        }
+
+        let (Some(customer_id), Some(payment_method), false) =
+            (target.stripe_customer_id.as_deref(), target.stripe_payment_method_id.as_deref(), target.method_removed)
+        else {
+            self.fail(transaction, "The saved payment method is no longer available").await?;
+            return Ok(ChargeOutcome::Failed);
+        };
+
+        let description = format!("Invoice {}", target.invoice_number.as_deref().unwrap_or_default());
+        let amount = transaction.amount.min(balance);
+        let intent = match stripe
+            .create_payment_intent(&PaymentIntentRequest {
+                customer_id,
+                payment_transaction_id: transaction.id,
+                description: &description,
+                amount,
+                currency: &transaction.currency,
+                payment_method: Some(payment_method),
+                off_session: true,
+                save_payment_method: false,
+            })
+            .await
+        {
+            Ok(intent) => intent,
+            Err(e) => {
+                self.fail(transaction, &e.to_string()).await?;
+                return Ok(ChargeOutcome::Failed);
+            }
+        };
+
+        match intent.status.as_str() {
+            "succeeded" => {
+                let received = stripe::from_minor_units(
+                    intent.amount_received.unwrap_or(intent.amount),
+                    &intent.currency.to_uppercase(),
+                );
+                let mut tx = self.db_pool.begin().await?;
+                let completed = complete_stripe_payment(&mut tx, transaction.id, &intent.id, received, None).await?;
+                tx.commit().await?;
+
+                if let Some(completed) = completed {
+                    notify_payment_received(&self.db_pool, &completed).await;
+                }
+                Ok(ChargeOutcome::Succeeded)
+            }
+            // Bank debits settle later; the payment_intent.succeeded webhook records them
+            "processing" => {
+                sqlx::query("UPDATE payment_transactions SET processor_transaction_id = $2 WHERE id = $1")
+                    .bind(transaction.id)
+                    .bind(&intent.id)
+                    .execute(&self.db_pool)
+                    .await?;
+                Ok(ChargeOutcome::Pending)
+            }
+            status => {
+                let reason = match status {
+                    "requires_action" => "Your bank asked for additional authentication".to_string(),
+                    other => format!("Payment ended in status {}", other),
+                };
+                self.fail(transaction, &reason).await?;
+                Ok(ChargeOutcome::Failed)
+            }
+        }
+    }
+
+    async fn fail(&self, transaction: &PaymentTransaction, reason: &str) -> PaymentResult<()> {
+        warn!("Auto-charge {} failed: {}", transaction.id, reason);
+        if let Some(failed) = fail_transaction(&self.db_pool, transaction.id, reason).await? {
+            notify_charge_failed(&self.db_pool, &failed, reason).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invoice(status: &str, currency: &str, balance: Decimal) -> InvoiceBalance {
+        InvoiceBalance {
+            id: Uuid::new_v4(),
+            client_id: Uuid::new_v4(),
+            status: Some(status.to_string()),
+            currency: currency.to_string(),
+            total: Decimal::new(500, 0),
+            balance,
+        }
+    }
+
+    #[test]
+    fn test_split_payment_within_balance() {
+        let open = invoice("sent", "USD", Decimal::new(500, 0));
+        assert_eq!(
+            split_payment(Decimal::new(200, 0), Some(&open), "USD"),
+            (Decimal::new(200, 0), Decimal::ZERO)
+        );
+        assert_eq!(
+            split_payment(Decimal::new(500, 0), Some(&open), "USD"),
+            (Decimal::new(500, 0), Decimal::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_split_payment_overpayment_becomes_credit() {
+        let partly_paid = invoice("partial", "USD", Decimal::new(12050, 2));
+        assert_eq!(
+            split_payment(Decimal::new(500, 0), Some(&partly_paid), "USD"),
+            (Decimal::new(12050, 2), Decimal::new(37950, 2))
+        );
+
+        let paid = invoice("paid", "USD", Decimal::ZERO);
+        assert_eq!(
+            split_payment(Decimal::new(500, 0), Some(&paid), "USD"),
+            (Decimal::ZERO, Decimal::new(500, 0))
+        );
+    }
+
+    #[test]
+    fn test_split_payment_void_or_mismatched_invoice() {
+        let void = invoice("void", "USD", Decimal::new(500, 0));
+        assert_eq!(
+            split_payment(Decimal::new(500, 0), Some(&void), "USD"),
+            (Decimal::ZERO, Decimal::new(500, 0))
+        );
+
+        let euro = invoice("sent", "EUR", Decimal::new(500, 0));
+        assert_eq!(
+            split_payment(Decimal::new(500, 0), Some(&euro), "USD"),
+            (Decimal::ZERO, Decimal::new(500, 0))
+        );
+        assert_eq!(split_payment(Decimal::new(80, 0), None, "USD"), (Decimal::ZERO, Decimal::new(80, 0)));
+    }
+}
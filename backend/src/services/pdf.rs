@@ -0,0 +1,240 @@
+//! Minimal PDF writer for text documents such as quotes and statements.
+//!
+//! Lays text out top-down on US Letter pages using the standard Helvetica
+//! fonts, so no font files or external renderer are needed.
+
+const PAGE_WIDTH: f32 = 612.0;
+const PAGE_HEIGHT: f32 = 792.0;
+const MARGIN: f32 = 50.0;
+
+#[derive(Debug, Clone)]
+struct TextRun {
+    x: f32,
+    y: f32,
+    size: f32,
+    bold: bool,
+    text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct PdfDocument {
+    title: String,
+    pages: Vec<Vec<TextRun>>,
+    cursor_y: f32,
+}
+
+/// One cell of a table row: text and its left offset from the margin.
+pub struct Column<'a> {
+    pub text: &'a str,
+    pub x: f32,
+    pub right_align: bool,
+}
+
+impl PdfDocument {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            pages: vec![Vec::new()],
+            cursor_y: PAGE_HEIGHT - MARGIN,
+        }
+    }
+
+    pub fn heading(&mut self, text: &str) -> &mut Self {
+        self.line(text, 16.0, true)
+    }
+
+    pub fn subheading(&mut self, text: &str) -> &mut Self {
+        self.line(text, 12.0, true)
+    }
+
+    /// Body text, wrapped to the page width.
+    pub fn text(&mut self, text: &str) -> &mut Self {
+        let max_chars = ((PAGE_WIDTH - 2.0 * MARGIN) / (10.0 * 0.5)) as usize;
+        for paragraph in text.lines() {
+            for wrapped in wrap(paragraph, max_chars) {
+                self.line(&wrapped, 10.0, false);
+            }
+        }
+        self
+    }
+
+    pub fn row(&mut self, columns: &[Column<'_>], bold: bool) -> &mut Self {
+        let size = 10.0;
+        self.advance(size);
+        let y = self.cursor_y;
+        let page = self.pages.last_mut().expect("document always has a page");
+        for column in columns {
+            let x = if column.right_align {
+                MARGIN + column.x - text_width(column.text, size)
+            } else {
+                MARGIN + column.x
+            };
+            page.push(TextRun { x, y, size, bold, text: column.text.to_string() });
+        }
+        self
+    }
+
+    pub fn spacer(&mut self, points: f32) -> &mut Self {
+        self.cursor_y -= points;
+        self
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out: Vec<u8> = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        let page_count = self.pages.len();
+        // 1 catalog, 2 pages, 3 regular font, 4 bold font, 5 info, then page/content pairs
+        let page_ids: Vec<usize> = (0..page_count).map(|i| 6 + i * 2).collect();
+
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                page_ids.iter().map(|id| format!("{} 0 R", id)).collect::<Vec<_>>().join(" "),
+                page_count
+            ),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_string(),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_string(),
+            format!("<< /Title ({}) /Producer (GhostHub) >>", escape(&self.title)),
+        ];
+
+        for (i, runs) in self.pages.iter().enumerate() {
+            let content: String = runs
+                .iter()
+                .map(|r| {
+                    format!(
+                        "BT /{} {} Tf {:.2} {:.2} Td ({}) Tj ET\n",
+                        if r.bold { "F2" } else { "F1" },
+                        r.size,
+                        r.x,
+                        r.y,
+                        escape(&r.text)
+                    )
+                })
+                .collect();
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                page_ids[i] + 1
+            ));
+            objects.push(format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content));
+        }
+
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+        }
+
+        let xref_offset = out.len();
+        out.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            out.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        out.extend_from_slice(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R /Info 5 0 R >>\nstartxref\n{}\n%%EOF\n",
+                objects.len() + 1,
+                xref_offset
+            )
+            .as_bytes(),
+        );
+
+        out
+    }
+
+    fn line(&mut self, text: &str, size: f32, bold: bool) -> &mut Self {
+        self.advance(size);
+        let y = self.cursor_y;
+        self.pages
+            .last_mut()
+            .expect("document always has a page")
+            .push(TextRun { x: MARGIN, y, size, bold, text: text.to_string() });
+        self
+    }
+
+    fn advance(&mut self, size: f32) {
+        let height = size * 1.4;
+        if self.cursor_y - height < MARGIN {
+            self.pages.push(Vec::new());
+            self.cursor_y = PAGE_HEIGHT - MARGIN;
+        }
+        self.cursor_y -= height;
+    }
+}
+
+/// Rough Helvetica width, good enough for right-aligning figures.
+fn text_width(text: &str, size: f32) -> f32 {
+    text.chars().count() as f32 * size * 0.5
+}
+
+fn wrap(text: &str, max_chars: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() || lines.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Escapes a string for a PDF literal; characters outside Latin-1 become '?'.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_structure() {
+        let mut doc = PdfDocument::new("Quote Q-000001");
+        doc.heading("Quote (draft)").text("Managed services");
+        let bytes = doc.to_bytes();
+        let body = String::from_utf8_lossy(&bytes);
+
+        assert!(body.starts_with("%PDF-1.4"));
+        assert!(body.contains("(Quote \\(draft\\)) Tj"));
+        assert!(body.trim_end().ends_with("%%EOF"));
+
+        // startxref must point at the xref table
+        let offset: usize = body.lines().rev().nth(1).unwrap().parse().unwrap();
+        assert!(body[offset..].starts_with("xref"));
+    }
+
+    #[test]
+    fn test_long_documents_paginate() {
+        let mut doc = PdfDocument::new("Long");
+        for i in 0..120 {
+            doc.text(&format!("Line {}", i));
+        }
+        assert!(doc.pages.len() > 1);
+        assert!(String::from_utf8_lossy(&doc.to_bytes()).contains("/Count 3"));
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrap("one two three", 7), vec!["one two", "three"]);
+        assert_eq!(wrap("", 10), vec![""]);
+    }
+}
@@ -0,0 +1,519 @@
+use crate::notifications::{enqueue_notification, QueuedNotification};
+use crate::services::currency::format_money;
+use chrono::{Months, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+type BlockResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Balance left on a block, in hours for hour blocks and the client's currency for amount blocks.
+pub const BLOCK_REMAINING_SQL: &str = "(b.quantity - COALESCE(b.expired_quantity, 0) - COALESCE((
+        SELECT SUM(CASE WHEN b.block_type = 'hours' THEN d.hours ELSE d.amount END)
+        FROM prepaid_block_drawdowns d WHERE d.block_id = b.id), 0))";
+
+#[derive(Debug, Clone)]
+pub struct PrepaidBlockConfig {
+    pub check_interval_seconds: u64, // How often expired blocks are closed out
+}
+
+impl Default for PrepaidBlockConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_seconds: 60 * 60,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PrepaidBlockService {
+    config: PrepaidBlockConfig,
+    db_pool: PgPool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockType {
+    Hours,
+    Amount,
+}
+
+impl BlockType {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "hours" => Some(Self::Hours),
+            "amount" => Some(Self::Amount),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockBalance {
+    pub id: Uuid,
+    pub block_type: BlockType,
+    pub remaining: Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BlockDraw {
+    pub block_id: Uuid,
+    pub hours: Decimal,
+    pub amount: Decimal,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Allocation {
+    pub draws: Vec<BlockDraw>,
+    pub covered_hours: Decimal,
+    pub overage_hours: Decimal,
+}
+
+#[derive(Debug, FromRow)]
+struct EntryForDrawdown {
+    client_id: Option<Uuid>,
+    work_date: Option<NaiveDate>,
+    duration_minutes: Option<i32>,
+    billable: Option<bool>,
+    billed: Option<bool>,
+    invoice_id: Option<Uuid>,
+    rate: Option<Decimal>,
+}
+
+#[derive(Debug, FromRow)]
+struct BlockRow {
+    id: Uuid,
+    block_type: String,
+    remaining: Decimal,
+}
+
+#[derive(Debug, FromRow)]
+struct BlockStatusRow {
+    id: Uuid,
+    client_id: Uuid,
+    contract_name: String,
+    currency: String,
+    block_type: String,
+    quantity: Decimal,
+    remaining: Decimal,
+    low_balance_percent: Option<i32>,
+    low_balance_alerted_at: Option<chrono::DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow)]
+struct ExpiringBlock {
+    id: Uuid,
+    contract_id: Uuid,
+    client_id: Uuid,
+    block_type: String,
+    remaining: Decimal,
+    block_rollover_policy: Option<String>,
+    block_rollover_cap: Option<Decimal>,
+    block_rollover_months: Option<i32>,
+}
+
+impl PrepaidBlockService {
+    pub fn new(config: PrepaidBlockConfig, db_pool: PgPool) -> Self {
+        Self { config, db_pool }
+    }
+
+    pub async fn start(&self) -> BlockResult<()> {
+        info!("Starting prepaid block expiry worker");
+
+        let mut check_interval = interval(Duration::from_secs(self.config.check_interval_seconds));
+
+        tokio::spawn({
+            let service = self.clone();
+            async move {
+                loop {
+                    check_interval.tick().await;
+
+                    if let Err(e) = service.expire_blocks(Utc::now().date_naive()).await {
+                        error!("Error expiring prepaid blocks: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Closes blocks past their expiry date, carrying the balance into a new
+    /// block when the contract's rollover policy allows it.
+    pub async fn expire_blocks(&self, today: NaiveDate) -> BlockResult<u32> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let blocks = sqlx::query_as::<_, ExpiringBlock>(&format!(
+            r#"
+            SELECT b.id, b.contract_id, b.client_id, b.block_type, {} as remaining,
+                   ct.block_rollover_policy, ct.block_rollover_cap, ct.block_rollover_months
+            FROM contract_prepaid_blocks b
+            JOIN contracts ct ON ct.id = b.contract_id
+            WHERE b.status IN ('active', 'depleted') AND b.expires_on < $1
+            FOR UPDATE OF b SKIP LOCKED
+            "#,
+            BLOCK_REMAINING_SQL
+        ))
+        .bind(today)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut closed = 0;
+        for block in blocks {
+            let remaining = block.remaining.max(Decimal::ZERO);
+            let carried = rollover_quantity(
+                remaining,
+                block.block_rollover_policy.as_deref().unwrap_or("none"),
+                block.block_rollover_cap,
+            );
+
+            if carried > Decimal::ZERO {
+                let months = block.block_rollover_months.unwrap_or(3).max(1) as u32;
+                sqlx::query(
+                    r#"
+                    INSERT INTO contract_prepaid_blocks (contract_id, client_id, block_type, quantity, price,
+                                                         purchased_on, expires_on, rolled_over_from, notes)
+                    VALUES ($1, $2, $3, $4, 0, $5, $6, $7, 'Rolled over from expired block')
+                    "#,
+                )
+                .bind(block.contract_id)
+                .bind(block.client_id)
+                .bind(&block.block_type)
+                .bind(carried)
+                .bind(today)
+                .bind(today.checked_add_months(Months::new(months)))
+                .bind(block.id)
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            sqlx::query(
+                "UPDATE contract_prepaid_blocks SET status = $2, expired_quantity = COALESCE(expired_quantity, 0) + $3,
+                 updated_at = NOW() WHERE id = $1",
+            )
+            .bind(block.id)
+            .bind(if carried > Decimal::ZERO { "rolled_over" } else { "expired" })
+            .bind(remaining)
+            .execute(&mut *tx)
+            .await?;
+
+            info!("Prepaid block {} expired with {} remaining, {} carried forward", block.id, remaining, carried);
+            closed += 1;
+        }
+
+        tx.commit().await?;
+        Ok(closed)
+    }
+}
+
+/// Re-applies a time entry against the client's prepaid blocks.
+///
+/// Any earlier drawdown for the entry is released first, so this is safe to
+/// call whenever an entry is created, stopped or edited. Billed entries are
+/// left untouched.
+pub async fn draw_down_time_entry(db_pool: &PgPool, time_entry_id: Uuid) -> BlockResult<Allocation> {
+    let mut tx = db_pool.begin().await?;
+
+    let entry = sqlx::query_as::<_, EntryForDrawdown>(
+        r#"
+        SELECT COALESCE(t.client_id, p.client_id) as client_id, te.start_time::date as work_date,
+               COALESCE(te.billable_minutes, te.duration_minutes) as duration_minutes, te.billable, te.billed, te.invoice_id,
+               COALESCE(te.hourly_rate,
+                        (SELECT ct.hourly_rate FROM contracts ct
+                         WHERE ct.client_id = COALESCE(t.client_id, p.client_id) AND ct.status = 'active'
+                           AND ct.hourly_rate IS NOT NULL
+                         ORDER BY ct.start_date DESC LIMIT 1),
+                        150) as rate
+        FROM time_entries te
+        LEFT JOIN tickets t ON t.id = te.ticket_id
+        LEFT JOIN projects p ON p.id = te.project_id
+        WHERE te.id = $1
+        FOR UPDATE OF te
+        "#,
+    )
+    .bind(time_entry_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(entry) = entry else {
+        return Ok(Allocation::default());
+    };
+    if entry.billed.unwrap_or(false) || entry.invoice_id.is_some() {
+        return Ok(Allocation::default());
+    }
+    let Some(client_id) = entry.client_id else {
+        return Ok(Allocation::default());
+    };
+
+    // Serialises drawdowns per client so two entries can't spend the same balance
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext('prepaid_blocks:' || $1::text))")
+        .bind(client_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let released: Vec<Uuid> = sqlx::query_scalar(
+        "DELETE FROM prepaid_block_drawdowns WHERE time_entry_id = $1 RETURNING block_id",
+    )
+    .bind(time_entry_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    sqlx::query("UPDATE contract_prepaid_blocks SET status = 'active', updated_at = NOW() WHERE id = ANY($1) AND status = 'depleted'")
+        .bind(&released)
+        .execute(&mut *tx)
+        .await?;
+
+    let hours = Decimal::from(entry.duration_minutes.unwrap_or(0).max(0)) / Decimal::from(60);
+    if !entry.billable.unwrap_or(false) || hours.is_zero() {
+        tx.commit().await?;
+        return Ok(Allocation::default());
+    }
+
+    let work_date = entry.work_date.unwrap_or_else(|| Utc::now().date_naive());
+    let blocks = sqlx::query_as::<_, BlockRow>(&format!(
+        r#"
+        SELECT b.id, b.block_type, {} as remaining
+        FROM contract_prepaid_blocks b
+        WHERE b.client_id = $1 AND b.status = 'active'
+          AND b.purchased_on <= $2 AND (b.expires_on IS NULL OR b.expires_on >= $2)
+        ORDER BY b.expires_on NULLS LAST, b.purchased_on, b.created_at
+        "#,
+        BLOCK_REMAINING_SQL
+    ))
+    .bind(client_id)
+    .bind(work_date)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let balances: Vec<BlockBalance> = blocks
+        .iter()
+        .filter_map(|b| {
+            BlockType::parse(&b.block_type).map(|block_type| BlockBalance {
+                id: b.id,
+                block_type,
+                remaining: b.remaining,
+            })
+        })
+        .collect();
+
+    let allocation = allocate(hours, entry.rate.unwrap_or_default(), &balances);
+
+    for draw in &allocation.draws {
+        sqlx::query(
+            "INSERT INTO prepaid_block_drawdowns (block_id, time_entry_id, hours, amount) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(draw.block_id)
+        .bind(time_entry_id)
+        .bind(draw.hours)
+        .bind(draw.amount)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let touched: Vec<Uuid> = allocation.draws.iter().map(|d| d.block_id).collect();
+    let statuses = sqlx::query_as::<_, BlockStatusRow>(&format!(
+        r#"
+        SELECT b.id, b.client_id, ct.name as contract_name, c.currency, b.block_type, b.quantity, {} as remaining,
+               ct.low_balance_percent, b.low_balance_alerted_at
+        FROM contract_prepaid_blocks b
+        JOIN contracts ct ON ct.id = b.contract_id
+        JOIN clients c ON c.id = b.client_id
+        WHERE b.id = ANY($1)
+        "#,
+        BLOCK_REMAINING_SQL
+    ))
+    .bind(&touched)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut alerts = Vec::new();
+    for block in &statuses {
+        if block.remaining <= Decimal::ZERO {
+            sqlx::query("UPDATE contract_prepaid_blocks SET status = 'depleted', updated_at = NOW() WHERE id = $1")
+                .bind(block.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        if block.low_balance_alerted_at.is_none()
+            && is_low_balance(block.remaining, block.quantity, block.low_balance_percent.unwrap_or(20))
+        {
+            sqlx::query("UPDATE contract_prepaid_blocks SET low_balance_alerted_at = NOW() WHERE id = $1")
+                .bind(block.id)
+                .execute(&mut *tx)
+                .await?;
+            alerts.push(block);
+        }
+    }
+
+    tx.commit().await?;
+
+    for block in alerts {
+        notify_low_balance(db_pool, block).await;
+    }
+
+    Ok(allocation)
+}
+
+async fn notify_low_balance(db_pool: &PgPool, block: &BlockStatusRow) {
+    let contact_id: Option<Uuid> = sqlx::query_scalar(
+        "SELECT id FROM contacts WHERE client_id = $1 AND archived_at IS NULL AND email IS NOT NULL
+         ORDER BY is_primary DESC NULLS LAST, created_at LIMIT 1",
+    )
+    .bind(block.client_id)
+    .fetch_optional(db_pool)
+    .await
+    .unwrap_or(None);
+
+    let Some(contact_id) = contact_id else {
+        return;
+    };
+
+    let remaining = block.remaining.max(Decimal::ZERO).round_dp(2);
+    let balance = if block.block_type == "hours" {
+        format!("{} hours", remaining.normalize())
+    } else {
+        format_money(remaining, &block.currency)
+    };
+
+    let notification = QueuedNotification::for_contact(
+        contact_id,
+        "prepaid_block_low_balance",
+        format!("Prepaid balance running low on {}", block.contract_name),
+        format!("Your prepaid block on {} has {} remaining.", block.contract_name, balance),
+    )
+    .with_entity("prepaid_block", block.id)
+    .with_variables(serde_json::json!({
+        "contract_name": block.contract_name,
+        "block_type": block.block_type,
+        "remaining": remaining,
+        "quantity": block.quantity,
+    }));
+
+    if let Err(e) = enqueue_notification(db_pool, notification).await {
+        warn!("Failed to queue low balance alert for block {}: {}", block.id, e);
+    }
+}
+
+/// Spreads `hours` of work at `rate` over the blocks in order, oldest expiry first.
+pub fn allocate(hours: Decimal, rate: Decimal, blocks: &[BlockBalance]) -> Allocation {
+    let mut left = hours;
+    let mut draws = Vec::new();
+
+    for block in blocks {
+        if left <= Decimal::ZERO {
+            break;
+        }
+        if block.remaining <= Decimal::ZERO {
+            continue;
+        }
+
+        let draw_hours = match block.block_type {
+            BlockType::Hours => left.min(block.remaining),
+            BlockType::Amount if rate > Decimal::ZERO => left.min(block.remaining / rate),
+            BlockType::Amount => continue,
+        };
+        let draw_hours = draw_hours.round_dp(2);
+        if draw_hours <= Decimal::ZERO {
+            continue;
+        }
+
+        let amount = match block.block_type {
+            BlockType::Hours => (draw_hours * rate).round_dp(2),
+            BlockType::Amount => (draw_hours * rate).round_dp(2).min(block.remaining),
+        };
+
+        draws.push(BlockDraw {
+            block_id: block.id,
+            hours: draw_hours,
+            amount,
+        });
+        left -= draw_hours;
+    }
+
+    let covered_hours = draws.iter().map(|d| d.hours).sum();
+    Allocation {
+        draws,
+        covered_hours,
+        overage_hours: left.max(Decimal::ZERO),
+    }
+}
+
+/// Balance carried into a new block when one expires.
+pub fn rollover_quantity(remaining: Decimal, policy: &str, cap: Option<Decimal>) -> Decimal {
+    if remaining <= Decimal::ZERO {
+        return Decimal::ZERO;
+    }
+    match policy {
+        "full" => remaining,
+        "capped" => cap.map(|c| remaining.min(c.max(Decimal::ZERO))).unwrap_or(remaining),
+        _ => Decimal::ZERO,
+    }
+}
+
+pub fn is_low_balance(remaining: Decimal, quantity: Decimal, percent: i32) -> bool {
+    if quantity <= Decimal::ZERO {
+        return false;
+    }
+    remaining * Decimal::from(100) <= quantity * Decimal::from(percent.max(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(block_type: BlockType, remaining: i64) -> BlockBalance {
+        BlockBalance {
+            id: Uuid::new_v4(),
+            block_type,
+            remaining: Decimal::from(remaining),
+        }
+    }
+
+    #[test]
+    fn test_allocate_draws_oldest_block_first() {
+        let blocks = vec![block(BlockType::Hours, 2), block(BlockType::Hours, 10)];
+        let allocation = allocate(Decimal::from(3), Decimal::from(100), &blocks);
+
+        assert_eq!(allocation.draws.len(), 2);
+        assert_eq!(allocation.draws[0].hours, Decimal::from(2));
+        assert_eq!(allocation.draws[1].hours, Decimal::from(1));
+        assert_eq!(allocation.overage_hours, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_allocate_overage_when_blocks_run_out() {
+        let blocks = vec![block(BlockType::Hours, 1)];
+        let allocation = allocate(Decimal::new(25, 1), Decimal::from(100), &blocks);
+
+        assert_eq!(allocation.covered_hours, Decimal::from(1));
+        assert_eq!(allocation.overage_hours, Decimal::new(15, 1));
+    }
+
+    #[test]
+    fn test_allocate_amount_block_uses_rate() {
+        let blocks = vec![block(BlockType::Amount, 300)];
+        let allocation = allocate(Decimal::from(4), Decimal::from(120), &blocks);
+
+        assert_eq!(allocation.draws[0].hours, Decimal::new(25, 1));
+        assert_eq!(allocation.draws[0].amount, Decimal::from(300));
+        assert_eq!(allocation.overage_hours, Decimal::new(15, 1));
+    }
+
+    #[test]
+    fn test_rollover_policies() {
+        let remaining = Decimal::from(6);
+        assert_eq!(rollover_quantity(remaining, "none", None), Decimal::ZERO);
+        assert_eq!(rollover_quantity(remaining, "full", None), remaining);
+        assert_eq!(rollover_quantity(remaining, "capped", Some(Decimal::from(4))), Decimal::from(4));
+    }
+
+    #[test]
+    fn test_low_balance_threshold() {
+        assert!(is_low_balance(Decimal::from(2), Decimal::from(10), 20));
+        assert!(!is_low_balance(Decimal::from(3), Decimal::from(10), 20));
+    }
+}
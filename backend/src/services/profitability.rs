@@ -0,0 +1,871 @@
+use crate::notifications::{enqueue_notification, QueuedNotification};
+use crate::services::currency::{self, format_money};
+use crate::services::recurring_billing::round_money;
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use std::collections::HashMap;
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+pub type ProfitabilityResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+pub const PROFITABILITY_COLUMNS: &str = "p.id, p.client_id, c.name as client_name, p.period_start, p.period_end,
+    COALESCE(p.recurring_revenue, 0) as recurring_revenue, COALESCE(p.project_revenue, 0) as project_revenue,
+    COALESCE(p.service_revenue, 0) as service_revenue, COALESCE(p.product_revenue, 0) as product_revenue,
+    COALESCE(p.total_revenue, 0) as total_revenue, COALESCE(p.labor_cost, 0) as labor_cost,
+    COALESCE(p.expense_cost, 0) as expense_cost, COALESCE(p.license_cost, 0) as license_cost,
+    COALESCE(p.total_cost, 0) as total_cost, COALESCE(p.gross_profit, 0) as gross_profit,
+    COALESCE(p.gross_margin_percent, 0) as gross_margin_percent, COALESCE(p.hours_worked, 0) as hours_worked,
+    COALESCE(p.billable_hours, 0) as billable_hours, COALESCE(p.utilization_rate, 0) as utilization_rate,
+    COALESCE(p.effective_hourly_rate, 0) as effective_hourly_rate, COALESCE(p.write_off_amount, 0) as write_off_amount,
+    p.calculated_at";
+
+pub const CONTRACT_PROFITABILITY_COLUMNS: &str = "cp.id, cp.contract_id, ct.name as contract_name, ct.contract_type,
+    cp.client_id, c.name as client_name, cp.period_start, cp.period_end, cp.revenue, cp.revenue_source,
+    cp.hours_worked, cp.labor_cost, cp.gross_profit, cp.gross_margin_percent, cp.effective_hourly_rate,
+    ct.hourly_rate as contract_hourly_rate, cp.calculated_at";
+
+pub const BUDGET_COLUMNS: &str = "b.id, b.client_id, c.name as client_name, b.name, b.budget_type, b.category,
+    b.amount, b.start_date, b.end_date, COALESCE(b.spent_amount, 0) as spent_amount,
+    COALESCE(b.committed_amount, 0) as committed_amount, b.remaining_amount,
+    COALESCE(b.alert_threshold_percent, 80) as alert_threshold_percent, COALESCE(b.alert_sent, false) as alert_sent,
+    b.alert_sent_at, b.exceeded_alert_sent_at, b.status, b.notes, b.created_by, b.created_at, b.updated_at";
+
+pub const BUDGET_TYPES: [&str; 4] = ["monthly", "quarterly", "annual", "project"];
+
+/// Budget categories and the revenue they are measured against.
+pub const BUDGET_CATEGORIES: [&str; 4] = ["support", "projects", "hardware", "software"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ClientProfitability {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub recurring_revenue: Decimal,
+    pub project_revenue: Decimal,
+    pub service_revenue: Decimal,
+    pub product_revenue: Decimal,
+    pub total_revenue: Decimal,
+    pub labor_cost: Decimal,
+    pub expense_cost: Decimal,
+    pub license_cost: Decimal,
+    pub total_cost: Decimal,
+    pub gross_profit: Decimal,
+    pub gross_margin_percent: Decimal,
+    pub hours_worked: Decimal,
+    pub billable_hours: Decimal,
+    pub utilization_rate: Decimal,
+    pub effective_hourly_rate: Decimal,
+    pub write_off_amount: Decimal, // billable work given up in billing review
+    pub calculated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ContractProfitability {
+    pub id: Uuid,
+    pub contract_id: Uuid,
+    pub contract_name: String,
+    pub contract_type: Option<String>,
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub revenue: Decimal,
+    pub revenue_source: String,
+    pub hours_worked: Decimal,
+    pub labor_cost: Decimal,
+    pub gross_profit: Decimal,
+    pub gross_margin_percent: Decimal,
+    pub effective_hourly_rate: Option<Decimal>,
+    pub contract_hourly_rate: Option<Decimal>, // the agreement's own rate, for comparison
+    pub calculated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ClientBudget {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub name: String,
+    pub budget_type: String,
+    pub category: Option<String>,
+    pub amount: Decimal,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub spent_amount: Decimal,
+    pub committed_amount: Decimal,
+    pub remaining_amount: Option<Decimal>,
+    pub alert_threshold_percent: i32,
+    pub alert_sent: bool,
+    pub alert_sent_at: Option<DateTime<Utc>>,
+    pub exceeded_alert_sent_at: Option<DateTime<Utc>>,
+    pub status: Option<String>,
+    pub notes: Option<String>,
+    pub created_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// A budget with its spend so far and where it is heading at the current rate.
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetVariance {
+    #[serde(flatten)]
+    pub budget: ClientBudget,
+    pub variance: Decimal, // amount - spent; negative when over budget
+    pub percent_used: Decimal,
+    pub projected_spend: Decimal,
+    pub projected_variance: Decimal,
+}
+
+/// One client's figures for a month, in the base currency.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientMonth {
+    pub recurring_revenue: Decimal,
+    pub project_revenue: Decimal,
+    pub product_revenue: Decimal,
+    pub total_revenue: Decimal,
+    pub labor_cost: Decimal,
+    pub expense_cost: Decimal,
+    pub license_cost: Decimal,
+    pub write_off_amount: Decimal,
+    pub minutes_worked: i64,
+    pub billable_minutes: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Margins {
+    pub service_revenue: Decimal,
+    pub total_cost: Decimal,
+    pub gross_profit: Decimal,
+    pub gross_margin_percent: Decimal,
+    pub hours_worked: Decimal,
+    pub billable_hours: Decimal,
+    pub utilization_rate: Decimal,
+    pub effective_hourly_rate: Decimal,
+}
+
+impl ClientMonth {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Effective hourly rate is what the client's revenue earns per hour worked
+    /// once pass-through expenses and licenses are taken out.
+    pub fn margins(&self) -> Margins {
+        let total_cost = self.labor_cost + self.expense_cost + self.license_cost;
+        let gross_profit = self.total_revenue - total_cost;
+        let hours_worked = hours(self.minutes_worked);
+
+        Margins {
+            service_revenue: self.total_revenue - self.recurring_revenue - self.project_revenue - self.product_revenue,
+            total_cost,
+            gross_profit,
+            gross_margin_percent: margin_percent(gross_profit, self.total_revenue),
+            hours_worked,
+            billable_hours: hours(self.billable_minutes),
+            utilization_rate: if self.minutes_worked > 0 {
+                (Decimal::from(self.billable_minutes) * Decimal::ONE_HUNDRED / Decimal::from(self.minutes_worked))
+                    .round_dp(2)
+            } else {
+                Decimal::ZERO
+            },
+            effective_hourly_rate: effective_rate(
+                self.total_revenue - self.expense_cost - self.license_cost,
+                self.minutes_worked,
+            )
+            .unwrap_or_default(),
+        }
+    }
+}
+
+/// One agreement's figures for a month.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractMonth {
+    pub revenue: Decimal,
+    pub revenue_source: &'static str,
+    pub hours_worked: Decimal,
+    pub labor_cost: Decimal,
+    pub gross_profit: Decimal,
+    pub gross_margin_percent: Decimal,
+    pub effective_hourly_rate: Option<Decimal>,
+}
+
+/// Revenue is what was invoiced against the agreement, or its monthly value
+/// when the month's billing wasn't tied to it.
+pub fn contract_month(
+    invoiced: Option<Decimal>,
+    monthly_value: Option<Decimal>,
+    minutes_worked: i64,
+    labor_cost: Decimal,
+) -> ContractMonth {
+    let (revenue, revenue_source) = match (invoiced, monthly_value) {
+        (Some(invoiced), _) if !invoiced.is_zero() => (invoiced, "invoiced"),
+        (_, Some(value)) if !value.is_zero() => (value, "contract_value"),
+        _ => (Decimal::ZERO, "invoiced"),
+    };
+    let gross_profit = revenue - labor_cost;
+
+    ContractMonth {
+        revenue,
+        revenue_source,
+        hours_worked: hours(minutes_worked),
+        labor_cost,
+        gross_profit,
+        gross_margin_percent: margin_percent(gross_profit, revenue),
+        effective_hourly_rate: effective_rate(revenue, minutes_worked),
+    }
+}
+
+fn hours(minutes: i64) -> Decimal {
+    (Decimal::from(minutes) / Decimal::from(60)).round_dp(2)
+}
+
+pub fn margin_percent(profit: Decimal, revenue: Decimal) -> Decimal {
+    if revenue.is_zero() {
+        return Decimal::ZERO;
+    }
+    (profit * Decimal::ONE_HUNDRED / revenue).round_dp(2)
+}
+
+pub fn effective_rate(revenue: Decimal, minutes_worked: i64) -> Option<Decimal> {
+    if minutes_worked <= 0 {
+        return None;
+    }
+    Some(round_money(revenue * Decimal::from(60) / Decimal::from(minutes_worked)))
+}
+
+pub fn month_bounds(date: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let start = date.with_day(1).unwrap_or(date);
+    let end = start + Months::new(1) - chrono::Duration::days(1);
+    (start, end)
+}
+
+/// The revenue a budget category is measured against.
+pub fn budget_actual(category: Option<&str>, month: &ClientProfitability) -> Decimal {
+    match category {
+        Some("support") => month.recurring_revenue + month.service_revenue,
+        Some("projects") => month.project_revenue,
+        Some("hardware") | Some("software") => month.product_revenue,
+        _ => month.total_revenue,
+    }
+}
+
+/// Spend at the end of the period if it carries on at the rate so far.
+pub fn projected_spend(spent: Decimal, start: NaiveDate, end: NaiveDate, as_of: NaiveDate) -> Decimal {
+    if as_of >= end {
+        return spent;
+    }
+    let elapsed = (as_of - start).num_days() + 1;
+    if elapsed <= 0 {
+        return spent;
+    }
+    let total = (end - start).num_days() + 1;
+    round_money(spent * Decimal::from(total) / Decimal::from(elapsed))
+}
+
+impl BudgetVariance {
+    pub fn new(budget: ClientBudget, as_of: NaiveDate) -> Self {
+        let spent = budget.spent_amount;
+        let projected_spend = projected_spend(spent, budget.start_date, budget.end_date, as_of);
+
+        Self {
+            variance: budget.amount - spent,
+            percent_used: margin_percent(spent, budget.amount),
+            projected_variance: budget.amount - projected_spend,
+            projected_spend,
+            budget,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetAlert {
+    Threshold,
+    Exceeded,
+}
+
+/// Alerts due for a budget's spend; each fires once.
+pub fn budget_alerts(budget: &ClientBudget, spent: Decimal) -> Vec<BudgetAlert> {
+    let mut alerts = Vec::new();
+    if budget.amount <= Decimal::ZERO {
+        return alerts;
+    }
+
+    let percent = spent * Decimal::ONE_HUNDRED / budget.amount;
+    if spent > budget.amount {
+        if budget.exceeded_alert_sent_at.is_none() {
+            alerts.push(BudgetAlert::Exceeded);
+        }
+    } else if percent >= Decimal::from(budget.alert_threshold_percent) && !budget.alert_sent {
+        alerts.push(BudgetAlert::Threshold);
+    }
+    alerts
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct RevenueRow {
+    client_id: Uuid,
+    total_revenue: Decimal,
+    project_revenue: Decimal,
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct LineRevenueRow {
+    client_id: Uuid,
+    recurring_revenue: Decimal,
+    product_revenue: Decimal,
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct LaborRow {
+    client_id: Uuid,
+    minutes_worked: i64,
+    billable_minutes: i64,
+    labor_cost: Decimal,
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct ContractRow {
+    contract_id: Uuid,
+    client_id: Uuid,
+    invoiced_revenue: Option<Decimal>,
+    monthly_value: Option<Decimal>,
+    minutes_worked: i64,
+    labor_cost: Decimal,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProfitabilityConfig {
+    pub calculation_interval_seconds: u64, // How often the current and previous month are recalculated
+    pub default_cost_rate: Decimal,        // Hourly cost for technicians without a cost_rate, from DEFAULT_LABOR_COST_RATE
+}
+
+impl Default for ProfitabilityConfig {
+    fn default() -> Self {
+        Self {
+            calculation_interval_seconds: 24 * 60 * 60,
+            default_cost_rate: std::env::var("DEFAULT_LABOR_COST_RATE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_else(|| Decimal::from(50)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ProfitabilityService {
+    config: ProfitabilityConfig,
+    db_pool: PgPool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProfitabilityRunSummary {
+    pub clients: u32,
+    pub contracts: u32,
+    pub budgets_updated: u32,
+    pub budget_alerts: u32,
+}
+
+impl ProfitabilityService {
+    pub fn new(config: ProfitabilityConfig, db_pool: PgPool) -> Self {
+        Self { config, db_pool }
+    }
+
+    pub async fn start(&self) -> ProfitabilityResult<()> {
+        info!("Starting profitability worker");
+
+        let mut calculation_interval = interval(Duration::from_secs(self.config.calculation_interval_seconds));
+
+        tokio::spawn({
+            let service = self.clone();
+            async move {
+                loop {
+                    calculation_interval.tick().await;
+
+                    if let Err(e) = service.run(Utc::now().date_naive()).await {
+                        error!("Error calculating profitability: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Recalculates the current and previous month, so late invoices and time
+    /// edits are picked up, then updates budgets against the new figures.
+    pub async fn run(&self, today: NaiveDate) -> ProfitabilityResult<ProfitabilityRunSummary> {
+        let (current, _) = month_bounds(today);
+        let previous = current - Months::new(1);
+
+        let mut summary = ProfitabilityRunSummary::default();
+        for month in [previous, current] {
+            let month_summary = self.calculate_month(month).await?;
+            summary.clients += month_summary.clients;
+            summary.contracts += month_summary.contracts;
+        }
+        self.update_budgets(today, &mut summary).await?;
+
+        info!(
+            "Calculated profitability for {} client months and {} agreement months",
+            summary.clients, summary.contracts
+        );
+
+        Ok(summary)
+    }
+
+    pub async fn calculate_month(&self, month: NaiveDate) -> ProfitabilityResult<ProfitabilityRunSummary> {
+        let (start, end) = month_bounds(month);
+        let mut months: HashMap<Uuid, ClientMonth> = HashMap::new();
+
+        // Revenue is pre-tax and converted at each invoice's own rate
+        for row in sqlx::query_as::<_, RevenueRow>(
+            "SELECT client_id,
+                    COALESCE(SUM(ROUND(COALESCE(subtotal, 0) * exchange_rate, 2)), 0) as total_revenue,
+                    COALESCE(SUM(ROUND(COALESCE(subtotal, 0) * exchange_rate, 2)) FILTER (WHERE project_id IS NOT NULL), 0)
+                        as project_revenue
+             FROM invoices
+             WHERE date BETWEEN $1 AND $2 AND COALESCE(status, 'draft') NOT IN ('draft', 'void')
+             GROUP BY client_id",
+        )
+        .bind(start)
+        .bind(end)
+        .fetch_all(&self.db_pool)
+        .await?
+        {
+            let m = months.entry(row.client_id).or_default();
+            m.total_revenue = row.total_revenue;
+            m.project_revenue = row.project_revenue;
+        }
+
+        for row in sqlx::query_as::<_, LineRevenueRow>(
+            "SELECT i.client_id,
+                    COALESCE(SUM(ROUND(COALESCE(li.line_total, li.total_price, 0) * i.exchange_rate, 2))
+                        FILTER (WHERE li.source_type IN ('recurring', 'adjustment', 'usage')), 0) as recurring_revenue,
+                    COALESCE(SUM(ROUND(COALESCE(li.line_total, li.total_price, 0) * i.exchange_rate, 2))
+                        FILTER (WHERE li.source_type = 'expense'), 0) as product_revenue
+             FROM invoice_line_items li
+             JOIN invoices i ON i.id = li.invoice_id
+             WHERE i.date BETWEEN $1 AND $2 AND COALESCE(i.status, 'draft') NOT IN ('draft', 'void')
+               AND i.project_id IS NULL AND li.voided_at IS NULL
+             GROUP BY i.client_id",
+        )
+        .bind(start)
+        .bind(end)
+        .fetch_all(&self.db_pool)
+        .await?
+        {
+            let m = months.entry(row.client_id).or_default();
+            m.recurring_revenue = row.recurring_revenue;
+            m.product_revenue = row.product_revenue;
+        }
+
+        for row in sqlx::query_as::<_, LaborRow>(
+            "SELECT COALESCE(t.client_id, p.client_id) as client_id,
+                    COALESCE(SUM(COALESCE(te.duration_minutes, 0)), 0)::BIGINT as minutes_worked,
+                    COALESCE(SUM(COALESCE(te.duration_minutes, 0)) FILTER (WHERE te.billable), 0)::BIGINT
+                        as billable_minutes,
+                    COALESCE(SUM(ROUND(COALESCE(te.duration_minutes, 0) * COALESCE(u.cost_rate, $3) / 60.0, 2)), 0)
+                        as labor_cost
+             FROM time_entries te
+             JOIN users u ON u.id = te.user_id
+             LEFT JOIN tickets t ON t.id = te.ticket_id
+             LEFT JOIN projects p ON p.id = te.project_id
+             WHERE te.start_time::date BETWEEN $1 AND $2 AND COALESCE(t.client_id, p.client_id) IS NOT NULL
+             GROUP BY 1",
+        )
+        .bind(start)
+        .bind(end)
+        .bind(self.config.default_cost_rate)
+        .fetch_all(&self.db_pool)
+        .await?
+        {
+            let m = months.entry(row.client_id).or_default();
+            m.minutes_worked = row.minutes_worked;
+            m.billable_minutes = row.billable_minutes;
+            m.labor_cost = row.labor_cost;
+        }
+
+        let expenses: Vec<(Uuid, Decimal)> = sqlx::query_as(
+            "SELECT client_id, COALESCE(SUM(amount), 0) FROM expenses
+             WHERE client_id IS NOT NULL AND expense_date BETWEEN $1 AND $2 AND COALESCE(status, 'pending') <> 'rejected'
+             GROUP BY client_id",
+        )
+        .bind(start)
+        .bind(end)
+        .fetch_all(&self.db_pool)
+        .await?;
+        for (client_id, amount) in expenses {
+            months.entry(client_id).or_default().expense_cost = amount;
+        }
+
+        let licenses: Vec<(Uuid, Decimal)> = sqlx::query_as(
+            "SELECT client_id, COALESCE(SUM(ROUND(annual_cost / 12, 2)), 0) FROM licenses
+             WHERE annual_cost IS NOT NULL AND COALESCE(status, 'active') <> 'terminated'
+               AND COALESCE(start_date, purchase_date, $1) <= $2 AND (end_date IS NULL OR end_date >= $1)
+             GROUP BY client_id",
+        )
+        .bind(start)
+        .bind(end)
+        .fetch_all(&self.db_pool)
+        .await?;
+        for (client_id, amount) in licenses {
+            months.entry(client_id).or_default().license_cost = amount;
+        }
+
+        // Written-off work earns nothing; its labour is already in labour cost
+        let write_offs: Vec<(Uuid, Decimal)> = sqlx::query_as(
+            "SELECT client_id, COALESCE(SUM(base_amount), 0) FROM billing_write_offs
+             WHERE work_date BETWEEN $1 AND $2
+             GROUP BY client_id",
+        )
+        .bind(start)
+        .bind(end)
+        .fetch_all(&self.db_pool)
+        .await?;
+        for (client_id, amount) in write_offs {
+            months.entry(client_id).or_default().write_off_amount = amount;
+        }
+
+        let mut summary = ProfitabilityRunSummary::default();
+        for (client_id, month) in months.iter().filter(|(_, m)| !m.is_empty()) {
+            let margins = month.margins();
+            sqlx::query(
+                r#"
+                INSERT INTO client_profitability (
+                    client_id, period_start, period_end,
+                    recurring_revenue, project_revenue, service_revenue, product_revenue, total_revenue,
+                    labor_cost, expense_cost, license_cost, overhead_cost, total_cost,
+                    gross_profit, gross_margin_percent, net_profit, net_margin_percent,
+                    hours_worked, billable_hours, utilization_rate, effective_hourly_rate, write_off_amount, calculated_at
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, 0, $12, $13, $14, $13, $14, $15, $16, $17, $18, $19,
+                          NOW())
+                ON CONFLICT (client_id, period_start, period_end) DO UPDATE SET
+                    recurring_revenue = EXCLUDED.recurring_revenue,
+                    project_revenue = EXCLUDED.project_revenue,
+                    service_revenue = EXCLUDED.service_revenue,
+                    product_revenue = EXCLUDED.product_revenue,
+                    total_revenue = EXCLUDED.total_revenue,
+                    labor_cost = EXCLUDED.labor_cost,
+                    expense_cost = EXCLUDED.expense_cost,
+                    license_cost = EXCLUDED.license_cost,
+                    total_cost = EXCLUDED.total_cost,
+                    gross_profit = EXCLUDED.gross_profit,
+                    gross_margin_percent = EXCLUDED.gross_margin_percent,
+                    net_profit = EXCLUDED.net_profit,
+                    net_margin_percent = EXCLUDED.net_margin_percent,
+                    hours_worked = EXCLUDED.hours_worked,
+                    billable_hours = EXCLUDED.billable_hours,
+                    utilization_rate = EXCLUDED.utilization_rate,
+                    effective_hourly_rate = EXCLUDED.effective_hourly_rate,
+                    write_off_amount = EXCLUDED.write_off_amount,
+                    calculated_at = NOW()
+                "#,
+            )
+            .bind(client_id)
+            .bind(start)
+            .bind(end)
+            .bind(month.recurring_revenue)
+            .bind(month.project_revenue)
+            .bind(margins.service_revenue)
+            .bind(month.product_revenue)
+            .bind(month.total_revenue)
+            .bind(month.labor_cost)
+            .bind(month.expense_cost)
+            .bind(month.license_cost)
+            .bind(margins.total_cost)
+            .bind(margins.gross_profit)
+            .bind(margins.gross_margin_percent)
+            .bind(margins.hours_worked)
+            .bind(margins.billable_hours)
+            .bind(margins.utilization_rate)
+            .bind(margins.effective_hourly_rate)
+            .bind(month.write_off_amount)
+            .execute(&self.db_pool)
+            .await?;
+            summary.clients += 1;
+        }
+
+        summary.contracts = self.calculate_contracts(start, end).await?;
+        Ok(summary)
+    }
+
+    /// Ticket time counts towards the agreement of the ticket's SLA, or the
+    /// client's only active agreement when the SLA doesn't name one.
+    async fn calculate_contracts(&self, start: NaiveDate, end: NaiveDate) -> ProfitabilityResult<u32> {
+        let rows = sqlx::query_as::<_, ContractRow>(
+            r#"
+            WITH active AS (
+                SELECT id, client_id, monthly_value FROM contracts
+                WHERE start_date <= $2 AND (end_date IS NULL OR end_date >= $1)
+                  AND COALESCE(status, 'active') NOT IN ('draft', 'cancelled')
+            ),
+            sole AS (
+                SELECT client_id, (ARRAY_AGG(id))[1] as contract_id FROM active GROUP BY client_id HAVING COUNT(*) = 1
+            ),
+            work AS (
+                SELECT COALESCE(s.contract_id, sole.contract_id) as contract_id,
+                       COALESCE(SUM(COALESCE(te.duration_minutes, 0)), 0)::BIGINT as minutes_worked,
+                       COALESCE(SUM(ROUND(COALESCE(te.duration_minutes, 0) * COALESCE(u.cost_rate, $3) / 60.0, 2)), 0)
+                           as labor_cost
+                FROM time_entries te
+                JOIN tickets t ON t.id = te.ticket_id
+                JOIN users u ON u.id = te.user_id
+                LEFT JOIN slas s ON s.id = t.sla_id
+                LEFT JOIN sole ON sole.client_id = t.client_id
+                WHERE te.start_time::date BETWEEN $1 AND $2
+                GROUP BY 1
+            ),
+            billed AS (
+                SELECT contract_id, SUM(ROUND(COALESCE(subtotal, 0) * exchange_rate, 2)) as revenue
+                FROM invoices
+                WHERE contract_id IS NOT NULL AND date BETWEEN $1 AND $2
+                  AND COALESCE(status, 'draft') NOT IN ('draft', 'void')
+                GROUP BY contract_id
+            )
+            SELECT a.id as contract_id, a.client_id, b.revenue as invoiced_revenue, a.monthly_value,
+                   COALESCE(w.minutes_worked, 0) as minutes_worked, COALESCE(w.labor_cost, 0) as labor_cost
+            FROM active a
+            LEFT JOIN work w ON w.contract_id = a.id
+            LEFT JOIN billed b ON b.contract_id = a.id
+            "#,
+        )
+        .bind(start)
+        .bind(end)
+        .bind(self.config.default_cost_rate)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let mut count = 0;
+        for row in rows {
+            let month = contract_month(row.invoiced_revenue, row.monthly_value, row.minutes_worked, row.labor_cost);
+            sqlx::query(
+                r#"
+                INSERT INTO contract_profitability (contract_id, client_id, period_start, period_end, revenue,
+                    revenue_source, hours_worked, labor_cost, gross_profit, gross_margin_percent,
+                    effective_hourly_rate, calculated_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, NOW())
+                ON CONFLICT (contract_id, period_start) DO UPDATE SET
+                    period_end = EXCLUDED.period_end,
+                    revenue = EXCLUDED.revenue,
+                    revenue_source = EXCLUDED.revenue_source,
+                    hours_worked = EXCLUDED.hours_worked,
+                    labor_cost = EXCLUDED.labor_cost,
+                    gross_profit = EXCLUDED.gross_profit,
+                    gross_margin_percent = EXCLUDED.gross_margin_percent,
+                    effective_hourly_rate = EXCLUDED.effective_hourly_rate,
+                    calculated_at = NOW()
+                "#,
+            )
+            .bind(row.contract_id)
+            .bind(row.client_id)
+            .bind(start)
+            .bind(end)
+            .bind(month.revenue)
+            .bind(month.revenue_source)
+            .bind(month.hours_worked)
+            .bind(month.labor_cost)
+            .bind(month.gross_profit)
+            .bind(month.gross_margin_percent)
+            .bind(month.effective_hourly_rate)
+            .execute(&self.db_pool)
+            .await?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Recomputes spend on open budgets from the monthly figures and alerts
+    /// the budget owner at the alert threshold and again when it is exceeded.
+    pub async fn update_budgets(&self, today: NaiveDate, summary: &mut ProfitabilityRunSummary) -> ProfitabilityResult<()> {
+        let budgets = sqlx::query_as::<_, ClientBudget>(&format!(
+            "SELECT {} FROM client_budgets b JOIN clients c ON c.id = b.client_id
+             WHERE COALESCE(b.status, 'active') IN ('active', 'exceeded') AND b.end_date >= $1",
+            BUDGET_COLUMNS
+        ))
+        .bind(today - chrono::Duration::days(31))
+        .fetch_all(&self.db_pool)
+        .await?;
+        if budgets.is_empty() {
+            return Ok(());
+        }
+
+        let mut conn = self.db_pool.acquire().await?;
+        let base_currency = currency::base_currency(&mut conn).await?;
+        drop(conn);
+
+        for budget in budgets {
+            let months = sqlx::query_as::<_, ClientProfitability>(&format!(
+                "SELECT {} FROM client_profitability p JOIN clients c ON c.id = p.client_id
+                 WHERE p.client_id = $1 AND p.period_start >= DATE_TRUNC('month', $2::DATE)::DATE AND p.period_start <= $3",
+                PROFITABILITY_COLUMNS
+            ))
+            .bind(budget.client_id)
+            .bind(budget.start_date)
+            .bind(budget.end_date)
+            .fetch_all(&self.db_pool)
+            .await?;
+            let spent: Decimal = months.iter().map(|m| budget_actual(budget.category.as_deref(), m)).sum();
+            let alerts = budget_alerts(&budget, spent);
+
+            sqlx::query(
+                "UPDATE client_budgets SET
+                     spent_amount = $2,
+                     remaining_amount = amount - $2,
+                     status = CASE WHEN $2 > amount THEN 'exceeded' ELSE 'active' END,
+                     alert_sent = alert_sent OR $3,
+                     alert_sent_at = CASE WHEN $3 THEN NOW() ELSE alert_sent_at END,
+                     exceeded_alert_sent_at = CASE WHEN $4 THEN NOW() ELSE exceeded_alert_sent_at END,
+                     updated_at = NOW()
+                 WHERE id = $1",
+            )
+            .bind(budget.id)
+            .bind(spent)
+            .bind(alerts.contains(&BudgetAlert::Threshold))
+            .bind(alerts.contains(&BudgetAlert::Exceeded))
+            .execute(&self.db_pool)
+            .await?;
+            summary.budgets_updated += 1;
+
+            let Some(user_id) = budget.created_by else { continue };
+            for alert in alerts {
+                let (title, priority) = match alert {
+                    BudgetAlert::Threshold => (
+                        format!("{} budget '{}' is at {}%", budget.client_name, budget.name, budget.alert_threshold_percent),
+                        "normal",
+                    ),
+                    BudgetAlert::Exceeded => (format!("{} budget '{}' exceeded", budget.client_name, budget.name), "high"),
+                };
+                let notification = QueuedNotification::for_user(
+                    user_id,
+                    "budget_alert",
+                    title,
+                    format!(
+                        "{} of {} spent between {} and {}.",
+                        format_money(spent, &base_currency),
+                        format_money(budget.amount, &base_currency),
+                        budget.start_date.format("%Y-%m-%d"),
+                        budget.end_date.format("%Y-%m-%d")
+                    ),
+                )
+                .with_priority(priority)
+                .with_entity("client_budget", budget.id);
+
+                match enqueue_notification(&self.db_pool, notification).await {
+                    Ok(_) => summary.budget_alerts += 1,
+                    Err(e) => warn!("Failed to queue budget alert {}: {}", budget.id, e),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(amount: i64, threshold: i32) -> ClientBudget {
+        ClientBudget {
+            id: Uuid::new_v4(),
+            client_id: Uuid::new_v4(),
+            client_name: "Acme".to_string(),
+            name: "FY24 support".to_string(),
+            budget_type: "annual".to_string(),
+            category: Some("support".to_string()),
+            amount: Decimal::from(amount),
+            start_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            end_date: NaiveDate::from_ymd_opt(2024, 12, 31).unwrap(),
+            spent_amount: Decimal::ZERO,
+            committed_amount: Decimal::ZERO,
+            remaining_amount: None,
+            alert_threshold_percent: threshold,
+            alert_sent: false,
+            alert_sent_at: None,
+            exceeded_alert_sent_at: None,
+            status: Some("active".to_string()),
+            notes: None,
+            created_by: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_client_month_margins() {
+        // A flat-fee client: $1,500 a month, 30 hours of work at $55/h cost, $200 of licenses
+        let month = ClientMonth {
+            recurring_revenue: Decimal::from(1500),
+            total_revenue: Decimal::from(1500),
+            labor_cost: Decimal::from(1650),
+            license_cost: Decimal::from(200),
+            minutes_worked: 30 * 60,
+            billable_minutes: 6 * 60,
+            ..Default::default()
+        };
+
+        let margins = month.margins();
+        assert_eq!(margins.total_cost, Decimal::from(1850));
+        assert_eq!(margins.gross_profit, Decimal::from(-350));
+        assert_eq!(margins.gross_margin_percent, Decimal::new(-2333, 2));
+        assert_eq!(margins.hours_worked, Decimal::from(30));
+        assert_eq!(margins.utilization_rate, Decimal::from(20));
+        assert_eq!(margins.service_revenue, Decimal::ZERO);
+        // (1500 - 200) / 30h
+        assert_eq!(margins.effective_hourly_rate, Decimal::new(4333, 2));
+
+        assert!(ClientMonth::default().is_empty());
+        assert_eq!(ClientMonth::default().margins().effective_hourly_rate, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_contract_month() {
+        let invoiced = contract_month(Some(Decimal::from(2000)), Some(Decimal::from(1800)), 25 * 60, Decimal::from(1250));
+        assert_eq!(invoiced.revenue_source, "invoiced");
+        assert_eq!(invoiced.effective_hourly_rate, Some(Decimal::from(80)));
+        assert_eq!(invoiced.gross_profit, Decimal::from(750));
+        assert_eq!(invoiced.gross_margin_percent, Decimal::new(3750, 2));
+
+        let fallback = contract_month(None, Some(Decimal::from(1800)), 0, Decimal::ZERO);
+        assert_eq!(fallback.revenue, Decimal::from(1800));
+        assert_eq!(fallback.revenue_source, "contract_value");
+        assert_eq!(fallback.effective_hourly_rate, None);
+    }
+
+    #[test]
+    fn test_month_bounds() {
+        let (start, end) = month_bounds(NaiveDate::from_ymd_opt(2024, 2, 17).unwrap());
+        assert_eq!(start, NaiveDate::from_ymd_opt(2024, 2, 1).unwrap());
+        assert_eq!(end, NaiveDate::from_ymd_opt(2024, 2, 29).unwrap());
+        let (_, end) = month_bounds(NaiveDate::from_ymd_opt(2024, 12, 31).unwrap());
+        assert_eq!(end, NaiveDate::from_ymd_opt(2024, 12, 31).unwrap());
+    }
+
+    #[test]
+    fn test_budget_alerts_and_projection() {
+        let b = budget(10000, 80);
+        assert!(budget_alerts(&b, Decimal::from(7999)).is_empty());
+        assert_eq!(budget_alerts(&b, Decimal::from(8000)), vec![BudgetAlert::Threshold]);
+        assert_eq!(budget_alerts(&b, Decimal::from(10001)), vec![BudgetAlert::Exceeded]);
+
+        let warned = ClientBudget { alert_sent: true, ..b.clone() };
+        assert!(budget_alerts(&warned, Decimal::from(9000)).is_empty());
+        let exceeded = ClientBudget { exceeded_alert_sent_at: Some(Utc::now()), ..warned };
+        assert!(budget_alerts(&exceeded, Decimal::from(12000)).is_empty());
+
+        // A quarter of the year gone with 3,000 spent heads for roughly 12,000
+        let spent = ClientBudget { spent_amount: Decimal::from(3000), ..b };
+        let variance = BudgetVariance::new(spent, NaiveDate::from_ymd_opt(2024, 3, 31).unwrap());
+        assert_eq!(variance.variance, Decimal::from(7000));
+        assert_eq!(variance.percent_used, Decimal::from(30));
+        assert_eq!(variance.projected_spend, Decimal::new(1206593, 2));
+        assert!(variance.projected_variance < Decimal::ZERO);
+    }
+}
@@ -0,0 +1,641 @@
+use crate::notifications::{enqueue_notification, QueuedNotification};
+use crate::services::currency::{self, format_money};
+use crate::services::project_schedule::{self, PlanTaskRow, HOURS_PER_DURATION_DAY};
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+pub type ProjectBudgetResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Percent of budget at which the project manager is alerted, once each.
+pub const BUDGET_ALERT_THRESHOLDS: [i32; 3] = [50, 80, 100];
+
+pub const BILLING_TYPES: &[&str] = &["fixed_fee", "time_and_materials"];
+
+pub const CHANGE_ORDER_COLUMNS: &str = "co.id, co.project_id, p.name as project_name, p.client_id, co.number,
+    co.title, co.description, co.amount_change, co.hours_change, co.schedule_days_change, co.status, co.contact_id,
+    co.sent_at, co.approved_at, co.approved_name, co.approved_by_contact_id, co.approved_by_user_id, co.rejected_at,
+    co.rejection_reason, co.created_by, co.created_at, co.updated_at";
+
+// Fixed-fee projects burn every hour and expense (at cost) against the fee;
+// time-and-materials projects burn what the client will be billed.
+const BASIS_QUERY: &str = r#"
+    SELECT p.id as project_id, p.name as project_name, p.client_id, c.name as client_name,
+           COALESCE(p.status, 'active') as status, p.billing_type, p.start_date, p.end_date,
+           p.budget as base_budget, p.budget_hours as base_budget_hours, p.budget_alert_level, p.project_manager_id,
+           COALESCE(co.amount, 0) as approved_amount, COALESCE(co.hours, 0) as approved_hours,
+           COALESCE(co.days, 0)::int as approved_schedule_days,
+           COALESCE(te.hours, 0) as hours_used, COALESCE(te.amount, 0) as labor_amount,
+           COALESCE(ex.amount, 0) as expense_amount
+    FROM projects p
+    JOIN clients c ON c.id = p.client_id
+    LEFT JOIN LATERAL (
+        SELECT SUM(amount_change) as amount, SUM(hours_change) as hours, SUM(schedule_days_change) as days
+        FROM project_change_orders WHERE project_id = p.id AND status = 'approved'
+    ) co ON true
+    LEFT JOIN LATERAL (
+        SELECT SUM(te.duration_minutes) / 60.0 as hours,
+               SUM(COALESCE(te.total_amount, te.duration_minutes / 60.0 * COALESCE(te.hourly_rate, p.hourly_rate, 0))) as amount
+        FROM time_entries te
+        WHERE te.project_id = p.id AND te.end_time IS NOT NULL
+          AND (p.billing_type = 'fixed_fee' OR te.billable)
+    ) te ON true
+    LEFT JOIN LATERAL (
+        SELECT SUM(CASE WHEN p.billing_type = 'fixed_fee' THEN e.amount
+                        ELSE COALESCE(e.billed_amount, ROUND(e.amount * (1 + COALESCE(e.markup_percent, 0) / 100), 2)) END) as amount
+        FROM expenses e
+        WHERE e.project_id = p.id AND COALESCE(e.status, 'pending') <> 'rejected'
+          AND (p.billing_type = 'fixed_fee' OR COALESCE(e.is_billable, false))
+    ) ex ON true
+"#;
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ChangeOrder {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    pub project_name: String,
+    pub client_id: Uuid,
+    pub number: i32,
+    pub title: String,
+    pub description: Option<String>,
+    pub amount_change: Decimal,
+    pub hours_change: Decimal,
+    pub schedule_days_change: i32,
+    pub status: String,
+    pub contact_id: Option<Uuid>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub approved_at: Option<DateTime<Utc>>,
+    pub approved_name: Option<String>,
+    pub approved_by_contact_id: Option<Uuid>,
+    pub approved_by_user_id: Option<Uuid>,
+    pub rejected_at: Option<DateTime<Utc>>,
+    pub rejection_reason: Option<String>,
+    pub created_by: Uuid,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl ChangeOrder {
+    pub fn reference(&self) -> String {
+        format!("CO-{}", self.number)
+    }
+}
+
+/// A project's budget with approved change orders, and what has been burnt
+/// against it.
+#[derive(Debug, Clone, FromRow)]
+pub struct BudgetBasis {
+    pub project_id: Uuid,
+    pub project_name: String,
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub status: String,
+    pub billing_type: String,
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    pub base_budget: Option<Decimal>,
+    pub base_budget_hours: Option<Decimal>,
+    pub budget_alert_level: i32,
+    pub project_manager_id: Option<Uuid>,
+    pub approved_amount: Decimal,
+    pub approved_hours: Decimal,
+    pub approved_schedule_days: i32,
+    pub hours_used: Decimal,
+    pub labor_amount: Decimal,
+    pub expense_amount: Decimal,
+}
+
+impl BudgetBasis {
+    pub fn budget_amount(&self) -> Option<Decimal> {
+        self.base_budget.map(|b| b + self.approved_amount)
+    }
+
+    pub fn budget_hours(&self) -> Option<Decimal> {
+        self.base_budget_hours.map(|h| h + self.approved_hours)
+    }
+
+    pub fn spent_amount(&self) -> Decimal {
+        self.labor_amount + self.expense_amount
+    }
+
+    /// The further burnt of hours and money.
+    pub fn percent_used(&self) -> Option<Decimal> {
+        let amount = percent_of(self.spent_amount(), self.budget_amount());
+        let hours = percent_of(self.hours_used, self.budget_hours());
+        amount.into_iter().chain(hours).max()
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct DailyBurn {
+    pub date: NaiveDate,
+    pub hours: Decimal,
+    pub amount: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct BurnDownPoint {
+    pub date: NaiveDate,
+    pub hours_remaining: Option<Decimal>,
+    pub amount_remaining: Option<Decimal>,
+    pub ideal_hours_remaining: Option<Decimal>,
+    pub ideal_amount_remaining: Option<Decimal>,
+}
+
+/// A task's share of the work and how far along it is, for earned value.
+#[derive(Debug, Clone)]
+pub struct WorkItem {
+    pub weight: Decimal,
+    pub start: Option<NaiveDate>,
+    pub finish: Option<NaiveDate>,
+    pub progress: Decimal, // 0 to 1
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct EarnedValue {
+    pub budget_at_completion: Decimal,
+    pub planned_percent: Decimal,
+    pub earned_percent: Decimal,
+    pub planned_value: Decimal,
+    pub earned_value: Decimal,
+    pub actual_cost: Decimal,
+    pub cost_variance: Decimal,     // EV - AC; negative is over budget
+    pub schedule_variance: Decimal, // EV - PV; negative is behind schedule
+    pub cost_performance_index: Option<Decimal>,
+    pub schedule_performance_index: Option<Decimal>,
+    pub estimate_at_completion: Option<Decimal>,
+    pub variance_at_completion: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectBudget {
+    pub project_id: Uuid,
+    pub project_name: String,
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub billing_type: String,
+    pub currency: String,
+    pub base_budget_amount: Option<Decimal>,
+    pub change_order_amount: Decimal,
+    pub budget_amount: Option<Decimal>,
+    pub base_budget_hours: Option<Decimal>,
+    pub change_order_hours: Decimal,
+    pub budget_hours: Option<Decimal>,
+    pub change_order_schedule_days: i32,
+    pub hours_used: Decimal,
+    pub labor_amount: Decimal,
+    pub expense_amount: Decimal,
+    pub spent_amount: Decimal,
+    pub hours_remaining: Option<Decimal>,
+    pub amount_remaining: Option<Decimal>,
+    pub hours_percent_used: Option<Decimal>,
+    pub amount_percent_used: Option<Decimal>,
+    pub alert_level: i32,
+    pub pending_change_orders: i64,
+    pub earned_value: Option<EarnedValue>,
+    pub burn_down: Vec<BurnDownPoint>,
+}
+
+pub fn percent_of(used: Decimal, budget: Option<Decimal>) -> Option<Decimal> {
+    let budget = budget.filter(|b| *b > Decimal::ZERO)?;
+    Some((used * Decimal::ONE_HUNDRED / budget).round_dp(1))
+}
+
+/// The highest alert threshold `percent` has reached, or 0.
+pub fn threshold_reached(percent: Decimal) -> i32 {
+    BUDGET_ALERT_THRESHOLDS
+        .iter()
+        .copied()
+        .filter(|t| percent >= Decimal::from(*t))
+        .max()
+        .unwrap_or(0)
+}
+
+/// The threshold to alert at, if one above the last alert has been reached.
+/// Skipped thresholds are folded into the highest one.
+pub fn alert_due(percent: Decimal, sent_level: i32) -> Option<i32> {
+    let reached = threshold_reached(percent);
+    (reached > sent_level).then_some(reached)
+}
+
+/// Remaining budget week by week from the project start, next to a straight
+/// line to zero at the project end. Both are against today's budget.
+pub fn burn_down(
+    start: NaiveDate,
+    end: Option<NaiveDate>,
+    today: NaiveDate,
+    budget_hours: Option<Decimal>,
+    budget_amount: Option<Decimal>,
+    daily: &[DailyBurn],
+) -> Vec<BurnDownPoint> {
+    let mut dates = Vec::new();
+    let mut date = start;
+    while date < today {
+        dates.push(date);
+        date = date + Days::new(7);
+    }
+    dates.push(today.max(start));
+
+    let span = end.filter(|e| *e > start).map(|e| Decimal::from((e - start).num_days()));
+    let ideal = |budget: Option<Decimal>, date: NaiveDate| {
+        let (budget, span) = (budget?, span?);
+        let elapsed = Decimal::from((date - start).num_days()).min(span);
+        Some((budget * (Decimal::ONE - elapsed / span)).round_dp(2))
+    };
+
+    dates
+        .into_iter()
+        .map(|date| {
+            let (hours, amount) = daily
+                .iter()
+                .filter(|d| d.date <= date)
+                .fold((Decimal::ZERO, Decimal::ZERO), |(h, a), d| (h + d.hours, a + d.amount));
+            BurnDownPoint {
+                date,
+                hours_remaining: budget_hours.map(|b| (b - hours).round_dp(2)),
+                amount_remaining: budget_amount.map(|b| (b - amount).round_dp(2)),
+                ideal_hours_remaining: ideal(budget_hours, date),
+                ideal_amount_remaining: ideal(budget_amount, date),
+            }
+        })
+        .collect()
+}
+
+/// Share of the work that should be done by `today`. Tasks without dates
+/// follow the project's own dates, or are left out.
+pub fn planned_fraction(
+    items: &[WorkItem],
+    project_start: Option<NaiveDate>,
+    project_end: Option<NaiveDate>,
+    today: NaiveDate,
+) -> Option<Decimal> {
+    let elapsed = |start: NaiveDate, finish: NaiveDate| {
+        if today < start {
+            return Decimal::ZERO;
+        }
+        if today >= finish {
+            return Decimal::ONE;
+        }
+        Decimal::from((today - start).num_days() + 1) / Decimal::from((finish - start).num_days() + 1)
+    };
+
+    let (mut planned, mut total) = (Decimal::ZERO, Decimal::ZERO);
+    for item in items {
+        let span = match (item.start.or(project_start), item.finish.or(project_end)) {
+            (Some(start), Some(finish)) if finish >= start => (start, finish),
+            _ => continue,
+        };
+        planned += item.weight * elapsed(span.0, span.1);
+        total += item.weight;
+    }
+    (total > Decimal::ZERO).then(|| planned / total)
+}
+
+/// Share of the work done, weighted by each task's size.
+pub fn earned_fraction(items: &[WorkItem]) -> Option<Decimal> {
+    let total: Decimal = items.iter().map(|i| i.weight).sum();
+    (total > Decimal::ZERO).then(|| items.iter().map(|i| i.weight * i.progress).sum::<Decimal>() / total)
+}
+
+pub fn earned_value(
+    budget_at_completion: Decimal,
+    planned_fraction: Decimal,
+    earned_fraction: Decimal,
+    actual_cost: Decimal,
+) -> EarnedValue {
+    let planned_value = (budget_at_completion * planned_fraction).round_dp(2);
+    let earned = (budget_at_completion * earned_fraction).round_dp(2);
+    let ratio = |numerator: Decimal, denominator: Decimal| {
+        (denominator > Decimal::ZERO).then(|| (numerator / denominator).round_dp(2))
+    };
+    let cpi = ratio(earned, actual_cost);
+    let estimate_at_completion = cpi
+        .filter(|c| *c > Decimal::ZERO)
+        .map(|c| (budget_at_completion / c).round_dp(2));
+
+    EarnedValue {
+        budget_at_completion,
+        planned_percent: (planned_fraction * Decimal::ONE_HUNDRED).round_dp(1),
+        earned_percent: (earned_fraction * Decimal::ONE_HUNDRED).round_dp(1),
+        planned_value,
+        earned_value: earned,
+        actual_cost,
+        cost_variance: earned - actual_cost,
+        schedule_variance: earned - planned_value,
+        cost_performance_index: cpi,
+        schedule_performance_index: ratio(earned, planned_value),
+        estimate_at_completion,
+        variance_at_completion: estimate_at_completion.map(|eac| budget_at_completion - eac),
+    }
+}
+
+/// Tasks as work items: sized by estimate, or by duration at a full day's
+/// work, with progress from time logged against the estimate.
+pub fn work_items(tasks: &[PlanTaskRow]) -> Vec<WorkItem> {
+    tasks
+        .iter()
+        .filter(|t| t.status != "cancelled")
+        .map(|t| WorkItem {
+            weight: t
+                .estimated_hours
+                .filter(|e| *e > Decimal::ZERO)
+                .unwrap_or_else(|| Decimal::from(t.duration().max(1) as i64 * HOURS_PER_DURATION_DAY)),
+            start: t.start_date,
+            finish: t.due_date,
+            progress: Decimal::from(t.progress_percent()) / Decimal::ONE_HUNDRED,
+        })
+        .collect()
+}
+
+pub async fn load_basis(pool: &PgPool, project_id: Uuid) -> ProjectBudgetResult<Option<BudgetBasis>> {
+    Ok(sqlx::query_as::<_, BudgetBasis>(&format!("{} WHERE p.id = $1", BASIS_QUERY))
+        .bind(project_id)
+        .fetch_optional(pool)
+        .await?)
+}
+
+pub async fn daily_burn(pool: &PgPool, project_id: Uuid) -> ProjectBudgetResult<Vec<DailyBurn>> {
+    Ok(sqlx::query_as::<_, DailyBurn>(
+        r#"
+        SELECT date, SUM(hours) as hours, SUM(amount) as amount FROM (
+            SELECT te.start_time::date as date, te.duration_minutes / 60.0 as hours,
+                   COALESCE(te.total_amount, te.duration_minutes / 60.0 * COALESCE(te.hourly_rate, p.hourly_rate, 0)) as amount
+            FROM time_entries te JOIN projects p ON p.id = te.project_id
+            WHERE te.project_id = $1 AND te.end_time IS NOT NULL AND (p.billing_type = 'fixed_fee' OR te.billable)
+            UNION ALL
+            SELECT e.expense_date, 0,
+                   CASE WHEN p.billing_type = 'fixed_fee' THEN e.amount
+                        ELSE COALESCE(e.billed_amount, ROUND(e.amount * (1 + COALESCE(e.markup_percent, 0) / 100), 2)) END
+            FROM expenses e JOIN projects p ON p.id = e.project_id
+            WHERE e.project_id = $1 AND COALESCE(e.status, 'pending') <> 'rejected'
+              AND (p.billing_type = 'fixed_fee' OR COALESCE(e.is_billable, false))
+        ) burn
+        GROUP BY date
+        ORDER BY date
+        "#,
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn project_budget(pool: &PgPool, project_id: Uuid, today: NaiveDate) -> ProjectBudgetResult<Option<ProjectBudget>> {
+    let Some(basis) = load_basis(pool, project_id).await? else {
+        return Ok(None);
+    };
+    let daily = daily_burn(pool, project_id).await?;
+    let (tasks, _) = project_schedule::load_plan(pool, project_id).await?;
+    let pending_change_orders: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM project_change_orders WHERE project_id = $1 AND status = 'sent'",
+    )
+    .bind(project_id)
+    .fetch_one(pool)
+    .await?;
+    let mut conn = pool.acquire().await?;
+    let currency = currency::client_currency(&mut conn, basis.client_id).await?;
+    drop(conn);
+
+    let end_date = basis
+        .end_date
+        .map(|end| end + Days::new(basis.approved_schedule_days.max(0) as u64));
+    let items = work_items(&tasks);
+    let earned = match (
+        basis.budget_amount(),
+        planned_fraction(&items, basis.start_date, end_date, today),
+        earned_fraction(&items),
+    ) {
+        (Some(bac), Some(planned), Some(earned)) => Some(earned_value(bac, planned, earned, basis.spent_amount())),
+        _ => None,
+    };
+    let start = basis
+        .start_date
+        .or_else(|| daily.first().map(|d| d.date))
+        .unwrap_or(today);
+
+    Ok(Some(ProjectBudget {
+        project_id,
+        project_name: basis.project_name.clone(),
+        client_id: basis.client_id,
+        client_name: basis.client_name.clone(),
+        billing_type: basis.billing_type.clone(),
+        currency,
+        base_budget_amount: basis.base_budget,
+        change_order_amount: basis.approved_amount,
+        budget_amount: basis.budget_amount(),
+        base_budget_hours: basis.base_budget_hours,
+        change_order_hours: basis.approved_hours,
+        budget_hours: basis.budget_hours(),
+        change_order_schedule_days: basis.approved_schedule_days,
+        hours_used: basis.hours_used.round_dp(2),
+        labor_amount: basis.labor_amount.round_dp(2),
+        expense_amount: basis.expense_amount.round_dp(2),
+        spent_amount: basis.spent_amount().round_dp(2),
+        hours_remaining: basis.budget_hours().map(|b| (b - basis.hours_used).round_dp(2)),
+        amount_remaining: basis.budget_amount().map(|b| (b - basis.spent_amount()).round_dp(2)),
+        hours_percent_used: percent_of(basis.hours_used, basis.budget_hours()),
+        amount_percent_used: percent_of(basis.spent_amount(), basis.budget_amount()),
+        alert_level: basis.budget_alert_level,
+        pending_change_orders,
+        earned_value: earned,
+        burn_down: burn_down(start, end_date, today, basis.budget_hours(), basis.budget_amount(), &daily),
+    }))
+}
+
+pub async fn get_change_order(pool: &PgPool, id: Uuid) -> ProjectBudgetResult<Option<ChangeOrder>> {
+    Ok(sqlx::query_as::<_, ChangeOrder>(&format!(
+        "SELECT {} FROM project_change_orders co JOIN projects p ON p.id = co.project_id WHERE co.id = $1",
+        CHANGE_ORDER_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?)
+}
+
+/// Resets the alert level to what the project has burnt against its new
+/// budget, so a change order that adds budget re-arms the lower alerts
+/// without sending them again straight away.
+pub async fn rebaseline_alerts(pool: &PgPool, project_id: Uuid) -> ProjectBudgetResult<()> {
+    let Some(basis) = load_basis(pool, project_id).await? else {
+        return Ok(());
+    };
+    let level = basis.percent_used().map(threshold_reached).unwrap_or(0);
+    sqlx::query("UPDATE projects SET budget_alert_level = $2, updated_at = NOW() WHERE id = $1")
+        .bind(project_id)
+        .bind(level)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct ProjectBudgetConfig {
+    pub check_interval_seconds: u64,
+}
+
+impl Default for ProjectBudgetConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_seconds: 60 * 60,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ProjectBudgetService {
+    config: ProjectBudgetConfig,
+    db_pool: PgPool,
+}
+
+impl ProjectBudgetService {
+    pub fn new(config: ProjectBudgetConfig, db_pool: PgPool) -> Self {
+        Self { config, db_pool }
+    }
+
+    pub async fn start(&self) -> ProjectBudgetResult<()> {
+        info!("Starting project budget worker");
+
+        let mut check_interval = interval(Duration::from_secs(self.config.check_interval_seconds));
+
+        tokio::spawn({
+            let service = self.clone();
+            async move {
+                loop {
+                    check_interval.tick().await;
+
+                    if let Err(e) = service.check_budgets().await {
+                        error!("Error checking project budgets: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Alerts project managers as active projects cross 50%, 80% and 100%
+    /// of their hours or money budget.
+    pub async fn check_budgets(&self) -> ProjectBudgetResult<u32> {
+        let projects = sqlx::query_as::<_, BudgetBasis>(&format!(
+            "{} WHERE COALESCE(p.status, 'active') = 'active' AND (p.budget IS NOT NULL OR p.budget_hours IS NOT NULL)",
+            BASIS_QUERY
+        ))
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let mut alerts = 0;
+        for basis in projects {
+            let Some(percent) = basis.percent_used() else { continue };
+            let Some(level) = alert_due(percent, basis.budget_alert_level) else { continue };
+
+            sqlx::query("UPDATE projects SET budget_alert_level = $2, budget_alerted_at = NOW() WHERE id = $1")
+                .bind(basis.project_id)
+                .bind(level)
+                .execute(&self.db_pool)
+                .await?;
+
+            let Some(manager_id) = basis.project_manager_id else { continue };
+            let mut conn = self.db_pool.acquire().await?;
+            let currency = currency::client_currency(&mut conn, basis.client_id).await?;
+            drop(conn);
+
+            let mut used = Vec::new();
+            if let Some(budget) = basis.budget_amount() {
+                used.push(format!("{} of {}", format_money(basis.spent_amount(), &currency), format_money(budget, &currency)));
+            }
+            if let Some(hours) = basis.budget_hours() {
+                used.push(format!("{} of {} hours", basis.hours_used.round_dp(1), hours.round_dp(1)));
+            }
+            let (title, priority) = if level >= 100 {
+                (format!("{} is over budget", basis.project_name), "high")
+            } else {
+                (format!("{} has used {}% of its budget", basis.project_name, level), "normal")
+            };
+            let notification = QueuedNotification::for_user(
+                manager_id,
+                "project_budget_alert",
+                title,
+                format!("{} ({}) has used {}.", basis.project_name, basis.client_name, used.join(" and ")),
+            )
+            .with_priority(priority)
+            .with_entity("project", basis.project_id);
+
+            match enqueue_notification(&self.db_pool, notification).await {
+                Ok(_) => alerts += 1,
+                Err(e) => warn!("Failed to queue budget alert for project {}: {}", basis.project_id, e),
+            }
+        }
+
+        if alerts > 0 {
+            info!("Sent {} project budget alerts", alerts);
+        }
+        Ok(alerts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_alert_thresholds() {
+        assert_eq!(threshold_reached(Decimal::from(49)), 0);
+        assert_eq!(alert_due(Decimal::from(50), 0), Some(50));
+        assert_eq!(alert_due(Decimal::from(79), 50), None);
+        // A jump past two thresholds alerts once, at the higher
+        assert_eq!(alert_due(Decimal::new(1015, 1), 50), Some(100));
+        assert_eq!(alert_due(Decimal::from(130), 100), None);
+        assert_eq!(percent_of(Decimal::from(30), Some(Decimal::ZERO)), None);
+        assert_eq!(percent_of(Decimal::from(30), Some(Decimal::from(40))), Some(Decimal::from(75)));
+    }
+
+    #[test]
+    fn test_burn_down() {
+        let daily = vec![
+            DailyBurn { date: date(3, 4), hours: Decimal::from(10), amount: Decimal::from(1500) },
+            DailyBurn { date: date(3, 12), hours: Decimal::from(6), amount: Decimal::from(900) },
+        ];
+        let points = burn_down(
+            date(3, 4),
+            Some(date(3, 24)),
+            date(3, 14),
+            Some(Decimal::from(40)),
+            Some(Decimal::from(6000)),
+            &daily,
+        );
+
+        assert_eq!(points.iter().map(|p| p.date).collect::<Vec<_>>(), vec![date(3, 4), date(3, 11), date(3, 14)]);
+        assert_eq!(points[0].amount_remaining, Some(Decimal::from(4500)));
+        assert_eq!(points[1].hours_remaining, Some(Decimal::from(30)));
+        assert_eq!(points[2].hours_remaining, Some(Decimal::from(24)));
+        assert_eq!(points[2].ideal_amount_remaining, Some(Decimal::from(3000)));
+    }
+
+    #[test]
+    fn test_earned_value() {
+        let items = vec![
+            WorkItem { weight: Decimal::from(30), start: Some(date(3, 4)), finish: Some(date(3, 8)), progress: Decimal::ONE },
+            WorkItem { weight: Decimal::from(10), start: Some(date(3, 11)), finish: Some(date(3, 20)), progress: Decimal::ZERO },
+        ];
+        // Day 5 of 10 on the second task
+        let planned = planned_fraction(&items, None, None, date(3, 15)).unwrap();
+        assert_eq!(planned, Decimal::new(875, 3));
+        let earned = earned_fraction(&items).unwrap();
+        assert_eq!(earned, Decimal::new(75, 2));
+
+        let ev = earned_value(Decimal::from(10000), planned, earned, Decimal::from(9375));
+        assert_eq!(ev.planned_value, Decimal::from(8750));
+        assert_eq!(ev.earned_value, Decimal::from(7500));
+        assert_eq!(ev.schedule_variance, Decimal::from(-1250));
+        assert_eq!(ev.cost_performance_index, Some(Decimal::new(80, 2)));
+        assert_eq!(ev.estimate_at_completion, Some(Decimal::from(12500)));
+        assert_eq!(ev.variance_at_completion, Some(Decimal::from(-2500)));
+    }
+}
@@ -0,0 +1,735 @@
+use crate::services::timesheets::{self, UserSchedule};
+use chrono::{Datelike, Days, NaiveDate, Utc, Weekday};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use uuid::Uuid;
+
+pub type ScheduleResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Working hours in a day of task duration, for tasks sized only by estimate.
+pub const HOURS_PER_DURATION_DAY: i64 = 8;
+
+pub const TEMPLATE_TASK_COLUMNS: &str = "id, template_id, sort_order, name, description, start_offset_days, duration_days,
+    estimated_hours, is_milestone, priority, created_at";
+
+pub const PLAN_TASK_COLUMNS: &str = "t.id, t.name, COALESCE(t.status, 'todo') as status, t.assigned_to,
+    CASE WHEN u.id IS NOT NULL THEN u.first_name || ' ' || u.last_name ELSE NULL END as assigned_name,
+    t.estimated_hours, COALESCE(te.time_logged, 0) as time_logged, t.duration_days, t.start_constraint, t.start_date,
+    t.due_date, t.is_milestone, t.sort_order, t.completed_at";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScheduleError {
+    Cycle(Vec<Uuid>), // tasks on or behind a dependency loop
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::Cycle(ids) => write!(f, "dependency cycle between {} tasks", ids.len()),
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+#[derive(Debug, Clone)]
+pub struct PlanTask {
+    pub id: Uuid,
+    pub duration_days: i32,
+    pub min_offset: i32, // earliest start, in working days after project start
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PlanDependency {
+    pub id: Uuid,
+    pub task_id: Uuid,    // successor
+    pub depends_on: Uuid, // predecessor
+    pub lag_days: i32,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ScheduledTask {
+    pub id: Uuid,
+    pub start_offset: i32,
+    pub finish_offset: i32, // exclusive; equal to start for milestones
+    pub start_date: NaiveDate,
+    pub finish_date: NaiveDate, // last working day of the task
+    pub slack_days: i32,
+    pub critical: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TemplateTask {
+    pub id: Uuid,
+    pub template_id: Uuid,
+    pub sort_order: i32,
+    pub name: String,
+    pub description: Option<String>,
+    pub start_offset_days: i32,
+    pub duration_days: i32,
+    pub estimated_hours: Option<Decimal>,
+    pub is_milestone: bool,
+    pub priority: Option<String>,
+    pub created_at: Option<chrono::DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct PlanTaskRow {
+    pub id: Uuid,
+    pub name: String,
+    pub status: String,
+    pub assigned_to: Option<Uuid>,
+    pub assigned_name: Option<String>,
+    pub estimated_hours: Option<Decimal>,
+    pub time_logged: Decimal,
+    pub duration_days: Option<i32>,
+    pub start_constraint: Option<NaiveDate>,
+    pub start_date: Option<NaiveDate>,
+    pub due_date: Option<NaiveDate>,
+    pub is_milestone: bool,
+    pub sort_order: i32,
+    pub completed_at: Option<chrono::DateTime<Utc>>,
+}
+
+impl PlanTaskRow {
+    pub fn duration(&self) -> i32 {
+        duration_for(self.duration_days, self.estimated_hours, self.is_milestone)
+    }
+
+    pub fn progress_percent(&self) -> i32 {
+        if self.status == "completed" {
+            return 100;
+        }
+        match self.estimated_hours.filter(|e| *e > Decimal::ZERO) {
+            Some(estimate) => (self.time_logged / estimate * Decimal::from(100)).round().to_i32().unwrap_or(0).min(99),
+            None => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GanttLink {
+    pub dependency_id: Uuid,
+    pub depends_on: Uuid,
+    pub lag_days: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GanttTask {
+    pub id: Uuid,
+    pub name: String,
+    pub status: String,
+    pub assigned_to: Option<Uuid>,
+    pub assigned_name: Option<String>,
+    pub start_date: NaiveDate,
+    pub finish_date: NaiveDate,
+    pub duration_days: i32,
+    pub is_milestone: bool,
+    pub progress_percent: i32,
+    pub slack_days: i32,
+    pub critical: bool,
+    pub overdue: bool,
+    pub dependencies: Vec<GanttLink>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Gantt {
+    pub project_id: Uuid,
+    pub start_date: NaiveDate,
+    pub finish_date: NaiveDate,
+    pub tasks: Vec<GanttTask>,
+    pub critical_path: Vec<Uuid>,
+    pub milestones: Vec<GanttTask>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct Booking {
+    pub task_id: Uuid,
+    pub user_id: Uuid,
+    pub start: NaiveDate,
+    pub finish: NaiveDate,
+    pub hours: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CapacityConflict {
+    pub user_id: Uuid,
+    pub user_name: String,
+    pub date: NaiveDate,
+    pub booked_hours: Decimal,
+    pub capacity_hours: Decimal,
+    pub task_ids: Vec<Uuid>,
+}
+
+pub fn is_working_day(date: NaiveDate) -> bool {
+    !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+}
+
+/// `date` if it's a working day, otherwise the Monday after.
+pub fn next_working_day(date: NaiveDate) -> NaiveDate {
+    let mut date = date;
+    while !is_working_day(date) {
+        date = date + Days::new(1);
+    }
+    date
+}
+
+/// The working day `offset` working days after the project starts.
+pub fn working_day_at(start: NaiveDate, offset: i32) -> NaiveDate {
+    let mut date = next_working_day(start);
+    for _ in 0..offset.max(0) {
+        date = next_working_day(date + Days::new(1));
+    }
+    date
+}
+
+/// Working days from `start` up to, not including, `date`.
+pub fn working_days_between(start: NaiveDate, date: NaiveDate) -> i32 {
+    let mut count = 0;
+    let mut day = next_working_day(start);
+    while day < date {
+        count += 1;
+        day = next_working_day(day + Days::new(1));
+    }
+    count
+}
+
+/// Working days a task takes: as set, or from its estimate at a full day's
+/// work per day. Milestones take none.
+pub fn duration_for(duration_days: Option<i32>, estimated_hours: Option<Decimal>, is_milestone: bool) -> i32 {
+    if is_milestone {
+        return 0;
+    }
+    if let Some(days) = duration_days {
+        return days.max(0);
+    }
+    let hours = estimated_hours.unwrap_or(Decimal::ZERO);
+    (hours / Decimal::from(HOURS_PER_DURATION_DAY)).ceil().to_i32().unwrap_or(1).max(1)
+}
+
+/// Schedules tasks as early as their constraints and finish-to-start
+/// dependencies allow, then works back from the project finish to find each
+/// task's slack. Tasks without slack are on the critical path.
+pub fn schedule(
+    project_start: NaiveDate,
+    tasks: &[PlanTask],
+    dependencies: &[PlanDependency],
+) -> Result<Vec<ScheduledTask>, ScheduleError> {
+    let index: HashMap<Uuid, usize> = tasks.iter().enumerate().map(|(i, t)| (t.id, i)).collect();
+    let links: Vec<(usize, usize, i32)> = dependencies
+        .iter()
+        .filter_map(|d| Some((*index.get(&d.depends_on)?, *index.get(&d.task_id)?, d.lag_days)))
+        .collect();
+
+    let mut successors: Vec<Vec<(usize, i32)>> = vec![Vec::new(); tasks.len()];
+    let mut predecessors: Vec<Vec<(usize, i32)>> = vec![Vec::new(); tasks.len()];
+    let mut waiting = vec![0usize; tasks.len()];
+    for &(pred, succ, lag) in &links {
+        successors[pred].push((succ, lag));
+        predecessors[succ].push((pred, lag));
+        waiting[succ] += 1;
+    }
+
+    // Kahn's algorithm, keeping the given order among ready tasks
+    let mut ready: VecDeque<usize> = (0..tasks.len()).filter(|&i| waiting[i] == 0).collect();
+    let mut order = Vec::with_capacity(tasks.len());
+    while let Some(i) = ready.pop_front() {
+        order.push(i);
+        for &(succ, _) in &successors[i] {
+            waiting[succ] -= 1;
+            if waiting[succ] == 0 {
+                ready.push_back(succ);
+            }
+        }
+    }
+    if order.len() < tasks.len() {
+        let stuck = (0..tasks.len()).filter(|&i| waiting[i] > 0).map(|i| tasks[i].id).collect();
+        return Err(ScheduleError::Cycle(stuck));
+    }
+
+    let mut early_start = vec![0i32; tasks.len()];
+    for &i in &order {
+        let after_predecessors = predecessors[i]
+            .iter()
+            .map(|&(pred, lag)| early_start[pred] + tasks[pred].duration_days + lag)
+            .max()
+            .unwrap_or(0);
+        early_start[i] = after_predecessors.max(tasks[i].min_offset).max(0);
+    }
+
+    let project_finish = order.iter().map(|&i| early_start[i] + tasks[i].duration_days).max().unwrap_or(0);
+    let mut late_start = vec![0i32; tasks.len()];
+    for &i in order.iter().rev() {
+        let late_finish = successors[i]
+            .iter()
+            .map(|&(succ, lag)| late_start[succ] - lag)
+            .min()
+            .unwrap_or(project_finish)
+            .min(project_finish);
+        late_start[i] = late_finish - tasks[i].duration_days;
+    }
+
+    Ok(tasks
+        .iter()
+        .enumerate()
+        .map(|(i, task)| {
+            let start = early_start[i];
+            let finish = start + task.duration_days;
+            let slack = (late_start[i] - start).max(0);
+            ScheduledTask {
+                id: task.id,
+                start_offset: start,
+                finish_offset: finish,
+                start_date: working_day_at(project_start, start),
+                finish_date: working_day_at(project_start, (finish - 1).max(start)),
+                slack_days: slack,
+                critical: slack == 0,
+            }
+        })
+        .collect())
+}
+
+/// Critical tasks from first to last.
+pub fn critical_path(scheduled: &[ScheduledTask]) -> Vec<Uuid> {
+    let mut critical: Vec<&ScheduledTask> = scheduled.iter().filter(|t| t.critical).collect();
+    critical.sort_by_key(|t| (t.start_offset, t.finish_offset));
+    critical.into_iter().map(|t| t.id).collect()
+}
+
+/// Days a technician is booked beyond their expected hours. Each task's
+/// estimate is spread evenly over its working days.
+pub fn capacity_conflicts(bookings: &[Booking], schedules: &HashMap<Uuid, UserSchedule>) -> Vec<CapacityConflict> {
+    let mut days: BTreeMap<(Uuid, NaiveDate), (Decimal, Vec<Uuid>)> = BTreeMap::new();
+    for booking in bookings {
+        let mut working = Vec::new();
+        let mut day = booking.start;
+        while day <= booking.finish {
+            if is_working_day(day) {
+                working.push(day);
+            }
+            day = day + Days::new(1);
+        }
+        if working.is_empty() || booking.hours <= Decimal::ZERO {
+            continue;
+        }
+        let per_day = booking.hours / Decimal::from(working.len() as i64);
+        for date in working {
+            let entry = days.entry((booking.user_id, date)).or_insert((Decimal::ZERO, Vec::new()));
+            entry.0 += per_day;
+            entry.1.push(booking.task_id);
+        }
+    }
+
+    days.into_iter()
+        .filter_map(|((user_id, date), (booked, task_ids))| {
+            let schedule = schedules.get(&user_id)?;
+            let capacity = Decimal::from(schedule.expected_minutes(date)) / Decimal::from(60);
+            let booked = booked.round_dp(2);
+            (booked > capacity).then(|| CapacityConflict {
+                user_id,
+                user_name: schedule.user_name.clone(),
+                date,
+                booked_hours: booked,
+                capacity_hours: capacity.round_dp(2),
+                task_ids,
+            })
+        })
+        .collect()
+}
+
+pub async fn load_plan(pool: &PgPool, project_id: Uuid) -> ScheduleResult<(Vec<PlanTaskRow>, Vec<PlanDependency>)> {
+    let tasks = sqlx::query_as::<_, PlanTaskRow>(&format!(
+        "SELECT {} FROM tasks t
+         LEFT JOIN users u ON u.id = t.assigned_to
+         LEFT JOIN (
+            SELECT task_id, SUM(duration_minutes) / 60.0 as time_logged
+            FROM time_entries WHERE task_id IS NOT NULL GROUP BY task_id
+         ) te ON te.task_id = t.id
+         WHERE t.project_id = $1
+         ORDER BY t.sort_order, t.created_at",
+        PLAN_TASK_COLUMNS
+    ))
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    let dependencies = sqlx::query_as::<_, PlanDependency>(
+        "SELECT d.id, d.task_id, d.depends_on_task_id as depends_on, d.lag_days
+         FROM task_dependencies d
+         JOIN tasks t ON t.id = d.task_id
+         WHERE t.project_id = $1",
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok((tasks, dependencies))
+}
+
+/// Where a project's schedule is anchored: its start date, else its
+/// earliest constrained task, else today.
+pub async fn project_start(pool: &PgPool, project_id: Uuid, tasks: &[PlanTaskRow]) -> ScheduleResult<NaiveDate> {
+    let start: Option<NaiveDate> = sqlx::query_scalar("SELECT start_date FROM projects WHERE id = $1")
+        .bind(project_id)
+        .fetch_optional(pool)
+        .await?
+        .flatten();
+    Ok(start
+        .or_else(|| tasks.iter().filter_map(|t| t.start_constraint).min())
+        .unwrap_or_else(|| Utc::now().date_naive()))
+}
+
+pub fn plan_tasks(start: NaiveDate, tasks: &[PlanTaskRow]) -> Vec<PlanTask> {
+    tasks
+        .iter()
+        .map(|t| PlanTask {
+            id: t.id,
+            duration_days: t.duration(),
+            min_offset: t.start_constraint.map(|c| working_days_between(start, c)).unwrap_or(0),
+        })
+        .collect()
+}
+
+/// Recalculates a project's schedule and stores each task's dates and the
+/// project's end date.
+pub async fn reschedule(pool: &PgPool, project_id: Uuid) -> ScheduleResult<Vec<ScheduledTask>> {
+    let (tasks, dependencies) = load_plan(pool, project_id).await?;
+    let start = project_start(pool, project_id, &tasks).await?;
+    let scheduled = schedule(start, &plan_tasks(start, &tasks), &dependencies)?;
+
+    let mut tx = pool.begin().await?;
+    for task in &scheduled {
+        sqlx::query("UPDATE tasks SET start_date = $2, due_date = $3, updated_at = NOW() WHERE id = $1")
+            .bind(task.id)
+            .bind(task.start_date)
+            .bind(task.finish_date)
+            .execute(&mut *tx)
+            .await?;
+    }
+    if let Some(finish) = scheduled.iter().map(|t| t.finish_date).max() {
+        sqlx::query("UPDATE projects SET end_date = $2, updated_at = NOW() WHERE id = $1")
+            .bind(project_id)
+            .bind(finish)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(scheduled)
+}
+
+/// Gantt chart data, scheduled live from the current plan.
+pub async fn gantt(pool: &PgPool, project_id: Uuid) -> ScheduleResult<Gantt> {
+    let (tasks, dependencies) = load_plan(pool, project_id).await?;
+    let start = project_start(pool, project_id, &tasks).await?;
+    let scheduled = schedule(start, &plan_tasks(start, &tasks), &dependencies)?;
+    let today = Utc::now().date_naive();
+
+    let by_id: HashMap<Uuid, &ScheduledTask> = scheduled.iter().map(|s| (s.id, s)).collect();
+    let gantt_tasks: Vec<GanttTask> = tasks
+        .iter()
+        .filter_map(|t| {
+            let s = by_id.get(&t.id)?;
+            Some(GanttTask {
+                id: t.id,
+                name: t.name.clone(),
+                status: t.status.clone(),
+                assigned_to: t.assigned_to,
+                assigned_name: t.assigned_name.clone(),
+                start_date: s.start_date,
+                finish_date: s.finish_date,
+                duration_days: t.duration(),
+                is_milestone: t.is_milestone,
+                progress_percent: t.progress_percent(),
+                slack_days: s.slack_days,
+                critical: s.critical,
+                overdue: t.status != "completed" && s.finish_date < today,
+                dependencies: dependencies
+                    .iter()
+                    .filter(|d| d.task_id == t.id)
+                    .map(|d| GanttLink { dependency_id: d.id, depends_on: d.depends_on, lag_days: d.lag_days })
+                    .collect(),
+            })
+        })
+        .collect();
+
+    Ok(Gantt {
+        project_id,
+        start_date: next_working_day(start),
+        finish_date: scheduled.iter().map(|t| t.finish_date).max().unwrap_or(start),
+        critical_path: critical_path(&scheduled),
+        milestones: gantt_tasks.iter().filter(|t| t.is_milestone).cloned().collect(),
+        tasks: gantt_tasks,
+    })
+}
+
+/// Over-booked days for everyone assigned to the project, across all their
+/// open scheduled tasks in the project's date range.
+pub async fn project_capacity(pool: &PgPool, project_id: Uuid) -> ScheduleResult<Vec<CapacityConflict>> {
+    let range: Option<(Option<NaiveDate>, Option<NaiveDate>)> = sqlx::query_as(
+        "SELECT MIN(start_date), MAX(due_date) FROM tasks WHERE project_id = $1 AND assigned_to IS NOT NULL",
+    )
+    .bind(project_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some((Some(from), Some(to))) = range else {
+        return Ok(Vec::new());
+    };
+
+    // Unestimated work fills the days it is scheduled for
+    let bookings = sqlx::query_as::<_, Booking>(
+        "SELECT t.id as task_id, t.assigned_to as user_id, t.start_date as start, t.due_date as finish,
+                COALESCE(t.estimated_hours, CASE WHEN t.is_milestone THEN 0 ELSE COALESCE(t.duration_days, 1) * $4 END) as hours
+         FROM tasks t
+         WHERE t.assigned_to IN (SELECT assigned_to FROM tasks WHERE project_id = $1 AND assigned_to IS NOT NULL)
+           AND t.start_date IS NOT NULL AND t.due_date IS NOT NULL
+           AND t.start_date <= $3 AND t.due_date >= $2
+           AND COALESCE(t.status, 'todo') NOT IN ('completed', 'cancelled')",
+    )
+    .bind(project_id)
+    .bind(from)
+    .bind(to)
+    .bind(Decimal::from(HOURS_PER_DURATION_DAY))
+    .fetch_all(pool)
+    .await?;
+
+    let mut schedules = HashMap::new();
+    for user_id in bookings.iter().map(|b| b.user_id) {
+        if schedules.contains_key(&user_id) {
+            continue;
+        }
+        if let Some(schedule) = timesheets::load_schedule(pool, user_id).await? {
+            schedules.insert(user_id, schedule);
+        }
+    }
+
+    // Only days this project contributes to are its problem
+    let project_tasks: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM tasks WHERE project_id = $1")
+        .bind(project_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(capacity_conflicts(&bookings, &schedules)
+        .into_iter()
+        .filter(|c| c.task_ids.iter().any(|id| project_tasks.contains(id)))
+        .collect())
+}
+
+pub async fn load_template_plan(
+    pool: &PgPool,
+    template_id: Uuid,
+) -> ScheduleResult<(Vec<TemplateTask>, Vec<PlanDependency>)> {
+    let tasks = sqlx::query_as::<_, TemplateTask>(&format!(
+        "SELECT {} FROM project_template_tasks WHERE template_id = $1 ORDER BY sort_order, created_at",
+        TEMPLATE_TASK_COLUMNS
+    ))
+    .bind(template_id)
+    .fetch_all(pool)
+    .await?;
+
+    let dependencies = sqlx::query_as::<_, PlanDependency>(
+        "SELECT id, task_id, depends_on_id as depends_on, lag_days
+         FROM project_template_dependencies WHERE template_id = $1",
+    )
+    .bind(template_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok((tasks, dependencies))
+}
+
+/// A template's tasks as they would be planned in a new project.
+pub fn template_plan(tasks: &[TemplateTask]) -> Vec<PlanTask> {
+    tasks
+        .iter()
+        .map(|t| PlanTask {
+            id: t.id,
+            duration_days: if t.is_milestone { 0 } else { t.duration_days },
+            min_offset: t.start_offset_days,
+        })
+        .collect()
+}
+
+/// Copies a template's tasks and dependencies into a project. Relative
+/// starts become start constraints; assignments map template tasks to
+/// technicians, with `default_assignee` for the rest.
+pub async fn create_tasks_from_template(
+    tx: &mut Transaction<'_, Postgres>,
+    template_id: Uuid,
+    project_id: Uuid,
+    project_start: NaiveDate,
+    assignments: &HashMap<Uuid, Uuid>,
+    default_assignee: Option<Uuid>,
+) -> ScheduleResult<usize> {
+    let template_tasks = sqlx::query_as::<_, TemplateTask>(&format!(
+        "SELECT {} FROM project_template_tasks WHERE template_id = $1 ORDER BY sort_order, created_at",
+        TEMPLATE_TASK_COLUMNS
+    ))
+    .bind(template_id)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let mut task_ids = HashMap::new();
+    for task in &template_tasks {
+        let start_constraint = (task.start_offset_days > 0).then(|| working_day_at(project_start, task.start_offset_days));
+        let id: Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO tasks (project_id, name, description, assigned_to, priority, estimated_hours, start_constraint,
+                               duration_days, is_milestone, sort_order, template_task_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            RETURNING id
+            "#,
+        )
+        .bind(project_id)
+        .bind(&task.name)
+        .bind(&task.description)
+        .bind(assignments.get(&task.id).copied().or(default_assignee))
+        .bind(task.priority.as_deref().unwrap_or("medium"))
+        .bind(task.estimated_hours)
+        .bind(start_constraint)
+        .bind(if task.is_milestone { 0 } else { task.duration_days })
+        .bind(task.is_milestone)
+        .bind(task.sort_order)
+        .bind(task.id)
+        .fetch_one(&mut **tx)
+        .await?;
+        task_ids.insert(task.id, id);
+    }
+
+    let dependencies: Vec<(Uuid, Uuid, i32)> = sqlx::query_as(
+        "SELECT task_id, depends_on_id, lag_days FROM project_template_dependencies WHERE template_id = $1",
+    )
+    .bind(template_id)
+    .fetch_all(&mut **tx)
+    .await?;
+    for (task_id, depends_on, lag_days) in dependencies {
+        let (Some(task_id), Some(depends_on)) = (task_ids.get(&task_id), task_ids.get(&depends_on)) else {
+            continue;
+        };
+        sqlx::query("INSERT INTO task_dependencies (task_id, depends_on_task_id, lag_days) VALUES ($1, $2, $3)")
+            .bind(task_id)
+            .bind(depends_on)
+            .bind(lag_days)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    Ok(template_tasks.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    fn task(duration_days: i32) -> PlanTask {
+        PlanTask { id: Uuid::new_v4(), duration_days, min_offset: 0 }
+    }
+
+    fn link(successor: &PlanTask, predecessor: &PlanTask, lag_days: i32) -> PlanDependency {
+        PlanDependency { id: Uuid::new_v4(), task_id: successor.id, depends_on: predecessor.id, lag_days }
+    }
+
+    #[test]
+    fn test_working_days() {
+        // 2024-03-08 is a Friday
+        assert_eq!(next_working_day(date(3, 9)), date(3, 11));
+        assert_eq!(working_day_at(date(3, 8), 0), date(3, 8));
+        assert_eq!(working_day_at(date(3, 8), 1), date(3, 11));
+        assert_eq!(working_day_at(date(3, 9), 5), date(3, 18));
+        assert_eq!(working_days_between(date(3, 8), date(3, 12)), 2);
+        assert_eq!(duration_for(None, Some(Decimal::from(20)), false), 3);
+        assert_eq!(duration_for(Some(4), None, true), 0);
+        assert_eq!(duration_for(None, None, false), 1);
+    }
+
+    #[test]
+    fn test_schedule_finish_to_start_with_lag() {
+        // Kickoff (1) -> Procurement (3) -> +2 days shipping -> Install (2) -> Go-live milestone
+        //             \-> Documentation (2) ------------------------------/
+        let kickoff = task(1);
+        let procurement = task(3);
+        let install = task(2);
+        let docs = task(2);
+        let go_live = task(0);
+        let tasks = vec![kickoff.clone(), procurement.clone(), install.clone(), docs.clone(), go_live.clone()];
+        let deps = vec![
+            link(&procurement, &kickoff, 0),
+            link(&install, &procurement, 2),
+            link(&docs, &kickoff, 0),
+            link(&go_live, &install, 0),
+            link(&go_live, &docs, 0),
+        ];
+
+        let scheduled = schedule(date(3, 4), &tasks, &deps).unwrap();
+        let by_id: HashMap<Uuid, &ScheduledTask> = scheduled.iter().map(|s| (s.id, s)).collect();
+
+        assert_eq!(by_id[&procurement.id].start_date, date(3, 5));
+        assert_eq!(by_id[&procurement.id].finish_date, date(3, 7));
+        // Two working days of lag carry over the weekend
+        assert_eq!(by_id[&install.id].start_date, date(3, 12));
+        assert_eq!(by_id[&install.id].finish_date, date(3, 13));
+        assert_eq!(by_id[&go_live.id].start_date, date(3, 14));
+        assert_eq!(by_id[&go_live.id].finish_date, date(3, 14));
+
+        assert_eq!(by_id[&docs.id].slack_days, 5);
+        assert!(!by_id[&docs.id].critical);
+        assert_eq!(critical_path(&scheduled), vec![kickoff.id, procurement.id, install.id, go_live.id]);
+    }
+
+    #[test]
+    fn test_schedule_constraints_and_cycles() {
+        let mut late = task(2);
+        late.min_offset = 5;
+        let after = task(1);
+        let deps = vec![link(&after, &late, -1)];
+        let scheduled = schedule(date(3, 4), &[late.clone(), after.clone()], &deps).unwrap();
+        assert_eq!(scheduled[0].start_date, date(3, 11));
+        // Negative lag overlaps the predecessor's last day
+        assert_eq!(scheduled[1].start_date, date(3, 12));
+
+        let a = task(1);
+        let b = task(1);
+        let c = task(1);
+        let deps = vec![link(&b, &a, 0), link(&c, &b, 0), link(&b, &c, 0)];
+        match schedule(date(3, 4), &[a.clone(), b.clone(), c.clone()], &deps) {
+            Err(ScheduleError::Cycle(ids)) => {
+                assert!(ids.contains(&b.id) && ids.contains(&c.id) && !ids.contains(&a.id));
+            }
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_capacity_conflicts() {
+        let tech = Uuid::new_v4();
+        let schedule = UserSchedule {
+            user_id: tech,
+            user_name: "Sam Tech".to_string(),
+            manager_id: None,
+            timezone: "UTC".to_string(),
+            hours_per_day: Decimal::from(8),
+            working_days: vec![1, 2, 3, 4, 5],
+        };
+        let schedules = HashMap::from([(tech, schedule)]);
+        let (migration, onboarding) = (Uuid::new_v4(), Uuid::new_v4());
+        let bookings = vec![
+            // 24 hours over Fri-Tue is 8 a working day
+            Booking { task_id: migration, user_id: tech, start: date(3, 8), finish: date(3, 12), hours: Decimal::from(24) },
+            Booking { task_id: onboarding, user_id: tech, start: date(3, 12), finish: date(3, 12), hours: Decimal::from(4) },
+        ];
+
+        let conflicts = capacity_conflicts(&bookings, &schedules);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].date, date(3, 12));
+        assert_eq!(conflicts[0].booked_hours, Decimal::from(12));
+        assert_eq!(conflicts[0].capacity_hours, Decimal::from(8));
+        assert_eq!(conflicts[0].task_ids, vec![migration, onboarding]);
+    }
+}
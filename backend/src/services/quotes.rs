@@ -0,0 +1,463 @@
+use crate::notifications::{enqueue_notification, QueuedNotification};
+use crate::services::currency::format_money;
+use crate::services::pdf::{Column, PdfDocument};
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+type QuoteResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+pub const QUOTE_COLUMNS: &str = "q.id, q.client_id, c.name as client_name, q.contact_id, q.project_id, q.number,
+    q.title, q.date, q.expiry_date, q.subtotal, q.tax_amount, q.total, q.recurring_total, q.discount_percentage,
+    q.discount_amount, q.status, q.notes, q.terms, q.version, q.root_quote_id, q.superseded_by, q.sent_at,
+    q.viewed_at, q.accepted_at, q.accepted_name, q.accepted_by_contact_id, q.rejected_at, q.rejection_reason,
+    q.converted_at, q.converted_project_id, q.converted_invoice_id, q.converted_recurring_billing_id,
+    q.created_by, q.created_at, q.updated_at, c.currency";
+
+pub const LINE_COLUMNS: &str =
+    "id, quote_id, item_type, description, quantity, unit_price, total_price, frequency, tax_rate, sort_order";
+
+pub const LINE_ITEM_TYPES: [&str; 4] = ["service", "product", "labor", "recurring"];
+
+#[derive(Debug, Clone)]
+pub struct QuoteConfig {
+    pub check_interval_seconds: u64, // How often sent quotes are checked for expiry
+}
+
+impl Default for QuoteConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_seconds: 60 * 60,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct QuoteService {
+    config: QuoteConfig,
+    db_pool: PgPool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Quote {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub client_name: Option<String>,
+    pub contact_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub number: String,
+    pub title: String,
+    pub date: NaiveDate,
+    pub expiry_date: Option<NaiveDate>,
+    pub subtotal: Decimal,
+    pub tax_amount: Decimal,
+    pub total: Decimal,
+    pub recurring_total: Decimal,
+    pub discount_percentage: Option<Decimal>,
+    pub discount_amount: Option<Decimal>,
+    pub status: Option<String>,
+    pub notes: Option<String>,
+    pub terms: Option<String>,
+    pub version: i32,
+    pub root_quote_id: Option<Uuid>,
+    pub superseded_by: Option<Uuid>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub viewed_at: Option<DateTime<Utc>>,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub accepted_name: Option<String>,
+    pub accepted_by_contact_id: Option<Uuid>,
+    pub rejected_at: Option<DateTime<Utc>>,
+    pub rejection_reason: Option<String>,
+    pub converted_at: Option<DateTime<Utc>>,
+    pub converted_project_id: Option<Uuid>,
+    pub converted_invoice_id: Option<Uuid>,
+    pub converted_recurring_billing_id: Option<Uuid>,
+    pub created_by: Uuid,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub currency: String, // the client's billing currency
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct QuoteLine {
+    pub id: Uuid,
+    pub quote_id: Uuid,
+    pub item_type: String,
+    pub description: String,
+    pub quantity: Option<Decimal>,
+    pub unit_price: Decimal,
+    pub total_price: Decimal,
+    pub frequency: Option<String>,
+    pub tax_rate: Option<Decimal>,
+    pub sort_order: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuoteLineInput {
+    pub item_type: Option<String>,
+    pub description: String,
+    pub quantity: Option<Decimal>,
+    pub unit_price: Decimal,
+    pub frequency: Option<String>,
+    pub tax_rate: Option<Decimal>,
+}
+
+impl QuoteLineInput {
+    pub fn item_type(&self) -> &str {
+        self.item_type.as_deref().unwrap_or("service")
+    }
+
+    pub fn total(&self) -> Decimal {
+        (self.quantity.unwrap_or(Decimal::ONE) * self.unit_price).round_dp(2)
+    }
+
+    pub fn is_recurring(&self) -> bool {
+        self.item_type() == "recurring"
+    }
+}
+
+impl From<&QuoteLine> for QuoteLineInput {
+    fn from(line: &QuoteLine) -> Self {
+        Self {
+            item_type: Some(line.item_type.clone()),
+            description: line.description.clone(),
+            quantity: line.quantity,
+            unit_price: line.unit_price,
+            frequency: line.frequency.clone(),
+            tax_rate: line.tax_rate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct QuoteTotals {
+    pub subtotal: Decimal,
+    pub discount: Decimal,
+    pub tax_amount: Decimal,
+    pub total: Decimal,
+    pub recurring_total: Decimal,
+}
+
+#[derive(Debug, FromRow)]
+struct ExpiredQuote {
+    id: Uuid,
+    number: String,
+    created_by: Uuid,
+}
+
+impl QuoteService {
+    pub fn new(config: QuoteConfig, db_pool: PgPool) -> Self {
+        Self { config, db_pool }
+    }
+
+    pub async fn start(&self) -> QuoteResult<()> {
+        info!("Starting quote expiry worker");
+
+        let mut check_interval = interval(Duration::from_secs(self.config.check_interval_seconds));
+
+        tokio::spawn({
+            let service = self.clone();
+            async move {
+                loop {
+                    check_interval.tick().await;
+
+                    if let Err(e) = service.expire_quotes(Utc::now().date_naive()).await {
+                        error!("Error expiring quotes: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Marks sent quotes past their expiry date as expired and lets the author know.
+    pub async fn expire_quotes(&self, today: NaiveDate) -> QuoteResult<usize> {
+        let expired = sqlx::query_as::<_, ExpiredQuote>(
+            r#"
+            UPDATE quotes SET status = 'expired', updated_at = NOW()
+            WHERE status = 'sent' AND expiry_date < $1
+            RETURNING id, number, created_by
+            "#,
+        )
+        .bind(today)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        for quote in &expired {
+            let notification = QueuedNotification::for_user(
+                quote.created_by,
+                "quote_expired",
+                format!("Quote {} expired", quote.number),
+                format!("Quote {} passed its expiry date without being accepted.", quote.number),
+            )
+            .with_entity("quote", quote.id);
+
+            if let Err(e) = enqueue_notification(&self.db_pool, notification).await {
+                warn!("Failed to queue expiry notice for quote {}: {}", quote.number, e);
+            }
+        }
+
+        if !expired.is_empty() {
+            info!("Expired {} quotes", expired.len());
+        }
+
+        Ok(expired.len())
+    }
+}
+
+/// Totals for a set of lines. One-off lines make up the quote total; recurring
+/// lines are summed separately as the per-cycle value. A discount is taken off
+/// the one-off subtotal before tax.
+pub fn quote_totals(
+    lines: &[QuoteLineInput],
+    discount_percentage: Option<Decimal>,
+    discount_amount: Option<Decimal>,
+) -> QuoteTotals {
+    let (recurring, one_off): (Vec<&QuoteLineInput>, Vec<&QuoteLineInput>) =
+        lines.iter().partition(|l| l.is_recurring());
+
+    let subtotal: Decimal = one_off.iter().map(|l| l.total()).sum();
+    let discount = discount_amount
+        .or_else(|| discount_percentage.map(|pct| subtotal * pct / Decimal::from(100)))
+        .unwrap_or_default()
+        .min(subtotal)
+        .max(Decimal::ZERO)
+        .round_dp(2);
+
+    let discount_ratio = if subtotal.is_zero() {
+        Decimal::ZERO
+    } else {
+        discount / subtotal
+    };
+    let tax_amount: Decimal = one_off
+        .iter()
+        .map(|l| {
+            let taxable = l.total() * (Decimal::ONE - discount_ratio);
+            taxable * l.tax_rate.unwrap_or_default() / Decimal::from(100)
+        })
+        .sum::<Decimal>()
+        .round_dp(2);
+
+    QuoteTotals {
+        subtotal,
+        discount,
+        tax_amount,
+        total: subtotal - discount + tax_amount,
+        recurring_total: recurring.iter().map(|l| l.total()).sum(),
+    }
+}
+
+/// Number shown on a revision, e.g. Q-000042-R2 for the second version of Q-000042.
+pub fn revision_number(root_number: &str, version: i32) -> String {
+    format!("{}-R{}", root_number, version)
+}
+
+pub async fn next_quote_number(tx: &mut Transaction<'_, Postgres>) -> QuoteResult<String> {
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext('quote_number'))")
+        .execute(&mut **tx)
+        .await?;
+
+    let next: i32 = sqlx::query_scalar(
+        "SELECT COALESCE(MAX(CAST(SUBSTRING(number FROM '^Q-(\\d+)$') AS INTEGER)), 0) + 1
+         FROM quotes WHERE number ~ '^Q-\\d+$'",
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(format!("Q-{:06}", next))
+}
+
+/// Replaces a quote's lines and stores the recalculated totals.
+pub async fn save_lines(
+    tx: &mut Transaction<'_, Postgres>,
+    quote_id: Uuid,
+    lines: &[QuoteLineInput],
+    discount_percentage: Option<Decimal>,
+    discount_amount: Option<Decimal>,
+) -> QuoteResult<QuoteTotals> {
+    sqlx::query("DELETE FROM quote_line_items WHERE quote_id = $1")
+        .bind(quote_id)
+        .execute(&mut **tx)
+        .await?;
+
+    for (i, line) in lines.iter().enumerate() {
+        sqlx::query(
+            r#"
+            INSERT INTO quote_line_items (quote_id, item_type, description, quantity, unit_price, total_price,
+                                          frequency, tax_rate, sort_order)
+            VALUES ($1, $2, $3, COALESCE($4, 1), $5, $6, $7, $8, $9)
+            "#,
+        )
+        .bind(quote_id)
+        .bind(line.item_type())
+        .bind(&line.description)
+        .bind(line.quantity)
+        .bind(line.unit_price)
+        .bind(line.total())
+        .bind(line.is_recurring().then(|| line.frequency.clone().unwrap_or_else(|| "monthly".to_string())))
+        .bind(line.tax_rate)
+        .bind(i as i32)
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    let totals = quote_totals(lines, discount_percentage, discount_amount);
+    sqlx::query(
+        r#"
+        UPDATE quotes SET subtotal = $2, tax_amount = $3, total = $4, recurring_total = $5,
+                          discount_amount = $6, updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(quote_id)
+    .bind(totals.subtotal)
+    .bind(totals.tax_amount)
+    .bind(totals.total)
+    .bind(totals.recurring_total)
+    .bind(Some(totals.discount).filter(|d| !d.is_zero()))
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(totals)
+}
+
+pub fn render_pdf(quote: &Quote, lines: &[QuoteLine]) -> Vec<u8> {
+    let mut doc = PdfDocument::new(&format!("Quote {}", quote.number));
+    doc.heading(&format!("Quote {}", quote.number))
+        .subheading(&quote.title)
+        .spacer(6.0)
+        .text(&format!("Prepared for: {}", quote.client_name.as_deref().unwrap_or("")))
+        .text(&format!("Date: {}", quote.date.format("%Y-%m-%d")));
+    if let Some(expiry) = quote.expiry_date {
+        doc.text(&format!("Valid until: {}", expiry.format("%Y-%m-%d")));
+    }
+    doc.spacer(12.0);
+
+    let row = |doc: &mut PdfDocument, cells: [&str; 4], bold: bool| {
+        doc.row(
+            &[
+                Column { text: cells[0], x: 0.0, right_align: false },
+                Column { text: cells[1], x: 370.0, right_align: true },
+                Column { text: cells[2], x: 440.0, right_align: true },
+                Column { text: cells[3], x: 512.0, right_align: true },
+            ],
+            bold,
+        );
+    };
+
+    let (recurring, one_off): (Vec<&QuoteLine>, Vec<&QuoteLine>) =
+        lines.iter().partition(|l| l.item_type == "recurring");
+
+    for (heading, section) in [("One-time", &one_off), ("Recurring", &recurring)] {
+        if section.is_empty() {
+            continue;
+        }
+        doc.subheading(heading);
+        row(&mut doc, ["Description", "Qty", "Price", "Total"], true);
+        for line in section.iter() {
+            let description = match &line.frequency {
+                Some(frequency) if line.item_type == "recurring" => format!("{} ({})", line.description, frequency),
+                _ => line.description.clone(),
+            };
+            let description: String = description.chars().take(60).collect();
+            row(
+                &mut doc,
+                [
+                    &description,
+                    &line.quantity.unwrap_or(Decimal::ONE).normalize().to_string(),
+                    &format_money(line.unit_price, &quote.currency),
+                    &format_money(line.total_price, &quote.currency),
+                ],
+                false,
+            );
+        }
+        doc.spacer(8.0);
+    }
+
+    row(&mut doc, ["Subtotal", "", "", &format_money(quote.subtotal, &quote.currency)], false);
+    if let Some(discount) = quote.discount_amount.filter(|d| !d.is_zero()) {
+        row(&mut doc, ["Discount", "", "", &format_money(-discount, &quote.currency)], false);
+    }
+    row(&mut doc, ["Tax", "", "", &format_money(quote.tax_amount, &quote.currency)], false);
+    row(&mut doc, ["Total", "", "", &format_money(quote.total, &quote.currency)], true);
+    if !quote.recurring_total.is_zero() {
+        row(&mut doc, ["Recurring charges", "", "", &format_money(quote.recurring_total, &quote.currency)], true);
+    }
+
+    if let Some(terms) = quote.terms.as_deref().filter(|t| !t.is_empty()) {
+        doc.spacer(12.0).subheading("Terms").text(terms);
+    }
+    if let (Some(name), Some(at)) = (&quote.accepted_name, quote.accepted_at) {
+        doc.spacer(12.0)
+            .subheading("Accepted")
+            .text(&format!("Signed by {} on {}", name, at.format("%Y-%m-%d %H:%M UTC")));
+    }
+
+    doc.to_bytes()
+}
+
+/// Renders the quote to the upload directory so it can be attached to emails.
+pub async fn write_pdf(quote: &Quote, lines: &[QuoteLine]) -> QuoteResult<String> {
+    let dir = format!("{}/quotes", crate::files::get_upload_directory());
+    tokio::fs::create_dir_all(&dir).await?;
+
+    let path = format!("{}/{}.pdf", dir, quote.id);
+    tokio::fs::write(&path, render_pdf(quote, lines)).await?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(item_type: &str, quantity: i64, unit_price: i64, tax_rate: Option<i64>) -> QuoteLineInput {
+        QuoteLineInput {
+            item_type: Some(item_type.to_string()),
+            description: "Line".to_string(),
+            quantity: Some(Decimal::from(quantity)),
+            unit_price: Decimal::from(unit_price),
+            frequency: None,
+            tax_rate: tax_rate.map(Decimal::from),
+        }
+    }
+
+    #[test]
+    fn test_quote_totals_split_recurring() {
+        let lines = vec![
+            line("product", 2, 500, Some(10)),
+            line("labor", 8, 150, None),
+            line("recurring", 25, 40, None),
+        ];
+
+        let totals = quote_totals(&lines, None, None);
+        assert_eq!(totals.subtotal, Decimal::from(2200));
+        assert_eq!(totals.tax_amount, Decimal::from(100));
+        assert_eq!(totals.total, Decimal::from(2300));
+        assert_eq!(totals.recurring_total, Decimal::from(1000));
+    }
+
+    #[test]
+    fn test_quote_totals_discount_reduces_tax() {
+        let lines = vec![line("product", 1, 1000, Some(10))];
+
+        let totals = quote_totals(&lines, Some(Decimal::from(10)), None);
+        assert_eq!(totals.discount, Decimal::from(100));
+        assert_eq!(totals.tax_amount, Decimal::from(90));
+        assert_eq!(totals.total, Decimal::from(990));
+
+        // A fixed discount wins over a percentage and can't exceed the subtotal
+        let totals = quote_totals(&lines, Some(Decimal::from(10)), Some(Decimal::from(5000)));
+        assert_eq!(totals.discount, Decimal::from(1000));
+        assert_eq!(totals.total, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_revision_number() {
+        assert_eq!(revision_number("Q-000042", 2), "Q-000042-R2");
+    }
+}
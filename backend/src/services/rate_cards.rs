@@ -0,0 +1,487 @@
+use chrono::{DateTime, Datelike, NaiveDateTime, NaiveTime, Utc, Weekday};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection, PgPool};
+use uuid::Uuid;
+
+pub type RateResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Rate used when no rate card, project, contract, client or technician rate applies.
+const DEFAULT_HOURLY_RATE: i64 = 150;
+
+pub const RATE_CARD_COLUMNS: &str = "rc.id, rc.name, rc.description, rc.client_id, rc.is_default, rc.base_rate,
+    rc.business_hours_start, rc.business_hours_end, rc.after_hours_multiplier, rc.weekend_multiplier,
+    rc.minimum_minutes, rc.increment_minutes, rc.rounding, rc.is_active, rc.created_at, rc.updated_at";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkType {
+    Remote,
+    Onsite,
+    Project,
+}
+
+impl WorkType {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "remote" => Some(Self::Remote),
+            "onsite" => Some(Self::Onsite),
+            "project" => Some(Self::Project),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Remote => "remote",
+            Self::Onsite => "onsite",
+            Self::Project => "project",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    Up,
+    Nearest,
+    Down,
+}
+
+impl Rounding {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "up" => Some(Self::Up),
+            "nearest" => Some(Self::Nearest),
+            "down" => Some(Self::Down),
+            _ => None,
+        }
+    }
+}
+
+/// When the work happened, in the client's local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateWindow {
+    BusinessHours,
+    AfterHours,
+    Weekend,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RateCard {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub client_id: Option<Uuid>,
+    pub is_default: Option<bool>,
+    pub base_rate: Decimal,
+    pub business_hours_start: NaiveTime,
+    pub business_hours_end: NaiveTime,
+    pub after_hours_multiplier: Decimal,
+    pub weekend_multiplier: Decimal,
+    pub minimum_minutes: i32,
+    pub increment_minutes: i32,
+    pub rounding: String,
+    pub is_active: Option<bool>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RateCardRate {
+    pub id: Uuid,
+    pub rate_card_id: Uuid,
+    pub work_type: Option<String>,
+    pub technician_role: Option<String>,
+    pub hourly_rate: Decimal,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// The outcome of pricing a piece of work.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PricedTime {
+    pub rate_card_id: Option<Uuid>,
+    pub work_type: WorkType,
+    pub window: Option<RateWindow>,
+    pub base_rate: Decimal,
+    pub multiplier: Decimal,
+    pub hourly_rate: Decimal,
+    pub billable_minutes: i32,
+    pub amount: Decimal,
+    pub rate_source: &'static str,
+}
+
+#[derive(Debug, FromRow)]
+struct EntryForPricing {
+    project_id: Option<Uuid>,
+    client_id: Option<Uuid>,
+    work_type: Option<String>,
+    duration_minutes: Option<i32>,
+    billable: Option<bool>,
+    billed: Option<bool>,
+    invoice_id: Option<Uuid>,
+    hourly_rate: Option<Decimal>,
+    rate_source: Option<String>,
+    technician_role: Option<String>,
+    local_start: Option<NaiveDateTime>,
+    fallback_rate: Option<Decimal>,
+}
+
+/// Loads the card that governs work for a client: the project's card, then the
+/// client's override card, then the default card.
+pub async fn card_for(
+    conn: &mut PgConnection,
+    client_id: Option<Uuid>,
+    project_id: Option<Uuid>,
+) -> RateResult<Option<(RateCard, Vec<RateCardRate>)>> {
+    let card = sqlx::query_as::<_, RateCard>(&format!(
+        r#"
+        SELECT {} FROM rate_cards rc
+        WHERE rc.is_active = true
+          AND (rc.id = (SELECT rate_card_id FROM projects WHERE id = $2)
+               OR rc.client_id = $1
+               OR rc.is_default = true)
+        ORDER BY CASE WHEN rc.id = (SELECT rate_card_id FROM projects WHERE id = $2) THEN 0
+                      WHEN rc.client_id = $1 THEN 1
+                      ELSE 2 END
+        LIMIT 1
+        "#,
+        RATE_CARD_COLUMNS
+    ))
+    .bind(client_id)
+    .bind(project_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some(card) = card else {
+        return Ok(None);
+    };
+
+    let rates = sqlx::query_as::<_, RateCardRate>(
+        "SELECT id, rate_card_id, work_type, technician_role, hourly_rate, created_at FROM rate_card_rates WHERE rate_card_id = $1",
+    )
+    .bind(card.id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(Some((card, rates)))
+}
+
+/// Prices a time entry from its rate card and stores the result on the entry.
+///
+/// Runs whenever an entry's duration is set or edited. Billed entries are left
+/// alone, and a manually overridden rate is kept (only minimums and rounding
+/// are applied to it).
+pub async fn price_time_entry(db_pool: &PgPool, time_entry_id: Uuid) -> RateResult<Option<PricedTime>> {
+    let mut conn = db_pool.acquire().await?;
+
+    let entry = sqlx::query_as::<_, EntryForPricing>(
+        r#"
+        SELECT te.project_id, COALESCE(t.client_id, p.client_id) as client_id, te.work_type,
+               te.duration_minutes, te.billable, te.billed, te.invoice_id, te.hourly_rate, te.rate_source,
+               u.role as technician_role,
+               te.start_time AT TIME ZONE COALESCE(
+                   (SELECT l.timezone FROM locations l
+                    JOIN pg_timezone_names tz ON tz.name = l.timezone
+                    WHERE l.client_id = COALESCE(t.client_id, p.client_id)
+                    ORDER BY l.primary_location DESC NULLS LAST, l.created_at
+                    LIMIT 1),
+                   'UTC') as local_start,
+               COALESCE(p.hourly_rate,
+                        (SELECT ct.hourly_rate FROM contracts ct
+                         WHERE ct.client_id = COALESCE(t.client_id, p.client_id) AND ct.status = 'active'
+                           AND ct.hourly_rate IS NOT NULL
+                         ORDER BY ct.start_date DESC LIMIT 1),
+                        c.default_hourly_rate,
+                        u.hourly_rate) as fallback_rate
+        FROM time_entries te
+        JOIN users u ON u.id = te.user_id
+        LEFT JOIN tickets t ON t.id = te.ticket_id
+        LEFT JOIN projects p ON p.id = te.project_id
+        LEFT JOIN clients c ON c.id = COALESCE(t.client_id, p.client_id)
+        WHERE te.id = $1
+        "#,
+    )
+    .bind(time_entry_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some(entry) = entry else {
+        return Ok(None);
+    };
+    if entry.billed.unwrap_or(false) || entry.invoice_id.is_some() {
+        return Ok(None);
+    }
+    let Some(duration_minutes) = entry.duration_minutes else {
+        return Ok(None);
+    };
+
+    let work_type = entry
+        .work_type
+        .as_deref()
+        .and_then(WorkType::parse)
+        .unwrap_or(if entry.project_id.is_some() { WorkType::Project } else { WorkType::Remote });
+    let local_start = entry.local_start.unwrap_or_else(|| Utc::now().naive_utc());
+    let card = card_for(&mut conn, entry.client_id, entry.project_id).await?;
+
+    let manual_rate = entry.hourly_rate.filter(|_| entry.rate_source.as_deref() == Some("manual"));
+
+    let mut priced = match (&card, manual_rate) {
+        (_, Some(rate)) => {
+            let mut priced = flat_price(rate, duration_minutes, work_type);
+            if let Some((card, _)) = &card {
+                priced.rate_card_id = Some(card.id);
+                priced.billable_minutes = billable_minutes(
+                    duration_minutes,
+                    card.minimum_minutes,
+                    card.increment_minutes,
+                    Rounding::parse(&card.rounding).unwrap_or(Rounding::Up),
+                );
+                priced.amount = amount_for(rate, priced.billable_minutes);
+            }
+            priced.rate_source = "manual";
+            priced
+        }
+        (Some((card, rates)), None) => {
+            price(card, rates, work_type, entry.technician_role.as_deref(), local_start, duration_minutes)
+        }
+        (None, None) => flat_price(
+            entry.fallback_rate.unwrap_or_else(|| Decimal::from(DEFAULT_HOURLY_RATE)),
+            duration_minutes,
+            work_type,
+        ),
+    };
+    if !entry.billable.unwrap_or(true) {
+        priced.amount = Decimal::ZERO;
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE time_entries SET
+            hourly_rate = $2, base_hourly_rate = $3, rate_multiplier = $4, billable_minutes = $5,
+            total_amount = $6, rate_card_id = $7, rate_source = $8
+        WHERE id = $1
+        "#,
+    )
+    .bind(time_entry_id)
+    .bind(priced.hourly_rate)
+    .bind(priced.base_rate)
+    .bind(priced.multiplier)
+    .bind(priced.billable_minutes)
+    .bind(priced.amount)
+    .bind(priced.rate_card_id)
+    .bind(priced.rate_source)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(Some(priced))
+}
+
+/// Prices `minutes` of work against a card.
+pub fn price(
+    card: &RateCard,
+    rates: &[RateCardRate],
+    work_type: WorkType,
+    technician_role: Option<&str>,
+    local_start: NaiveDateTime,
+    minutes: i32,
+) -> PricedTime {
+    let base_rate = matching_rate(rates, work_type, technician_role).unwrap_or(card.base_rate);
+    let window = rate_window(card, local_start);
+    let multiplier = match window {
+        RateWindow::BusinessHours => Decimal::ONE,
+        RateWindow::AfterHours => card.after_hours_multiplier,
+        RateWindow::Weekend => card.weekend_multiplier,
+    };
+    let hourly_rate = (base_rate * multiplier).round_dp(2);
+    let billable_minutes = billable_minutes(
+        minutes,
+        card.minimum_minutes,
+        card.increment_minutes,
+        Rounding::parse(&card.rounding).unwrap_or(Rounding::Up),
+    );
+
+    PricedTime {
+        rate_card_id: Some(card.id),
+        work_type,
+        window: Some(window),
+        base_rate,
+        multiplier,
+        hourly_rate,
+        billable_minutes,
+        amount: amount_for(hourly_rate, billable_minutes),
+        rate_source: "rate_card",
+    }
+}
+
+fn flat_price(rate: Decimal, minutes: i32, work_type: WorkType) -> PricedTime {
+    let billable_minutes = minutes.max(0);
+    PricedTime {
+        rate_card_id: None,
+        work_type,
+        window: None,
+        base_rate: rate,
+        multiplier: Decimal::ONE,
+        hourly_rate: rate,
+        billable_minutes,
+        amount: amount_for(rate, billable_minutes),
+        rate_source: "fallback",
+    }
+}
+
+/// Most specific rate row for the work: work type and role, then role, then work type,
+/// then a catch-all row.
+pub fn matching_rate(rates: &[RateCardRate], work_type: WorkType, technician_role: Option<&str>) -> Option<Decimal> {
+    rates
+        .iter()
+        .filter_map(|r| {
+            let type_score = match r.work_type.as_deref() {
+                None => 0,
+                Some(t) if t == work_type.as_str() => 1,
+                Some(_) => return None,
+            };
+            let role_score = match (r.technician_role.as_deref(), technician_role) {
+                (None, _) => 0,
+                (Some(rate_role), Some(role)) if rate_role.eq_ignore_ascii_case(role) => 2,
+                (Some(_), _) => return None,
+            };
+            Some((type_score + role_score, r.hourly_rate))
+        })
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, rate)| rate)
+}
+
+pub fn rate_window(card: &RateCard, local_start: NaiveDateTime) -> RateWindow {
+    if is_weekend(local_start) {
+        return RateWindow::Weekend;
+    }
+
+    let time = local_start.time();
+    let (start, end) = (card.business_hours_start, card.business_hours_end);
+    let in_hours = if start <= end {
+        time >= start && time < end
+    } else {
+        // Business day that spans midnight, e.g. 20:00 - 04:00
+        time >= start || time < end
+    };
+
+    if in_hours { RateWindow::BusinessHours } else { RateWindow::AfterHours }
+}
+
+/// Applies a card's increment, rounding and minimum charge to a raw duration.
+pub fn billable_minutes(minutes: i32, minimum_minutes: i32, increment_minutes: i32, rounding: Rounding) -> i32 {
+    if minutes <= 0 {
+        return 0;
+    }
+
+    let increment = increment_minutes.max(1);
+    let rounded = match rounding {
+        Rounding::Up => (minutes + increment - 1) / increment * increment,
+        Rounding::Nearest => (minutes * 2 + increment) / (increment * 2) * increment,
+        Rounding::Down => minutes / increment * increment,
+    };
+
+    rounded.max(minimum_minutes)
+}
+
+fn amount_for(hourly_rate: Decimal, minutes: i32) -> Decimal {
+    (hourly_rate * Decimal::from(minutes) / Decimal::from(60)).round_dp(2)
+}
+
+fn is_weekend(local: NaiveDateTime) -> bool {
+    matches!(local.weekday(), Weekday::Sat | Weekday::Sun)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn card() -> RateCard {
+        RateCard {
+            id: Uuid::new_v4(),
+            name: "Standard".to_string(),
+            description: None,
+            client_id: None,
+            is_default: Some(true),
+            base_rate: Decimal::from(120),
+            business_hours_start: NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+            business_hours_end: NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            after_hours_multiplier: Decimal::new(15, 1),
+            weekend_multiplier: Decimal::from(2),
+            minimum_minutes: 30,
+            increment_minutes: 15,
+            rounding: "up".to_string(),
+            is_active: Some(true),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn rate(work_type: Option<&str>, role: Option<&str>, amount: i64) -> RateCardRate {
+        RateCardRate {
+            id: Uuid::new_v4(),
+            rate_card_id: Uuid::nil(),
+            work_type: work_type.map(str::to_string),
+            technician_role: role.map(str::to_string),
+            hourly_rate: Decimal::from(amount),
+            created_at: None,
+        }
+    }
+
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        // March 2024: the 4th is a Monday, the 9th a Saturday
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_matching_rate_prefers_most_specific() {
+        let rates = vec![
+            rate(Some("onsite"), None, 140),
+            rate(None, Some("engineer"), 160),
+            rate(Some("onsite"), Some("engineer"), 180),
+        ];
+
+        assert_eq!(matching_rate(&rates, WorkType::Onsite, Some("Engineer")), Some(Decimal::from(180)));
+        assert_eq!(matching_rate(&rates, WorkType::Remote, Some("engineer")), Some(Decimal::from(160)));
+        assert_eq!(matching_rate(&rates, WorkType::Onsite, Some("technician")), Some(Decimal::from(140)));
+        assert_eq!(matching_rate(&rates, WorkType::Remote, None), None);
+    }
+
+    #[test]
+    fn test_rate_window() {
+        let card = card();
+        assert_eq!(rate_window(&card, at(4, 10)), RateWindow::BusinessHours);
+        assert_eq!(rate_window(&card, at(4, 18)), RateWindow::AfterHours);
+        assert_eq!(rate_window(&card, at(4, 7)), RateWindow::AfterHours);
+        assert_eq!(rate_window(&card, at(9, 10)), RateWindow::Weekend);
+    }
+
+    #[test]
+    fn test_billable_minutes_rounding() {
+        assert_eq!(billable_minutes(31, 0, 15, Rounding::Up), 45);
+        assert_eq!(billable_minutes(37, 0, 15, Rounding::Nearest), 30);
+        assert_eq!(billable_minutes(38, 0, 15, Rounding::Nearest), 45);
+        assert_eq!(billable_minutes(44, 0, 15, Rounding::Down), 30);
+        assert_eq!(billable_minutes(5, 30, 15, Rounding::Up), 30);
+        assert_eq!(billable_minutes(0, 30, 15, Rounding::Up), 0);
+    }
+
+    #[test]
+    fn test_price_applies_multiplier_and_increment() {
+        let card = card();
+        let rates = vec![rate(Some("remote"), None, 100)];
+
+        let priced = price(&card, &rates, WorkType::Remote, None, at(4, 20), 50);
+        assert_eq!(priced.window, Some(RateWindow::AfterHours));
+        assert_eq!(priced.hourly_rate, Decimal::from(150));
+        assert_eq!(priced.billable_minutes, 60);
+        assert_eq!(priced.amount, Decimal::from(150));
+
+        let priced = price(&card, &rates, WorkType::Onsite, None, at(9, 10), 20);
+        assert_eq!(priced.base_rate, Decimal::from(120));
+        assert_eq!(priced.hourly_rate, Decimal::from(240));
+        assert_eq!(priced.amount, Decimal::from(120));
+    }
+}
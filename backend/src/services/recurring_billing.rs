@@ -0,0 +1,1399 @@
+use crate::notifications::{enqueue_notification, QueuedNotification};
+use crate::services::billing_metrics::{self, BillingMetric, MetricReading};
+use crate::services::credit_notes;
+use crate::services::currency;
+use crate::services::expenses;
+use crate::services::sales_tax::{self, TaxContext};
+use chrono::{Datelike, Duration as ChronoDuration, Months, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+type BillingResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+// Used when neither the time entry nor an active contract carries a rate
+const DEFAULT_HOURLY_RATE: i64 = 150;
+
+#[derive(Debug, Clone)]
+pub struct RecurringBillingConfig {
+    pub check_interval_seconds: u64,  // How often due profiles are looked for
+    pub default_payment_terms_days: i32,
+    pub include_time_entries: bool,   // Merge unbilled time into the recurring invoice
+    pub include_expenses: bool,       // Merge approved billable expenses into the recurring invoice
+    pub max_catch_up_periods: u32,    // Periods billed per profile in one run after downtime
+    pub hold_for_review: bool,        // Leave invoices as drafts for the billing review, from BILLING_REVIEW_REQUIRED
+}
+
+impl Default for RecurringBillingConfig {
+    fn default() -> Self {
+        let hold_for_review = std::env::var("BILLING_REVIEW_REQUIRED")
+            .map(|v| v != "false" && v != "0")
+            .unwrap_or(true);
+
+        Self {
+            check_interval_seconds: 60 * 60,
+            default_payment_terms_days: 30,
+            // Reviewed time and expenses are invoiced when their review is approved
+            include_time_entries: !hold_for_review,
+            include_expenses: !hold_for_review,
+            max_catch_up_periods: 12,
+            hold_for_review,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RecurringBillingService {
+    config: RecurringBillingConfig,
+    db_pool: PgPool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RecurringProfile {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub billing_type: String,
+    pub amount: Option<Decimal>,
+    pub quantity: Option<i32>,
+    pub unit_price: Option<Decimal>,
+    pub frequency: String,
+    pub billing_day: Option<i32>,
+    pub start_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+    pub next_billing_date: NaiveDate,
+    pub last_billed_date: Option<NaiveDate>,
+    pub payment_method_id: Option<Uuid>,
+    pub auto_charge: Option<bool>,
+    pub send_invoice: Option<bool>,
+    pub payment_terms_days: Option<i32>,
+    pub status: Option<String>,
+    pub pricing_tiers: Option<serde_json::Value>,
+    pub prorate: Option<bool>,
+    pub currency: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RecurringItem {
+    pub id: Uuid,
+    pub recurring_billing_id: Uuid,
+    pub item_type: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub quantity: Option<Decimal>,
+    pub unit_price: Decimal,
+    pub discount_percent: Option<Decimal>,
+    pub tax_rate: Option<Decimal>,
+    pub tax_category: Option<String>,
+    pub total: Decimal,
+    pub metric_type: Option<String>,
+    pub metric_filter: Option<serde_json::Value>,
+    pub min_quantity: Option<Decimal>,
+    pub last_metric_quantity: Option<Decimal>,
+}
+
+pub const ITEM_COLUMNS: &str = "id, recurring_billing_id, item_type, name, description, quantity, unit_price,
+    discount_percent, tax_rate, COALESCE(tax_category, item_type) as tax_category, total, metric_type, metric_filter,
+    min_quantity, last_metric_quantity";
+
+impl RecurringItem {
+    /// Per-unit price after the item discount.
+    pub fn net_unit_price(&self) -> Decimal {
+        let discount = self.discount_percent.unwrap_or_default();
+        round_money(self.unit_price * (Decimal::from(100) - discount) / Decimal::from(100))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceTier {
+    pub up_to: Option<Decimal>, // None for the last, open-ended tier
+    pub unit_price: Decimal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BillingPeriod {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub next_billing_date: NaiveDate,
+    pub days_billed: i64,
+    pub days_in_cycle: i64,
+}
+
+impl BillingPeriod {
+    pub fn is_partial(&self) -> bool {
+        self.days_billed < self.days_in_cycle
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DraftLine {
+    pub description: String,
+    pub quantity: Decimal,
+    pub unit_price: Decimal,
+    pub tax_rate: Option<Decimal>,        // Entered rate; replaces the jurisdiction rates
+    pub tax_category: Option<String>,     // Lines without a category aren't taxed
+    pub source_type: String,
+    pub source_id: Option<Uuid>,
+    pub period_start: Option<NaiveDate>,
+    pub period_end: Option<NaiveDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metric: Option<MetricReading>, // Snapshot behind a metered quantity
+}
+
+impl DraftLine {
+    pub fn line_total(&self) -> Decimal {
+        round_money(self.quantity * self.unit_price)
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct BillingRunSummary {
+    pub run_id: Option<Uuid>,
+    pub run_date: Option<NaiveDate>,
+    pub clients_processed: i32,
+    pub invoices_created: i32,
+    pub total_amount: Decimal, // in the base currency
+    pub invoice_ids: Vec<Uuid>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, FromRow)]
+struct UnbilledTimeEntry {
+    id: Uuid,
+    work_date: Option<NaiveDate>,
+    duration_minutes: Option<i32>,
+    rate: Option<Decimal>,
+    prepaid_hours: Option<Decimal>,
+    overage_rate: Option<Decimal>,
+    description: Option<String>,
+    user_name: Option<String>,
+}
+
+#[derive(Debug, FromRow)]
+struct PendingAdjustment {
+    id: Uuid,
+    profile_name: String,
+    effective_date: NaiveDate,
+    period_end: NaiveDate,
+    prorated_amount: Decimal,
+    description: Option<String>,
+}
+
+#[derive(Debug, FromRow)]
+struct UsageTotal {
+    recurring_billing_id: Uuid,
+    quantity: Decimal,
+    first_date: NaiveDate,
+    last_date: NaiveDate,
+}
+
+struct BilledPeriod {
+    profile_id: Uuid,
+    period: BillingPeriod,
+    amount: Decimal,
+}
+
+impl RecurringBillingService {
+    pub fn new(config: RecurringBillingConfig, db_pool: PgPool) -> Self {
+        Self { config, db_pool }
+    }
+
+    pub async fn start(&self) -> BillingResult<()> {
+        info!("Starting recurring billing engine");
+
+        let mut check_interval = interval(Duration::from_secs(self.config.check_interval_seconds));
+
+        tokio::spawn({
+            let service = self.clone();
+            async move {
+                loop {
+                    check_interval.tick().await;
+
+                    let today = Utc::now().date_naive();
+                    match service.run_billing(today, None).await {
+                        Ok(summary) if summary.invoices_created > 0 => {
+                            info!("Recurring billing created {} invoices", summary.invoices_created)
+                        }
+                        Ok(_) => {}
+                        Err(e) => error!("Error running recurring billing: {}", e),
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Bills every profile due on or before `run_date`, one invoice per client.
+    ///
+    /// Each client is billed in a single transaction that also advances
+    /// `next_billing_date` and records the billed periods, so a run that dies
+    /// part-way can simply be started again.
+    pub async fn run_billing(&self, run_date: NaiveDate, triggered_by: Option<Uuid>) -> BillingResult<BillingRunSummary> {
+        let run_id: Option<Uuid> = sqlx::query_scalar(
+            r#"
+            INSERT INTO billing_runs (run_date, status, triggered_by)
+            VALUES ($1, 'running', $2)
+            ON CONFLICT (run_date) DO UPDATE SET
+                status = 'running', started_at = NOW(), completed_at = NULL, error_message = NULL,
+                triggered_by = EXCLUDED.triggered_by
+            WHERE billing_runs.status <> 'running' OR billing_runs.started_at < NOW() - INTERVAL '1 hour'
+            RETURNING id
+            "#,
+        )
+        .bind(run_date)
+        .bind(triggered_by)
+        .fetch_optional(&self.db_pool)
+        .await?;
+
+        let Some(run_id) = run_id else {
+            return Err(format!("A billing run for {} is already in progress", run_date).into());
+        };
+
+        let client_ids: Vec<Uuid> = sqlx::query_scalar(
+            "SELECT DISTINCT client_id FROM recurring_billing
+             WHERE status = 'active' AND next_billing_date <= $1 AND start_date <= $1",
+        )
+        .bind(run_date)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let mut summary = BillingRunSummary {
+            run_id: Some(run_id),
+            run_date: Some(run_date),
+            ..Default::default()
+        };
+
+        for client_id in client_ids {
+            summary.clients_processed += 1;
+
+            match self.bill_client(client_id, run_id, run_date).await {
+                Ok(Some((invoice_id, base_total))) => {
+                    summary.invoices_created += 1;
+                    summary.total_amount += base_total;
+                    summary.invoice_ids.push(invoice_id);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    error!("Recurring billing failed for client {}: {}", client_id, e);
+                    summary.errors.push(format!("{}: {}", client_id, e));
+                }
+            }
+        }
+
+        let status = if !summary.errors.is_empty() && summary.invoices_created == 0 && summary.clients_processed > 0 {
+            "failed"
+        } else {
+            "completed"
+        };
+
+        sqlx::query(
+            r#"
+            UPDATE billing_runs SET status = $2, clients_processed = $3, invoices_created = $4,
+                total_amount = $5, error_message = $6, completed_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(run_id)
+        .bind(status)
+        .bind(summary.clients_processed)
+        .bind(summary.invoices_created)
+        .bind(summary.total_amount)
+        .bind(if summary.errors.is_empty() { None } else { Some(summary.errors.join("\n")) })
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(summary)
+    }
+
+    async fn bill_client(&self, client_id: Uuid, run_id: Uuid, run_date: NaiveDate) -> BillingResult<Option<(Uuid, Decimal)>> {
+        let mut tx = self.db_pool.begin().await?;
+
+        // Serialises concurrent runs for the same client
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext('recurring_billing:' || $1::text))")
+            .bind(client_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let profiles = sqlx::query_as::<_, RecurringProfile>(
+            r#"
+            SELECT id, client_id, name, description, billing_type, amount, quantity, unit_price,
+                   frequency, billing_day, start_date, end_date, next_billing_date, last_billed_date,
+                   payment_method_id, auto_charge, send_invoice, payment_terms_days, status,
+                   pricing_tiers, prorate, currency
+            FROM recurring_billing
+            WHERE client_id = $1 AND status = 'active' AND next_billing_date <= $2 AND start_date <= $2
+            ORDER BY name
+            FOR UPDATE
+            "#,
+        )
+        .bind(client_id)
+        .bind(run_date)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        if profiles.is_empty() {
+            return Ok(None);
+        }
+
+        // Profile prices are in the client's currency; a client moved to another currency needs them repriced
+        let client_currency = currency::client_currency(&mut *tx, client_id).await?;
+        if let Some(profile) = profiles.iter().find(|p| p.currency != client_currency) {
+            return Err(format!(
+                "Profile '{}' is priced in {} but the client is invoiced in {}",
+                profile.name, profile.currency, client_currency
+            )
+            .into());
+        }
+
+        let mut lines = Vec::new();
+        let mut billed_periods = Vec::new();
+
+        for profile in &profiles {
+            let mut items = load_items(&mut tx, profile.id).await?;
+            let readings = measure_items(&mut tx, client_id, &mut items).await?;
+            let mut next_billing_date = profile.next_billing_date;
+
+            for _ in 0..self.config.max_catch_up_periods {
+                if next_billing_date > run_date {
+                    break;
+                }
+
+                let Some(period) = billing_period(
+                    next_billing_date,
+                    &profile.frequency,
+                    profile.billing_day,
+                    profile.start_date,
+                    profile.end_date,
+                    profile.prorate.unwrap_or(true),
+                ) else {
+                    break;
+                };
+
+                let period_lines = recurring_lines(profile, &items, &period, &readings);
+                let amount = period_lines.iter().map(|l| l.line_total()).sum();
+                lines.extend(period_lines);
+                billed_periods.push(BilledPeriod { profile_id: profile.id, period, amount });
+
+                next_billing_date = period.next_billing_date;
+            }
+        }
+
+        let usage = self.usage_lines(&mut tx, &profiles, run_date).await?;
+        lines.extend(usage.iter().map(|(line, _)| line.clone()));
+
+        let adjustments = sqlx::query_as::<_, PendingAdjustment>(
+            r#"
+            SELECT a.id, rb.name as profile_name, a.effective_date, a.period_end, a.prorated_amount, a.description
+            FROM recurring_billing_adjustments a
+            JOIN recurring_billing rb ON rb.id = a.recurring_billing_id
+            WHERE rb.client_id = $1 AND a.invoice_id IS NULL AND a.effective_date <= $2
+            ORDER BY a.effective_date
+            FOR UPDATE OF a
+            "#,
+        )
+        .bind(client_id)
+        .bind(run_date)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for adjustment in &adjustments {
+            lines.push(DraftLine {
+                description: adjustment.description.clone().unwrap_or_else(|| {
+                    format!(
+                        "{} - mid-cycle change {} to {}",
+                        adjustment.profile_name,
+                        adjustment.effective_date.format("%Y-%m-%d"),
+                        adjustment.period_end.format("%Y-%m-%d")
+                    )
+                }),
+                quantity: Decimal::ONE,
+                unit_price: adjustment.prorated_amount,
+                tax_rate: None,
+                tax_category: None,
+                source_type: "adjustment".to_string(),
+                source_id: Some(adjustment.id),
+                period_start: Some(adjustment.effective_date),
+                period_end: Some(adjustment.period_end),
+                metric: None,
+            });
+        }
+
+        let time_entries = if self.config.include_time_entries {
+            unbilled_time_lines(&mut tx, client_id, run_date).await?
+        } else {
+            Vec::new()
+        };
+        lines.extend(time_entries.iter().map(|(line, _)| line.clone()));
+
+        let expenses = if self.config.include_expenses {
+            expenses::unbilled_lines(&mut tx, client_id, run_date).await?
+        } else {
+            Vec::new()
+        };
+        lines.extend(expenses.iter().map(|(line, _)| line.clone()));
+
+        // Nothing to charge (e.g. a usage profile with no usage) still advances the schedule
+        let send = !self.config.hold_for_review && profiles.iter().any(|p| p.send_invoice.unwrap_or(true));
+        let invoiced = if lines.iter().any(|l| !l.line_total().is_zero()) {
+            let terms_days = profiles
+                .iter()
+                .filter_map(|p| p.payment_terms_days)
+                .min()
+                .unwrap_or(self.config.default_payment_terms_days);
+
+            let invoice = insert_invoice(&mut tx, client_id, Some(run_id), run_date, terms_days, &lines, send, "Recurring billing")
+                .await?;
+            let invoice_id = invoice.id;
+
+            // Open account credit is used before anything is charged
+            let credited = credit_notes::apply_available_credits(&mut tx, invoice_id, None).await?;
+            let amount_due = invoice.total - credited;
+
+            if self.config.hold_for_review {
+                // Charged, if at all, once the review is approved
+                sqlx::query("UPDATE invoices SET approval_status = 'pending' WHERE id = $1")
+                    .bind(invoice_id)
+                    .execute(&mut *tx)
+                    .await?;
+            } else if let Some(profile) = profiles
+                .iter()
+                .find(|p| p.auto_charge.unwrap_or(false) && p.payment_method_id.is_some())
+                .filter(|_| amount_due > Decimal::ZERO)
+            {
+                sqlx::query(
+                    "INSERT INTO payment_transactions (client_id, invoice_id, payment_method_id, transaction_type, amount,
+                                                       currency, status)
+                     VALUES ($1, $2, $3, 'payment', $4, $5, 'pending')",
+                )
+                .bind(client_id)
+                .bind(invoice_id)
+                .bind(profile.payment_method_id)
+                .bind(amount_due)
+                .bind(&client_currency)
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            Some(invoice)
+        } else {
+            None
+        };
+        let invoice_id = invoiced.as_ref().map(|i| i.id);
+
+        for billed in &billed_periods {
+            let inserted: Option<Uuid> = sqlx::query_scalar(
+                r#"
+                INSERT INTO recurring_billing_periods (recurring_billing_id, period_start, period_end, days_billed,
+                                                       days_in_cycle, amount, invoice_id, billing_run_id)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                ON CONFLICT (recurring_billing_id, period_start) DO NOTHING
+                RETURNING id
+                "#,
+            )
+            .bind(billed.profile_id)
+            .bind(billed.period.start)
+            .bind(billed.period.end)
+            .bind(billed.period.days_billed as i32)
+            .bind(billed.period.days_in_cycle as i32)
+            .bind(billed.amount)
+            .bind(invoice_id)
+            .bind(run_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            if inserted.is_none() {
+                // Rolling back leaves the earlier invoice as the only one for this period
+                return Err(format!(
+                    "Period starting {} of profile {} has already been billed",
+                    billed.period.start, billed.profile_id
+                )
+                .into());
+            }
+        }
+
+        for profile in &profiles {
+            let Some(last) = billed_periods.iter().filter(|b| b.profile_id == profile.id).last() else {
+                continue;
+            };
+            let expired = profile.end_date.map(|end| end <= last.period.end).unwrap_or(false);
+
+            sqlx::query(
+                r#"
+                UPDATE recurring_billing SET next_billing_date = $2, last_billed_date = $3,
+                    status = CASE WHEN $4 THEN 'expired' ELSE status END, updated_at = NOW()
+                WHERE id = $1
+                "#,
+            )
+            .bind(profile.id)
+            .bind(last.period.next_billing_date)
+            .bind(run_date)
+            .bind(expired)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        if let Some(invoice_id) = invoice_id {
+            let adjustment_ids: Vec<Uuid> = adjustments.iter().map(|a| a.id).collect();
+            sqlx::query("UPDATE recurring_billing_adjustments SET invoice_id = $2 WHERE id = ANY($1)")
+                .bind(&adjustment_ids)
+                .bind(invoice_id)
+                .execute(&mut *tx)
+                .await?;
+
+            let usage_profile_ids: Vec<Uuid> = usage.iter().map(|(_, id)| *id).collect();
+            sqlx::query(
+                "UPDATE recurring_billing_usage SET invoice_id = $2
+                 WHERE recurring_billing_id = ANY($1) AND invoice_id IS NULL AND usage_date <= $3",
+            )
+            .bind(&usage_profile_ids)
+            .bind(invoice_id)
+            .bind(run_date)
+            .execute(&mut *tx)
+            .await?;
+
+            let time_entry_ids: Vec<Uuid> = time_entries.iter().map(|(_, id)| *id).collect();
+            sqlx::query("UPDATE time_entries SET billed = true, invoice_id = $2 WHERE id = ANY($1)")
+                .bind(&time_entry_ids)
+                .bind(invoice_id)
+                .execute(&mut *tx)
+                .await?;
+
+            expenses::mark_billed(&mut tx, &expenses, invoice_id).await?;
+        }
+
+        tx.commit().await?;
+
+        let Some(NewInvoice { id: invoice_id, total, base_total, .. }) = invoiced else {
+            return Ok(None);
+        };
+
+        let total = currency::format_money(total, &client_currency);
+        info!("Created recurring invoice {} for client {} ({})", invoice_id, client_id, total);
+
+        if send {
+            notify_invoice_ready(&self.db_pool, client_id, invoice_id, &total).await;
+        }
+
+        Ok(Some((invoice_id, base_total)))
+    }
+
+    async fn usage_lines(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        profiles: &[RecurringProfile],
+        run_date: NaiveDate,
+    ) -> BillingResult<Vec<(DraftLine, Uuid)>> {
+        let usage_profiles: Vec<&RecurringProfile> = profiles.iter().filter(|p| p.billing_type == "usage_based").collect();
+        if usage_profiles.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let ids: Vec<Uuid> = usage_profiles.iter().map(|p| p.id).collect();
+        let totals = sqlx::query_as::<_, UsageTotal>(
+            r#"
+            SELECT recurring_billing_id, SUM(quantity) as quantity, MIN(usage_date) as first_date, MAX(usage_date) as last_date
+            FROM recurring_billing_usage
+            WHERE recurring_billing_id = ANY($1) AND invoice_id IS NULL AND usage_date <= $2
+            GROUP BY recurring_billing_id
+            "#,
+        )
+        .bind(&ids)
+        .bind(run_date)
+        .fetch_all(&mut **tx)
+        .await?;
+
+        let mut lines = Vec::new();
+        for usage in totals {
+            let Some(profile) = usage_profiles.iter().find(|p| p.id == usage.recurring_billing_id) else {
+                continue;
+            };
+
+            let tiers = parse_tiers(profile.pricing_tiers.as_ref());
+            let (quantity, unit_price) = if tiers.is_empty() {
+                (usage.quantity, profile.unit_price.unwrap_or_default())
+            } else {
+                (Decimal::ONE, tiered_amount(usage.quantity, &tiers))
+            };
+
+            lines.push((
+                DraftLine {
+                    description: format!(
+                        "{} - usage {} to {} ({} units)",
+                        profile.name,
+                        usage.first_date.format("%Y-%m-%d"),
+                        usage.last_date.format("%Y-%m-%d"),
+                        usage.quantity.normalize()
+                    ),
+                    quantity,
+                    unit_price,
+                    tax_rate: None,
+                    tax_category: None,
+                    source_type: "usage".to_string(),
+                    source_id: Some(profile.id),
+                    period_start: Some(usage.first_date),
+                    period_end: Some(usage.last_date),
+                    metric: None,
+                },
+                profile.id,
+            ));
+        }
+
+        Ok(lines)
+    }
+}
+
+/// Billable time not yet invoiced, written off or deferred past `run_date`,
+/// priced at the entry's rate, else the client's contract rate. Also staged
+/// by the billing review.
+pub(crate) async fn unbilled_time_lines(
+    tx: &mut Transaction<'_, Postgres>,
+    client_id: Uuid,
+    run_date: NaiveDate,
+) -> BillingResult<Vec<(DraftLine, Uuid)>> {
+    let entries = sqlx::query_as::<_, UnbilledTimeEntry>(
+        r#"
+        SELECT te.id, te.start_time::date as work_date, COALESCE(te.billable_minutes, te.duration_minutes) as duration_minutes,
+               COALESCE(te.hourly_rate,
+                        (SELECT ct.hourly_rate FROM contracts ct
+                         WHERE ct.client_id = $1 AND ct.status = 'active' AND ct.hourly_rate IS NOT NULL
+                         ORDER BY ct.start_date DESC LIMIT 1),
+                        $3) as rate,
+               (SELECT SUM(d.hours) FROM prepaid_block_drawdowns d WHERE d.time_entry_id = te.id) as prepaid_hours,
+               (SELECT ct.overage_rate FROM contract_prepaid_blocks b
+                JOIN contracts ct ON ct.id = b.contract_id
+                WHERE b.client_id = $1 AND ct.overage_rate IS NOT NULL AND ct.status = 'active'
+                  AND b.status IN ('active', 'depleted')
+                  AND (b.expires_on IS NULL OR b.expires_on >= te.start_time::date)
+                ORDER BY b.purchased_on DESC LIMIT 1) as overage_rate,
+               COALESCE(te.description, t.subject, p.name) as description,
+               u.first_name || ' ' || u.last_name as user_name
+        FROM time_entries te
+        JOIN users u ON u.id = te.user_id
+        LEFT JOIN tickets t ON t.id = te.ticket_id
+        LEFT JOIN projects p ON p.id = te.project_id
+        WHERE (t.client_id = $1 OR p.client_id = $1)
+          AND te.billable = true AND te.billed = false AND te.invoice_id IS NULL AND te.written_off_at IS NULL
+          AND te.pending_confirmation = false
+          AND (te.deferred_until IS NULL OR te.deferred_until <= $2)
+          AND te.duration_minutes > 0 AND te.start_time::date <= $2
+        ORDER BY te.start_time
+        FOR UPDATE OF te
+        "#,
+    )
+    .bind(client_id)
+    .bind(run_date)
+    .bind(Decimal::from(DEFAULT_HOURLY_RATE))
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let mut lines = Vec::new();
+    for entry in entries {
+        let hours = (Decimal::from(entry.duration_minutes.unwrap_or(0)) / Decimal::from(60)).round_dp(2);
+        let date = entry.work_date.unwrap_or(run_date);
+        let description = format!(
+            "{} - {}",
+            date.format("%Y-%m-%d"),
+            entry
+                .description
+                .unwrap_or_else(|| format!("Work by {}", entry.user_name.unwrap_or_default()))
+        );
+        let line = |description: String, quantity: Decimal, unit_price: Decimal| DraftLine {
+            description,
+            quantity,
+            unit_price,
+            tax_rate: None,
+            tax_category: Some("labor".to_string()),
+            source_type: "time_entry".to_string(),
+            source_id: Some(entry.id),
+            period_start: Some(date),
+            period_end: Some(date),
+            metric: None,
+        };
+
+        let rate = entry.rate.unwrap_or_else(|| Decimal::from(DEFAULT_HOURLY_RATE));
+        let (prepaid_hours, charged_hours, unit_price) =
+            time_charges(hours, entry.prepaid_hours.unwrap_or_default(), rate, entry.overage_rate);
+        if prepaid_hours > Decimal::ZERO {
+            // Covered hours are listed at no charge so the client sees the drawdown
+            lines.push((line(format!("{} (prepaid block)", description), prepaid_hours, Decimal::ZERO), entry.id));
+
+            if charged_hours > Decimal::ZERO {
+                lines.push((line(format!("{} (overage)", description), charged_hours, unit_price), entry.id));
+            }
+        } else {
+            lines.push((line(description, charged_hours, unit_price), entry.id));
+        }
+    }
+
+    Ok(lines)
+}
+
+/// Splits an entry into prepaid hours and charged hours with their unit price.
+/// Only hours beyond what the entry drew from a block are overage; entries that
+/// drew nothing bill at their normal rate.
+fn time_charges(
+    hours: Decimal,
+    prepaid_hours: Decimal,
+    rate: Decimal,
+    overage_rate: Option<Decimal>,
+) -> (Decimal, Decimal, Decimal) {
+    let prepaid_hours = prepaid_hours.min(hours).max(Decimal::ZERO);
+    if prepaid_hours > Decimal::ZERO {
+        (prepaid_hours, hours - prepaid_hours, overage_rate.unwrap_or(rate))
+    } else {
+        (Decimal::ZERO, hours, rate)
+    }
+}
+
+/// Tells the client's primary contact that an invoice is waiting in the portal.
+pub(crate) async fn notify_invoice_ready(pool: &PgPool, client_id: Uuid, invoice_id: Uuid, total: &str) {
+    let contact_id: Option<Uuid> = match sqlx::query_scalar(
+        "SELECT id FROM contacts WHERE client_id = $1 AND archived_at IS NULL AND email IS NOT NULL
+         ORDER BY is_primary DESC NULLS LAST, created_at LIMIT 1",
+    )
+    .bind(client_id)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(contact_id) => contact_id,
+        Err(e) => {
+            warn!("Could not look up billing contact for client {}: {}", client_id, e);
+            return;
+        }
+    };
+
+    let Some(contact_id) = contact_id else {
+        return;
+    };
+
+    let number: String = sqlx::query_scalar("SELECT number FROM invoices WHERE id = $1")
+        .bind(invoice_id)
+        .fetch_one(pool)
+        .await
+        .unwrap_or_default();
+
+    let notification = QueuedNotification::for_contact(
+        contact_id,
+        "invoice_created",
+        format!("Invoice {} is ready", number),
+        format!("Invoice {} for {} is now available in your client portal.", number, total),
+    )
+    .with_entity("invoice", invoice_id)
+    .with_variables(serde_json::json!({ "invoice_number": number, "total": total }));
+
+    if let Err(e) = enqueue_notification(pool, notification).await {
+        warn!("Failed to queue invoice notification for {}: {}", invoice_id, e);
+    }
+}
+
+async fn load_items(tx: &mut Transaction<'_, Postgres>, profile_id: Uuid) -> BillingResult<Vec<RecurringItem>> {
+    Ok(sqlx::query_as::<_, RecurringItem>(&format!(
+        "SELECT {} FROM recurring_billing_items WHERE recurring_billing_id = $1 ORDER BY created_at",
+        ITEM_COLUMNS
+    ))
+    .bind(profile_id)
+    .fetch_all(&mut **tx)
+    .await?)
+}
+
+/// Measures every metered item and rewrites its quantity and total to what the metric says now.
+async fn measure_items(
+    tx: &mut Transaction<'_, Postgres>,
+    client_id: Uuid,
+    items: &mut [RecurringItem],
+) -> BillingResult<HashMap<Uuid, MetricReading>> {
+    let mut readings = HashMap::new();
+
+    for item in items.iter_mut() {
+        let Some(metric_type) = item.metric_type.as_deref() else {
+            continue;
+        };
+        let Some(metric) = BillingMetric::parse(metric_type) else {
+            warn!("Unknown billing metric '{}' on recurring item {}", metric_type, item.id);
+            continue;
+        };
+
+        let reading = billing_metrics::read(&mut **tx, client_id, metric, item).await?;
+
+        sqlx::query(
+            "UPDATE recurring_billing_items SET last_metric_quantity = $2, last_metric_at = NOW() WHERE id = $1",
+        )
+        .bind(item.id)
+        .bind(reading.billed_quantity)
+        .execute(&mut **tx)
+        .await?;
+
+        if reading.changed() {
+            info!(
+                "Recurring item {} quantity changed from {:?} to {}",
+                item.id, reading.previous_quantity, reading.billed_quantity
+            );
+        }
+
+        item.quantity = Some(reading.billed_quantity);
+        item.total = round_money(reading.billed_quantity * item.net_unit_price());
+        readings.insert(item.id, reading);
+    }
+
+    Ok(readings)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct NewInvoice {
+    pub id: Uuid,
+    pub subtotal: Decimal,
+    pub tax_amount: Decimal,
+    pub total: Decimal,
+    pub base_total: Decimal,
+}
+
+/// Writes an invoice and its lines with the next sequential number, taxing
+/// each line for the client's jurisdictions. The invoice is in the client's
+/// currency with the exchange rate of the day stored alongside. Also used
+/// when an accepted quote is converted.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn insert_invoice(
+    tx: &mut Transaction<'_, Postgres>,
+    client_id: Uuid,
+    run_id: Option<Uuid>,
+    run_date: NaiveDate,
+    payment_terms_days: i32,
+    lines: &[DraftLine],
+    send: bool,
+    notes: &str,
+) -> BillingResult<NewInvoice> {
+    // Invoice numbers are sequential across all clients
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext('invoice_number'))")
+        .execute(&mut **tx)
+        .await?;
+
+    let next_number: i32 = sqlx::query_scalar(
+        "SELECT COALESCE(MAX(CAST(SUBSTRING(number FROM '^INV-(\\d+)$') AS INTEGER)), 0) + 1
+         FROM invoices WHERE number ~ '^INV-\\d+$'",
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let tax_context = TaxContext::load(&mut **tx, client_id, run_date).await?;
+    let line_taxes: Vec<_> = lines
+        .iter()
+        .map(|l| tax_context.line_taxes(l.line_total(), l.tax_category.as_deref(), l.tax_rate))
+        .collect();
+
+    let subtotal: Decimal = lines.iter().map(|l| l.line_total()).sum();
+    let tax_amount: Decimal = line_taxes.iter().map(|t| sales_tax::total_tax(t)).sum();
+    let total = subtotal + tax_amount;
+
+    let client_currency = currency::client_currency(&mut **tx, client_id).await?;
+    let rate = currency::snapshot(&mut **tx, &client_currency, run_date).await?;
+    let base_total = rate.to_base(total);
+
+    let invoice_id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO invoices (client_id, number, date, due_date, subtotal, tax_amount, total, balance,
+                              status, payment_terms, notes, billing_run_id, currency, base_currency,
+                              exchange_rate, exchange_rate_date, base_total)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+        RETURNING id
+        "#,
+    )
+    .bind(client_id)
+    .bind(format!("INV-{:06}", next_number))
+    .bind(run_date)
+    .bind(run_date + ChronoDuration::days(payment_terms_days as i64))
+    .bind(subtotal)
+    .bind(tax_amount)
+    .bind(total)
+    .bind(if send { "sent" } else { "draft" })
+    .bind(format!("net_{}", payment_terms_days))
+    .bind(notes)
+    .bind(run_id)
+    .bind(&rate.currency)
+    .bind(&rate.base_currency)
+    .bind(rate.rate)
+    .bind(rate.rate_date)
+    .bind(base_total)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    for (line, taxes) in lines.iter().zip(&line_taxes) {
+        let line_item_id: Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO invoice_line_items (invoice_id, description, quantity, unit_price, line_total,
+                                            tax_rate, tax_amount, tax_category, source_type, source_id,
+                                            period_start, period_end)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            RETURNING id
+            "#,
+        )
+        .bind(invoice_id)
+        .bind(&line.description)
+        .bind(line.quantity)
+        .bind(line.unit_price)
+        .bind(line.line_total())
+        .bind(sales_tax::effective_rate(line.line_total(), taxes))
+        .bind(sales_tax::total_tax(taxes))
+        .bind(&line.tax_category)
+        .bind(&line.source_type)
+        .bind(line.source_id)
+        .bind(line.period_start)
+        .bind(line.period_end)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        if let Some(reading) = &line.metric {
+            sqlx::query(
+                r#"
+                INSERT INTO invoice_line_metric_snapshots (invoice_id, invoice_line_item_id, recurring_billing_item_id,
+                                                           metric_type, metric_filter, measured_quantity,
+                                                           billed_quantity, previous_quantity, evidence)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                "#,
+            )
+            .bind(invoice_id)
+            .bind(line_item_id)
+            .bind(reading.item_id)
+            .bind(reading.metric.as_str())
+            .bind(&reading.filter)
+            .bind(reading.measured_quantity)
+            .bind(reading.billed_quantity)
+            .bind(reading.previous_quantity)
+            .bind(serde_json::to_value(&reading.evidence)?)
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        sales_tax::record_line_taxes(&mut **tx, invoice_id, line_item_id, taxes).await?;
+    }
+
+    Ok(NewInvoice { id: invoice_id, subtotal, tax_amount, total, base_total })
+}
+
+/// Charge for one full cycle of a profile before proration.
+pub fn base_period_amount(profile: &RecurringProfile, items: &[RecurringItem]) -> Decimal {
+    if !items.is_empty() {
+        return items.iter().map(|i| i.total).sum();
+    }
+
+    let quantity = Decimal::from(profile.quantity.unwrap_or(1));
+    let unit_price = profile.unit_price.unwrap_or_default();
+
+    match profile.billing_type.as_str() {
+        "per_user" => round_money(quantity * unit_price),
+        "tiered" => {
+            let tiers = parse_tiers(profile.pricing_tiers.as_ref());
+            if tiers.is_empty() {
+                round_money(quantity * unit_price)
+            } else {
+                tiered_amount(quantity, &tiers)
+            }
+        }
+        // Usage is billed separately in arrears; `amount` is an optional base fee
+        "usage_based" => profile.amount.unwrap_or_default(),
+        _ => profile.amount.unwrap_or_else(|| round_money(quantity * unit_price)),
+    }
+}
+
+/// Invoice lines for one billed period of a profile.
+///
+/// Metered items are expected to carry the quantity from `readings` already;
+/// the reading is attached to the line as evidence.
+pub fn recurring_lines(
+    profile: &RecurringProfile,
+    items: &[RecurringItem],
+    period: &BillingPeriod,
+    readings: &HashMap<Uuid, MetricReading>,
+) -> Vec<DraftLine> {
+    let period_label = format!("{} to {}", period.start.format("%Y-%m-%d"), period.end.format("%Y-%m-%d"));
+    let proration_label = if period.is_partial() {
+        format!(", prorated {}/{} days", period.days_billed, period.days_in_cycle)
+    } else {
+        String::new()
+    };
+
+    let line = |description: String,
+                quantity: Decimal,
+                unit_price: Decimal,
+                tax_rate: Option<Decimal>,
+                tax_category: Option<String>| {
+        // Partial periods bill a single prorated amount rather than fractional quantities
+        let (quantity, unit_price) = if period.is_partial() {
+            (Decimal::ONE, prorate(quantity * unit_price, period.days_billed, period.days_in_cycle))
+        } else {
+            (quantity, unit_price)
+        };
+        DraftLine {
+            description,
+            quantity,
+            unit_price,
+            tax_rate,
+            tax_category,
+            source_type: "recurring".to_string(),
+            source_id: Some(profile.id),
+            period_start: Some(period.start),
+            period_end: Some(period.end),
+            metric: None,
+        }
+    };
+
+    if !items.is_empty() {
+        return items
+            .iter()
+            .map(|item| {
+                let quantity = item.quantity.unwrap_or(Decimal::ONE);
+                let unit_price = if quantity.is_zero() {
+                    item.total
+                } else {
+                    item.total / quantity
+                };
+                let reading = readings.get(&item.id);
+                let measured = reading
+                    .map(|r| format!(", {} {}", r.measured_quantity.normalize(), r.metric.unit_label()))
+                    .unwrap_or_default();
+                let mut draft = line(
+                    format!("{} - {} ({}{}{})", profile.name, item.name, period_label, measured, proration_label),
+                    quantity,
+                    unit_price,
+                    item.tax_rate.filter(|r| !r.is_zero()),
+                    item.tax_category.clone(),
+                );
+                draft.metric = reading.cloned();
+                draft
+            })
+            .collect();
+    }
+
+    let amount = base_period_amount(profile, items);
+    if amount.is_zero() {
+        return Vec::new();
+    }
+
+    match profile.billing_type.as_str() {
+        "per_user" => vec![line(
+            format!("{} ({}{})", profile.name, period_label, proration_label),
+            Decimal::from(profile.quantity.unwrap_or(1)),
+            profile.unit_price.unwrap_or_default(),
+            None,
+            None,
+        )],
+        "tiered" => vec![line(
+            format!("{} - {} units, tiered ({}{})", profile.name, profile.quantity.unwrap_or(1), period_label, proration_label),
+            Decimal::ONE,
+            amount,
+            None,
+            None,
+        )],
+        "usage_based" => vec![line(
+            format!("{} - base fee ({}{})", profile.name, period_label, proration_label),
+            Decimal::ONE,
+            amount,
+            None,
+            None,
+        )],
+        _ => vec![line(
+            format!("{} ({}{})", profile.name, period_label, proration_label),
+            Decimal::ONE,
+            amount,
+            None,
+            None,
+        )],
+    }
+}
+
+fn cycle_months(frequency: &str) -> Option<u32> {
+    match frequency {
+        "monthly" => Some(1),
+        "quarterly" => Some(3),
+        "semi_annually" | "semiannually" => Some(6),
+        "annually" | "yearly" => Some(12),
+        _ => None,
+    }
+}
+
+fn cycle_days(frequency: &str) -> i64 {
+    match frequency {
+        "daily" => 1,
+        "biweekly" => 14,
+        _ => 7,
+    }
+}
+
+fn clamped_date(year: i32, month: u32, day: u32) -> NaiveDate {
+    (1..=day.max(1))
+        .rev()
+        .find_map(|d| NaiveDate::from_ymd_opt(year, month, d))
+        .unwrap_or_default()
+}
+
+fn anchor_day(billing_day: Option<i32>, start_date: NaiveDate) -> u32 {
+    billing_day
+        .filter(|d| (1..=31).contains(d))
+        .map(|d| d as u32)
+        .unwrap_or_else(|| start_date.day())
+}
+
+/// The date one billing cycle after `date`, keeping month-based cycles on their anchor day.
+pub fn advance_date(date: NaiveDate, frequency: &str, billing_day: Option<i32>, start_date: NaiveDate) -> NaiveDate {
+    match cycle_months(frequency) {
+        Some(months) => {
+            let next = date.checked_add_months(Months::new(months)).unwrap_or(date);
+            clamped_date(next.year(), next.month(), anchor_day(billing_day, start_date))
+        }
+        None => date + ChronoDuration::days(cycle_days(frequency)),
+    }
+}
+
+fn retreat_date(date: NaiveDate, frequency: &str, billing_day: Option<i32>, start_date: NaiveDate) -> NaiveDate {
+    match cycle_months(frequency) {
+        Some(months) => {
+            let previous = date.checked_sub_months(Months::new(months)).unwrap_or(date);
+            clamped_date(previous.year(), previous.month(), anchor_day(billing_day, start_date))
+        }
+        None => date - ChronoDuration::days(cycle_days(frequency)),
+    }
+}
+
+/// First billing-day anchor after `date` when `date` itself is off-cycle.
+pub fn aligned_anchor(date: NaiveDate, frequency: &str, billing_day: Option<i32>) -> Option<NaiveDate> {
+    let billing_day = billing_day?;
+
+    if cycle_months(frequency).is_some() {
+        let day = anchor_day(Some(billing_day), date);
+        let this_month = clamped_date(date.year(), date.month(), day);
+        if this_month == date {
+            return None;
+        }
+        if this_month > date {
+            return Some(this_month);
+        }
+        let next = date.checked_add_months(Months::new(1))?;
+        return Some(clamped_date(next.year(), next.month(), day));
+    }
+
+    if frequency == "daily" || !(1..=7).contains(&billing_day) {
+        return None;
+    }
+
+    // Weekly cycles use ISO weekdays, 1 = Monday
+    let current = date.weekday().number_from_monday() as i64;
+    let offset = (billing_day as i64 - current).rem_euclid(7);
+    if offset == 0 {
+        None
+    } else {
+        Some(date + ChronoDuration::days(offset))
+    }
+}
+
+/// Works out the period billed from `next_billing_date`.
+///
+/// Billing is in advance. An off-cycle start bills a stub up to the next
+/// billing-day anchor, and an `end_date` inside the period shortens it; both
+/// are prorated by day when `prorate` is set. Returns `None` once the profile
+/// has ended.
+pub fn billing_period(
+    next_billing_date: NaiveDate,
+    frequency: &str,
+    billing_day: Option<i32>,
+    start_date: NaiveDate,
+    end_date: Option<NaiveDate>,
+    prorate: bool,
+) -> Option<BillingPeriod> {
+    let start = next_billing_date;
+    if end_date.map(|end| end < start).unwrap_or(false) {
+        return None;
+    }
+
+    let (next, cycle_start) = match aligned_anchor(start, frequency, billing_day) {
+        Some(anchor) => (anchor, retreat_date(anchor, frequency, billing_day, start_date)),
+        None => (advance_date(start, frequency, billing_day, start_date), start),
+    };
+
+    let days_in_cycle = (next - cycle_start).num_days().max(1);
+    let mut end = next - ChronoDuration::days(1);
+    let mut days_billed = (next - start).num_days();
+
+    if let Some(end_date) = end_date {
+        if end_date < end {
+            end = end_date;
+            days_billed = (end_date - start).num_days() + 1;
+        }
+    }
+
+    if !prorate {
+        days_billed = days_in_cycle;
+    }
+
+    Some(BillingPeriod {
+        start,
+        end,
+        next_billing_date: next,
+        days_billed: days_billed.min(days_in_cycle),
+        days_in_cycle,
+    })
+}
+
+/// Share of `amount` for a partial cycle, rounded to cents.
+pub fn prorate(amount: Decimal, days_billed: i64, days_in_cycle: i64) -> Decimal {
+    if days_in_cycle <= 0 || days_billed >= days_in_cycle {
+        return round_money(amount);
+    }
+    round_money(amount * Decimal::from(days_billed.max(0)) / Decimal::from(days_in_cycle))
+}
+
+/// Charge or credit for changing a profile from `previous_amount` to
+/// `new_amount` per cycle on `effective_date`, for the rest of an already
+/// billed period ending `period_end`.
+pub fn mid_cycle_adjustment(
+    previous_amount: Decimal,
+    new_amount: Decimal,
+    effective_date: NaiveDate,
+    period_end: NaiveDate,
+    days_in_cycle: i64,
+) -> Decimal {
+    if effective_date > period_end {
+        return Decimal::ZERO;
+    }
+    let days_remaining = (period_end - effective_date).num_days() + 1;
+    prorate(new_amount - previous_amount, days_remaining, days_in_cycle)
+}
+
+/// Graduated pricing: each tier's price applies to the units that fall within it.
+pub fn tiered_amount(quantity: Decimal, tiers: &[PriceTier]) -> Decimal {
+    let mut remaining = quantity;
+    let mut lower = Decimal::ZERO;
+    let mut total = Decimal::ZERO;
+
+    for tier in tiers {
+        if remaining <= Decimal::ZERO {
+            break;
+        }
+        let units = match tier.up_to {
+            Some(upper) => remaining.min((upper - lower).max(Decimal::ZERO)),
+            None => remaining,
+        };
+        total += units * tier.unit_price;
+        remaining -= units;
+        if let Some(upper) = tier.up_to {
+            lower = upper;
+        }
+    }
+
+    round_money(total)
+}
+
+pub fn parse_tiers(value: Option<&serde_json::Value>) -> Vec<PriceTier> {
+    value
+        .and_then(|v| serde_json::from_value::<Vec<PriceTier>>(v.clone()).ok())
+        .unwrap_or_default()
+}
+
+pub fn round_money(amount: Decimal) -> Decimal {
+    amount.round_dp(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_advance_keeps_anchor_day() {
+        let start = date(2024, 1, 31);
+        let feb = advance_date(start, "monthly", None, start);
+        assert_eq!(feb, date(2024, 2, 29));
+        assert_eq!(advance_date(feb, "monthly", None, start), date(2024, 3, 31));
+        assert_eq!(advance_date(date(2024, 1, 1), "quarterly", Some(1), start), date(2024, 4, 1));
+        assert_eq!(advance_date(date(2024, 1, 1), "weekly", None, start), date(2024, 1, 8));
+    }
+
+    #[test]
+    fn test_full_monthly_period() {
+        let period = billing_period(date(2024, 3, 1), "monthly", Some(1), date(2024, 1, 1), None, true).unwrap();
+        assert_eq!(period.start, date(2024, 3, 1));
+        assert_eq!(period.end, date(2024, 3, 31));
+        assert_eq!(period.next_billing_date, date(2024, 4, 1));
+        assert!(!period.is_partial());
+    }
+
+    #[test]
+    fn test_mid_cycle_start_is_prorated_to_anchor() {
+        let start = date(2024, 4, 16);
+        let period = billing_period(start, "monthly", Some(1), start, None, true).unwrap();
+        assert_eq!(period.end, date(2024, 4, 30));
+        assert_eq!(period.next_billing_date, date(2024, 5, 1));
+        assert_eq!((period.days_billed, period.days_in_cycle), (15, 30));
+        assert_eq!(prorate(Decimal::from(300), period.days_billed, period.days_in_cycle), Decimal::from(150));
+    }
+
+    #[test]
+    fn test_end_date_shortens_period() {
+        let start = date(2024, 1, 1);
+        let period = billing_period(date(2024, 6, 1), "monthly", Some(1), start, Some(date(2024, 6, 10)), true).unwrap();
+        assert_eq!(period.end, date(2024, 6, 10));
+        assert_eq!((period.days_billed, period.days_in_cycle), (10, 30));
+        assert!(billing_period(date(2024, 7, 1), "monthly", Some(1), start, Some(date(2024, 6, 10)), true).is_none());
+    }
+
+    #[test]
+    fn test_no_proration_bills_full_cycle() {
+        let start = date(2024, 4, 16);
+        let period = billing_period(start, "monthly", Some(1), start, None, false).unwrap();
+        assert!(!period.is_partial());
+    }
+
+    #[test]
+    fn test_weekly_anchor() {
+        // 2024-04-17 is a Wednesday; billing day 1 is Monday
+        assert_eq!(aligned_anchor(date(2024, 4, 17), "weekly", Some(1)), Some(date(2024, 4, 22)));
+        assert_eq!(aligned_anchor(date(2024, 4, 22), "weekly", Some(1)), None);
+    }
+
+    #[test]
+    fn test_mid_cycle_adjustment() {
+        // 10 of 30 days remain when moving from 300 to 450
+        let amount = mid_cycle_adjustment(Decimal::from(300), Decimal::from(450), date(2024, 4, 21), date(2024, 4, 30), 30);
+        assert_eq!(amount, Decimal::from(50));
+        assert_eq!(
+            mid_cycle_adjustment(Decimal::from(450), Decimal::from(300), date(2024, 4, 21), date(2024, 4, 30), 30),
+            Decimal::from(-50)
+        );
+        assert!(mid_cycle_adjustment(Decimal::from(300), Decimal::from(450), date(2024, 5, 1), date(2024, 4, 30), 30).is_zero());
+    }
+
+    #[test]
+    fn test_overage_rate_only_beyond_drawdown() {
+        let (rate, overage) = (Decimal::from(150), Some(Decimal::from(200)));
+
+        // No drawdown: the normal rate, even for a client with a block on file
+        assert_eq!(time_charges(Decimal::from(3), Decimal::ZERO, rate, overage), (Decimal::ZERO, Decimal::from(3), rate));
+
+        // Partly covered: the remainder is overage
+        assert_eq!(
+            time_charges(Decimal::from(3), Decimal::from(2), rate, overage),
+            (Decimal::from(2), Decimal::from(1), Decimal::from(200))
+        );
+
+        // Fully covered, or drawn on a contract without an overage rate
+        assert_eq!(time_charges(Decimal::from(2), Decimal::from(5), rate, overage).1, Decimal::ZERO);
+        assert_eq!(time_charges(Decimal::from(3), Decimal::from(1), rate, None).2, rate);
+    }
+
+    #[test]
+    fn test_tiered_amount() {
+        let tiers = vec![
+            PriceTier { up_to: Some(Decimal::from(10)), unit_price: Decimal::from(50) },
+            PriceTier { up_to: None, unit_price: Decimal::from(40) },
+        ];
+        assert_eq!(tiered_amount(Decimal::from(4), &tiers), Decimal::from(200));
+        assert_eq!(tiered_amount(Decimal::from(15), &tiers), Decimal::from(700));
+    }
+}
@@ -0,0 +1,372 @@
+use crate::services::recurring_billing::round_money;
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub type TaxResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+pub const JURISDICTION_COLUMNS: &str = "j.id, j.name, j.level, j.state_code, j.county, j.city, j.rate, j.is_compound,
+    j.priority, j.effective_from, j.effective_to, j.is_active, j.created_at, j.updated_at";
+
+pub const CERTIFICATE_COLUMNS: &str = "id, client_id, certificate_number, state_code, jurisdiction_id, category_codes,
+    exemption_reason, issued_date, expiry_date, file_id, notes, revoked_at, created_by, created_at";
+
+pub const LINE_TAX_COLUMNS: &str = "jurisdiction_id, jurisdiction_name, level, state_code, rate, is_compound,
+    taxable_amount, exempt_amount, exempt_reason, exemption_certificate_id, tax_amount";
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TaxJurisdiction {
+    pub id: Uuid,
+    pub name: String,
+    pub level: String,
+    pub state_code: String,
+    pub county: Option<String>,
+    pub city: Option<String>,
+    pub rate: Decimal,
+    pub is_compound: bool,
+    pub priority: i32,
+    pub effective_from: NaiveDate,
+    pub effective_to: Option<NaiveDate>,
+    pub is_active: bool,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ExemptionCertificate {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub certificate_number: String,
+    pub state_code: String,
+    pub jurisdiction_id: Option<Uuid>,
+    pub category_codes: Option<Vec<String>>,
+    pub exemption_reason: String,
+    pub issued_date: NaiveDate,
+    pub expiry_date: Option<NaiveDate>,
+    pub file_id: Option<Uuid>,
+    pub notes: Option<String>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_by: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl ExemptionCertificate {
+    fn covers(&self, jurisdiction: &TaxJurisdiction, category: &str) -> bool {
+        self.state_code.eq_ignore_ascii_case(&jurisdiction.state_code)
+            && self.jurisdiction_id.is_none_or(|id| id == jurisdiction.id)
+            && self
+                .category_codes
+                .as_ref()
+                .is_none_or(|codes| codes.iter().any(|c| c == category))
+    }
+}
+
+/// Tax charged (or not charged) on one line by one jurisdiction.
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub struct LineTax {
+    pub jurisdiction_id: Option<Uuid>,
+    pub jurisdiction_name: String,
+    pub level: String,
+    pub state_code: Option<String>,
+    pub rate: Decimal,
+    pub is_compound: bool,
+    pub taxable_amount: Decimal,
+    pub exempt_amount: Decimal,
+    pub exempt_reason: Option<String>,
+    pub exemption_certificate_id: Option<Uuid>,
+    pub tax_amount: Decimal,
+}
+
+/// Everything needed to tax a client's lines on a given date.
+#[derive(Debug, Default)]
+pub struct TaxContext {
+    jurisdictions: Vec<TaxJurisdiction>, // state, county, city, special; then priority
+    categories: HashMap<String, bool>,
+    rules: HashMap<(Uuid, String), bool>,
+    certificates: Vec<ExemptionCertificate>,
+}
+
+impl TaxContext {
+    /// Loads the jurisdictions for the client's tax location (its tax profile,
+    /// falling back to its address) and the certificates valid on `date`.
+    pub async fn load(conn: &mut PgConnection, client_id: Uuid, date: NaiveDate) -> TaxResult<Self> {
+        let jurisdictions = sqlx::query_as::<_, TaxJurisdiction>(&format!(
+            r#"
+            WITH location AS (
+                SELECT UPPER(COALESCE(tp.state_code, c.state)) as state_code, tp.county,
+                       COALESCE(tp.city, c.city) as city
+                FROM clients c
+                LEFT JOIN client_tax_profiles tp ON tp.client_id = c.id
+                WHERE c.id = $1
+            )
+            SELECT {} FROM tax_jurisdictions j, location l
+            WHERE j.is_active = true
+              AND j.state_code = l.state_code
+              AND (j.county IS NULL OR LOWER(j.county) = LOWER(l.county))
+              AND (j.city IS NULL OR LOWER(j.city) = LOWER(l.city))
+              AND j.effective_from <= $2 AND (j.effective_to IS NULL OR j.effective_to >= $2)
+            ORDER BY CASE j.level WHEN 'state' THEN 0 WHEN 'county' THEN 1 WHEN 'city' THEN 2 ELSE 3 END,
+                     j.priority, j.name
+            "#,
+            JURISDICTION_COLUMNS
+        ))
+        .bind(client_id)
+        .bind(date)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        if jurisdictions.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let categories: Vec<(String, bool)> = sqlx::query_as("SELECT code, is_taxable FROM tax_categories")
+            .fetch_all(&mut *conn)
+            .await?;
+
+        let ids: Vec<Uuid> = jurisdictions.iter().map(|j| j.id).collect();
+        let rules: Vec<(Uuid, String, bool)> = sqlx::query_as(
+            "SELECT jurisdiction_id, category_code, is_taxable FROM tax_category_rules WHERE jurisdiction_id = ANY($1)",
+        )
+        .bind(&ids)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let certificates = sqlx::query_as::<_, ExemptionCertificate>(&format!(
+            "SELECT {} FROM tax_exemption_certificates
+             WHERE client_id = $1 AND revoked_at IS NULL
+               AND issued_date <= $2 AND (expiry_date IS NULL OR expiry_date >= $2)
+             ORDER BY issued_date",
+            CERTIFICATE_COLUMNS
+        ))
+        .bind(client_id)
+        .bind(date)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        Ok(Self {
+            jurisdictions,
+            categories: categories.into_iter().collect(),
+            rules: rules.into_iter().map(|(id, code, taxable)| ((id, code), taxable)).collect(),
+            certificates,
+        })
+    }
+
+    pub fn jurisdictions(&self) -> &[TaxJurisdiction] {
+        &self.jurisdictions
+    }
+
+    fn is_taxable(&self, jurisdiction: &TaxJurisdiction, category: &str) -> bool {
+        self.rules
+            .get(&(jurisdiction.id, category.to_string()))
+            .or_else(|| self.categories.get(category))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Taxes one line. A rate entered on the line replaces the jurisdiction
+    /// rates; lines without a category aren't taxed.
+    ///
+    /// Each jurisdiction is charged on the line amount, except compound ones,
+    /// which are charged on the amount plus the taxes applied before them.
+    pub fn line_taxes(&self, amount: Decimal, category: Option<&str>, rate_override: Option<Decimal>) -> Vec<LineTax> {
+        if let Some(rate) = rate_override.filter(|r| !r.is_zero()) {
+            return vec![LineTax {
+                jurisdiction_id: None,
+                jurisdiction_name: "Tax".to_string(),
+                level: "manual".to_string(),
+                state_code: None,
+                rate,
+                is_compound: false,
+                taxable_amount: amount,
+                exempt_amount: Decimal::ZERO,
+                exempt_reason: None,
+                exemption_certificate_id: None,
+                tax_amount: round_money(amount * rate / Decimal::from(100)),
+            }];
+        }
+        let Some(category) = category else {
+            return Vec::new();
+        };
+
+        let mut taxes: Vec<LineTax> = Vec::with_capacity(self.jurisdictions.len());
+        for jurisdiction in &self.jurisdictions {
+            let certificate = self.certificates.iter().find(|c| c.covers(jurisdiction, category));
+            let exempt_reason = if !self.is_taxable(jurisdiction, category) {
+                Some("category")
+            } else if certificate.is_some() {
+                Some("certificate")
+            } else {
+                None
+            };
+
+            let (taxable_amount, exempt_amount, tax_amount) = if exempt_reason.is_some() {
+                (Decimal::ZERO, amount, Decimal::ZERO)
+            } else {
+                let base = if jurisdiction.is_compound {
+                    amount + taxes.iter().map(|t| t.tax_amount).sum::<Decimal>()
+                } else {
+                    amount
+                };
+                (base, Decimal::ZERO, round_money(base * jurisdiction.rate / Decimal::from(100)))
+            };
+
+            taxes.push(LineTax {
+                jurisdiction_id: Some(jurisdiction.id),
+                jurisdiction_name: jurisdiction.name.clone(),
+                level: jurisdiction.level.clone(),
+                state_code: Some(jurisdiction.state_code.clone()),
+                rate: jurisdiction.rate,
+                is_compound: jurisdiction.is_compound,
+                taxable_amount,
+                exempt_amount,
+                exempt_reason: exempt_reason.map(str::to_string),
+                exemption_certificate_id: certificate.filter(|_| exempt_reason == Some("certificate")).map(|c| c.id),
+                tax_amount,
+            });
+        }
+
+        taxes
+    }
+}
+
+pub fn total_tax(taxes: &[LineTax]) -> Decimal {
+    taxes.iter().map(|t| t.tax_amount).sum()
+}
+
+/// Combined rate shown on a line, or `None` when nothing was charged.
+pub fn effective_rate(amount: Decimal, taxes: &[LineTax]) -> Option<Decimal> {
+    let tax = total_tax(taxes);
+    if tax.is_zero() || amount.is_zero() {
+        return None;
+    }
+    Some((tax / amount * Decimal::from(100)).round_dp(4))
+}
+
+pub async fn record_line_taxes(
+    conn: &mut PgConnection,
+    invoice_id: Uuid,
+    line_item_id: Uuid,
+    taxes: &[LineTax],
+) -> TaxResult<()> {
+    for tax in taxes {
+        sqlx::query(
+            r#"
+            INSERT INTO invoice_line_taxes (invoice_id, invoice_line_item_id, jurisdiction_id, jurisdiction_name, level,
+                                            state_code, rate, is_compound, taxable_amount, exempt_amount, exempt_reason,
+                                            exemption_certificate_id, tax_amount)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            "#,
+        )
+        .bind(invoice_id)
+        .bind(line_item_id)
+        .bind(tax.jurisdiction_id)
+        .bind(&tax.jurisdiction_name)
+        .bind(&tax.level)
+        .bind(&tax.state_code)
+        .bind(tax.rate)
+        .bind(tax.is_compound)
+        .bind(tax.taxable_amount)
+        .bind(tax.exempt_amount)
+        .bind(&tax.exempt_reason)
+        .bind(tax.exemption_certificate_id)
+        .bind(tax.tax_amount)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jurisdiction(level: &str, rate: i64, is_compound: bool) -> TaxJurisdiction {
+        TaxJurisdiction {
+            id: Uuid::new_v4(),
+            name: format!("{} tax", level),
+            level: level.to_string(),
+            state_code: "TX".to_string(),
+            county: None,
+            city: None,
+            rate: Decimal::new(rate, 2),
+            is_compound,
+            priority: 0,
+            effective_from: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            effective_to: None,
+            is_active: true,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn context(jurisdictions: Vec<TaxJurisdiction>) -> TaxContext {
+        TaxContext {
+            jurisdictions,
+            categories: [("hardware".to_string(), true), ("labor".to_string(), false)].into_iter().collect(),
+            ..TaxContext::default()
+        }
+    }
+
+    #[test]
+    fn test_stacks_jurisdictions_and_compounds() {
+        let ctx = context(vec![jurisdiction("state", 625, false), jurisdiction("city", 200, true)]);
+        let taxes = ctx.line_taxes(Decimal::from(1000), Some("hardware"), None);
+
+        assert_eq!(taxes[0].tax_amount, Decimal::new(6250, 2));
+        // 2% of 1062.50
+        assert_eq!(taxes[1].taxable_amount, Decimal::new(106250, 2));
+        assert_eq!(taxes[1].tax_amount, Decimal::new(2125, 2));
+        assert_eq!(total_tax(&taxes), Decimal::new(8375, 2));
+    }
+
+    #[test]
+    fn test_category_rules_and_certificates_exempt() {
+        let state = jurisdiction("state", 625, false);
+        let city = jurisdiction("city", 200, false);
+        let mut ctx = context(vec![state.clone(), city.clone()]);
+
+        let labor = ctx.line_taxes(Decimal::from(500), Some("labor"), None);
+        assert!(labor.iter().all(|t| t.tax_amount.is_zero() && t.exempt_reason.as_deref() == Some("category")));
+        assert!(ctx.line_taxes(Decimal::from(500), None, None).is_empty());
+
+        // The city taxes labour even though the category default doesn't
+        ctx.rules.insert((city.id, "labor".to_string()), true);
+        assert_eq!(total_tax(&ctx.line_taxes(Decimal::from(500), Some("labor"), None)), Decimal::from(10));
+
+        ctx.certificates.push(ExemptionCertificate {
+            id: Uuid::new_v4(),
+            client_id: Uuid::new_v4(),
+            certificate_number: "RESALE-1".to_string(),
+            state_code: "tx".to_string(),
+            jurisdiction_id: Some(state.id),
+            category_codes: Some(vec!["hardware".to_string()]),
+            exemption_reason: "resale".to_string(),
+            issued_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            expiry_date: None,
+            file_id: None,
+            notes: None,
+            revoked_at: None,
+            created_by: None,
+            created_at: None,
+        });
+        let hardware = ctx.line_taxes(Decimal::from(100), Some("hardware"), None);
+        assert_eq!(hardware[0].exempt_reason.as_deref(), Some("certificate"));
+        assert_eq!(hardware[0].exempt_amount, Decimal::from(100));
+        assert_eq!(hardware[1].tax_amount, Decimal::from(2));
+    }
+
+    #[test]
+    fn test_line_rate_overrides_jurisdictions() {
+        let ctx = context(vec![jurisdiction("state", 625, false)]);
+        let taxes = ctx.line_taxes(Decimal::from(200), Some("hardware"), Some(Decimal::from(10)));
+
+        assert_eq!(taxes.len(), 1);
+        assert_eq!(taxes[0].level, "manual");
+        assert_eq!(total_tax(&taxes), Decimal::from(20));
+        assert_eq!(effective_rate(Decimal::from(200), &taxes), Some(Decimal::from(10)));
+    }
+}
@@ -0,0 +1,655 @@
+use crate::services::EncryptionService;
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sqlx::{FromRow, PgPool};
+use std::time::Duration;
+use tracing::{error, info};
+use uuid::Uuid;
+
+const TWILIO_DEFAULT_ENDPOINT: &str = "https://api.twilio.com";
+
+// Claims the provider message id; returns nothing when the message was already recorded
+const INSERT_INBOUND_SQL: &str = r#"
+    INSERT INTO sms_messages (
+        to_number, from_number, message, client_id, contact_id,
+        provider_id_external, direction, status, received_at
+    )
+    VALUES ($1, $2, $3, $4, $5, $6, 'inbound', 'received', NOW())
+    ON CONFLICT (provider_id_external) WHERE direction = 'inbound' AND provider_id_external IS NOT NULL
+    DO NOTHING
+    RETURNING id
+"#;
+
+// Ticket numbers come from the tickets.number sequence
+const CREATE_TICKET_SQL: &str = r#"
+    INSERT INTO tickets (client_id, contact_id, opened_by, subject, details, priority, status, source)
+    VALUES ($1, $2, $3, $4, $5, 'medium', 'open', 'sms')
+    RETURNING id, number
+"#;
+
+const TICKET_BY_NUMBER_SQL: &str = "SELECT id FROM tickets WHERE number = $1 AND client_id = $2";
+
+const INTAKE_USER_SQL: &str = r#"
+    SELECT id FROM users
+    WHERE COALESCE(is_active, true)
+    ORDER BY (role = 'admin') DESC, created_at
+    LIMIT 1
+"#;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmsSendResult {
+    pub external_id: Option<String>,
+    pub status: String,
+}
+
+#[async_trait]
+pub trait SmsProvider: Send + Sync {
+    fn provider_type(&self) -> &str;
+
+    async fn send_sms(
+        &self,
+        from: &str,
+        to: &str,
+        body: &str,
+    ) -> Result<SmsSendResult, Box<dyn std::error::Error + Send + Sync>>;
+}
+
+// Twilio Messages API. Any endpoint speaking the same form-encoded protocol
+// (including a local mock) can be configured through sms_providers.api_endpoint.
+pub struct TwilioSmsProvider {
+    client: reqwest::Client,
+    api_endpoint: String,
+    account_sid: String,
+    auth_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TwilioMessageResponse {
+    sid: Option<String>,
+    status: Option<String>,
+    message: Option<String>,
+}
+
+impl TwilioSmsProvider {
+    pub fn new(api_endpoint: Option<String>, account_sid: String, auth_token: String) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(15))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            client,
+            api_endpoint: api_endpoint
+                .filter(|e| !e.is_empty())
+                .unwrap_or_else(|| TWILIO_DEFAULT_ENDPOINT.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            account_sid,
+            auth_token,
+        }
+    }
+}
+
+#[async_trait]
+impl SmsProvider for TwilioSmsProvider {
+    fn provider_type(&self) -> &str {
+        "twilio"
+    }
+
+    async fn send_sms(
+        &self,
+        from: &str,
+        to: &str,
+        body: &str,
+    ) -> Result<SmsSendResult, Box<dyn std::error::Error + Send + Sync>> {
+        let url = format!(
+            "{}/2010-04-01/Accounts/{}/Messages.json",
+            self.api_endpoint, self.account_sid
+        );
+
+        let response = self
+            .client
+            .post(&url)
+            .basic_auth(&self.account_sid, Some(&self.auth_token))
+            .form(&[("From", from), ("To", to), ("Body", body)])
+            .send()
+            .await?;
+
+        let status = response.status();
+        let payload: TwilioMessageResponse = response.json().await?;
+
+        if !status.is_success() {
+            return Err(format!(
+                "SMS provider returned {}: {}",
+                status,
+                payload.message.unwrap_or_else(|| "unknown error".to_string())
+            )
+            .into());
+        }
+
+        Ok(SmsSendResult {
+            external_id: payload.sid,
+            status: payload.status.unwrap_or_else(|| "sent".to_string()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct SmsProviderRecord {
+    pub id: Uuid,
+    pub name: String,
+    pub provider_type: String,
+    pub api_endpoint: Option<String>,
+    pub api_key: Option<String>,
+    pub api_secret: Option<String>,
+    pub sender_number: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OutboundSms {
+    pub to_number: String,
+    pub message: String,
+    pub client_id: Option<Uuid>,
+    pub contact_id: Option<Uuid>,
+    pub ticket_id: Option<Uuid>,
+    pub user_id: Option<Uuid>,
+    pub notification_queue_id: Option<Uuid>,
+}
+
+#[derive(Clone)]
+pub struct SmsService {
+    db_pool: PgPool,
+}
+
+impl SmsService {
+    pub fn new(db_pool: PgPool) -> Self {
+        Self { db_pool }
+    }
+
+    pub async fn default_provider(
+        &self,
+    ) -> Result<SmsProviderRecord, Box<dyn std::error::Error + Send + Sync>> {
+        let record = sqlx::query_as::<_, SmsProviderRecord>(
+            r#"
+            SELECT id, name, provider_type, api_endpoint, api_key, api_secret, sender_number
+            FROM sms_providers
+            WHERE is_active = true
+            ORDER BY is_default DESC, created_at
+            LIMIT 1
+            "#
+        )
+        .fetch_optional(&self.db_pool)
+        .await?
+        .ok_or("No active SMS provider configured")?;
+
+        Ok(record)
+    }
+
+    pub fn build_provider(
+        record: &SmsProviderRecord,
+    ) -> Result<Box<dyn SmsProvider>, Box<dyn std::error::Error + Send + Sync>> {
+        let encryption = EncryptionService::new()?;
+        let api_key = record
+            .api_key
+            .as_deref()
+            .map(|k| encryption.decrypt(k))
+            .transpose()?
+            .ok_or("SMS provider is missing its API key")?;
+        let api_secret = record
+            .api_secret
+            .as_deref()
+            .map(|s| encryption.decrypt(s))
+            .transpose()?
+            .ok_or("SMS provider is missing its API secret")?;
+
+        match record.provider_type.as_str() {
+            "twilio" => Ok(Box::new(TwilioSmsProvider::new(
+                record.api_endpoint.clone(),
+                api_key,
+                api_secret,
+            ))),
+            other => Err(format!("Unsupported SMS provider type: {}", other).into()),
+        }
+    }
+
+    // Records the message, hands it to the provider and stores the outcome.
+    pub async fn send(
+        &self,
+        sms: OutboundSms,
+    ) -> Result<Uuid, Box<dyn std::error::Error + Send + Sync>> {
+        let record = self.default_provider().await?;
+        let provider = Self::build_provider(&record)?;
+        let from_number = record
+            .sender_number
+            .clone()
+            .ok_or("SMS provider has no sender number")?;
+        let to_number = normalize_phone_number(&sms.to_number);
+
+        let message_id: Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO sms_messages (
+                provider_id, to_number, from_number, message, client_id, contact_id,
+                ticket_id, user_id, notification_queue_id, direction, status
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, 'outbound', 'pending')
+            RETURNING id
+            "#
+        )
+        .bind(record.id)
+        .bind(&to_number)
+        .bind(&from_number)
+        .bind(&sms.message)
+        .bind(sms.client_id)
+        .bind(sms.contact_id)
+        .bind(sms.ticket_id)
+        .bind(sms.user_id)
+        .bind(sms.notification_queue_id)
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        match provider.send_sms(&from_number, &to_number, &sms.message).await {
+            Ok(result) => {
+                sqlx::query(
+                    r#"
+                    UPDATE sms_messages
+                    SET status = 'sent', provider_id_external = $2, sent_at = NOW()
+                    WHERE id = $1
+                    "#
+                )
+                .bind(message_id)
+                .bind(&result.external_id)
+                .execute(&self.db_pool)
+                .await?;
+
+                info!("SMS {} sent to {} via {}", message_id, to_number, provider.provider_type());
+                Ok(message_id)
+            }
+            Err(e) => {
+                error!("Failed to send SMS {} to {}: {}", message_id, to_number, e);
+
+                sqlx::query(
+                    "UPDATE sms_messages SET status = 'failed', error_message = $2 WHERE id = $1"
+                )
+                .bind(message_id)
+                .bind(e.to_string())
+                .execute(&self.db_pool)
+                .await?;
+
+                Err(e)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InboundSms {
+    pub from_number: String,
+    pub to_number: String,
+    pub body: String,
+    pub external_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InboundSmsResult {
+    pub message_id: Uuid,
+    pub contact_id: Option<Uuid>,
+    pub client_id: Option<Uuid>,
+    pub ticket_id: Option<Uuid>,
+    pub ticket_reply_id: Option<Uuid>,
+    pub created_ticket: bool,
+    pub duplicate: bool, // a provider retry of a message already recorded
+}
+
+#[derive(Debug, FromRow)]
+struct RecordedInbound {
+    id: Uuid,
+    contact_id: Option<Uuid>,
+    client_id: Option<Uuid>,
+    ticket_id: Option<Uuid>,
+    ticket_reply_id: Option<Uuid>,
+}
+
+#[derive(Debug, FromRow)]
+struct MatchedContact {
+    id: Uuid,
+    client_id: Uuid,
+    name: String,
+}
+
+impl SmsService {
+    // Threads an inbound text onto a ticket. The ticket is chosen from an explicit
+    // "#1234" reference, then the last ticket we texted this number about, then the
+    // contact's most recent open ticket. Known contacts with no ticket get a new one.
+    // The provider's message id is claimed first, so a retried webhook changes nothing.
+    pub async fn receive(
+        &self,
+        inbound: InboundSms,
+    ) -> Result<InboundSmsResult, Box<dyn std::error::Error + Send + Sync>> {
+        let from_number = normalize_phone_number(&inbound.from_number);
+        let contact = self.find_contact_by_number(&from_number).await?;
+
+        let mut tx = self.db_pool.begin().await?;
+
+        let message_id: Option<Uuid> = sqlx::query_scalar(INSERT_INBOUND_SQL)
+            .bind(normalize_phone_number(&inbound.to_number))
+            .bind(&from_number)
+            .bind(&inbound.body)
+            .bind(contact.as_ref().map(|c| c.client_id))
+            .bind(contact.as_ref().map(|c| c.id))
+            .bind(&inbound.external_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+        let Some(message_id) = message_id else {
+            tx.rollback().await?;
+            info!("Ignoring repeated inbound SMS {:?}", inbound.external_id);
+            let existing = sqlx::query_as::<_, RecordedInbound>(
+                "SELECT id, contact_id, client_id, ticket_id, ticket_reply_id FROM sms_messages
+                 WHERE provider_id_external = $1 AND direction = 'inbound'",
+            )
+            .bind(&inbound.external_id)
+            .fetch_one(&self.db_pool)
+            .await?;
+            return Ok(InboundSmsResult {
+                message_id: existing.id,
+                contact_id: existing.contact_id,
+                client_id: existing.client_id,
+                ticket_id: existing.ticket_id,
+                ticket_reply_id: existing.ticket_reply_id,
+                created_ticket: false,
+                duplicate: true,
+            });
+        };
+
+        let mut ticket_id = None;
+        let mut ticket_reply_id = None;
+        let mut created_ticket = false;
+
+        if let Some(contact) = &contact {
+            let ticket = match self.find_ticket_for_reply(contact, &from_number, &inbound.body).await? {
+                Some(id) => id,
+                None => {
+                    let opened_by = self.intake_user().await?;
+                    let subject = format!("SMS from {}", contact.name);
+                    let (id, number): (Uuid, i32) = sqlx::query_as(CREATE_TICKET_SQL)
+                        .bind(contact.client_id)
+                        .bind(contact.id)
+                        .bind(opened_by)
+                        .bind(&subject)
+                        .bind(&inbound.body)
+                        .fetch_one(&mut *tx)
+                        .await?;
+
+                    info!("Created ticket #{} from SMS", number);
+                    created_ticket = true;
+                    id
+                }
+            };
+
+            if !created_ticket {
+                let reply_id: Uuid = sqlx::query_scalar(
+                    r#"
+                    INSERT INTO ticket_replies (ticket_id, contact_id, type, details, source, created_at)
+                    VALUES ($1, $2, 'reply', $3, 'sms', NOW())
+                    RETURNING id
+                    "#
+                )
+                .bind(ticket)
+                .bind(contact.id)
+                .bind(&inbound.body)
+                .fetch_one(&mut *tx)
+                .await?;
+
+                sqlx::query(
+                    r#"
+                    UPDATE tickets
+                    SET status = CASE WHEN status IN ('resolved', 'closed') THEN 'open' ELSE status END,
+                        updated_at = NOW()
+                    WHERE id = $1
+                    "#
+                )
+                .bind(ticket)
+                .execute(&mut *tx)
+                .await?;
+
+                ticket_reply_id = Some(reply_id);
+            }
+
+            sqlx::query("UPDATE sms_messages SET ticket_id = $2, ticket_reply_id = $3 WHERE id = $1")
+                .bind(message_id)
+                .bind(ticket)
+                .bind(ticket_reply_id)
+                .execute(&mut *tx)
+                .await?;
+            ticket_id = Some(ticket);
+        }
+
+        tx.commit().await?;
+
+        Ok(InboundSmsResult {
+            message_id,
+            contact_id: contact.as_ref().map(|c| c.id),
+            client_id: contact.as_ref().map(|c| c.client_id),
+            ticket_id,
+            ticket_reply_id,
+            created_ticket,
+            duplicate: false,
+        })
+    }
+
+    // Tickets need a staff member in opened_by; texts from clients are opened
+    // by the longest-standing active admin, falling back to any active user.
+    async fn intake_user(&self) -> Result<Uuid, Box<dyn std::error::Error + Send + Sync>> {
+        let user_id: Option<Uuid> = sqlx::query_scalar(INTAKE_USER_SQL)
+            .fetch_optional(&self.db_pool)
+            .await?;
+        Ok(user_id.ok_or("No active user to open SMS tickets")?)
+    }
+
+    pub async fn update_delivery_status(
+        &self,
+        external_id: &str,
+        status: &str,
+        error_message: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        sqlx::query(
+            r#"
+            UPDATE sms_messages
+            SET status = $2,
+                error_message = COALESCE($3, error_message),
+                delivered_at = CASE WHEN $2 = 'delivered' THEN NOW() ELSE delivered_at END
+            WHERE provider_id_external = $1
+            "#
+        )
+        .bind(external_id)
+        .bind(status)
+        .bind(error_message)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn find_contact_by_number(
+        &self,
+        number: &str,
+    ) -> Result<Option<MatchedContact>, Box<dyn std::error::Error + Send + Sync>> {
+        let digits = phone_match_key(number);
+        if digits.is_empty() {
+            return Ok(None);
+        }
+
+        let contact = sqlx::query_as::<_, MatchedContact>(
+            r#"
+            SELECT id, client_id, name FROM contacts
+            WHERE archived_at IS NULL
+            AND (RIGHT(regexp_replace(mobile, '[^0-9]', '', 'g'), 10) = $1
+                 OR RIGHT(regexp_replace(phone, '[^0-9]', '', 'g'), 10) = $1)
+            ORDER BY is_primary DESC, updated_at DESC
+            LIMIT 1
+            "#
+        )
+        .bind(digits)
+        .fetch_optional(&self.db_pool)
+        .await?;
+
+        Ok(contact)
+    }
+
+    async fn find_ticket_for_reply(
+        &self,
+        contact: &MatchedContact,
+        from_number: &str,
+        body: &str,
+    ) -> Result<Option<Uuid>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(ticket_number) = extract_ticket_number(body) {
+            let ticket: Option<Uuid> = sqlx::query_scalar(TICKET_BY_NUMBER_SQL)
+            .bind(ticket_number)
+            .bind(contact.client_id)
+            .fetch_optional(&self.db_pool)
+            .await?;
+
+            if ticket.is_some() {
+                return Ok(ticket);
+            }
+        }
+
+        let recent: Option<Uuid> = sqlx::query_scalar(
+            r#"
+            SELECT m.ticket_id FROM sms_messages m
+            JOIN tickets t ON t.id = m.ticket_id
+            WHERE m.direction = 'outbound' AND m.to_number = $1
+            AND t.client_id = $2
+            AND m.created_at > NOW() - INTERVAL '7 days'
+            ORDER BY m.created_at DESC
+            LIMIT 1
+            "#
+        )
+        .bind(from_number)
+        .bind(contact.client_id)
+        .fetch_optional(&self.db_pool)
+        .await?;
+
+        if recent.is_some() {
+            return Ok(recent);
+        }
+
+        let open: Option<Uuid> = sqlx::query_scalar(
+            r#"
+            SELECT id FROM tickets
+            WHERE contact_id = $1 AND status NOT IN ('resolved', 'closed')
+            ORDER BY updated_at DESC
+            LIMIT 1
+            "#
+        )
+        .bind(contact.id)
+        .fetch_optional(&self.db_pool)
+        .await?;
+
+        Ok(open)
+    }
+}
+
+/// Pulls a "#1234" ticket reference out of a message body.
+pub fn extract_ticket_number(body: &str) -> Option<i32> {
+    let ticket_regex = regex::Regex::new(r"#(\d+)").ok()?;
+    ticket_regex
+        .captures(body)
+        .and_then(|c| c.get(1))
+        .and_then(|m| m.as_str().parse().ok())
+}
+
+/// Last ten digits of a number, used to match contacts regardless of formatting.
+pub fn phone_match_key(number: &str) -> String {
+    let digits: String = number.chars().filter(|c| c.is_ascii_digit()).collect();
+    digits[digits.len().saturating_sub(10)..].to_string()
+}
+
+/// Normalizes a phone number to E.164. Ten-digit numbers are assumed to be NANP.
+pub fn normalize_phone_number(raw: &str) -> String {
+    let digits: String = raw.chars().filter(|c| c.is_ascii_digit()).collect();
+
+    if digits.len() == 10 {
+        format!("+1{}", digits)
+    } else if raw.trim_start().starts_with('+') || digits.len() > 10 {
+        format!("+{}", digits)
+    } else {
+        digits
+    }
+}
+
+/// Twilio request signature: base64(HMAC-SHA1(auth_token, url + sorted key/value pairs)).
+pub fn twilio_signature(auth_token: &str, url: &str, params: &[(String, String)]) -> String {
+    general_purpose::STANDARD.encode(twilio_mac(auth_token, url, params).finalize().into_bytes())
+}
+
+/// Checks a webhook's X-Twilio-Signature, comparing in constant time.
+pub fn verify_twilio_signature(auth_token: &str, url: &str, params: &[(String, String)], signature: &str) -> bool {
+    let Ok(expected) = general_purpose::STANDARD.decode(signature) else {
+        return false;
+    };
+    twilio_mac(auth_token, url, params).verify_slice(&expected).is_ok()
+}
+
+fn twilio_mac(auth_token: &str, url: &str, params: &[(String, String)]) -> Hmac<Sha1> {
+    let mut sorted = params.to_vec();
+    sorted.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut data = url.to_string();
+    for (key, value) in sorted {
+        data.push_str(&key);
+        data.push_str(&value);
+    }
+
+    let mut mac = Hmac::<Sha1>::new_from_slice(auth_token.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_phone_number() {
+        assert_eq!(normalize_phone_number("(555) 123-4567"), "+15551234567");
+        assert_eq!(normalize_phone_number("+1 555 123 4567"), "+15551234567");
+        assert_eq!(normalize_phone_number("+44 20 7946 0958"), "+442079460958");
+    }
+
+    #[test]
+    fn test_phone_matching_helpers() {
+        assert_eq!(phone_match_key("+1 (555) 123-4567"), "5551234567");
+        assert_eq!(phone_match_key("123"), "123");
+        assert_eq!(extract_ticket_number("Re #1042: still broken"), Some(1042));
+        assert_eq!(extract_ticket_number("still broken"), None);
+    }
+
+    #[test]
+    fn test_twilio_signature() {
+        // Example from Twilio's webhook security documentation
+        let params = vec![
+            ("CallSid".to_string(), "CA1234567890ABCDE".to_string()),
+            ("Caller".to_string(), "+12349013030".to_string()),
+            ("Digits".to_string(), "1234".to_string()),
+            ("From".to_string(), "+12349013030".to_string()),
+            ("To".to_string(), "+18005551212".to_string()),
+        ];
+        let signature = twilio_signature(
+            "12345",
+            "https://mycompany.com/myapp.php?foo=1&bar=2",
+            &params,
+        );
+        assert_eq!(signature, "0/KCTR6DLpKmkAf8muzZqo1nDgQ=");
+
+        let url = "https://mycompany.com/myapp.php?foo=1&bar=2";
+        assert!(verify_twilio_signature("12345", url, &params, &signature));
+        assert!(!verify_twilio_signature("12345", url, &params, "1/KCTR6DLpKmkAf8muzZqo1nDgQ="));
+        assert!(!verify_twilio_signature("12345", url, &params[1..], &signature));
+        assert!(!verify_twilio_signature("12345", url, &params, "not base64"));
+    }
+}
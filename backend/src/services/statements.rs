@@ -0,0 +1,928 @@
+use crate::notifications::{enqueue_notification, QueuedNotification};
+use crate::services::currency::{self, format_money};
+use crate::services::email::EmailAttachment;
+use crate::services::pdf::{Column, PdfDocument};
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc, Weekday};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection, PgPool, Postgres, Transaction};
+use std::collections::BTreeMap;
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+pub type StatementResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+pub const STATEMENT_FREQUENCIES: &[&str] = &["none", "monthly", "quarterly"];
+
+/// Every change to what a client owes, one row per invoice, payment and balance
+/// adjustment. Amounts are signed: positive raises the balance, negative lowers it.
+/// Invoices are charged at their original total; later partial voids and late
+/// fees appear as their own adjustments.
+pub const LEDGER_SQL: &str = r#"
+    SELECT i.id as invoice_id, i.client_id, i.currency, i.date as entry_date, i.created_at as recorded_at,
+           'invoice' as entry_type, i.number as reference, 'Invoice ' || i.number as description,
+           COALESCE(i.total, 0) + COALESCE((SELECT SUM(CASE a.adjustment_type WHEN 'late_fee' THEN -a.amount ELSE a.amount END)
+                                            FROM invoice_adjustments a
+                                            WHERE a.invoice_id = i.id
+                                              AND a.adjustment_type IN ('partial_void', 'late_fee')), 0) as amount
+    FROM invoices i
+    WHERE COALESCE(i.status, 'draft') <> 'draft'
+    UNION ALL
+    SELECT i.id, i.client_id, i.currency, p.payment_date, p.created_at,
+           'payment', COALESCE(p.reference_number, i.number), 'Payment on invoice ' || i.number, -p.amount
+    FROM payments p
+    JOIN invoices i ON i.id = p.invoice_id
+    UNION ALL
+    SELECT i.id, i.client_id, i.currency, a.created_at::date, a.created_at,
+           a.adjustment_type, i.number,
+           CASE a.adjustment_type
+               WHEN 'refund' THEN 'Refund on invoice '
+               WHEN 'credit' THEN 'Credit applied to invoice '
+               WHEN 'void' THEN 'Invoice voided: '
+               WHEN 'late_fee' THEN 'Late fee on invoice '
+               ELSE 'Lines voided on invoice '
+           END || i.number,
+           a.balance_after - a.balance_before
+    FROM invoice_adjustments a
+    JOIN invoices i ON i.id = a.invoice_id
+"#;
+
+pub const STATEMENT_COLUMNS: &str = "s.id, s.client_id, c.name as client_name, s.statement_number, s.period_start,
+    s.period_end, s.currency, COALESCE(s.opening_balance, 0) as opening_balance,
+    COALESCE(s.total_charges, 0) as total_charges, COALESCE(s.total_payments, 0) as total_payments,
+    COALESCE(s.total_credits, 0) as total_credits, COALESCE(s.closing_balance, 0) as closing_balance,
+    COALESCE(s.line_items, '[]'::jsonb) as line_items, s.status, s.sent_at, s.sent_to, s.generated_at";
+
+/// Aging buckets by days past due, as used in the report and CSV headings.
+pub const AGING_BUCKETS: [&str; 6] = ["Current", "1-30", "31-60", "61-90", "91-120", "120+"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Statement {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub statement_number: String,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub currency: String,
+    pub opening_balance: Decimal,
+    pub total_charges: Decimal,
+    pub total_payments: Decimal,
+    pub total_credits: Decimal,
+    pub closing_balance: Decimal,
+    pub line_items: serde_json::Value,
+    pub status: Option<String>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub sent_to: Option<Vec<String>>,
+    pub generated_at: Option<DateTime<Utc>>,
+}
+
+impl Statement {
+    pub fn lines(&self) -> Vec<StatementLine> {
+        serde_json::from_value(self.line_items.clone()).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct LedgerEntry {
+    pub invoice_id: Uuid,
+    pub entry_date: NaiveDate,
+    pub entry_type: String,
+    pub reference: String,
+    pub description: String,
+    pub amount: Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatementLine {
+    pub date: NaiveDate,
+    pub entry_type: String,
+    pub reference: String,
+    pub description: String,
+    pub amount: Decimal,
+    pub balance: Decimal, // running balance after this line
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StatementTotals {
+    pub opening_balance: Decimal,
+    pub total_charges: Decimal,  // invoices, late fees and refunds
+    pub total_payments: Decimal, // payments received
+    pub total_credits: Decimal,  // credits applied and voids
+    pub closing_balance: Decimal,
+}
+
+/// A statement worked out from the ledger but not yet stored.
+#[derive(Debug, Clone)]
+pub struct StatementDraft {
+    pub client_id: Uuid,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub currency: String,
+    pub totals: StatementTotals,
+    pub lines: Vec<StatementLine>,
+}
+
+impl StatementDraft {
+    /// Nothing owed and nothing happened, so there is nothing to send.
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty() && self.totals.opening_balance.is_zero() && self.totals.closing_balance.is_zero()
+    }
+}
+
+/// Totals and running-balance lines for a period from entries up to its end,
+/// oldest first. Entries before the period make up the opening balance.
+pub fn build_statement(entries: &[LedgerEntry], period_start: NaiveDate) -> (StatementTotals, Vec<StatementLine>) {
+    let mut totals = StatementTotals::default();
+    let mut lines = Vec::new();
+
+    for entry in entries {
+        if entry.entry_date < period_start {
+            totals.opening_balance += entry.amount;
+            continue;
+        }
+
+        match entry.entry_type.as_str() {
+            "invoice" | "refund" | "late_fee" => totals.total_charges += entry.amount,
+            "payment" => totals.total_payments -= entry.amount,
+            _ => totals.total_credits -= entry.amount,
+        }
+
+        let balance = totals.opening_balance + totals.total_charges - totals.total_payments - totals.total_credits;
+        lines.push(StatementLine {
+            date: entry.entry_date,
+            entry_type: entry.entry_type.clone(),
+            reference: entry.reference.clone(),
+            description: entry.description.clone(),
+            amount: entry.amount,
+            balance,
+        });
+    }
+
+    totals.closing_balance = totals.opening_balance + totals.total_charges - totals.total_payments - totals.total_credits;
+    (totals, lines)
+}
+
+/// The statement period that has just ended for a frequency: the previous
+/// calendar month or quarter.
+pub fn statement_period(frequency: &str, today: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+    let month_start = today.with_day(1)?;
+    let months = match frequency {
+        "monthly" => 1,
+        "quarterly" => {
+            // Back to the start of the current quarter, then one quarter further
+            let into_quarter = (today.month0() % 3) as u32;
+            return month_start
+                .checked_sub_months(Months::new(into_quarter + 3))
+                .zip(month_start.checked_sub_months(Months::new(into_quarter)))
+                .map(|(start, next)| (start, next.pred_opt().unwrap_or(next)));
+        }
+        _ => return None,
+    };
+
+    let start = month_start.checked_sub_months(Months::new(months))?;
+    Some((start, month_start.pred_opt()?))
+}
+
+pub async fn client_ledger(
+    conn: &mut PgConnection,
+    client_id: Uuid,
+    currency: &str,
+    through: NaiveDate,
+) -> StatementResult<Vec<LedgerEntry>> {
+    Ok(sqlx::query_as::<_, LedgerEntry>(&format!(
+        "WITH ledger AS ({})
+         SELECT invoice_id, entry_date, entry_type, reference, description, amount
+         FROM ledger
+         WHERE client_id = $1 AND currency = $2 AND entry_date <= $3
+         ORDER BY entry_date, recorded_at",
+        LEDGER_SQL
+    ))
+    .bind(client_id)
+    .bind(currency)
+    .bind(through)
+    .fetch_all(conn)
+    .await?)
+}
+
+/// Works out a client's statement in their billing currency.
+pub async fn compute_statement(
+    conn: &mut PgConnection,
+    client_id: Uuid,
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+) -> StatementResult<StatementDraft> {
+    let currency = currency::client_currency(&mut *conn, client_id).await?;
+    let entries = client_ledger(conn, client_id, &currency, period_end).await?;
+    let (totals, lines) = build_statement(&entries, period_start);
+
+    Ok(StatementDraft { client_id, period_start, period_end, currency, totals, lines })
+}
+
+async fn next_statement_number(tx: &mut Transaction<'_, Postgres>) -> StatementResult<String> {
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext('statement_number'))")
+        .execute(&mut **tx)
+        .await?;
+
+    let next: i32 = sqlx::query_scalar(
+        "SELECT COALESCE(MAX(CAST(SUBSTRING(statement_number FROM '^ST-(\\d+)$') AS INTEGER)), 0) + 1
+         FROM billing_statements WHERE statement_number ~ '^ST-\\d+$'",
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(format!("ST-{:06}", next))
+}
+
+/// Stores a statement, replacing an unsent one for the same period so it can
+/// be regenerated after corrections. Returns None when that period's statement
+/// has already been sent.
+pub async fn save_statement(
+    tx: &mut Transaction<'_, Postgres>,
+    draft: &StatementDraft,
+    created_by: Option<Uuid>,
+) -> StatementResult<Option<Uuid>> {
+    let number = next_statement_number(tx).await?;
+
+    let id: Option<Uuid> = sqlx::query_scalar(
+        r#"
+        INSERT INTO billing_statements (client_id, statement_number, period_start, period_end, currency,
+                                        opening_balance, total_charges, total_payments, total_credits,
+                                        closing_balance, line_items, status, generated_at, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, 'final', NOW(), $12)
+        ON CONFLICT (client_id, period_start, period_end) DO UPDATE SET
+            currency = EXCLUDED.currency, opening_balance = EXCLUDED.opening_balance,
+            total_charges = EXCLUDED.total_charges, total_payments = EXCLUDED.total_payments,
+            total_credits = EXCLUDED.total_credits, closing_balance = EXCLUDED.closing_balance,
+            line_items = EXCLUDED.line_items, status = 'final', pdf_path = NULL,
+            generated_at = NOW(), created_by = EXCLUDED.created_by
+        WHERE billing_statements.status IS DISTINCT FROM 'sent'
+        RETURNING id
+        "#,
+    )
+    .bind(draft.client_id)
+    .bind(number)
+    .bind(draft.period_start)
+    .bind(draft.period_end)
+    .bind(&draft.currency)
+    .bind(draft.totals.opening_balance)
+    .bind(draft.totals.total_charges)
+    .bind(draft.totals.total_payments)
+    .bind(draft.totals.total_credits)
+    .bind(draft.totals.closing_balance)
+    .bind(serde_json::to_value(&draft.lines)?)
+    .bind(created_by)
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(id)
+}
+
+pub async fn load_statement(db_pool: &PgPool, id: Uuid) -> StatementResult<Option<Statement>> {
+    Ok(sqlx::query_as::<_, Statement>(&format!(
+        "SELECT {} FROM billing_statements s JOIN clients c ON c.id = s.client_id WHERE s.id = $1",
+        STATEMENT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(db_pool)
+    .await?)
+}
+
+pub fn render_pdf(statement: &Statement) -> Vec<u8> {
+    let money = |amount: Decimal| format_money(amount, &statement.currency);
+
+    let mut doc = PdfDocument::new(&format!("Statement {}", statement.statement_number));
+    doc.heading(&format!("Statement {}", statement.statement_number))
+        .subheading(&statement.client_name)
+        .spacer(6.0)
+        .text(&format!(
+            "Period: {} to {}",
+            statement.period_start.format("%Y-%m-%d"),
+            statement.period_end.format("%Y-%m-%d")
+        ))
+        .spacer(12.0);
+
+    let row = |doc: &mut PdfDocument, cells: [&str; 4], bold: bool| {
+        doc.row(
+            &[
+                Column { text: cells[0], x: 0.0, right_align: false },
+                Column { text: cells[1], x: 75.0, right_align: false },
+                Column { text: cells[2], x: 430.0, right_align: true },
+                Column { text: cells[3], x: 512.0, right_align: true },
+            ],
+            bold,
+        );
+    };
+
+    row(&mut doc, ["Date", "Description", "Amount", "Balance"], true);
+    row(
+        &mut doc,
+        [&statement.period_start.format("%Y-%m-%d").to_string(), "Opening balance", "", &money(statement.opening_balance)],
+        false,
+    );
+    for line in statement.lines() {
+        let description: String = line.description.chars().take(55).collect();
+        row(
+            &mut doc,
+            [&line.date.format("%Y-%m-%d").to_string(), &description, &money(line.amount), &money(line.balance)],
+            false,
+        );
+    }
+    doc.spacer(8.0);
+
+    row(&mut doc, ["", "Opening balance", "", &money(statement.opening_balance)], false);
+    row(&mut doc, ["", "Charges", "", &money(statement.total_charges)], false);
+    row(&mut doc, ["", "Payments", "", &money(-statement.total_payments)], false);
+    row(&mut doc, ["", "Credits", "", &money(-statement.total_credits)], false);
+    row(&mut doc, ["", "Balance due", "", &money(statement.closing_balance)], true);
+
+    doc.to_bytes()
+}
+
+pub async fn write_pdf(statement: &Statement) -> StatementResult<String> {
+    let dir = format!("{}/statements", crate::files::get_upload_directory());
+    tokio::fs::create_dir_all(&dir).await?;
+
+    let path = format!("{}/{}.pdf", dir, statement.id);
+    tokio::fs::write(&path, render_pdf(statement)).await?;
+    Ok(path)
+}
+
+/// Emails the statement PDF to the client's billing contact and marks it sent.
+/// Returns the addresses it went to; empty when the client has no contact with an email.
+pub async fn send_statement(db_pool: &PgPool, statement: &Statement) -> StatementResult<Vec<String>> {
+    let contact: Option<(Uuid, String)> = sqlx::query_as(
+        "SELECT id, email FROM contacts WHERE client_id = $1 AND archived_at IS NULL AND email IS NOT NULL
+         ORDER BY is_primary DESC NULLS LAST, created_at LIMIT 1",
+    )
+    .bind(statement.client_id)
+    .fetch_optional(db_pool)
+    .await?;
+    let Some((contact_id, email)) = contact else {
+        return Ok(Vec::new());
+    };
+
+    let pdf_path = write_pdf(statement).await?;
+    let notification = QueuedNotification::for_contact(
+        contact_id,
+        "account_statement",
+        format!("Statement {} for {}", statement.statement_number, statement.client_name),
+        format!(
+            "Please find your account statement for {} to {} attached. Balance due: {}.",
+            statement.period_start.format("%Y-%m-%d"),
+            statement.period_end.format("%Y-%m-%d"),
+            format_money(statement.closing_balance, &statement.currency)
+        ),
+    )
+    .with_entity("billing_statement", statement.id)
+    .with_variables(serde_json::json!({
+        "statement_number": statement.statement_number,
+        "period_start": statement.period_start,
+        "period_end": statement.period_end,
+        "opening_balance": statement.opening_balance,
+        "closing_balance": statement.closing_balance,
+        "currency": statement.currency,
+    }))
+    .with_attachment(EmailAttachment {
+        filename: format!("{}.pdf", statement.statement_number),
+        content_type: "application/pdf".to_string(),
+        file_path: pdf_path.clone(),
+    });
+    enqueue_notification(db_pool, notification).await?;
+
+    let sent_to = vec![email];
+    sqlx::query(
+        "UPDATE billing_statements SET status = 'sent', sent_at = NOW(), sent_to = $2, pdf_path = $3 WHERE id = $1",
+    )
+    .bind(statement.id)
+    .bind(&sent_to)
+    .bind(&pdf_path)
+    .execute(db_pool)
+    .await?;
+
+    Ok(sent_to)
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct AgingInvoiceRow {
+    pub invoice_id: Uuid,
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub number: String,
+    pub date: NaiveDate,
+    pub due_date: NaiveDate,
+    pub currency: String,
+    pub exchange_rate: Decimal,
+    pub balance: Decimal,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AgingBuckets {
+    pub current: Decimal,
+    pub days_1_30: Decimal,
+    pub days_31_60: Decimal,
+    pub days_61_90: Decimal,
+    pub days_91_120: Decimal,
+    pub days_over_120: Decimal,
+    pub total: Decimal,
+}
+
+impl AgingBuckets {
+    pub fn add(&mut self, days_past_due: i64, amount: Decimal) {
+        let bucket = match aging_bucket(days_past_due) {
+            0 => &mut self.current,
+            1 => &mut self.days_1_30,
+            2 => &mut self.days_31_60,
+            3 => &mut self.days_61_90,
+            4 => &mut self.days_91_120,
+            _ => &mut self.days_over_120,
+        };
+        *bucket += amount;
+        self.total += amount;
+    }
+
+    pub fn values(&self) -> [Decimal; 6] {
+        [
+            self.current,
+            self.days_1_30,
+            self.days_31_60,
+            self.days_61_90,
+            self.days_91_120,
+            self.days_over_120,
+        ]
+    }
+}
+
+/// Index into AGING_BUCKETS for an invoice this many days past its due date.
+pub fn aging_bucket(days_past_due: i64) -> usize {
+    match days_past_due {
+        d if d <= 0 => 0,
+        1..=30 => 1,
+        31..=60 => 2,
+        61..=90 => 3,
+        91..=120 => 4,
+        _ => 5,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AgingInvoice {
+    pub invoice_id: Uuid,
+    pub number: String,
+    pub date: NaiveDate,
+    pub due_date: NaiveDate,
+    pub days_past_due: i64,
+    pub bucket: &'static str,
+    pub balance: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientAging {
+    pub client_id: Uuid,
+    pub client_name: String,
+    pub currency: String,
+    #[serde(flatten)]
+    pub buckets: AgingBuckets,
+    pub invoices: Vec<AgingInvoice>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CurrencyAging {
+    pub currency: String,
+    #[serde(flatten)]
+    pub buckets: AgingBuckets,
+}
+
+/// Receivables by age as of a date. Balances are in each client's currency;
+/// base_totals converts them at the rate snapshotted on each invoice.
+#[derive(Debug, Clone, Serialize)]
+pub struct AgingReport {
+    pub as_of: NaiveDate,
+    pub base_currency: String,
+    pub clients: Vec<ClientAging>,
+    pub currency_totals: Vec<CurrencyAging>,
+    pub base_totals: AgingBuckets,
+}
+
+pub fn build_aging(as_of: NaiveDate, base_currency: String, rows: Vec<AgingInvoiceRow>) -> AgingReport {
+    let mut clients: Vec<ClientAging> = Vec::new();
+    let mut currency_totals: BTreeMap<String, AgingBuckets> = BTreeMap::new();
+    let mut base_totals = AgingBuckets::default();
+
+    for row in rows {
+        let days_past_due = (as_of - row.due_date).num_days();
+
+        let client = match clients
+            .iter_mut()
+            .position(|c| c.client_id == row.client_id && c.currency == row.currency)
+        {
+            Some(index) => &mut clients[index],
+            None => {
+                clients.push(ClientAging {
+                    client_id: row.client_id,
+                    client_name: row.client_name.clone(),
+                    currency: row.currency.clone(),
+                    buckets: AgingBuckets::default(),
+                    invoices: Vec::new(),
+                });
+                clients.last_mut().expect("just pushed")
+            }
+        };
+        client.buckets.add(days_past_due, row.balance);
+        client.invoices.push(AgingInvoice {
+            invoice_id: row.invoice_id,
+            number: row.number,
+            date: row.date,
+            due_date: row.due_date,
+            days_past_due: days_past_due.max(0),
+            bucket: AGING_BUCKETS[aging_bucket(days_past_due)],
+            balance: row.balance,
+        });
+
+        currency_totals.entry(row.currency).or_default().add(days_past_due, row.balance);
+        base_totals.add(days_past_due, currency::convert(row.balance, row.exchange_rate));
+    }
+
+    AgingReport {
+        as_of,
+        base_currency,
+        clients,
+        currency_totals: currency_totals
+            .into_iter()
+            .map(|(currency, buckets)| CurrencyAging { currency, buckets })
+            .collect(),
+        base_totals,
+    }
+}
+
+/// Open invoice balances as of a date, rebuilt from the ledger so past dates
+/// report what was outstanding then.
+pub async fn aging_report(db_pool: &PgPool, as_of: NaiveDate, client_id: Option<Uuid>) -> StatementResult<AgingReport> {
+    let mut conn = db_pool.acquire().await?;
+    let base_currency = currency::base_currency(&mut conn).await?;
+
+    let rows = sqlx::query_as::<_, AgingInvoiceRow>(&format!(
+        "WITH ledger AS ({})
+         SELECT i.id as invoice_id, i.client_id, c.name as client_name, i.number, i.date,
+                COALESCE(i.due_date, i.date) as due_date, i.currency,
+                COALESCE(i.exchange_rate, 1) as exchange_rate, SUM(l.amount) as balance
+         FROM ledger l
+         JOIN invoices i ON i.id = l.invoice_id
+         JOIN clients c ON c.id = i.client_id
+         WHERE l.entry_date <= $1 AND ($2::UUID IS NULL OR i.client_id = $2)
+         GROUP BY i.id, c.name
+         HAVING SUM(l.amount) > 0
+         ORDER BY c.name, i.currency, COALESCE(i.due_date, i.date), i.number",
+        LEDGER_SQL
+    ))
+    .bind(as_of)
+    .bind(client_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(build_aging(as_of, base_currency, rows))
+}
+
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// One row per client and currency, then a total per currency and in base currency.
+pub fn aging_csv(report: &AgingReport) -> String {
+    let mut out = String::new();
+    let mut write_row = |cells: Vec<String>| {
+        out.push_str(&cells.iter().map(|c| csv_field(c)).collect::<Vec<_>>().join(","));
+        out.push_str("\r\n");
+    };
+
+    let mut header = vec!["Client".to_string(), "Currency".to_string()];
+    header.extend(AGING_BUCKETS.iter().map(|b| b.to_string()));
+    header.push("Total".to_string());
+    write_row(header);
+
+    let amounts = |buckets: &AgingBuckets| {
+        let mut cells: Vec<String> = buckets.values().iter().map(|v| v.round_dp(2).to_string()).collect();
+        cells.push(buckets.total.round_dp(2).to_string());
+        cells
+    };
+
+    for client in &report.clients {
+        let mut cells = vec![client.client_name.clone(), client.currency.clone()];
+        cells.extend(amounts(&client.buckets));
+        write_row(cells);
+    }
+    for total in &report.currency_totals {
+        let mut cells = vec!["Total".to_string(), total.currency.clone()];
+        cells.extend(amounts(&total.buckets));
+        write_row(cells);
+    }
+    let mut cells = vec![format!("Total ({} equivalent)", report.base_currency), report.base_currency.clone()];
+    cells.extend(amounts(&report.base_totals));
+    write_row(cells);
+
+    out
+}
+
+#[derive(Debug, Clone)]
+pub struct StatementConfig {
+    pub check_interval_seconds: u64,          // How often due statements and reports are checked for
+    pub aging_report_weekday: Weekday,        // Day the aging report is emailed
+    pub aging_report_recipients: Vec<String>, // Staff emails, from AGING_REPORT_RECIPIENTS (comma separated)
+}
+
+impl Default for StatementConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_seconds: 60 * 60,
+            aging_report_weekday: Weekday::Mon,
+            aging_report_recipients: std::env::var("AGING_REPORT_RECIPIENTS")
+                .map(|v| {
+                    v.split(',')
+                        .map(|e| e.trim().to_string())
+                        .filter(|e| !e.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct StatementService {
+    config: StatementConfig,
+    db_pool: PgPool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StatementRunSummary {
+    pub generated: u32,
+    pub sent: u32,
+    pub skipped: u32,
+}
+
+impl StatementService {
+    pub fn new(config: StatementConfig, db_pool: PgPool) -> Self {
+        Self { config, db_pool }
+    }
+
+    pub async fn start(&self) -> StatementResult<()> {
+        info!("Starting statement worker");
+
+        let mut check_interval = interval(Duration::from_secs(self.config.check_interval_seconds));
+
+        tokio::spawn({
+            let service = self.clone();
+            async move {
+                loop {
+                    check_interval.tick().await;
+
+                    let today = Utc::now().date_naive();
+                    if let Err(e) = service.generate_due_statements(today).await {
+                        error!("Error generating client statements: {}", e);
+                    }
+                    if let Err(e) = service.send_aging_report(today).await {
+                        error!("Error sending aging report: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Generates and sends the statement for the period just ended for every
+    /// client on a statement schedule. Periods already covered are left alone.
+    pub async fn generate_due_statements(&self, today: NaiveDate) -> StatementResult<StatementRunSummary> {
+        let mut summary = StatementRunSummary::default();
+
+        let clients: Vec<(Uuid, String)> = sqlx::query_as(
+            "SELECT id, statement_frequency FROM clients
+             WHERE statement_frequency <> 'none' AND archived_at IS NULL",
+        )
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        for (client_id, frequency) in clients {
+            let Some((period_start, period_end)) = statement_period(&frequency, today) else {
+                continue;
+            };
+
+            let exists: bool = sqlx::query_scalar(
+                "SELECT EXISTS (SELECT 1 FROM billing_statements
+                                WHERE client_id = $1 AND period_start = $2 AND period_end = $3)",
+            )
+            .bind(client_id)
+            .bind(period_start)
+            .bind(period_end)
+            .fetch_one(&self.db_pool)
+            .await?;
+            if exists {
+                continue;
+            }
+
+            let mut tx = self.db_pool.begin().await?;
+            let draft = compute_statement(&mut tx, client_id, period_start, period_end).await?;
+            if draft.is_empty() {
+                summary.skipped += 1;
+                continue;
+            }
+            let Some(id) = save_statement(&mut tx, &draft, None).await? else {
+                continue;
+            };
+            tx.commit().await?;
+            summary.generated += 1;
+
+            let Some(statement) = load_statement(&self.db_pool, id).await? else {
+                continue;
+            };
+            match send_statement(&self.db_pool, &statement).await {
+                Ok(sent_to) if !sent_to.is_empty() => summary.sent += 1,
+                Ok(_) => warn!("Statement {} has no billing contact to send to", statement.statement_number),
+                Err(e) => warn!("Failed to send statement {}: {}", statement.statement_number, e),
+            }
+        }
+
+        if summary.generated > 0 {
+            info!("Generated {} client statements, sent {}", summary.generated, summary.sent);
+        }
+
+        Ok(summary)
+    }
+
+    /// Emails the aging report as CSV to the configured staff on the report day.
+    pub async fn send_aging_report(&self, today: NaiveDate) -> StatementResult<bool> {
+        if today.weekday() != self.config.aging_report_weekday || self.config.aging_report_recipients.is_empty() {
+            return Ok(false);
+        }
+
+        let recipients: Vec<(Uuid, String)> =
+            sqlx::query_as("SELECT id, email FROM users WHERE email = ANY($1) AND is_active IS NOT FALSE")
+                .bind(&self.config.aging_report_recipients)
+                .fetch_all(&self.db_pool)
+                .await?;
+        if recipients.is_empty() {
+            warn!("No users match AGING_REPORT_RECIPIENTS; aging report not sent");
+            return Ok(false);
+        }
+
+        // Claimed first so a restart the same day doesn't send it again
+        let emails: Vec<String> = recipients.iter().map(|(_, email)| email.clone()).collect();
+        let claimed: Option<NaiveDate> = sqlx::query_scalar(
+            "INSERT INTO aging_report_deliveries (report_date, recipients) VALUES ($1, $2)
+             ON CONFLICT (report_date) DO NOTHING RETURNING report_date",
+        )
+        .bind(today)
+        .bind(&emails)
+        .fetch_optional(&self.db_pool)
+        .await?;
+        if claimed.is_none() {
+            return Ok(false);
+        }
+
+        let report = aging_report(&self.db_pool, today, None).await?;
+        let dir = format!("{}/reports", crate::files::get_upload_directory());
+        tokio::fs::create_dir_all(&dir).await?;
+        let path = format!("{}/ar-aging-{}.csv", dir, today.format("%Y-%m-%d"));
+        tokio::fs::write(&path, aging_csv(&report)).await?;
+
+        let total = format_money(report.base_totals.total, &report.base_currency);
+        let overdue = format_money(report.base_totals.total - report.base_totals.current, &report.base_currency);
+        for (user_id, _) in &recipients {
+            let notification = QueuedNotification::for_user(
+                *user_id,
+                "ar_aging_report",
+                format!("AR aging as of {}", today.format("%Y-%m-%d")),
+                format!("Receivables total {}, of which {} is past due. The full aging report is attached.", total, overdue),
+            )
+            .with_variables(serde_json::json!({
+                "as_of": today,
+                "total": report.base_totals.total,
+                "past_due": report.base_totals.total - report.base_totals.current,
+                "currency": report.base_currency,
+            }))
+            .with_attachment(EmailAttachment {
+                filename: format!("ar-aging-{}.csv", today.format("%Y-%m-%d")),
+                content_type: "text/csv".to_string(),
+                file_path: path.clone(),
+            });
+
+            if let Err(e) = enqueue_notification(&self.db_pool, notification).await {
+                warn!("Failed to queue aging report for user {}: {}", user_id, e);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn entry(entry_date: NaiveDate, entry_type: &str, amount: i64) -> LedgerEntry {
+        LedgerEntry {
+            invoice_id: Uuid::nil(),
+            entry_date,
+            entry_type: entry_type.to_string(),
+            reference: "INV-1".to_string(),
+            description: entry_type.to_string(),
+            amount: Decimal::from(amount),
+        }
+    }
+
+    #[test]
+    fn test_build_statement_totals_and_running_balance() {
+        let entries = vec![
+            entry(date(2024, 2, 10), "invoice", 500),
+            entry(date(2024, 2, 20), "payment", -200),
+            entry(date(2024, 3, 1), "invoice", 1000),
+            entry(date(2024, 3, 5), "payment", -300),
+            entry(date(2024, 3, 9), "credit", -100),
+            entry(date(2024, 3, 15), "refund", 50),
+            entry(date(2024, 3, 20), "void", -150),
+        ];
+
+        let (totals, lines) = build_statement(&entries, date(2024, 3, 1));
+        assert_eq!(
+            totals,
+            StatementTotals {
+                opening_balance: Decimal::from(300),
+                total_charges: Decimal::from(1050),
+                total_payments: Decimal::from(300),
+                total_credits: Decimal::from(250),
+                closing_balance: Decimal::from(800),
+            }
+        );
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0].balance, Decimal::from(1300));
+        assert_eq!(lines[4].balance, Decimal::from(800));
+    }
+
+    #[test]
+    fn test_statement_period() {
+        assert_eq!(statement_period("monthly", date(2024, 3, 1)), Some((date(2024, 2, 1), date(2024, 2, 29))));
+        assert_eq!(statement_period("monthly", date(2024, 1, 15)), Some((date(2023, 12, 1), date(2023, 12, 31))));
+        assert_eq!(statement_period("quarterly", date(2024, 4, 1)), Some((date(2024, 1, 1), date(2024, 3, 31))));
+        assert_eq!(statement_period("quarterly", date(2024, 2, 10)), Some((date(2023, 10, 1), date(2023, 12, 31))));
+        assert_eq!(statement_period("none", date(2024, 2, 10)), None);
+    }
+
+    #[test]
+    fn test_aging_bucket_boundaries() {
+        assert_eq!(aging_bucket(-5), 0);
+        assert_eq!(aging_bucket(0), 0);
+        assert_eq!(aging_bucket(1), 1);
+        assert_eq!(aging_bucket(30), 1);
+        assert_eq!(aging_bucket(31), 2);
+        assert_eq!(aging_bucket(90), 3);
+        assert_eq!(aging_bucket(120), 4);
+        assert_eq!(aging_bucket(121), 5);
+    }
+
+    fn aging_row(client: &str, currency: &str, due_date: NaiveDate, balance: i64, rate: Decimal) -> AgingInvoiceRow {
+        AgingInvoiceRow {
+            invoice_id: Uuid::new_v4(),
+            client_id: Uuid::new_v5(&Uuid::NAMESPACE_OID, client.as_bytes()),
+            client_name: client.to_string(),
+            number: "INV".to_string(),
+            date: due_date,
+            due_date,
+            currency: currency.to_string(),
+            exchange_rate: rate,
+            balance: Decimal::from(balance),
+        }
+    }
+
+    #[test]
+    fn test_build_aging_groups_by_client_and_currency() {
+        let as_of = date(2024, 6, 30);
+        let rows = vec![
+            aging_row("Acme, Inc.", "USD", date(2024, 7, 15), 100, Decimal::ONE),
+            aging_row("Acme, Inc.", "USD", date(2024, 5, 20), 200, Decimal::ONE),
+            aging_row("Globex", "EUR", date(2024, 1, 1), 400, Decimal::new(11, 1)),
+        ];
+
+        let report = build_aging(as_of, "USD".to_string(), rows);
+        assert_eq!(report.clients.len(), 2);
+        assert_eq!(report.clients[0].buckets.current, Decimal::from(100));
+        assert_eq!(report.clients[0].buckets.days_31_60, Decimal::from(200));
+        assert_eq!(report.clients[0].buckets.total, Decimal::from(300));
+        assert_eq!(report.clients[1].buckets.days_over_120, Decimal::from(400));
+        assert_eq!(report.base_totals.total, Decimal::from(740));
+
+        let csv = aging_csv(&report);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "Client,Currency,Current,1-30,31-60,61-90,91-120,120+,Total");
+        assert_eq!(lines[1], "\"Acme, Inc.\",USD,100,0,200,0,0,0,300");
+        assert_eq!(lines.last().copied(), Some("Total (USD equivalent),USD,100,0,200,0,0,440.0,740.0"));
+    }
+}
@@ -0,0 +1,791 @@
+use crate::notifications::{enqueue_notification, QueuedNotification};
+use crate::services::prepaid_blocks;
+use crate::services::rate_cards;
+use crate::services::timesheets::{self, format_minutes};
+use chrono::{DateTime, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection, PgPool, Postgres, Transaction};
+use std::collections::{HashMap, HashSet};
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+pub type ReconciliationResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Offline events stamped further ahead than this are clock trouble, not work.
+pub const MAX_CLOCK_SKEW_MINUTES: i64 = 5;
+
+pub const SYNC_EVENT_TYPES: [&str; 3] = ["start", "stop", "switch"];
+
+const DEFAULT_WORKDAY_END: &str = "18:00";
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RunningTimer {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub user_name: String,
+    pub ticket_id: Option<Uuid>,
+    pub client_name: Option<String>,
+    pub description: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub elapsed_minutes: i32,
+    pub long_running_notified_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UnconfirmedEntry {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub user_name: String,
+    pub ticket_id: Option<Uuid>,
+    pub client_name: Option<String>,
+    pub description: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub duration_minutes: Option<i32>,
+    pub auto_stopped_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct EntryOverlap {
+    pub entry_id: Uuid,
+    pub other_entry_id: Uuid,
+    pub user_id: Uuid,
+    pub user_name: String,
+    pub overlap_minutes: i32,
+    pub entry_start: DateTime<Utc>,
+    pub entry_end: Option<DateTime<Utc>>,
+    pub other_start: DateTime<Utc>,
+    pub other_end: Option<DateTime<Utc>>,
+    pub detected_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Reconciliation {
+    pub long_running: Vec<RunningTimer>,
+    pub pending_confirmation: Vec<UnconfirmedEntry>,
+    pub overlaps: Vec<EntryOverlap>,
+}
+
+/// A timer event recorded by a client while it couldn't reach the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimerSyncEvent {
+    pub client_event_id: Uuid,
+    pub event_type: String, // start, stop, switch
+    pub occurred_at: DateTime<Utc>,
+    pub ticket_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub task_id: Option<Uuid>,
+    pub description: Option<String>,
+    pub billable: Option<bool>,
+    pub work_type: Option<String>,
+    pub timer_id: Option<Uuid>,       // stop a timer the server already knows
+    pub start_event_id: Option<Uuid>, // stop a timer that was itself started offline
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimerSyncResult {
+    pub client_event_id: Uuid,
+    pub status: String, // applied, rejected, duplicate
+    pub time_entry_id: Option<Uuid>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct TimerClock {
+    id: Uuid,
+    user_id: Uuid,
+    timezone: String,
+    local_start: NaiveDateTime,
+    workday_end: NaiveTime,
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct OverlapCandidate {
+    entry_id: Uuid,
+    other_entry_id: Uuid,
+    user_id: Uuid,
+    entry_start: DateTime<Utc>,
+    entry_end: Option<DateTime<Utc>>,
+    other_start: DateTime<Utc>,
+    other_end: Option<DateTime<Utc>>,
+}
+
+/// Local time a timer started at `local_start` should be stopped: the end of
+/// that working day, or for work begun after hours, once it has run
+/// `max_minutes`.
+pub fn auto_stop_at(local_start: NaiveDateTime, workday_end: NaiveTime, max_minutes: i64) -> NaiveDateTime {
+    let end_of_day = local_start.date().and_time(workday_end);
+    if end_of_day > local_start {
+        end_of_day
+    } else {
+        local_start + chrono::Duration::minutes(max_minutes)
+    }
+}
+
+/// Whole minutes two entries share; running entries run until `now`.
+pub fn overlap_minutes(
+    a_start: DateTime<Utc>,
+    a_end: Option<DateTime<Utc>>,
+    b_start: DateTime<Utc>,
+    b_end: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> i64 {
+    let start = a_start.max(b_start);
+    let end = a_end.unwrap_or(now).min(b_end.unwrap_or(now));
+    (end - start).num_minutes().max(0)
+}
+
+/// Why an offline event can't be applied, given the user's running timer.
+pub fn sync_rejection(
+    event: &TimerSyncEvent,
+    now: DateTime<Utc>,
+    running_since: Option<DateTime<Utc>>,
+) -> Option<&'static str> {
+    if !SYNC_EVENT_TYPES.contains(&event.event_type.as_str()) {
+        return Some("unknown event type");
+    }
+    if event.occurred_at > now + chrono::Duration::minutes(MAX_CLOCK_SKEW_MINUTES) {
+        return Some("event is in the future");
+    }
+    match (event.event_type.as_str(), running_since) {
+        ("stop", None) => Some("no running timer to stop"),
+        ("stop", Some(since)) if event.occurred_at <= since => Some("stop is before the timer started"),
+        // A timer started after this event means the server has newer state
+        (_, Some(since)) if event.occurred_at < since => Some("a later timer is already running"),
+        _ => None,
+    }
+}
+
+/// The running entry a stop names, given the entry its offline start created.
+/// A stop whose start was rejected or never arrived must not fall back to
+/// whatever the user has running now.
+pub fn stop_target(event: &TimerSyncEvent, started_entry_id: Option<Uuid>) -> Result<Option<Uuid>, &'static str> {
+    match (event.event_type.as_str(), event.start_event_id, event.timer_id) {
+        ("stop", Some(_), _) => started_entry_id.map(Some).ok_or("timer was never started"),
+        ("stop", None, Some(timer_id)) => Ok(Some(timer_id)),
+        _ => Ok(None),
+    }
+}
+
+/// Prices a stopped entry and draws it down from prepaid blocks, as stopping
+/// a timer by hand does.
+async fn price_stopped_entry(pool: &PgPool, entry_id: Uuid) {
+    if let Err(e) = rate_cards::price_time_entry(pool, entry_id).await {
+        error!("Error pricing time entry {}: {}", entry_id, e);
+        return;
+    }
+    if let Err(e) = prepaid_blocks::draw_down_time_entry(pool, entry_id).await {
+        warn!("Error applying time entry {} to prepaid blocks: {}", entry_id, e);
+    }
+}
+
+/// Ends a running entry. The caller prices it once the change is committed.
+async fn stop_entry(conn: &mut PgConnection, entry_id: Uuid, end_time: DateTime<Utc>) -> ReconciliationResult<bool> {
+    let stopped = sqlx::query(
+        "UPDATE time_entries SET end_time = $2, duration_minutes = EXTRACT(EPOCH FROM ($2 - start_time)) / 60,
+                updated_at = NOW()
+         WHERE id = $1 AND end_time IS NULL AND start_time < $2",
+    )
+    .bind(entry_id)
+    .bind(end_time)
+    .execute(conn)
+    .await?;
+
+    Ok(stopped.rows_affected() > 0)
+}
+
+/// What applying one offline event did: the entry it started or stopped, an
+/// entry it ended that needs pricing, or why it was turned away.
+struct SyncOutcome {
+    time_entry_id: Option<Uuid>,
+    stopped_entry_id: Option<Uuid>,
+    rejection: Option<&'static str>,
+}
+
+impl SyncOutcome {
+    fn rejected(time_entry_id: Option<Uuid>, reason: &'static str) -> Self {
+        Self { time_entry_id, stopped_entry_id: None, rejection: Some(reason) }
+    }
+}
+
+/// Applies a client's offline timer events in the order they happened.
+/// Events already seen are reported as duplicates, so clients can retry a
+/// sync safely. Each event is claimed before anything is written, in the
+/// same transaction as its entry changes, so concurrent retries of the same
+/// sync apply it once.
+pub async fn apply_sync_events(
+    pool: &PgPool,
+    user_id: Uuid,
+    mut events: Vec<TimerSyncEvent>,
+    now: DateTime<Utc>,
+) -> ReconciliationResult<Vec<TimerSyncResult>> {
+    events.sort_by_key(|e| e.occurred_at);
+    let mut results = Vec::with_capacity(events.len());
+
+    for event in events {
+        // Nothing to record for events the client shouldn't have sent
+        if !SYNC_EVENT_TYPES.contains(&event.event_type.as_str()) {
+            results.push(TimerSyncResult {
+                client_event_id: event.client_event_id,
+                status: "rejected".to_string(),
+                time_entry_id: None,
+                message: Some("unknown event type".to_string()),
+            });
+            continue;
+        }
+
+        let mut tx = pool.begin().await?;
+        let claimed: Option<Uuid> = sqlx::query_scalar(
+            "INSERT INTO timer_sync_events (client_event_id, user_id, event_type, occurred_at, status)
+             VALUES ($1, $2, $3, $4, 'applied')
+             ON CONFLICT (client_event_id) DO NOTHING
+             RETURNING client_event_id",
+        )
+        .bind(event.client_event_id)
+        .bind(user_id)
+        .bind(&event.event_type)
+        .bind(event.occurred_at)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if claimed.is_none() {
+            tx.rollback().await?;
+            let seen: Option<(Option<Uuid>, Option<String>)> = sqlx::query_as(
+                "SELECT time_entry_id, message FROM timer_sync_events WHERE client_event_id = $1 AND user_id = $2",
+            )
+            .bind(event.client_event_id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+            let (time_entry_id, message) = seen.unwrap_or_default();
+            results.push(TimerSyncResult {
+                client_event_id: event.client_event_id,
+                status: "duplicate".to_string(),
+                time_entry_id,
+                message,
+            });
+            continue;
+        }
+
+        let outcome = apply_sync_event(pool, &mut tx, user_id, &event, now).await?;
+        let status = if outcome.rejection.is_some() { "rejected" } else { "applied" };
+
+        sqlx::query("UPDATE timer_sync_events SET time_entry_id = $2, status = $3, message = $4 WHERE client_event_id = $1")
+            .bind(event.client_event_id)
+            .bind(outcome.time_entry_id)
+            .bind(status)
+            .bind(outcome.rejection)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        if let Some(stopped_id) = outcome.stopped_entry_id {
+            price_stopped_entry(pool, stopped_id).await;
+        }
+
+        results.push(TimerSyncResult {
+            client_event_id: event.client_event_id,
+            status: status.to_string(),
+            time_entry_id: outcome.time_entry_id,
+            message: outcome.rejection.map(str::to_string),
+        });
+    }
+
+    Ok(results)
+}
+
+async fn apply_sync_event(
+    pool: &PgPool,
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    event: &TimerSyncEvent,
+    now: DateTime<Utc>,
+) -> ReconciliationResult<SyncOutcome> {
+    let week_status = timesheets::week_status(pool, user_id, event.occurred_at).await?;
+    if !timesheets::week_is_open(week_status.as_deref()) {
+        return Ok(SyncOutcome::rejected(None, "timesheet week is closed"));
+    }
+
+    // Stops name their timer; starts and switches replace whatever is running
+    let started_entry_id = match (event.event_type.as_str(), event.start_event_id) {
+        ("stop", Some(start_event_id)) => sqlx::query_scalar::<_, Option<Uuid>>(
+            "SELECT time_entry_id FROM timer_sync_events
+             WHERE client_event_id = $1 AND user_id = $2 AND status = 'applied'",
+        )
+        .bind(start_event_id)
+        .bind(user_id)
+        .fetch_optional(&mut **tx)
+        .await?
+        .flatten(),
+        _ => None,
+    };
+    let target = match stop_target(event, started_entry_id) {
+        Ok(target) => target,
+        Err(reason) => return Ok(SyncOutcome::rejected(None, reason)),
+    };
+    let running: Option<(Uuid, DateTime<Utc>)> = sqlx::query_as(
+        "SELECT id, start_time FROM time_entries
+         WHERE user_id = $1 AND end_time IS NULL AND ($2::uuid IS NULL OR id = $2)
+         ORDER BY start_time DESC LIMIT 1
+         FOR UPDATE",
+    )
+    .bind(user_id)
+    .bind(target)
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    if let Some(reason) = sync_rejection(event, now, running.map(|(_, since)| since)) {
+        return Ok(SyncOutcome::rejected(running.map(|(id, _)| id), reason));
+    }
+
+    let mut stopped_entry_id = None;
+    if let Some((running_id, _)) = running {
+        if stop_entry(tx, running_id, event.occurred_at).await? {
+            stopped_entry_id = Some(running_id);
+        }
+        if event.event_type == "stop" {
+            return Ok(SyncOutcome { time_entry_id: Some(running_id), stopped_entry_id, rejection: None });
+        }
+    }
+
+    let entry_id = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO time_entries (id, user_id, ticket_id, project_id, task_id, start_time, description, billable, work_type)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+    )
+    .bind(entry_id)
+    .bind(user_id)
+    .bind(event.ticket_id)
+    .bind(event.project_id)
+    .bind(event.task_id)
+    .bind(event.occurred_at)
+    .bind(&event.description)
+    .bind(event.billable.unwrap_or(true))
+    .bind(&event.work_type)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(SyncOutcome { time_entry_id: Some(entry_id), stopped_entry_id, rejection: None })
+}
+
+/// Confirms an auto-stopped entry, optionally correcting when the work
+/// actually ended. Returns false if the entry wasn't awaiting confirmation.
+pub async fn confirm_entry(pool: &PgPool, entry_id: Uuid, end_time: Option<DateTime<Utc>>) -> ReconciliationResult<bool> {
+    let confirmed = sqlx::query(
+        "UPDATE time_entries SET pending_confirmation = false,
+                end_time = COALESCE($2, end_time),
+                duration_minutes = EXTRACT(EPOCH FROM (COALESCE($2, end_time) - start_time)) / 60,
+                updated_at = NOW()
+         WHERE id = $1 AND pending_confirmation = true AND ($2::timestamptz IS NULL OR $2 > start_time)",
+    )
+    .bind(entry_id)
+    .bind(end_time)
+    .execute(pool)
+    .await?;
+
+    if confirmed.rows_affected() > 0 && end_time.is_some() {
+        price_stopped_entry(pool, entry_id).await;
+    }
+    Ok(confirmed.rows_affected() > 0)
+}
+
+/// Timers running past the threshold, entries awaiting confirmation and
+/// unresolved overlaps, for one technician or everyone.
+pub async fn reconciliation(
+    pool: &PgPool,
+    user_id: Option<Uuid>,
+    long_running_minutes: i64,
+) -> ReconciliationResult<Reconciliation> {
+    let long_running = sqlx::query_as::<_, RunningTimer>(
+        "SELECT te.id, te.user_id, u.first_name || ' ' || u.last_name as user_name, te.ticket_id, c.name as client_name,
+                te.description, te.start_time, (EXTRACT(EPOCH FROM (NOW() - te.start_time)) / 60)::int as elapsed_minutes,
+                te.long_running_notified_at
+         FROM time_entries te
+         JOIN users u ON u.id = te.user_id
+         LEFT JOIN tickets t ON te.ticket_id = t.id
+         LEFT JOIN projects p ON te.project_id = p.id
+         LEFT JOIN clients c ON COALESCE(t.client_id, p.client_id) = c.id
+         WHERE te.end_time IS NULL AND te.start_time < NOW() - make_interval(mins => $2)
+           AND ($1::uuid IS NULL OR te.user_id = $1)
+         ORDER BY te.start_time",
+    )
+    .bind(user_id)
+    .bind(long_running_minutes as i32)
+    .fetch_all(pool)
+    .await?;
+
+    let pending_confirmation = sqlx::query_as::<_, UnconfirmedEntry>(
+        "SELECT te.id, te.user_id, u.first_name || ' ' || u.last_name as user_name, te.ticket_id, c.name as client_name,
+                te.description, te.start_time, te.end_time, te.duration_minutes, te.auto_stopped_at
+         FROM time_entries te
+         JOIN users u ON u.id = te.user_id
+         LEFT JOIN tickets t ON te.ticket_id = t.id
+         LEFT JOIN projects p ON te.project_id = p.id
+         LEFT JOIN clients c ON COALESCE(t.client_id, p.client_id) = c.id
+         WHERE te.pending_confirmation = true AND ($1::uuid IS NULL OR te.user_id = $1)
+         ORDER BY te.start_time",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let overlaps = sqlx::query_as::<_, EntryOverlap>(
+        "SELECT o.entry_id, o.other_entry_id, o.user_id, u.first_name || ' ' || u.last_name as user_name,
+                o.overlap_minutes, a.start_time as entry_start, a.end_time as entry_end,
+                b.start_time as other_start, b.end_time as other_end, o.detected_at
+         FROM time_entry_overlaps o
+         JOIN users u ON u.id = o.user_id
+         JOIN time_entries a ON a.id = o.entry_id
+         JOIN time_entries b ON b.id = o.other_entry_id
+         WHERE o.resolved_at IS NULL AND ($1::uuid IS NULL OR o.user_id = $1)
+         ORDER BY a.start_time",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Reconciliation { long_running, pending_confirmation, overlaps })
+}
+
+#[derive(Debug, Clone)]
+pub struct TimerReconciliationConfig {
+    pub check_interval_seconds: u64,
+    pub long_running_minutes: i64, // Timers running longer are flagged, from TIMER_LONG_RUNNING_HOURS
+    pub overlap_lookback_days: i64,
+}
+
+impl Default for TimerReconciliationConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_seconds: 15 * 60,
+            long_running_minutes: std::env::var("TIMER_LONG_RUNNING_HOURS")
+                .ok()
+                .and_then(|v| v.parse::<i64>().ok())
+                .unwrap_or(10)
+                * 60,
+            overlap_lookback_days: 14,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TimerReconciliationService {
+    config: TimerReconciliationConfig,
+    db_pool: PgPool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReconciliationRunSummary {
+    pub long_running_flagged: u32,
+    pub auto_stopped: u32,
+    pub overlaps_found: u32,
+    pub overlaps_resolved: u32,
+}
+
+impl TimerReconciliationService {
+    pub fn new(config: TimerReconciliationConfig, db_pool: PgPool) -> Self {
+        Self { config, db_pool }
+    }
+
+    pub async fn start(&self) -> ReconciliationResult<()> {
+        info!("Starting timer reconciliation worker");
+
+        let mut check_interval = interval(Duration::from_secs(self.config.check_interval_seconds));
+
+        tokio::spawn({
+            let service = self.clone();
+            async move {
+                loop {
+                    check_interval.tick().await;
+
+                    if let Err(e) = service.run(Utc::now()).await {
+                        error!("Error reconciling timers: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    pub async fn run(&self, now: DateTime<Utc>) -> ReconciliationResult<ReconciliationRunSummary> {
+        let mut summary = ReconciliationRunSummary::default();
+        self.flag_long_running(now, &mut summary).await?;
+        self.auto_stop(now, &mut summary).await?;
+        self.detect_overlaps(now, &mut summary).await?;
+
+        if summary.long_running_flagged + summary.auto_stopped + summary.overlaps_found > 0 {
+            info!(
+                "Timer reconciliation: {} long-running, {} auto-stopped, {} new overlaps",
+                summary.long_running_flagged, summary.auto_stopped, summary.overlaps_found
+            );
+        }
+
+        Ok(summary)
+    }
+
+    async fn flag_long_running(&self, now: DateTime<Utc>, summary: &mut ReconciliationRunSummary) -> ReconciliationResult<()> {
+        let timers: Vec<(Uuid, Uuid, DateTime<Utc>, Option<String>)> = sqlx::query_as(
+            "UPDATE time_entries SET long_running_notified_at = $1
+             WHERE end_time IS NULL AND long_running_notified_at IS NULL
+               AND start_time < $1 - make_interval(mins => $2)
+             RETURNING id, user_id, start_time, description",
+        )
+        .bind(now)
+        .bind(self.config.long_running_minutes as i32)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        for (entry_id, user_id, start_time, description) in timers {
+            let elapsed = (now - start_time).num_minutes() as i32;
+            let notification = QueuedNotification::for_user(
+                user_id,
+                "timer_long_running",
+                "Timer still running".to_string(),
+                format!(
+                    "Your timer{} has been running for {}. Stop it if you're done, or it will be stopped at the end of your working day.",
+                    description.map(|d| format!(" for \"{}\"", d)).unwrap_or_default(),
+                    format_minutes(elapsed)
+                ),
+            )
+            .with_priority("high")
+            .with_entity("time_entry", entry_id);
+
+            match enqueue_notification(&self.db_pool, notification).await {
+                Ok(_) => summary.long_running_flagged += 1,
+                Err(e) => warn!("Failed to queue long-running timer notice {}: {}", entry_id, e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stops timers that have run past the end of the technician's working
+    /// day. They stop at that time, not now, and wait for confirmation
+    /// before they can be billed.
+    async fn auto_stop(&self, now: DateTime<Utc>, summary: &mut ReconciliationRunSummary) -> ReconciliationResult<()> {
+        let timers = sqlx::query_as::<_, TimerClock>(&format!(
+            "SELECT te.id, te.user_id, COALESCE(NULLIF(u.timezone, ''), 'UTC') as timezone,
+                    te.start_time AT TIME ZONE COALESCE(NULLIF(u.timezone, ''), 'UTC') as local_start,
+                    COALESCE(u.workday_end, '{}'::time) as workday_end
+             FROM time_entries te
+             JOIN users u ON u.id = te.user_id
+             WHERE te.end_time IS NULL",
+            DEFAULT_WORKDAY_END
+        ))
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        for timer in timers {
+            let local_stop = auto_stop_at(timer.local_start, timer.workday_end, self.config.long_running_minutes);
+            let stopped: Option<DateTime<Utc>> = sqlx::query_scalar(
+                "UPDATE time_entries SET end_time = ($2::timestamp AT TIME ZONE $3),
+                        duration_minutes = EXTRACT(EPOCH FROM (($2::timestamp AT TIME ZONE $3) - start_time)) / 60,
+                        auto_stopped_at = $4, pending_confirmation = true, updated_at = NOW()
+                 WHERE id = $1 AND end_time IS NULL AND ($2::timestamp AT TIME ZONE $3) <= $4
+                 RETURNING end_time",
+            )
+            .bind(timer.id)
+            .bind(local_stop)
+            .bind(&timer.timezone)
+            .bind(now)
+            .fetch_optional(&self.db_pool)
+            .await?;
+            let Some(end_time) = stopped else {
+                continue;
+            };
+
+            price_stopped_entry(&self.db_pool, timer.id).await;
+            summary.auto_stopped += 1;
+
+            let notification = QueuedNotification::for_user(
+                timer.user_id,
+                "timer_auto_stopped",
+                "Timer stopped automatically".to_string(),
+                format!(
+                    "A timer left running was stopped at the end of your working day ({}). \
+                     Confirm or correct it before it can be billed.",
+                    local_stop.format("%a %b %d %H:%M")
+                ),
+            )
+            .with_priority("high")
+            .with_entity("time_entry", timer.id);
+            if let Err(e) = enqueue_notification(&self.db_pool, notification).await {
+                warn!("Failed to queue auto-stop notice {}: {}", timer.id, e);
+            }
+            info!("Auto-stopped timer {} at {}", timer.id, end_time);
+        }
+
+        Ok(())
+    }
+
+    async fn detect_overlaps(&self, now: DateTime<Utc>, summary: &mut ReconciliationRunSummary) -> ReconciliationResult<()> {
+        let since = now - chrono::Duration::days(self.config.overlap_lookback_days);
+
+        // Adjustments are zero-length corrections, not work
+        let candidates = sqlx::query_as::<_, OverlapCandidate>(
+            "SELECT a.id as entry_id, b.id as other_entry_id, a.user_id,
+                    a.start_time as entry_start, a.end_time as entry_end,
+                    b.start_time as other_start, b.end_time as other_end
+             FROM time_entries a
+             JOIN time_entries b ON b.user_id = a.user_id AND a.id < b.id
+                 AND a.start_time < COALESCE(b.end_time, $1) AND b.start_time < COALESCE(a.end_time, $1)
+             WHERE a.start_time >= $2 AND b.start_time >= $2
+               AND a.adjusts_entry_id IS NULL AND b.adjusts_entry_id IS NULL",
+        )
+        .bind(now)
+        .bind(since)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let open: HashSet<(Uuid, Uuid)> = sqlx::query_as::<_, (Uuid, Uuid)>(
+            "SELECT o.entry_id, o.other_entry_id FROM time_entry_overlaps o
+             JOIN time_entries a ON a.id = o.entry_id
+             WHERE o.resolved_at IS NULL AND a.start_time >= $1",
+        )
+        .bind(since)
+        .fetch_all(&self.db_pool)
+        .await?
+        .into_iter()
+        .collect();
+
+        let mut detected = HashSet::new();
+        let mut new_by_user: HashMap<Uuid, u32> = HashMap::new();
+        for c in candidates {
+            let minutes = overlap_minutes(c.entry_start, c.entry_end, c.other_start, c.other_end, now);
+            if minutes < 1 {
+                continue;
+            }
+            detected.insert((c.entry_id, c.other_entry_id));
+
+            sqlx::query(
+                "INSERT INTO time_entry_overlaps (entry_id, other_entry_id, user_id, overlap_minutes)
+                 VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (entry_id, other_entry_id) DO UPDATE SET overlap_minutes = EXCLUDED.overlap_minutes,
+                     resolved_at = NULL",
+            )
+            .bind(c.entry_id)
+            .bind(c.other_entry_id)
+            .bind(c.user_id)
+            .bind(minutes as i32)
+            .execute(&self.db_pool)
+            .await?;
+
+            if !open.contains(&(c.entry_id, c.other_entry_id)) {
+                *new_by_user.entry(c.user_id).or_default() += 1;
+                summary.overlaps_found += 1;
+            }
+        }
+
+        for (entry_id, other_entry_id) in open.difference(&detected) {
+            sqlx::query("UPDATE time_entry_overlaps SET resolved_at = NOW() WHERE entry_id = $1 AND other_entry_id = $2")
+                .bind(entry_id)
+                .bind(other_entry_id)
+                .execute(&self.db_pool)
+                .await?;
+            summary.overlaps_resolved += 1;
+        }
+
+        for (user_id, count) in new_by_user {
+            let notification = QueuedNotification::for_user(
+                user_id,
+                "time_entries_overlap",
+                "Overlapping time entries".to_string(),
+                format!(
+                    "{} of your time entries overlap another entry. Please correct them so the same time isn't billed twice.",
+                    count
+                ),
+            )
+            .with_priority("high");
+            if let Err(e) = enqueue_notification(&self.db_pool, notification).await {
+                warn!("Failed to queue overlap notice for {}: {}", user_id, e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeZone};
+
+    fn local(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn utc(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, day, hour, 0, 0).unwrap()
+    }
+
+    fn event(event_type: &str, occurred_at: DateTime<Utc>) -> TimerSyncEvent {
+        TimerSyncEvent {
+            client_event_id: Uuid::new_v4(),
+            event_type: event_type.to_string(),
+            occurred_at,
+            ticket_id: None,
+            project_id: None,
+            task_id: None,
+            description: None,
+            billable: None,
+            work_type: None,
+            timer_id: None,
+            start_event_id: None,
+        }
+    }
+
+    #[test]
+    fn test_auto_stop_at_end_of_working_day() {
+        let end = NaiveTime::from_hms_opt(18, 0, 0).unwrap();
+        // Friday morning timer left running all weekend stops Friday evening
+        assert_eq!(auto_stop_at(local(8, 9, 30), end, 600), local(8, 18, 0));
+        // After-hours callouts run for the long-running threshold
+        assert_eq!(auto_stop_at(local(8, 22, 0), end, 600), local(9, 8, 0));
+        assert_eq!(auto_stop_at(local(8, 18, 0), end, 120), local(8, 20, 0));
+    }
+
+    #[test]
+    fn test_overlap_minutes() {
+        let now = utc(10, 12);
+        assert_eq!(overlap_minutes(utc(4, 9), Some(utc(4, 12)), utc(4, 11), Some(utc(4, 13)), now), 60);
+        assert_eq!(overlap_minutes(utc(4, 9), Some(utc(4, 10)), utc(4, 10), Some(utc(4, 11)), now), 0);
+        // A running timer overlaps everything after it started
+        assert_eq!(overlap_minutes(utc(10, 9), None, utc(10, 10), Some(utc(10, 11)), now), 60);
+        assert_eq!(overlap_minutes(utc(10, 9), None, utc(10, 10), None, now), 120);
+    }
+
+    #[test]
+    fn test_sync_rejection() {
+        let now = utc(10, 12);
+        assert_eq!(sync_rejection(&event("start", utc(10, 9)), now, None), None);
+        assert_eq!(sync_rejection(&event("pause", utc(10, 9)), now, None), Some("unknown event type"));
+        assert_eq!(sync_rejection(&event("start", utc(10, 13)), now, None), Some("event is in the future"));
+        assert_eq!(sync_rejection(&event("stop", utc(10, 11)), now, None), Some("no running timer to stop"));
+        assert_eq!(sync_rejection(&event("stop", utc(10, 11)), now, Some(utc(10, 9))), None);
+        assert_eq!(
+            sync_rejection(&event("stop", utc(10, 9)), now, Some(utc(10, 10))),
+            Some("stop is before the timer started")
+        );
+        assert_eq!(
+            sync_rejection(&event("switch", utc(10, 9)), now, Some(utc(10, 10))),
+            Some("a later timer is already running")
+        );
+        assert_eq!(sync_rejection(&event("switch", utc(10, 11)), now, Some(utc(10, 10))), None);
+    }
+
+    #[test]
+    fn test_stop_target_never_falls_back_to_latest_timer() {
+        let entry_id = Uuid::new_v4();
+        let mut stop = event("stop", utc(10, 11));
+        stop.start_event_id = Some(Uuid::new_v4());
+        assert_eq!(stop_target(&stop, Some(entry_id)), Ok(Some(entry_id)));
+        assert_eq!(stop_target(&stop, None), Err("timer was never started"));
+
+        stop.start_event_id = None;
+        stop.timer_id = Some(entry_id);
+        assert_eq!(stop_target(&stop, None), Ok(Some(entry_id)));
+        assert_eq!(stop_target(&event("start", utc(10, 9)), None), Ok(None));
+    }
+}
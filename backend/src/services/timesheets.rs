@@ -0,0 +1,889 @@
+use crate::notifications::{enqueue_notification, QueuedNotification};
+use chrono::{DateTime, Datelike, Days, NaiveDate, Utc};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use tokio::time::{interval, Duration};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+pub type TimesheetResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+pub const TIMESHEET_COLUMNS: &str = "ts.id, ts.user_id, u.first_name || ' ' || u.last_name as user_name, ts.week_start,
+    ts.status, ts.total_minutes, ts.billable_minutes, ts.expected_minutes, ts.submitted_at, ts.submission_note,
+    ts.reviewed_by, ts.reviewed_at, ts.review_comment, ts.created_at, ts.updated_at";
+
+pub const SCHEDULE_COLUMNS: &str = "u.id as user_id, u.first_name || ' ' || u.last_name as user_name, u.manager_id,
+    COALESCE(NULLIF(u.timezone, ''), 'UTC') as timezone, COALESCE(u.expected_daily_hours, 8) as hours_per_day,
+    COALESCE(u.working_days, '{1,2,3,4,5}') as working_days";
+
+/// Entries are dated in the technician's own timezone, so late-evening work
+/// lands on the day it was done.
+const ENTRY_COLUMNS: &str = "te.id, (te.start_time AT TIME ZONE $3)::date as work_date, te.start_time, te.end_time,
+    te.duration_minutes, te.description, COALESCE(te.billable, false) as billable, COALESCE(te.billed, false) as billed,
+    te.invoice_id, te.written_off_at, COALESCE(t.client_id, p.client_id) as client_id, c.name as client_name,
+    te.ticket_id, t.number as ticket_number, t.subject as ticket_subject, te.project_id, p.name as project_name,
+    te.work_type, te.adjusts_entry_id, te.adjustment_reason, te.pending_confirmation";
+
+/// Shortfalls smaller than this are rounding, not missing time.
+pub const MISSING_TIME_TOLERANCE_MINUTES: i32 = 15;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Timesheet {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub user_name: String,
+    pub week_start: NaiveDate,
+    pub status: String, // open, submitted, approved, rejected
+    pub total_minutes: i32,
+    pub billable_minutes: i32,
+    pub expected_minutes: i32,
+    pub submitted_at: Option<DateTime<Utc>>,
+    pub submission_note: Option<String>,
+    pub reviewed_by: Option<Uuid>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+    pub review_comment: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TimesheetEvent {
+    pub id: Uuid,
+    pub action: String,
+    pub comment: Option<String>,
+    pub actor_id: Option<Uuid>,
+    pub actor_name: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// A technician's working pattern, used for expected hours.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct UserSchedule {
+    pub user_id: Uuid,
+    pub user_name: String,
+    pub manager_id: Option<Uuid>,
+    pub timezone: String,
+    pub hours_per_day: Decimal,
+    pub working_days: Vec<i16>, // ISO weekdays, Monday = 1
+}
+
+impl UserSchedule {
+    pub fn expected_minutes(&self, date: NaiveDate) -> i32 {
+        let weekday = date.weekday().number_from_monday() as i16;
+        if !self.working_days.contains(&weekday) {
+            return 0;
+        }
+        (self.hours_per_day * Decimal::from(60)).round().to_i32().unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct TimesheetEntry {
+    pub id: Uuid,
+    pub work_date: NaiveDate,
+    pub start_time: DateTime<Utc>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub duration_minutes: Option<i32>,
+    pub description: Option<String>,
+    pub billable: bool,
+    pub billed: bool,
+    pub invoice_id: Option<Uuid>,
+    pub written_off_at: Option<DateTime<Utc>>,
+    pub client_id: Option<Uuid>,
+    pub client_name: Option<String>,
+    pub ticket_id: Option<Uuid>,
+    pub ticket_number: Option<i32>,
+    pub ticket_subject: Option<String>,
+    pub project_id: Option<Uuid>,
+    pub project_name: Option<String>,
+    pub work_type: Option<String>,
+    pub adjusts_entry_id: Option<Uuid>,
+    pub adjustment_reason: Option<String>,
+    pub pending_confirmation: bool, // auto-stopped timer awaiting the technician
+    #[sqlx(skip)]
+    pub locked: Option<&'static str>, // why the entry can only be changed by adjustment
+}
+
+impl TimesheetEntry {
+    /// Running timers count once they are stopped.
+    pub fn minutes(&self) -> i32 {
+        self.duration_minutes.unwrap_or(0)
+    }
+
+    pub fn billable_minutes(&self) -> i32 {
+        if self.billable { self.minutes() } else { 0 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TicketGroup {
+    pub ticket_id: Option<Uuid>,
+    pub ticket_number: Option<i32>,
+    pub ticket_subject: Option<String>,
+    pub project_id: Option<Uuid>,
+    pub project_name: Option<String>,
+    pub total_minutes: i32,
+    pub billable_minutes: i32,
+    pub entries: Vec<TimesheetEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientGroup {
+    pub client_id: Option<Uuid>, // None for internal work
+    pub client_name: Option<String>,
+    pub total_minutes: i32,
+    pub billable_minutes: i32,
+    pub tickets: Vec<TicketGroup>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimesheetDay {
+    pub date: NaiveDate,
+    pub expected_minutes: i32,
+    pub total_minutes: i32,
+    pub billable_minutes: i32,
+    pub clients: Vec<ClientGroup>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct MissingDay {
+    pub date: NaiveDate,
+    pub expected_minutes: i32,
+    pub logged_minutes: i32,
+    pub missing_minutes: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MissingTime {
+    pub user_id: Uuid,
+    pub user_name: String,
+    pub manager_id: Option<Uuid>,
+    pub week_start: NaiveDate,
+    pub status: String,
+    pub logged_minutes: i32,
+    pub missing_minutes: i32,
+    pub days: Vec<MissingDay>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WeeklyTimesheet {
+    pub timesheet_id: Option<Uuid>, // None until the week is submitted or flagged
+    pub user_id: Uuid,
+    pub user_name: String,
+    pub manager_id: Option<Uuid>,
+    pub week_start: NaiveDate,
+    pub week_end: NaiveDate,
+    pub status: String,
+    pub submitted_at: Option<DateTime<Utc>>,
+    pub submission_note: Option<String>,
+    pub reviewed_by: Option<Uuid>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+    pub review_comment: Option<String>,
+    pub total_minutes: i32,
+    pub billable_minutes: i32,
+    pub expected_minutes: i32,
+    pub running_timers: i32,
+    pub unconfirmed_entries: i32,
+    pub days: Vec<TimesheetDay>,
+    pub missing: Vec<MissingDay>,
+    pub events: Vec<TimesheetEvent>,
+}
+
+/// Monday of the week containing `date`.
+pub fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Days::new(date.weekday().num_days_from_monday() as u64)
+}
+
+/// Why an entry can no longer be edited directly, if it can't. Billed and
+/// written-off work has left the timesheet; submitted and approved weeks are
+/// with, or past, the manager.
+pub fn lock_reason(
+    billed: bool,
+    invoice_id: Option<Uuid>,
+    written_off: bool,
+    timesheet_status: Option<&str>,
+) -> Option<&'static str> {
+    if billed || invoice_id.is_some() {
+        return Some("billed");
+    }
+    if written_off {
+        return Some("written_off");
+    }
+    match timesheet_status {
+        Some("submitted") => Some("submitted"),
+        Some("approved") => Some("approved"),
+        _ => None,
+    }
+}
+
+/// Whether a week in this status takes new or moved entries.
+pub fn week_is_open(status: Option<&str>) -> bool {
+    matches!(status, None | Some("open") | Some("rejected"))
+}
+
+/// Reviewers never approve their own time; when a manager is set only they can.
+pub fn can_review(owner_id: Uuid, manager_id: Option<Uuid>, reviewer_id: Uuid) -> bool {
+    reviewer_id != owner_id && manager_id.is_none_or(|m| m == reviewer_id)
+}
+
+/// Lays a week of entries out by day, then client, then ticket or project,
+/// in the order the work was started.
+pub fn group_week(week_start: NaiveDate, entries: Vec<TimesheetEntry>, schedule: &UserSchedule) -> Vec<TimesheetDay> {
+    let mut days: Vec<TimesheetDay> = (0..7)
+        .map(|offset| {
+            let date = week_start + Days::new(offset);
+            TimesheetDay {
+                date,
+                expected_minutes: schedule.expected_minutes(date),
+                total_minutes: 0,
+                billable_minutes: 0,
+                clients: Vec::new(),
+            }
+        })
+        .collect();
+
+    let mut entries = entries;
+    entries.sort_by_key(|e| e.start_time);
+
+    for entry in entries {
+        let Some(day) = days.iter_mut().find(|d| d.date == entry.work_date) else {
+            continue;
+        };
+        let (minutes, billable) = (entry.minutes(), entry.billable_minutes());
+        day.total_minutes += minutes;
+        day.billable_minutes += billable;
+
+        let client = match day.clients.iter().position(|c| c.client_id == entry.client_id) {
+            Some(i) => &mut day.clients[i],
+            None => {
+                day.clients.push(ClientGroup {
+                    client_id: entry.client_id,
+                    client_name: entry.client_name.clone(),
+                    total_minutes: 0,
+                    billable_minutes: 0,
+                    tickets: Vec::new(),
+                });
+                day.clients.last_mut().unwrap()
+            }
+        };
+        client.total_minutes += minutes;
+        client.billable_minutes += billable;
+
+        let ticket = match client
+            .tickets
+            .iter()
+            .position(|t| t.ticket_id == entry.ticket_id && t.project_id == entry.project_id)
+        {
+            Some(i) => &mut client.tickets[i],
+            None => {
+                client.tickets.push(TicketGroup {
+                    ticket_id: entry.ticket_id,
+                    ticket_number: entry.ticket_number,
+                    ticket_subject: entry.ticket_subject.clone(),
+                    project_id: entry.project_id,
+                    project_name: entry.project_name.clone(),
+                    total_minutes: 0,
+                    billable_minutes: 0,
+                    entries: Vec::new(),
+                });
+                client.tickets.last_mut().unwrap()
+            }
+        };
+        ticket.total_minutes += minutes;
+        ticket.billable_minutes += billable;
+        ticket.entries.push(entry);
+    }
+
+    days
+}
+
+/// Working days up to and including `through` that fall short of the
+/// schedule by more than the tolerance.
+pub fn missing_days(days: &[TimesheetDay], through: NaiveDate, tolerance_minutes: i32) -> Vec<MissingDay> {
+    days.iter()
+        .filter(|d| d.date <= through && d.expected_minutes > 0)
+        .filter(|d| d.expected_minutes - d.total_minutes > tolerance_minutes)
+        .map(|d| MissingDay {
+            date: d.date,
+            expected_minutes: d.expected_minutes,
+            logged_minutes: d.total_minutes,
+            missing_minutes: d.expected_minutes - d.total_minutes,
+        })
+        .collect()
+}
+
+pub async fn load_schedule(pool: &PgPool, user_id: Uuid) -> TimesheetResult<Option<UserSchedule>> {
+    Ok(sqlx::query_as::<_, UserSchedule>(&format!("SELECT {} FROM users u WHERE u.id = $1", SCHEDULE_COLUMNS))
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?)
+}
+
+pub async fn get_timesheet(pool: &PgPool, id: Uuid) -> TimesheetResult<Option<Timesheet>> {
+    Ok(sqlx::query_as::<_, Timesheet>(&format!(
+        "SELECT {} FROM timesheets ts JOIN users u ON u.id = ts.user_id WHERE ts.id = $1",
+        TIMESHEET_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?)
+}
+
+/// The week containing `date` for one technician, grouped for display, with
+/// missing time counted up to `today`.
+pub async fn load_week(
+    pool: &PgPool,
+    schedule: &UserSchedule,
+    date: NaiveDate,
+    today: NaiveDate,
+    tolerance_minutes: i32,
+) -> TimesheetResult<WeeklyTimesheet> {
+    let start = week_start(date);
+
+    let timesheet = sqlx::query_as::<_, Timesheet>(&format!(
+        "SELECT {} FROM timesheets ts JOIN users u ON u.id = ts.user_id WHERE ts.user_id = $1 AND ts.week_start = $2",
+        TIMESHEET_COLUMNS
+    ))
+    .bind(schedule.user_id)
+    .bind(start)
+    .fetch_optional(pool)
+    .await?;
+    let status = timesheet.as_ref().map(|t| t.status.as_str());
+
+    let mut entries = sqlx::query_as::<_, TimesheetEntry>(&format!(
+        "SELECT {} FROM time_entries te
+         LEFT JOIN tickets t ON te.ticket_id = t.id
+         LEFT JOIN projects p ON te.project_id = p.id
+         LEFT JOIN clients c ON COALESCE(t.client_id, p.client_id) = c.id
+         WHERE te.user_id = $1 AND (te.start_time AT TIME ZONE $3)::date BETWEEN $2 AND $2::date + 6
+         ORDER BY te.start_time",
+        ENTRY_COLUMNS
+    ))
+    .bind(schedule.user_id)
+    .bind(start)
+    .bind(&schedule.timezone)
+    .fetch_all(pool)
+    .await?;
+    for entry in &mut entries {
+        entry.locked = lock_reason(entry.billed, entry.invoice_id, entry.written_off_at.is_some(), status);
+    }
+    let running_timers = entries.iter().filter(|e| e.end_time.is_none()).count() as i32;
+    let unconfirmed_entries = entries.iter().filter(|e| e.pending_confirmation).count() as i32;
+
+    let events = match &timesheet {
+        Some(t) => {
+            sqlx::query_as::<_, TimesheetEvent>(
+                "SELECT e.id, e.action, e.comment, e.actor_id, u.first_name || ' ' || u.last_name as actor_name,
+                        e.created_at
+                 FROM timesheet_events e
+                 LEFT JOIN users u ON u.id = e.actor_id
+                 WHERE e.timesheet_id = $1
+                 ORDER BY e.created_at",
+            )
+            .bind(t.id)
+            .fetch_all(pool)
+            .await?
+        }
+        None => Vec::new(),
+    };
+
+    let days = group_week(start, entries, schedule);
+    let missing = missing_days(&days, today, tolerance_minutes);
+
+    Ok(WeeklyTimesheet {
+        timesheet_id: timesheet.as_ref().map(|t| t.id),
+        user_id: schedule.user_id,
+        user_name: schedule.user_name.clone(),
+        manager_id: schedule.manager_id,
+        week_start: start,
+        week_end: start + Days::new(6),
+        status: status.unwrap_or("open").to_string(),
+        submitted_at: timesheet.as_ref().and_then(|t| t.submitted_at),
+        submission_note: timesheet.as_ref().and_then(|t| t.submission_note.clone()),
+        reviewed_by: timesheet.as_ref().and_then(|t| t.reviewed_by),
+        reviewed_at: timesheet.as_ref().and_then(|t| t.reviewed_at),
+        review_comment: timesheet.as_ref().and_then(|t| t.review_comment.clone()),
+        total_minutes: days.iter().map(|d| d.total_minutes).sum(),
+        billable_minutes: days.iter().map(|d| d.billable_minutes).sum(),
+        expected_minutes: days.iter().map(|d| d.expected_minutes).sum(),
+        running_timers,
+        unconfirmed_entries,
+        days,
+        missing,
+        events,
+    })
+}
+
+/// Active technicians expected to log time, optionally only one manager's team.
+pub async fn active_schedules(pool: &PgPool, manager_id: Option<Uuid>) -> TimesheetResult<Vec<UserSchedule>> {
+    Ok(sqlx::query_as::<_, UserSchedule>(&format!(
+        "SELECT {} FROM users u
+         WHERE u.is_active = true AND COALESCE(u.expected_daily_hours, 8) > 0
+           AND ($1::uuid IS NULL OR u.manager_id = $1)
+         ORDER BY u.first_name, u.last_name",
+        SCHEDULE_COLUMNS
+    ))
+    .bind(manager_id)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Everyone short of their expected hours in the week containing `date`.
+pub async fn team_missing_time(
+    pool: &PgPool,
+    manager_id: Option<Uuid>,
+    date: NaiveDate,
+    today: NaiveDate,
+    tolerance_minutes: i32,
+) -> TimesheetResult<Vec<MissingTime>> {
+    let mut missing = Vec::new();
+    for schedule in active_schedules(pool, manager_id).await? {
+        let week = load_week(pool, &schedule, date, today, tolerance_minutes).await?;
+        if week.missing.is_empty() {
+            continue;
+        }
+        missing.push(MissingTime {
+            user_id: week.user_id,
+            user_name: week.user_name,
+            manager_id: week.manager_id,
+            week_start: week.week_start,
+            status: week.status,
+            logged_minutes: week.total_minutes,
+            missing_minutes: week.missing.iter().map(|m| m.missing_minutes).sum(),
+            days: week.missing,
+        });
+    }
+    Ok(missing)
+}
+
+/// Why an existing entry can't be edited or deleted, if it can't.
+pub async fn entry_lock(pool: &PgPool, entry_id: Uuid) -> TimesheetResult<Option<&'static str>> {
+    let row: Option<(bool, Option<Uuid>, bool, Option<String>)> = sqlx::query_as(
+        "SELECT COALESCE(te.billed, false), te.invoice_id, te.written_off_at IS NOT NULL, ts.status
+         FROM time_entries te
+         JOIN users u ON u.id = te.user_id
+         LEFT JOIN timesheets ts ON ts.user_id = te.user_id
+             AND ts.week_start = date_trunc('week', te.start_time AT TIME ZONE COALESCE(NULLIF(u.timezone, ''), 'UTC'))::date
+         WHERE te.id = $1",
+    )
+    .bind(entry_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.and_then(|(billed, invoice_id, written_off, status)| {
+        lock_reason(billed, invoice_id, written_off, status.as_deref())
+    }))
+}
+
+/// Status of the timesheet covering `at` in the technician's timezone, if
+/// the week has one.
+pub async fn week_status(pool: &PgPool, user_id: Uuid, at: DateTime<Utc>) -> TimesheetResult<Option<String>> {
+    Ok(sqlx::query_scalar(
+        "SELECT ts.status FROM timesheets ts
+         JOIN users u ON u.id = ts.user_id
+         WHERE ts.user_id = $1
+           AND ts.week_start = date_trunc('week', $2 AT TIME ZONE COALESCE(NULLIF(u.timezone, ''), 'UTC'))::date",
+    )
+    .bind(user_id)
+    .bind(at)
+    .fetch_optional(pool)
+    .await?)
+}
+
+/// Submits an open or rejected week for approval, recording its totals as
+/// submitted. Returns `None` if the week is already submitted or approved.
+pub async fn submit_week(
+    pool: &PgPool,
+    week: &WeeklyTimesheet,
+    submitted_by: Uuid,
+    note: Option<&str>,
+) -> TimesheetResult<Option<Timesheet>> {
+    let mut tx = pool.begin().await?;
+
+    let id: Option<Uuid> = sqlx::query_scalar(
+        r#"
+        INSERT INTO timesheets (user_id, week_start, status, total_minutes, billable_minutes, expected_minutes,
+                                submitted_at, submission_note)
+        VALUES ($1, $2, 'submitted', $3, $4, $5, NOW(), $6)
+        ON CONFLICT (user_id, week_start) DO UPDATE SET
+            status = 'submitted', total_minutes = EXCLUDED.total_minutes,
+            billable_minutes = EXCLUDED.billable_minutes, expected_minutes = EXCLUDED.expected_minutes,
+            submitted_at = NOW(), submission_note = EXCLUDED.submission_note,
+            reviewed_by = NULL, reviewed_at = NULL, review_comment = NULL, updated_at = NOW()
+        WHERE timesheets.status IN ('open', 'rejected')
+        RETURNING id
+        "#,
+    )
+    .bind(week.user_id)
+    .bind(week.week_start)
+    .bind(week.total_minutes)
+    .bind(week.billable_minutes)
+    .bind(week.expected_minutes)
+    .bind(note)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(id) = id else {
+        return Ok(None);
+    };
+
+    sqlx::query("INSERT INTO timesheet_events (timesheet_id, action, comment, actor_id) VALUES ($1, 'submitted', $2, $3)")
+        .bind(id)
+        .bind(note)
+        .bind(submitted_by)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    let timesheet = get_timesheet(pool, id).await?;
+    if let (Some(timesheet), Some(manager_id)) = (&timesheet, week.manager_id) {
+        let missing: i32 = week.missing.iter().map(|m| m.missing_minutes).sum();
+        let mut message = format!(
+            "{} submitted their timesheet for the week of {}: {} logged, {} billable.",
+            timesheet.user_name,
+            timesheet.week_start.format("%b %d"),
+            format_minutes(timesheet.total_minutes),
+            format_minutes(timesheet.billable_minutes)
+        );
+        if missing > 0 {
+            message.push_str(&format!(" {} short of expected hours.", format_minutes(missing)));
+        }
+        let notification = QueuedNotification::for_user(
+            manager_id,
+            "timesheet_submitted",
+            format!("Timesheet to approve: {}", timesheet.user_name),
+            message,
+        )
+        .with_entity("timesheet", id);
+        if let Err(e) = enqueue_notification(pool, notification).await {
+            warn!("Failed to queue timesheet submission notice {}: {}", id, e);
+        }
+    }
+
+    Ok(timesheet)
+}
+
+/// Approves or rejects a submitted week and tells the technician. Returns
+/// `None` if the week isn't awaiting review.
+pub async fn review_timesheet(
+    pool: &PgPool,
+    timesheet_id: Uuid,
+    reviewer_id: Uuid,
+    approve: bool,
+    comment: Option<&str>,
+) -> TimesheetResult<Option<Timesheet>> {
+    let status = if approve { "approved" } else { "rejected" };
+    let mut tx = pool.begin().await?;
+
+    let updated = sqlx::query(
+        "UPDATE timesheets SET status = $2, reviewed_by = $3, reviewed_at = NOW(), review_comment = $4, updated_at = NOW()
+         WHERE id = $1 AND status = 'submitted'",
+    )
+    .bind(timesheet_id)
+    .bind(status)
+    .bind(reviewer_id)
+    .bind(comment)
+    .execute(&mut *tx)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    sqlx::query("INSERT INTO timesheet_events (timesheet_id, action, comment, actor_id) VALUES ($1, $2, $3, $4)")
+        .bind(timesheet_id)
+        .bind(status)
+        .bind(comment)
+        .bind(reviewer_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    let timesheet = get_timesheet(pool, timesheet_id).await?;
+    if let Some(timesheet) = &timesheet {
+        let week = timesheet.week_start.format("%b %d");
+        let (title, mut message, priority) = if approve {
+            (
+                "Timesheet approved".to_string(),
+                format!("Your timesheet for the week of {} was approved.", week),
+                "normal",
+            )
+        } else {
+            (
+                "Timesheet returned".to_string(),
+                format!("Your timesheet for the week of {} was returned for changes.", week),
+                "high",
+            )
+        };
+        if let Some(comment) = comment {
+            message.push_str(&format!(" Comment: {}", comment));
+        }
+        let notification = QueuedNotification::for_user(timesheet.user_id, &format!("timesheet_{}", status), title, message)
+            .with_priority(priority)
+            .with_entity("timesheet", timesheet_id);
+        if let Err(e) = enqueue_notification(pool, notification).await {
+            warn!("Failed to queue timesheet review notice {}: {}", timesheet_id, e);
+        }
+    }
+
+    Ok(timesheet)
+}
+
+pub fn format_minutes(minutes: i32) -> String {
+    let sign = if minutes < 0 { "-" } else { "" };
+    let minutes = minutes.abs();
+    format!("{}{}h {:02}m", sign, minutes / 60, minutes % 60)
+}
+
+#[derive(Debug, Clone)]
+pub struct TimesheetConfig {
+    pub check_interval_seconds: u64, // How often last week is checked for missing time
+    pub tolerance_minutes: i32,
+}
+
+impl Default for TimesheetConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_seconds: 24 * 60 * 60,
+            tolerance_minutes: MISSING_TIME_TOLERANCE_MINUTES,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TimesheetService {
+    config: TimesheetConfig,
+    db_pool: PgPool,
+}
+
+impl TimesheetService {
+    pub fn new(config: TimesheetConfig, db_pool: PgPool) -> Self {
+        Self { config, db_pool }
+    }
+
+    pub async fn start(&self) -> TimesheetResult<()> {
+        info!("Starting timesheet worker");
+
+        let mut check_interval = interval(Duration::from_secs(self.config.check_interval_seconds));
+
+        tokio::spawn({
+            let service = self.clone();
+            async move {
+                loop {
+                    check_interval.tick().await;
+
+                    if let Err(e) = service.notify_missing_time(Utc::now().date_naive()).await {
+                        error!("Error checking timesheets for missing time: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Tells technicians, and their managers, about working days last week
+    /// with too little time logged. Each week is flagged once.
+    pub async fn notify_missing_time(&self, today: NaiveDate) -> TimesheetResult<u32> {
+        let last_week = week_start(today) - Days::new(7);
+        let mut notified = 0;
+        for schedule in active_schedules(&self.db_pool, None).await? {
+            let week = load_week(&self.db_pool, &schedule, last_week, today, self.config.tolerance_minutes).await?;
+            if week.missing.is_empty() || !week_is_open(Some(week.status.as_str())) {
+                continue;
+            }
+
+            let flagged: Option<Uuid> = sqlx::query_scalar(
+                r#"
+                INSERT INTO timesheets (user_id, week_start, total_minutes, billable_minutes, expected_minutes,
+                                        missing_time_notified_at)
+                VALUES ($1, $2, $3, $4, $5, NOW())
+                ON CONFLICT (user_id, week_start) DO UPDATE SET missing_time_notified_at = NOW(), updated_at = NOW()
+                WHERE timesheets.missing_time_notified_at IS NULL AND timesheets.status IN ('open', 'rejected')
+                RETURNING id
+                "#,
+            )
+            .bind(week.user_id)
+            .bind(week.week_start)
+            .bind(week.total_minutes)
+            .bind(week.billable_minutes)
+            .bind(week.expected_minutes)
+            .fetch_optional(&self.db_pool)
+            .await?;
+            let Some(timesheet_id) = flagged else {
+                continue;
+            };
+
+            let missing: i32 = week.missing.iter().map(|m| m.missing_minutes).sum();
+            let days = week.missing.iter().map(|m| m.date.format("%a %b %d").to_string()).collect::<Vec<_>>().join(", ");
+            let message = format!(
+                "{} of expected time is missing from the week of {} ({}).",
+                format_minutes(missing),
+                week.week_start.format("%b %d"),
+                days
+            );
+
+            let notification = QueuedNotification::for_user(
+                week.user_id,
+                "timesheet_missing_time",
+                "Missing time on your timesheet".to_string(),
+                format!("{} Please log it and submit your timesheet.", message),
+            )
+            .with_priority("high")
+            .with_entity("timesheet", timesheet_id);
+            match enqueue_notification(&self.db_pool, notification).await {
+                Ok(_) => notified += 1,
+                Err(e) => warn!("Failed to queue missing time notice for {}: {}", week.user_id, e),
+            }
+
+            if let Some(manager_id) = week.manager_id {
+                let notification = QueuedNotification::for_user(
+                    manager_id,
+                    "timesheet_missing_time",
+                    format!("Missing time: {}", week.user_name),
+                    format!("{}: {}", week.user_name, message),
+                )
+                .with_entity("timesheet", timesheet_id);
+                if let Err(e) = enqueue_notification(&self.db_pool, notification).await {
+                    warn!("Failed to queue missing time notice for manager {}: {}", manager_id, e);
+                }
+            }
+        }
+
+        if notified > 0 {
+            info!("Flagged missing time for {} technicians for the week of {}", notified, last_week);
+        }
+
+        Ok(notified)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    fn schedule() -> UserSchedule {
+        UserSchedule {
+            user_id: Uuid::new_v4(),
+            user_name: "Sam Tech".to_string(),
+            manager_id: None,
+            timezone: "UTC".to_string(),
+            hours_per_day: Decimal::new(75, 1),
+            working_days: vec![1, 2, 3, 4, 5],
+        }
+    }
+
+    fn entry(day: u32, hour: u32, minutes: i32, client_id: Option<Uuid>, ticket_id: Option<Uuid>) -> TimesheetEntry {
+        TimesheetEntry {
+            id: Uuid::new_v4(),
+            work_date: date(day),
+            start_time: Utc.with_ymd_and_hms(2024, 3, day, hour, 0, 0).unwrap(),
+            end_time: None,
+            duration_minutes: Some(minutes),
+            description: None,
+            billable: client_id.is_some(),
+            billed: false,
+            invoice_id: None,
+            written_off_at: None,
+            client_id,
+            client_name: client_id.map(|_| "Acme".to_string()),
+            ticket_id,
+            ticket_number: None,
+            ticket_subject: None,
+            project_id: None,
+            project_name: None,
+            work_type: None,
+            adjusts_entry_id: None,
+            adjustment_reason: None,
+            pending_confirmation: false,
+            locked: None,
+        }
+    }
+
+    #[test]
+    fn test_week_start_and_expected_minutes() {
+        // 2024-03-04 is a Monday
+        assert_eq!(week_start(date(4)), date(4));
+        assert_eq!(week_start(date(7)), date(4));
+        assert_eq!(week_start(date(10)), date(4));
+
+        let schedule = schedule();
+        assert_eq!(schedule.expected_minutes(date(4)), 450);
+        assert_eq!(schedule.expected_minutes(date(9)), 0);
+    }
+
+    #[test]
+    fn test_group_week_by_day_client_and_ticket() {
+        let client = Some(Uuid::new_v4());
+        let ticket = Some(Uuid::new_v4());
+        let entries = vec![
+            entry(5, 14, 30, client, ticket),
+            entry(5, 9, 90, client, ticket),
+            entry(5, 11, 60, client, None),
+            entry(5, 16, 45, None, None),
+            entry(6, 9, 120, client, ticket),
+        ];
+
+        let days = group_week(date(4), entries, &schedule());
+        assert_eq!(days.len(), 7);
+        assert_eq!(days[0].total_minutes, 0);
+
+        let tuesday = &days[1];
+        assert_eq!(tuesday.total_minutes, 225);
+        assert_eq!(tuesday.billable_minutes, 180);
+        assert_eq!(tuesday.clients.len(), 2);
+        assert_eq!(tuesday.clients[0].client_id, client);
+        assert_eq!(tuesday.clients[0].tickets.len(), 2);
+        assert_eq!(tuesday.clients[0].tickets[0].ticket_id, ticket);
+        assert_eq!(tuesday.clients[0].tickets[0].total_minutes, 120);
+        // Earliest work first
+        assert_eq!(tuesday.clients[0].tickets[0].entries[0].start_time.format("%H").to_string(), "09");
+        assert_eq!(tuesday.clients[1].client_id, None);
+        assert_eq!(days[2].total_minutes, 120);
+    }
+
+    #[test]
+    fn test_missing_days() {
+        let client = Some(Uuid::new_v4());
+        let entries = vec![
+            entry(4, 9, 440, client, None), // within tolerance
+            entry(5, 9, 300, client, None),
+            entry(9, 9, 60, client, None), // weekend work isn't expected
+        ];
+        let days = group_week(date(4), entries, &schedule());
+
+        let missing = missing_days(&days, date(6), MISSING_TIME_TOLERANCE_MINUTES);
+        assert_eq!(
+            missing,
+            vec![
+                MissingDay { date: date(5), expected_minutes: 450, logged_minutes: 300, missing_minutes: 150 },
+                MissingDay { date: date(6), expected_minutes: 450, logged_minutes: 0, missing_minutes: 450 },
+            ]
+        );
+        assert_eq!(missing_days(&days, date(10), MISSING_TIME_TOLERANCE_MINUTES).len(), 4);
+    }
+
+    #[test]
+    fn test_locks_and_reviewers() {
+        assert_eq!(lock_reason(true, None, false, None), Some("billed"));
+        assert_eq!(lock_reason(false, Some(Uuid::new_v4()), false, Some("open")), Some("billed"));
+        assert_eq!(lock_reason(false, None, true, None), Some("written_off"));
+        assert_eq!(lock_reason(false, None, false, Some("approved")), Some("approved"));
+        assert_eq!(lock_reason(false, None, false, Some("submitted")), Some("submitted"));
+        assert_eq!(lock_reason(false, None, false, Some("rejected")), None);
+        assert!(week_is_open(None));
+        assert!(!week_is_open(Some("approved")));
+
+        let (tech, manager, other) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        assert!(!can_review(tech, None, tech));
+        assert!(can_review(tech, None, other));
+        assert!(can_review(tech, Some(manager), manager));
+        assert!(!can_review(tech, Some(manager), other));
+        assert_eq!(format_minutes(150), "2h 30m");
+    }
+}
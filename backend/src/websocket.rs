@@ -6,23 +6,58 @@ use axum::{
     response::Response,
 };
 use futures::{sink::SinkExt, stream::StreamExt};
+use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    sync::Arc,
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 use tokio::sync::{RwLock, broadcast};
 use uuid::Uuid;
 use crate::{AppState, auth::{verify_token}};
 
+// Redis keys shared by every API replica
+const REDIS_CHANNEL: &str = "ghosthub:ws:events";
+const REDIS_SEQUENCE_KEY: &str = "ghosthub:ws:sequence";
+const REDIS_REPLAY_KEY: &str = "ghosthub:ws:replay";
+// Each replica keeps its own presence hash, rebuilt from its connections on every
+// heartbeat and expiring with it, so a crashed replica's users drop off on their own
+const REDIS_PRESENCE_PREFIX: &str = "ghosthub:ws:presence";
+const REDIS_REPLICAS_KEY: &str = "ghosthub:ws:replicas";
+const PRESENCE_HEARTBEAT_SECONDS: u64 = 30;
+const PRESENCE_TTL_SECONDS: i64 = 90;
+
+// Number of recent events kept for clients resuming after a reconnect
+const REPLAY_CAPACITY: usize = 1000;
+
+// Per-connection queue; large enough to hold a full replay plus live traffic
+const CONNECTION_BUFFER: usize = REPLAY_CAPACITY + 100;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WsMessage {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_id: Option<u64>,
     pub event_type: String,
     pub payload: serde_json::Value,
+    #[serde(default = "chrono::Utc::now")]
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
+impl WsMessage {
+    pub fn new(event_type: &str, payload: serde_json::Value) -> Self {
+        Self {
+            event_id: None,
+            event_type: event_type.to_string(),
+            payload,
+            timestamp: chrono::Utc::now(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WsAuth {
     pub token: String,
@@ -33,23 +68,88 @@ pub struct WsConnection {
     pub id: Uuid,
     pub user_id: Option<Uuid>,
     pub contact_id: Option<Uuid>,
+    pub topics: HashSet<String>,
     pub sender: broadcast::Sender<WsMessage>,
 }
 
+// Who an event is addressed to. Targets are resolved on each replica against
+// its own connections, so the same envelope can be fanned out everywhere.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum WsTarget {
+    User(Uuid),
+    Users(Vec<Uuid>),
+    Contact(Uuid),
+    Staff(Option<Uuid>), // optional user to exclude
+    Topic(String),
+    All,
+}
+
+impl WsTarget {
+    pub fn matches(&self, conn: &WsConnection) -> bool {
+        match self {
+            WsTarget::User(id) => conn.user_id == Some(*id),
+            WsTarget::Users(ids) => conn.user_id.map(|id| ids.contains(&id)).unwrap_or(false),
+            WsTarget::Contact(id) => conn.contact_id == Some(*id),
+            WsTarget::Staff(exclude) => conn.user_id.is_some() && conn.user_id != *exclude,
+            WsTarget::Topic(topic) => conn.topics.contains(topic),
+            WsTarget::All => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WsEnvelope {
+    target: WsTarget,
+    message: WsMessage,
+}
+
+#[derive(Clone)]
+struct RedisBackplane {
+    connection: redis::aio::ConnectionManager,
+    replica_id: Uuid,
+}
+
+impl RedisBackplane {
+    fn presence_key(&self) -> String {
+        presence_key(self.replica_id)
+    }
+}
+
 pub struct WsManager {
     connections: Arc<RwLock<HashMap<Uuid, WsConnection>>>,
-    broadcast: broadcast::Sender<WsMessage>,
+    replay: Arc<RwLock<VecDeque<WsEnvelope>>>,
+    sequence: Arc<AtomicU64>,
+    backplane: Option<RedisBackplane>,
 }
 
 impl WsManager {
     pub fn new() -> Self {
-        let (broadcast, _) = broadcast::channel(1000);
         Self {
             connections: Arc::new(RwLock::new(HashMap::new())),
-            broadcast,
+            replay: Arc::new(RwLock::new(VecDeque::with_capacity(REPLAY_CAPACITY))),
+            sequence: Arc::new(AtomicU64::new(0)),
+            backplane: None,
         }
     }
 
+    // Fans events out through Redis pub/sub so every replica delivers to its own
+    // connections. Event ids and the replay buffer live in Redis as well.
+    pub async fn with_redis(redis_url: &str) -> Result<Self, redis::RedisError> {
+        let client = redis::Client::open(redis_url)?;
+        let connection = client.get_connection_manager().await?;
+
+        let mut manager = Self::new();
+        let backplane = RedisBackplane { connection, replica_id: Uuid::new_v4() };
+        manager.backplane = Some(backplane.clone());
+
+        tokio::spawn(run_redis_subscriber(client, manager.connections.clone()));
+        tokio::spawn(run_presence_heartbeat(backplane, manager.connections.clone()));
+        tracing::info!("WebSocket fan-out using Redis backplane");
+
+        Ok(manager)
+    }
+
     pub async fn add_connection(&self, conn: WsConnection) {
         let mut connections = self.connections.write().await;
         connections.insert(conn.id, conn);
@@ -60,32 +160,343 @@ impl WsManager {
         connections.remove(id);
     }
 
+    pub async fn subscribe(&self, connection_id: Uuid, topic: &str) {
+        let mut connections = self.connections.write().await;
+        if let Some(conn) = connections.get_mut(&connection_id) {
+            conn.topics.insert(topic.to_string());
+        }
+    }
+
+    pub async fn unsubscribe(&self, connection_id: Uuid, topic: &str) {
+        let mut connections = self.connections.write().await;
+        if let Some(conn) = connections.get_mut(&connection_id) {
+            conn.topics.remove(topic);
+        }
+    }
+
+    pub async fn publish(&self, target: WsTarget, mut message: WsMessage) {
+        if let Some(backplane) = &self.backplane {
+            match publish_to_redis(backplane.connection.clone(), &target, &mut message).await {
+                Ok(()) => return,
+                Err(e) => {
+                    // Still reach clients on this replica if Redis is unavailable. The event
+                    // has no place in the shared sequence, so it goes out without an id and
+                    // isn't kept for replay.
+                    tracing::error!("Redis publish failed, delivering locally: {}", e);
+                    message.event_id = None;
+                    deliver_local(&self.connections, &WsEnvelope { target, message }).await;
+                    return;
+                }
+            }
+        }
+
+        message.event_id = Some(self.sequence.fetch_add(1, Ordering::SeqCst) + 1);
+        let envelope = WsEnvelope { target, message };
+
+        {
+            let mut replay = self.replay.write().await;
+            if replay.len() >= REPLAY_CAPACITY {
+                replay.pop_front();
+            }
+            replay.push_back(envelope.clone());
+        }
+
+        deliver_local(&self.connections, &envelope).await;
+    }
+
     pub async fn broadcast_to_user(&self, user_id: Uuid, message: WsMessage) {
+        self.publish(WsTarget::User(user_id), message).await;
+    }
+
+    pub async fn broadcast_to_contact(&self, contact_id: Uuid, message: WsMessage) {
+        self.publish(WsTarget::Contact(contact_id), message).await;
+    }
+
+    pub async fn broadcast_to_users(&self, user_ids: &[Uuid], message: WsMessage) {
+        self.publish(WsTarget::Users(user_ids.to_vec()), message).await;
+    }
+
+    // Staff connections only; portal contacts never see internal traffic
+    pub async fn broadcast_to_staff(&self, message: WsMessage, exclude_user: Option<Uuid>) {
+        self.publish(WsTarget::Staff(exclude_user), message).await;
+    }
+
+    pub async fn broadcast_to_topic(&self, topic: &str, message: WsMessage) {
+        self.publish(WsTarget::Topic(topic.to_string()), message).await;
+    }
+
+    pub async fn broadcast_all(&self, message: WsMessage) {
+        self.publish(WsTarget::All, message).await;
+    }
+
+    // Re-sends buffered events newer than `last_event_id` that this connection
+    // would have received. A "replay_gap" event tells the client the buffer no
+    // longer reaches back far enough and it should refetch state instead.
+    pub async fn replay_since(&self, connection_id: Uuid, last_event_id: u64, topic: Option<&str>) {
+        let envelopes = match &self.backplane {
+            Some(backplane) => match load_redis_replay(backplane.connection.clone()).await {
+                Ok(envelopes) => envelopes,
+                Err(e) => {
+                    tracing::error!("Failed to load WebSocket replay buffer: {}", e);
+                    return;
+                }
+            },
+            None => self.replay.read().await.iter().cloned().collect(),
+        };
+
         let connections = self.connections.read().await;
-        for conn in connections.values() {
-            if conn.user_id == Some(user_id) {
-                let _ = conn.sender.send(message.clone());
+        let Some(conn) = connections.get(&connection_id) else {
+            return;
+        };
+
+        let oldest = envelopes.first().and_then(|e| e.message.event_id).unwrap_or(0);
+        if oldest > last_event_id + 1 {
+            let _ = conn.sender.send(WsMessage::new(
+                "replay_gap",
+                serde_json::json!({ "last_event_id": last_event_id, "oldest_available": oldest }),
+            ));
+        }
+
+        for envelope in replayable(&envelopes, last_event_id, topic) {
+            if envelope.target.matches(conn) {
+                let _ = conn.sender.send(envelope.message.clone());
             }
         }
     }
 
-    pub async fn broadcast_to_contact(&self, contact_id: Uuid, message: WsMessage) {
+    // Returns how many connections the user has across all replicas after the change.
+    pub async fn track_presence(&self, user_id: Uuid, delta: i64) -> i64 {
+        if let Some(backplane) = &self.backplane {
+            match update_redis_presence(backplane, user_id, delta).await {
+                Ok(count) => return count,
+                Err(e) => tracing::error!("Failed to update presence in Redis: {}", e),
+            }
+        }
+
         let connections = self.connections.read().await;
-        for conn in connections.values() {
-            if conn.contact_id == Some(contact_id) {
-                let _ = conn.sender.send(message.clone());
+        connections.values().filter(|c| c.user_id == Some(user_id)).count() as i64
+    }
+
+    pub async fn online_user_ids(&self) -> Vec<Uuid> {
+        if let Some(backplane) = &self.backplane {
+            match load_redis_presence(backplane.connection.clone()).await {
+                Ok(presence) => {
+                    let mut user_ids: Vec<Uuid> = presence
+                        .into_iter()
+                        .filter(|(_, count)| *count > 0)
+                        .filter_map(|(id, _)| Uuid::parse_str(&id).ok())
+                        .collect();
+                    user_ids.sort();
+                    return user_ids;
+                }
+                Err(e) => tracing::error!("Failed to read presence from Redis: {}", e),
             }
         }
+
+        let connections = self.connections.read().await;
+        let mut user_ids: Vec<Uuid> = connections.values().filter_map(|c| c.user_id).collect();
+        user_ids.sort();
+        user_ids.dedup();
+        user_ids
     }
+}
 
-    pub async fn broadcast_all(&self, message: WsMessage) {
-        let _ = self.broadcast.send(message);
+async fn deliver_local(connections: &Arc<RwLock<HashMap<Uuid, WsConnection>>>, envelope: &WsEnvelope) {
+    let connections = connections.read().await;
+    for conn in connections.values() {
+        if envelope.target.matches(conn) {
+            let _ = conn.sender.send(envelope.message.clone());
+        }
+    }
+}
+
+fn replayable<'a>(
+    envelopes: &'a [WsEnvelope],
+    last_event_id: u64,
+    topic: Option<&'a str>,
+) -> impl Iterator<Item = &'a WsEnvelope> {
+    envelopes.iter().filter(move |e| {
+        e.message.event_id.map(|id| id > last_event_id).unwrap_or(false)
+            && topic
+                .map(|t| e.target == WsTarget::Topic(t.to_string()))
+                .unwrap_or(true)
+    })
+}
+
+async fn publish_to_redis(
+    mut connection: redis::aio::ConnectionManager,
+    target: &WsTarget,
+    message: &mut WsMessage,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let event_id: u64 = connection.incr(REDIS_SEQUENCE_KEY, 1).await?;
+    message.event_id = Some(event_id);
+
+    let envelope = serde_json::to_string(&WsEnvelope {
+        target: target.clone(),
+        message: message.clone(),
+    })?;
+
+    redis::pipe()
+        .atomic()
+        .lpush(REDIS_REPLAY_KEY, &envelope)
+        .ltrim(REDIS_REPLAY_KEY, 0, REPLAY_CAPACITY as isize - 1)
+        .publish(REDIS_CHANNEL, &envelope)
+        .query_async::<_, ()>(&mut connection)
+        .await?;
+
+    Ok(())
+}
+
+async fn load_redis_replay(
+    mut connection: redis::aio::ConnectionManager,
+) -> Result<Vec<WsEnvelope>, Box<dyn std::error::Error + Send + Sync>> {
+    let raw: Vec<String> = connection.lrange(REDIS_REPLAY_KEY, 0, -1).await?;
+
+    // The list is newest-first; replay in event order
+    let mut envelopes: Vec<WsEnvelope> = raw
+        .iter()
+        .filter_map(|item| serde_json::from_str(item).ok())
+        .collect();
+    envelopes.sort_by_key(|e| e.message.event_id);
+
+    Ok(envelopes)
+}
+
+fn presence_key(replica_id: Uuid) -> String {
+    format!("{}:{}", REDIS_PRESENCE_PREFIX, replica_id)
+}
+
+fn unix_now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+// Replicas that have sent a heartbeat within the presence TTL
+async fn live_replicas(
+    connection: &mut redis::aio::ConnectionManager,
+) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let replicas: Vec<String> = connection
+        .zrangebyscore(REDIS_REPLICAS_KEY, unix_now() - PRESENCE_TTL_SECONDS, "+inf")
+        .await?;
+    Ok(replicas)
+}
+
+// Connection counts per user, summed over live replicas
+async fn load_redis_presence(
+    mut connection: redis::aio::ConnectionManager,
+) -> Result<HashMap<String, i64>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut totals: HashMap<String, i64> = HashMap::new();
+    for replica in live_replicas(&mut connection).await? {
+        let Ok(replica_id) = Uuid::parse_str(&replica) else { continue };
+        let counts: HashMap<String, i64> = connection.hgetall(presence_key(replica_id)).await?;
+        for (user_id, count) in counts {
+            *totals.entry(user_id).or_default() += count;
+        }
+    }
+    Ok(totals)
+}
+
+async fn update_redis_presence(
+    backplane: &RedisBackplane,
+    user_id: Uuid,
+    delta: i64,
+) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+    let mut connection = backplane.connection.clone();
+    let key = backplane.presence_key();
+
+    let (count,): (i64,) = redis::pipe()
+        .atomic()
+        .hincr(&key, user_id.to_string(), delta)
+        .expire(&key, PRESENCE_TTL_SECONDS)
+        .ignore()
+        .zadd(REDIS_REPLICAS_KEY, backplane.replica_id.to_string(), unix_now())
+        .ignore()
+        .query_async(&mut connection)
+        .await?;
+    if count <= 0 {
+        let _: () = connection.hdel(&key, user_id.to_string()).await?;
+    }
+
+    let presence = load_redis_presence(connection).await?;
+    Ok(presence.get(&user_id.to_string()).copied().unwrap_or(0).max(0))
+}
+
+// Rewrites this replica's presence hash from its live connections, keeps it and the
+// replica's heartbeat fresh, and prunes replicas that stopped reporting.
+async fn run_presence_heartbeat(
+    backplane: RedisBackplane,
+    connections: Arc<RwLock<HashMap<Uuid, WsConnection>>>,
+) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(PRESENCE_HEARTBEAT_SECONDS));
+    loop {
+        ticker.tick().await;
+
+        let counts: Vec<(String, i64)> = {
+            let connections = connections.read().await;
+            let mut counts: HashMap<Uuid, i64> = HashMap::new();
+            for user_id in connections.values().filter_map(|c| c.user_id) {
+                *counts.entry(user_id).or_default() += 1;
+            }
+            counts.into_iter().map(|(id, count)| (id.to_string(), count)).collect()
+        };
+
+        let key = backplane.presence_key();
+        let now = unix_now();
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(&key).ignore();
+        if !counts.is_empty() {
+            pipe.hset_multiple(&key, &counts).ignore().expire(&key, PRESENCE_TTL_SECONDS).ignore();
+        }
+        pipe.zadd(REDIS_REPLICAS_KEY, backplane.replica_id.to_string(), now)
+            .ignore()
+            .zrembyscore(REDIS_REPLICAS_KEY, "-inf", now - PRESENCE_TTL_SECONDS)
+            .ignore();
+
+        let mut connection = backplane.connection.clone();
+        if let Err(e) = pipe.query_async::<_, ()>(&mut connection).await {
+            tracing::error!("Failed to refresh WebSocket presence: {}", e);
+        }
+    }
+}
+
+async fn run_redis_subscriber(
+    client: redis::Client,
+    connections: Arc<RwLock<HashMap<Uuid, WsConnection>>>,
+) {
+    loop {
+        match client.get_async_pubsub().await {
+            Ok(mut pubsub) => {
+                if let Err(e) = pubsub.subscribe(REDIS_CHANNEL).await {
+                    tracing::error!("Failed to subscribe to Redis channel: {}", e);
+                } else {
+                    let mut messages = pubsub.on_message();
+                    while let Some(msg) = messages.next().await {
+                        let payload: String = match msg.get_payload() {
+                            Ok(payload) => payload,
+                            Err(e) => {
+                                tracing::warn!("Invalid Redis WebSocket payload: {}", e);
+                                continue;
+                            }
+                        };
+
+                        match serde_json::from_str::<WsEnvelope>(&payload) {
+                            Ok(envelope) => deliver_local(&connections, &envelope).await,
+                            Err(e) => tracing::warn!("Could not decode WebSocket envelope: {}", e),
+                        }
+                    }
+                    tracing::warn!("Redis WebSocket subscription ended, reconnecting");
+                }
+            }
+            Err(e) => tracing::error!("Failed to connect to Redis for WebSocket fan-out: {}", e),
+        }
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
     }
 }
 
 #[derive(Deserialize)]
 pub struct WsQuery {
     pub token: Option<String>,
+    pub last_event_id: Option<u64>,
 }
 
 pub async fn websocket_handler(
@@ -93,10 +504,15 @@ pub async fn websocket_handler(
     State(state): State<Arc<AppState>>,
     ws: WebSocketUpgrade,
 ) -> Response {
-    ws.on_upgrade(move |socket| handle_socket(socket, state, query.token))
+    ws.on_upgrade(move |socket| handle_socket(socket, state, query.token, query.last_event_id))
 }
 
-async fn handle_socket(socket: WebSocket, state: Arc<AppState>, token: Option<String>) {
+async fn handle_socket(
+    socket: WebSocket,
+    state: Arc<AppState>,
+    token: Option<String>,
+    last_event_id: Option<u64>,
+) {
     let (mut sender, mut receiver) = socket.split();
     let connection_id = Uuid::new_v4();
     
@@ -134,17 +550,24 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>, token: Option<St
     };
 
     // Create broadcast channel for this connection
-    let (tx, mut rx) = broadcast::channel(100);
+    let (tx, mut rx) = broadcast::channel(CONNECTION_BUFFER);
     
     // Add connection to manager
     let connection = WsConnection {
         id: connection_id,
         user_id,
         contact_id,
+        topics: HashSet::new(),
         sender: tx.clone(),
     };
     
     state.ws_manager.add_connection(connection.clone()).await;
+
+    if let Some(user_id) = user_id {
+        if state.ws_manager.track_presence(user_id, 1).await == 1 {
+            broadcast_presence(&state, user_id, "online").await;
+        }
+    }
     
     // Send connection success message
     let _ = sender.send(Message::Text(
@@ -157,7 +580,7 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>, token: Option<St
             }
         }).to_string()
     )).await;
-    
+
     // Store connection in database
     let _ = sqlx::query!(
         "INSERT INTO websocket_connections (connection_id, user_id, contact_id, connected_at) 
@@ -171,12 +594,27 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>, token: Option<St
     
     // Create tasks for handling messages
     let mut send_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
+        loop {
+            let msg = match rx.recv().await {
+                Ok(msg) => msg,
+                // A slow client misses the oldest queued events but stays connected
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("WebSocket connection {} lagged, skipped {} events", connection_id, skipped);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
             if sender.send(Message::Text(serde_json::to_string(&msg).unwrap())).await.is_err() {
                 break;
             }
         }
     });
+
+    // Catch up on events missed while the client was disconnected, now that the
+    // sender is draining the connection's queue
+    if let Some(last_event_id) = last_event_id {
+        state.ws_manager.replay_since(connection_id, last_event_id, None).await;
+    }
     
     let state_clone = state.clone();
     let mut recv_task = tokio::spawn(async move {
@@ -211,6 +649,12 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>, token: Option<St
     
     // Clean up
     state.ws_manager.remove_connection(&connection_id).await;
+
+    if let Some(user_id) = user_id {
+        if state.ws_manager.track_presence(user_id, -1).await == 0 {
+            broadcast_presence(&state, user_id, "offline").await;
+        }
+    }
     
     // Update disconnection time
     let _ = sqlx::query!(
@@ -227,18 +671,61 @@ async fn handle_client_message(state: &Arc<AppState>, connection_id: Uuid, messa
             // Simple ping/pong
             let connections = state.ws_manager.connections.read().await;
             if let Some(conn) = connections.get(&connection_id) {
-                let _ = conn.sender.send(WsMessage {
-                    event_type: "pong".to_string(),
-                    payload: serde_json::json!({}),
-                    timestamp: chrono::Utc::now(),
-                });
+                let _ = conn.sender.send(WsMessage::new("pong", serde_json::json!({})));
             }
         }
         "subscribe" => {
-            // Handle subscription to specific events
-            if let Some(channel) = message.payload.get("channel").and_then(|v| v.as_str()) {
-                // Store subscription preference
-                tracing::info!("Connection {} subscribed to channel: {}", connection_id, channel);
+            // Subscribe to a topic such as ticket:{id} or client:{id}
+            let Some(topic) = message.payload.get("topic")
+                .or_else(|| message.payload.get("channel"))
+                .and_then(|v| v.as_str())
+            else {
+                return;
+            };
+
+            let connection = {
+                let connections = state.ws_manager.connections.read().await;
+                connections.get(&connection_id).cloned()
+            };
+            let Some(connection) = connection else {
+                return;
+            };
+
+            if !authorize_topic(state, &connection, topic).await {
+                let _ = connection.sender.send(WsMessage::new(
+                    "error",
+                    serde_json::json!({ "message": "Not allowed to subscribe to topic", "topic": topic }),
+                ));
+                return;
+            }
+
+            state.ws_manager.subscribe(connection_id, topic).await;
+            let _ = connection.sender.send(WsMessage::new("subscribed", serde_json::json!({ "topic": topic })));
+
+            if let Some(last_event_id) = message.payload.get("last_event_id").and_then(|v| v.as_u64()) {
+                state.ws_manager.replay_since(connection_id, last_event_id, Some(topic)).await;
+            }
+        }
+        "unsubscribe" => {
+            if let Some(topic) = message.payload.get("topic")
+                .or_else(|| message.payload.get("channel"))
+                .and_then(|v| v.as_str())
+            {
+                state.ws_manager.unsubscribe(connection_id, topic).await;
+            }
+        }
+        "resume" => {
+            if let Some(last_event_id) = message.payload.get("last_event_id").and_then(|v| v.as_u64()) {
+                state.ws_manager.replay_since(connection_id, last_event_id, None).await;
+            }
+        }
+        "chat_typing" => {
+            let user_id = {
+                let connections = state.ws_manager.connections.read().await;
+                connections.get(&connection_id).and_then(|c| c.user_id)
+            };
+            if let Some(user_id) = user_id {
+                crate::handlers::chat::relay_typing(state, user_id, &message.payload).await;
             }
         }
         _ => {
@@ -247,6 +734,44 @@ async fn handle_client_message(state: &Arc<AppState>, connection_id: Uuid, messa
     }
 }
 
+async fn broadcast_presence(state: &Arc<AppState>, user_id: Uuid, status: &str) {
+    let message = WsMessage::new("presence", serde_json::json!({ "user_id": user_id, "status": status }));
+    state.ws_manager.broadcast_to_staff(message, Some(user_id)).await;
+}
+
+// Staff may follow any topic; portal contacts only their own client and its tickets
+async fn authorize_topic(state: &Arc<AppState>, connection: &WsConnection, topic: &str) -> bool {
+    if connection.user_id.is_some() {
+        return true;
+    }
+
+    let Some(contact_id) = connection.contact_id else {
+        return false;
+    };
+    let Some((kind, id)) = topic.split_once(':') else {
+        return false;
+    };
+    let Ok(id) = Uuid::parse_str(id) else {
+        return false;
+    };
+
+    let query = match kind {
+        "client" => "SELECT EXISTS(SELECT 1 FROM contacts WHERE id = $1 AND client_id = $2)",
+        "ticket" => "SELECT EXISTS(
+                SELECT 1 FROM tickets t JOIN contacts c ON c.client_id = t.client_id
+                WHERE c.id = $1 AND t.id = $2
+            )",
+        _ => return false,
+    };
+
+    sqlx::query_scalar::<_, bool>(query)
+        .bind(contact_id)
+        .bind(id)
+        .fetch_one(&state.db_pool)
+        .await
+        .unwrap_or(false)
+}
+
 async fn verify_portal_token(state: &Arc<AppState>, token: &str) -> Result<Uuid, String> {
     // Verify portal access token
     let result = sqlx::query!(
@@ -267,29 +792,86 @@ async fn verify_portal_token(state: &Arc<AppState>, token: &str) -> Result<Uuid,
 // Helper functions to send notifications through WebSocket
 impl AppState {
     pub async fn notify_user(&self, user_id: Uuid, event_type: &str, payload: serde_json::Value) {
-        let message = WsMessage {
-            event_type: event_type.to_string(),
-            payload,
-            timestamp: chrono::Utc::now(),
-        };
+        let message = WsMessage::new(event_type, payload);
         self.ws_manager.broadcast_to_user(user_id, message).await;
     }
 
     pub async fn notify_contact(&self, contact_id: Uuid, event_type: &str, payload: serde_json::Value) {
-        let message = WsMessage {
-            event_type: event_type.to_string(),
-            payload,
-            timestamp: chrono::Utc::now(),
-        };
+        let message = WsMessage::new(event_type, payload);
         self.ws_manager.broadcast_to_contact(contact_id, message).await;
     }
 
+    pub async fn notify_topic(&self, topic: &str, event_type: &str, payload: serde_json::Value) {
+        let message = WsMessage::new(event_type, payload);
+        self.ws_manager.broadcast_to_topic(topic, message).await;
+    }
+
+    // Support team notifications; portal connections are not included
     pub async fn broadcast_notification(&self, event_type: &str, payload: serde_json::Value) {
-        let message = WsMessage {
-            event_type: event_type.to_string(),
-            payload,
-            timestamp: chrono::Utc::now(),
-        };
-        self.ws_manager.broadcast_all(message).await;
+        let message = WsMessage::new(event_type, payload);
+        self.ws_manager.broadcast_to_staff(message, None).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection(user_id: Option<Uuid>, contact_id: Option<Uuid>, topics: &[&str]) -> WsConnection {
+        let (sender, _) = broadcast::channel(1);
+        WsConnection {
+            id: Uuid::new_v4(),
+            user_id,
+            contact_id,
+            topics: topics.iter().map(|t| t.to_string()).collect(),
+            sender,
+        }
+    }
+
+    #[test]
+    fn test_target_matching() {
+        let user = Uuid::new_v4();
+        let contact = Uuid::new_v4();
+        let staff = connection(Some(user), None, &["ticket:1"]);
+        let portal = connection(None, Some(contact), &[]);
+
+        assert!(WsTarget::User(user).matches(&staff));
+        assert!(!WsTarget::User(user).matches(&portal));
+        assert!(WsTarget::Contact(contact).matches(&portal));
+        assert!(WsTarget::Staff(None).matches(&staff));
+        assert!(!WsTarget::Staff(None).matches(&portal));
+        assert!(!WsTarget::Staff(Some(user)).matches(&staff));
+        assert!(WsTarget::Topic("ticket:1".to_string()).matches(&staff));
+        assert!(!WsTarget::Topic("ticket:2".to_string()).matches(&staff));
+        assert!(WsTarget::All.matches(&portal));
+    }
+
+    #[test]
+    fn test_envelope_round_trip() {
+        let mut message = WsMessage::new("ticket_updated", serde_json::json!({ "id": 1 }));
+        message.event_id = Some(42);
+        let envelope = WsEnvelope { target: WsTarget::Topic("ticket:1".to_string()), message };
+
+        let decoded: WsEnvelope = serde_json::from_str(&serde_json::to_string(&envelope).unwrap()).unwrap();
+        assert_eq!(decoded.target, envelope.target);
+        assert_eq!(decoded.message.event_id, Some(42));
+    }
+
+    #[test]
+    fn test_replayable_filters_by_id_and_topic() {
+        let envelopes: Vec<WsEnvelope> = (1..=5)
+            .map(|id| {
+                let mut message = WsMessage::new("event", serde_json::json!({}));
+                message.event_id = Some(id);
+                let target = if id % 2 == 0 { WsTarget::Topic("ticket:1".to_string()) } else { WsTarget::All };
+                WsEnvelope { target, message }
+            })
+            .collect();
+
+        let ids: Vec<u64> = replayable(&envelopes, 2, None).filter_map(|e| e.message.event_id).collect();
+        assert_eq!(ids, vec![3, 4, 5]);
+
+        let ids: Vec<u64> = replayable(&envelopes, 0, Some("ticket:1")).filter_map(|e| e.message.event_id).collect();
+        assert_eq!(ids, vec![2, 4]);
     }
-}
\ No newline at end of file
+}
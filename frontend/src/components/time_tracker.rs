@@ -5,7 +5,12 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use web_sys::window;
+use gloo::events::EventListener;
 use gloo::timers::callback::Interval;
+use gloo_storage::{LocalStorage, Storage};
+
+// Timer events recorded while the API was unreachable, replayed on reconnect
+const OFFLINE_QUEUE_KEY: &str = "time_tracker_offline_events";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ActiveTimer {
@@ -41,6 +46,66 @@ pub struct TimerStartRequest {
     pub billable: Option<bool>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OfflineTimerEvent {
+    pub client_event_id: Uuid,
+    pub event_type: String, // start, stop
+    pub occurred_at: DateTime<Utc>,
+    pub ticket_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub task_id: Option<Uuid>,
+    pub description: Option<String>,
+    pub billable: Option<bool>,
+    pub timer_id: Option<Uuid>,
+    pub start_event_id: Option<Uuid>, // stopping a timer that was started offline
+}
+
+impl OfflineTimerEvent {
+    fn start(request: &TimerStartRequest) -> Self {
+        Self {
+            client_event_id: Uuid::new_v4(),
+            event_type: "start".to_string(),
+            occurred_at: Utc::now(),
+            ticket_id: request.ticket_id,
+            project_id: request.project_id,
+            task_id: request.task_id,
+            description: request.description.clone(),
+            billable: request.billable,
+            timer_id: None,
+            start_event_id: None,
+        }
+    }
+
+    fn stop(timer_id: Option<Uuid>, start_event_id: Option<Uuid>) -> Self {
+        Self {
+            client_event_id: Uuid::new_v4(),
+            event_type: "stop".to_string(),
+            occurred_at: Utc::now(),
+            ticket_id: None,
+            project_id: None,
+            task_id: None,
+            description: None,
+            billable: None,
+            timer_id,
+            start_event_id,
+        }
+    }
+}
+
+/// Client errors other than auth, timeouts and rate limits mean the server
+/// refused the batch as sent, so retrying it unchanged won't help.
+fn is_permanent_rejection(status: u16) -> bool {
+    (400..500).contains(&status) && !matches!(status, 401 | 403 | 408 | 429)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimerSyncResult {
+    pub client_event_id: Uuid,
+    pub status: String, // applied, rejected, duplicate
+    pub time_entry_id: Option<Uuid>,
+    pub message: Option<String>,
+}
+
 #[derive(Properties, PartialEq)]
 pub struct TimeTrackerProps {
     #[prop_or_default]
@@ -58,6 +123,11 @@ pub enum TimeTrackerMsg {
     ActiveTimersLoaded(Vec<ActiveTimer>),
     TimerStarted(ActiveTimer),
     TimerStopped,
+    QueueOfflineEvent(OfflineTimerEvent),
+    SyncOfflineEvents,
+    OfflineEventsSynced(Vec<TimerSyncResult>),
+    OfflineSyncFailed(String),
+    OfflineSyncRejected(u16),
     Error(String),
 }
 
@@ -66,7 +136,11 @@ pub struct TimeTracker {
     active_timers: Vec<ActiveTimer>,
     loading: bool,
     show_start_form: bool,
+    offline_events: Vec<OfflineTimerEvent>,
+    syncing: bool,
+    sync_error: Option<String>, // set when the server refused the batch; automatic retries pause
     _interval: Option<Interval>,
+    _online_listener: Option<EventListener>,
 }
 
 impl Component for TimeTracker {
@@ -80,9 +154,22 @@ impl Component for TimeTracker {
             link.send_message(TimeTrackerMsg::UpdateElapsedTime);
         });
 
+        // Replay queued events as soon as the browser is back online
+        let online_listener = window().map(|w| {
+            let link = ctx.link().clone();
+            EventListener::new(&w, "online", move |_| {
+                link.send_message(TimeTrackerMsg::SyncOfflineEvents);
+            })
+        });
+
+        let offline_events: Vec<OfflineTimerEvent> = LocalStorage::get(OFFLINE_QUEUE_KEY).unwrap_or_default();
+
         // Load initial data
         ctx.link().send_message(TimeTrackerMsg::LoadStats);
         ctx.link().send_message(TimeTrackerMsg::LoadActiveTimers);
+        if !offline_events.is_empty() {
+            ctx.link().send_message(TimeTrackerMsg::SyncOfflineEvents);
+        }
 
         Self {
             stats: TimeStats {
@@ -96,7 +183,11 @@ impl Component for TimeTracker {
             active_timers: Vec::new(),
             loading: true,
             show_start_form: false,
+            offline_events,
+            syncing: false,
+            sync_error: None,
             _interval: Some(interval),
+            _online_listener: online_listener,
         }
     }
 
@@ -140,6 +231,7 @@ impl Component for TimeTracker {
             }
             TimeTrackerMsg::StartTimer(request) => {
                 let link = ctx.link().clone();
+                let offline_event = OfflineTimerEvent::start(&request);
                 spawn_local(async move {
                     match Request::post("/api/v1/time/timer/start")
                         .json(&request)
@@ -154,8 +246,9 @@ impl Component for TimeTracker {
                                 link.send_message(TimeTrackerMsg::Error("Failed to start timer".to_string()));
                             }
                         }
-                        Err(e) => {
-                            link.send_message(TimeTrackerMsg::Error(format!("Failed to start timer: {:?}", e)));
+                        Err(_) => {
+                            // Unreachable: keep timing locally and sync later
+                            link.send_message(TimeTrackerMsg::QueueOfflineEvent(offline_event));
                         }
                     }
                 });
@@ -163,6 +256,19 @@ impl Component for TimeTracker {
             }
             TimeTrackerMsg::StopTimer(timer_id) => {
                 let link = ctx.link().clone();
+                let started_offline = timer_id.filter(|id| {
+                    self.offline_events.iter().any(|e| e.event_type == "start" && e.client_event_id == *id)
+                });
+                let offline_event = match started_offline {
+                    Some(start_event_id) => OfflineTimerEvent::stop(None, Some(start_event_id)),
+                    None => OfflineTimerEvent::stop(timer_id, None),
+                };
+                // The server hasn't heard of a timer started offline, so its stop waits in the queue too
+                if started_offline.is_some() {
+                    ctx.link().send_message(TimeTrackerMsg::QueueOfflineEvent(offline_event));
+                    return false;
+                }
+
                 let request_body = if let Some(id) = timer_id {
                     serde_json::json!({"timer_id": id})
                 } else {
@@ -179,8 +285,8 @@ impl Component for TimeTracker {
                         Ok(_) => {
                             link.send_message(TimeTrackerMsg::TimerStopped);
                         }
-                        Err(e) => {
-                            link.send_message(TimeTrackerMsg::Error(format!("Failed to stop timer: {:?}", e)));
+                        Err(_) => {
+                            link.send_message(TimeTrackerMsg::QueueOfflineEvent(offline_event));
                         }
                     }
                 });
@@ -194,6 +300,9 @@ impl Component for TimeTracker {
                     let elapsed_ms = now - start_time;
                     timer.elapsed_minutes = (elapsed_ms / 60000) as i32;
                 }
+                if !self.offline_events.is_empty() && self.sync_error.is_none() {
+                    ctx.link().send_message(TimeTrackerMsg::SyncOfflineEvents);
+                }
                 true
             }
             TimeTrackerMsg::StatsLoaded(stats) => {
@@ -217,6 +326,97 @@ impl Component for TimeTracker {
                 ctx.link().send_message(TimeTrackerMsg::LoadStats);
                 true
             }
+            TimeTrackerMsg::QueueOfflineEvent(event) => {
+                match event.event_type.as_str() {
+                    "start" => {
+                        self.active_timers.insert(0, ActiveTimer {
+                            id: event.client_event_id,
+                            user_id: Uuid::nil(),
+                            ticket_id: event.ticket_id,
+                            ticket_subject: None,
+                            project_id: event.project_id,
+                            project_name: None,
+                            client_name: None,
+                            description: event.description.clone(),
+                            start_time: event.occurred_at,
+                            elapsed_minutes: 0,
+                            billable: event.billable.unwrap_or(true),
+                        });
+                        self.show_start_form = false;
+                    }
+                    _ => {
+                        let stopped = event.start_event_id.or(event.timer_id);
+                        self.active_timers.retain(|t| stopped.is_some_and(|id| t.id != id));
+                    }
+                }
+                self.offline_events.push(event);
+                self.sync_error = None;
+                let _ = LocalStorage::set(OFFLINE_QUEUE_KEY, &self.offline_events);
+                true
+            }
+            TimeTrackerMsg::SyncOfflineEvents => {
+                if self.offline_events.is_empty() || self.syncing {
+                    return false;
+                }
+                self.syncing = true;
+
+                let link = ctx.link().clone();
+                let request_body = serde_json::json!({ "events": self.offline_events });
+                spawn_local(async move {
+                    match Request::post("/api/v1/time/timer/sync")
+                        .json(&request_body)
+                        .unwrap()
+                        .send()
+                        .await
+                    {
+                        Ok(response) if response.ok() => match response.json::<Vec<TimerSyncResult>>().await {
+                            Ok(results) => link.send_message(TimeTrackerMsg::OfflineEventsSynced(results)),
+                            Err(e) => link.send_message(TimeTrackerMsg::OfflineSyncFailed(format!("{:?}", e))),
+                        },
+                        // A request the server can't accept will fail the same way every retry
+                        Ok(response) if is_permanent_rejection(response.status()) => {
+                            link.send_message(TimeTrackerMsg::OfflineSyncRejected(response.status()));
+                        }
+                        Ok(response) => {
+                            link.send_message(TimeTrackerMsg::OfflineSyncFailed(format!("status {}", response.status())));
+                        }
+                        Err(e) => link.send_message(TimeTrackerMsg::OfflineSyncFailed(format!("{:?}", e))),
+                    }
+                });
+                false
+            }
+            TimeTrackerMsg::OfflineEventsSynced(results) => {
+                self.syncing = false;
+                self.sync_error = None;
+                // Every answered event is settled, including rejections; retrying won't change them
+                self.offline_events.retain(|e| !results.iter().any(|r| r.client_event_id == e.client_event_id));
+                let _ = LocalStorage::set(OFFLINE_QUEUE_KEY, &self.offline_events);
+
+                for result in results.iter().filter(|r| r.status == "rejected") {
+                    ctx.link().send_message(TimeTrackerMsg::Error(format!(
+                        "Offline timer change was not applied: {}",
+                        result.message.as_deref().unwrap_or("rejected")
+                    )));
+                }
+                ctx.link().send_message(TimeTrackerMsg::LoadActiveTimers);
+                ctx.link().send_message(TimeTrackerMsg::LoadStats);
+                true
+            }
+            TimeTrackerMsg::OfflineSyncFailed(error) => {
+                // Still offline, signed out or the server is struggling; the queue is kept for the next attempt
+                self.syncing = false;
+                web_sys::console::warn_1(&format!("Time Tracker sync failed: {}", error).into());
+                false
+            }
+            TimeTrackerMsg::OfflineSyncRejected(status) => {
+                // Nothing is discarded: the queue holds the technician's time until they retry
+                // or record another change
+                self.syncing = false;
+                let error = format!("The server refused the offline timer changes (status {})", status);
+                ctx.link().send_message(TimeTrackerMsg::Error(error.clone()));
+                self.sync_error = Some(error);
+                true
+            }
             TimeTrackerMsg::Error(error) => {
                 // TODO: Show error toast/notification
                 web_sys::console::error_1(&format!("Time Tracker Error: {}", error).into());
@@ -285,6 +485,11 @@ impl TimeTracker {
                     </div>
                     
                     <div class="flex items-center space-x-2">
+                        if !self.offline_events.is_empty() {
+                            <span class="text-xs text-yellow-700 bg-yellow-100 px-2 py-1 rounded-full">
+                                {format!("{} offline", self.offline_events.len())}
+                            </span>
+                        }
                         if self.active_timers.is_empty() {
                             <button 
                                 onclick={on_start_quick_timer}
@@ -326,6 +531,24 @@ impl TimeTracker {
                         </div>
                     </div>
 
+                    if !self.offline_events.is_empty() {
+                        <div class="mt-4 bg-yellow-50 border border-yellow-200 rounded-md p-3 text-sm text-yellow-800">
+                            {format!(
+                                "{} timer change{} recorded offline, waiting to sync",
+                                self.offline_events.len(),
+                                if self.offline_events.len() == 1 { "" } else { "s" }
+                            )}
+                            if let Some(error) = &self.sync_error {
+                                <p class="mt-1 text-red-700">{error.clone()}</p>
+                                <button
+                                    onclick={ctx.link().callback(|_| TimeTrackerMsg::SyncOfflineEvents)}
+                                    class="mt-2 text-sm font-medium text-yellow-900 underline">
+                                    {"Retry sync"}
+                                </button>
+                            }
+                        </div>
+                    }
+
                     // Stats cards
                     <div class="mt-6 grid grid-cols-1 gap-5 sm:grid-cols-2 lg:grid-cols-4">
                         <div class="bg-gray-50 overflow-hidden shadow rounded-lg">
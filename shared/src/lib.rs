@@ -217,21 +217,35 @@ pub struct Integration {
     pub updated_at: Option<DateTime<Utc>>,
 }
 
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Expense {
     pub id: Uuid,
     pub client_id: Option<Uuid>,
+    pub project_id: Option<Uuid>,
+    pub ticket_id: Option<Uuid>,
+    pub category: String,
+    pub category_id: Option<Uuid>,
+    pub vendor: String,
     pub vendor_id: Option<Uuid>,
-    pub category_id: Uuid,
-    pub amount: Decimal,
-    pub tax_amount: Option<Decimal>,
     pub description: String,
+    pub amount: Decimal,
+    pub currency: String,
+    pub tax_amount: Decimal,
     pub expense_date: NaiveDate,
-    pub receipt_file_id: Option<Uuid>,
-    pub billable: bool,
-    pub billed: bool,
+    pub is_billable: bool,
+    pub is_reimbursable: bool,
+    pub markup_percent: Decimal,
+    pub billed_amount: Option<Decimal>,
     pub invoice_id: Option<Uuid>,
-    pub created_by: Uuid,
+    pub payment_method: Option<String>,
+    pub receipt_file_id: Option<Uuid>,
+    pub status: String, // pending, approved, rejected, reimbursed
+    pub requires_approval: bool,
+    pub approved_by: Option<Uuid>,
+    pub approved_at: Option<DateTime<Utc>>,
+    pub approval_notes: Option<String>,
+    pub created_by: Option<Uuid>,
     pub created_at: DateTime<Utc>,
     pub updated_at: Option<DateTime<Utc>>,
 }
@@ -255,12 +269,18 @@ pub struct Vendor {
     pub updated_at: Option<DateTime<Utc>>,
 }
 
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExpenseCategory {
     pub id: Uuid,
     pub name: String,
     pub description: Option<String>,
     pub tax_deductible: bool,
+    pub markup_percent: Decimal,
+    pub billable_by_default: bool,
+    pub approval_threshold: Option<Decimal>, // auto-approve up to this amount
+    pub tax_category: Option<String>,        // sales tax category when rebilled
+    pub is_active: bool,
     pub created_at: DateTime<Utc>,
 }
 
@@ -277,6 +297,7 @@ pub struct Client {
     pub zip: Option<String>,
     pub billing_address: Option<String>,
     pub notes: Option<String>,
+    pub currency: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: Option<DateTime<Utc>>,
     pub archived_at: Option<DateTime<Utc>>,